@@ -0,0 +1,58 @@
+#ifndef TRANSIT_MODEL_H
+#define TRANSIT_MODEL_H
+
+/* Generated by cbindgen from ffi/src/lib.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ Convert the GTFS `input` (a directory or a ZIP archive) into the NTFS
+ `output` (a directory, or a ZIP archive if the path ends with `.zip`).
+
+ `config_json` is NULL or a JSON object with the optional keys `config`,
+ `prefix`, `current_datetime` and `transformations`.
+
+ Returns `0` on success and `-1` on failure (see
+ `transit_model_last_error`).
+
+ # Safety
+
+ `input` and `output` must be valid NUL-terminated UTF-8 strings, and
+ `config_json` NULL or a valid NUL-terminated UTF-8 string.
+ */
+int transit_model_convert_gtfs_to_ntfs(const char *input,
+                                       const char *output,
+                                       const char *config_json);
+
+/*
+ Convert the NTFS `input` (a directory or a ZIP archive) into the GTFS
+ `output` (a directory, or a ZIP archive if the path ends with `.zip`).
+
+ `config_json` is as in `transit_model_convert_gtfs_to_ntfs`, except the
+ key `config` which is only supported for a GTFS input.
+
+ Returns `0` on success and `-1` on failure (see
+ `transit_model_last_error`).
+
+ # Safety
+
+ `input` and `output` must be valid NUL-terminated UTF-8 strings, and
+ `config_json` NULL or a valid NUL-terminated UTF-8 string.
+ */
+int transit_model_convert_ntfs_to_gtfs(const char *input,
+                                       const char *output,
+                                       const char *config_json);
+
+/*
+ Message of the last failure of a conversion in the current thread, NULL
+ if the last conversion succeeded.
+
+ The string is owned by the library and valid until the next conversion
+ in the current thread: it must not be freed by the caller.
+ */
+const char *transit_model_last_error(void);
+
+#endif /* TRANSIT_MODEL_H */
@@ -0,0 +1,246 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! C ABI of the conversions of `transit_model`, for the systems embedding
+//! the converter without shelling out (C++, Python via cffi, ...).
+//!
+//! The functions return `0` on success and `-1` on failure, the message of
+//! the failure being then available with `transit_model_last_error`. The C
+//! header `include/transit_model.h` is generated with
+//! `cbindgen --config cbindgen.toml --output include/transit_model.h`.
+
+#![deny(missing_docs)]
+
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+};
+use transit_model::{
+    pipeline::{self, Format, Input, Output, PipelineConfig, Transformation},
+    Result,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Options of a conversion, given as a JSON object to the entry points
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ConvertOptions {
+    /// JSON file of the contributor, the dataset and the feed infos of a
+    /// GTFS (see `transit_model::configuration::read_config`)
+    #[serde(default)]
+    config: Option<PathBuf>,
+    /// Prefix added to all the identifiers
+    #[serde(default)]
+    prefix: Option<String>,
+    /// Date and time of the conversion (RFC 3339), the current one if empty
+    #[serde(default)]
+    current_datetime: Option<String>,
+    /// Transformations applied in order (see
+    /// `transit_model::pipeline::Transformation`)
+    #[serde(default)]
+    transformations: Vec<Transformation>,
+}
+
+unsafe fn to_string(name: &str, value: *const c_char) -> Result<String> {
+    if value.is_null() {
+        return Err(transit_model::Error::msg(format!("{} is NULL", name)));
+    }
+    Ok(CStr::from_ptr(value).to_str()?.to_string())
+}
+
+unsafe fn convert(
+    input: *const c_char,
+    input_format: Format,
+    output: *const c_char,
+    output_format: Format,
+    config_json: *const c_char,
+) -> Result<()> {
+    let input = to_string("input", input)?;
+    let output = to_string("output", output)?;
+    let options: ConvertOptions = if config_json.is_null() {
+        ConvertOptions::default()
+    } else {
+        serde_json::from_str(&to_string("config_json", config_json)?)?
+    };
+    let config = PipelineConfig {
+        input: Input {
+            format: input_format,
+            path: input.into(),
+            config: options.config,
+            prefix: options.prefix,
+        },
+        transformations: options.transformations,
+        output: Output {
+            format: output_format,
+            path: output.into(),
+            current_datetime: options.current_datetime,
+            extend_route_type: false,
+        },
+        check_idempotency: false,
+    };
+    pipeline::run_pipeline(config)
+}
+
+fn set_last_error(message: String) {
+    let message =
+        CString::new(message.replace('\0', " ")).expect("the NUL characters have been replaced");
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+fn handle_result<F>(conversion: F) -> c_int
+where
+    F: FnOnce() -> Result<()>,
+{
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+    match catch_unwind(AssertUnwindSafe(conversion)) {
+        Ok(Ok(())) => 0,
+        Ok(Err(error)) => {
+            set_last_error(format!("{:#}", error));
+            -1
+        }
+        Err(_) => {
+            set_last_error("the conversion panicked".to_string());
+            -1
+        }
+    }
+}
+
+/// Convert the GTFS `input` (a directory or a ZIP archive) into the NTFS
+/// `output` (a directory, or a ZIP archive if the path ends with `.zip`).
+///
+/// `config_json` is NULL or a JSON object with the optional keys `config`,
+/// `prefix`, `current_datetime` and `transformations`.
+///
+/// Returns `0` on success and `-1` on failure (see
+/// `transit_model_last_error`).
+///
+/// # Safety
+///
+/// `input` and `output` must be valid NUL-terminated UTF-8 strings, and
+/// `config_json` NULL or a valid NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn transit_model_convert_gtfs_to_ntfs(
+    input: *const c_char,
+    output: *const c_char,
+    config_json: *const c_char,
+) -> c_int {
+    handle_result(|| convert(input, Format::Gtfs, output, Format::Ntfs, config_json))
+}
+
+/// Convert the NTFS `input` (a directory or a ZIP archive) into the GTFS
+/// `output` (a directory, or a ZIP archive if the path ends with `.zip`).
+///
+/// `config_json` is as in `transit_model_convert_gtfs_to_ntfs`, except the
+/// key `config` which is only supported for a GTFS input.
+///
+/// Returns `0` on success and `-1` on failure (see
+/// `transit_model_last_error`).
+///
+/// # Safety
+///
+/// `input` and `output` must be valid NUL-terminated UTF-8 strings, and
+/// `config_json` NULL or a valid NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn transit_model_convert_ntfs_to_gtfs(
+    input: *const c_char,
+    output: *const c_char,
+    config_json: *const c_char,
+) -> c_int {
+    handle_result(|| convert(input, Format::Ntfs, output, Format::Gtfs, config_json))
+}
+
+/// Message of the last failure of a conversion in the current thread, NULL
+/// if the last conversion succeeded.
+///
+/// The string is owned by the library and valid until the next conversion
+/// in the current thread: it must not be freed by the caller.
+#[no_mangle]
+pub extern "C" fn transit_model_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> Option<String> {
+        let message = transit_model_last_error();
+        if message.is_null() {
+            None
+        } else {
+            Some(
+                unsafe { CStr::from_ptr(message) }
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            )
+        }
+    }
+
+    #[test]
+    fn convert_gtfs_to_ntfs() {
+        let output = tempfile::tempdir().unwrap();
+        let input = CString::new("../tests/fixtures/gtfs").unwrap();
+        let output_path = CString::new(output.path().to_str().unwrap()).unwrap();
+        let config_json =
+            CString::new(r#"{ "prefix": "ABC", "current_datetime": "2019-04-03T17:19:00Z" }"#)
+                .unwrap();
+
+        let status = unsafe {
+            transit_model_convert_gtfs_to_ntfs(
+                input.as_ptr(),
+                output_path.as_ptr(),
+                config_json.as_ptr(),
+            )
+        };
+
+        assert_eq!(None, last_error());
+        assert_eq!(0, status);
+        assert!(output.path().join("stops.txt").is_file());
+    }
+
+    #[test]
+    fn report_the_last_error() {
+        let input = CString::new("unknown").unwrap();
+        let output = CString::new("ntfs").unwrap();
+
+        let status = unsafe {
+            transit_model_convert_gtfs_to_ntfs(input.as_ptr(), output.as_ptr(), ptr::null())
+        };
+
+        assert_eq!(-1, status);
+        assert_eq!(
+            Some("input \"unknown\" doesn't exist"),
+            last_error().as_deref()
+        );
+        let status = unsafe {
+            transit_model_convert_gtfs_to_ntfs(ptr::null(), output.as_ptr(), ptr::null())
+        };
+        assert_eq!(-1, status);
+        assert_eq!(Some("input is NULL"), last_error().as_deref());
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Reading of a valid GTFS in which one of the files is replaced by arbitrary
+//! content, the first byte of the input selecting the file.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::collections::BTreeMap;
+
+macro_rules! fixture {
+    ($name:expr) => {
+        (
+            $name,
+            &include_bytes!(concat!("../../tests/fixtures/gtfs/", $name))[..],
+        )
+    };
+}
+
+const FILES: &[(&str, &[u8])] = &[
+    fixture!("agency.txt"),
+    fixture!("calendar_dates.txt"),
+    fixture!("equipments.txt"),
+    fixture!("frequencies.txt"),
+    fixture!("levels.txt"),
+    fixture!("pathways.txt"),
+    fixture!("routes.txt"),
+    fixture!("stop_times.txt"),
+    fixture!("stops.txt"),
+    fixture!("transfers.txt"),
+    fixture!("trips.txt"),
+];
+
+fuzz_target!(|data: &[u8]| {
+    let (selector, content) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let fuzzed_file = FILES[*selector as usize % FILES.len()].0;
+    let files: BTreeMap<String, Vec<u8>> = FILES
+        .iter()
+        .map(|(name, fixture)| {
+            let content = if *name == fuzzed_file {
+                content
+            } else {
+                *fixture
+            };
+            (name.to_string(), content.to_vec())
+        })
+        .collect();
+    let _ = transit_model::gtfs::from_memory(files, "fuzz");
+});
@@ -18,12 +18,16 @@ use chrono::{DateTime, FixedOffset};
 use clap::Parser;
 use std::path::PathBuf;
 use tracing::info;
-use tracing_subscriber::{
-    filter::{EnvFilter, LevelFilter},
-    layer::SubscriberExt as _,
-    util::SubscriberInitExt as _,
+use transit_model::{
+    configuration,
+    gtfs::{DuplicateIdStrategy, RouteNameStrategy},
+    line_branding, name_normalization, network_update,
+    ntfs::{self, WriteConfiguration},
+    report::ReportFormat,
+    stamp::Stamp,
+    transfers::generates_transfers,
+    Error, PrefixConfiguration, Result,
 };
-use transit_model::{configuration, transfers::generates_transfers, PrefixConfiguration, Result};
 
 lazy_static::lazy_static! {
     pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
@@ -74,6 +78,68 @@ struct Opt {
     #[clap(long = "read-as-line")]
     read_as_line: bool,
 
+    /// If true, each GTFS `Route` generates a single route for all the
+    /// directions of its trips, instead of one route by `direction_id`.
+    #[clap(long)]
+    merge_directions: bool,
+
+    /// Strategy applied when several stops or several trips share the same
+    /// identifier: 'fail', 'keep-first', 'keep-last' or 'suffix' (trips
+    /// only, a duplicated stop_id fails the conversion).
+    #[clap(long = "duplicate-ids", default_value = "fail")]
+    duplicate_id_strategy: DuplicateIdStrategy,
+
+    /// Normalize the dates and the times written in exotic formats
+    /// (`24:00`, `2020-01-31`) instead of failing.
+    #[clap(long)]
+    lenient: bool,
+
+    /// Mapping of the route short and long names to the code and the name of
+    /// the lines: 'code-and-name' or 'duplicated-name'.
+    #[clap(long = "route-names", default_value = "code-and-name")]
+    route_name_strategy: RouteNameStrategy,
+
+    /// JSON file of the rules moving the routes of an agency whose short
+    /// name matches a pattern into another network (e.g. `N.*` into a
+    /// night network).
+    #[clap(long, parse(from_os_str))]
+    network_rules: Option<PathBuf>,
+
+    /// Output file of the decisions taken while reading the GTFS.
+    #[clap(long, parse(from_os_str))]
+    report: Option<PathBuf>,
+
+    /// Format of the report files: 'json', 'jsonl' (one JSON object per
+    /// line and per entry) or 'text' (human-readable summary).
+    #[clap(long, default_value = "json")]
+    report_format: ReportFormat,
+
+    /// CSV file of the branding of the lines (`network_id`, `line_code`,
+    /// `line_color`, `line_text_color`, `logo_url`), filling their missing
+    /// colors and logos.
+    #[clap(long, parse(from_os_str))]
+    branding: Option<PathBuf>,
+
+    /// Output file of the report of the branding of the lines.
+    #[clap(long, parse(from_os_str), requires = "branding")]
+    branding_report: Option<PathBuf>,
+
+    /// JSON file of the rules of the normalization of the names of the stops
+    /// and of the lines (operator prefixes, abbreviations, casing).
+    #[clap(long, parse(from_os_str))]
+    name_normalization: Option<PathBuf>,
+
+    /// Output file listing the names before and after their
+    /// normalization.
+    #[clap(long, parse(from_os_str), requires = "name_normalization")]
+    name_normalization_report: Option<PathBuf>,
+
+    /// Existing NTFS (directory or zip) in which the network of the GTFS
+    /// replaces the previous version of this network, everything else being
+    /// preserved. Stops are matched with the existing ones by their codes.
+    #[clap(long, parse(from_os_str))]
+    update: Option<PathBuf>,
+
     /// Current datetime.
     #[clap(
         short = 'x',
@@ -95,6 +161,25 @@ struct Opt {
     /// Waiting time at stop in seconds.
     #[clap(long, short = 't', default_value = transit_model::TRANSFER_WAITING_TIME)]
     waiting_time: u32,
+
+    /// Identifier of the run of the pipeline, stamped on the NTFS.
+    #[clap(long)]
+    run_id: Option<String>,
+
+    /// Version of a source feed stamped on the NTFS, as `<feed>=<version>`
+    /// (can be repeated).
+    #[clap(long, parse(try_from_str = parse_source_version))]
+    source_version: Vec<(String, String)>,
+}
+
+fn parse_source_version(source_version: &str) -> Result<(String, String)> {
+    match source_version.split_once('=') {
+        Some((feed, version)) if !feed.is_empty() => Ok((feed.to_string(), version.to_string())),
+        _ => Err(Error::msg(format!(
+            "invalid source version {:?}, expected <feed>=<version>",
+            source_version
+        ))),
+    }
 }
 
 fn run(opt: Opt) -> Result<()> {
@@ -116,9 +201,53 @@ fn run(opt: Opt) -> Result<()> {
         on_demand_transport: opt.odt,
         on_demand_transport_comment: opt.odt_comment,
         read_as_line: opt.read_as_line,
+        duplicate_id_strategy: opt.duplicate_id_strategy,
+        lenient: opt.lenient,
+        route_name_strategy: opt.route_name_strategy,
+        merge_directions: opt.merge_directions,
+        network_rules: opt
+            .network_rules
+            .map(transit_model::gtfs::read_network_rules)
+            .transpose()?
+            .unwrap_or_default(),
     };
 
-    let model = transit_model::gtfs::Reader::new(configuration).parse(opt.input)?;
+    let (mut collections, report) =
+        transit_model::gtfs::Reader::new(configuration).parse_collections_with_report(opt.input)?;
+    if let Some(report_path) = opt.report {
+        report.write(report_path, opt.report_format)?;
+    }
+    if let Some(branding) = opt.branding {
+        let brandings = line_branding::read_brandings(branding)?;
+        let branding_report = line_branding::apply_brandings(&mut collections, &brandings);
+        if let Some(branding_report_path) = opt.branding_report {
+            branding_report.write(branding_report_path, opt.report_format)?;
+        }
+    }
+    if let Some(name_normalization) = opt.name_normalization {
+        let rules = name_normalization::read_rules(name_normalization)?;
+        let normalization_report = name_normalization::normalize_names(&mut collections, &rules);
+        if let Some(normalization_report_path) = opt.name_normalization_report {
+            normalization_report.write(normalization_report_path, opt.report_format)?;
+        }
+    }
+    let model = transit_model::Model::new(collections)?;
+    let model = match opt.update {
+        Some(referential) => {
+            let mut networks = model.networks.values();
+            let network_id = match (networks.next(), networks.next()) {
+                (Some(network), None) => network.id.clone(),
+                _ => {
+                    return Err(Error::msg(
+                        "the GTFS should contain exactly one network to update an NTFS",
+                    ))
+                }
+            };
+            let referential = transit_model::ntfs::read(referential)?;
+            network_update::update_network(referential, model, &network_id)?
+        }
+        None => model,
+    };
 
     let model = generates_transfers(
         model,
@@ -128,38 +257,30 @@ fn run(opt: Opt) -> Result<()> {
         None,
     )?;
 
+    let mut write_config = WriteConfiguration::new(opt.current_datetime);
+    if opt.run_id.is_some() || !opt.source_version.is_empty() {
+        let mut stamp = Stamp::new(opt.current_datetime);
+        if let Some(run_id) = opt.run_id {
+            stamp = stamp.run_id(run_id);
+        }
+        for (feed, version) in opt.source_version {
+            stamp = stamp.source_version(feed, version);
+        }
+        write_config = write_config.stamp(stamp);
+    }
     match opt.output.extension() {
         Some(ext) if ext == "zip" => {
-            transit_model::ntfs::write_to_zip(&model, opt.output, opt.current_datetime)?;
+            ntfs::write_to_zip_with_configuration(&model, opt.output, write_config)?;
         }
         _ => {
-            transit_model::ntfs::write(&model, opt.output, opt.current_datetime)?;
+            ntfs::write_with_configuration(&model, opt.output, write_config)?;
         }
     };
     Ok(())
 }
 
-fn init_logger() {
-    let default_level = LevelFilter::INFO;
-    let rust_log =
-        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
-    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
-        eprintln!(
-            "invalid {}, falling back to level '{}' - {}",
-            EnvFilter::DEFAULT_ENV,
-            default_level,
-            e,
-        );
-        EnvFilter::new(default_level.to_string())
-    });
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(env_filter_subscriber)
-        .init();
-}
-
 fn main() {
-    init_logger();
+    transit_model::logger::init_logger();
     if let Err(err) = run(Opt::parse()) {
         for cause in err.chain() {
             eprintln!("{}", cause);
@@ -18,12 +18,12 @@ use clap::Parser;
 use ntfs2gtfs::add_mode_to_line_code;
 use std::path::PathBuf;
 use tracing::info;
-use tracing_subscriber::{
-    filter::{EnvFilter, LevelFilter},
-    layer::SubscriberExt as _,
-    util::SubscriberInitExt as _,
+use transit_model::{
+    csv_format::{CsvFormat, LineTerminator, QuoteStyle},
+    gtfs::{self, EmptyFileStrategy, RouteNameStrategy, SplitBy, WriteConfiguration},
+    report::ReportFormat,
+    Model, Result,
 };
-use transit_model::{Model, Result};
 
 lazy_static::lazy_static! {
     pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
@@ -55,25 +55,79 @@ struct Opt {
                 https://developers.google.com/transit/gtfs/reference/extended-route-types"
     )]
     extend_route_type: bool,
-}
 
-fn init_logger() {
-    let default_level = LevelFilter::INFO;
-    let rust_log =
-        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
-    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
-        eprintln!(
-            "invalid {}, falling back to level '{}' - {}",
-            EnvFilter::DEFAULT_ENV,
-            default_level,
-            e,
-        );
-        EnvFilter::new(default_level.to_string())
-    });
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(env_filter_subscriber)
-        .init();
+    /// Write the optional files without any object with their header only,
+    /// instead of skipping them.
+    #[clap(long)]
+    header_only_empty_files: bool,
+
+    /// Leave empty the arrival at the first stop and the departure from the
+    /// last stop of the trips when they are equal to the other time.
+    #[clap(long)]
+    blank_redundant_times: bool,
+
+    /// Mapping of the code and the name of the lines to the route short and long
+    /// names: 'code-and-name' or 'duplicated-name'.
+    #[clap(long = "route-names", default_value = "code-and-name")]
+    route_name_strategy: RouteNameStrategy,
+
+    /// Write the comments and their links to the stops, routes, trips and
+    /// stop times in the comments.txt and comment_links.txt extension files.
+    #[clap(long)]
+    write_comments: bool,
+
+    /// Write the first and last departure times of the lines by day type in
+    /// the line_operating_hours.txt extension file.
+    #[clap(long)]
+    line_operating_hours: bool,
+
+    /// CSV file of the GTFS route types (`line_id`, `route_type`) forced on
+    /// the routes of some lines, instead of the route types deduced from
+    /// their physical modes.
+    #[clap(long, parse(from_os_str))]
+    route_type_overrides: Option<PathBuf>,
+
+    /// Generate a walkway from each entrance without any pathway to each stop
+    /// point of its stop area, walking at the given speed (in m/s).
+    #[clap(long)]
+    entrance_pathways: Option<f64>,
+
+    /// Output file of the report listing the default values written in
+    /// place of the missing fields.
+    #[clap(long, parse(from_os_str))]
+    report: Option<PathBuf>,
+
+    /// Format of the report files: 'json', 'jsonl' (one JSON object per
+    /// line and per entry) or 'text' (human-readable summary).
+    #[clap(long, default_value = "json")]
+    report_format: ReportFormat,
+
+    /// Quote all the fields of the CSV files.
+    #[clap(long)]
+    quote_all: bool,
+
+    /// End the lines of the CSV files with CRLF instead of LF.
+    #[clap(long)]
+    crlf: bool,
+
+    /// Write the coordinates with this fixed number of decimals.
+    #[clap(long)]
+    coord_precision: Option<usize>,
+
+    /// Transliterate the names, the headsigns and the comments to ASCII (e.g.
+    /// `é` written `e`).
+    #[clap(long)]
+    ascii: bool,
+
+    /// Write a manifest.json file listing the checksum and the number of rows
+    /// of each file (inside the archive for a ZIP output).
+    #[clap(long)]
+    manifest: bool,
+
+    /// Write one GTFS per 'network' or per 'physical-mode', in the
+    /// subdirectories of the output directory named after their identifiers.
+    #[clap(long)]
+    split_by: Option<SplitBy>,
 }
 
 fn run(opt: Opt) -> Result<()> {
@@ -81,25 +135,88 @@ fn run(opt: Opt) -> Result<()> {
     let mut collections = transit_model::ntfs::read_collections(opt.input)?;
     collections.remove_stop_zones();
     collections.remove_route_points();
+    if let Some(walking_speed) = opt.entrance_pathways {
+        transit_model::entrance_pathways::generate_entrance_pathways(
+            &mut collections,
+            walking_speed,
+        );
+    }
+    if opt.ascii {
+        collections.transliterate_to_ascii();
+    }
     let mut model = Model::new(collections)?;
 
     if opt.mode_in_route_short_name {
         model = add_mode_to_line_code(model)?;
     }
 
+    if let Some(report_path) = opt.report {
+        transit_model::gtfs::default_values_report(&model).write(report_path, opt.report_format)?;
+    }
+
+    let csv_format = CsvFormat {
+        quote_style: if opt.quote_all {
+            QuoteStyle::Always
+        } else {
+            QuoteStyle::Necessary
+        },
+        line_terminator: if opt.crlf {
+            LineTerminator::Crlf
+        } else {
+            LineTerminator::Lf
+        },
+        coord_precision: opt.coord_precision,
+    };
+    let write_config = WriteConfiguration {
+        extend_route_type: opt.extend_route_type,
+        empty_file_strategy: if opt.header_only_empty_files {
+            EmptyFileStrategy::HeaderOnly
+        } else {
+            EmptyFileStrategy::Skip
+        },
+        route_type_overrides: match opt.route_type_overrides {
+            Some(route_type_overrides) => gtfs::read_route_type_overrides(route_type_overrides)?,
+            None => Default::default(),
+        },
+        blank_redundant_times: opt.blank_redundant_times,
+        route_name_strategy: opt.route_name_strategy,
+        write_comments: opt.write_comments,
+        write_line_operating_hours: opt.line_operating_hours,
+        csv_format,
+        ..Default::default()
+    };
+    if let Some(split_by) = opt.split_by {
+        gtfs::write_split_with_configuration(&model, &opt.output, split_by, write_config)?;
+        for entry in std::fs::read_dir(&opt.output)? {
+            let part = entry?.path();
+            if !part.is_dir() {
+                continue;
+            }
+            if opt.manifest {
+                transit_model::manifest::write(&part)?;
+            }
+        }
+        return Ok(());
+    }
     match opt.output.extension() {
         Some(ext) if ext == "zip" => {
-            transit_model::gtfs::write_to_zip(model, opt.output, opt.extend_route_type)?;
+            gtfs::write_to_zip_with_configuration(model, &opt.output, write_config)?;
+            if opt.manifest {
+                transit_model::manifest::write_to_zip(&opt.output)?;
+            }
         }
         _ => {
-            transit_model::gtfs::write(model, opt.output, opt.extend_route_type)?;
+            gtfs::write_with_configuration(model, &opt.output, write_config)?;
+            if opt.manifest {
+                transit_model::manifest::write(&opt.output)?;
+            }
         }
     };
     Ok(())
 }
 
 fn main() {
-    init_logger();
+    transit_model::logger::init_logger();
     if let Err(err) = run(Opt::parse()) {
         for cause in err.chain() {
             eprintln!("{}", cause);
@@ -16,7 +16,7 @@ use assert_cmd::prelude::*;
 use ntfs2gtfs::add_mode_to_line_code;
 use std::process::Command;
 use tempfile::TempDir;
-use transit_model::{test_utils::*, Model};
+use transit_model::{gtfs::EmptyFileStrategy, test_utils::*, Model};
 
 #[test]
 fn test_stop_zones_not_exported_and_cleaned() {
@@ -26,7 +26,7 @@ fn test_stop_zones_not_exported_and_cleaned() {
         collections.remove_stop_zones();
         collections.remove_route_points();
         let model = Model::new(collections).unwrap();
-        transit_model::gtfs::write(model, path, false).unwrap();
+        transit_model::gtfs::write(model, path, false, EmptyFileStrategy::Skip).unwrap();
         compare_output_dir_with_expected(path, None, "./tests/fixtures/output");
     });
 }
@@ -37,7 +37,7 @@ fn test_mode_in_route_shortname() {
         let input = "./tests/fixtures/input";
         let model = transit_model::ntfs::read(input).unwrap();
         let model = add_mode_to_line_code(model).unwrap();
-        transit_model::gtfs::write(model, path, false).unwrap();
+        transit_model::gtfs::write(model, path, false, EmptyFileStrategy::Skip).unwrap();
         compare_output_dir_with_expected(
             path,
             Some(vec!["routes.txt"]),
@@ -51,7 +51,7 @@ fn test_platforms_preserving() {
     test_in_tmp_dir(|path| {
         let input = "./tests/fixtures/platforms/input";
         let model = transit_model::ntfs::read(input).unwrap();
-        transit_model::gtfs::write(model, path, false).unwrap();
+        transit_model::gtfs::write(model, path, false, EmptyFileStrategy::Skip).unwrap();
         compare_output_dir_with_expected(
             path,
             Some(vec!["stops.txt"]),
@@ -89,6 +89,46 @@ fn test_ntfs2gtfs_create_output_directory() {
     assert!(unexisting_dir.join("agency.txt").is_file())
 }
 
+#[test]
+fn test_ntfs2gtfs_default_values_report() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    let report_path = output_dir.path().join("report.json");
+    Command::cargo_bin("ntfs2gtfs")
+        .expect("Failed to find binary 'ntfs2gtfs'")
+        .arg("--input")
+        .arg("tests/fixtures/input/")
+        .arg("--output")
+        .arg(output_dir.path().join("gtfs").to_str().unwrap())
+        .arg("--report")
+        .arg(report_path.to_str().unwrap())
+        .assert()
+        .success();
+    let report = std::fs::read_to_string(report_path).unwrap();
+    assert!(report.contains(
+        "network network:kept has no url, agency_url is written as \\\"http://www.navitia.io/\\\""
+    ));
+    assert!(report.contains("default_agency_timezone"));
+}
+
+#[test]
+fn test_ntfs2gtfs_header_only_empty_files() {
+    let output_dir = TempDir::new().expect("create temp dir failed");
+    Command::cargo_bin("ntfs2gtfs")
+        .expect("Failed to find binary 'ntfs2gtfs'")
+        .arg("--input")
+        .arg("tests/fixtures/input/")
+        .arg("--output")
+        .arg(output_dir.path().to_str().unwrap())
+        .arg("--header-only-empty-files")
+        .assert()
+        .success();
+    let transfers = std::fs::read_to_string(output_dir.path().join("transfers.txt")).unwrap();
+    assert_eq!(
+        "from_stop_id,to_stop_id,transfer_type,min_transfer_time\n",
+        transfers
+    );
+}
+
 #[test]
 fn test_ntfs2gtfs_create_zip() {
     let output_dir = TempDir::new().expect("create temp dir failed");
@@ -18,11 +18,6 @@ use chrono::{DateTime, FixedOffset};
 use clap::Parser;
 use std::path::PathBuf;
 use tracing::info;
-use tracing_subscriber::{
-    filter::{EnvFilter, LevelFilter},
-    layer::SubscriberExt as _,
-    util::SubscriberInitExt as _,
-};
 use transit_model::{Model, Result};
 
 lazy_static::lazy_static! {
@@ -68,25 +63,6 @@ struct Opt {
     current_datetime: DateTime<FixedOffset>,
 }
 
-fn init_logger() {
-    let default_level = LevelFilter::INFO;
-    let rust_log =
-        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
-    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
-        eprintln!(
-            "invalid {}, falling back to level '{}' - {}",
-            EnvFilter::DEFAULT_ENV,
-            default_level,
-            e,
-        );
-        EnvFilter::new(default_level.to_string())
-    });
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(env_filter_subscriber)
-        .init();
-}
-
 fn run(opt: Opt) -> Result<()> {
     info!("Launching ntfs2netexfr...");
 
@@ -111,7 +87,7 @@ fn run(opt: Opt) -> Result<()> {
 }
 
 fn main() {
-    init_logger();
+    transit_model::logger::init_logger();
     if let Err(err) = run(Opt::parse()) {
         for cause in err.chain() {
             eprintln!("{}", cause);
@@ -18,12 +18,13 @@ use chrono::{DateTime, FixedOffset};
 use clap::Parser;
 use std::path::PathBuf;
 use tracing::info;
-use tracing_subscriber::{
-    filter::{EnvFilter, LevelFilter},
-    layer::SubscriberExt as _,
-    util::SubscriberInitExt as _,
+use transit_model::{
+    csv_format::{CsvDialect, CsvFormat, LineTerminator, QuoteStyle},
+    ntfs::{NtfsVersion, WriteConfiguration},
+    report::ReportFormat,
+    transfers::generates_transfers,
+    Error, Model, Result,
 };
-use transit_model::{transfers::generates_transfers, Result};
 
 lazy_static::lazy_static! {
     pub static ref GIT_VERSION: String = transit_model::binary_full_version(env!("CARGO_PKG_VERSION"));
@@ -44,6 +45,14 @@ struct Opt {
     #[clap(short = 'o', long = "output", parse(from_os_str))]
     output: Option<PathBuf>,
 
+    /// Separator of the fields of the input CSV files (e.g. ';').
+    #[clap(long, default_value = ",", parse(try_from_str = parse_separator))]
+    delimiter: u8,
+
+    /// Separator of the decimal numbers of the input CSV files (e.g. ',').
+    #[clap(long, default_value = ".", parse(try_from_str = parse_separator))]
+    decimal_separator: u8,
+
     /// Current datetime.
     #[clap(
         short = 'x',
@@ -53,6 +62,17 @@ struct Opt {
     )]
     current_datetime: DateTime<FixedOffset>,
 
+    /// Version of the NTFS specification to write: '0.11' or '0.14'
+    /// (default). The files and columns not available in this version are
+    /// not written.
+    #[clap(long, default_value = "0.14")]
+    ntfs_version: NtfsVersion,
+
+    /// Write the bounding box and the convex hull of the stop points in
+    /// feed_infos.txt.
+    #[clap(long)]
+    geographic_extent: bool,
+
     /// The maximum distance in meters to compute the tranfer.
     #[clap(long, short = 'd', default_value = transit_model::TRANSFER_MAX_DISTANCE)]
     max_distance: f64,
@@ -65,31 +85,65 @@ struct Opt {
     /// Waiting time at stop in seconds.
     #[clap(long, short = 't', default_value = transit_model::TRANSFER_WAITING_TIME)]
     waiting_time: u32,
+
+    /// Output file of the report of the validation of the codes of the
+    /// objects (invalid UIC or IFOPT codes, unknown code systems).
+    #[clap(long, parse(from_os_str))]
+    codes_report: Option<PathBuf>,
+
+    /// Format of the report files: 'json', 'jsonl' (one JSON object per
+    /// line and per entry) or 'text' (human-readable summary).
+    #[clap(long, default_value = "json")]
+    report_format: ReportFormat,
+
+    /// Quote all the fields of the CSV files.
+    #[clap(long)]
+    quote_all: bool,
+
+    /// End the lines of the CSV files with CRLF instead of LF.
+    #[clap(long)]
+    crlf: bool,
+
+    /// Write the coordinates with this fixed number of decimals.
+    #[clap(long)]
+    coord_precision: Option<usize>,
+
+    /// Transliterate the names, the headsigns and the comments to ASCII (e.g.
+    /// `é` written `e`).
+    #[clap(long)]
+    ascii: bool,
+
+    /// Write a manifest.json file listing the checksum and the number of rows
+    /// of each file (inside the archive for a ZIP output).
+    #[clap(long)]
+    manifest: bool,
 }
 
-fn init_logger() {
-    let default_level = LevelFilter::INFO;
-    let rust_log =
-        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
-    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
-        eprintln!(
-            "invalid {}, falling back to level '{}' - {}",
-            EnvFilter::DEFAULT_ENV,
-            default_level,
-            e,
-        );
-        EnvFilter::new(default_level.to_string())
-    });
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(env_filter_subscriber)
-        .init();
+fn parse_separator(separator: &str) -> Result<u8> {
+    match separator.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(Error::msg(format!(
+            "invalid separator {:?}, expected a single ASCII character",
+            separator
+        ))),
+    }
 }
 
 fn run(opt: Opt) -> Result<()> {
     info!("Launching ntfs2ntfs...");
 
-    let model = transit_model::ntfs::read(opt.input)?;
+    let dialect = CsvDialect {
+        delimiter: opt.delimiter,
+        decimal_separator: opt.decimal_separator,
+    };
+    let model = if dialect == CsvDialect::default() {
+        transit_model::ntfs::read(opt.input)?
+    } else {
+        transit_model::ntfs::read_with_dialect(opt.input, dialect)?
+    };
+    if let Some(codes_report) = opt.codes_report {
+        transit_model::codes::validate_codes(&model).write(codes_report, opt.report_format)?;
+    }
     let model = generates_transfers(
         model,
         opt.max_distance,
@@ -97,14 +151,50 @@ fn run(opt: Opt) -> Result<()> {
         opt.waiting_time,
         None,
     )?;
+    let model = if opt.ascii {
+        let mut collections = model.into_collections();
+        collections.transliterate_to_ascii();
+        Model::new(collections)?
+    } else {
+        model
+    };
 
+    let csv_format = CsvFormat {
+        quote_style: if opt.quote_all {
+            QuoteStyle::Always
+        } else {
+            QuoteStyle::Necessary
+        },
+        line_terminator: if opt.crlf {
+            LineTerminator::Crlf
+        } else {
+            LineTerminator::Lf
+        },
+        coord_precision: opt.coord_precision,
+    };
+    let mut write_config = WriteConfiguration::new(opt.current_datetime)
+        .version(opt.ntfs_version)
+        .csv_format(csv_format);
+    if opt.geographic_extent {
+        write_config = write_config.geographic_extent();
+    }
     if let Some(output) = opt.output {
         match output.extension() {
             Some(ext) if ext == "zip" => {
-                transit_model::ntfs::write_to_zip(&model, output, opt.current_datetime)?;
+                transit_model::ntfs::write_to_zip_with_configuration(
+                    &model,
+                    &output,
+                    write_config,
+                )?;
+                if opt.manifest {
+                    transit_model::manifest::write_to_zip(&output)?;
+                }
             }
             _ => {
-                transit_model::ntfs::write(&model, output, opt.current_datetime)?;
+                transit_model::ntfs::write_with_configuration(&model, &output, write_config)?;
+                if opt.manifest {
+                    transit_model::manifest::write(&output)?;
+                }
             }
         };
     }
@@ -112,7 +202,7 @@ fn run(opt: Opt) -> Result<()> {
 }
 
 fn main() {
-    init_logger();
+    transit_model::logger::init_logger();
     if let Err(err) = run(Opt::parse()) {
         for cause in err.chain() {
             eprintln!("{}", cause);
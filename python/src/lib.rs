@@ -0,0 +1,213 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Python bindings of `transit_model`, to inspect and check the datasets in
+//! notebooks: loading of a `Model`, iteration over its collections, and its
+//! statistics and validation reports.
+
+#![deny(missing_docs)]
+
+use pyo3::{
+    exceptions::{PyKeyError, PyRuntimeError},
+    prelude::*,
+    types::PyDict,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+use transit_model::{
+    configuration, connectivity, gtfs,
+    model::Model,
+    ntfs, run_time_check,
+    speed_check::{self, SpeedThresholds},
+    PrefixConfiguration,
+};
+
+fn to_py_err(error: transit_model::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{:#}", error))
+}
+
+fn to_py<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    Ok(pythonize::pythonize(py, value)?)
+}
+
+fn unknown_collection(name: &str) -> PyErr {
+    PyKeyError::new_err(format!("unknown collection {:?}", name))
+}
+
+// Names, lengths and objects of the collections of a `Model`
+macro_rules! collections {
+    ($($field:ident),*) => {
+        const COLLECTION_NAMES: &[&str] = &[$(stringify!($field)),*];
+
+        fn collection_len(model: &Model, name: &str) -> PyResult<usize> {
+            match name {
+                $(stringify!($field) => Ok(model.$field.len()),)*
+                _ => Err(unknown_collection(name)),
+            }
+        }
+
+        fn collection_to_py(py: Python<'_>, model: &Model, name: &str) -> PyResult<Vec<PyObject>> {
+            match name {
+                $(stringify!($field) => model
+                    .$field
+                    .values()
+                    .map(|object| to_py(py, object))
+                    .collect(),)*
+                _ => Err(unknown_collection(name)),
+            }
+        }
+    };
+}
+
+collections!(
+    contributors,
+    datasets,
+    networks,
+    commercial_modes,
+    lines,
+    routes,
+    vehicle_journeys,
+    frequencies,
+    physical_modes,
+    stop_areas,
+    stop_points,
+    stop_locations,
+    calendars,
+    companies,
+    comments,
+    equipments,
+    transfers,
+    interchanges,
+    trip_properties,
+    geometries,
+    admin_stations,
+    pathways,
+    levels
+);
+
+/// A transit model, read with `read_ntfs` or `read_gtfs`
+#[pyclass(name = "Model", unsendable)]
+struct PyModel {
+    model: Model,
+}
+
+#[pymethods]
+impl PyModel {
+    /// Names of the collections of the model
+    #[staticmethod]
+    fn collection_names() -> Vec<&'static str> {
+        COLLECTION_NAMES.to_vec()
+    }
+
+    /// Number of objects of the collection `name`
+    fn count(&self, name: &str) -> PyResult<usize> {
+        collection_len(&self.model, name)
+    }
+
+    /// Objects of the collection `name` (e.g. `"stop_points"`), as
+    /// dictionaries
+    fn collection(&self, py: Python<'_>, name: &str) -> PyResult<Vec<PyObject>> {
+        collection_to_py(py, &self.model, name)
+    }
+
+    /// Feed infos of the model, as a dictionary
+    fn feed_infos(&self) -> BTreeMap<String, String> {
+        self.model
+            .feed_infos
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Statistics of the model: the number of objects of each collection,
+    /// the number of stop times and the validity period (`YYYY-MM-DD`)
+    fn statistics<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let statistics = PyDict::new(py);
+        for name in COLLECTION_NAMES {
+            statistics.set_item(name, collection_len(&self.model, name)?)?;
+        }
+        let stop_times: usize = self
+            .model
+            .vehicle_journeys
+            .values()
+            .map(|vehicle_journey| vehicle_journey.stop_times.len())
+            .sum();
+        statistics.set_item("stop_times", stop_times)?;
+        if let Ok((start_date, end_date)) = self.model.calculate_validity_period() {
+            statistics.set_item("start_date", start_date.to_string())?;
+            statistics.set_item("end_date", end_date.to_string())?;
+        }
+        Ok(statistics)
+    }
+
+    /// Report of the implausible speeds between the consecutive stops of the
+    /// vehicle journeys, with the default thresholds
+    fn check_speeds(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let report = speed_check::check_speeds(&self.model, &SpeedThresholds::default());
+        to_py(py, &report)
+    }
+
+    /// Report of the implausible running times between the consecutive
+    /// stops of the vehicle journeys
+    fn check_run_times(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_py(py, &run_time_check::check_run_times(&self.model))
+    }
+
+    /// Report of the connectivity of the stops
+    fn analyze_connectivity(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_py(py, &connectivity::analyze_connectivity(&self.model))
+    }
+}
+
+/// Read the NTFS `path` (a directory or a ZIP archive)
+#[pyfunction]
+fn read_ntfs(path: PathBuf) -> PyResult<PyModel> {
+    let model = ntfs::read(path).map_err(to_py_err)?;
+    Ok(PyModel { model })
+}
+
+/// Read the GTFS `path` (a directory or a ZIP archive), with the optional
+/// JSON configuration file `config` and the `prefix` of the identifiers
+#[pyfunction]
+#[pyo3(signature = (path, config=None, prefix=None))]
+fn read_gtfs(path: PathBuf, config: Option<PathBuf>, prefix: Option<String>) -> PyResult<PyModel> {
+    let (contributor, dataset, feed_infos) =
+        configuration::read_config(config).map_err(to_py_err)?;
+    let prefix_conf = prefix.map(|prefix| {
+        let mut prefix_conf = PrefixConfiguration::default();
+        prefix_conf.set_data_prefix(prefix);
+        prefix_conf
+    });
+    let configuration = gtfs::Configuration {
+        contributor,
+        dataset,
+        feed_infos,
+        prefix_conf,
+        ..Default::default()
+    };
+    let model = gtfs::Reader::new(configuration)
+        .parse(path)
+        .map_err(to_py_err)?;
+    Ok(PyModel { model })
+}
+
+/// Python module `transit_model`
+#[pymodule]
+#[pyo3(name = "transit_model")]
+fn transit_model_py(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyModel>()?;
+    module.add_function(wrap_pyfunction!(read_ntfs, module)?)?;
+    module.add_function(wrap_pyfunction!(read_gtfs, module)?)?;
+    Ok(())
+}
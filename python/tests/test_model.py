@@ -0,0 +1,26 @@
+import transit_model
+
+
+def test_read_gtfs():
+    model = transit_model.read_gtfs("../tests/fixtures/gtfs", prefix="ABC")
+    stop_points = model.collection("stop_points")
+    assert len(stop_points) == model.count("stop_points")
+    assert all(stop_point["id"].startswith("ABC:") for stop_point in stop_points)
+    statistics = model.statistics()
+    assert statistics["stop_areas"] == 2
+    assert statistics["stop_times"] > 0
+
+
+def test_reports():
+    model = transit_model.read_ntfs("../tests/fixtures/ntfs")
+    report = model.check_speeds()
+    assert set(report) == {"errors", "warnings"}
+
+
+def test_unknown_collection():
+    model = transit_model.read_ntfs("../tests/fixtures/ntfs")
+    try:
+        model.collection("unknown")
+        assert False
+    except KeyError:
+        pass
@@ -187,6 +187,7 @@ impl AddPrefix for Collections {
         self.comments.prefix(prefix_conf);
         self.equipments.prefix(prefix_conf);
         self.transfers.prefix(prefix_conf);
+        self.interchanges.prefix(prefix_conf);
         self.trip_properties.prefix(prefix_conf);
         self.geometries.prefix(prefix_conf);
         self.admin_stations.prefix(prefix_conf);
@@ -204,7 +205,10 @@ impl AddPrefix for Collections {
         self.grid_exception_dates.prefix(prefix_conf);
         self.grid_periods.prefix(prefix_conf);
         self.grid_rel_calendar_line.prefix(prefix_conf);
+        self.poi_types.prefix(prefix_conf);
+        self.pois.prefix(prefix_conf);
         self.occupancies.prefix(prefix_conf);
+        self.stop_time_occupancies.prefix(prefix_conf);
         self.stop_time_headsigns =
             add_prefix_on_vehicle_journey_ids(&self.stop_time_headsigns, prefix_conf);
         self.stop_time_ids =
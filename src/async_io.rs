@@ -0,0 +1,137 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Reading and writing of ZIP archives of datasets from an `AsyncRead` and
+//! into an `AsyncWrite` (e.g. the objects of an object store like S3 or GCS).
+//!
+//! The archives are read and written in memory, the conversion itself being
+//! synchronous: in an async runtime, the calls should be done in a blocking
+//! task (e.g. `tokio::task::spawn_blocking`) for big datasets. The files of
+//! the NTFS archives written are staged in a temporary directory.
+
+use crate::{gtfs, model::Model, ntfs, utils::zip_to_writer, Result};
+use anyhow::Context;
+use std::io::Cursor;
+use tempfile::tempdir;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::info;
+
+async fn read_to_memory<R>(mut reader: R, source_name: &str) -> Result<Cursor<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buffer = Vec::new();
+    reader
+        .read_to_end(&mut buffer)
+        .await
+        .with_context(|| format!("Error reading {:?}", source_name))?;
+    Ok(Cursor::new(buffer))
+}
+
+async fn write_from_memory<W>(mut writer: W, buffer: Cursor<Vec<u8>>) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(buffer.get_ref()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Imports a `Model` from a ZIP archive of a
+/// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+/// read from `reader`. The `source_name` is needed to have nicer error
+/// messages.
+pub async fn read_ntfs<R>(reader: R, source_name: &str) -> Result<Model>
+where
+    R: AsyncRead + Unpin,
+{
+    let archive = read_to_memory(reader, source_name).await?;
+    ntfs::from_zip_reader(archive, source_name)
+}
+
+/// Imports a `Model` from a ZIP archive of a
+/// [GTFS](https://gtfs.org/reference/static) read from `reader`, following
+/// the `configuration`. The `source_name` is needed to have nicer error
+/// messages.
+pub async fn read_gtfs<R>(
+    reader: R,
+    source_name: &str,
+    configuration: gtfs::Configuration,
+) -> Result<Model>
+where
+    R: AsyncRead + Unpin,
+{
+    let archive = read_to_memory(reader, source_name).await?;
+    gtfs::Reader::new(configuration).parse_zip_reader(archive, source_name)
+}
+
+/// Exports a `Model` to a ZIP archive of a
+/// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+/// written into `writer`, following the `config`.
+pub async fn write_ntfs<W>(model: &Model, writer: W, config: ntfs::WriteConfiguration) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    info!("Writing NTFS to an asynchronous writer");
+    let tmp_dir = tempdir()?;
+    ntfs::write_with_configuration(model, tmp_dir.path(), config)?;
+    let archive = zip_to_writer(tmp_dir.path(), Cursor::new(Vec::new()))?;
+    tmp_dir.close()?;
+    write_from_memory(writer, archive).await
+}
+
+/// Exports a `Model` to a ZIP archive of a
+/// [GTFS](https://gtfs.org/reference/static) written into `writer`,
+/// following the `config`.
+pub async fn write_gtfs<W>(model: Model, writer: W, config: gtfs::WriteConfiguration) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    info!("Writing GTFS to an asynchronous writer");
+    let archive = gtfs::write_to_zip_writer(model, Cursor::new(Vec::new()), "gtfs.zip", config)?;
+    write_from_memory(writer, archive).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn gtfs_to_ntfs_in_memory() {
+        let gtfs = tokio::fs::File::open("tests/fixtures/zipped_gtfs/gtfs.zip")
+            .await
+            .unwrap();
+        let model = read_gtfs(gtfs, "gtfs.zip", gtfs::Configuration::default())
+            .await
+            .unwrap();
+
+        let mut ntfs = Vec::new();
+        write_ntfs(
+            &model,
+            &mut ntfs,
+            ntfs::WriteConfiguration::new(get_test_datetime()),
+        )
+        .await
+        .unwrap();
+        let ntfs_model = read_ntfs(ntfs.as_slice(), "ntfs.zip").await.unwrap();
+
+        assert_eq!(model.stop_points.len(), ntfs_model.stop_points.len());
+        assert_eq!(
+            model.vehicle_journeys.len(),
+            ntfs_model.vehicle_journeys.len()
+        );
+    }
+}
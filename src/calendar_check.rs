@@ -0,0 +1,293 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+//! Detection, and optional trimming, of the service dates outside the
+//! validity period of the datasets or in implausible years (e.g. a service
+//! running until 2099 to mean "forever"), which break the ingestion of the
+//! dataset by the downstream systems.
+
+use crate::{
+    model::Collections,
+    objects::{Date, ValidityPeriod},
+    report::{Report, ReportEntry, Severity},
+};
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Categories of the `Report` produced by the calendar checks
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarReportCategory {
+    /// A service date is in an implausible year
+    ImplausibleDate,
+    /// A service date is outside the validity period of the datasets
+    DateOutsideValidityPeriod,
+    /// The validity period of a dataset has been trimmed to the plausible
+    /// years
+    ValidityPeriodTrimmed,
+    /// The service dates of a calendar have been trimmed
+    DatesTrimmed,
+}
+
+/// Plausible years of the service dates, the dates outside being considered
+/// as errors of the dataset
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlausibleYears {
+    /// First plausible year
+    pub first: i32,
+    /// Last plausible year
+    pub last: i32,
+}
+
+impl Default for PlausibleYears {
+    fn default() -> Self {
+        PlausibleYears {
+            first: 2000,
+            last: 2050,
+        }
+    }
+}
+
+impl PlausibleYears {
+    fn contains(&self, date: Date) -> bool {
+        (self.first..=self.last).contains(&date.year())
+    }
+
+    fn validity_period(&self) -> ValidityPeriod {
+        ValidityPeriod {
+            start_date: Date::from_ymd_opt(self.first, 1, 1).unwrap_or(Date::MIN),
+            end_date: Date::from_ymd_opt(self.last, 12, 31).unwrap_or(Date::MAX),
+        }
+    }
+}
+
+// Union of the validity periods of the datasets, `None` without dataset
+fn datasets_validity_period(collections: &Collections) -> Option<ValidityPeriod> {
+    let start_date = collections.datasets.values().map(|d| d.start_date).min()?;
+    let end_date = collections.datasets.values().map(|d| d.end_date).max()?;
+    Some(ValidityPeriod {
+        start_date,
+        end_date,
+    })
+}
+
+fn is_in(validity_period: &Option<ValidityPeriod>, date: Date) -> bool {
+    validity_period.as_ref().map_or(true, |validity_period| {
+        validity_period.start_date <= date && date <= validity_period.end_date
+    })
+}
+
+/// Check the service dates of the calendars of `collections`.
+///
+/// The calendars with dates in implausible years are errors of the returned
+/// `Report`, the calendars with dates outside the validity period of the
+/// datasets are warnings; the entries are attached to the calendars.
+pub fn check_calendar_dates(
+    collections: &Collections,
+    plausible_years: &PlausibleYears,
+) -> Report<CalendarReportCategory> {
+    let mut report = Report::default();
+    let validity_period = datasets_validity_period(collections);
+    for calendar in collections.calendars.values() {
+        if calendar
+            .dates
+            .iter()
+            .any(|&date| !plausible_years.contains(date))
+        {
+            report.add_entry(
+                Severity::Error,
+                ReportEntry::new(
+                    CalendarReportCategory::ImplausibleDate,
+                    format!(
+                        "service dates outside the years {} to {}",
+                        plausible_years.first, plausible_years.last
+                    ),
+                )
+                .with_object_id(calendar.id.clone()),
+            );
+        } else if calendar
+            .dates
+            .iter()
+            .any(|&date| !is_in(&validity_period, date))
+        {
+            report.add_entry(
+                Severity::Warning,
+                ReportEntry::new(
+                    CalendarReportCategory::DateOutsideValidityPeriod,
+                    "service dates outside the validity period of the datasets".to_string(),
+                )
+                .with_object_id(calendar.id.clone()),
+            );
+        }
+    }
+    report
+}
+
+/// Trim the service dates of the calendars of `collections` to the validity
+/// period of the datasets, itself first trimmed to the `plausible_years`.
+///
+/// Every trimmed dataset and calendar is a warning of the returned `Report`.
+/// The calendars left without any date are removed, along with their trips,
+/// by `Collections::sanitize`.
+pub fn trim_calendar_dates(
+    collections: &mut Collections,
+    plausible_years: &PlausibleYears,
+) -> Report<CalendarReportCategory> {
+    let mut report = Report::default();
+    let plausible_period = plausible_years.validity_period();
+    for dataset_idx in collections.datasets.indexes() {
+        let mut dataset = collections.datasets.index_mut(dataset_idx);
+        if dataset.start_date < plausible_period.start_date
+            || dataset.end_date > plausible_period.end_date
+        {
+            dataset.start_date = dataset.start_date.max(plausible_period.start_date);
+            dataset.end_date = dataset.end_date.min(plausible_period.end_date);
+            report.add_entry(
+                Severity::Warning,
+                ReportEntry::new(
+                    CalendarReportCategory::ValidityPeriodTrimmed,
+                    format!(
+                        "validity period of the dataset trimmed to the years {} to {}",
+                        plausible_years.first, plausible_years.last
+                    ),
+                )
+                .with_object_id(dataset.id.clone()),
+            );
+        }
+    }
+
+    let validity_period = datasets_validity_period(collections);
+    let mut trimmed_count = 0;
+    for calendar_idx in collections.calendars.indexes() {
+        let mut calendar = collections.calendars.index_mut(calendar_idx);
+        let dates_count = calendar.dates.len();
+        calendar
+            .dates
+            .retain(|&date| plausible_years.contains(date) && is_in(&validity_period, date));
+        if calendar.dates.len() < dates_count {
+            report.add_entry(
+                Severity::Warning,
+                ReportEntry::new(
+                    CalendarReportCategory::DatesTrimmed,
+                    "service dates trimmed to the validity period of the datasets".to_string(),
+                )
+                .with_object_id(calendar.id.clone()),
+            );
+            trimmed_count += 1;
+        }
+    }
+    info!("{} calendar(s) trimmed", trimmed_count);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn dates(collections: &Collections, calendar_id: &str) -> Vec<String> {
+        collections
+            .calendars
+            .get(calendar_id)
+            .unwrap()
+            .dates
+            .iter()
+            .map(|date| date.to_string())
+            .collect()
+    }
+
+    /// Calendars within, across and far beyond a validity period starting on
+    /// 2020-01-01 and ending on `end_date`
+    fn three_calendars(end_date: &str) -> Collections {
+        let mut collections = ModelBuilder::default()
+            .calendar("c1", &["2020-01-01", "2020-01-02"])
+            .calendar("c2", &["2020-01-01", "2020-02-01"])
+            .calendar("c3", &["2020-01-01", "2099-12-31"])
+            .vj("vj1", |vj| {
+                vj.calendar("c1").st("A", "10:00:00", "10:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.calendar("c2").st("A", "10:00:00", "10:01:00");
+            })
+            .vj("vj3", |vj| {
+                vj.calendar("c3").st("A", "10:00:00", "10:01:00");
+            })
+            .build_model()
+            .into_collections();
+        for dataset_idx in collections.datasets.indexes() {
+            let mut dataset = collections.datasets.index_mut(dataset_idx);
+            dataset.start_date = "2020-01-01".parse().unwrap();
+            dataset.end_date = end_date.parse().unwrap();
+        }
+        collections
+    }
+
+    #[test]
+    fn detect_calendar_dates() {
+        let collections = three_calendars("2020-01-31");
+        let report = check_calendar_dates(&collections, &PlausibleYears::default());
+
+        assert_eq!(1, report.errors().len());
+        assert_eq!(
+            CalendarReportCategory::ImplausibleDate,
+            report.errors()[0].category
+        );
+        assert_eq!(Some("c3"), report.errors()[0].object_id.as_deref());
+        assert_eq!(1, report.warnings().len());
+        assert_eq!(
+            CalendarReportCategory::DateOutsideValidityPeriod,
+            report.warnings()[0].category
+        );
+        assert_eq!(Some("c2"), report.warnings()[0].object_id.as_deref());
+    }
+
+    #[test]
+    fn trim_implausible_validity_period() {
+        let mut collections = three_calendars("2099-12-31");
+        let report = trim_calendar_dates(&mut collections, &PlausibleYears::default());
+
+        assert_eq!(
+            1,
+            report.count_category(
+                Severity::Warning,
+                &CalendarReportCategory::ValidityPeriodTrimmed
+            )
+        );
+        assert_eq!(
+            1,
+            report.count_category(Severity::Warning, &CalendarReportCategory::DatesTrimmed)
+        );
+        assert_eq!(vec!["2020-01-01", "2020-01-02"], dates(&collections, "c1"));
+        assert_eq!(vec!["2020-01-01", "2020-02-01"], dates(&collections, "c2"));
+        assert_eq!(vec!["2020-01-01"], dates(&collections, "c3"));
+        assert!(collections
+            .datasets
+            .values()
+            .all(|dataset| dataset.end_date.to_string() == "2050-12-31"));
+    }
+
+    #[test]
+    fn trim_to_validity_period() {
+        let mut collections = three_calendars("2020-01-31");
+        let report = trim_calendar_dates(&mut collections, &PlausibleYears::default());
+
+        assert_eq!(2, report.warnings().len());
+        assert_eq!(vec!["2020-01-01", "2020-01-02"], dates(&collections, "c1"));
+        assert_eq!(vec!["2020-01-01"], dates(&collections, "c2"));
+        assert_eq!(vec!["2020-01-01"], dates(&collections, "c3"));
+    }
+}
@@ -17,7 +17,8 @@
 //! - calendar.txt and calendar_dates.txt format are identical between the GTFS
 //!   and NTFS
 
-use crate::file_handler::FileHandler;
+use crate::csv_format::CsvFormat;
+use crate::file_handler::{FileHandler, FileWriter, PathFileWriter};
 use crate::model::Collections;
 use crate::objects::{self, Date, ExceptionType};
 use crate::parser::read_objects;
@@ -30,7 +31,7 @@ use serde::{Deserialize, Serialize};
 use skip_error::skip_error_and_warn;
 use std::collections::BTreeSet;
 use std::path;
-use tracing::info;
+use tracing::{info, info_span};
 use typed_index_collection::*;
 
 /// Structure to serialize/deserialize the file calendar_dates.txt
@@ -141,28 +142,28 @@ where
         bail!("calendar_dates.txt or calendar.txt not found");
     }
     for calendar_date in calendar_dates {
-        let is_inserted = calendars
-            .get_mut(&calendar_date.service_id)
-            .map(|mut calendar| match calendar_date.exception_type {
-                ExceptionType::Add => {
-                    calendar.dates.insert(calendar_date.date);
+        match calendars.get_idx(&calendar_date.service_id) {
+            Some(calendar_idx) => {
+                let mut calendar = calendars.index_mut(calendar_idx);
+                match calendar_date.exception_type {
+                    ExceptionType::Add => {
+                        calendar.dates.insert(calendar_date.date);
+                    }
+                    ExceptionType::Remove => {
+                        calendar.dates.remove(&calendar_date.date);
+                    }
                 }
-                ExceptionType::Remove => {
-                    calendar.dates.remove(&calendar_date.date);
-                }
-            });
-        is_inserted.unwrap_or_else(|| {
-            if calendar_date.exception_type == ExceptionType::Add {
+            }
+            None if calendar_date.exception_type == ExceptionType::Add => {
                 let mut dates = BTreeSet::new();
                 dates.insert(calendar_date.date);
-                calendars
-                    .push(objects::Calendar {
-                        id: calendar_date.service_id,
-                        dates,
-                    })
-                    .unwrap();
+                calendars.push(objects::Calendar {
+                    id: calendar_date.service_id,
+                    dates,
+                })?;
             }
-        });
+            None => {}
+        }
     }
     Ok(())
 }
@@ -208,16 +209,74 @@ where
     _manage_calendars(file_handler, collections)
 }
 
+/// Set operation applied on the dates of calendars
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarOperation {
+    /// The dates are added to the calendars
+    Union,
+    /// Only the dates of the calendars which are also in the dates are kept
+    Intersection,
+    /// The dates are removed from the calendars
+    Subtraction,
+}
+
+/// Returns all the dates between `start_date` and `end_date` (included)
+pub fn dates_between(start_date: Date, end_date: Date) -> BTreeSet<Date> {
+    start_date
+        .iter_days()
+        .take_while(|date| *date <= end_date)
+        .collect()
+}
+
+/// Applies the `operation` with `dates` on all the calendars of the
+/// `collections`, e.g. to remove the service on strike days or to restrict
+/// the service to a period.
+///
+/// The calendars left without any date are kept, they are removed along with
+/// their trips when building a `Model`.
+pub fn apply_operation(
+    collections: &mut Collections,
+    operation: CalendarOperation,
+    dates: &BTreeSet<Date>,
+) {
+    info!(
+        "Applying {:?} of {} dates on calendars",
+        operation,
+        dates.len()
+    );
+    for calendar_idx in collections.calendars.indexes() {
+        let mut calendar = collections.calendars.index_mut(calendar_idx);
+        match operation {
+            CalendarOperation::Union => calendar.union(dates),
+            CalendarOperation::Intersection => calendar.intersection(dates),
+            CalendarOperation::Subtraction => calendar.subtraction(dates),
+        }
+    }
+}
+
 /// Write the calendar_dates.txt file into a Path from a list of Calendar
 pub fn write_calendar_dates(
     path: &path::Path,
     calendars: &CollectionWithId<objects::Calendar>,
+    csv_format: &CsvFormat,
 ) -> Result<()> {
+    write_calendar_dates_to(&mut PathFileWriter::new(path), calendars, csv_format)
+}
+
+/// Write the calendar_dates.txt file with a FileWriter from a list of
+/// Calendar
+pub fn write_calendar_dates_to<H>(
+    file_writer: &mut H,
+    calendars: &CollectionWithId<objects::Calendar>,
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
     info!("Writing calendar_dates.txt");
-    let calendar_dates_path = path.join("calendar_dates.txt");
     let mut translations: Vec<Calendar> = vec![];
     let mut exceptions: Vec<CalendarDate> = vec![];
-    for c in calendars.values() {
+    for c in crate::utils::sorted_by_id(calendars) {
         let translation = translate(&c.dates);
         if !translation.operating_days.is_empty() {
             let validity_period = skip_error_and_warn!(translation.validity_period.ok_or_else(
@@ -245,8 +304,15 @@ pub fn write_calendar_dates(
         }
     }
     if !exceptions.is_empty() {
-        let mut wtr = csv::Writer::from_path(&calendar_dates_path)
-            .with_context(|| format!("Error reading {:?}", calendar_dates_path))?;
+        let _span = info_span!(
+            "write_file",
+            file = "calendar_dates.txt",
+            objects = exceptions.len()
+        )
+        .entered();
+        let (writer, calendar_dates_path) =
+            (&mut *file_writer).create_file("calendar_dates.txt")?;
+        let mut wtr = csv_format.writer(writer);
         for e in exceptions {
             wtr.serialize(&e)
                 .with_context(|| format!("Error reading {:?}", calendar_dates_path))?;
@@ -254,19 +320,40 @@ pub fn write_calendar_dates(
         wtr.flush()
             .with_context(|| format!("Error reading {:?}", calendar_dates_path))?;
     }
-    write_calendar(path, &translations)
+    write_calendar_to(file_writer, &translations, csv_format)
 }
 
 /// Write the calendar.txt file into a Path from a list of Calendar
-pub fn write_calendar(path: &path::Path, calendars: &[Calendar]) -> Result<()> {
+pub fn write_calendar(
+    path: &path::Path,
+    calendars: &[Calendar],
+    csv_format: &CsvFormat,
+) -> Result<()> {
+    write_calendar_to(&mut PathFileWriter::new(path), calendars, csv_format)
+}
+
+/// Write the calendar.txt file with a FileWriter from a list of Calendar
+pub fn write_calendar_to<H>(
+    file_writer: &mut H,
+    calendars: &[Calendar],
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    let _span = info_span!(
+        "write_file",
+        file = "calendar.txt",
+        objects = calendars.len()
+    )
+    .entered();
     info!("Writing calendar.txt");
     if calendars.is_empty() {
         return Ok(());
     }
 
-    let calendar_path = path.join("calendar.txt");
-    let mut wtr = csv::Writer::from_path(&calendar_path)
-        .with_context(|| format!("Error reading {:?}", calendar_path))?;
+    let (writer, calendar_path) = file_writer.create_file("calendar.txt")?;
+    let mut wtr = csv_format.writer(writer);
     for calendar in calendars {
         wtr.serialize(calendar)
             .with_context(|| format!("Error reading {:?}", calendar_path))?;
@@ -275,3 +362,41 @@ pub fn write_calendar(path: &path::Path, calendars: &[Calendar]) -> Result<()> {
         .with_context(|| format!("Error reading {:?}", calendar_path))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn date(date: &str) -> Date {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn remove_strike_days() {
+        let mut collections = Collections::default();
+        collections
+            .calendars
+            .push(objects::Calendar {
+                id: "service".to_string(),
+                dates: dates_between(date("2020-01-01"), date("2020-01-05")),
+            })
+            .unwrap();
+        let strike_days = vec![date("2020-01-02"), date("2020-01-03")]
+            .into_iter()
+            .collect();
+        apply_operation(
+            &mut collections,
+            CalendarOperation::Subtraction,
+            &strike_days,
+        );
+        let expected: BTreeSet<Date> =
+            vec![date("2020-01-01"), date("2020-01-04"), date("2020-01-05")]
+                .into_iter()
+                .collect();
+        assert_eq!(
+            expected,
+            collections.calendars.get("service").unwrap().dates
+        );
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Codes of the objects in external systems (`object_codes.txt` of a NTFS):
+//! registry of the known code systems, validation of the format of their
+//! codes and lookup of the objects by code.
+
+use crate::{
+    model::Collections,
+    objects::{Codes, Company, Line, Network, Route, StopArea, StopPoint, VehicleJourney},
+    report::Report,
+    Result,
+};
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+use typed_index_collection::{CollectionWithId, Id, Idx};
+
+/// A known system of codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CodeSystem {
+    /// Identifier of the object in the source data (e.g. the `stop_id` of a
+    /// GTFS)
+    Source,
+    /// `stop_code` of a GTFS stop
+    GtfsStopCode,
+    /// Station code of the International Union of Railways (7 or 8 digits)
+    Uic,
+    /// Identification of Fixed Objects in Public Transport
+    /// (e.g. `FR:75056:1234`)
+    Ifopt,
+}
+
+impl CodeSystem {
+    /// All the known systems
+    pub const ALL: [CodeSystem; 4] = [
+        CodeSystem::Source,
+        CodeSystem::GtfsStopCode,
+        CodeSystem::Uic,
+        CodeSystem::Ifopt,
+    ];
+
+    /// Name of the system, as written in the codes of the objects
+    pub fn name(self) -> &'static str {
+        match self {
+            CodeSystem::Source => "source",
+            CodeSystem::GtfsStopCode => "gtfs_stop_code",
+            CodeSystem::Uic => "UIC",
+            CodeSystem::Ifopt => "IFOPT",
+        }
+    }
+
+    /// The known system named `name` (ignoring the case)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|system| system.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Check that `code` has the format of the codes of the system
+    pub fn validate(self, code: &str) -> Result<()> {
+        if code.is_empty() {
+            bail!("empty {} code", self.name());
+        }
+        match self {
+            CodeSystem::Source | CodeSystem::GtfsStopCode => {}
+            CodeSystem::Uic => {
+                if !(7..=8).contains(&code.len()) || !code.chars().all(|c| c.is_ascii_digit()) {
+                    bail!("UIC code {:?} is not made of 7 or 8 digits", code);
+                }
+            }
+            CodeSystem::Ifopt => {
+                let parts: Vec<&str> = code.split(':').collect();
+                let country = parts[0];
+                if parts.len() < 3
+                    || parts.iter().any(|part| part.is_empty())
+                    || country.len() != 2
+                    || !country.chars().all(|c| c.is_ascii_uppercase())
+                {
+                    bail!(
+                        "IFOPT code {:?} is not of the form <country>:<area>:<stop>[:...]",
+                        code
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Category of the entries of the report of `validate_codes`
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeReportCategory {
+    /// A system of codes not in the registry of the known systems (reported
+    /// once per system)
+    UnknownSystem,
+    /// A code of a known system with an invalid format
+    InvalidCode,
+}
+
+fn validate_collection<T: Id<T> + Codes>(
+    collection: &CollectionWithId<T>,
+    object_type: &str,
+    unknown_systems: &mut BTreeSet<String>,
+    report: &mut Report<CodeReportCategory>,
+) {
+    for object in collection.values() {
+        for (system, code) in object.codes() {
+            match CodeSystem::from_name(system) {
+                Some(known_system) => {
+                    if let Err(error) = known_system.validate(code) {
+                        report.add_error(
+                            format!("{} {}: {}", object_type, object.id(), error),
+                            CodeReportCategory::InvalidCode,
+                        );
+                    }
+                }
+                None => {
+                    unknown_systems.insert(system.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Check the codes of all the objects of `collections`: a code of a known
+/// system with an invalid format is an error, a system not in the registry
+/// is a warning.
+pub fn validate_codes(collections: &Collections) -> Report<CodeReportCategory> {
+    let mut report = Report::default();
+    let mut unknown_systems = BTreeSet::new();
+    let unknown = &mut unknown_systems;
+    let r = &mut report;
+    validate_collection(&collections.networks, "network", unknown, r);
+    validate_collection(&collections.companies, "company", unknown, r);
+    validate_collection(&collections.lines, "line", unknown, r);
+    validate_collection(&collections.routes, "route", unknown, r);
+    validate_collection(&collections.vehicle_journeys, "vehicle journey", unknown, r);
+    validate_collection(&collections.stop_areas, "stop area", unknown, r);
+    validate_collection(&collections.stop_points, "stop point", unknown, r);
+    for system in unknown_systems {
+        report.add_warning(
+            format!("unknown code system {:?}", system),
+            CodeReportCategory::UnknownSystem,
+        );
+    }
+    report
+}
+
+/// An object having a code, as returned by `CodeIndex::get`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodedObject {
+    /// A network
+    Network(Idx<Network>),
+    /// A company
+    Company(Idx<Company>),
+    /// A line
+    Line(Idx<Line>),
+    /// A route
+    Route(Idx<Route>),
+    /// A vehicle journey
+    VehicleJourney(Idx<VehicleJourney>),
+    /// A stop area
+    StopArea(Idx<StopArea>),
+    /// A stop point
+    StopPoint(Idx<StopPoint>),
+}
+
+/// An object type having codes, which can be looked up by code with
+/// `Model::object_by_code`
+pub trait CodedType: Sized {
+    /// Index of the object if `coded_object` is of this type
+    fn from_coded_object(coded_object: CodedObject) -> Option<Idx<Self>>;
+}
+
+macro_rules! impl_coded_type {
+    ($type:ident) => {
+        impl CodedType for $type {
+            fn from_coded_object(coded_object: CodedObject) -> Option<Idx<Self>> {
+                match coded_object {
+                    CodedObject::$type(idx) => Some(idx),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_coded_type!(Network);
+impl_coded_type!(Company);
+impl_coded_type!(Line);
+impl_coded_type!(Route);
+impl_coded_type!(VehicleJourney);
+impl_coded_type!(StopArea);
+impl_coded_type!(StopPoint);
+
+/// Code of an object in an external system, selecting the object in the rule
+/// files (e.g. `{"object_system": "SIRI", "object_code": "C01742"}`) for their
+/// authors knowing the codes of the objects rather than their identifiers in
+/// the model
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ObjectCode {
+    /// System of the code
+    pub object_system: String,
+    /// Code of the object in the system
+    pub object_code: String,
+}
+
+impl ObjectCode {
+    /// Identifier of the object of `collection` having the code, looked up in
+    /// `index` like `Model::object_by_code` does
+    pub fn object_id<'a, T: CodedType + Id<T>>(
+        &self,
+        index: &CodeIndex,
+        collection: &'a CollectionWithId<T>,
+    ) -> Option<&'a str> {
+        index
+            .object::<T>(&self.object_system, &self.object_code)
+            .map(|idx| collection[idx].id())
+    }
+}
+
+impl fmt::Display for ObjectCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "code {:?} of {}", self.object_code, self.object_system)
+    }
+}
+
+/// Index of the objects of a `Collections` by system and code
+#[derive(Debug, Default)]
+pub struct CodeIndex {
+    objects: HashMap<String, HashMap<String, Vec<CodedObject>>>,
+}
+
+impl CodeIndex {
+    /// Index the objects of all the collections having codes
+    pub fn new(collections: &Collections) -> Self {
+        let mut index = CodeIndex::default();
+        index.add(&collections.networks, CodedObject::Network);
+        index.add(&collections.companies, CodedObject::Company);
+        index.add(&collections.lines, CodedObject::Line);
+        index.add(&collections.routes, CodedObject::Route);
+        index.add(&collections.vehicle_journeys, CodedObject::VehicleJourney);
+        index.add(&collections.stop_areas, CodedObject::StopArea);
+        index.add(&collections.stop_points, CodedObject::StopPoint);
+        index
+    }
+
+    fn add<T: Id<T> + Codes>(
+        &mut self,
+        collection: &CollectionWithId<T>,
+        coded_object: fn(Idx<T>) -> CodedObject,
+    ) {
+        for (idx, object) in collection.iter() {
+            for (system, code) in object.codes() {
+                self.objects
+                    .entry(system.clone())
+                    .or_default()
+                    .entry(code.clone())
+                    .or_default()
+                    .push(coded_object(idx));
+            }
+        }
+    }
+
+    /// The objects having the code `code` in the system `system`, in the
+    /// order of the collections then of the objects
+    pub fn get(&self, system: &str, code: &str) -> &[CodedObject] {
+        self.objects
+            .get(system)
+            .and_then(|codes| codes.get(code))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// The object of type `T` having the code `code` in the system `system`,
+    /// the first one in the order of the collection if several objects share
+    /// the code
+    pub fn object<T: CodedType>(&self, system: &str, code: &str) -> Option<Idx<T>> {
+        self.get(system, code)
+            .iter()
+            .find_map(|coded_object| T::from_coded_object(*coded_object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::one_vehicle_journey;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn validate_formats() {
+        assert!(CodeSystem::Uic.validate("8768600").is_ok());
+        assert!(CodeSystem::Uic.validate("87686006").is_ok());
+        assert!(CodeSystem::Uic.validate("876860").is_err());
+        assert!(CodeSystem::Uic.validate("87686A06").is_err());
+        assert!(CodeSystem::Ifopt.validate("DE:08111:6115:1:1").is_ok());
+        assert!(CodeSystem::Ifopt.validate("FR:75056").is_err());
+        assert!(CodeSystem::Ifopt.validate("fr:75056:1234").is_err());
+        assert!(CodeSystem::Source.validate("").is_err());
+        assert_eq!(Some(CodeSystem::Uic), CodeSystem::from_name("uic"));
+        assert_eq!(None, CodeSystem::from_name("my_system"));
+    }
+
+    #[test]
+    fn report_invalid_codes() {
+        let mut collections = one_vehicle_journey().into_collections();
+        let mut stop_point = collections.stop_points.get_mut("A").unwrap();
+        stop_point
+            .codes
+            .insert(("IFOPT".to_string(), "FR:75056:1234".to_string()));
+        drop(stop_point);
+        let mut stop_point = collections.stop_points.get_mut("B").unwrap();
+        stop_point
+            .codes
+            .insert(("UIC".to_string(), "876-86".to_string()));
+        stop_point
+            .codes
+            .insert(("my_system".to_string(), "B".to_string()));
+        drop(stop_point);
+        let report = validate_codes(&collections);
+
+        assert_eq!(1, report.errors().len());
+        assert_eq!(CodeReportCategory::InvalidCode, report.errors()[0].category);
+        assert!(report.errors()[0].message.starts_with("stop point B: "));
+        assert_eq!(1, report.warnings().len());
+        assert_eq!(
+            "unknown code system \"my_system\"",
+            report.warnings()[0].message
+        );
+    }
+
+    #[test]
+    fn lookup_by_code() {
+        let mut collections = one_vehicle_journey().into_collections();
+        collections
+            .stop_areas
+            .get_mut("sa:A")
+            .unwrap()
+            .codes
+            .insert(("UIC".to_string(), "87686006".to_string()));
+        collections
+            .stop_points
+            .get_mut("A")
+            .unwrap()
+            .codes
+            .insert(("UIC".to_string(), "87686006".to_string()));
+        let index = CodeIndex::new(&collections);
+
+        assert_eq!(
+            &[
+                CodedObject::StopArea(collections.stop_areas.get_idx("sa:A").unwrap()),
+                CodedObject::StopPoint(collections.stop_points.get_idx("A").unwrap()),
+            ],
+            index.get("UIC", "87686006")
+        );
+        assert!(index.get("UIC", "unknown").is_empty());
+        assert!(index.get("unknown", "87686006").is_empty());
+    }
+}
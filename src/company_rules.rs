@@ -0,0 +1,445 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Update of the name and of the contact details of the companies, and
+//! merging of the duplicate companies produced by the merge of several
+//! sources.
+
+use crate::{
+    codes::{CodeIndex, ObjectCode},
+    model::Collections,
+    objects::{Company, ObjectType},
+    report::{Report, ReportEntry, Severity},
+    Result,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs::File,
+    path::Path,
+};
+use tracing::info;
+
+/// A rule updating the company `company_id` and merging the companies
+/// `merged_company_ids` into it, the companies being selected by identifier
+/// or by code
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompanyRule {
+    /// Identifier of the company kept
+    #[serde(default)]
+    pub company_id: String,
+    /// Code of the company kept, selecting it instead of `company_id`
+    #[serde(default)]
+    pub company_object_code: Option<ObjectCode>,
+    /// Identifiers of the companies merged into `company_id` and then removed
+    #[serde(default)]
+    pub merged_company_ids: Vec<String>,
+    /// Codes of other companies merged into the kept company
+    #[serde(default)]
+    pub merged_company_object_codes: Vec<ObjectCode>,
+    /// New name of the company, the name is kept if empty
+    #[serde(default)]
+    pub name: Option<String>,
+    /// New address of the company, the address is kept if empty
+    #[serde(default)]
+    pub address: Option<String>,
+    /// New website of the company, the website is kept if empty
+    #[serde(default)]
+    pub url: Option<String>,
+    /// New email address of the company, the email address is kept if empty
+    #[serde(default)]
+    pub mail: Option<String>,
+    /// New phone number of the company, the phone number is kept if empty
+    #[serde(default)]
+    pub phone: Option<String>,
+}
+
+/// Categories of the `Report` produced by the application of the company
+/// rules
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompanyRulesReportCategory {
+    /// A company has been merged into another one
+    CompanyMerged,
+    /// A property of a company has been changed
+    CompanyUpdated,
+    /// A company of a rule doesn't exist, the rule is ignored or partially
+    /// applied
+    UnknownCompany,
+    /// A company is used by several rules, only the first one is applied
+    CompanyAlreadyMerged,
+}
+
+/// Read the company rules from the JSON file `path`, containing an array of
+/// `CompanyRule`
+pub fn read_rules<P: AsRef<Path>>(path: P) -> Result<Vec<CompanyRule>> {
+    let path = path.as_ref();
+    info!("Reading company rules from {:?}", path);
+    let file = File::open(path).with_context(|| format!("Error reading {:?}", path))?;
+    let rules =
+        serde_json::from_reader(file).with_context(|| format!("Error parsing {:?}", path))?;
+    Ok(rules)
+}
+
+// `rule` with the companies selected by code replaced by their identifier,
+// `None` if the kept company is unknown
+fn select_by_code<'a>(
+    rule: &'a CompanyRule,
+    collections: &Collections,
+    codes: &mut Option<CodeIndex>,
+    report: &mut Report<CompanyRulesReportCategory>,
+) -> Option<Cow<'a, CompanyRule>> {
+    if rule.company_object_code.is_none() && rule.merged_company_object_codes.is_empty() {
+        return Some(Cow::Borrowed(rule));
+    }
+    let codes = codes.get_or_insert_with(|| CodeIndex::new(collections));
+    let mut rule = rule.clone();
+    if let Some(object_code) = rule.company_object_code.take() {
+        match object_code.object_id(codes, &collections.companies) {
+            Some(company_id) => rule.company_id = company_id.to_string(),
+            None => {
+                report.add_error(
+                    format!("no company has the {}, the rule is ignored", object_code),
+                    CompanyRulesReportCategory::UnknownCompany,
+                );
+                return None;
+            }
+        }
+    }
+    for merged_object_code in std::mem::take(&mut rule.merged_company_object_codes) {
+        match merged_object_code.object_id(codes, &collections.companies) {
+            Some(merged_company_id) => rule.merged_company_ids.push(merged_company_id.to_string()),
+            None => report.add_error(
+                format!(
+                    "no company has the {}, it is not merged into company {}",
+                    merged_object_code, rule.company_id
+                ),
+                CompanyRulesReportCategory::UnknownCompany,
+            ),
+        }
+    }
+    Some(Cow::Owned(rule))
+}
+
+fn report_update(
+    report: &mut Report<CompanyRulesReportCategory>,
+    company_id: &str,
+    property: &str,
+    value: &str,
+) {
+    report.add_entry(
+        Severity::Info,
+        ReportEntry::new(
+            CompanyRulesReportCategory::CompanyUpdated,
+            format!("{} of company {} set to {:?}", property, company_id, value),
+        )
+        .with_object_id(company_id),
+    );
+}
+
+// Fill the missing contact details of `company` with the ones of
+// `merged_company`
+fn merge_company(company: &mut Company, merged_company: Company) {
+    company.address = company.address.take().or(merged_company.address);
+    company.url = company.url.take().or(merged_company.url);
+    company.mail = company.mail.take().or(merged_company.mail);
+    company.phone = company.phone.take().or(merged_company.phone);
+    company.codes.extend(merged_company.codes);
+}
+
+fn update_company(
+    company: &mut Company,
+    rule: &CompanyRule,
+    report: &mut Report<CompanyRulesReportCategory>,
+) {
+    if let Some(name) = &rule.name {
+        company.name = name.clone();
+        report_update(report, &company.id, "name", name);
+    }
+    let contact_details = [
+        ("address", &rule.address, &mut company.address),
+        ("url", &rule.url, &mut company.url),
+        ("mail", &rule.mail, &mut company.mail),
+        ("phone", &rule.phone, &mut company.phone),
+    ];
+    for (property, new_value, value) in contact_details {
+        if let Some(new_value) = new_value {
+            *value = Some(new_value.clone());
+            report_update(report, &rule.company_id, property, new_value);
+        }
+    }
+}
+
+/// Update and merge the companies of `collections` following `rules`.
+///
+/// The vehicle journeys of a merged company are attached to the kept
+/// company, which receives the codes of the merged company and its contact
+/// details when it has none. The ticket use perimeters referencing a merged
+/// company are updated, then the merged companies are removed.
+///
+/// Every change and every ignored part of a rule is listed in the returned
+/// `Report`, attached to the company.
+pub fn apply_company_rules(
+    collections: &mut Collections,
+    rules: &[CompanyRule],
+) -> Report<CompanyRulesReportCategory> {
+    let mut report = Report::default();
+    // merged company id => kept company id
+    let mut replacements: HashMap<String, String> = HashMap::new();
+    let mut kept_company_ids: HashSet<String> = HashSet::new();
+    // built for the first rule selecting companies by code
+    let mut codes = None;
+    for rule in rules {
+        let rule = match select_by_code(rule, collections, &mut codes, &mut report) {
+            Some(rule) => rule,
+            None => continue,
+        };
+        let company_idx = match collections.companies.get_idx(&rule.company_id) {
+            Some(company_idx) => company_idx,
+            None => {
+                report.add_error(
+                    format!(
+                        "company {} doesn't exist, the rule is ignored",
+                        rule.company_id
+                    ),
+                    CompanyRulesReportCategory::UnknownCompany,
+                );
+                continue;
+            }
+        };
+        if replacements.contains_key(&rule.company_id) {
+            report.add_error(
+                format!(
+                    "company {} has already been merged, the rule is ignored",
+                    rule.company_id
+                ),
+                CompanyRulesReportCategory::CompanyAlreadyMerged,
+            );
+            continue;
+        }
+        for merged_company_id in &rule.merged_company_ids {
+            if *merged_company_id == rule.company_id {
+                continue;
+            }
+            if replacements.contains_key(merged_company_id)
+                || kept_company_ids.contains(merged_company_id)
+            {
+                report.add_error(
+                    format!(
+                        "company {} is already used by another rule, it is not merged into company {}",
+                        merged_company_id, rule.company_id
+                    ),
+                    CompanyRulesReportCategory::CompanyAlreadyMerged,
+                );
+                continue;
+            }
+            let merged_company = match collections.companies.get(merged_company_id) {
+                Some(merged_company) => merged_company.clone(),
+                None => {
+                    report.add_error(
+                        format!(
+                            "company {} doesn't exist, it is not merged into company {}",
+                            merged_company_id, rule.company_id
+                        ),
+                        CompanyRulesReportCategory::UnknownCompany,
+                    );
+                    continue;
+                }
+            };
+            merge_company(
+                &mut collections.companies.index_mut(company_idx),
+                merged_company,
+            );
+            report.add_entry(
+                Severity::Info,
+                ReportEntry::new(
+                    CompanyRulesReportCategory::CompanyMerged,
+                    format!(
+                        "company {} is merged into company {}",
+                        merged_company_id, rule.company_id
+                    ),
+                )
+                .with_object_id(merged_company_id.clone()),
+            );
+            replacements.insert(merged_company_id.clone(), rule.company_id.clone());
+        }
+        kept_company_ids.insert(rule.company_id.clone());
+        update_company(
+            &mut collections.companies.index_mut(company_idx),
+            &rule,
+            &mut report,
+        );
+    }
+    if replacements.is_empty() {
+        return report;
+    }
+
+    for vj_idx in collections.vehicle_journeys.indexes() {
+        let new_company_id = replacements.get(&collections.vehicle_journeys[vj_idx].company_id);
+        if let Some(new_company_id) = new_company_id {
+            collections.vehicle_journeys.index_mut(vj_idx).company_id = new_company_id.clone();
+        }
+    }
+    for perimeter in collections.ticket_use_perimeters.values_mut() {
+        if perimeter.object_type == ObjectType::Company {
+            if let Some(new_company_id) = replacements.get(&perimeter.object_id) {
+                perimeter.object_id = new_company_id.clone();
+            }
+        }
+    }
+    collections
+        .companies
+        .retain(|company| !replacements.contains_key(&company.id));
+    info!("{} company(ies) merged", replacements.len());
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::one_vehicle_journey;
+    use pretty_assertions::assert_eq;
+
+    fn rule() -> CompanyRule {
+        CompanyRule {
+            company_id: "default_company".to_string(),
+            merged_company_ids: vec!["OPERATOR".to_string()],
+            name: Some("The Operator".to_string()),
+            url: Some("https://operator.example.com".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge_companies() {
+        let mut collections = one_vehicle_journey().into_collections();
+        collections
+            .companies
+            .push(Company {
+                id: "OPERATOR".to_string(),
+                name: "Operator".to_string(),
+                phone: Some("0123456789".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .company_id = "OPERATOR".to_string();
+        let report = apply_company_rules(&mut collections, &[rule()]);
+
+        assert!(report.errors().is_empty());
+        assert!(!collections.companies.contains_id("OPERATOR"));
+        let company = collections.companies.get("default_company").unwrap();
+        assert_eq!("The Operator", company.name);
+        assert_eq!(Some("0123456789".to_string()), company.phone);
+        assert_eq!(
+            Some("https://operator.example.com".to_string()),
+            company.url
+        );
+        assert_eq!(
+            "default_company",
+            collections.vehicle_journeys.get("vj1").unwrap().company_id
+        );
+        let categories: Vec<_> = report.infos().iter().map(|entry| entry.category).collect();
+        assert_eq!(
+            vec![
+                CompanyRulesReportCategory::CompanyMerged,
+                CompanyRulesReportCategory::CompanyUpdated,
+                CompanyRulesReportCategory::CompanyUpdated,
+            ],
+            categories
+        );
+    }
+
+    #[test]
+    fn unknown_company() {
+        let mut collections = one_vehicle_journey().into_collections();
+        collections
+            .companies
+            .push(Company {
+                id: "OPERATOR".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        let rule = CompanyRule {
+            company_id: "unknown".to_string(),
+            ..rule()
+        };
+        let report = apply_company_rules(&mut collections, &[rule]);
+
+        assert_eq!(1, report.errors().len());
+        assert_eq!(
+            CompanyRulesReportCategory::UnknownCompany,
+            report.errors()[0].category
+        );
+        assert!(collections.companies.contains_id("OPERATOR"));
+    }
+
+    #[test]
+    fn select_companies_by_code() {
+        let mut collections = one_vehicle_journey().into_collections();
+        collections
+            .companies
+            .get_mut("default_company")
+            .unwrap()
+            .codes
+            .insert(("SIRI".to_string(), "OP1".to_string()));
+        collections
+            .companies
+            .push(Company {
+                id: "OPERATOR".to_string(),
+                codes: vec![("SIRI".to_string(), "OP2".to_string())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            })
+            .unwrap();
+        let code = |object_code: &str| ObjectCode {
+            object_system: "SIRI".to_string(),
+            object_code: object_code.to_string(),
+        };
+        let rules: Vec<CompanyRule> = serde_json::from_str(
+            r#"[{
+                "company_object_code": {"object_system": "SIRI", "object_code": "OP1"},
+                "merged_company_object_codes": [
+                    {"object_system": "SIRI", "object_code": "OP2"},
+                    {"object_system": "SIRI", "object_code": "unknown"}
+                ],
+                "name": "The Operator"
+            }]"#,
+        )
+        .unwrap();
+        assert_eq!(Some(code("OP1")), rules[0].company_object_code);
+        assert_eq!(
+            vec![code("OP2"), code("unknown")],
+            rules[0].merged_company_object_codes
+        );
+        let report = apply_company_rules(&mut collections, &rules);
+
+        assert_eq!(1, report.errors().len());
+        assert_eq!(
+            "no company has the code \"unknown\" of SIRI, it is not merged into company default_company",
+            report.errors()[0].message
+        );
+        assert!(!collections.companies.contains_id("OPERATOR"));
+        assert_eq!(
+            "The Operator",
+            collections.companies.get("default_company").unwrap().name
+        );
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Analysis of the connectivity of a network: stops never served, stop areas
+//! unreachable from the rest of the network and trips referencing missing
+//! stops.
+
+use crate::{
+    model::Collections,
+    objects::{StopPoint, StopType},
+    report::Report,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use typed_index_collection::Idx;
+
+/// Categories of the `Report` produced by the connectivity analysis
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectivityReportCategory {
+    /// The stop point is not served by any trip
+    UnusedStopPoint,
+    /// The stop area is linked to no other stop area by a trip, a transfer or
+    /// a pathway
+    IsolatedStopArea,
+    /// The stop area belongs to a sub-network disconnected from the main one
+    UnreachableStopArea,
+    /// A stop time of the trip references a stop point that doesn't exist
+    MissingStopPoint,
+}
+
+// Stop area of a stop point or of a stop location (entrance, node, ...)
+fn stop_area_id<'a>(collections: &'a Collections, stop_id: &str) -> Option<&'a str> {
+    collections
+        .stop_points
+        .get(stop_id)
+        .map(|stop_point| stop_point.stop_area_id.as_str())
+        .or_else(|| {
+            collections
+                .stop_locations
+                .get(stop_id)
+                .and_then(|stop_location| stop_location.parent_id.as_deref())
+                .and_then(|parent_id| {
+                    collections
+                        .stop_points
+                        .get(parent_id)
+                        .map(|stop_point| stop_point.stop_area_id.as_str())
+                        .or_else(|| {
+                            collections
+                                .stop_areas
+                                .get(parent_id)
+                                .map(|stop_area| stop_area.id.as_str())
+                        })
+                })
+        })
+}
+
+// Link the stop areas `from` and `to` if both of them are known
+fn link<'a>(neighbors: &mut BTreeMap<&'a str, BTreeSet<&'a str>>, from: &'a str, to: &'a str) {
+    if from != to && neighbors.contains_key(from) && neighbors.contains_key(to) {
+        // both stop areas are known, checked just above
+        neighbors.get_mut(from).unwrap().insert(to);
+        neighbors.get_mut(to).unwrap().insert(from);
+    }
+}
+
+/// Analyze the connectivity of `collections`.
+///
+/// The stop areas are linked to each other by the consecutive stops of the
+/// trips, the transfers and the pathways. A stop area linked to no other one
+/// is isolated; the stop areas of a sub-network that is not the biggest one
+/// are unreachable from the rest of the network. The stop points served by no
+/// trip and the trips referencing a missing stop point are also listed.
+///
+/// The missing stop points are errors of the returned `Report`, everything
+/// else is a warning.
+pub fn analyze_connectivity(collections: &Collections) -> Report<ConnectivityReportCategory> {
+    let mut report = Report::default();
+    let stop_point_indexes: HashSet<Idx<StopPoint>> =
+        collections.stop_points.iter().map(|(idx, _)| idx).collect();
+    let mut used_stop_points: HashSet<Idx<StopPoint>> = HashSet::new();
+    let mut neighbors: BTreeMap<&str, BTreeSet<&str>> = collections
+        .stop_areas
+        .values()
+        .map(|stop_area| (stop_area.id.as_str(), BTreeSet::new()))
+        .collect();
+
+    for vehicle_journey in collections.vehicle_journeys.values() {
+        let mut previous_stop_area_id: Option<&str> = None;
+        for stop_time in &vehicle_journey.stop_times {
+            if !stop_point_indexes.contains(&stop_time.stop_point_idx) {
+                report.add_error(
+                    format!(
+                        "vehicle journey {} references a missing stop point at sequence {}",
+                        vehicle_journey.id, stop_time.sequence
+                    ),
+                    ConnectivityReportCategory::MissingStopPoint,
+                );
+                continue;
+            }
+            used_stop_points.insert(stop_time.stop_point_idx);
+            let stop_area_id = collections.stop_points[stop_time.stop_point_idx]
+                .stop_area_id
+                .as_str();
+            if let Some(previous_stop_area_id) = previous_stop_area_id {
+                link(&mut neighbors, previous_stop_area_id, stop_area_id);
+            }
+            previous_stop_area_id = Some(stop_area_id);
+        }
+    }
+    for transfer in collections.transfers.values() {
+        if let (Some(from), Some(to)) = (
+            stop_area_id(collections, &transfer.from_stop_id),
+            stop_area_id(collections, &transfer.to_stop_id),
+        ) {
+            link(&mut neighbors, from, to);
+        }
+    }
+    for pathway in collections.pathways.values() {
+        if let (Some(from), Some(to)) = (
+            stop_area_id(collections, &pathway.from_stop_id),
+            stop_area_id(collections, &pathway.to_stop_id),
+        ) {
+            link(&mut neighbors, from, to);
+        }
+    }
+
+    for (stop_point_idx, stop_point) in collections.stop_points.iter() {
+        if stop_point.stop_type == StopType::Point && !used_stop_points.contains(&stop_point_idx) {
+            report.add_warning(
+                format!("stop point {} is not served by any trip", stop_point.id),
+                ConnectivityReportCategory::UnusedStopPoint,
+            );
+        }
+    }
+
+    let mut components: Vec<Vec<&str>> = Vec::new();
+    let mut visited: HashSet<&str> = HashSet::new();
+    for (stop_area_id, stop_area_neighbors) in &neighbors {
+        if stop_area_neighbors.is_empty() {
+            report.add_warning(
+                format!(
+                    "stop area {} is linked to no other stop area by a trip, a transfer or a pathway",
+                    stop_area_id
+                ),
+                ConnectivityReportCategory::IsolatedStopArea,
+            );
+            continue;
+        }
+        if !visited.insert(*stop_area_id) {
+            continue;
+        }
+        let mut component = vec![*stop_area_id];
+        let mut to_visit = vec![*stop_area_id];
+        while let Some(current) = to_visit.pop() {
+            for neighbor in &neighbors[current] {
+                if visited.insert(*neighbor) {
+                    component.push(*neighbor);
+                    to_visit.push(*neighbor);
+                }
+            }
+        }
+        components.push(component);
+    }
+    // the main network is the biggest sub-network, the first one on equality
+    let main_component = components
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, component)| component.len())
+        .map(|(position, _)| position);
+    for (position, component) in components.iter().enumerate() {
+        if Some(position) == main_component {
+            continue;
+        }
+        let mut component = component.clone();
+        component.sort_unstable();
+        for stop_area_id in &component {
+            report.add_warning(
+                format!(
+                    "stop area {} belongs to a sub-network of {} stop area(s) disconnected from the main network",
+                    stop_area_id,
+                    component.len()
+                ),
+                ConnectivityReportCategory::UnreachableStopArea,
+            );
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{StopArea, Transfer};
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn messages(
+        report: &Report<ConnectivityReportCategory>,
+        category: ConnectivityReportCategory,
+    ) -> Vec<&str> {
+        report
+            .warnings()
+            .iter()
+            .chain(report.errors())
+            .filter(|entry| entry.category == category)
+            .map(|entry| entry.message.as_str())
+            .collect()
+    }
+
+    fn two_sub_networks() -> Collections {
+        ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00")
+                    .st("C", "12:00:00", "12:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("D", "10:00:00", "10:01:00")
+                    .st("E", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections()
+    }
+
+    #[test]
+    fn unused_and_unreachable_stops() {
+        let mut collections = two_sub_networks();
+        collections
+            .stop_areas
+            .push(StopArea {
+                id: "sa:F".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        collections
+            .stop_points
+            .push(StopPoint {
+                id: "F".to_string(),
+                stop_area_id: "sa:F".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        let report = analyze_connectivity(&collections);
+
+        assert!(report.errors().is_empty());
+        assert_eq!(
+            vec!["stop point F is not served by any trip"],
+            messages(&report, ConnectivityReportCategory::UnusedStopPoint)
+        );
+        assert_eq!(
+            vec![
+                "stop area sa:F is linked to no other stop area by a trip, a transfer or a pathway"
+            ],
+            messages(&report, ConnectivityReportCategory::IsolatedStopArea)
+        );
+        assert_eq!(
+            vec![
+                "stop area sa:D belongs to a sub-network of 2 stop area(s) disconnected from the main network",
+                "stop area sa:E belongs to a sub-network of 2 stop area(s) disconnected from the main network",
+            ],
+            messages(&report, ConnectivityReportCategory::UnreachableStopArea)
+        );
+    }
+
+    #[test]
+    fn sub_networks_linked_by_transfer() {
+        let mut collections = two_sub_networks();
+        collections.transfers.push(Transfer {
+            from_stop_id: "C".to_string(),
+            to_stop_id: "D".to_string(),
+            min_transfer_time: None,
+            real_min_transfer_time: None,
+            equipment_id: None,
+            transfer_mode: None,
+            ..Default::default()
+        });
+        let report = analyze_connectivity(&collections);
+
+        assert!(messages(&report, ConnectivityReportCategory::UnreachableStopArea).is_empty());
+    }
+
+    #[test]
+    fn missing_stop_point() {
+        let mut collections = two_sub_networks();
+        // 'E' is the last stop point, its index used by the stop times of
+        // 'vj2' becomes invalid
+        collections
+            .stop_points
+            .retain(|stop_point| stop_point.id != "E");
+        let report = analyze_connectivity(&collections);
+
+        assert_eq!(
+            vec!["vehicle journey vj2 references a missing stop point at sequence 1"],
+            messages(&report, ConnectivityReportCategory::MissingStopPoint)
+        );
+    }
+}
@@ -0,0 +1,461 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Detection, and optional repair, of the obviously wrong coordinates of the
+//! stops: missing coordinates (`0, 0`), values out of range, or longitude and
+//! latitude swapped relative to the other stops of the dataset.
+
+use crate::{
+    model::Collections,
+    objects::Coord,
+    report::{Report, ReportEntry, Severity},
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Id};
+
+/// Categories of the `Report` produced by the coordinate checks
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoordReportCategory {
+    /// The coordinates of a stop are missing (`0, 0`)
+    MissingCoord,
+    /// The longitude or the latitude of a stop is out of range
+    OutOfRangeCoord,
+    /// The longitude and the latitude of a stop are swapped
+    SwappedCoord,
+    /// The coordinates of a stop have been repaired
+    CoordRepaired,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CoordIssue {
+    Missing,
+    OutOfRange,
+    Swapped,
+}
+
+impl CoordIssue {
+    fn category(self) -> CoordReportCategory {
+        match self {
+            CoordIssue::Missing => CoordReportCategory::MissingCoord,
+            CoordIssue::OutOfRange => CoordReportCategory::OutOfRangeCoord,
+            CoordIssue::Swapped => CoordReportCategory::SwappedCoord,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            CoordIssue::Missing => "missing coordinates",
+            CoordIssue::OutOfRange => "coordinates out of range",
+            CoordIssue::Swapped => "longitude and latitude swapped",
+        }
+    }
+}
+
+fn swapped(coord: &Coord) -> Coord {
+    Coord {
+        lon: coord.lat,
+        lat: coord.lon,
+    }
+}
+
+fn is_plausible(coord: &Coord) -> bool {
+    (coord.lon != 0.0 || coord.lat != 0.0)
+        && (-180.0..=180.0).contains(&coord.lon)
+        && (-90.0..=90.0).contains(&coord.lat)
+}
+
+// Bounding box of most of the stops of the dataset, the extreme percent of
+// each coordinate being ignored (e.g. the swapped ones), with a margin
+struct BoundingBox {
+    min: Coord,
+    max: Coord,
+}
+
+// Values at the 1st and 99th percentiles (at least the extreme values being
+// ignored when there are more than 2 values)
+fn percentiles(mut values: Vec<f64>) -> (f64, f64) {
+    values.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let last = values.len() - 1;
+    let ignored = ((last + 99) / 100).min(last / 2);
+    (values[ignored], values[last - ignored])
+}
+
+impl BoundingBox {
+    fn new(coords: &[Coord]) -> Option<Self> {
+        if coords.is_empty() {
+            return None;
+        }
+        let (min_lon, max_lon) = percentiles(coords.iter().map(|c| c.lon).collect());
+        let (min_lat, max_lat) = percentiles(coords.iter().map(|c| c.lat).collect());
+        let lon_margin = (max_lon - min_lon) * 0.1 + 0.1;
+        let lat_margin = (max_lat - min_lat) * 0.1 + 0.1;
+        Some(BoundingBox {
+            min: Coord {
+                lon: min_lon - lon_margin,
+                lat: min_lat - lat_margin,
+            },
+            max: Coord {
+                lon: max_lon + lon_margin,
+                lat: max_lat + lat_margin,
+            },
+        })
+    }
+
+    fn contains(&self, coord: &Coord) -> bool {
+        (self.min.lon..=self.max.lon).contains(&coord.lon)
+            && (self.min.lat..=self.max.lat).contains(&coord.lat)
+    }
+}
+
+fn dataset_bounding_box(collections: &Collections) -> Option<BoundingBox> {
+    let coords: Vec<Coord> = collections
+        .stop_points
+        .values()
+        .map(|stop_point| stop_point.coord)
+        .chain(
+            collections
+                .stop_areas
+                .values()
+                .map(|stop_area| stop_area.coord),
+        )
+        .filter(is_plausible)
+        .collect();
+    BoundingBox::new(&coords)
+}
+
+fn coord_issue(coord: &Coord, bounding_box: &Option<BoundingBox>) -> Option<CoordIssue> {
+    let is_swapped = |bounding_box: &BoundingBox| {
+        !bounding_box.contains(coord) && bounding_box.contains(&swapped(coord))
+    };
+    if coord.lon == 0.0 && coord.lat == 0.0 {
+        Some(CoordIssue::Missing)
+    } else if bounding_box.as_ref().map_or(false, is_swapped) {
+        Some(CoordIssue::Swapped)
+    } else if !is_plausible(coord) {
+        Some(CoordIssue::OutOfRange)
+    } else {
+        None
+    }
+}
+
+fn report_issue(
+    report: &mut Report<CoordReportCategory>,
+    issue: CoordIssue,
+    object_type: &str,
+    object_id: &str,
+) {
+    let severity = match issue {
+        CoordIssue::Swapped => Severity::Warning,
+        CoordIssue::Missing | CoordIssue::OutOfRange => Severity::Error,
+    };
+    report.add_entry(
+        severity,
+        ReportEntry::new(
+            issue.category(),
+            format!("{} of the {}", issue.description(), object_type),
+        )
+        .with_object_id(object_id),
+    );
+}
+
+fn report_repair(report: &mut Report<CoordReportCategory>, message: String, object_id: &str) {
+    report.add_entry(
+        Severity::Warning,
+        ReportEntry::new(CoordReportCategory::CoordRepaired, message).with_object_id(object_id),
+    );
+}
+
+fn check_stops<T: Id<T>>(
+    stops: &CollectionWithId<T>,
+    object_type: &str,
+    coord: fn(&T) -> &Coord,
+    bounding_box: &Option<BoundingBox>,
+    report: &mut Report<CoordReportCategory>,
+) {
+    for stop in stops.values() {
+        if let Some(issue) = coord_issue(coord(stop), bounding_box) {
+            report_issue(report, issue, object_type, stop.id());
+        }
+    }
+}
+
+fn check_all_stops(collections: &Collections, report: &mut Report<CoordReportCategory>) {
+    let bounding_box = dataset_bounding_box(collections);
+    check_stops(
+        &collections.stop_areas,
+        "stop area",
+        |stop_area| &stop_area.coord,
+        &bounding_box,
+        report,
+    );
+    check_stops(
+        &collections.stop_points,
+        "stop point",
+        |stop_point| &stop_point.coord,
+        &bounding_box,
+        report,
+    );
+    check_stops(
+        &collections.stop_locations,
+        "stop location",
+        |stop_location| &stop_location.coord,
+        &bounding_box,
+        report,
+    );
+}
+
+/// Check the coordinates of the stop areas, the stop points and the other
+/// stops of `collections`.
+///
+/// The missing (`0, 0`) and out of range coordinates are errors of the
+/// returned `Report`. The coordinates outside the bounding box of the other
+/// stops of the dataset, but inside once the longitude and the latitude are
+/// swapped, are warnings. The entries are attached to the stops.
+pub fn check_coords(collections: &Collections) -> Report<CoordReportCategory> {
+    let mut report = Report::default();
+    check_all_stops(collections, &mut report);
+    report
+}
+
+fn swap_back<T: Id<T>>(
+    stops: &mut CollectionWithId<T>,
+    object_type: &str,
+    coord: fn(&mut T) -> &mut Coord,
+    bounding_box: &Option<BoundingBox>,
+    report: &mut Report<CoordReportCategory>,
+) {
+    for idx in stops.indexes() {
+        let mut stop = stops.index_mut(idx);
+        let id = stop.id().to_string();
+        let coord = coord(&mut stop);
+        if coord_issue(coord, bounding_box) == Some(CoordIssue::Swapped) {
+            *coord = swapped(coord);
+            report_repair(
+                report,
+                format!("longitude and latitude of the {} swapped back", object_type),
+                &id,
+            );
+        }
+    }
+}
+
+/// Repair the coordinates of the stops of `collections` which can be:
+/// - the swapped longitude and latitude (see [check_coords]) are swapped back,
+/// - the missing or out of range coordinates of a stop area are set to the
+///   centroid of its stop points,
+/// - the missing or out of range coordinates of a stop point or of another
+///   stop are set to the ones of its stop area.
+///
+/// Every repaired stop is a warning of the returned `Report`, which also
+/// lists the issues of the stops left unrepaired.
+pub fn repair_coords(collections: &mut Collections) -> Report<CoordReportCategory> {
+    let mut report = Report::default();
+    let bounding_box = dataset_bounding_box(collections);
+    swap_back(
+        &mut collections.stop_areas,
+        "stop area",
+        |stop_area| &mut stop_area.coord,
+        &bounding_box,
+        &mut report,
+    );
+    swap_back(
+        &mut collections.stop_points,
+        "stop point",
+        |stop_point| &mut stop_point.coord,
+        &bounding_box,
+        &mut report,
+    );
+    swap_back(
+        &mut collections.stop_locations,
+        "stop location",
+        |stop_location| &mut stop_location.coord,
+        &bounding_box,
+        &mut report,
+    );
+
+    let mut stop_point_coords: HashMap<String, Vec<Coord>> = HashMap::new();
+    for stop_point in collections.stop_points.values() {
+        if coord_issue(&stop_point.coord, &bounding_box).is_none() {
+            stop_point_coords
+                .entry(stop_point.stop_area_id.clone())
+                .or_default()
+                .push(stop_point.coord);
+        }
+    }
+    for idx in collections.stop_areas.indexes() {
+        let mut stop_area = collections.stop_areas.index_mut(idx);
+        if coord_issue(&stop_area.coord, &bounding_box).is_none() {
+            continue;
+        }
+        if let Some(coords) = stop_point_coords.get(&stop_area.id) {
+            let count = coords.len() as f64;
+            stop_area.coord = Coord {
+                lon: coords.iter().map(|c| c.lon).sum::<f64>() / count,
+                lat: coords.iter().map(|c| c.lat).sum::<f64>() / count,
+            };
+            report_repair(
+                &mut report,
+                "stop area coordinates set to the centroid of its stop points".to_string(),
+                &stop_area.id,
+            );
+        }
+    }
+
+    let stop_area_coords: HashMap<String, Coord> = collections
+        .stop_areas
+        .values()
+        .filter(|stop_area| coord_issue(&stop_area.coord, &bounding_box).is_none())
+        .map(|stop_area| (stop_area.id.clone(), stop_area.coord))
+        .collect();
+    for idx in collections.stop_points.indexes() {
+        let mut stop_point = collections.stop_points.index_mut(idx);
+        if coord_issue(&stop_point.coord, &bounding_box).is_none() {
+            continue;
+        }
+        if let Some(coord) = stop_area_coords.get(&stop_point.stop_area_id) {
+            stop_point.coord = *coord;
+            report_repair(
+                &mut report,
+                "stop point coordinates set to the ones of its stop area".to_string(),
+                &stop_point.id,
+            );
+        }
+    }
+    for idx in collections.stop_locations.indexes() {
+        let mut stop_location = collections.stop_locations.index_mut(idx);
+        if coord_issue(&stop_location.coord, &bounding_box).is_none() {
+            continue;
+        }
+        let parent_coord = stop_location
+            .parent_id
+            .as_ref()
+            .and_then(|parent_id| stop_area_coords.get(parent_id));
+        if let Some(coord) = parent_coord.copied() {
+            stop_location.coord = coord;
+            report_repair(
+                &mut report,
+                "stop location coordinates set to the ones of its stop area".to_string(),
+                &stop_location.id,
+            );
+        }
+    }
+
+    info!("{} stop(s) repaired", report.warnings().len());
+    check_all_stops(collections, &mut report);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn issues(report: &Report<CoordReportCategory>) -> Vec<(CoordReportCategory, String)> {
+        let mut issues: Vec<_> = report
+            .errors()
+            .iter()
+            .chain(report.warnings())
+            .filter(|entry| entry.category != CoordReportCategory::CoordRepaired)
+            .map(|entry| (entry.category, entry.object_id.clone().unwrap_or_default()))
+            // the stop areas created by the builder have no coordinates
+            .filter(|(_, id)| !id.starts_with("sa:"))
+            .collect();
+        issues.sort_unstable_by(|left, right| left.1.cmp(&right.1));
+        issues
+    }
+
+    fn wrong_coords() -> Collections {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "10:10:00", "10:11:00")
+                    .st("C", "10:20:00", "10:21:00")
+                    .st("D", "10:30:00", "10:31:00")
+                    .st("E", "10:40:00", "10:41:00")
+                    .st("F", "10:50:00", "10:51:00");
+            })
+            .build_model()
+            .into_collections();
+        let coords = [
+            ("A", 2.35, 48.85),
+            ("B", 2.36, 48.86),
+            ("C", 2.37, 48.87),
+            // swapped
+            ("D", 48.88, 2.38),
+            // missing
+            ("E", 0.0, 0.0),
+            // out of range
+            ("F", 2.39, 148.89),
+        ];
+        for &(id, lon, lat) in coords.iter() {
+            let mut stop_point = collections.stop_points.get_mut(id).unwrap();
+            stop_point.coord = Coord { lon, lat };
+        }
+        collections
+    }
+
+    #[test]
+    fn check_wrong_coords() {
+        let collections = wrong_coords();
+        let report = check_coords(&collections);
+        assert_eq!(
+            vec![
+                (CoordReportCategory::SwappedCoord, "D".to_string()),
+                (CoordReportCategory::MissingCoord, "E".to_string()),
+                (CoordReportCategory::OutOfRangeCoord, "F".to_string()),
+            ],
+            issues(&report)
+        );
+    }
+
+    #[test]
+    fn repair_wrong_coords() {
+        let mut collections = wrong_coords();
+        let stop_area_id = collections
+            .stop_points
+            .get("E")
+            .unwrap()
+            .stop_area_id
+            .clone();
+        let stop_area_coord = Coord {
+            lon: 2.4,
+            lat: 48.9,
+        };
+        collections.stop_areas.get_mut(&stop_area_id).unwrap().coord = stop_area_coord;
+
+        let report = repair_coords(&mut collections);
+
+        assert_eq!(
+            Coord {
+                lon: 2.38,
+                lat: 48.88
+            },
+            collections.stop_points.get("D").unwrap().coord
+        );
+        assert_eq!(
+            stop_area_coord,
+            collections.stop_points.get("E").unwrap().coord
+        );
+        // the stop area of F has no coordinates and no other stop point
+        assert_eq!(
+            vec![(CoordReportCategory::OutOfRangeCoord, "F".to_string())],
+            issues(&report)
+        );
+    }
+}
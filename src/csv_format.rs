@@ -0,0 +1,848 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Formatting of the CSV files written by the NTFS and GTFS writers, for the
+//! consumers requiring a specific format (e.g. legacy AVL systems expecting
+//! CRLF line endings or coordinates with a fixed number of decimals), and
+//! reading of the CSV files of another dialect (e.g.
+//! semicolon separated files with decimal commas).
+
+use crate::Result;
+use anyhow::Context;
+use derivative::Derivative;
+use serde::{ser, Serialize};
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+use thiserror::Error;
+
+/// Quoting of the fields
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum QuoteStyle {
+    /// Only the fields containing a delimiter, a quote or a line terminator
+    #[derivative(Default)]
+    Necessary,
+    /// All the fields
+    Always,
+    /// All the fields which are not numbers
+    NonNumeric,
+}
+
+impl From<QuoteStyle> for csv::QuoteStyle {
+    fn from(quote_style: QuoteStyle) -> Self {
+        match quote_style {
+            QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+            QuoteStyle::Always => csv::QuoteStyle::Always,
+            QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+        }
+    }
+}
+
+/// Line terminator of the records
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum LineTerminator {
+    /// `\n`
+    #[derivative(Default)]
+    Lf,
+    /// `\r\n`
+    Crlf,
+}
+
+impl From<LineTerminator> for csv::Terminator {
+    fn from(line_terminator: LineTerminator) -> Self {
+        match line_terminator {
+            LineTerminator::Lf => csv::Terminator::Any(b'\n'),
+            LineTerminator::Crlf => csv::Terminator::CRLF,
+        }
+    }
+}
+
+/// Format of the CSV files, the default one being the format of the writers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CsvFormat {
+    /// Quoting of the fields
+    pub quote_style: QuoteStyle,
+    /// Line terminator of the records
+    pub line_terminator: LineTerminator,
+    /// Number of decimals of the coordinates (the columns whose name ends
+    /// with `_lat` or `_lon`), unchanged if `None`
+    pub coord_precision: Option<usize>,
+}
+
+/// Dialect of the CSV files read, the default one being the dialect of the
+/// specifications (comma separated fields and decimal points)
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub struct CsvDialect {
+    /// Separator of the fields
+    #[derivative(Default(value = "b','"))]
+    pub delimiter: u8,
+    /// Separator of the integer and the fractional parts of the decimal
+    /// numbers (e.g. `b','` for `48,8809`)
+    #[derivative(Default(value = "b'.'"))]
+    pub decimal_separator: u8,
+}
+
+// `true` if `field` is a decimal number written with `decimal_separator`
+fn is_decimal(field: &str, decimal_separator: char) -> bool {
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    match field
+        .trim_start_matches(|c| c == '-' || c == '+')
+        .split_once(decimal_separator)
+    {
+        Some((integer, fraction)) => is_number(integer) && is_number(fraction),
+        None => false,
+    }
+}
+
+// Columns of decimal numbers other than the coordinates, i.e. the float and
+// `Decimal` fields of the objects
+const DECIMAL_COLUMNS: &[&str] = &[
+    "co2_emission",
+    "length",
+    "level_index",
+    "max_slope",
+    "min_width",
+    "ticket_price",
+];
+
+fn is_decimal_column(name: &str) -> bool {
+    is_coord_column(name.as_bytes()) || DECIMAL_COLUMNS.contains(&name)
+}
+
+/// Convert the CSV file of `reader` (whose path `path` is used in the error
+/// messages) from the `dialect` to the dialect of the specifications, the
+/// decimal separator being converted only in the columns of decimal numbers
+/// (the coordinates, the CO2 emissions, the indexes of the levels, the
+/// dimensions of the pathways and the prices)
+pub fn normalize<R: Read>(reader: R, dialect: &CsvDialect, path: &Path) -> Result<Vec<u8>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(dialect.delimiter)
+        .from_reader(reader);
+    let mut wtr = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    let decimal_separator = char::from(dialect.decimal_separator);
+    // known once the header is read
+    let mut decimal_columns: Option<Vec<bool>> = None;
+    for record in rdr.records() {
+        let record = record.with_context(|| format!("Error reading {:?}", path))?;
+        let decimal_columns = match &decimal_columns {
+            Some(decimal_columns) => decimal_columns,
+            None => {
+                wtr.write_record(&record)
+                    .with_context(|| format!("Error reading {:?}", path))?;
+                decimal_columns = Some(record.iter().map(is_decimal_column).collect());
+                continue;
+            }
+        };
+        let fields = record.iter().enumerate().map(|(column, field)| {
+            if decimal_separator != '.'
+                && decimal_columns.get(column) == Some(&true)
+                && is_decimal(field, decimal_separator)
+            {
+                field.replacen(decimal_separator, ".", 1)
+            } else {
+                field.to_string()
+            }
+        });
+        wtr.write_record(fields)
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.into_inner()
+        .with_context(|| format!("Error reading {:?}", path))
+}
+
+fn is_coord_column(name: &[u8]) -> bool {
+    name.ends_with(b"_lat") || name.ends_with(b"_lon")
+}
+
+impl CsvFormat {
+    /// Builder of the CSV writers, with the quoting and the line terminator
+    /// of the format
+    pub fn writer_builder(&self) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .quote_style(self.quote_style.into())
+            .terminator(self.line_terminator.into());
+        builder
+    }
+
+    /// Create a CSV writer of the format on `writer`
+    pub fn writer<W: Write>(&self, writer: W) -> CsvWriter<W> {
+        CsvWriter {
+            writer: self.writer_builder().from_writer(writer),
+            coord_precision: self.coord_precision,
+            coord_columns: None,
+        }
+    }
+
+    /// Create a CSV writer of the format on the file `path`
+    pub fn writer_from_path<P: AsRef<Path>>(&self, path: P) -> csv::Result<CsvWriter<File>> {
+        Ok(self.writer(File::create(path)?))
+    }
+
+    /// Serialize `record` into its fields, with the coordinates formatted
+    /// with the precision of the format, and into its header if it is a
+    /// struct (a tuple has no header and its fields are not formatted)
+    pub fn serialize_record<S: Serialize>(
+        &self,
+        record: S,
+    ) -> csv::Result<(Option<Vec<&'static str>>, Vec<String>)> {
+        serialize_record(record, self.coord_precision)
+    }
+}
+
+fn serialize_record<S: Serialize>(
+    record: S,
+    coord_precision: Option<usize>,
+) -> csv::Result<(Option<Vec<&'static str>>, Vec<String>)> {
+    let mut serializer = RecordSerializer {
+        coord_precision,
+        header: None,
+        fields: Vec::new(),
+    };
+    record.serialize(&mut serializer)?;
+    Ok((serializer.header, serializer.fields))
+}
+
+/// CSV writer formatting the coordinates with the precision of its
+/// `CsvFormat`, the first record written being the header
+pub struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+    coord_precision: Option<usize>,
+    // known once the header is written
+    coord_columns: Option<Vec<bool>>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Serialize a single record, see `csv::Writer::serialize`
+    pub fn serialize<S: Serialize>(&mut self, record: S) -> csv::Result<()> {
+        let (header, fields) = serialize_record(record, self.coord_precision)?;
+        if self.coord_columns.is_none() {
+            match header {
+                Some(header) => self.write_record(header)?,
+                // a tuple written without header
+                None => self.coord_columns = Some(Vec::new()),
+            }
+        }
+        // the fields are already formatted
+        self.writer.write_record(fields)
+    }
+
+    /// Write a single record, see `csv::Writer::write_record`
+    pub fn write_record<I, T>(&mut self, record: I) -> csv::Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        match (&self.coord_columns, self.coord_precision) {
+            (Some(coord_columns), Some(precision)) => {
+                let fields = record.into_iter().enumerate().map(|(column, field)| {
+                    let coord = std::str::from_utf8(field.as_ref())
+                        .ok()
+                        .and_then(|field| field.parse::<f64>().ok());
+                    match coord {
+                        Some(coord) if coord_columns.get(column) == Some(&true) => {
+                            format!("{:.*}", precision, coord).into_bytes()
+                        }
+                        _ => field.as_ref().to_vec(),
+                    }
+                });
+                self.writer.write_record(fields)
+            }
+            (Some(_), None) => self.writer.write_record(record),
+            (None, _) => {
+                let header: Vec<T> = record.into_iter().collect();
+                self.coord_columns = Some(
+                    header
+                        .iter()
+                        .map(|name| is_coord_column(name.as_ref()))
+                        .collect(),
+                );
+                self.writer.write_record(header)
+            }
+        }
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Error of the serialization of a record which is neither a struct nor a
+// tuple of scalar fields
+#[derive(Debug, Error)]
+#[error("{0}")]
+struct RecordError(String);
+
+impl ser::Error for RecordError {
+    fn custom<T: Display>(msg: T) -> Self {
+        RecordError(msg.to_string())
+    }
+}
+
+impl From<RecordError> for csv::Error {
+    fn from(error: RecordError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error).into()
+    }
+}
+
+// Serializer of a field into its text, like the `csv` crate does, the floats
+// being formatted with `precision` if any
+struct FieldSerializer {
+    precision: Option<usize>,
+}
+
+macro_rules! serialize_with_to_string {
+    ($($method:ident($type:ty)),*) => {
+        $(fn $method(self, value: $type) -> Result<String, RecordError> {
+            Ok(value.to_string())
+        })*
+    };
+}
+
+macro_rules! serialize_float {
+    ($($method:ident($type:ty)),*) => {
+        $(fn $method(self, value: $type) -> Result<String, RecordError> {
+            Ok(match self.precision {
+                Some(precision) => format!("{:.*}", precision, value),
+                // the shortest representation, with a decimal point
+                None => format!("{:?}", value),
+            })
+        })*
+    };
+}
+
+impl ser::Serializer for FieldSerializer {
+    type Ok = String;
+    type Error = RecordError;
+    type SerializeSeq = ser::Impossible<String, RecordError>;
+    type SerializeTuple = ser::Impossible<String, RecordError>;
+    type SerializeTupleStruct = ser::Impossible<String, RecordError>;
+    type SerializeTupleVariant = ser::Impossible<String, RecordError>;
+    type SerializeMap = ser::Impossible<String, RecordError>;
+    type SerializeStruct = ser::Impossible<String, RecordError>;
+    type SerializeStructVariant = ser::Impossible<String, RecordError>;
+
+    serialize_with_to_string!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_char(char),
+        serialize_str(&str)
+    );
+    serialize_float!(serialize_f32(f32), serialize_f64(f64));
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<String, RecordError> {
+        Ok(String::from_utf8_lossy(value).into_owned())
+    }
+    fn serialize_none(self) -> Result<String, RecordError> {
+        Ok(String::new())
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<String, RecordError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<String, RecordError> {
+        Ok(String::new())
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, RecordError> {
+        Ok(String::new())
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<String, RecordError> {
+        Ok(variant.to_string())
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, RecordError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _value: &T,
+    ) -> Result<String, RecordError> {
+        Err(nested_field(&format!("{}::{}", name, variant)))
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, RecordError> {
+        Err(nested_field("sequence"))
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, RecordError> {
+        Err(nested_field("tuple"))
+    }
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, RecordError> {
+        Err(nested_field(name))
+    }
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, RecordError> {
+        Err(nested_field(&format!("{}::{}", name, variant)))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, RecordError> {
+        Err(nested_field("map"))
+    }
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, RecordError> {
+        Err(nested_field(name))
+    }
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, RecordError> {
+        Err(nested_field(&format!("{}::{}", name, variant)))
+    }
+}
+
+fn nested_field(kind: &str) -> RecordError {
+    RecordError(format!("cannot serialize a {} as a CSV field", kind))
+}
+
+// Serializer of a struct or a tuple into the header and the fields of a
+// record, the coordinates of a struct being formatted with `coord_precision`
+struct RecordSerializer {
+    coord_precision: Option<usize>,
+    header: Option<Vec<&'static str>>,
+    fields: Vec<String>,
+}
+
+impl RecordSerializer {
+    fn push_field<T: ?Sized + Serialize>(
+        &mut self,
+        precision: Option<usize>,
+        value: &T,
+    ) -> Result<(), RecordError> {
+        let field = value.serialize(FieldSerializer { precision })?;
+        self.fields.push(field);
+        Ok(())
+    }
+}
+
+macro_rules! serialize_single_field {
+    ($($method:ident($type:ty)),*) => {
+        $(fn $method(self, value: $type) -> Result<(), RecordError> {
+            self.push_field(None, &value)
+        })*
+    };
+}
+
+impl<'a> ser::Serializer for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = RecordError;
+    type SerializeSeq = ser::Impossible<(), RecordError>;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = ser::Impossible<(), RecordError>;
+    type SerializeMap = ser::Impossible<(), RecordError>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = ser::Impossible<(), RecordError>;
+
+    // a scalar is a record of a single field
+    serialize_single_field!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str)
+    );
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), RecordError> {
+        self.fields
+            .push(String::from_utf8_lossy(value).into_owned());
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), RecordError> {
+        self.push_field(None, &None::<()>)
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), RecordError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), RecordError> {
+        self.push_field(None, &())
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), RecordError> {
+        self.push_field(None, &())
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), RecordError> {
+        self.push_field(None, variant)
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), RecordError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _value: &T,
+    ) -> Result<(), RecordError> {
+        Err(unsupported_record(&format!("{}::{}", name, variant)))
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, RecordError> {
+        Err(unsupported_record("sequence"))
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self, RecordError> {
+        Ok(self)
+    }
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, RecordError> {
+        Ok(self)
+    }
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, RecordError> {
+        Err(unsupported_record(&format!("{}::{}", name, variant)))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, RecordError> {
+        Err(unsupported_record("map"))
+    }
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self, RecordError> {
+        self.header = Some(Vec::with_capacity(len));
+        Ok(self)
+    }
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, RecordError> {
+        Err(unsupported_record(&format!("{}::{}", name, variant)))
+    }
+}
+
+fn unsupported_record(kind: &str) -> RecordError {
+    RecordError(format!("cannot serialize a {} as a CSV record", kind))
+}
+
+impl<'a> ser::SerializeStruct for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = RecordError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), RecordError> {
+        let precision = self
+            .coord_precision
+            .filter(|_| is_coord_column(key.as_bytes()));
+        self.header.get_or_insert_with(Vec::new).push(key);
+        self.push_field(precision, value)
+    }
+
+    fn end(self) -> Result<(), RecordError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTuple for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = RecordError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), RecordError> {
+        self.push_field(None, value)
+    }
+
+    fn end(self) -> Result<(), RecordError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = RecordError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), RecordError> {
+        self.push_field(None, value)
+    }
+
+    fn end(self) -> Result<(), RecordError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{get_test_datetime, test_in_tmp_dir};
+    use pretty_assertions::assert_eq;
+    use rust_decimal::Decimal;
+    use std::collections::BTreeSet;
+    use typed_index_collection::CollectionWithId;
+
+    #[test]
+    fn normalize_semicolon_separated_file() {
+        let content = "stop_id;stop_name;stop_lat;stop_lon\n\
+                       sp1;\"Gare; Nord\";48,8809;-2,35\n\
+                       sp2;Ligne 1,2;;\n";
+        let dialect = CsvDialect {
+            delimiter: b';',
+            decimal_separator: b',',
+        };
+        let normalized = normalize(content.as_bytes(), &dialect, Path::new("stops.txt")).unwrap();
+
+        assert_eq!(
+            "stop_id,stop_name,stop_lat,stop_lon\n\
+             sp1,Gare; Nord,48.8809,-2.35\n\
+             sp2,\"Ligne 1,2\",,\n",
+            String::from_utf8(normalized).unwrap()
+        );
+    }
+
+    #[test]
+    fn normalize_only_decimal_columns() {
+        let content = "stop_id;stop_name;stop_code;stop_lat;stop_lon\n\
+                       sp1;12,5;3,14;48,8809;2,35\n";
+        let dialect = CsvDialect {
+            delimiter: b';',
+            decimal_separator: b',',
+        };
+        let normalized = normalize(content.as_bytes(), &dialect, Path::new("stops.txt")).unwrap();
+
+        assert_eq!(
+            "stop_id,stop_name,stop_code,stop_lat,stop_lon\n\
+             sp1,\"12,5\",\"3,14\",48.8809,2.35\n",
+            String::from_utf8(normalized).unwrap()
+        );
+    }
+
+    #[test]
+    fn write_with_format() {
+        #[derive(Serialize)]
+        struct Stop {
+            stop_id: &'static str,
+            stop_name: &'static str,
+            stop_lat: Option<f64>,
+            stop_lon: Option<f64>,
+            stop_code: &'static str,
+        }
+        let format = CsvFormat {
+            quote_style: QuoteStyle::Always,
+            line_terminator: LineTerminator::Crlf,
+            coord_precision: Some(6),
+        };
+        let mut wtr = format.writer(Vec::new());
+        wtr.serialize(Stop {
+            stop_id: "sp1",
+            stop_name: "Gare, Nord",
+            stop_lat: Some(48.8809),
+            stop_lon: Some(2.35),
+            stop_code: "1.5",
+        })
+        .unwrap();
+        wtr.write_record(&["sp2", "Opéra", "", "", ""]).unwrap();
+        wtr.flush().unwrap();
+
+        assert_eq!(
+            "\"stop_id\",\"stop_name\",\"stop_lat\",\"stop_lon\",\"stop_code\"\r\n\
+             \"sp1\",\"Gare, Nord\",\"48.880900\",\"2.350000\",\"1.5\"\r\n\
+             \"sp2\",\"Opéra\",\"\",\"\",\"\"\r\n",
+            String::from_utf8(wtr.writer.into_inner().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn serialize_record_with_format() {
+        #[derive(Serialize)]
+        enum Mode {
+            #[serde(rename = "3")]
+            Bus,
+        }
+        #[derive(Serialize)]
+        struct Stop {
+            stop_id: &'static str,
+            stop_lat: f64,
+            stop_lon: Option<f64>,
+            co2_emission: Option<f32>,
+            mode: Mode,
+            parent_station: Option<&'static str>,
+        }
+        let stop = Stop {
+            stop_id: "sp1",
+            stop_lat: 48.8809,
+            stop_lon: Some(2.0),
+            co2_emission: Some(132.0),
+            mode: Mode::Bus,
+            parent_station: None,
+        };
+        let format = CsvFormat {
+            coord_precision: Some(6),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            (
+                Some(vec![
+                    "stop_id",
+                    "stop_lat",
+                    "stop_lon",
+                    "co2_emission",
+                    "mode",
+                    "parent_station"
+                ]),
+                vec!["sp1", "48.880900", "2.000000", "132.0", "3", ""]
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            ),
+            format.serialize_record(&stop).unwrap()
+        );
+        assert_eq!(
+            vec!["sp1", "48.8809", "2.0", "132.0", "3", ""],
+            CsvFormat::default().serialize_record(&stop).unwrap().1
+        );
+    }
+
+    #[test]
+    fn serialize_tuples_after_header() {
+        let format = CsvFormat {
+            coord_precision: Some(6),
+            ..Default::default()
+        };
+        let mut wtr = format.writer(Vec::new());
+        wtr.write_record(["feed_info_param", "feed_info_value"])
+            .unwrap();
+        wtr.serialize(("feed_start_date", "20200101")).unwrap();
+        wtr.flush().unwrap();
+
+        assert_eq!(
+            "feed_info_param,feed_info_value\n\
+             feed_start_date,20200101\n",
+            String::from_utf8(wtr.writer.into_inner().unwrap()).unwrap()
+        );
+    }
+
+    // The columns of the NTFS written from `model` having a decimal number
+    fn written_decimal_columns(model: &crate::Model) -> BTreeSet<String> {
+        let mut columns = BTreeSet::new();
+        test_in_tmp_dir(|path| {
+            crate::ntfs::write(model, path, get_test_datetime()).unwrap();
+            for entry in std::fs::read_dir(path).unwrap() {
+                let file = entry.unwrap().path();
+                if file.extension() != Some("txt".as_ref()) {
+                    continue;
+                }
+                let mut rdr = csv::Reader::from_path(&file).unwrap();
+                let header = rdr.headers().unwrap().clone();
+                for record in rdr.records() {
+                    for (name, field) in header.iter().zip(record.unwrap().iter()) {
+                        if is_decimal(field, '.') {
+                            columns.insert(name.to_string());
+                        }
+                    }
+                }
+            }
+        });
+        columns
+    }
+
+    #[test]
+    fn normalize_every_written_decimal_column() {
+        // the GTFS has levels and pathways
+        let mut collections = crate::gtfs::read("tests/fixtures/gtfs")
+            .unwrap()
+            .into_collections();
+        let mut pathways = collections.pathways.take();
+        for pathway in &mut pathways {
+            pathway.length = Some(Decimal::new(125, 1));
+            pathway.max_slope = Some(0.5);
+            pathway.min_width = Some(1.5);
+        }
+        collections.pathways = CollectionWithId::new(pathways).unwrap();
+        let mut columns = written_decimal_columns(&crate::Model::new(collections).unwrap());
+        columns.extend(written_decimal_columns(
+            &crate::ntfs::read("tests/fixtures/ntfs").unwrap(),
+        ));
+
+        for column in &columns {
+            assert!(
+                is_decimal_column(column),
+                "the decimal separator of '{}' is not normalized",
+                column
+            );
+        }
+        let expected: BTreeSet<String> = [
+            "co2_emission",
+            "length",
+            "level_index",
+            "max_slope",
+            "min_width",
+            "stop_lat",
+            "stop_lon",
+            "ticket_price",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        assert!(columns.is_superset(&expected), "{:?}", columns);
+    }
+}
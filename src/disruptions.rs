@@ -0,0 +1,513 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Planned disruptions (e.g. works) closing lines or stops during a period,
+//! applied on the model to produce the adapted dataset actually run.
+
+use crate::{
+    model::Collections,
+    objects::{Comment, CommentType, Date, StopPoint},
+    report::{Report, ReportEntry, Severity},
+    serde_utils::de_from_date_string,
+    stop_relocation::{add_calendar, copy_frequencies, copy_stop_time_properties},
+    Result,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::Path,
+};
+use tracing::info;
+use typed_index_collection::Idx;
+
+/// Type of the object closed by a `PlannedDisruption`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisruptedObjectType {
+    /// No vehicle journey of the line runs
+    Line,
+    /// The vehicle journeys don't stop at any stop point of the stop area
+    StopArea,
+    /// The vehicle journeys don't stop at the stop point
+    StopPoint,
+}
+
+/// An object closed from `start_date` to `end_date`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlannedDisruption {
+    /// Identifier of the disruption
+    pub disruption_id: String,
+    /// Type of the object closed
+    pub object_type: DisruptedObjectType,
+    /// Identifier of the object closed
+    pub object_id: String,
+    /// First day of the disruption
+    #[serde(deserialize_with = "de_from_date_string")]
+    pub start_date: Date,
+    /// Last day of the disruption
+    #[serde(deserialize_with = "de_from_date_string")]
+    pub end_date: Date,
+    /// Explanation of the disruption, added as a comment on the vehicle
+    /// journeys disrupted
+    pub message: String,
+}
+
+/// Categories of the `Report` produced by the application of the planned
+/// disruptions
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisruptionReportCategory {
+    /// The object of a disruption doesn't exist, the disruption is ignored
+    UnknownObject,
+    /// The start date of a disruption is after its end date, the disruption
+    /// is ignored
+    InvalidPeriod,
+    /// A vehicle journey no longer runs during a disruption
+    VehicleJourneyTrimmed,
+    /// A vehicle journey no longer runs at all
+    VehicleJourneyRemoved,
+    /// The stop times at the closed stops of a vehicle journey have been
+    /// removed
+    StopTimesRemoved,
+    /// A vehicle journey has been split in two, one running during a
+    /// disruption without the closed stops and the other one outside
+    VehicleJourneySplit,
+}
+
+/// Read the disruptions from the CSV file `path`, with the columns
+/// `disruption_id`, `object_type` (`line`, `stop_area` or `stop_point`),
+/// `object_id`, `start_date`, `end_date` (dates written `YYYYMMDD`) and
+/// `message`
+pub fn read_disruptions<P: AsRef<Path>>(path: P) -> Result<Vec<PlannedDisruption>> {
+    let path = path.as_ref();
+    info!("Reading planned disruptions from {:?}", path);
+    let mut rdr =
+        csv::Reader::from_path(path).with_context(|| format!("Error reading {:?}", path))?;
+    let disruptions = rdr
+        .deserialize()
+        .collect::<std::result::Result<_, _>>()
+        .with_context(|| format!("Error parsing {:?}", path))?;
+    Ok(disruptions)
+}
+
+// The stop points closed by the disruption, `None` if its object doesn't exist
+fn closed_stop_point_idxs(
+    collections: &Collections,
+    disruption: &PlannedDisruption,
+) -> Option<HashSet<Idx<StopPoint>>> {
+    match disruption.object_type {
+        DisruptedObjectType::Line => {
+            if collections.lines.contains_id(&disruption.object_id) {
+                Some(HashSet::new())
+            } else {
+                None
+            }
+        }
+        DisruptedObjectType::StopPoint => collections
+            .stop_points
+            .get_idx(&disruption.object_id)
+            .map(|stop_point_idx| std::iter::once(stop_point_idx).collect()),
+        DisruptedObjectType::StopArea => {
+            if collections.stop_areas.contains_id(&disruption.object_id) {
+                Some(
+                    collections
+                        .stop_points
+                        .iter()
+                        .filter(|(_, stop_point)| stop_point.stop_area_id == disruption.object_id)
+                        .map(|(stop_point_idx, _)| stop_point_idx)
+                        .collect(),
+                )
+            } else {
+                None
+            }
+        }
+    }
+}
+
+// Remove the headsigns, identifiers and comments of the stop times of the
+// vehicle journey `vj_id` which are not in `sequences`
+fn retain_stop_time_properties(
+    collections: &mut Collections,
+    vj_id: &str,
+    sequences: &HashSet<u32>,
+) {
+    let keep = |(id, sequence): &(String, u32), _: &mut String| {
+        id != vj_id || sequences.contains(sequence)
+    };
+    collections.stop_time_headsigns.retain(keep);
+    collections.stop_time_ids.retain(keep);
+    collections.stop_time_comments.retain(keep);
+}
+
+/// Apply the `disruptions` on `collections`.
+///
+/// During a disruption of a line, its vehicle journeys no longer run: their
+/// calendars are trimmed, and the vehicle journeys no longer running at all
+/// are removed. During a disruption of a stop, the vehicle journeys run
+/// without their stop times at the closed stop points: a vehicle journey
+/// running both during and outside the disruption is split, the copy
+/// running the days of the disruption with its own calendar, its
+/// frequencies and the properties of its remaining stop times. A vehicle
+/// journey left with less than 2 stop times doesn't run during the
+/// disruption.
+///
+/// The `message` of a disruption is added as a comment on the vehicle
+/// journeys changed, and every change is listed in the returned `Report`.
+/// The objects no longer used are left to `Collections::sanitize`.
+pub fn apply_disruptions(
+    collections: &mut Collections,
+    disruptions: &[PlannedDisruption],
+) -> Report<DisruptionReportCategory> {
+    let mut report = Report::default();
+    let mut removed_vehicle_journeys = HashSet::new();
+    for (position, disruption) in disruptions.iter().enumerate() {
+        let closed_stop_points = match closed_stop_point_idxs(collections, disruption) {
+            Some(closed_stop_points) => closed_stop_points,
+            None => {
+                report.add_error(
+                    format!(
+                        "{:?} {} doesn't exist, the disruption {} is ignored",
+                        disruption.object_type, disruption.object_id, disruption.disruption_id
+                    ),
+                    DisruptionReportCategory::UnknownObject,
+                );
+                continue;
+            }
+        };
+        if disruption.start_date > disruption.end_date {
+            report.add_error(
+                format!(
+                    "the disruption {} starts after its end, it is ignored",
+                    disruption.disruption_id
+                ),
+                DisruptionReportCategory::InvalidPeriod,
+            );
+            continue;
+        }
+        let during = |date: &Date| disruption.start_date <= *date && *date <= disruption.end_date;
+        let comment_id = format!("disruption:{}", disruption.disruption_id);
+        if !collections.comments.contains_id(&comment_id) {
+            // the identifier is checked just above
+            let _ = collections.comments.push(Comment {
+                id: comment_id.clone(),
+                comment_type: CommentType::Information,
+                label: None,
+                name: disruption.message.clone(),
+                url: None,
+            });
+        }
+
+        let vj_idxs: Vec<_> = collections
+            .vehicle_journeys
+            .iter()
+            .filter(|(_, vj)| match disruption.object_type {
+                DisruptedObjectType::Line => collections
+                    .routes
+                    .get(&vj.route_id)
+                    .map_or(false, |route| route.line_id == disruption.object_id),
+                _ => vj
+                    .stop_times
+                    .iter()
+                    .any(|stop_time| closed_stop_points.contains(&stop_time.stop_point_idx)),
+            })
+            .map(|(vj_idx, _)| vj_idx)
+            .collect();
+        // original vehicle journey id => id of its copy during the disruption
+        let mut split_vehicle_journeys = HashMap::new();
+        for vj_idx in vj_idxs {
+            let vj = &collections.vehicle_journeys[vj_idx];
+            if removed_vehicle_journeys.contains(&vj.id) {
+                continue;
+            }
+            let (dates_during, dates_outside): (BTreeSet<Date>, BTreeSet<Date>) =
+                match collections.calendars.get(&vj.service_id) {
+                    Some(calendar) => calendar.dates.iter().copied().partition(during),
+                    None => continue,
+                };
+            if dates_during.is_empty() {
+                continue;
+            }
+            let vj_id = vj.id.clone();
+            let service_id = vj.service_id.clone();
+            let mut adapted_vj = vj.clone();
+            adapted_vj
+                .stop_times
+                .retain(|stop_time| !closed_stop_points.contains(&stop_time.stop_point_idx));
+            let sequences: HashSet<u32> = adapted_vj
+                .stop_times
+                .iter()
+                .map(|stop_time| stop_time.sequence)
+                .collect();
+
+            let runs_during = disruption.object_type != DisruptedObjectType::Line
+                && adapted_vj.stop_times.len() >= 2;
+            if !runs_during {
+                if dates_outside.is_empty() {
+                    removed_vehicle_journeys.insert(vj_id.clone());
+                    report.add_entry(
+                        Severity::Info,
+                        ReportEntry::new(
+                            DisruptionReportCategory::VehicleJourneyRemoved,
+                            format!(
+                                "vehicle journey {} removed by the disruption {}",
+                                vj_id, disruption.disruption_id
+                            ),
+                        )
+                        .with_object_id(vj_id),
+                    );
+                    continue;
+                }
+                let outside_service_id = add_calendar(
+                    collections,
+                    format!("{}:not_disrupted:{}", service_id, position),
+                    dates_outside,
+                );
+                let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+                vj.service_id = outside_service_id;
+                vj.comment_links.insert(comment_id.clone());
+                drop(vj);
+                report.add_entry(
+                    Severity::Info,
+                    ReportEntry::new(
+                        DisruptionReportCategory::VehicleJourneyTrimmed,
+                        format!(
+                            "vehicle journey {} doesn't run during the disruption {}",
+                            vj_id, disruption.disruption_id
+                        ),
+                    )
+                    .with_object_id(vj_id),
+                );
+            } else if dates_outside.is_empty() {
+                let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+                vj.stop_times = adapted_vj.stop_times;
+                vj.comment_links.insert(comment_id.clone());
+                drop(vj);
+                retain_stop_time_properties(collections, &vj_id, &sequences);
+                report.add_entry(
+                    Severity::Info,
+                    ReportEntry::new(
+                        DisruptionReportCategory::StopTimesRemoved,
+                        format!(
+                            "vehicle journey {} doesn't stop at {} during the disruption {}",
+                            vj_id, disruption.object_id, disruption.disruption_id
+                        ),
+                    )
+                    .with_object_id(vj_id),
+                );
+            } else {
+                adapted_vj.id = format!("{}:disrupted:{}", vj_id, position);
+                if collections.vehicle_journeys.contains_id(&adapted_vj.id) {
+                    continue;
+                }
+                adapted_vj.service_id = add_calendar(
+                    collections,
+                    format!("{}:disrupted:{}", service_id, position),
+                    dates_during,
+                );
+                adapted_vj.comment_links.insert(comment_id.clone());
+                let outside_service_id = add_calendar(
+                    collections,
+                    format!("{}:not_disrupted:{}", service_id, position),
+                    dates_outside,
+                );
+                collections.vehicle_journeys.index_mut(vj_idx).service_id = outside_service_id;
+                report.add_entry(
+                    Severity::Info,
+                    ReportEntry::new(
+                        DisruptionReportCategory::VehicleJourneySplit,
+                        format!(
+                            "vehicle journey {} split, {} doesn't stop at {} during the disruption {}",
+                            vj_id, adapted_vj.id, disruption.object_id, disruption.disruption_id
+                        ),
+                    )
+                    .with_object_id(vj_id.clone()),
+                );
+                split_vehicle_journeys.insert(vj_id, (adapted_vj.id.clone(), sequences));
+                // the identifier is checked above
+                let _ = collections.vehicle_journeys.push(adapted_vj);
+            }
+        }
+        if split_vehicle_journeys.is_empty() {
+            continue;
+        }
+
+        let suffix = format!(":disrupted:{}", position);
+        let adapted_ids: HashMap<String, String> = split_vehicle_journeys
+            .iter()
+            .map(|(vj_id, (adapted_vj_id, _))| (vj_id.clone(), adapted_vj_id.clone()))
+            .collect();
+        copy_stop_time_properties(
+            &mut collections.stop_time_headsigns,
+            &adapted_ids,
+            str::to_string,
+        );
+        copy_stop_time_properties(&mut collections.stop_time_ids, &adapted_ids, |id| {
+            format!("{}{}", id, suffix)
+        });
+        copy_stop_time_properties(
+            &mut collections.stop_time_comments,
+            &adapted_ids,
+            str::to_string,
+        );
+        for (adapted_vj_id, sequences) in split_vehicle_journeys.values() {
+            retain_stop_time_properties(collections, adapted_vj_id, sequences);
+        }
+        copy_frequencies(collections, &adapted_ids);
+    }
+    collections
+        .vehicle_journeys
+        .retain(|vj| !removed_vehicle_journeys.contains(&vj.id));
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn disruption(object_type: DisruptedObjectType, object_id: &str) -> PlannedDisruption {
+        PlannedDisruption {
+            disruption_id: "works".to_string(),
+            object_type,
+            object_id: object_id.to_string(),
+            start_date: Date::from_ymd_opt(2020, 1, 2).unwrap(),
+            end_date: Date::from_ymd_opt(2020, 1, 31).unwrap(),
+            message: "Works from January 2nd".to_string(),
+        }
+    }
+
+    fn dates(collections: &Collections, vj_id: &str) -> Vec<String> {
+        let service_id = &collections.vehicle_journeys.get(vj_id).unwrap().service_id;
+        collections
+            .calendars
+            .get(service_id)
+            .unwrap()
+            .dates
+            .iter()
+            .map(Date::to_string)
+            .collect()
+    }
+
+    fn stop_point_ids<'a>(collections: &'a Collections, vj_id: &str) -> Vec<&'a str> {
+        collections
+            .vehicle_journeys
+            .get(vj_id)
+            .unwrap()
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                collections.stop_points[stop_time.stop_point_idx]
+                    .id
+                    .as_str()
+            })
+            .collect()
+    }
+
+    fn two_lines() -> Collections {
+        ModelBuilder::default()
+            .calendar("c1", &["2020-01-01", "2020-01-02", "2020-01-03"])
+            .calendar("c2", &["2020-01-02"])
+            .route("r1", |route| {
+                route.line_id = "l1".to_string();
+            })
+            .route("r2", |route| {
+                route.line_id = "l2".to_string();
+            })
+            .vj("vj1", |vj| {
+                vj.route("r1")
+                    .calendar("c1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00")
+                    .st("C", "12:00:00", "12:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("r2")
+                    .calendar("c2")
+                    .st("A", "12:00:00", "12:01:00")
+                    .st("B", "13:00:00", "13:01:00");
+            })
+            .build_model()
+            .into_collections()
+    }
+
+    #[test]
+    fn closed_line() {
+        let mut collections = two_lines();
+        let report = apply_disruptions(
+            &mut collections,
+            &[disruption(DisruptedObjectType::Line, "l1")],
+        );
+
+        assert!(report.errors().is_empty());
+        assert_eq!(vec!["2020-01-01"], dates(&collections, "vj1"));
+        assert_eq!(vec!["2020-01-02"], dates(&collections, "vj2"));
+        let vj1 = collections.vehicle_journeys.get("vj1").unwrap();
+        assert!(vj1.comment_links.contains("disruption:works"));
+        assert_eq!(
+            "Works from January 2nd",
+            collections.comments.get("disruption:works").unwrap().name
+        );
+    }
+
+    #[test]
+    fn closed_stop_point() {
+        let mut collections = two_lines();
+        apply_disruptions(
+            &mut collections,
+            &[disruption(DisruptedObjectType::StopPoint, "B")],
+        );
+
+        assert_eq!(3, collections.vehicle_journeys.len());
+        assert_eq!(vec!["A", "B", "C"], stop_point_ids(&collections, "vj1"));
+        assert_eq!(vec!["2020-01-01"], dates(&collections, "vj1"));
+        assert_eq!(
+            vec!["A", "C"],
+            stop_point_ids(&collections, "vj1:disrupted:0")
+        );
+        assert_eq!(
+            vec!["2020-01-02", "2020-01-03"],
+            dates(&collections, "vj1:disrupted:0")
+        );
+        // vj2 is left with only 1 stop time during the disruption
+        assert!(!collections.vehicle_journeys.contains_id("vj2"));
+    }
+
+    #[test]
+    fn unknown_object() {
+        let mut collections = ModelBuilder::default()
+            .calendar("c1", &["2020-01-01", "2020-01-02", "2020-01-03"])
+            .vj("vj1", |vj| {
+                vj.calendar("c1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections();
+        let report = apply_disruptions(
+            &mut collections,
+            &[disruption(DisruptedObjectType::Line, "unknown")],
+        );
+        assert_eq!(
+            DisruptionReportCategory::UnknownObject,
+            report.errors()[0].category
+        );
+        assert_eq!(
+            vec!["2020-01-01", "2020-01-02", "2020-01-03"],
+            dates(&collections, "vj1")
+        );
+    }
+}
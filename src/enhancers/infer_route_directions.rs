@@ -0,0 +1,306 @@
+use crate::{
+    model::Collections,
+    objects::{Coord, Route, StopTime, VehicleJourney},
+};
+use geo::Geometry as GeoGeometry;
+use relational_types::Relation;
+use std::collections::BTreeMap;
+use tracing::debug;
+use typed_index_collection::Idx;
+
+// Terminus stop areas and overall heading (as a (lon, lat) vector) of a route
+struct RouteTerminus<'a> {
+    origin_id: &'a str,
+    destination_id: &'a str,
+    heading: Option<(f64, f64)>,
+}
+
+impl RouteTerminus<'_> {
+    fn is_loop(&self) -> bool {
+        self.origin_id == self.destination_id
+    }
+    fn same_as(&self, other: &RouteTerminus) -> bool {
+        self.origin_id == other.origin_id && self.destination_id == other.destination_id
+    }
+    fn reversed_of(&self, other: &RouteTerminus) -> bool {
+        self.origin_id == other.destination_id && self.destination_id == other.origin_id
+    }
+    fn heading_opposite_to(&self, other: &RouteTerminus) -> bool {
+        match (self.heading, other.heading) {
+            (Some((x1, y1)), Some((x2, y2))) => x1 * x2 + y1 * y2 < 0.0,
+            _ => false,
+        }
+    }
+}
+
+fn opposite_direction(direction_type: &str) -> Option<&'static str> {
+    match direction_type {
+        "forward" => Some("backward"),
+        "backward" => Some("forward"),
+        "inbound" => Some("outbound"),
+        "outbound" => Some("inbound"),
+        "clockwise" => Some("anticlockwise"),
+        "anticlockwise" => Some("clockwise"),
+        _ => None,
+    }
+}
+
+// Most frequent identifier, the first in alphabetical order in case of equality
+fn most_frequent<'a>(ids: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let mut frequencies: BTreeMap<&str, usize> = BTreeMap::new();
+    for id in ids {
+        *frequencies.entry(id).or_default() += 1;
+    }
+    frequencies
+        .into_iter()
+        .max_by(|(id1, freq1), (id2, freq2)| freq1.cmp(freq2).then(id2.cmp(id1)))
+        .map(|(id, _)| id)
+}
+
+fn route_terminus<'a>(
+    collections: &'a Collections,
+    route_idx: Idx<Route>,
+    routes_to_vehicle_journeys: &impl Relation<From = Route, To = VehicleJourney>,
+) -> Option<RouteTerminus<'a>> {
+    let vehicle_journeys: Vec<&VehicleJourney> = routes_to_vehicle_journeys
+        .get_corresponding_forward(&std::iter::once(route_idx).collect())
+        .into_iter()
+        .map(|vj_idx| &collections.vehicle_journeys[vj_idx])
+        .filter(|vj| vj.stop_times.len() > 1)
+        .collect();
+    let stop_points = &collections.stop_points;
+    let stop_area_id =
+        move |stop_time: &StopTime| stop_points[stop_time.stop_point_idx].stop_area_id.as_str();
+    let origin_id = most_frequent(
+        vehicle_journeys
+            .iter()
+            .filter_map(|vj| vj.stop_times.first())
+            .map(stop_area_id),
+    )?;
+    let destination_id = most_frequent(
+        vehicle_journeys
+            .iter()
+            .filter_map(|vj| vj.stop_times.last())
+            .map(stop_area_id),
+    )?;
+
+    let route = &collections.routes[route_idx];
+    let geometry_heading = route
+        .geometry_id
+        .as_ref()
+        .and_then(|geometry_id| collections.geometries.get(geometry_id))
+        .and_then(|geometry| match &geometry.geometry {
+            GeoGeometry::LineString(line_string) => line_string.0.first().zip(line_string.0.last()),
+            _ => None,
+        })
+        .map(|(first, last)| (last.x - first.x, last.y - first.y));
+    let terminus_heading = || {
+        let origin = collections.stop_areas.get(origin_id)?.coord;
+        let destination = collections.stop_areas.get(destination_id)?.coord;
+        if origin == Coord::default() || destination == Coord::default() {
+            return None;
+        }
+        Some((destination.lon - origin.lon, destination.lat - origin.lat))
+    };
+    let heading = geometry_heading
+        .or_else(terminus_heading)
+        .filter(|(x, y)| *x != 0.0 || *y != 0.0);
+
+    Some(RouteTerminus {
+        origin_id,
+        destination_id,
+        heading,
+    })
+}
+
+/// Infer the `direction_type` of routes which don't have one, using the other
+/// routes of the same line:
+/// - a route going back to its origin is `clockwise`
+/// - a route with the same terminus as another route takes its direction
+/// - a route with reversed terminus (or, for a line with only 2 routes, an
+///   opposite heading of the geometries) takes the opposite direction; if none
+///   of the 2 routes have a direction, the first one (in alphabetical order of
+///   identifiers) is `forward` and the other one `backward`
+///
+/// Routes for which nothing can be inferred are left untouched.
+pub fn infer_route_directions(
+    collections: &mut Collections,
+    routes_to_vehicle_journeys: &impl Relation<From = Route, To = VehicleJourney>,
+) {
+    let mut routes_by_line: BTreeMap<&str, Vec<Idx<Route>>> = BTreeMap::new();
+    for (route_idx, route) in collections.routes.iter() {
+        routes_by_line
+            .entry(route.line_id.as_str())
+            .or_default()
+            .push(route_idx);
+    }
+
+    let mut direction_types: BTreeMap<Idx<Route>, String> = BTreeMap::new();
+    for route_idxs in routes_by_line.values_mut() {
+        if route_idxs
+            .iter()
+            .all(|route_idx| collections.routes[*route_idx].direction_type.is_some())
+        {
+            continue;
+        }
+        route_idxs.sort_by_key(|route_idx| &collections.routes[*route_idx].id);
+        let terminus: BTreeMap<Idx<Route>, RouteTerminus> = route_idxs
+            .iter()
+            .filter_map(|route_idx| {
+                route_terminus(collections, *route_idx, routes_to_vehicle_journeys)
+                    .map(|terminus| (*route_idx, terminus))
+            })
+            .collect();
+        let mut line_directions: BTreeMap<Idx<Route>, String> = route_idxs
+            .iter()
+            .filter_map(|route_idx| {
+                collections.routes[*route_idx]
+                    .direction_type
+                    .clone()
+                    .map(|direction_type| (*route_idx, direction_type))
+            })
+            .collect();
+
+        for route_idx in route_idxs.iter() {
+            if line_directions.contains_key(route_idx) {
+                continue;
+            }
+            let current = match terminus.get(route_idx) {
+                Some(current) => current,
+                None => continue,
+            };
+            if current.is_loop() {
+                line_directions.insert(*route_idx, String::from("clockwise"));
+                continue;
+            }
+            let others: Vec<(Idx<Route>, &RouteTerminus)> = route_idxs
+                .iter()
+                .filter(|other_idx| *other_idx != route_idx)
+                .filter_map(|other_idx| terminus.get(other_idx).map(|t| (*other_idx, t)))
+                .collect();
+            let same = others
+                .iter()
+                .filter(|(_, other)| current.same_as(other))
+                .find_map(|(other_idx, _)| line_directions.get(other_idx).cloned());
+            let opposite_of = |(other_idx, _): &(Idx<Route>, &RouteTerminus)| {
+                line_directions
+                    .get(other_idx)
+                    .and_then(|direction_type| opposite_direction(direction_type))
+                    .map(String::from)
+            };
+            let is_mirrored = |other: &RouteTerminus| {
+                current.reversed_of(other)
+                    || (route_idxs.len() == 2 && current.heading_opposite_to(other))
+            };
+            let opposite = others
+                .iter()
+                .filter(|(_, other)| is_mirrored(other))
+                .find_map(opposite_of);
+            // the routes being sorted by identifier, the first route of a
+            // mirrored pair without any direction is `forward`, the other one
+            // then taking the opposite direction
+            let first_of_pair = || {
+                others
+                    .iter()
+                    .any(|(other_idx, other)| {
+                        is_mirrored(other) && !line_directions.contains_key(other_idx)
+                    })
+                    .then(|| String::from("forward"))
+            };
+            if let Some(direction_type) = same.or(opposite).or_else(first_of_pair) {
+                line_directions.insert(*route_idx, direction_type);
+            }
+        }
+
+        for (route_idx, direction_type) in line_directions {
+            if collections.routes[route_idx].direction_type.is_none() {
+                direction_types.insert(route_idx, direction_type);
+            }
+        }
+    }
+
+    for (route_idx, direction_type) in direction_types {
+        debug!(
+            "direction type '{}' inferred for route {}",
+            direction_type, collections.routes[route_idx].id
+        );
+        collections.routes.index_mut(route_idx).direction_type = Some(direction_type);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn direction_type<'a>(model: &'a crate::Model, route_id: &str) -> &'a str {
+        model
+            .routes
+            .get(route_id)
+            .unwrap()
+            .direction_type
+            .as_deref()
+            .unwrap()
+    }
+
+    #[test]
+    fn reversed_terminus_without_direction() {
+        let model = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.route("route2")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("route1")
+                    .st("B", "10:00:00", "10:01:00")
+                    .st("A", "11:00:00", "11:01:00");
+            })
+            .build_model();
+        // the first route in alphabetical order is forward
+        assert_eq!("forward", direction_type(&model, "route1"));
+        assert_eq!("backward", direction_type(&model, "route2"));
+    }
+
+    #[test]
+    fn opposite_of_existing_direction() {
+        let model = ModelBuilder::default()
+            .route("route1", |r| {
+                r.id = "route1".to_string();
+                r.direction_type = Some("inbound".to_string());
+            })
+            .vj("vj1", |vj| {
+                vj.route("route1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("route2")
+                    .st("B", "10:00:00", "10:01:00")
+                    .st("A", "11:00:00", "11:01:00");
+            })
+            .vj("vj3", |vj| {
+                vj.route("route3")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .build_model();
+        assert_eq!("inbound", direction_type(&model, "route1"));
+        assert_eq!("outbound", direction_type(&model, "route2"));
+        assert_eq!("inbound", direction_type(&model, "route3"));
+    }
+
+    #[test]
+    fn loop_route() {
+        let model = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.route("route1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00")
+                    .st("A", "12:00:00", "12:01:00");
+            })
+            .build_model();
+        assert_eq!("clockwise", direction_type(&model, "route1"));
+    }
+}
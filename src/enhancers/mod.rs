@@ -4,6 +4,7 @@ mod adjust_lines_names;
 mod check_stop_times_order;
 mod enhance_pickup_dropoff;
 mod fill_co2;
+mod infer_route_directions;
 mod memory_shrink;
 
 pub(crate) use adjust_lines_names::adjust_lines_names;
@@ -11,4 +12,5 @@ pub(crate) use check_stop_times_order::check_stop_times_order;
 pub(crate) use enhance_pickup_dropoff::enhance_pickup_dropoff;
 pub(crate) use fill_co2::fill_co2;
 pub(crate) use fill_co2::FALLBACK_PHYSICAL_MODES;
+pub(crate) use infer_route_directions::infer_route_directions;
 pub(crate) use memory_shrink::memory_shrink;
@@ -0,0 +1,163 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Generation of the pathways between the entrances of the stop areas and
+//! their stop points.
+//!
+//! Some sources describe the entrances of a station (with their address)
+//! without any pathway, like the `StopPlaceEntrance` of NeTEx. The GTFS
+//! requires a pathway to reach a stop from an entrance, so these entrances
+//! are linked to every stop point of their stop area.
+
+use crate::{
+    model::Collections,
+    objects::{Coord, Pathway, PathwayMode, StopType},
+};
+use num_traits::cast::FromPrimitive;
+use rust_decimal::Decimal;
+use skip_error::skip_error_and_warn;
+use std::collections::HashSet;
+use tracing::info;
+
+/// Default walking speed in the stations, in meters per second
+pub const DEFAULT_WALKING_SPEED: f64 = 0.785;
+
+/// Add a bidirectional walkway from each entrance of `collections` without
+/// any pathway to each stop point of its stop area.
+///
+/// The length of the walkway is the distance as the crow flies between the
+/// entrance and the stop point, and its traversal time is computed with
+/// `walking_speed` (in meters per second); both are unknown when a
+/// coordinate is missing. The identifier of a walkway is
+/// `<entrance_id>:<stop_point_id>`.
+///
+/// Returns the number of generated pathways.
+pub fn generate_entrance_pathways(collections: &mut Collections, walking_speed: f64) -> usize {
+    let linked_stop_ids: HashSet<&str> = collections
+        .pathways
+        .values()
+        .flat_map(|pathway| vec![pathway.from_stop_id.as_str(), pathway.to_stop_id.as_str()])
+        .collect();
+    let mut pathways = Vec::new();
+    for entrance in collections.stop_locations.values() {
+        if entrance.stop_type != StopType::StopEntrance
+            || linked_stop_ids.contains(entrance.id.as_str())
+        {
+            continue;
+        }
+        let stop_area_id = match &entrance.parent_id {
+            Some(stop_area_id) => stop_area_id,
+            None => continue,
+        };
+        for stop_point in collections
+            .stop_points
+            .values()
+            .filter(|stop_point| &stop_point.stop_area_id == stop_area_id)
+        {
+            let distance =
+                if entrance.coord == Coord::default() || stop_point.coord == Coord::default() {
+                    None
+                } else {
+                    Some(entrance.coord.distance_to(&stop_point.coord))
+                };
+            pathways.push(Pathway {
+                id: format!("{}:{}", entrance.id, stop_point.id),
+                from_stop_id: entrance.id.clone(),
+                from_stop_type: StopType::StopEntrance,
+                to_stop_id: stop_point.id.clone(),
+                to_stop_type: StopType::Point,
+                pathway_mode: PathwayMode::Walkway,
+                is_bidirectional: true,
+                length: distance
+                    .and_then(Decimal::from_f64)
+                    .map(|length| length.round_dp(0)),
+                traversal_time: distance.map(|distance| (distance / walking_speed).round() as u32),
+                ..Default::default()
+            });
+        }
+    }
+    let mut count = 0;
+    for pathway in pathways {
+        skip_error_and_warn!(collections.pathways.push(pathway));
+        count += 1;
+    }
+    info!("{} pathway(s) generated from the entrances", count);
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::StopLocation;
+    use crate::test_fixtures::one_vehicle_journey;
+    use pretty_assertions::assert_eq;
+
+    // An entrance of the stop area of 'A', about 111 meters away from 'A'
+    fn entrance_near_stop_point() -> Collections {
+        let mut collections = one_vehicle_journey().into_collections();
+        let mut stop_point = collections.stop_points.get_mut("A").unwrap();
+        stop_point.coord = Coord {
+            lon: 0.,
+            lat: 0.001,
+        };
+        drop(stop_point);
+        collections
+            .stop_locations
+            .push(StopLocation {
+                id: "entrance:A".to_string(),
+                name: "Main entrance".to_string(),
+                code: None,
+                comment_links: Default::default(),
+                visible: true,
+                coord: Coord {
+                    lon: 0.,
+                    lat: 0.002,
+                },
+                parent_id: Some("sa:A".to_string()),
+                timezone: None,
+                geometry_id: None,
+                equipment_id: None,
+                level_id: None,
+                stop_type: StopType::StopEntrance,
+                address_id: Some("address:1".to_string()),
+            })
+            .unwrap();
+        collections
+    }
+
+    #[test]
+    fn generate_walkway() {
+        let mut collections = entrance_near_stop_point();
+        let count = generate_entrance_pathways(&mut collections, DEFAULT_WALKING_SPEED);
+
+        assert_eq!(1, count);
+        let pathway = collections.pathways.get("entrance:A:A").unwrap();
+        assert_eq!("entrance:A", pathway.from_stop_id);
+        assert_eq!("A", pathway.to_stop_id);
+        assert!(pathway.is_bidirectional);
+        // about 111 meters between the entrance and the stop point
+        assert_eq!(Some(Decimal::from(111)), pathway.length);
+        assert_eq!(Some(142), pathway.traversal_time);
+    }
+
+    #[test]
+    fn entrance_with_pathway() {
+        let mut collections = entrance_near_stop_point();
+        generate_entrance_pathways(&mut collections, DEFAULT_WALKING_SPEED);
+        let count = generate_entrance_pathways(&mut collections, DEFAULT_WALKING_SPEED);
+
+        assert_eq!(0, count);
+        assert_eq!(1, collections.pathways.len());
+    }
+}
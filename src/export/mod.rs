@@ -0,0 +1,25 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Exports of a `Model` into formats dedicated to data analysis, most of them
+//! being enabled by a feature of the crate.
+
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "postgis")]
+pub mod postgis;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod timetable;
+pub mod timetable_notes;
@@ -0,0 +1,303 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Export of the stops, trips and stop times of a `Model` as
+//! [Parquet](https://parquet.apache.org/) files, for analysis with tools like
+//! pandas or DuckDB.
+//!
+//! Times are exported as a number of seconds since midnight of the service
+//! day (they can be greater than 24 hours).
+
+use crate::{Model, Result};
+use anyhow::Context;
+use arrow::{
+    array::{ArrayRef, Float64Array, StringArray, UInt16Array, UInt32Array, UInt8Array},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    record_batch::RecordBatch,
+};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{fs::File, path::Path, sync::Arc};
+use tracing::info;
+
+// Number of stop times in each batch written in the Parquet file
+const STOP_TIMES_BATCH_SIZE: usize = 100_000;
+
+fn writer(path: &Path, file_name: &str, schema: SchemaRef) -> Result<ArrowWriter<File>> {
+    let file_path = path.join(file_name);
+    let file =
+        File::create(&file_path).with_context(|| format!("Error creating {:?}", file_path))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let writer = ArrowWriter::try_new(file, schema, Some(properties))
+        .with_context(|| format!("Error writing {:?}", file_path))?;
+    Ok(writer)
+}
+
+fn write_stops(path: &Path, model: &Model) -> Result<()> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("stop_id", DataType::Utf8, false),
+        Field::new("stop_name", DataType::Utf8, false),
+        Field::new("stop_code", DataType::Utf8, true),
+        Field::new("location_type", DataType::UInt8, false),
+        Field::new("parent_station", DataType::Utf8, true),
+        Field::new("stop_lat", DataType::Float64, false),
+        Field::new("stop_lon", DataType::Float64, false),
+        Field::new("stop_timezone", DataType::Utf8, true),
+    ]));
+    let stop_areas = model.stop_areas.values();
+    let stop_points = model.stop_points.values();
+    let capacity = model.stop_areas.len() + model.stop_points.len();
+    let mut ids = Vec::with_capacity(capacity);
+    let mut names = Vec::with_capacity(capacity);
+    let mut codes = Vec::with_capacity(capacity);
+    let mut location_types = Vec::with_capacity(capacity);
+    let mut parents = Vec::with_capacity(capacity);
+    let mut lats = Vec::with_capacity(capacity);
+    let mut lons = Vec::with_capacity(capacity);
+    let mut timezones = Vec::with_capacity(capacity);
+    for stop_area in stop_areas {
+        ids.push(stop_area.id.as_str());
+        names.push(stop_area.name.as_str());
+        codes.push(None);
+        location_types.push(1);
+        parents.push(None);
+        lats.push(stop_area.coord.lat);
+        lons.push(stop_area.coord.lon);
+        timezones.push(stop_area.timezone.map(|tz| tz.name()));
+    }
+    for stop_point in stop_points {
+        ids.push(stop_point.id.as_str());
+        names.push(stop_point.name.as_str());
+        codes.push(stop_point.code.as_deref());
+        location_types.push(0);
+        parents.push(Some(stop_point.stop_area_id.as_str()));
+        lats.push(stop_point.coord.lat);
+        lons.push(stop_point.coord.lon);
+        timezones.push(stop_point.timezone.map(|tz| tz.name()));
+    }
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(ids)),
+        Arc::new(StringArray::from(names)),
+        Arc::new(StringArray::from(codes)),
+        Arc::new(UInt8Array::from(location_types)),
+        Arc::new(StringArray::from(parents)),
+        Arc::new(Float64Array::from(lats)),
+        Arc::new(Float64Array::from(lons)),
+        Arc::new(StringArray::from(timezones)),
+    ];
+    let mut writer = writer(path, "stops.parquet", schema.clone())?;
+    writer.write(&RecordBatch::try_new(schema, columns)?)?;
+    writer.close()?;
+    Ok(())
+}
+
+fn write_trips(path: &Path, model: &Model) -> Result<()> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("trip_id", DataType::Utf8, false),
+        Field::new("route_id", DataType::Utf8, false),
+        Field::new("line_id", DataType::Utf8, true),
+        Field::new("service_id", DataType::Utf8, false),
+        Field::new("physical_mode_id", DataType::Utf8, false),
+        Field::new("company_id", DataType::Utf8, false),
+        Field::new("dataset_id", DataType::Utf8, false),
+        Field::new("trip_headsign", DataType::Utf8, true),
+        Field::new("trip_short_name", DataType::Utf8, true),
+        Field::new("block_id", DataType::Utf8, true),
+    ]));
+    let vehicle_journeys: Vec<_> = model.vehicle_journeys.values().collect();
+    let line_id = |route_id: &str| {
+        model
+            .routes
+            .get(route_id)
+            .map(|route| route.line_id.as_str())
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            vehicle_journeys.iter().map(|vj| &vj.id),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            vehicle_journeys.iter().map(|vj| &vj.route_id),
+        )),
+        Arc::new(StringArray::from_iter(
+            vehicle_journeys.iter().map(|vj| line_id(&vj.route_id)),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            vehicle_journeys.iter().map(|vj| &vj.service_id),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            vehicle_journeys.iter().map(|vj| &vj.physical_mode_id),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            vehicle_journeys.iter().map(|vj| &vj.company_id),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            vehicle_journeys.iter().map(|vj| &vj.dataset_id),
+        )),
+        Arc::new(StringArray::from_iter(
+            vehicle_journeys.iter().map(|vj| vj.headsign.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter(
+            vehicle_journeys.iter().map(|vj| vj.short_name.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter(
+            vehicle_journeys.iter().map(|vj| vj.block_id.as_deref()),
+        )),
+    ];
+    let mut writer = writer(path, "trips.parquet", schema.clone())?;
+    writer.write(&RecordBatch::try_new(schema, columns)?)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct StopTimesBatch<'a> {
+    trip_ids: Vec<&'a str>,
+    stop_sequences: Vec<u32>,
+    stop_ids: Vec<&'a str>,
+    arrival_times: Vec<u32>,
+    departure_times: Vec<u32>,
+    boarding_durations: Vec<u16>,
+    alighting_durations: Vec<u16>,
+    pickup_types: Vec<u8>,
+    drop_off_types: Vec<u8>,
+    local_zone_ids: Vec<Option<u16>>,
+    stop_headsigns: Vec<Option<&'a str>>,
+}
+
+impl StopTimesBatch<'_> {
+    fn len(&self) -> usize {
+        self.trip_ids.len()
+    }
+
+    fn into_record_batch(self, schema: SchemaRef) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(self.trip_ids)),
+            Arc::new(UInt32Array::from(self.stop_sequences)),
+            Arc::new(StringArray::from(self.stop_ids)),
+            Arc::new(UInt32Array::from(self.arrival_times)),
+            Arc::new(UInt32Array::from(self.departure_times)),
+            Arc::new(UInt16Array::from(self.boarding_durations)),
+            Arc::new(UInt16Array::from(self.alighting_durations)),
+            Arc::new(UInt8Array::from(self.pickup_types)),
+            Arc::new(UInt8Array::from(self.drop_off_types)),
+            Arc::new(UInt16Array::from(self.local_zone_ids)),
+            Arc::new(StringArray::from(self.stop_headsigns)),
+        ];
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+}
+
+fn write_stop_times(path: &Path, model: &Model) -> Result<()> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("trip_id", DataType::Utf8, false),
+        Field::new("stop_sequence", DataType::UInt32, false),
+        Field::new("stop_id", DataType::Utf8, false),
+        Field::new("arrival_time", DataType::UInt32, false),
+        Field::new("departure_time", DataType::UInt32, false),
+        Field::new("boarding_duration", DataType::UInt16, false),
+        Field::new("alighting_duration", DataType::UInt16, false),
+        Field::new("pickup_type", DataType::UInt8, false),
+        Field::new("drop_off_type", DataType::UInt8, false),
+        Field::new("local_zone_id", DataType::UInt16, true),
+        Field::new("stop_headsign", DataType::Utf8, true),
+    ]));
+    let mut writer = writer(path, "stop_times.parquet", schema.clone())?;
+    let mut batch = StopTimesBatch::default();
+    for vj in model.vehicle_journeys.values() {
+        for stop_time in &vj.stop_times {
+            batch.trip_ids.push(&vj.id);
+            batch.stop_sequences.push(stop_time.sequence);
+            batch
+                .stop_ids
+                .push(&model.stop_points[stop_time.stop_point_idx].id);
+            batch
+                .arrival_times
+                .push(stop_time.arrival_time.total_seconds());
+            batch
+                .departure_times
+                .push(stop_time.departure_time.total_seconds());
+            batch.boarding_durations.push(stop_time.boarding_duration);
+            batch.alighting_durations.push(stop_time.alighting_duration);
+            batch.pickup_types.push(stop_time.pickup_type);
+            batch.drop_off_types.push(stop_time.drop_off_type);
+            batch.local_zone_ids.push(stop_time.local_zone_id);
+            batch.stop_headsigns.push(
+                model
+                    .stop_time_headsigns
+                    .get(&(vj.id.clone(), stop_time.sequence))
+                    .map(String::as_str),
+            );
+        }
+        if batch.len() >= STOP_TIMES_BATCH_SIZE {
+            let full_batch = std::mem::take(&mut batch);
+            writer.write(&full_batch.into_record_batch(schema.clone())?)?;
+        }
+    }
+    if !batch.trip_ids.is_empty() {
+        writer.write(&batch.into_record_batch(schema)?)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// Exports the stops (`stops.parquet`), the trips (`trips.parquet`) and the
+/// stop times (`stop_times.parquet`) of the `Model` into the directory `path`
+pub fn write<P: AsRef<Path>>(model: &Model, path: P) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path)?;
+    info!("Writing Parquet files to {:?}", path);
+    write_stops(path, model)?;
+    write_trips(path, model)?;
+    write_stop_times(path, model)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use crate::test_utils::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn number_of_rows(path: &Path, file_name: &str) -> i64 {
+        let file = File::open(path.join(file_name)).unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+        reader.metadata().file_metadata().num_rows()
+    }
+
+    #[test]
+    fn write_parquet_files() {
+        let model = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("B", "10:00:00", "10:01:00")
+                    .st("C", "11:00:00", "11:01:00")
+                    .st("D", "25:00:00", "25:01:00");
+            })
+            .build_model();
+        test_in_tmp_dir(|path| {
+            write(&model, path).unwrap();
+            // 4 stop points and their 4 stop areas
+            assert_eq!(8, number_of_rows(path, "stops.parquet"));
+            assert_eq!(2, number_of_rows(path, "trips.parquet"));
+            assert_eq!(5, number_of_rows(path, "stop_times.parquet"));
+        });
+    }
+}
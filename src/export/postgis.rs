@@ -0,0 +1,414 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Export of the referential of a `Model` as a SQL dump for
+//! [PostGIS](https://postgis.net), to be loaded with
+//! `psql -f dump.sql`.
+//!
+//! Coordinates of the stops and geometries of the shapes are written as
+//! hexadecimal [EWKB](https://postgis.net/docs/using_postgis_dbmanagement.html#EWKB_EWKT)
+//! geometries with the SRID 4326 (WGS84). The data is loaded with `COPY`
+//! statements inside a single transaction.
+
+use crate::{objects::Coord, Model, Result};
+use anyhow::Context;
+use geo::{Geometry, LineString, Point, Polygon};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+use tracing::info;
+
+/// SRID of the exported geometries (WGS84)
+pub const SRID: u32 = 4326;
+
+const WKB_POINT: u32 = 1;
+const WKB_LINE_STRING: u32 = 2;
+const WKB_POLYGON: u32 = 3;
+const WKB_MULTI_POINT: u32 = 4;
+const WKB_MULTI_LINE_STRING: u32 = 5;
+const WKB_MULTI_POLYGON: u32 = 6;
+const WKB_GEOMETRY_COLLECTION: u32 = 7;
+// Flag of the geometry type announcing a SRID in the EWKB header
+const EWKB_SRID_FLAG: u32 = 0x2000_0000;
+
+const SCHEMA: &str = "CREATE EXTENSION IF NOT EXISTS postgis;
+CREATE TABLE networks (
+    network_id TEXT PRIMARY KEY,
+    network_name TEXT NOT NULL,
+    network_url TEXT,
+    network_timezone TEXT
+);
+CREATE TABLE geometries (
+    geometry_id TEXT PRIMARY KEY,
+    geom geometry(Geometry, 4326) NOT NULL
+);
+CREATE TABLE lines (
+    line_id TEXT PRIMARY KEY,
+    line_code TEXT,
+    line_name TEXT NOT NULL,
+    network_id TEXT NOT NULL REFERENCES networks(network_id),
+    commercial_mode_id TEXT NOT NULL,
+    geometry_id TEXT REFERENCES geometries(geometry_id)
+);
+CREATE TABLE routes (
+    route_id TEXT PRIMARY KEY,
+    route_name TEXT NOT NULL,
+    line_id TEXT NOT NULL REFERENCES lines(line_id),
+    geometry_id TEXT REFERENCES geometries(geometry_id)
+);
+CREATE TABLE stop_areas (
+    stop_area_id TEXT PRIMARY KEY,
+    stop_area_name TEXT NOT NULL,
+    geom geometry(Point, 4326) NOT NULL
+);
+CREATE TABLE stop_points (
+    stop_point_id TEXT PRIMARY KEY,
+    stop_point_name TEXT NOT NULL,
+    stop_code TEXT,
+    stop_area_id TEXT NOT NULL REFERENCES stop_areas(stop_area_id),
+    geom geometry(Point, 4326) NOT NULL
+);
+CREATE TABLE trips (
+    trip_id TEXT PRIMARY KEY,
+    route_id TEXT NOT NULL REFERENCES routes(route_id),
+    service_id TEXT NOT NULL,
+    trip_headsign TEXT,
+    geometry_id TEXT REFERENCES geometries(geometry_id)
+);
+";
+
+const INDICES: &str = "CREATE INDEX stop_areas_geom ON stop_areas USING GIST (geom);
+CREATE INDEX stop_points_geom ON stop_points USING GIST (geom);
+CREATE INDEX geometries_geom ON geometries USING GIST (geom);
+";
+
+struct EwkbWriter(Vec<u8>);
+
+impl EwkbWriter {
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+    fn xy(&mut self, x: f64, y: f64) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+        self.0.extend_from_slice(&y.to_le_bytes());
+    }
+    fn header(&mut self, geometry_type: u32, with_srid: bool) {
+        // Little endian
+        self.0.push(1);
+        if with_srid {
+            self.u32(geometry_type | EWKB_SRID_FLAG);
+            self.u32(SRID);
+        } else {
+            self.u32(geometry_type);
+        }
+    }
+    fn coordinates(&mut self, line_string: &LineString<f64>) {
+        self.u32(line_string.0.len() as u32);
+        for coordinate in &line_string.0 {
+            self.xy(coordinate.x, coordinate.y);
+        }
+    }
+    fn point(&mut self, point: &Point<f64>, with_srid: bool) {
+        self.header(WKB_POINT, with_srid);
+        self.xy(point.x(), point.y());
+    }
+    fn line_string(&mut self, line_string: &LineString<f64>, with_srid: bool) {
+        self.header(WKB_LINE_STRING, with_srid);
+        self.coordinates(line_string);
+    }
+    fn polygon(&mut self, polygon: &Polygon<f64>, with_srid: bool) {
+        self.header(WKB_POLYGON, with_srid);
+        self.u32(1 + polygon.interiors().len() as u32);
+        self.coordinates(polygon.exterior());
+        for interior in polygon.interiors() {
+            self.coordinates(interior);
+        }
+    }
+    fn geometry(&mut self, geometry: &Geometry<f64>, with_srid: bool) {
+        match geometry {
+            Geometry::Point(point) => self.point(point, with_srid),
+            Geometry::Line(line) => {
+                self.line_string(&LineString(vec![line.start, line.end]), with_srid)
+            }
+            Geometry::LineString(line_string) => self.line_string(line_string, with_srid),
+            Geometry::Polygon(polygon) => self.polygon(polygon, with_srid),
+            Geometry::Rect(rect) => self.polygon(&rect.to_polygon(), with_srid),
+            Geometry::Triangle(triangle) => self.polygon(&triangle.to_polygon(), with_srid),
+            Geometry::MultiPoint(multi_point) => {
+                self.header(WKB_MULTI_POINT, with_srid);
+                self.u32(multi_point.0.len() as u32);
+                for point in &multi_point.0 {
+                    self.point(point, false);
+                }
+            }
+            Geometry::MultiLineString(multi_line_string) => {
+                self.header(WKB_MULTI_LINE_STRING, with_srid);
+                self.u32(multi_line_string.0.len() as u32);
+                for line_string in &multi_line_string.0 {
+                    self.line_string(line_string, false);
+                }
+            }
+            Geometry::MultiPolygon(multi_polygon) => {
+                self.header(WKB_MULTI_POLYGON, with_srid);
+                self.u32(multi_polygon.0.len() as u32);
+                for polygon in &multi_polygon.0 {
+                    self.polygon(polygon, false);
+                }
+            }
+            Geometry::GeometryCollection(collection) => {
+                self.header(WKB_GEOMETRY_COLLECTION, with_srid);
+                self.u32(collection.0.len() as u32);
+                for geometry in &collection.0 {
+                    self.geometry(geometry, false);
+                }
+            }
+        }
+    }
+    fn into_hex(self) -> String {
+        self.0.iter().map(|byte| format!("{:02X}", byte)).collect()
+    }
+}
+
+/// Encodes a geometry as an hexadecimal EWKB with the SRID 4326.
+pub fn to_ewkb_hex(geometry: &Geometry<f64>) -> String {
+    let mut writer = EwkbWriter(Vec::new());
+    writer.geometry(geometry, true);
+    writer.into_hex()
+}
+
+fn coord_to_ewkb_hex(coord: &Coord) -> String {
+    to_ewkb_hex(&Geometry::Point(Point::new(coord.lon, coord.lat)))
+}
+
+// Escapes a value for the text format of `COPY`
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn optional(value: Option<&str>) -> String {
+    value.map(escape).unwrap_or_else(|| "\\N".to_string())
+}
+
+fn copy<W: Write>(
+    writer: &mut W,
+    table: &str,
+    columns: &[&str],
+    rows: impl Iterator<Item = Vec<String>>,
+) -> Result<()> {
+    writeln!(
+        writer,
+        "COPY {} ({}) FROM stdin;",
+        table,
+        columns.join(", ")
+    )?;
+    for row in rows {
+        writeln!(writer, "{}", row.join("\t"))?;
+    }
+    writeln!(writer, "\\.")?;
+    Ok(())
+}
+
+fn write_dump<W: Write>(model: &Model, writer: &mut W) -> Result<()> {
+    writeln!(writer, "BEGIN;")?;
+    write!(writer, "{}", SCHEMA)?;
+    copy(
+        writer,
+        "networks",
+        &[
+            "network_id",
+            "network_name",
+            "network_url",
+            "network_timezone",
+        ],
+        model.networks.values().map(|network| {
+            vec![
+                escape(&network.id),
+                escape(&network.name),
+                optional(network.url.as_deref()),
+                optional(network.timezone.map(|tz| tz.name())),
+            ]
+        }),
+    )?;
+    copy(
+        writer,
+        "geometries",
+        &["geometry_id", "geom"],
+        model
+            .geometries
+            .values()
+            .map(|geometry| vec![escape(&geometry.id), to_ewkb_hex(&geometry.geometry)]),
+    )?;
+    copy(
+        writer,
+        "lines",
+        &[
+            "line_id",
+            "line_code",
+            "line_name",
+            "network_id",
+            "commercial_mode_id",
+            "geometry_id",
+        ],
+        model.lines.values().map(|line| {
+            vec![
+                escape(&line.id),
+                optional(line.code.as_deref()),
+                escape(&line.name),
+                escape(&line.network_id),
+                escape(&line.commercial_mode_id),
+                optional(line.geometry_id.as_deref()),
+            ]
+        }),
+    )?;
+    copy(
+        writer,
+        "routes",
+        &["route_id", "route_name", "line_id", "geometry_id"],
+        model.routes.values().map(|route| {
+            vec![
+                escape(&route.id),
+                escape(&route.name),
+                escape(&route.line_id),
+                optional(route.geometry_id.as_deref()),
+            ]
+        }),
+    )?;
+    copy(
+        writer,
+        "stop_areas",
+        &["stop_area_id", "stop_area_name", "geom"],
+        model.stop_areas.values().map(|stop_area| {
+            vec![
+                escape(&stop_area.id),
+                escape(&stop_area.name),
+                coord_to_ewkb_hex(&stop_area.coord),
+            ]
+        }),
+    )?;
+    copy(
+        writer,
+        "stop_points",
+        &[
+            "stop_point_id",
+            "stop_point_name",
+            "stop_code",
+            "stop_area_id",
+            "geom",
+        ],
+        model.stop_points.values().map(|stop_point| {
+            vec![
+                escape(&stop_point.id),
+                escape(&stop_point.name),
+                optional(stop_point.code.as_deref()),
+                escape(&stop_point.stop_area_id),
+                coord_to_ewkb_hex(&stop_point.coord),
+            ]
+        }),
+    )?;
+    copy(
+        writer,
+        "trips",
+        &[
+            "trip_id",
+            "route_id",
+            "service_id",
+            "trip_headsign",
+            "geometry_id",
+        ],
+        model.vehicle_journeys.values().map(|vj| {
+            vec![
+                escape(&vj.id),
+                escape(&vj.route_id),
+                escape(&vj.service_id),
+                optional(vj.headsign.as_deref()),
+                optional(vj.geometry_id.as_deref()),
+            ]
+        }),
+    )?;
+    write!(writer, "{}", INDICES)?;
+    writeln!(writer, "COMMIT;")?;
+    Ok(())
+}
+
+/// Writes the referential of the `Model` (networks, lines, routes, stops,
+/// trips and geometries) as a PostGIS SQL dump into the file `path`.
+pub fn write<P: AsRef<Path>>(model: &Model, path: P) -> Result<()> {
+    let path = path.as_ref();
+    info!("Writing PostGIS dump {:?}", path);
+    let file = File::create(path).with_context(|| format!("Error creating {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+    write_dump(model, &mut writer).with_context(|| format!("Error writing {:?}", path))?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::one_vehicle_journey;
+    use geo::line_string;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn point_as_ewkb() {
+        let point = Geometry::Point(Point::new(2.0, 48.0));
+        assert_eq!(
+            "0101000020E610000000000000000000400000000000004840",
+            to_ewkb_hex(&point)
+        );
+    }
+
+    #[test]
+    fn line_string_as_ewkb() {
+        let line_string = Geometry::LineString(line_string![(x: 1.0, y: 2.0), (x: 3.0, y: 4.0)]);
+        assert_eq!(
+            "0102000020E610000002000000\
+             000000000000F03F0000000000000040\
+             00000000000008400000000000001040",
+            to_ewkb_hex(&line_string)
+        );
+    }
+
+    #[test]
+    fn escape_copy_values() {
+        assert_eq!("a\\tb\\\\c\\nd", escape("a\tb\\c\nd"));
+        assert_eq!("\\N", optional(None));
+    }
+
+    #[test]
+    fn dump_stops() {
+        let model = one_vehicle_journey();
+        let mut dump = Vec::new();
+        write_dump(&model, &mut dump).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        assert!(dump.starts_with("BEGIN;\nCREATE EXTENSION IF NOT EXISTS postgis;\n"));
+        assert!(dump.contains(
+            "COPY stop_points (stop_point_id, stop_point_name, stop_code, stop_area_id, geom) FROM stdin;\n\
+             A\tA\t\\N\tsa:A\t0101000020E610000000000000000000000000000000000000\n"
+        ));
+        assert!(dump.ends_with("COMMIT;\n"));
+    }
+}
@@ -0,0 +1,436 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Export of a `Model` into a [SQLite](https://www.sqlite.org) database.
+//!
+//! The tables follow the NTFS files (`networks`, `lines`, `routes`, `trips`,
+//! `stop_times`, ...) with foreign keys between them and indices on the
+//! columns used to join them. Dates are exported as ISO 8601 strings
+//! (`YYYY-MM-DD`) and times as a number of seconds since midnight of the
+//! service day (they can be greater than 24 hours).
+
+use crate::{objects::Codes, Model, Result};
+use anyhow::Context;
+use rusqlite::{params, Connection, Transaction};
+use std::path::Path;
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Id};
+use wkt::ToWkt;
+
+const SCHEMA: &str = "
+CREATE TABLE contributors (
+    contributor_id TEXT PRIMARY KEY,
+    contributor_name TEXT NOT NULL,
+    contributor_license TEXT,
+    contributor_website TEXT
+);
+CREATE TABLE datasets (
+    dataset_id TEXT PRIMARY KEY,
+    contributor_id TEXT NOT NULL REFERENCES contributors(contributor_id),
+    dataset_start_date TEXT NOT NULL,
+    dataset_end_date TEXT NOT NULL,
+    dataset_desc TEXT,
+    dataset_system TEXT
+);
+CREATE TABLE networks (
+    network_id TEXT PRIMARY KEY,
+    network_name TEXT NOT NULL,
+    network_url TEXT,
+    network_timezone TEXT,
+    network_lang TEXT,
+    network_phone TEXT
+);
+CREATE TABLE commercial_modes (
+    commercial_mode_id TEXT PRIMARY KEY,
+    commercial_mode_name TEXT NOT NULL
+);
+CREATE TABLE physical_modes (
+    physical_mode_id TEXT PRIMARY KEY,
+    physical_mode_name TEXT NOT NULL,
+    co2_emission REAL
+);
+CREATE TABLE companies (
+    company_id TEXT PRIMARY KEY,
+    company_name TEXT NOT NULL,
+    company_url TEXT,
+    company_phone TEXT
+);
+CREATE TABLE geometries (
+    geometry_id TEXT PRIMARY KEY,
+    geometry_wkt TEXT NOT NULL
+);
+CREATE TABLE lines (
+    line_id TEXT PRIMARY KEY,
+    line_code TEXT,
+    line_name TEXT NOT NULL,
+    forward_line_name TEXT,
+    backward_line_name TEXT,
+    line_color TEXT,
+    line_text_color TEXT,
+    line_sort_order INTEGER,
+    network_id TEXT NOT NULL REFERENCES networks(network_id),
+    commercial_mode_id TEXT NOT NULL REFERENCES commercial_modes(commercial_mode_id),
+    geometry_id TEXT REFERENCES geometries(geometry_id)
+);
+CREATE INDEX lines_network_id ON lines(network_id);
+CREATE TABLE routes (
+    route_id TEXT PRIMARY KEY,
+    route_name TEXT NOT NULL,
+    direction_type TEXT,
+    line_id TEXT NOT NULL REFERENCES lines(line_id),
+    destination_id TEXT,
+    geometry_id TEXT REFERENCES geometries(geometry_id)
+);
+CREATE INDEX routes_line_id ON routes(line_id);
+CREATE TABLE stop_areas (
+    stop_area_id TEXT PRIMARY KEY,
+    stop_area_name TEXT NOT NULL,
+    stop_lat REAL NOT NULL,
+    stop_lon REAL NOT NULL,
+    stop_timezone TEXT,
+    visible INTEGER NOT NULL
+);
+CREATE TABLE stop_points (
+    stop_point_id TEXT PRIMARY KEY,
+    stop_point_name TEXT NOT NULL,
+    stop_code TEXT,
+    stop_lat REAL NOT NULL,
+    stop_lon REAL NOT NULL,
+    stop_area_id TEXT NOT NULL REFERENCES stop_areas(stop_area_id),
+    platform_code TEXT,
+    stop_timezone TEXT,
+    visible INTEGER NOT NULL
+);
+CREATE INDEX stop_points_stop_area_id ON stop_points(stop_area_id);
+CREATE TABLE calendars (
+    service_id TEXT PRIMARY KEY
+);
+CREATE TABLE calendar_dates (
+    service_id TEXT NOT NULL REFERENCES calendars(service_id),
+    date TEXT NOT NULL,
+    PRIMARY KEY (service_id, date)
+);
+CREATE INDEX calendar_dates_date ON calendar_dates(date);
+CREATE TABLE trips (
+    trip_id TEXT PRIMARY KEY,
+    route_id TEXT NOT NULL REFERENCES routes(route_id),
+    service_id TEXT NOT NULL REFERENCES calendars(service_id),
+    physical_mode_id TEXT NOT NULL REFERENCES physical_modes(physical_mode_id),
+    company_id TEXT NOT NULL REFERENCES companies(company_id),
+    dataset_id TEXT NOT NULL REFERENCES datasets(dataset_id),
+    trip_headsign TEXT,
+    trip_short_name TEXT,
+    block_id TEXT,
+    geometry_id TEXT REFERENCES geometries(geometry_id)
+);
+CREATE INDEX trips_route_id ON trips(route_id);
+CREATE INDEX trips_service_id ON trips(service_id);
+CREATE TABLE stop_times (
+    trip_id TEXT NOT NULL REFERENCES trips(trip_id),
+    stop_sequence INTEGER NOT NULL,
+    stop_point_id TEXT NOT NULL REFERENCES stop_points(stop_point_id),
+    arrival_time INTEGER NOT NULL,
+    departure_time INTEGER NOT NULL,
+    boarding_duration INTEGER NOT NULL,
+    alighting_duration INTEGER NOT NULL,
+    pickup_type INTEGER NOT NULL,
+    drop_off_type INTEGER NOT NULL,
+    local_zone_id INTEGER,
+    stop_headsign TEXT,
+    PRIMARY KEY (trip_id, stop_sequence)
+);
+CREATE INDEX stop_times_stop_point_id ON stop_times(stop_point_id);
+CREATE TABLE transfers (
+    from_stop_id TEXT NOT NULL REFERENCES stop_points(stop_point_id),
+    to_stop_id TEXT NOT NULL REFERENCES stop_points(stop_point_id),
+    min_transfer_time INTEGER,
+    real_min_transfer_time INTEGER,
+    PRIMARY KEY (from_stop_id, to_stop_id)
+);
+CREATE TABLE object_codes (
+    object_type TEXT NOT NULL,
+    object_id TEXT NOT NULL,
+    object_system TEXT NOT NULL,
+    object_code TEXT NOT NULL
+);
+CREATE INDEX object_codes_object ON object_codes(object_type, object_id);
+CREATE INDEX object_codes_code ON object_codes(object_system, object_code);
+";
+
+fn insert_referential(tx: &Transaction, model: &Model) -> Result<()> {
+    let mut statement = tx.prepare("INSERT INTO contributors VALUES (?, ?, ?, ?)")?;
+    for contributor in model.contributors.values() {
+        statement.execute(params![
+            contributor.id,
+            contributor.name,
+            contributor.license,
+            contributor.website
+        ])?;
+    }
+    let mut statement = tx.prepare("INSERT INTO datasets VALUES (?, ?, ?, ?, ?, ?)")?;
+    for dataset in model.datasets.values() {
+        statement.execute(params![
+            dataset.id,
+            dataset.contributor_id,
+            dataset.start_date.to_string(),
+            dataset.end_date.to_string(),
+            dataset.desc,
+            dataset.system
+        ])?;
+    }
+    let mut statement = tx.prepare("INSERT INTO networks VALUES (?, ?, ?, ?, ?, ?)")?;
+    for network in model.networks.values() {
+        statement.execute(params![
+            network.id,
+            network.name,
+            network.url,
+            network.timezone.map(|tz| tz.name()),
+            network.lang,
+            network.phone
+        ])?;
+    }
+    let mut statement = tx.prepare("INSERT INTO commercial_modes VALUES (?, ?)")?;
+    for commercial_mode in model.commercial_modes.values() {
+        statement.execute(params![commercial_mode.id, commercial_mode.name])?;
+    }
+    let mut statement = tx.prepare("INSERT INTO physical_modes VALUES (?, ?, ?)")?;
+    for physical_mode in model.physical_modes.values() {
+        statement.execute(params![
+            physical_mode.id,
+            physical_mode.name,
+            physical_mode.co2_emission
+        ])?;
+    }
+    let mut statement = tx.prepare("INSERT INTO companies VALUES (?, ?, ?, ?)")?;
+    for company in model.companies.values() {
+        statement.execute(params![
+            company.id,
+            company.name,
+            company.url,
+            company.phone
+        ])?;
+    }
+    let mut statement = tx.prepare("INSERT INTO geometries VALUES (?, ?)")?;
+    for geometry in model.geometries.values() {
+        let wkt = geometry.geometry.to_wkt();
+        statement.execute(params![geometry.id, wkt.item.to_string()])?;
+    }
+    Ok(())
+}
+
+fn insert_lines_and_routes(tx: &Transaction, model: &Model) -> Result<()> {
+    let mut statement = tx.prepare("INSERT INTO lines VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+    for line in model.lines.values() {
+        statement.execute(params![
+            line.id,
+            line.code,
+            line.name,
+            line.forward_name,
+            line.backward_name,
+            line.color.as_ref().map(ToString::to_string),
+            line.text_color.as_ref().map(ToString::to_string),
+            line.sort_order,
+            line.network_id,
+            line.commercial_mode_id,
+            line.geometry_id
+        ])?;
+    }
+    let mut statement = tx.prepare("INSERT INTO routes VALUES (?, ?, ?, ?, ?, ?)")?;
+    for route in model.routes.values() {
+        statement.execute(params![
+            route.id,
+            route.name,
+            route.direction_type,
+            route.line_id,
+            route.destination_id,
+            route.geometry_id
+        ])?;
+    }
+    Ok(())
+}
+
+fn insert_stops(tx: &Transaction, model: &Model) -> Result<()> {
+    let mut statement = tx.prepare("INSERT INTO stop_areas VALUES (?, ?, ?, ?, ?, ?)")?;
+    for stop_area in model.stop_areas.values() {
+        statement.execute(params![
+            stop_area.id,
+            stop_area.name,
+            stop_area.coord.lat,
+            stop_area.coord.lon,
+            stop_area.timezone.map(|tz| tz.name()),
+            stop_area.visible
+        ])?;
+    }
+    let mut statement = tx.prepare("INSERT INTO stop_points VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+    for stop_point in model.stop_points.values() {
+        statement.execute(params![
+            stop_point.id,
+            stop_point.name,
+            stop_point.code,
+            stop_point.coord.lat,
+            stop_point.coord.lon,
+            stop_point.stop_area_id,
+            stop_point.platform_code,
+            stop_point.timezone.map(|tz| tz.name()),
+            stop_point.visible
+        ])?;
+    }
+    let mut statement = tx.prepare("INSERT OR IGNORE INTO transfers VALUES (?, ?, ?, ?)")?;
+    for transfer in model.transfers.values() {
+        statement.execute(params![
+            transfer.from_stop_id,
+            transfer.to_stop_id,
+            transfer.min_transfer_time,
+            transfer.real_min_transfer_time
+        ])?;
+    }
+    Ok(())
+}
+
+fn insert_schedules(tx: &Transaction, model: &Model) -> Result<()> {
+    let mut calendar_statement = tx.prepare("INSERT INTO calendars VALUES (?)")?;
+    let mut date_statement = tx.prepare("INSERT INTO calendar_dates VALUES (?, ?)")?;
+    for calendar in model.calendars.values() {
+        calendar_statement.execute(params![calendar.id])?;
+        for date in &calendar.dates {
+            date_statement.execute(params![calendar.id, date.to_string()])?;
+        }
+    }
+    let mut trip_statement =
+        tx.prepare("INSERT INTO trips VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+    let mut stop_time_statement =
+        tx.prepare("INSERT INTO stop_times VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+    for vj in model.vehicle_journeys.values() {
+        trip_statement.execute(params![
+            vj.id,
+            vj.route_id,
+            vj.service_id,
+            vj.physical_mode_id,
+            vj.company_id,
+            vj.dataset_id,
+            vj.headsign,
+            vj.short_name,
+            vj.block_id,
+            vj.geometry_id
+        ])?;
+        for stop_time in &vj.stop_times {
+            let headsign = model
+                .stop_time_headsigns
+                .get(&(vj.id.clone(), stop_time.sequence));
+            stop_time_statement.execute(params![
+                vj.id,
+                stop_time.sequence,
+                model.stop_points[stop_time.stop_point_idx].id,
+                stop_time.arrival_time.total_seconds(),
+                stop_time.departure_time.total_seconds(),
+                stop_time.boarding_duration,
+                stop_time.alighting_duration,
+                stop_time.pickup_type,
+                stop_time.drop_off_type,
+                stop_time.local_zone_id,
+                headsign
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+fn insert_codes<T: Id<T> + Codes>(
+    tx: &Transaction,
+    object_type: &str,
+    collection: &CollectionWithId<T>,
+) -> Result<()> {
+    let mut statement = tx.prepare("INSERT INTO object_codes VALUES (?, ?, ?, ?)")?;
+    for object in collection.values() {
+        for (system, code) in object.codes() {
+            statement.execute(params![object_type, object.id(), system, code])?;
+        }
+    }
+    Ok(())
+}
+
+/// Exports the `Model` into a new SQLite database `path` (an existing file is
+/// replaced).
+pub fn write<P: AsRef<Path>>(model: &Model, path: P) -> Result<()> {
+    let path = path.as_ref();
+    info!("Writing SQLite database {:?}", path);
+    if path.exists() {
+        std::fs::remove_file(path).with_context(|| format!("Error removing {:?}", path))?;
+    }
+    let mut connection =
+        Connection::open(path).with_context(|| format!("Error creating {:?}", path))?;
+    connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+    connection.execute_batch(SCHEMA)?;
+
+    let tx = connection.transaction()?;
+    insert_referential(&tx, model)?;
+    insert_lines_and_routes(&tx, model)?;
+    insert_stops(&tx, model)?;
+    insert_schedules(&tx, model)?;
+    insert_codes(&tx, "network", &model.networks)?;
+    insert_codes(&tx, "line", &model.lines)?;
+    insert_codes(&tx, "route", &model.routes)?;
+    insert_codes(&tx, "trip", &model.vehicle_journeys)?;
+    insert_codes(&tx, "stop_area", &model.stop_areas)?;
+    insert_codes(&tx, "stop_point", &model.stop_points)?;
+    insert_codes(&tx, "company", &model.companies)?;
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    #[test]
+    fn write_sqlite_database() {
+        let model = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("B", "10:00:00", "10:01:00")
+                    .st("C", "25:00:00", "25:01:00");
+            })
+            .build_model();
+        test_in_tmp_dir(|path| {
+            let database = path.join("model.sqlite");
+            write(&model, &database).unwrap();
+            let connection = Connection::open(&database).unwrap();
+            let count = |table: &str| -> u32 {
+                connection
+                    .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                        row.get(0)
+                    })
+                    .unwrap()
+            };
+            assert_eq!(2, count("trips"));
+            assert_eq!(4, count("stop_times"));
+            assert_eq!(3, count("stop_points"));
+            let departure: u32 = connection
+                .query_row(
+                    "SELECT departure_time FROM stop_times WHERE stop_point_id = 'C'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(25 * 3600 + 60, departure);
+        });
+    }
+}
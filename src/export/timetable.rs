@@ -0,0 +1,216 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Export of the timetable of each stop point as a CSV file, listing the
+//! departures by line, destination and day type. Useful to print paper
+//! timetables or to quickly check converted data with an operator.
+
+use crate::{
+    csv_format::CsvFormat,
+    objects::{Calendar, Time},
+    Model, Result,
+};
+use anyhow::Context;
+use chrono::{Datelike, Weekday};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
+use tracing::info;
+use typed_index_collection::Idx;
+
+/// Type of the days of operation of a trip
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DayType {
+    /// From Monday to Friday
+    Weekday,
+    /// Saturday
+    Saturday,
+    /// Sunday
+    Sunday,
+}
+
+impl From<Weekday> for DayType {
+    fn from(weekday: Weekday) -> Self {
+        match weekday {
+            Weekday::Sat => DayType::Saturday,
+            Weekday::Sun => DayType::Sunday,
+            _ => DayType::Weekday,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct TimetableRow<'a> {
+    line_name: &'a str,
+    line_code: Option<&'a str>,
+    destination: &'a str,
+    day_type: DayType,
+    departure_time: Time,
+}
+
+pub(crate) fn day_types(calendar: &Calendar) -> BTreeSet<DayType> {
+    calendar
+        .dates
+        .iter()
+        .map(|date| DayType::from(date.weekday()))
+        .collect()
+}
+
+// Name of the file of a stop point, without the characters forbidden in a
+// file name
+pub(super) fn file_name(stop_point_id: &str) -> String {
+    let name: String = stop_point_id
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.csv", name)
+}
+
+/// Exports the timetable of each stop point of the `Model` into the
+/// directory `path`, one `<stop_point_id>.csv` file per stop point having
+/// departures (the characters other than alphanumerics, `-` and `_` of the
+/// identifier are replaced by `_`).
+///
+/// Each row is a departure of a trip on a day type (weekday, Saturday or
+/// Sunday) the trip runs on at least once, with the line of the trip and its
+/// destination (the stop area of its last stop, or the stop itself if the
+/// stop area has no name). The last stops of the trips and the stops not
+/// allowing the boarding are not departures. The files are written in the
+/// `csv_format`.
+pub fn write<P: AsRef<Path>>(model: &Model, path: P, csv_format: &CsvFormat) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path)?;
+    info!("Writing timetables to {:?}", path);
+    let day_types_by_calendar: HashMap<&str, BTreeSet<DayType>> = model
+        .calendars
+        .values()
+        .map(|calendar| (calendar.id.as_str(), day_types(calendar)))
+        .collect();
+    let mut rows_by_stop_point: BTreeMap<Idx<_>, Vec<TimetableRow<'_>>> = BTreeMap::new();
+    for vehicle_journey in model.vehicle_journeys.values() {
+        let day_types = match day_types_by_calendar.get(vehicle_journey.service_id.as_str()) {
+            Some(day_types) => day_types,
+            None => continue,
+        };
+        let last_stop_time = match vehicle_journey.stop_times.last() {
+            Some(last_stop_time) => last_stop_time,
+            None => continue,
+        };
+        let last_stop_point = &model.stop_points[last_stop_time.stop_point_idx];
+        let destination = model
+            .stop_areas
+            .get(&last_stop_point.stop_area_id)
+            .map(|stop_area| stop_area.name.as_str())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| last_stop_point.name.as_str());
+        let line = match model
+            .routes
+            .get(&vehicle_journey.route_id)
+            .and_then(|route| model.lines.get(&route.line_id))
+        {
+            Some(line) => line,
+            None => continue,
+        };
+        let departures = &vehicle_journey.stop_times[..vehicle_journey.stop_times.len() - 1];
+        for stop_time in departures
+            .iter()
+            .filter(|stop_time| stop_time.pickup_type != 1)
+        {
+            let rows = rows_by_stop_point
+                .entry(stop_time.stop_point_idx)
+                .or_default();
+            for day_type in day_types {
+                rows.push(TimetableRow {
+                    line_name: &line.name,
+                    line_code: line.code.as_deref(),
+                    destination,
+                    day_type: *day_type,
+                    departure_time: stop_time.departure_time,
+                });
+            }
+        }
+    }
+
+    for (stop_point_idx, mut rows) in rows_by_stop_point {
+        rows.sort_unstable();
+        let file_path = path.join(file_name(&model.stop_points[stop_point_idx].id));
+        let mut wtr = csv_format
+            .writer_from_path(&file_path)
+            .with_context(|| format!("Error reading {:?}", file_path))?;
+        for row in rows {
+            wtr.serialize(row)
+                .with_context(|| format!("Error reading {:?}", file_path))?;
+        }
+        wtr.flush()
+            .with_context(|| format!("Error reading {:?}", file_path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    #[test]
+    fn write_timetables() {
+        let mut collections = ModelBuilder::default()
+            // a Friday and a Saturday
+            .calendar("c1", &["2020-01-03", "2020-01-04"])
+            .calendar("c2", &["2020-01-05"])
+            .vj("vj1", |vj| {
+                vj.calendar("c1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B:1", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.calendar("c2")
+                    .st("A", "09:00:00", "09:01:00")
+                    .st("C", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections();
+        let mut line = collections.lines.get_mut("default_line").unwrap();
+        line.name = "Line 1".to_string();
+        line.code = Some("1".to_string());
+        drop(line);
+        let model = Model::new(collections).unwrap();
+        test_in_tmp_dir(|path| {
+            write(&model, path, &CsvFormat::default()).unwrap();
+            let timetable = std::fs::read_to_string(path.join("A.csv")).unwrap();
+            assert_eq!(
+                "line_name,line_code,destination,day_type,departure_time\n\
+                 Line 1,1,B:1,weekday,10:01:00\n\
+                 Line 1,1,B:1,saturday,10:01:00\n\
+                 Line 1,1,C,sunday,09:01:00\n",
+                timetable
+            );
+            // last stops of the trips
+            assert!(!path.join("B_1.csv").is_file());
+            assert!(!path.join("C.csv").is_file());
+        });
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Export of the notes of the trips and of the stop times of each line as a
+//! CSV file, for the print timetable systems referencing the notes by a
+//! symbol (e.g. `a` for "Stops only on request").
+
+use crate::{csv_format::CsvFormat, objects::Comment, Model, Result};
+use anyhow::Context;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::Path,
+};
+use tracing::info;
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct NoteRow<'a> {
+    symbol: String,
+    comment_id: &'a str,
+    text: &'a str,
+    url: Option<&'a str>,
+}
+
+// Symbol of rank `rank` in `a`, `b`, ..., `z`, `aa`, `ab`...
+fn generated_symbol(mut rank: usize) -> String {
+    let mut symbol = Vec::new();
+    loop {
+        symbol.push(b'a' + (rank % 26) as u8);
+        if rank < 26 {
+            break;
+        }
+        rank = rank / 26 - 1;
+    }
+    symbol.reverse();
+    String::from_utf8(symbol).expect("ASCII letters are valid UTF-8")
+}
+
+// Symbols of the comments of a line: the label of the comment when it has one
+// not used by another comment of the line, else the first generated symbol
+// not used yet, the comments being taken in the order of their identifiers
+fn symbols<'a>(comments: &[&'a Comment]) -> Vec<(String, &'a Comment)> {
+    let mut used_symbols = HashSet::new();
+    let mut symbols: Vec<(Option<String>, &Comment)> = comments
+        .iter()
+        .map(|comment| {
+            let label = comment
+                .label
+                .as_ref()
+                .map(|label| label.trim().to_string())
+                .filter(|label| !label.is_empty() && used_symbols.insert(label.clone()));
+            (label, *comment)
+        })
+        .collect();
+    let mut rank = 0;
+    for (symbol, _) in symbols.iter_mut().filter(|(symbol, _)| symbol.is_none()) {
+        let mut generated = generated_symbol(rank);
+        while used_symbols.contains(&generated) {
+            rank += 1;
+            generated = generated_symbol(rank);
+        }
+        rank += 1;
+        *symbol = Some(generated);
+    }
+    symbols
+        .into_iter()
+        .map(|(symbol, comment)| (symbol.unwrap_or_default(), comment))
+        .collect()
+}
+
+/// Exports the notes of each line of the `Model` into the directory `path`,
+/// one `<line_id>.csv` file per line having notes (the characters other than
+/// alphanumerics, `-` and `_` of the identifier are replaced by `_`).
+///
+/// The notes of a line are the comments of its trips and of the stop times
+/// of its trips, ordered by identifier. Each note is given a symbol unique in
+/// the line: its `comment_label` when the label is not used by another note
+/// of the line, else the first letters (`a`, `b`... then `aa`, `ab`...) not
+/// used yet. The files are written in the `csv_format`.
+pub fn write<P: AsRef<Path>>(model: &Model, path: P, csv_format: &CsvFormat) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path)?;
+    info!("Writing timetable notes to {:?}", path);
+    let line_id = |vehicle_journey_id: &str| {
+        model
+            .vehicle_journeys
+            .get(vehicle_journey_id)
+            .and_then(|vehicle_journey| model.routes.get(&vehicle_journey.route_id))
+            .map(|route| route.line_id.as_str())
+    };
+    let mut comment_ids_by_line: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for vehicle_journey in model.vehicle_journeys.values() {
+        if let Some(line_id) = line_id(&vehicle_journey.id) {
+            comment_ids_by_line
+                .entry(line_id)
+                .or_default()
+                .extend(vehicle_journey.comment_links.iter().map(String::as_str));
+        }
+    }
+    for ((vehicle_journey_id, _), comment_id) in &model.stop_time_comments {
+        if let Some(line_id) = line_id(vehicle_journey_id) {
+            comment_ids_by_line
+                .entry(line_id)
+                .or_default()
+                .insert(comment_id.as_str());
+        }
+    }
+
+    for (line_id, comment_ids) in comment_ids_by_line {
+        let comments: Vec<&Comment> = comment_ids
+            .into_iter()
+            .filter_map(|comment_id| model.comments.get(comment_id))
+            .collect();
+        if comments.is_empty() {
+            continue;
+        }
+        let file_path = path.join(super::timetable::file_name(line_id));
+        let mut wtr = csv_format
+            .writer_from_path(&file_path)
+            .with_context(|| format!("Error reading {:?}", file_path))?;
+        for (symbol, comment) in symbols(&comments) {
+            wtr.serialize(NoteRow {
+                symbol,
+                comment_id: &comment.id,
+                text: &comment.name,
+                url: comment.url.as_deref(),
+            })
+            .with_context(|| format!("Error reading {:?}", file_path))?;
+        }
+        wtr.flush()
+            .with_context(|| format!("Error reading {:?}", file_path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use crate::{objects::CommentType, test_utils::*};
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+    use typed_index_collection::CollectionWithId;
+
+    fn comment(id: &str, label: Option<&str>, text: &str) -> Comment {
+        Comment {
+            id: id.to_string(),
+            comment_type: CommentType::Information,
+            label: label.map(ToString::to_string),
+            name: text.to_string(),
+            url: None,
+        }
+    }
+
+    #[test]
+    fn generated_symbols() {
+        assert_eq!("a", generated_symbol(0));
+        assert_eq!("z", generated_symbol(25));
+        assert_eq!("aa", generated_symbol(26));
+        assert_eq!("az", generated_symbol(51));
+        assert_eq!("ba", generated_symbol(52));
+    }
+
+    #[test]
+    fn write_timetable_notes() {
+        let mut collections = ModelBuilder::default()
+            .route("r1", |r| {
+                r.line_id = "l1".to_string();
+            })
+            .route("r2", |r| {
+                r.line_id = "l2".to_string();
+            })
+            .vj("vj1", |vj| {
+                vj.route("r1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("r1")
+                    .st("A", "12:00:00", "12:01:00")
+                    .st("B", "13:00:00", "13:01:00");
+            })
+            .vj("vj3", |vj| {
+                vj.route("r2")
+                    .st("C", "10:00:00", "10:01:00")
+                    .st("D", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections();
+        collections.comments = CollectionWithId::new(vec![
+            comment("on_request", None, "Stops only on request"),
+            comment("school", Some("S"), "School days only"),
+            comment("summer", Some("a"), "Runs during the summer holidays"),
+            comment("unused", None, "Never referenced"),
+        ])
+        .unwrap();
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .comment_links
+            .insert("school".to_string());
+        collections
+            .vehicle_journeys
+            .get_mut("vj2")
+            .unwrap()
+            .comment_links
+            .insert("summer".to_string());
+        collections
+            .stop_time_comments
+            .insert(("vj2".to_string(), 1), "on_request".to_string());
+        let model = Model::new(collections).unwrap();
+        test_in_tmp_dir(|path| {
+            write(&model, path, &CsvFormat::default()).unwrap();
+            assert_eq!(
+                "symbol,comment_id,text,url\n\
+                 b,on_request,Stops only on request,\n\
+                 S,school,School days only,\n\
+                 a,summer,Runs during the summer holidays,\n",
+                std::fs::read_to_string(path.join("l1.csv")).unwrap()
+            );
+            // line without note
+            assert!(!path.join("l2.csv").is_file());
+        });
+    }
+}
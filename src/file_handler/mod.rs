@@ -1,10 +1,13 @@
 //! Provides an easy way to access directory or flat zip archive
-use crate::Result;
+use crate::{
+    csv_format::{self, CsvDialect},
+    Result,
+};
 use anyhow::{anyhow, Context};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fs::File,
-    io::{Read, Seek},
+    io::{Cursor, Read, Seek, Write},
     path::{Path, PathBuf},
 };
 
@@ -125,6 +128,182 @@ where
     }
 }
 
+/// MemoryFileHandler is used to read files held in memory, without any access
+/// to the file system (e.g. files received by a service or generated by a
+/// fuzzer)
+pub struct MemoryFileHandler {
+    files: BTreeMap<String, Vec<u8>>,
+    source_name: String,
+}
+
+impl MemoryFileHandler {
+    /// Constructs a new MemoryFileHandler from the content of the files by
+    /// name. The `source_name` is needed to have nicer error messages.
+    pub fn new<S: Into<String>>(files: BTreeMap<String, Vec<u8>>, source_name: S) -> Self {
+        MemoryFileHandler {
+            files,
+            source_name: source_name.into(),
+        }
+    }
+}
+
+impl<'a> FileHandler for &'a mut MemoryFileHandler {
+    type Reader = &'a [u8];
+    fn get_file_if_exists(self, name: &str) -> Result<(Option<Self::Reader>, PathBuf)> {
+        let file_handler: &'a MemoryFileHandler = self;
+        Ok((
+            file_handler.files.get(name).map(Vec::as_slice),
+            Path::new(&file_handler.source_name).join(name),
+        ))
+    }
+    fn source_name(&self) -> &str {
+        &self.source_name
+    }
+}
+
+/// DialectFileHandler is used to read the CSV files of another file handler
+/// written in another dialect (e.g. semicolon separated), converted to the
+/// dialect of the specifications. Only the `.txt` and `.csv` files are
+/// converted.
+pub struct DialectFileHandler<H> {
+    file_handler: H,
+    dialect: CsvDialect,
+    source_name: String,
+}
+
+impl<H> DialectFileHandler<H>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    /// Constructs a new DialectFileHandler reading the files of
+    /// `file_handler` in the `dialect`
+    pub fn new(mut file_handler: H, dialect: CsvDialect) -> Self {
+        let source_name = (&mut file_handler).source_name().to_string();
+        DialectFileHandler {
+            file_handler,
+            dialect,
+            source_name,
+        }
+    }
+}
+
+impl<'a, H> FileHandler for &'a mut DialectFileHandler<H>
+where
+    for<'b> &'b mut H: FileHandler,
+{
+    type Reader = Cursor<Vec<u8>>;
+    fn get_file_if_exists(self, name: &str) -> Result<(Option<Self::Reader>, PathBuf)> {
+        let is_csv = name.ends_with(".txt") || name.ends_with(".csv");
+        let (reader, path) = self.file_handler.get_file_if_exists(name)?;
+        let content = match reader {
+            Some(reader) if is_csv => Some(csv_format::normalize(reader, &self.dialect, &path)?),
+            Some(mut reader) => {
+                let mut content = Vec::new();
+                reader
+                    .read_to_end(&mut content)
+                    .with_context(|| format!("Error reading {:?}", path))?;
+                Some(content)
+            }
+            None => None,
+        };
+        Ok((content.map(Cursor::new), path))
+    }
+    fn source_name(&self) -> &str {
+        &self.source_name
+    }
+}
+
+/// Allows files to be written either in a directory or in a ZipArchive
+pub trait FileWriter
+where
+    Self: std::marker::Sized,
+{
+    /// Writer
+    type Writer: Write;
+
+    /// Create the file `name`, returning its writer and its path (for the
+    /// error messages)
+    fn create_file(self, name: &str) -> Result<(Self::Writer, PathBuf)>;
+
+    /// `true` if the file `name` has already been written
+    fn contains_file(self, name: &str) -> bool;
+}
+
+/// PathFileWriter is used to write files into a directory
+pub struct PathFileWriter<P: AsRef<Path>> {
+    base_path: P,
+}
+
+impl<P: AsRef<Path>> PathFileWriter<P> {
+    /// Constructs a new PathFileWriter
+    pub fn new(path: P) -> Self {
+        PathFileWriter { base_path: path }
+    }
+}
+
+impl<'a, P: AsRef<Path>> FileWriter for &'a mut PathFileWriter<P> {
+    type Writer = File;
+    fn create_file(self, name: &str) -> Result<(Self::Writer, PathBuf)> {
+        let f = self.base_path.as_ref().join(name);
+        let file = File::create(&f).with_context(|| format!("Error reading {:?}", &f))?;
+        Ok((file, f))
+    }
+    fn contains_file(self, name: &str) -> bool {
+        self.base_path.as_ref().join(name).exists()
+    }
+}
+
+/// ZipFileWriter is used to write files into a flat ZipArchive, without any
+/// file system (e.g. with a `std::io::Cursor` on a buffer)
+pub struct ZipFileWriter<W: Write + Seek> {
+    zip: zip::ZipWriter<W>,
+    archive_path: PathBuf,
+    names: BTreeSet<String>,
+}
+
+impl<W> ZipFileWriter<W>
+where
+    W: Write + Seek,
+{
+    /// Constructs a new ZipFileWriter, `path` being only used in the error
+    /// messages
+    pub fn new<P: AsRef<Path>>(writer: W, path: P) -> Self {
+        ZipFileWriter {
+            zip: zip::ZipWriter::new(writer),
+            archive_path: path.as_ref().to_path_buf(),
+            names: BTreeSet::new(),
+        }
+    }
+
+    /// Write the central directory of the archive and return the writer
+    pub fn finish(mut self) -> Result<W> {
+        let archive_path = self.archive_path;
+        self.zip
+            .finish()
+            .with_context(|| format!("Error reading {:?}", archive_path))
+    }
+}
+
+impl<'a, W> FileWriter for &'a mut ZipFileWriter<W>
+where
+    W: Write + Seek,
+{
+    type Writer = &'a mut zip::ZipWriter<W>;
+    fn create_file(self, name: &str) -> Result<(Self::Writer, PathBuf)> {
+        let p = self.archive_path.join(name);
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        self.zip
+            .start_file(name, options)
+            .with_context(|| format!("Error reading {:?}", p))?;
+        self.names.insert(name.to_string());
+        Ok((&mut self.zip, p))
+    }
+    fn contains_file(self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -166,4 +345,66 @@ mod tests {
             assert_eq!("world\n", world_str);
         }
     }
+
+    #[test]
+    fn memory_file_handler() {
+        let files = vec![("hello.txt".to_string(), b"hello\n".to_vec())]
+            .into_iter()
+            .collect();
+        let mut file_handler = MemoryFileHandler::new(files, "memory");
+
+        let (mut hello, path) = file_handler.get_file("hello.txt").unwrap();
+        let mut hello_str = String::new();
+        hello.read_to_string(&mut hello_str).unwrap();
+        assert_eq!("hello\n", hello_str);
+        assert_eq!(Path::new("memory/hello.txt"), path);
+
+        let (world, _) = file_handler.get_file_if_exists("world.txt").unwrap();
+        assert!(world.is_none());
+    }
+
+    #[test]
+    fn dialect_file_handler() {
+        let mut files = BTreeMap::new();
+        files.insert(
+            "stops.txt".to_string(),
+            b"stop_id;stop_lat\nsp1;48,8809\n".to_vec(),
+        );
+        files.insert("stamp.json".to_string(), b"{\"a\";1,5}".to_vec());
+        let dialect = CsvDialect {
+            delimiter: b';',
+            decimal_separator: b',',
+        };
+        let mut file_handler =
+            DialectFileHandler::new(MemoryFileHandler::new(files, "memory"), dialect);
+        assert_eq!("memory", (&mut file_handler).source_name());
+
+        let (mut stops, _) = file_handler.get_file("stops.txt").unwrap();
+        let mut stops_str = String::new();
+        stops.read_to_string(&mut stops_str).unwrap();
+        assert_eq!("stop_id,stop_lat\nsp1,48.8809\n", stops_str);
+
+        let (mut stamp, _) = file_handler.get_file("stamp.json").unwrap();
+        let mut stamp_str = String::new();
+        stamp.read_to_string(&mut stamp_str).unwrap();
+        assert_eq!("{\"a\";1,5}", stamp_str);
+    }
+
+    #[test]
+    fn zip_file_writer() {
+        let mut file_writer = ZipFileWriter::new(std::io::Cursor::new(Vec::new()), "test.zip");
+        {
+            let (mut hello, _) = (&mut file_writer).create_file("hello.txt").unwrap();
+            hello.write_all(b"hello\n").unwrap();
+        }
+        assert!((&mut file_writer).contains_file("hello.txt"));
+        assert!(!(&mut file_writer).contains_file("world.txt"));
+        let archive = file_writer.finish().unwrap();
+
+        let mut file_handler = ZipHandler::new(archive, "test.zip").unwrap();
+        let (mut hello, _) = file_handler.get_file("hello.txt").unwrap();
+        let mut hello_str = String::new();
+        hello.read_to_string(&mut hello_str).unwrap();
+        assert_eq!("hello\n", hello_str);
+    }
 }
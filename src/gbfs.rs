@@ -0,0 +1,437 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Ingestion of the stations of a shared mobility system described by a
+//! [GBFS](https://gbfs.org) feed.
+//!
+//! The stations of `station_information.json` are added to the referential
+//! as points of interest, with the sharing mode of the system in their
+//! `sharing_mode` property. The availabilities of `station_status.json` are
+//! stored in their properties too. GBFS 1.x, 2.x and 3.x feeds are
+//! supported.
+//!
+//! The feeds are read from any `Read`, fetching them is left to the caller:
+//!
+//! ```ignore
+//! let url = "https://some_url/gbfs/en/station_information.json";
+//! let resp = reqwest::blocking::get(url)?;
+//! transit_model::gbfs::add_stations(&mut collections, resp, &Configuration::default())?;
+//! # Ok::<(), transit_model::Error>(())
+//! ```
+
+use crate::{
+    model::{Collections, BIKE_SHARING_SERVICE_PHYSICAL_MODE},
+    objects::{Coord, Poi, PoiType, PropertiesMap},
+    Result,
+};
+use anyhow::Context;
+use derivative::Derivative;
+use serde::Deserialize;
+use std::{fs::File, io::Read, path::Path};
+use tracing::{info, warn};
+
+/// Parameters of the GBFS ingestion
+#[derive(Derivative)]
+#[derivative(Default)]
+pub struct Configuration {
+    /// Identifier of the system, prefixing the identifiers of its stations
+    #[derivative(Default(value = "\"gbfs\".into()"))]
+    pub system_id: String,
+    /// Type of the points of interest created for the stations
+    #[derivative(Default(value = "default_poi_type()"))]
+    pub poi_type: PoiType,
+    /// Sharing mode of the system (e.g. `BikeSharingService`)
+    #[derivative(Default(value = "BIKE_SHARING_SERVICE_PHYSICAL_MODE.into()"))]
+    pub sharing_mode: String,
+    /// Language of the names of the stations, for the GBFS 3 feeds whose
+    /// names are translated. The first translation is used if `None` or not
+    /// found.
+    pub language: Option<String>,
+}
+
+fn default_poi_type() -> PoiType {
+    PoiType {
+        id: "amenity:bicycle_rental".to_string(),
+        name: "Bike sharing station".to_string(),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Feed<T> {
+    data: Stations<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Stations<T> {
+    stations: Vec<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LocalizedString {
+    text: String,
+    language: String,
+}
+
+/// A name, a plain string before GBFS 3 and a list of translations after
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Name {
+    Plain(String),
+    Localized(Vec<LocalizedString>),
+}
+
+impl Name {
+    fn into_string(self, language: Option<&str>) -> String {
+        match self {
+            Name::Plain(name) => name,
+            Name::Localized(mut names) => {
+                let idx = language
+                    .and_then(|language| names.iter().position(|n| n.language == language))
+                    .unwrap_or(0);
+                if idx < names.len() {
+                    names.swap_remove(idx).text
+                } else {
+                    String::new()
+                }
+            }
+        }
+    }
+}
+
+/// A boolean, written as 0 or 1 in GBFS 1.x
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(untagged)]
+enum Flag {
+    Bool(bool),
+    Int(u8),
+}
+
+impl From<Flag> for bool {
+    fn from(flag: Flag) -> bool {
+        match flag {
+            Flag::Bool(value) => value,
+            Flag::Int(value) => value != 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct StationInformation {
+    station_id: String,
+    name: Name,
+    lat: f64,
+    lon: f64,
+    address: Option<String>,
+    capacity: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StationStatus {
+    station_id: String,
+    #[serde(alias = "num_vehicles_available")]
+    num_bikes_available: Option<u32>,
+    num_docks_available: Option<u32>,
+    is_installed: Option<Flag>,
+    is_renting: Option<Flag>,
+    is_returning: Option<Flag>,
+}
+
+fn poi_id(configuration: &Configuration, station_id: &str) -> String {
+    format!("{}:{}", configuration.system_id, station_id)
+}
+
+/// Adds the stations of a GBFS `station_information.json` feed as points of
+/// interest. The stations already in the collections are replaced.
+pub fn add_stations<R: Read>(
+    collections: &mut Collections,
+    station_information: R,
+    configuration: &Configuration,
+) -> Result<()> {
+    let feed: Feed<StationInformation> = serde_json::from_reader(station_information)
+        .context("Error reading the GBFS station_information")?;
+    if !collections
+        .poi_types
+        .contains_id(&configuration.poi_type.id)
+    {
+        collections.poi_types.push(configuration.poi_type.clone())?;
+    }
+    let nb_stations = feed.data.stations.len();
+    for station in feed.data.stations {
+        let mut properties = PropertiesMap::new();
+        properties.insert(
+            "sharing_mode".to_string(),
+            configuration.sharing_mode.clone(),
+        );
+        properties.insert("system_id".to_string(), configuration.system_id.clone());
+        properties.insert("station_id".to_string(), station.station_id.clone());
+        if let Some(capacity) = station.capacity {
+            properties.insert("capacity".to_string(), capacity.to_string());
+        }
+        if let Some(address) = station.address {
+            properties.insert("address".to_string(), address);
+        }
+        let poi = Poi {
+            id: poi_id(configuration, &station.station_id),
+            name: station.name.into_string(configuration.language.as_deref()),
+            coord: Coord {
+                lon: station.lon,
+                lat: station.lat,
+            },
+            poi_type_id: configuration.poi_type.id.clone(),
+            properties,
+            ..Default::default()
+        };
+        if let Some(poi_idx) = collections.pois.get_idx(&poi.id) {
+            *collections.pois.index_mut(poi_idx) = poi;
+        } else {
+            collections.pois.push(poi)?;
+        }
+    }
+    info!(
+        "{} stations of the GBFS system {} added",
+        nb_stations, configuration.system_id
+    );
+    Ok(())
+}
+
+/// Updates the properties of the stations previously added with
+/// `add_stations` from a GBFS `station_status.json` feed. The stations which
+/// are not installed are hidden.
+pub fn update_stations_status<R: Read>(
+    collections: &mut Collections,
+    station_status: R,
+    configuration: &Configuration,
+) -> Result<()> {
+    let feed: Feed<StationStatus> =
+        serde_json::from_reader(station_status).context("Error reading the GBFS station_status")?;
+    for status in feed.data.stations {
+        let mut poi = match collections
+            .pois
+            .get_mut(&poi_id(configuration, &status.station_id))
+        {
+            Some(poi) => poi,
+            None => {
+                warn!(
+                    "status of the unknown GBFS station {} ignored",
+                    status.station_id
+                );
+                continue;
+            }
+        };
+        let availabilities = [
+            ("num_vehicles_available", status.num_bikes_available),
+            ("num_docks_available", status.num_docks_available),
+        ];
+        for (key, value) in availabilities.iter() {
+            if let Some(value) = value {
+                poi.properties.insert(key.to_string(), value.to_string());
+            }
+        }
+        let flags = [
+            ("is_renting", status.is_renting),
+            ("is_returning", status.is_returning),
+        ];
+        for (key, flag) in flags.iter() {
+            if let Some(flag) = flag {
+                poi.properties
+                    .insert(key.to_string(), bool::from(*flag).to_string());
+            }
+        }
+        poi.visible = status.is_installed.map_or(true, bool::from);
+    }
+    Ok(())
+}
+
+/// Adds the stations of the GBFS feeds `station_information.json` and
+/// `station_status.json` (optional) of the `path` directory.
+pub fn from_dir<P: AsRef<Path>>(
+    collections: &mut Collections,
+    path: P,
+    configuration: &Configuration,
+) -> Result<()> {
+    let path = path.as_ref().join("station_information.json");
+    let file = File::open(&path).with_context(|| format!("Error reading {:?}", path))?;
+    add_stations(collections, file, configuration)
+        .with_context(|| format!("Error reading {:?}", path))?;
+    let path = path.with_file_name("station_status.json");
+    if path.exists() {
+        let file = File::open(&path).with_context(|| format!("Error reading {:?}", path))?;
+        update_stations_status(collections, file, configuration)
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{create_file_with_content, test_in_tmp_dir};
+    use pretty_assertions::assert_eq;
+
+    const STATION_INFORMATION_V2: &str = r#"{
+        "last_updated": 1704067200,
+        "ttl": 60,
+        "version": "2.3",
+        "data": {
+            "stations": [
+                {
+                    "station_id": "1",
+                    "name": "Gare de Lyon",
+                    "lat": 48.8443,
+                    "lon": 2.3744,
+                    "capacity": 20,
+                    "address": "Place Louis Armand"
+                },
+                {
+                    "station_id": "2",
+                    "name": "Bastille",
+                    "lat": 48.8532,
+                    "lon": 2.3691
+                }
+            ]
+        }
+    }"#;
+
+    const STATION_STATUS_V1: &str = r#"{
+        "last_updated": 1704067200,
+        "ttl": 60,
+        "data": {
+            "stations": [
+                {
+                    "station_id": "1",
+                    "num_bikes_available": 7,
+                    "num_docks_available": 13,
+                    "is_installed": 1,
+                    "is_renting": 1,
+                    "is_returning": 0,
+                    "last_reported": 1704067100
+                },
+                {
+                    "station_id": "2",
+                    "num_bikes_available": 0,
+                    "is_installed": 0,
+                    "is_renting": 0,
+                    "is_returning": 0,
+                    "last_reported": 1704067100
+                },
+                {
+                    "station_id": "3",
+                    "num_bikes_available": 1,
+                    "is_installed": 1,
+                    "is_renting": 1,
+                    "is_returning": 1,
+                    "last_reported": 1704067100
+                }
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn add_stations_with_status() {
+        test_in_tmp_dir(|path| {
+            create_file_with_content(path, "station_information.json", STATION_INFORMATION_V2);
+            create_file_with_content(path, "station_status.json", STATION_STATUS_V1);
+            let mut collections = Collections::default();
+            let configuration = Configuration {
+                system_id: "velib".to_string(),
+                ..Default::default()
+            };
+            from_dir(&mut collections, path, &configuration).unwrap();
+
+            assert_eq!(
+                vec!["amenity:bicycle_rental"],
+                collections
+                    .poi_types
+                    .values()
+                    .map(|poi_type| poi_type.id.as_str())
+                    .collect::<Vec<_>>()
+            );
+            let poi = collections.pois.get("velib:1").unwrap();
+            assert_eq!("Gare de Lyon", poi.name);
+            assert_eq!(2.3744, poi.coord.lon);
+            assert!(poi.visible);
+            let properties: Vec<_> = poi
+                .properties
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            assert_eq!(
+                vec![
+                    ("address", "Place Louis Armand"),
+                    ("capacity", "20"),
+                    ("is_renting", "true"),
+                    ("is_returning", "false"),
+                    ("num_docks_available", "13"),
+                    ("num_vehicles_available", "7"),
+                    ("sharing_mode", "BikeSharingService"),
+                    ("station_id", "1"),
+                    ("system_id", "velib"),
+                ],
+                properties
+            );
+            assert!(!collections.pois.get("velib:2").unwrap().visible);
+            assert!(collections.pois.get("velib:3").is_none());
+        });
+    }
+
+    #[test]
+    fn add_stations_with_localized_names() {
+        let station_information = r#"{
+            "last_updated": "2024-01-01T00:00:00Z",
+            "ttl": 60,
+            "version": "3.0",
+            "data": {
+                "stations": [
+                    {
+                        "station_id": "42",
+                        "name": [
+                            {"text": "Central Station", "language": "en"},
+                            {"text": "Hauptbahnhof", "language": "de"}
+                        ],
+                        "lat": 52.5251,
+                        "lon": 13.3694
+                    }
+                ]
+            }
+        }"#;
+        let mut collections = Collections::default();
+        collections
+            .pois
+            .push(Poi {
+                id: "gbfs:42".to_string(),
+                name: "Old name".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        let configuration = Configuration {
+            language: Some("de".to_string()),
+            sharing_mode: "ScooterSharingService".to_string(),
+            ..Default::default()
+        };
+        add_stations(
+            &mut collections,
+            station_information.as_bytes(),
+            &configuration,
+        )
+        .unwrap();
+
+        assert_eq!(1, collections.pois.len());
+        let poi = collections.pois.get("gbfs:42").unwrap();
+        assert_eq!("Hauptbahnhof", poi.name);
+        assert_eq!(
+            Some(&"ScooterSharingService".to_string()),
+            poi.properties.get("sharing_mode")
+        );
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use super::{
+    default_values_report, write_to_zip_with_configuration, write_to_zip_writer,
+    write_with_configuration, EmptyFileStrategy, FrequencyDetection, GtfsFile, GtfsFileWriter,
+    RouteNameStrategy, RouteTypeOverrides, WriteConfiguration, WriterReportCategory,
+};
+use crate::{csv_format::CsvFormat, model::Model, report::Report, Result};
+use std::{path::Path, sync::Arc};
+
+/// Builder of a [GTFS](https://gtfs.org/reference/static) export.
+///
+/// ```
+/// # use transit_model::gtfs::GtfsExporter;
+/// # fn main() -> transit_model::Result<()> {
+/// let model = transit_model::ntfs::read("tests/fixtures/minimal_ntfs")?;
+/// # let output_dir = tempfile::tempdir()?;
+/// # let path = output_dir.path();
+/// GtfsExporter::new()
+///     .with_extended_route_types(true)
+///     .with_shapes(false)
+///     .export(model, path)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct GtfsExporter {
+    configuration: WriteConfiguration,
+}
+
+impl From<WriteConfiguration> for GtfsExporter {
+    fn from(configuration: WriteConfiguration) -> Self {
+        GtfsExporter { configuration }
+    }
+}
+
+impl GtfsExporter {
+    /// Create a new `GtfsExporter` with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the extended route types instead of the basic ones.
+    pub fn with_extended_route_types(mut self, extend_route_type: bool) -> Self {
+        self.configuration.extend_route_type = extend_route_type;
+        self
+    }
+
+    /// Setup the behavior for the optional files without any record.
+    pub fn with_empty_file_strategy(mut self, empty_file_strategy: EmptyFileStrategy) -> Self {
+        self.configuration.empty_file_strategy = empty_file_strategy;
+        self
+    }
+
+    /// Setup the route types of the routes of some lines.
+    pub fn with_route_type_overrides(mut self, route_type_overrides: RouteTypeOverrides) -> Self {
+        self.configuration.route_type_overrides = route_type_overrides;
+        self
+    }
+
+    /// Leave empty the redundant times at the first and last stops of the
+    /// trips.
+    pub fn with_blank_redundant_times(mut self, blank_redundant_times: bool) -> Self {
+        self.configuration.blank_redundant_times = blank_redundant_times;
+        self
+    }
+
+    /// Setup the mapping of the lines to the names of the routes.
+    pub fn with_route_name_strategy(mut self, route_name_strategy: RouteNameStrategy) -> Self {
+        self.configuration.route_name_strategy = route_name_strategy;
+        self
+    }
+
+    /// Write the comments in the `comments.txt` and `comment_links.txt`
+    /// extension files.
+    pub fn with_comments(mut self, write_comments: bool) -> Self {
+        self.configuration.write_comments = write_comments;
+        self
+    }
+
+    /// Write the operating hours of the lines in the
+    /// `line_operating_hours.txt` extension file.
+    pub fn with_line_operating_hours(mut self, write_line_operating_hours: bool) -> Self {
+        self.configuration.write_line_operating_hours = write_line_operating_hours;
+        self
+    }
+
+    /// Replace the trips running at a regular interval by frequencies.
+    pub fn with_frequency_detection(mut self, frequency_detection: FrequencyDetection) -> Self {
+        self.configuration.frequency_detection = Some(frequency_detection);
+        self
+    }
+
+    /// Write the `file` or not. All the files are written by default.
+    pub fn with_file(mut self, file: GtfsFile, written: bool) -> Self {
+        let mut files = self
+            .configuration
+            .files
+            .take()
+            .unwrap_or_else(|| GtfsFile::ALL.iter().copied().collect());
+        if written {
+            files.insert(file);
+        } else {
+            files.remove(&file);
+        }
+        self.configuration.files = Some(files);
+        self
+    }
+
+    /// Write only the `files`.
+    pub fn with_only_files<I: IntoIterator<Item = GtfsFile>>(mut self, files: I) -> Self {
+        self.configuration.files = Some(files.into_iter().collect());
+        self
+    }
+
+    /// Write the `shapes.txt` file or not.
+    pub fn with_shapes(self, written: bool) -> Self {
+        self.with_file(GtfsFile::Shapes, written)
+    }
+
+    /// Write the `stop_extensions.txt` file or not.
+    pub fn with_stop_extensions(self, written: bool) -> Self {
+        self.with_file(GtfsFile::StopExtensions, written)
+    }
+
+    /// Setup the quoting, the line terminator and the precision of the
+    /// coordinates of the CSV files.
+    pub fn with_csv_format(mut self, csv_format: CsvFormat) -> Self {
+        self.configuration.csv_format = csv_format;
+        self
+    }
+
+    /// Add a writer of an additional file.
+    pub fn with_extension_writer(mut self, extension_writer: Arc<dyn GtfsFileWriter>) -> Self {
+        self.configuration.extension_writers.push(extension_writer);
+        self
+    }
+
+    /// Configuration of the export.
+    pub fn configuration(&self) -> &WriteConfiguration {
+        &self.configuration
+    }
+
+    /// List the default values which would be written in place of the
+    /// missing fields of `model` (see [default_values_report]).
+    pub fn default_values_report(&self, model: &Model) -> Report<WriterReportCategory> {
+        default_values_report(model)
+    }
+
+    /// Exports the `model` to GTFS files in the directory `path`.
+    pub fn export<P: AsRef<Path>>(self, model: Model, path: P) -> Result<()> {
+        write_with_configuration(model, path, self.configuration)
+    }
+
+    /// Exports the `model` to a GTFS ZIP archive at `path`.
+    pub fn export_to_zip<P: AsRef<Path>>(self, model: Model, path: P) -> Result<()> {
+        write_to_zip_with_configuration(model, path, self.configuration)
+    }
+
+    /// Exports the `model` to a GTFS ZIP archive written into `writer`. The
+    /// `source_name` is needed to have nicer error messages.
+    pub fn export_to_zip_writer<W>(self, model: Model, writer: W, source_name: &str) -> Result<W>
+    where
+        W: std::io::Write + std::io::Seek,
+    {
+        write_to_zip_writer(model, writer, source_name, self.configuration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::one_vehicle_journey;
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn toggle_files() {
+        let exporter = GtfsExporter::new();
+        assert_eq!(None, exporter.configuration().files);
+
+        let exporter = exporter.with_shapes(false).with_stop_extensions(false);
+        let files = exporter.configuration().files.as_ref().unwrap();
+        assert_eq!(GtfsFile::ALL.len() - 2, files.len());
+        assert!(!files.contains(&GtfsFile::Shapes));
+        assert!(!files.contains(&GtfsFile::StopExtensions));
+
+        let exporter = exporter
+            .with_only_files(vec![GtfsFile::Stops])
+            .with_shapes(true);
+        assert_eq!(
+            Some(
+                vec![GtfsFile::Stops, GtfsFile::Shapes]
+                    .into_iter()
+                    .collect::<BTreeSet<_>>()
+            ),
+            exporter.configuration().files
+        );
+    }
+
+    #[test]
+    fn export_without_shapes() {
+        let model = one_vehicle_journey();
+        let output_dir = tempfile::tempdir().unwrap();
+        GtfsExporter::new()
+            .with_extended_route_types(true)
+            .with_only_files(vec![GtfsFile::Stops, GtfsFile::StopTimes])
+            .export(model, output_dir.path())
+            .unwrap();
+        let mut written: Vec<_> = std::fs::read_dir(output_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        written.sort();
+        assert_eq!(vec!["stop_times.txt", "stops.txt"], written);
+    }
+}
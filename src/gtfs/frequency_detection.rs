@@ -0,0 +1,340 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Detection of the trips running at a regular interval, the reverse of the
+//! expansion of `frequencies.txt` done when reading a GTFS.
+
+use super::FrequencyDetection;
+use crate::{
+    model::Model,
+    objects::{CommentLinksT, Frequency, StopPoint, StopTimePrecision, Time, VehicleJourney},
+    Result,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::info;
+use typed_index_collection::{Collection, Idx};
+
+// A stop time relative to the arrival at the first stop of its trip
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct RelativeStopTime<'a> {
+    stop_point_idx: Idx<StopPoint>,
+    sequence: u32,
+    arrival: i64,
+    departure: i64,
+    boarding_duration: u16,
+    alighting_duration: u16,
+    pickup_type: u8,
+    drop_off_type: u8,
+    local_zone_id: Option<u16>,
+    exact: bool,
+    headsign: Option<&'a str>,
+    comment_id: Option<&'a str>,
+}
+
+// The trips with the same pattern differ only by the time of their first
+// arrival, and can be written as the frequencies of one of them
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Pattern<'a> {
+    route_id: &'a str,
+    physical_mode_id: &'a str,
+    dataset_id: &'a str,
+    service_id: &'a str,
+    company_id: &'a str,
+    headsign: Option<&'a str>,
+    short_name: Option<&'a str>,
+    block_id: Option<&'a str>,
+    trip_property_id: Option<&'a str>,
+    geometry_id: Option<&'a str>,
+    comment_links: &'a CommentLinksT,
+    stop_times: Vec<RelativeStopTime<'a>>,
+}
+
+fn pattern<'a>(vj: &'a VehicleJourney, first_arrival: u32, model: &'a Model) -> Pattern<'a> {
+    let stop_time_attribute = |attributes: &'a HashMap<(String, u32), String>, sequence| {
+        attributes
+            .get(&(vj.id.clone(), sequence))
+            .map(String::as_str)
+    };
+    Pattern {
+        route_id: &vj.route_id,
+        physical_mode_id: &vj.physical_mode_id,
+        dataset_id: &vj.dataset_id,
+        service_id: &vj.service_id,
+        company_id: &vj.company_id,
+        headsign: vj.headsign.as_deref(),
+        short_name: vj.short_name.as_deref(),
+        block_id: vj.block_id.as_deref(),
+        trip_property_id: vj.trip_property_id.as_deref(),
+        geometry_id: vj.geometry_id.as_deref(),
+        comment_links: &vj.comment_links,
+        stop_times: vj
+            .stop_times
+            .iter()
+            .map(|st| RelativeStopTime {
+                stop_point_idx: st.stop_point_idx,
+                sequence: st.sequence,
+                arrival: i64::from(st.arrival_time.total_seconds()) - i64::from(first_arrival),
+                departure: i64::from(st.departure_time.total_seconds()) - i64::from(first_arrival),
+                boarding_duration: st.boarding_duration,
+                alighting_duration: st.alighting_duration,
+                pickup_type: st.pickup_type,
+                drop_off_type: st.drop_off_type,
+                local_zone_id: st.local_zone_id,
+                exact: matches!(st.precision, None | Some(StopTimePrecision::Exact)),
+                headsign: stop_time_attribute(&model.stop_time_headsigns, st.sequence),
+                comment_id: stop_time_attribute(&model.stop_time_comments, st.sequence),
+            })
+            .collect(),
+    }
+}
+
+// `true` if the trips arriving first at `first_arrivals` run every
+// `headway` seconds, up to the jitter
+fn is_regular(first_arrivals: &[u32], headway: u32, max_jitter: u32) -> bool {
+    headway > 0
+        && (0..).zip(first_arrivals).all(|(position, first_arrival)| {
+            let expected = first_arrivals[0] + position * headway;
+            first_arrival.max(&expected) - first_arrival.min(&expected) <= max_jitter
+        })
+}
+
+// Split the sorted first arrivals into runs at a regular interval, as
+// (position of the first trip, number of trips, headway)
+fn regular_runs(
+    first_arrivals: &[u32],
+    detection: &FrequencyDetection,
+) -> Vec<(usize, usize, u32)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    while start + 1 < first_arrivals.len() {
+        let mut run = None;
+        for end in start + 1..first_arrivals.len() {
+            let intervals = (end - start) as u32;
+            // the rounded mean interval
+            let headway = (first_arrivals[end] - first_arrivals[start] + intervals / 2) / intervals;
+            if !is_regular(&first_arrivals[start..=end], headway, detection.max_jitter) {
+                break;
+            }
+            run = Some((start, end - start + 1, headway));
+        }
+        match run {
+            Some((_, count, _)) if count >= detection.min_trips.max(2) => {
+                runs.extend(run);
+                start += count;
+            }
+            _ => start += 1,
+        }
+    }
+    runs
+}
+
+/// Replace the trips running at a regular interval by the frequencies of the
+/// first of them, returning the model without the replaced trips and the
+/// frequencies.
+///
+/// The trips of a run have the same attributes and the same stop times,
+/// shifted by a regular headway up to `max_jitter` seconds. The trips already
+/// having frequencies, or referenced by an interchange or an occupancy, are
+/// kept as they are, as are the trips whose stop times have an identifier,
+/// which would be lost with the replaced trips.
+pub fn detect_frequencies(
+    model: Model,
+    detection: &FrequencyDetection,
+) -> Result<(Model, Collection<Frequency>)> {
+    let excluded_vj_ids: HashSet<&str> = model
+        .frequencies
+        .values()
+        .map(|frequency| frequency.vehicle_journey_id.as_str())
+        .chain(model.interchanges.values().flat_map(|interchange| {
+            vec![
+                interchange.from_vehicle_journey_id.as_str(),
+                interchange.to_vehicle_journey_id.as_str(),
+            ]
+        }))
+        .chain(
+            model
+                .stop_time_occupancies
+                .values()
+                .map(|occupancy| occupancy.vehicle_journey_id.as_str()),
+        )
+        .chain(model.stop_time_ids.keys().map(|(vj_id, _)| vj_id.as_str()))
+        .collect();
+
+    let mut trips_by_pattern: BTreeMap<Pattern, Vec<(u32, Idx<VehicleJourney>)>> = BTreeMap::new();
+    for (vj_idx, vj) in model.vehicle_journeys.iter() {
+        if excluded_vj_ids.contains(vj.id.as_str()) {
+            continue;
+        }
+        let first_arrival = match vj.stop_times.first() {
+            Some(st) => st.arrival_time.total_seconds(),
+            None => continue,
+        };
+        trips_by_pattern
+            .entry(pattern(vj, first_arrival, &model))
+            .or_default()
+            .push((first_arrival, vj_idx));
+    }
+
+    let mut frequencies = Vec::new();
+    let mut replaced_vj_ids = HashSet::new();
+    for trips in trips_by_pattern.values_mut() {
+        trips.sort_unstable();
+        let first_arrivals: Vec<u32> = trips.iter().map(|(arrival, _)| *arrival).collect();
+        for (start, count, headway) in regular_runs(&first_arrivals, detection) {
+            let vj_id = &model.vehicle_journeys[trips[start].1].id;
+            frequencies.push(Frequency {
+                vehicle_journey_id: vj_id.clone(),
+                start_time: Time::new(0, 0, first_arrivals[start]),
+                end_time: Time::new(0, 0, first_arrivals[start] + count as u32 * headway),
+                headway_secs: headway,
+            });
+            replaced_vj_ids.extend(
+                trips[start + 1..start + count]
+                    .iter()
+                    .map(|(_, vj_idx)| model.vehicle_journeys[*vj_idx].id.clone()),
+            );
+        }
+    }
+    info!(
+        "{} trips replaced by {} frequencies",
+        replaced_vj_ids.len(),
+        frequencies.len()
+    );
+    if frequencies.is_empty() {
+        return Ok((model, Collection::default()));
+    }
+
+    let mut collections = model.into_collections();
+    collections
+        .vehicle_journeys
+        .retain(|vj| !replaced_vj_ids.contains(&vj.id));
+    Ok((Model::new(collections)?, Collection::new(frequencies)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn metro(departures: &[&str]) -> Model {
+        let mut builder = ModelBuilder::default();
+        for (number, departure) in departures.iter().enumerate() {
+            // the builder takes the `Time` of its own instance of the crate
+            let departure: transit_model_builder::Time = departure.parse().unwrap();
+            let next = departure + transit_model_builder::Time::new(0, 2, 0);
+            builder = builder.vj(&format!("vj{}", number), |vj| {
+                vj.st("A", departure, departure).st("B", next, next);
+            });
+        }
+        builder.build_model()
+    }
+
+    #[test]
+    fn replace_regular_trips_by_frequencies() {
+        let model = metro(&[
+            "08:00:00", "08:05:00", "08:10:00", "08:15:00", "09:00:00", "09:10:00",
+        ]);
+        let detection = FrequencyDetection::default();
+        let (model, frequencies) = detect_frequencies(model, &detection).unwrap();
+
+        assert_eq!(
+            vec![Frequency {
+                vehicle_journey_id: "vj0".to_string(),
+                start_time: Time::new(8, 0, 0),
+                end_time: Time::new(8, 20, 0),
+                headway_secs: 300,
+            }],
+            frequencies.into_vec()
+        );
+        let mut vj_ids: Vec<&str> = model
+            .vehicle_journeys
+            .values()
+            .map(|vj| vj.id.as_str())
+            .collect();
+        vj_ids.sort_unstable();
+        assert_eq!(vec!["vj0", "vj4", "vj5"], vj_ids);
+    }
+
+    // the regular metro of `replace_regular_trips_by_frequencies`, with the
+    // stop time collections updated by `update`
+    fn metro_with(update: impl FnOnce(&mut crate::model::Collections)) -> Model {
+        let model = metro(&["08:00:00", "08:05:00", "08:10:00", "08:15:00"]);
+        let mut collections = model.into_collections();
+        collections
+            .comments
+            .push(crate::objects::Comment {
+                id: "comment".to_string(),
+                name: "Stops on request".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        update(&mut collections);
+        Model::new(collections).unwrap()
+    }
+
+    #[test]
+    fn same_stop_time_comments() {
+        let model = metro_with(|collections| {
+            for vj_id in &["vj0", "vj1", "vj2", "vj3"] {
+                collections
+                    .stop_time_comments
+                    .insert((vj_id.to_string(), 1), "comment".to_string());
+            }
+        });
+        let (model, frequencies) =
+            detect_frequencies(model, &FrequencyDetection::default()).unwrap();
+        assert_eq!(1, frequencies.len());
+        assert_eq!(1, model.vehicle_journeys.len());
+        assert_eq!(1, model.stop_time_comments.len());
+    }
+
+    #[test]
+    fn keep_trips_with_other_stop_time_comments_or_ids() {
+        let model = metro_with(|collections| {
+            collections
+                .stop_time_comments
+                .insert(("vj1".to_string(), 1), "comment".to_string());
+            collections
+                .stop_time_ids
+                .insert(("vj3".to_string(), 0), "vj3:0".to_string());
+        });
+        let (model, frequencies) =
+            detect_frequencies(model, &FrequencyDetection::default()).unwrap();
+        // vj0 and vj2 alone are fewer than the `min_trips` of a frequency
+        assert!(frequencies.is_empty());
+        assert_eq!(4, model.vehicle_journeys.len());
+        assert_eq!(1, model.stop_time_comments.len());
+        assert_eq!(1, model.stop_time_ids.len());
+    }
+
+    #[test]
+    fn tolerate_jitter() {
+        let departures = ["08:00:00", "08:05:30", "08:10:00", "08:15:30"];
+        let (model, frequencies) =
+            detect_frequencies(metro(&departures), &FrequencyDetection::default()).unwrap();
+        assert!(frequencies.is_empty());
+        assert_eq!(4, model.vehicle_journeys.len());
+
+        let detection = FrequencyDetection {
+            max_jitter: 30,
+            ..Default::default()
+        };
+        let (model, frequencies) = detect_frequencies(metro(&departures), &detection).unwrap();
+        assert_eq!(1, frequencies.len());
+        assert_eq!(310, frequencies.values().next().unwrap().headway_secs);
+        assert_eq!(1, model.vehicle_journeys.len());
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+//! Lenient reading of the GTFS, normalizing the dates and the times written
+//! in the exotic formats of some exports before their parsing: times without
+//! seconds (`24:00`) and dates with dashes (`2020-01-31`).
+
+use crate::{file_handler::FileHandler, Result};
+use anyhow::Context;
+use std::{
+    borrow::Cow,
+    io::{self, BufRead, BufReader, Chain, Cursor, Read},
+    path::PathBuf,
+};
+use tracing::warn;
+
+// Columns of the GTFS files holding a time
+const TIME_COLUMNS: &[&str] = &["arrival_time", "departure_time", "start_time", "end_time"];
+
+// Columns of the GTFS files holding a date
+const DATE_COLUMNS: &[&str] = &[
+    "date",
+    "start_date",
+    "end_date",
+    "feed_start_date",
+    "feed_end_date",
+];
+
+type Normalizer = for<'a> fn(&'a str) -> Cow<'a, str>;
+
+fn is_number(value: &str, min_len: usize, max_len: usize) -> bool {
+    (min_len..=max_len).contains(&value.len()) && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Normalize a time written `H:MM` or `HH:MM` into `HH:MM:SS` (the times
+/// without leading zero like `7:05:00` are already accepted by `Time`)
+fn normalize_time(time: &str) -> Cow<'_, str> {
+    let parts: Vec<&str> = time.split(':').collect();
+    match parts.as_slice() {
+        [hours, minutes] if is_number(hours, 1, 3) && is_number(minutes, 2, 2) => {
+            Cow::Owned(format!("{:0>2}:{}:00", hours, minutes))
+        }
+        _ => Cow::Borrowed(time),
+    }
+}
+
+/// Normalize a date written `YYYY-MM-DD` into `YYYYMMDD`
+fn normalize_date(date: &str) -> Cow<'_, str> {
+    let parts: Vec<&str> = date.split('-').collect();
+    match parts.as_slice() {
+        [year, month, day]
+            if is_number(year, 4, 4) && is_number(month, 2, 2) && is_number(day, 2, 2) =>
+        {
+            Cow::Owned(format!("{}{}{}", year, month, day))
+        }
+        _ => Cow::Borrowed(date),
+    }
+}
+
+// The normalizer of each column of the `headers`, if any
+fn normalizers(headers: &csv::StringRecord) -> Vec<Option<Normalizer>> {
+    headers
+        .iter()
+        .map(|header| {
+            if TIME_COLUMNS.contains(&header) {
+                Some(normalize_time as Normalizer)
+            } else if DATE_COLUMNS.contains(&header) {
+                Some(normalize_date as Normalizer)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// Normalize the dates and the times of the CSV `reader`, returning the
+// normalized CSV and the number of values modified
+fn normalize_csv<R: Read>(reader: R) -> csv::Result<(Vec<u8>, usize)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let normalizers = normalizers(&headers);
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    writer.write_record(&headers)?;
+    let mut normalized_count = 0;
+    let mut normalized = csv::StringRecord::new();
+    for record in reader.records() {
+        let record = record?;
+        normalized.clear();
+        for (position, value) in record.iter().enumerate() {
+            let normalizer = normalizers.get(position).copied().flatten();
+            match normalizer.map(|normalize| normalize(value)) {
+                Some(Cow::Owned(value)) => {
+                    normalized_count += 1;
+                    normalized.push_field(&value);
+                }
+                _ => normalized.push_field(value),
+            }
+        }
+        writer.write_record(&normalized)?;
+    }
+    let buffer = writer
+        .into_inner()
+        .map_err(|error| csv::Error::from(error.into_error()))?;
+    Ok((buffer, normalized_count))
+}
+
+/// Reader of a file of a `LenientFileHandler`
+pub(crate) enum LenientReader<R> {
+    /// A file without any date or time column, read as is (its header line
+    /// being read first)
+    Unchanged(Chain<Cursor<Vec<u8>>, BufReader<R>>),
+    /// A file normalized in memory
+    Normalized(Cursor<Vec<u8>>),
+}
+
+impl<R: Read> Read for LenientReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            LenientReader::Unchanged(reader) => reader.read(buf),
+            LenientReader::Normalized(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// A `FileHandler` normalizing the dates and the times of the files of the
+/// wrapped `FileHandler`.
+///
+/// The files with a date or a time column are read in memory to be
+/// normalized, the other ones are read as is.
+pub(crate) struct LenientFileHandler<'h, H> {
+    file_handler: &'h mut H,
+}
+
+impl<'h, H> LenientFileHandler<'h, H> {
+    pub(crate) fn new(file_handler: &'h mut H) -> Self {
+        LenientFileHandler { file_handler }
+    }
+}
+
+impl<'a, 'h, H> FileHandler for &'a mut LenientFileHandler<'h, H>
+where
+    &'a mut H: FileHandler,
+{
+    type Reader = LenientReader<<&'a mut H as FileHandler>::Reader>;
+
+    fn get_file_if_exists(self, name: &str) -> Result<(Option<Self::Reader>, PathBuf)> {
+        let (reader, path) = (&mut *self.file_handler).get_file_if_exists(name)?;
+        let mut reader = match reader {
+            Some(reader) => BufReader::new(reader),
+            None => return Ok((None, path)),
+        };
+        let mut header = Vec::new();
+        reader
+            .read_until(b'\n', &mut header)
+            .with_context(|| format!("Error reading {:?}", path))?;
+        let headers = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(header.as_slice())
+            .headers()
+            .with_context(|| format!("Error reading {:?}", path))?
+            .clone();
+        let reader = Cursor::new(header).chain(reader);
+        if normalizers(&headers).iter().all(Option::is_none) {
+            return Ok((Some(LenientReader::Unchanged(reader)), path));
+        }
+        let (buffer, normalized_count) =
+            normalize_csv(reader).with_context(|| format!("Error reading {:?}", path))?;
+        if normalized_count > 0 {
+            warn!(
+                "{}: {} date(s) and time(s) in an exotic format normalized",
+                name, normalized_count
+            );
+        }
+        Ok((Some(LenientReader::Normalized(Cursor::new(buffer))), path))
+    }
+
+    fn source_name(&self) -> &str {
+        FileHandler::source_name(&self.file_handler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_handler::MemoryFileHandler;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn exotic_times() {
+        assert_eq!("7:05:00", normalize_time("7:05:00"));
+        assert_eq!("24:00:00", normalize_time("24:00"));
+        assert_eq!("07:05:00", normalize_time("7:05"));
+        assert_eq!("107:05:00", normalize_time("107:05"));
+        assert_eq!("10:05:30", normalize_time("10:05:30"));
+        assert_eq!("", normalize_time(""));
+        assert_eq!("7h05", normalize_time("7h05"));
+    }
+
+    #[test]
+    fn exotic_dates() {
+        assert_eq!("20200131", normalize_date("2020-01-31"));
+        assert_eq!("20200131", normalize_date("20200131"));
+        assert_eq!("2020-1-31", normalize_date("2020-1-31"));
+    }
+
+    #[test]
+    fn normalize_stop_times() {
+        let stop_times = "trip_id,arrival_time,departure_time,stop_id\n\
+                          t1,7:05:00,7:06,S1\n\
+                          t1,24:00,24:00:00,S2\n";
+        let (buffer, normalized_count) = normalize_csv(stop_times.as_bytes()).unwrap();
+        assert_eq!(
+            "trip_id,arrival_time,departure_time,stop_id\n\
+             t1,7:05:00,07:06:00,S1\n\
+             t1,24:00:00,24:00:00,S2\n",
+            String::from_utf8(buffer).unwrap()
+        );
+        assert_eq!(2, normalized_count);
+    }
+
+    #[test]
+    fn file_without_date_nor_time_unchanged() {
+        let stops = "stop_id, stop_name\n S1 ,2020-01-31\n";
+        let calendar_dates = "service_id,date,exception_type\nc1,2020-01-31,1\n";
+        let mut memory_file_handler = MemoryFileHandler::new(
+            vec![
+                ("stops.txt".to_string(), stops.as_bytes().to_vec()),
+                (
+                    "calendar_dates.txt".to_string(),
+                    calendar_dates.as_bytes().to_vec(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            "gtfs",
+        );
+        let mut file_handler = LenientFileHandler::new(&mut memory_file_handler);
+        fn read(
+            file_handler: &mut LenientFileHandler<'_, MemoryFileHandler>,
+            name: &str,
+        ) -> (bool, String) {
+            let (mut reader, _) = file_handler.get_file(name).unwrap();
+            let is_unchanged = matches!(reader, LenientReader::Unchanged(_));
+            let mut content = String::new();
+            reader.read_to_string(&mut content).unwrap();
+            (is_unchanged, content)
+        }
+
+        assert_eq!(
+            (true, stops.to_string()),
+            read(&mut file_handler, "stops.txt")
+        );
+        assert_eq!(
+            (
+                false,
+                "service_id,date,exception_type\nc1,20200131,1\n".to_string()
+            ),
+            read(&mut file_handler, "calendar_dates.txt")
+        );
+    }
+}
@@ -14,32 +14,54 @@
 
 //! [GTFS](https://gtfs.org/reference/static) format management.
 
+mod exporter;
+mod frequency_detection;
+mod lenient;
 mod read;
+mod split;
 mod write;
 
 use crate::{
-    calendars::{manage_calendars, write_calendar_dates},
-    file_handler::{FileHandler, PathFileHandler, ZipHandler},
+    calendars::{manage_calendars, write_calendar_dates_to},
+    csv_format::CsvFormat,
+    file_handler::{
+        FileHandler, FileWriter, MemoryFileHandler, PathFileHandler, PathFileWriter, ZipFileWriter,
+        ZipHandler,
+    },
     model::{Collections, Model},
     objects::{self, Availability, Contributor, Dataset, StopType, Time},
     parser::read_opt_collection,
+    report::Report,
     serde_utils::*,
+    stop_time_occupancy,
+    transfers::{generates_transfers, NeedTransfer},
     utils::*,
     validity_period, AddPrefix, PrefixConfiguration, Result,
 };
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use chrono_tz::Tz;
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, fmt, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    fs::File,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
 
-use tracing::info;
-use typed_index_collection::CollectionWithId;
+use tracing::{field, info, info_span};
+use typed_index_collection::{Collection, CollectionWithId, Idx};
 
+pub use exporter::GtfsExporter;
 #[cfg(all(feature = "gtfs", feature = "parser"))]
 pub use read::{
     manage_frequencies, manage_pathways, manage_shapes, manage_stop_times, read_agency,
-    read_routes, read_stops, read_transfers, EquipmentList,
+    read_routes, read_stops, read_transfers, DuplicatedTrips, EquipmentList,
+};
+pub use split::{
+    split_model, write_split_to_zip_with_configuration, write_split_with_configuration, SplitBy,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -60,6 +82,11 @@ struct Agency {
     email: Option<String>,
 }
 
+// Written in 'agency.txt' when the network has no url
+const DEFAULT_AGENCY_URL: &str = "http://www.navitia.io/";
+// Written in 'agency.txt' when the network has no timezone
+const DEFAULT_AGENCY_TIMEZONE: Tz = chrono_tz::Europe::Paris;
+
 impl<'a> From<&'a objects::Network> for Agency {
     fn from(obj: &objects::Network) -> Agency {
         Agency {
@@ -68,8 +95,8 @@ impl<'a> From<&'a objects::Network> for Agency {
             url: obj
                 .url
                 .clone()
-                .unwrap_or_else(|| "http://www.navitia.io/".to_string()),
-            timezone: obj.timezone.unwrap_or(chrono_tz::Europe::Paris),
+                .unwrap_or_else(|| DEFAULT_AGENCY_URL.to_string()),
+            timezone: obj.timezone.unwrap_or(DEFAULT_AGENCY_TIMEZONE),
             lang: obj.lang.clone(),
             phone: obj.phone.clone(),
             email: None,
@@ -191,26 +218,77 @@ fn default_true_bool() -> bool {
     true
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct StopTime {
-    trip_id: String,
-    arrival_time: Option<Time>,
-    departure_time: Option<Time>,
+/// A record of the `stop_times.txt` file of a GTFS
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StopTime {
+    /// Identifier of the trip
+    pub trip_id: String,
+    /// Arrival time at the stop
+    pub arrival_time: Option<Time>,
+    /// Departure time from the stop
+    pub departure_time: Option<Time>,
+    /// Identifier of the stop
     #[serde(deserialize_with = "de_without_slashes")]
-    stop_id: String,
-    stop_sequence: u32,
+    pub stop_id: String,
+    /// Order of the stop in the trip
+    pub stop_sequence: u32,
+    /// Pickup method (`0` for a regular pickup)
     #[serde(deserialize_with = "de_with_empty_default", default)]
-    pickup_type: u8,
+    pub pickup_type: u8,
+    /// Drop off method (`0` for a regular drop off)
     #[serde(deserialize_with = "de_with_empty_default", default)]
-    drop_off_type: u8,
-    local_zone_id: Option<u16>,
-    stop_headsign: Option<String>,
+    pub drop_off_type: u8,
+    /// Identifier of the local zone (NTFS extension)
+    pub local_zone_id: Option<u16>,
+    /// Headsign of the trip at the stop
+    pub stop_headsign: Option<String>,
+    /// `true` if the times are exact, `false` if they are approximate
     #[serde(
         deserialize_with = "de_from_u8_with_true_default",
         serialize_with = "ser_from_bool",
         default = "default_true_bool"
     )]
-    timepoint: bool,
+    pub timepoint: bool,
+}
+
+/// Iterate lazily over the stop times of the GTFS directory `path`, without
+/// building the `Collections` (e.g. to load them into a database)
+pub fn iter_stop_times<P: AsRef<Path>>(path: P) -> Result<impl Iterator<Item = Result<StopTime>>> {
+    let path = path.as_ref().join("stop_times.txt");
+    info!("Reading stop_times.txt");
+    let file = File::open(&path).with_context(|| format!("Error reading {:?}", path))?;
+    Ok(crate::parser::iter_csv_objects(file, path))
+}
+
+/// Iterate lazily over the stop times of a GTFS read by `file_handler` (e.g.
+/// a `ZipHandler` for an archive)
+#[cfg(feature = "parser")]
+pub fn iter_stop_times_from_handler<H: FileHandler>(
+    file_handler: H,
+) -> Result<impl Iterator<Item = Result<StopTime>>> {
+    crate::parser::iter_objects(file_handler, "stop_times.txt")
+}
+
+/// Write the `stop_times` into the `stop_times.txt` file of the directory
+/// `path` in the `csv_format`, as they are iterated, without building the
+/// `Collections`
+pub fn write_stop_times<P, I>(path: P, stop_times: I, csv_format: &CsvFormat) -> Result<()>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = StopTime>,
+{
+    let path = path.as_ref().join("stop_times.txt");
+    info!("Writing stop_times.txt");
+    let mut wtr = csv_format
+        .writer_from_path(&path)
+        .with_context(|| format!("Error reading {:?}", path))?;
+    for stop_time in stop_times {
+        wtr.serialize(stop_time)
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", path))?;
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize, Debug, Derivative, PartialEq, Clone)]
@@ -249,6 +327,51 @@ impl<'a> From<&'a objects::Transfer> for Transfer {
     }
 }
 
+// Transfer of the 'transfers.txt' file when the trip-to-trip transfers are
+// written
+#[derive(Serialize, Debug, PartialEq, Clone)]
+struct TripTransfer {
+    from_stop_id: String,
+    to_stop_id: String,
+    from_trip_id: Option<String>,
+    to_trip_id: Option<String>,
+    transfer_type: TransferType,
+    min_transfer_time: Option<u32>,
+}
+
+impl From<Transfer> for TripTransfer {
+    fn from(transfer: Transfer) -> TripTransfer {
+        TripTransfer {
+            from_stop_id: transfer.from_stop_id,
+            to_stop_id: transfer.to_stop_id,
+            from_trip_id: None,
+            to_trip_id: None,
+            transfer_type: transfer.transfer_type,
+            min_transfer_time: transfer.min_transfer_time,
+        }
+    }
+}
+
+impl<'a> From<&'a objects::Interchange> for TripTransfer {
+    fn from(obj: &objects::Interchange) -> TripTransfer {
+        let transfer_type = if obj.guaranteed {
+            TransferType::Timed
+        } else if obj.min_transfer_time.is_some() {
+            TransferType::WithTransferTime
+        } else {
+            TransferType::Recommended
+        };
+        TripTransfer {
+            from_stop_id: obj.from_stop_point_id.clone(),
+            to_stop_id: obj.to_stop_point_id.clone(),
+            from_trip_id: Some(obj.from_vehicle_journey_id.clone()),
+            to_trip_id: Some(obj.to_vehicle_journey_id.clone()),
+            transfer_type,
+            min_transfer_time: obj.min_transfer_time,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 struct Shape {
     #[serde(rename = "shape_id", deserialize_with = "de_without_slashes")]
@@ -261,6 +384,166 @@ struct Shape {
     sequence: u32,
 }
 
+/// Strategy applied when several stops (in `stops.txt`) or several trips (in
+/// `trips.txt`) share the same identifier.
+///
+/// The rows of `stop_times.txt` of a duplicated `trip_id` are assigned to its
+/// occurrences in their order, a new occurrence starting when a row follows a
+/// row of another trip or does not increase the `stop_sequence`: the stop
+/// times of the occurrences not kept are skipped, and the ones of a suffixed
+/// occurrence are read for it. The other rows referencing a duplicated
+/// identifier (e.g. `frequencies.txt`, `transfers.txt`) are linked to the kept
+/// object with the original identifier.
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum DuplicateIdStrategy {
+    /// The reading fails
+    #[derivative(Default)]
+    Fail,
+    /// Only the first object with a given identifier is kept
+    KeepFirst,
+    /// Only the last object with a given identifier is kept
+    KeepLast,
+    /// All the objects are kept, the identifier of the duplicates being suffixed
+    /// by their rank (`:1`, `:2`, ...), the original identifier is preserved as
+    /// a `source` code. Only available for `trips.txt`, the reading failing on
+    /// a duplicated `stop_id` whose references could not be assigned to its
+    /// occurrences.
+    Suffix,
+}
+
+impl FromStr for DuplicateIdStrategy {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fail" => Ok(DuplicateIdStrategy::Fail),
+            "keep-first" => Ok(DuplicateIdStrategy::KeepFirst),
+            "keep-last" => Ok(DuplicateIdStrategy::KeepLast),
+            "suffix" => Ok(DuplicateIdStrategy::Suffix),
+            _ => Err(anyhow!(
+                "unknown strategy {:?} for duplicated identifiers (expected 'fail', 'keep-first', 'keep-last' or 'suffix')",
+                s
+            )),
+        }
+    }
+}
+
+/// Mapping between the `route_short_name` and `route_long_name` of the GTFS
+/// routes and the code and name of the NTFS lines, applied both when reading
+/// and when writing a GTFS
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum RouteNameStrategy {
+    /// `route_short_name` is the code of the line and `route_long_name` its
+    /// name
+    #[derivative(Default)]
+    CodeAndName,
+    /// `route_short_name` and `route_long_name` are both the name of the line,
+    /// the code of the line not being written
+    DuplicatedName,
+}
+
+impl FromStr for RouteNameStrategy {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "code-and-name" => Ok(RouteNameStrategy::CodeAndName),
+            "duplicated-name" => Ok(RouteNameStrategy::DuplicatedName),
+            _ => Err(anyhow!(
+                "unknown strategy {:?} for the names of the routes (expected 'code-and-name' or 'duplicated-name')",
+                s
+            )),
+        }
+    }
+}
+
+impl RouteNameStrategy {
+    // `route_short_name` and `route_long_name` of the routes of `line`
+    fn route_names(self, line: &objects::Line) -> (String, String) {
+        match self {
+            RouteNameStrategy::CodeAndName => {
+                (line.code.clone().unwrap_or_default(), line.name.clone())
+            }
+            RouteNameStrategy::DuplicatedName => (line.name.clone(), line.name.clone()),
+        }
+    }
+
+    // Fix the code and the name of `line`, read as the `route_short_name`
+    // and the `route_long_name` of its routes
+    fn fix_line_names(self, line: &mut objects::Line) {
+        match self {
+            RouteNameStrategy::CodeAndName => {}
+            RouteNameStrategy::DuplicatedName => {
+                if line.name.is_empty() {
+                    line.name = line.code.take().unwrap_or_default();
+                } else if line.code.as_ref() == Some(&line.name) {
+                    line.code = None;
+                }
+            }
+        }
+    }
+}
+
+/// Categories of the `Report` produced while reading a GTFS
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportCategory {
+    /// Several objects of a file share the same identifier
+    DuplicateId,
+    /// The stop times of a trip are not sorted by `stop_sequence`
+    UnsortedStopTimes,
+    /// A stop time is an exact duplicate of another one, it is removed
+    DuplicateStopTime,
+    /// Several different stop times of a trip share the same
+    /// `stop_sequence`, only the first one is kept
+    DuplicateStopSequence,
+    /// A line has been moved from the network of its agency to the network
+    /// of a `NetworkRule`
+    LineMovedToNetwork,
+}
+
+/// Categories of the `Report` listing the default values written in a GTFS
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WriterReportCategory {
+    /// The network has no url, a placeholder is written as `agency_url`
+    DefaultAgencyUrl,
+    /// The network has no timezone, a default one is written as
+    /// `agency_timezone`
+    DefaultAgencyTimezone,
+    /// Stops have no comment, their `stop_desc` is written empty
+    EmptyStopDescription,
+}
+
+/// A rule moving the lines of an agency whose code matches a pattern into a
+/// network of their own (e.g. the lines `N1`, `N2`... of an agency into its
+/// night network), see `Configuration::network_rules`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NetworkRule {
+    /// Identifier of the agency of the lines, all the agencies if empty
+    #[serde(default)]
+    pub agency_id: Option<String>,
+    /// Regular expression matching the whole code of the lines (the
+    /// `route_short_name` of the routes)
+    pub route_code_pattern: String,
+    /// Identifier of the network of the matching lines
+    pub network_id: String,
+    /// Name of the network, created from the network of the agency when it
+    /// doesn't exist
+    pub network_name: String,
+}
+
+/// Read the `NetworkRule`s from the JSON file `path`, containing an array of
+/// rules applied in order (the first rule matching a line is applied)
+pub fn read_network_rules<P: AsRef<Path>>(path: P) -> Result<Vec<NetworkRule>> {
+    let path = path.as_ref();
+    info!("Reading network rules from {:?}", path);
+    let file = File::open(path).with_context(|| format!("Error reading {:?}", path))?;
+    let rules =
+        serde_json::from_reader(file).with_context(|| format!("Error parsing {:?}", path))?;
+    Ok(rules)
+}
+
 ///parameters consolidation
 #[derive(Default)]
 pub struct Configuration {
@@ -280,25 +563,66 @@ pub struct Configuration {
     /// Else we group the routes by `agency_id` and `route_short_name`
     /// (or `route_long_name` if the short name is empty) and create a `Line` for each group.
     pub read_as_line: bool,
+    /// Strategy applied on duplicated identifiers of stops and trips
+    pub duplicate_id_strategy: DuplicateIdStrategy,
+    /// If true, the dates and the times written in the exotic formats of
+    /// some exports (`24:00`, `2020-01-31`) are normalized before their
+    /// parsing, at the cost of reading in memory the files having a date or a
+    /// time column
+    pub lenient: bool,
+    /// Mapping of the names of the routes to the code and the name of the
+    /// lines
+    pub route_name_strategy: RouteNameStrategy,
+    /// If true, each GTFS `Route` generates a single NTFS route for all the
+    /// directions of its trips. Else one NTFS route is generated by
+    /// `direction_id`, the backward one being suffixed by `_R`.
+    pub merge_directions: bool,
+    /// Rules splitting the agencies into several networks by the code of
+    /// their lines
+    pub network_rules: Vec<NetworkRule>,
 }
 
 fn read_file_handler<H>(file_handler: &mut H, configuration: Configuration) -> Result<Model>
 where
     for<'a> &'a mut H: FileHandler,
 {
-    let collections = read_file_handler_to_collections(file_handler, configuration)?;
+    let (collections, _) = read_file_handler_to_collections(file_handler, configuration)?;
     Model::new(collections)
 }
 
 fn read_file_handler_to_collections<H>(
     file_handler: &mut H,
     configuration: Configuration,
-) -> Result<Collections>
+) -> Result<(Collections, Report<ReportCategory>)>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    if configuration.lenient {
+        let mut file_handler = lenient::LenientFileHandler::new(file_handler);
+        read_collections(&mut file_handler, configuration)
+    } else {
+        read_collections(file_handler, configuration)
+    }
+}
+
+fn read_collections<H>(
+    file_handler: &mut H,
+    configuration: Configuration,
+) -> Result<(Collections, Report<ReportCategory>)>
 where
     for<'a> &'a mut H: FileHandler,
 {
+    let span = info_span!(
+        "read_gtfs",
+        source = file_handler.source_name(),
+        stop_points = field::Empty,
+        vehicle_journeys = field::Empty,
+        stop_times = field::Empty,
+    );
+    let _enter = span.enter();
     let mut collections = Collections::default();
     let mut equipments = read::EquipmentList::default();
+    let mut report = Report::default();
 
     let Configuration {
         contributor,
@@ -308,6 +632,11 @@ where
         on_demand_transport,
         on_demand_transport_comment,
         read_as_line,
+        duplicate_id_strategy,
+        lenient: _,
+        route_name_strategy,
+        merge_directions,
+        network_rules,
     } = configuration;
 
     manage_calendars(file_handler, &mut collections)?;
@@ -315,13 +644,18 @@ where
 
     collections.contributors = CollectionWithId::from(contributor);
     collections.datasets = CollectionWithId::from(dataset);
-    collections.feed_infos = feed_infos;
+    collections.feed_infos = feed_infos.into();
 
     let (networks, companies) = read::read_agency(file_handler)?;
     collections.networks = networks;
     collections.companies = companies;
-    let (stop_areas, stop_points, stop_locations) =
-        read::read_stops(file_handler, &mut collections.comments, &mut equipments)?;
+    let (stop_areas, stop_points, stop_locations) = read::read_stops(
+        file_handler,
+        &mut collections.comments,
+        &mut equipments,
+        duplicate_id_strategy,
+        &mut report,
+    )?;
     collections.transfers = read::read_transfers(file_handler, &stop_points, &stop_areas)?;
     collections.stop_areas = stop_areas;
     collections.stop_points = stop_points;
@@ -329,15 +663,29 @@ where
 
     read::manage_shapes(&mut collections, file_handler)?;
 
-    read::read_routes(file_handler, &mut collections, read_as_line)?;
+    let duplicated_trips = read::read_routes(
+        file_handler,
+        &mut collections,
+        read_as_line,
+        merge_directions,
+        duplicate_id_strategy,
+        &mut report,
+    )?;
+    read::apply_network_rules(&mut collections, &network_rules, &mut report)?;
+    for line_idx in collections.lines.indexes() {
+        route_name_strategy.fix_line_names(&mut collections.lines.index_mut(line_idx));
+    }
     collections.equipments = CollectionWithId::new(equipments.into_equipments())?;
     read::manage_stop_times(
         &mut collections,
         file_handler,
         on_demand_transport,
         on_demand_transport_comment,
+        &duplicated_trips,
+        &mut report,
     )?;
     read::manage_frequencies(&mut collections, file_handler)?;
+    stop_time_occupancy::manage_stop_time_occupancies(&mut collections, file_handler)?;
     read::manage_pathways(&mut collections, file_handler)?;
     collections.levels = read_opt_collection(file_handler, "levels.txt")?;
 
@@ -347,7 +695,8 @@ where
     }
 
     collections.calendar_deduplication();
-    Ok(collections)
+    record_dataset_size(&span, &collections);
+    Ok((collections, report))
 }
 
 /// Imports a `Model` from the [GTFS](https://gtfs.org/reference/static)
@@ -386,6 +735,14 @@ where
     Reader::default().parse_zip_reader(reader, source_name)
 }
 
+/// Imports a `Model` from the [GTFS](https://gtfs.org/reference/static)
+/// files held in memory, by file name (e.g. `stops.txt`).
+///
+/// The `source_name` is needed to have nicer error messages.
+pub fn from_memory(files: BTreeMap<String, Vec<u8>>, source_name: &str) -> Result<Model> {
+    Reader::default().parse_memory(files, source_name)
+}
+
 /// Imports a `Model` from the
 /// [GTFS](https://gtfs.org/reference/static).
 /// files in the given directory.
@@ -437,6 +794,19 @@ impl Reader {
     /// files in the given directory.
     /// This method will try to detect if the input is a zipped archive or not.
     pub fn parse_collections(self, path: impl AsRef<Path>) -> Result<Collections> {
+        let (collections, _) = self.parse_collections_with_report(path)?;
+        Ok(collections)
+    }
+
+    /// Imports `Collections` from the
+    /// [GTFS](https://gtfs.org/reference/static).
+    /// files in the given directory, along with the `Report` of the decisions
+    /// taken while reading (e.g. handling of duplicated identifiers).
+    /// This method will try to detect if the input is a zipped archive or not.
+    pub fn parse_collections_with_report(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Result<(Collections, Report<ReportCategory>)> {
         let p = path.as_ref();
         if p.is_file() {
             // if it's a file, we consider it to be a zip (and an error will be returned if it is not)
@@ -458,27 +828,33 @@ impl Reader {
     /// Imports a `Model` from a zip file containing the
     /// [GTFS](https://gtfs.org/reference/static).
     pub fn parse_zip(self, path: impl AsRef<Path>) -> Result<Model> {
-        let collections = self.parse_zip_collections(path)?;
+        let (collections, _) = self.parse_zip_collections(path)?;
         Model::new(collections)
     }
 
     /// Imports a `Model` from the [GTFS](https://gtfs.org/reference/static)
     /// files in the `path` directory.
     pub fn parse_dir(self, path: impl AsRef<Path>) -> Result<Model> {
-        let collections = self.parse_dir_collections(path)?;
+        let (collections, _) = self.parse_dir_collections(path)?;
         Model::new(collections)
     }
 
     /// Imports `Collections` from the [GTFS](https://gtfs.org/reference/static)
     /// files in the `path` directory.
-    fn parse_dir_collections(self, path: impl AsRef<Path>) -> Result<Collections> {
+    fn parse_dir_collections(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Result<(Collections, Report<ReportCategory>)> {
         let mut file_handler = PathFileHandler::new(path.as_ref().to_path_buf());
         read_file_handler_to_collections(&mut file_handler, self.configuration)
     }
 
     /// Imports `Collections` from a zip file containing the
     /// [GTFS](https://gtfs.org/reference/static).
-    fn parse_zip_collections(self, path: impl AsRef<Path>) -> Result<Collections> {
+    fn parse_zip_collections(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Result<(Collections, Report<ReportCategory>)> {
         let reader = std::fs::File::open(path.as_ref())?;
         let mut file_handler = ZipHandler::new(reader, path)?;
         read_file_handler_to_collections(&mut file_handler, self.configuration)
@@ -506,6 +882,149 @@ impl Reader {
         let mut file_handler = ZipHandler::new(reader, source_name)?;
         read_file_handler(&mut file_handler, self.configuration)
     }
+
+    /// Imports a `Model` from the [GTFS](https://gtfs.org/reference/static)
+    /// files held in memory, by file name (e.g. `stops.txt`).
+    ///
+    /// The `source_name` is needed to have nicer error messages.
+    pub fn parse_memory(
+        self,
+        files: BTreeMap<String, Vec<u8>>,
+        source_name: &str,
+    ) -> Result<Model> {
+        let mut file_handler = MemoryFileHandler::new(files, source_name);
+        read_file_handler(&mut file_handler, self.configuration)
+    }
+}
+
+/// Configuration of the reading of several GTFS feeds with [read_many]
+#[derive(Derivative)]
+#[derivative(Default)]
+pub struct MultiFeedConfiguration {
+    /// Prefix of the identifiers of each feed, in the order of the paths. If
+    /// empty, the prefix of a feed is the name of its file (without extension)
+    /// or directory.
+    pub prefixes: Vec<String>,
+    /// stop time precision management
+    pub on_demand_transport: bool,
+    /// on demand transport comment template
+    pub on_demand_transport_comment: Option<String>,
+    /// See [Configuration::read_as_line]
+    pub read_as_line: bool,
+    /// Strategy applied on duplicated identifiers of stops and trips
+    pub duplicate_id_strategy: DuplicateIdStrategy,
+    /// The maximum distance in meters of the transfers generated between the
+    /// stop points of different feeds
+    #[derivative(Default(value = "crate::TRANSFER_MAX_DISTANCE.parse().unwrap()"))]
+    pub transfer_max_distance: f64,
+    /// The walking speed in meters per second used for the generated transfers
+    #[derivative(Default(value = "crate::TRANSFER_WALKING_SPEED.parse().unwrap()"))]
+    pub transfer_walking_speed: f64,
+    /// Waiting time at stop in seconds added to the generated transfers
+    #[derivative(Default(value = "crate::TRANSFER_WAITING_TIME.parse().unwrap()"))]
+    pub transfer_waiting_time: u32,
+}
+
+// Prefix of each feed, given by the configuration or generated from the paths
+fn feed_prefixes<P: AsRef<Path>>(paths: &[P], prefixes: Vec<String>) -> Result<Vec<String>> {
+    if !prefixes.is_empty() {
+        if prefixes.len() != paths.len() {
+            bail!(
+                "{} prefixes given for {} GTFS feeds",
+                prefixes.len(),
+                paths.len()
+            );
+        }
+        return Ok(prefixes);
+    }
+    let mut used_prefixes = HashSet::new();
+    Ok(paths
+        .iter()
+        .enumerate()
+        .map(|(index, path)| {
+            let name = path
+                .as_ref()
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .filter(|name| !name.is_empty())
+                .map(ToString::to_string)
+                .unwrap_or_else(|| format!("feed{}", index + 1));
+            let mut prefix = name.clone();
+            let mut rank = 1;
+            while !used_prefixes.insert(prefix.clone()) {
+                rank += 1;
+                prefix = format!("{}{}", name, rank);
+            }
+            prefix
+        })
+        .collect())
+}
+
+/// Imports several [GTFS](https://gtfs.org/reference/static) feeds (zip
+/// archives or directories) and merges them into a single `Model`.
+///
+/// The identifiers of each feed are prefixed (see
+/// [MultiFeedConfiguration::prefixes]) to avoid conflicts between the feeds.
+/// Transfers are then generated between the stop points of different feeds
+/// closer than [MultiFeedConfiguration::transfer_max_distance]; the transfers
+/// inside a feed are the ones of its `transfers.txt`.
+pub fn read_many<P: AsRef<Path>>(
+    paths: &[P],
+    configuration: MultiFeedConfiguration,
+) -> Result<Model> {
+    let MultiFeedConfiguration {
+        prefixes,
+        on_demand_transport,
+        on_demand_transport_comment,
+        read_as_line,
+        duplicate_id_strategy,
+        transfer_max_distance,
+        transfer_walking_speed,
+        transfer_waiting_time,
+    } = configuration;
+    let prefixes = feed_prefixes(paths, prefixes)?;
+
+    let mut collections = Collections::default();
+    let mut feed_of_stop_points = HashMap::new();
+    for (feed, (path, prefix)) in paths.iter().zip(prefixes).enumerate() {
+        let path = path.as_ref();
+        info!("Reading GTFS {:?} with prefix {}", path, prefix);
+        let mut prefix_conf = PrefixConfiguration::default();
+        prefix_conf.set_data_prefix(prefix);
+        let configuration = Configuration {
+            prefix_conf: Some(prefix_conf),
+            on_demand_transport,
+            on_demand_transport_comment: on_demand_transport_comment.clone(),
+            read_as_line,
+            duplicate_id_strategy,
+            ..Default::default()
+        };
+        let feed_collections = Reader::new(configuration).parse_collections(path)?;
+        feed_of_stop_points.extend(
+            feed_collections
+                .stop_points
+                .values()
+                .map(|stop_point| (stop_point.id.clone(), feed)),
+        );
+        collections
+            .try_merge(feed_collections)
+            .with_context(|| format!("impossible to merge gtfs {:?}", path))?;
+    }
+
+    let model = Model::new(collections)?;
+    let different_feeds: NeedTransfer = Box::new(
+        |model: &Model, from_idx: Idx<objects::StopPoint>, to_idx: Idx<objects::StopPoint>| {
+            feed_of_stop_points.get(&model.stop_points[from_idx].id)
+                != feed_of_stop_points.get(&model.stop_points[to_idx].id)
+        },
+    );
+    generates_transfers(
+        model,
+        transfer_max_distance,
+        transfer_walking_speed,
+        transfer_waiting_time,
+        Some(different_feeds),
+    )
 }
 
 #[derive(PartialOrd, Ord, Debug, Clone, Eq, PartialEq, Hash)]
@@ -632,37 +1151,411 @@ where
     serializer.serialize_str(&to_gtfs_extended_value(r))
 }
 
+/// Behavior of the GTFS writer for the optional files without any record
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum EmptyFileStrategy {
+    /// The file is not written
+    #[derivative(Default)]
+    Skip,
+    /// The file is written with its header only, for the consumers requiring
+    /// all the files to exist
+    HeaderOnly,
+}
+
+/// Files written by the GTFS writer, to export only some of them with
+/// `WriteConfiguration::files`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GtfsFile {
+    /// `agency.txt`
+    Agency,
+    /// `calendar_dates.txt` and `calendar.txt`
+    Calendars,
+    /// `stops.txt`
+    Stops,
+    /// `routes.txt`
+    Routes,
+    /// `trips.txt`
+    Trips,
+    /// `stop_times.txt`
+    StopTimes,
+    /// `transfers.txt`
+    Transfers,
+    /// `shapes.txt`
+    Shapes,
+    /// `pathways.txt`
+    Pathways,
+    /// `levels.txt`
+    Levels,
+    /// `frequencies.txt`
+    Frequencies,
+    /// `stop_extensions.txt`
+    StopExtensions,
+    /// `stop_time_occupancies.txt`
+    StopTimeOccupancies,
+    /// `comments.txt` and `comment_links.txt`, written if
+    /// `WriteConfiguration::write_comments` is set
+    Comments,
+    /// `line_operating_hours.txt`, written if
+    /// `WriteConfiguration::write_line_operating_hours` is set
+    LineOperatingHours,
+}
+
+impl GtfsFile {
+    /// All the files
+    pub const ALL: &'static [GtfsFile] = &[
+        GtfsFile::Agency,
+        GtfsFile::Calendars,
+        GtfsFile::Stops,
+        GtfsFile::Routes,
+        GtfsFile::Trips,
+        GtfsFile::StopTimes,
+        GtfsFile::Transfers,
+        GtfsFile::Shapes,
+        GtfsFile::Pathways,
+        GtfsFile::Levels,
+        GtfsFile::Frequencies,
+        GtfsFile::StopExtensions,
+        GtfsFile::StopTimeOccupancies,
+        GtfsFile::Comments,
+        GtfsFile::LineOperatingHours,
+    ];
+
+    /// Names of the files
+    pub fn file_names(self) -> &'static [&'static str] {
+        match self {
+            GtfsFile::Agency => &["agency.txt"],
+            GtfsFile::Calendars => &["calendar_dates.txt", "calendar.txt"],
+            GtfsFile::Stops => &["stops.txt"],
+            GtfsFile::Routes => &["routes.txt"],
+            GtfsFile::Trips => &["trips.txt"],
+            GtfsFile::StopTimes => &["stop_times.txt"],
+            GtfsFile::Transfers => &["transfers.txt"],
+            GtfsFile::Shapes => &["shapes.txt"],
+            GtfsFile::Pathways => &["pathways.txt"],
+            GtfsFile::Levels => &["levels.txt"],
+            GtfsFile::Frequencies => &["frequencies.txt"],
+            GtfsFile::StopExtensions => &["stop_extensions.txt"],
+            GtfsFile::StopTimeOccupancies => &[stop_time_occupancy::STOP_TIME_OCCUPANCIES_FILE],
+            GtfsFile::Comments => &["comments.txt", "comment_links.txt"],
+            GtfsFile::LineOperatingHours => &["line_operating_hours.txt"],
+        }
+    }
+}
+
+/// Writer of an additional file of a GTFS export, plugged in
+/// `WriteConfiguration::extension_writers`
+pub trait GtfsFileWriter: fmt::Debug + Send + Sync {
+    /// Name of the file (e.g. `my_extension.txt`)
+    fn file_name(&self) -> &str;
+
+    /// Writes the content of the file from the `model`
+    fn write(&self, model: &Model, writer: &mut dyn std::io::Write) -> Result<()>;
+}
+
+/// List the default values written by `write` in place of the missing
+/// fields of `model`, so that the data producers can complete their source
+/// data instead of publishing placeholders.
+///
+/// Every fallback is a warning of the returned `Report`.
+pub fn default_values_report(model: &Model) -> Report<WriterReportCategory> {
+    let mut report = Report::default();
+    for network in model.networks.values() {
+        if network.url.is_none() {
+            report.add_warning(
+                format!(
+                    "network {} has no url, agency_url is written as {:?}",
+                    network.id, DEFAULT_AGENCY_URL
+                ),
+                WriterReportCategory::DefaultAgencyUrl,
+            );
+        }
+        if network.timezone.is_none() {
+            report.add_warning(
+                format!(
+                    "network {} has no timezone, agency_timezone is written as {:?}",
+                    network.id,
+                    DEFAULT_AGENCY_TIMEZONE.name()
+                ),
+                WriterReportCategory::DefaultAgencyTimezone,
+            );
+        }
+    }
+    let has_no_comment = |comment_links: &objects::CommentLinksT| {
+        comment_links
+            .iter()
+            .all(|comment_id| !model.comments.contains_id(comment_id))
+    };
+    let stops_without_desc = model
+        .stop_points
+        .values()
+        .filter(|stop_point| has_no_comment(&stop_point.comment_links))
+        .count()
+        + model
+            .stop_areas
+            .values()
+            .filter(|stop_area| has_no_comment(&stop_area.comment_links))
+            .count()
+        + model
+            .stop_locations
+            .values()
+            .filter(|stop_location| has_no_comment(&stop_location.comment_links))
+            .count();
+    if stops_without_desc > 0 {
+        report.add_warning(
+            format!(
+                "{} stop(s) have no comment, their stop_desc is written empty",
+                stops_without_desc
+            ),
+            WriterReportCategory::EmptyStopDescription,
+        );
+    }
+    report
+}
+
+/// GTFS `route_type` codes (basic or extended) of the routes of some lines,
+/// by line identifier, overriding the route type deduced from the physical
+/// modes of the lines (e.g. tram-train lines written as tramways rather than
+/// trains).
+pub type RouteTypeOverrides = BTreeMap<String, u16>;
+
+#[derive(Deserialize, Debug)]
+struct RouteTypeOverride {
+    line_id: String,
+    route_type: u16,
+}
+
+/// Read the `RouteTypeOverrides` from the CSV file `path`, with the columns
+/// `line_id` and `route_type`
+pub fn read_route_type_overrides<P: AsRef<Path>>(path: P) -> Result<RouteTypeOverrides> {
+    let path = path.as_ref();
+    info!("Reading route type overrides from {:?}", path);
+    let mut rdr =
+        csv::Reader::from_path(path).with_context(|| format!("Error reading {:?}", path))?;
+    let mut overrides = RouteTypeOverrides::new();
+    for route_type_override in rdr.deserialize() {
+        let route_type_override: RouteTypeOverride =
+            route_type_override.with_context(|| format!("Error parsing {:?}", path))?;
+        overrides.insert(route_type_override.line_id, route_type_override.route_type);
+    }
+    Ok(overrides)
+}
+
+/// Detection of the trips running at a regular interval, written as the
+/// frequencies of one of them when exporting a GTFS (see
+/// `WriteConfiguration::frequency_detection`)
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub struct FrequencyDetection {
+    /// Minimum number of trips at a regular interval replaced by a frequency
+    #[derivative(Default(value = "3"))]
+    pub min_trips: usize,
+    /// Maximum difference in seconds between the first arrival of a trip and
+    /// the regular interval. The trips are shifted to the regular interval
+    /// in the export.
+    pub max_jitter: u32,
+}
+
+/// Configuration options for exporting a GTFS.
+#[derive(Debug, Default, Clone)]
+pub struct WriteConfiguration {
+    /// Write the extended route types instead of the basic ones
+    pub extend_route_type: bool,
+    /// Behavior for the optional files without any record
+    pub empty_file_strategy: EmptyFileStrategy,
+    /// Route types of the routes of some lines, overriding the route types
+    /// deduced from their physical modes
+    pub route_type_overrides: RouteTypeOverrides,
+    /// Leave empty the arrival at the first stop and the departure from the
+    /// last stop of the trips when they are equal to the other time
+    pub blank_redundant_times: bool,
+    /// Mapping of the code and the name of the lines to the names of the
+    /// routes
+    pub route_name_strategy: RouteNameStrategy,
+    /// Write the comments and their links to the objects in the
+    /// `comments.txt` and `comment_links.txt` extension files
+    pub write_comments: bool,
+    /// Write the first and last departure times of the lines by day type in
+    /// the `line_operating_hours.txt` extension file
+    pub write_line_operating_hours: bool,
+    /// Files to write, all of them if `None` (e.g. only `GtfsFile::Stops` for
+    /// an extract of the stops)
+    pub files: Option<BTreeSet<GtfsFile>>,
+    /// Replace the trips running at a regular interval by frequencies with
+    /// exact times in `frequencies.txt`, shrinking the `stop_times.txt` of
+    /// the metro-style services
+    pub frequency_detection: Option<FrequencyDetection>,
+    /// Quoting, line terminator and precision of the coordinates of the CSV
+    /// files
+    pub csv_format: CsvFormat,
+    /// Writers of additional files, called after the files of the GTFS
+    pub extension_writers: Vec<Arc<dyn GtfsFileWriter>>,
+}
+
+impl WriteConfiguration {
+    /// `true` if the `file` is written, following `files`
+    fn is_written(&self, file: GtfsFile) -> bool {
+        self.files
+            .as_ref()
+            .map_or(true, |files| files.contains(&file))
+    }
+}
+
 /// Exports a `Model` to [GTFS](https://gtfs.org/reference/static) files
 /// in the given directory.
 /// see [NTFS to GTFS conversion](https://github.com/hove-io/transit_model/blob/master/src/documentation/ntfs2gtfs.md)
-pub fn write<P: AsRef<Path>>(model: Model, path: P, extend_route_type: bool) -> Result<()> {
+///
+/// The optional files without any record are written following
+/// `empty_file_strategy`.
+pub fn write<P: AsRef<Path>>(
+    model: Model,
+    path: P,
+    extend_route_type: bool,
+    empty_file_strategy: EmptyFileStrategy,
+) -> Result<()> {
+    let config = WriteConfiguration {
+        extend_route_type,
+        empty_file_strategy,
+        ..Default::default()
+    };
+    write_with_configuration(model, path, config)
+}
+
+/// Exports a `Model` to [GTFS](https://gtfs.org/reference/static) files
+/// in the given directory, following the `config`.
+/// see [NTFS to GTFS conversion](https://github.com/hove-io/transit_model/blob/master/src/documentation/ntfs2gtfs.md)
+pub fn write_with_configuration<P: AsRef<Path>>(
+    model: Model,
+    path: P,
+    config: WriteConfiguration,
+) -> Result<()> {
     let path = path.as_ref();
     std::fs::create_dir_all(path)?;
     info!("Writing GTFS to {:?}", path);
+    write_file_writer(model, &mut PathFileWriter::new(path), config)
+}
 
-    write::write_transfers(path, &model.transfers)?;
-    write::write_agencies(path, &model.networks)?;
-    write_calendar_dates(path, &model.calendars)?;
-    write::write_stops(
-        path,
-        &model.stop_points,
-        &model.stop_areas,
-        &model.stop_locations,
-        &model.comments,
-        &model.equipments,
-    )?;
-    write::write_trips(path, &model)?;
-    write::write_routes(path, &model, extend_route_type)?;
-    write::write_stop_extensions(path, &model.stop_points, &model.stop_areas)?;
-    write::write_stop_times(
-        path,
-        &model.vehicle_journeys,
-        &model.stop_points,
-        &model.stop_time_headsigns,
-    )?;
-    write::write_shapes(path, &model.geometries)?;
-    write_collection_with_id(path, "pathways.txt", &model.pathways)?;
-    write_collection_with_id(path, "levels.txt", &model.levels)?;
+fn write_file_writer<H>(model: Model, file_writer: &mut H, config: WriteConfiguration) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    let span = info_span!(
+        "write_gtfs",
+        stop_points = field::Empty,
+        vehicle_journeys = field::Empty,
+        stop_times = field::Empty,
+    );
+    let _enter = span.enter();
+    record_dataset_size(&span, &model);
+    let csv_format = &config.csv_format;
+    let (model, exact_frequencies) = match config.frequency_detection {
+        Some(detection) if config.is_written(GtfsFile::Frequencies) => {
+            frequency_detection::detect_frequencies(model, &detection)?
+        }
+        _ => (model, Collection::default()),
+    };
+    if config.is_written(GtfsFile::Transfers) {
+        write::write_transfers(
+            file_writer,
+            &model.transfers,
+            &model.interchanges,
+            csv_format,
+        )?;
+    }
+    if config.is_written(GtfsFile::Agency) {
+        write::write_agencies(file_writer, &model.networks, csv_format)?;
+    }
+    if config.is_written(GtfsFile::Calendars) {
+        write_calendar_dates_to(file_writer, &model.calendars, csv_format)?;
+    }
+    if config.is_written(GtfsFile::Stops) {
+        write::write_stops(
+            file_writer,
+            &model.stop_points,
+            &model.stop_areas,
+            &model.stop_locations,
+            &model.comments,
+            &model.equipments,
+            csv_format,
+        )?;
+    }
+    if config.is_written(GtfsFile::Trips) {
+        write::write_trips(file_writer, &model, csv_format)?;
+    }
+    if config.is_written(GtfsFile::Routes) {
+        write::write_routes(
+            file_writer,
+            &model,
+            config.extend_route_type,
+            &config.route_type_overrides,
+            config.route_name_strategy,
+            csv_format,
+        )?;
+    }
+    if config.is_written(GtfsFile::StopExtensions) {
+        write::write_stop_extensions(
+            file_writer,
+            &model.stop_points,
+            &model.stop_areas,
+            csv_format,
+        )?;
+    }
+    if config.write_comments && config.is_written(GtfsFile::Comments) {
+        write::write_comments(file_writer, &model, csv_format)?;
+    }
+    if config.write_line_operating_hours && config.is_written(GtfsFile::LineOperatingHours) {
+        write::write_line_operating_hours(file_writer, &model, csv_format)?;
+    }
+    if config.is_written(GtfsFile::StopTimes) {
+        write::write_stop_times(
+            file_writer,
+            &model.vehicle_journeys,
+            &model.stop_points,
+            &model.stop_time_headsigns,
+            config.blank_redundant_times,
+            csv_format,
+        )?;
+    }
+    if config.is_written(GtfsFile::Shapes) {
+        write::write_shapes(file_writer, &model.geometries, csv_format)?;
+    }
+    if config.is_written(GtfsFile::Pathways) {
+        write_collection_with_id_to(file_writer, "pathways.txt", &model.pathways, csv_format)?;
+    }
+    if config.is_written(GtfsFile::Levels) {
+        write_collection_with_id_to(file_writer, "levels.txt", &model.levels, csv_format)?;
+    }
+    if config.is_written(GtfsFile::Frequencies) {
+        write::write_frequencies(
+            file_writer,
+            &model.frequencies,
+            &exact_frequencies,
+            csv_format,
+        )?;
+    }
+    if config.is_written(GtfsFile::StopTimeOccupancies) {
+        write_collection_to(
+            file_writer,
+            stop_time_occupancy::STOP_TIME_OCCUPANCIES_FILE,
+            &model.stop_time_occupancies,
+            csv_format,
+        )?;
+    }
+    for extension_writer in &config.extension_writers {
+        info!("Writing {}", extension_writer.file_name());
+        let (mut writer, path) = (&mut *file_writer).create_file(extension_writer.file_name())?;
+        extension_writer
+            .write(&model, &mut writer)
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    if config.empty_file_strategy == EmptyFileStrategy::HeaderOnly {
+        let written_files: Vec<&str> = GtfsFile::ALL
+            .iter()
+            .filter(|file| config.is_written(**file))
+            .flat_map(|file| file.file_names().iter().copied())
+            .collect();
+        write::write_empty_files_header(file_writer, &written_files, csv_format)?;
+    }
 
     Ok(())
 }
@@ -670,16 +1563,56 @@ pub fn write<P: AsRef<Path>>(model: Model, path: P, extend_route_type: bool) ->
 /// Exports a `Model` to [GTFS](https://gtfs.org/reference/static) files
 /// in the given ZIP archive.
 /// see [NTFS to GTFS conversion](https://github.com/hove-io/transit_model/blob/master/src/documentation/ntfs2gtfs.md)
+///
+/// The optional files without any record are written following
+/// `empty_file_strategy`.
 pub fn write_to_zip<P: AsRef<std::path::Path>>(
     model: Model,
     path: P,
     extend_route_type: bool,
+    empty_file_strategy: EmptyFileStrategy,
+) -> Result<()> {
+    let config = WriteConfiguration {
+        extend_route_type,
+        empty_file_strategy,
+        ..Default::default()
+    };
+    write_to_zip_with_configuration(model, path, config)
+}
+
+/// Exports a `Model` to [GTFS](https://gtfs.org/reference/static) files
+/// in the given ZIP archive, following the `config`.
+/// see [NTFS to GTFS conversion](https://github.com/hove-io/transit_model/blob/master/src/documentation/ntfs2gtfs.md)
+pub fn write_to_zip_with_configuration<P: AsRef<std::path::Path>>(
+    model: Model,
+    path: P,
+    config: WriteConfiguration,
 ) -> Result<()> {
     let path = path.as_ref();
     info!("Writing GTFS to ZIP File {:?}", path);
     let input_tmp_dir = tempfile::tempdir()?;
-    write(model, input_tmp_dir.path(), extend_route_type)?;
+    write_with_configuration(model, input_tmp_dir.path(), config)?;
     zip_to(input_tmp_dir.path(), path)?;
     input_tmp_dir.close()?;
     Ok(())
 }
+
+/// Exports a `Model` to a ZIP archive of [GTFS](https://gtfs.org/reference/static)
+/// files written into `writer`, following the `config`, without using the
+/// file system (e.g. with a `std::io::Cursor` on a buffer).
+///
+/// The `source_name` is needed to have nicer error messages.
+pub fn write_to_zip_writer<W>(
+    model: Model,
+    writer: W,
+    source_name: &str,
+    config: WriteConfiguration,
+) -> Result<W>
+where
+    W: std::io::Write + std::io::Seek,
+{
+    info!("Writing GTFS to ZIP archive {}", source_name);
+    let mut file_writer = ZipFileWriter::new(writer, source_name);
+    write_file_writer(model, &mut file_writer, config)?;
+    file_writer.finish()
+}
@@ -13,8 +13,8 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
 use super::{
-    Agency, DirectionType, Route, RouteType, Shape, Stop, StopLocationType, StopTime, Transfer,
-    TransferType, Trip,
+    Agency, DirectionType, DuplicateIdStrategy, NetworkRule, ReportCategory, Route, RouteType,
+    Shape, Stop, StopLocationType, StopTime, Transfer, TransferType, Trip,
 };
 use crate::{
     file_handler::FileHandler,
@@ -24,18 +24,20 @@ use crate::{
         StopPoint, StopTimePrecision, StopType, Time, TransportType,
     },
     parser::{read_collection, read_objects, read_objects_loose},
+    report::{Report, ReportEntry, Severity},
     serde_utils::de_with_empty_default,
     Result,
 };
-use anyhow::{anyhow, bail, Error};
+use anyhow::{anyhow, bail, Context, Error};
 use derivative::Derivative;
 use geo::{LineString, Point};
+use regex::Regex;
 use serde::Deserialize;
 use skip_error::{skip_error_and_warn, SkipError};
 use std::convert::TryFrom;
 use std::{
     cmp,
-    collections::{BTreeMap, BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 };
 use tracing::{info, warn};
 use typed_index_collection::{impl_id, Collection, CollectionWithId, Idx};
@@ -235,6 +237,25 @@ impl TryFrom<Frequency> for objects::Frequency {
 }
 
 impl RouteType {
+    /// Route type of a basic or extended GTFS `route_type` code
+    pub(crate) fn from_gtfs_code(code: u16) -> RouteType {
+        let hundreds = code / 100;
+        match (code, hundreds) {
+            (0, _) | (_, 9) => RouteType::Tramway,
+            (1, _) | (_, 4) | (_, 5) | (_, 6) => RouteType::Metro,
+            (2, _) | (_, 1) | (_, 3) => RouteType::Train,
+            (3, _) | (_, 7) | (_, 8) => RouteType::Bus,
+            (4, _) | (_, 10) | (_, 12) => RouteType::Ferry,
+            (5, _) => RouteType::CableCar,
+            (6, _) | (_, 13) => RouteType::SuspendedCableCar,
+            (7, _) | (_, 14) => RouteType::Funicular,
+            (_, 2) => RouteType::Coach,
+            (_, 11) => RouteType::Air,
+            (_, 15) => RouteType::Taxi,
+            _ => RouteType::UnknownMode,
+        }
+    }
+
     fn to_gtfs_value(&self) -> String {
         match *self {
             RouteType::Tramway => "0".to_string(),
@@ -268,21 +289,7 @@ impl<'de> ::serde::Deserialize<'de> for RouteType {
         D: ::serde::Deserializer<'de>,
     {
         let i = u16::deserialize(deserializer)?;
-        let hundreds = i / 100;
-        Ok(match (i, hundreds) {
-            (0, _) | (_, 9) => RouteType::Tramway,
-            (1, _) | (_, 4) | (_, 5) | (_, 6) => RouteType::Metro,
-            (2, _) | (_, 1) | (_, 3) => RouteType::Train,
-            (3, _) | (_, 7) | (_, 8) => RouteType::Bus,
-            (4, _) | (_, 10) | (_, 12) => RouteType::Ferry,
-            (5, _) => RouteType::CableCar,
-            (6, _) | (_, 13) => RouteType::SuspendedCableCar,
-            (7, _) | (_, 14) => RouteType::Funicular,
-            (_, 2) => RouteType::Coach,
-            (_, 11) => RouteType::Air,
-            (_, 15) => RouteType::Taxi,
-            _ => RouteType::UnknownMode,
-        })
+        Ok(RouteType::from_gtfs_code(i))
     }
 }
 
@@ -303,11 +310,11 @@ impl Route {
         (self.agency_id.clone(), name, key)
     }
 
-    fn get_id_by_direction(&self, d: DirectionType) -> String {
+    fn get_id_by_direction(&self, d: DirectionType, merge_directions: bool) -> String {
         let id = self.id.clone();
         match d {
-            DirectionType::Forward => id,
-            DirectionType::Backward => id + "_R",
+            DirectionType::Backward if !merge_directions => id + "_R",
+            _ => id,
         }
     }
 }
@@ -319,6 +326,7 @@ impl Trip {
         dataset: &objects::Dataset,
         trip_property_id: &Option<String>,
         networks: &CollectionWithId<objects::Network>,
+        merge_directions: bool,
     ) -> Result<objects::VehicleJourney> {
         let route = match routes.get(&self.route_id) {
             Some(route) => route,
@@ -333,7 +341,7 @@ impl Trip {
             codes,
             object_properties: PropertiesMap::default(),
             comment_links: CommentLinksT::default(),
-            route_id: route.get_id_by_direction(self.direction),
+            route_id: route.get_id_by_direction(self.direction, merge_directions),
             physical_mode_id: physical_mode.id,
             dataset_id: dataset.id.clone(),
             service_id: self.service_id.clone(),
@@ -380,12 +388,18 @@ where
     Ok(())
 }
 
-/// Reading times that a vehicle arrives at and departs from stops for each trip
+/// Reading times that a vehicle arrives at and departs from stops for each trip.
+/// The rows of a trip of `duplicated_trips` are assigned to its occurrences by
+/// `stop_sequence`: the n-th row of the trip with a given `stop_sequence`
+/// belongs to its n-th occurrence, whatever the order of the rows. The rows
+/// of the occurrences not kept are skipped.
 pub fn manage_stop_times<H>(
     collections: &mut Collections,
     file_handler: &mut H,
     on_demand_transport: bool,
     on_demand_transport_comment: Option<String>,
+    duplicated_trips: &DuplicatedTrips,
+    report: &mut Report<ReportCategory>,
 ) -> Result<()>
 where
     for<'a> &'a mut H: FileHandler,
@@ -395,7 +409,33 @@ where
     let mut tmp_vjs = BTreeMap::new();
     let stop_times = read_objects::<_, StopTime>(file_handler, file_name, true)?;
 
-    for stop_time in stop_times {
+    // the number of rows already read for each stop_sequence of the
+    // duplicated trips
+    let mut occurrences: HashMap<(String, u32), usize> = HashMap::new();
+    for mut stop_time in stop_times {
+        if let Some(occurrence_ids) = duplicated_trips.0.get(&stop_time.trip_id) {
+            let occurrence = occurrences
+                .entry((stop_time.trip_id.clone(), stop_time.stop_sequence))
+                .or_default();
+            let occurrence_id = occurrence_ids.get(*occurrence);
+            *occurrence += 1;
+            match occurrence_id {
+                Some(Some(trip_id)) => stop_time.trip_id = trip_id.clone(),
+                Some(None) => continue,
+                None => {
+                    let message = format!(
+                        "{}: stop time '{}' of the duplicated trip '{}' matches none of its {} occurrences, skipped",
+                        file_name,
+                        stop_time.stop_sequence,
+                        stop_time.trip_id,
+                        occurrence_ids.len()
+                    );
+                    warn!("{}", message);
+                    report.add_warning(message, ReportCategory::DuplicateId);
+                    continue;
+                }
+            }
+        }
         if let Some(vj_idx) = collections.vehicle_journeys.get_idx(&stop_time.trip_id) {
             tmp_vjs
                 .entry(vj_idx)
@@ -410,16 +450,47 @@ where
     }
 
     for (vj_idx, mut stop_times) in tmp_vjs {
-        stop_times.sort_unstable_by_key(|st| st.stop_sequence);
+        if stop_times
+            .windows(2)
+            .any(|pair| pair[0].stop_sequence > pair[1].stop_sequence)
+        {
+            report.add_info(
+                format!(
+                    "stop times of trip '{}' sorted by stop_sequence",
+                    collections.vehicle_journeys[vj_idx].id
+                ),
+                ReportCategory::UnsortedStopTimes,
+            );
+        }
+        // the sort is stable, so the first stop time of the file is kept when
+        // several ones have the same stop_sequence
+        stop_times.sort_by_key(|st| st.stop_sequence);
         stop_times.dedup_by(|st2, st1| {
-            let is_same_seq = st2.stop_sequence == st1.stop_sequence;
-            if is_same_seq {
+            if st2.stop_sequence != st1.stop_sequence {
+                return false;
+            }
+            if st2 == st1 {
+                report.add_info(
+                    format!(
+                        "remove duplicated stop time '{}' of trip '{}'",
+                        st2.stop_sequence, st2.trip_id
+                    ),
+                    ReportCategory::DuplicateStopTime,
+                );
+            } else {
                 warn!(
                     "remove duplicated stop_sequence '{}' of trip '{}'",
                     st2.stop_sequence, st2.trip_id
                 );
+                report.add_warning(
+                    format!(
+                        "remove stop time '{}' of trip '{}' at stop '{}', another stop time has the same stop_sequence",
+                        st2.stop_sequence, st2.trip_id, st2.stop_id
+                    ),
+                    ReportCategory::DuplicateStopSequence,
+                );
             }
-            is_same_seq
+            true
         });
         let st_values = interpolate_undefined_stop_times(
             &collections.vehicle_journeys[vj_idx].id,
@@ -754,11 +825,113 @@ fn get_equipment_id_and_populate_equipments(
     }
 }
 
+/// The identifiers given to the occurrences of each duplicated `trip_id` of
+/// `trips.txt`, in the order of the file (`None` for an occurrence not kept),
+/// used to assign the rows of `stop_times.txt` to these occurrences
+#[derive(Debug, Default)]
+pub struct DuplicatedTrips(BTreeMap<String, Vec<Option<String>>>);
+
+// Apply the `strategy` on the objects sharing the same identifier. Returns the
+// kept objects, for each renamed object its original identifier, and for each
+// duplicated identifier the identifiers of its occurrences.
+fn deduplicate_ids<T>(
+    objects: Vec<T>,
+    file: &str,
+    strategy: DuplicateIdStrategy,
+    report: &mut Report<ReportCategory>,
+    id: impl Fn(&mut T) -> &mut String,
+) -> (
+    Vec<T>,
+    BTreeMap<String, String>,
+    BTreeMap<String, Vec<Option<String>>>,
+) {
+    let mut renamed_ids = BTreeMap::new();
+    let mut occurrence_ids: BTreeMap<String, Vec<Option<String>>> = BTreeMap::new();
+    if strategy == DuplicateIdStrategy::Fail {
+        return (objects, renamed_ids, occurrence_ids);
+    }
+    let mut objects = objects;
+    let mut last_positions: HashMap<String, usize> = HashMap::new();
+    for (position, object) in objects.iter_mut().enumerate() {
+        last_positions.insert(id(object).clone(), position);
+    }
+    let mut used_ids: BTreeSet<String> = last_positions.keys().cloned().collect();
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    let mut kept_objects = Vec::with_capacity(objects.len());
+    for (position, mut object) in objects.into_iter().enumerate() {
+        let object_id = id(&mut object).clone();
+        let occurrence = occurrences.entry(object_id.clone()).or_default();
+        *occurrence += 1;
+        let message = match strategy {
+            DuplicateIdStrategy::KeepFirst if *occurrence > 1 => Some(format!(
+                "{}: duplicated identifier {:?}, only the first occurrence is kept",
+                file, object_id
+            )),
+            DuplicateIdStrategy::KeepLast if last_positions[&object_id] != position => {
+                Some(format!(
+                    "{}: duplicated identifier {:?}, only the last occurrence is kept",
+                    file, object_id
+                ))
+            }
+            DuplicateIdStrategy::Suffix if *occurrence > 1 => {
+                let mut rank = *occurrence - 1;
+                let mut new_id = format!("{}:{}", object_id, rank);
+                while used_ids.contains(&new_id) {
+                    rank += 1;
+                    new_id = format!("{}:{}", object_id, rank);
+                }
+                used_ids.insert(new_id.clone());
+                *id(&mut object) = new_id.clone();
+                let message = format!(
+                    "{}: duplicated identifier {:?}, occurrence renamed {:?}",
+                    file, object_id, new_id
+                );
+                renamed_ids.insert(new_id, object_id.clone());
+                warn!("{}", message);
+                report.add_warning(message, ReportCategory::DuplicateId);
+                None
+            }
+            _ => None,
+        };
+        match message {
+            Some(message) => {
+                warn!("{}", message);
+                report.add_warning(message, ReportCategory::DuplicateId);
+                occurrence_ids.entry(object_id).or_default().push(None);
+            }
+            None => {
+                let kept_id = id(&mut object).clone();
+                occurrence_ids
+                    .entry(object_id)
+                    .or_default()
+                    .push(Some(kept_id));
+                kept_objects.push(object);
+            }
+        }
+    }
+    occurrence_ids.retain(|_, occurrences| occurrences.len() > 1);
+    (kept_objects, renamed_ids, occurrence_ids)
+}
+
+// The `source` code of a renamed object references its original identifier
+fn restore_source_code(codes: &mut KeysValues, id: &str, renamed_ids: &BTreeMap<String, String>) {
+    if let Some(original_id) = renamed_ids.get(id) {
+        codes.remove(&("source".to_string(), id.to_string()));
+        codes.insert(("source".to_string(), original_id.clone()));
+    }
+}
+
 /// Reading stops where vehicles pick up or drop off riders. Also defines stations and station entrances.
+/// Stops sharing the same `stop_id` are handled according to the `duplicate_id_strategy`,
+/// the decisions being added to the `report`. The `Suffix` strategy fails on
+/// a duplicated `stop_id`, as the stop times, transfers and pathways
+/// referencing it could not be assigned to one of its occurrences.
 pub fn read_stops<H>(
     file_handler: &mut H,
     comments: &mut CollectionWithId<objects::Comment>,
     equipments: &mut EquipmentList,
+    duplicate_id_strategy: DuplicateIdStrategy,
+    report: &mut Report<ReportCategory>,
 ) -> Result<(
     CollectionWithId<objects::StopArea>,
     CollectionWithId<objects::StopPoint>,
@@ -770,6 +943,20 @@ where
     info!("Reading stops.txt");
     let file = "stops.txt";
     let gtfs_stops = read_objects::<_, Stop>(file_handler, file, true)?;
+    if duplicate_id_strategy == DuplicateIdStrategy::Suffix {
+        let mut stop_ids = HashSet::new();
+        if let Some(stop) = gtfs_stops.iter().find(|stop| !stop_ids.insert(&stop.id)) {
+            bail!(
+                "{}: duplicated identifier {:?} cannot be suffixed, the rows referencing it could not be assigned to its occurrences (use 'keep-first' or 'keep-last')",
+                file,
+                stop.id
+            );
+        }
+    }
+    let (gtfs_stops, renamed_ids, _) =
+        deduplicate_ids(gtfs_stops, file, duplicate_id_strategy, report, |stop| {
+            &mut stop.id
+        });
     let mut stop_areas = vec![];
     let mut stop_points = vec![];
     let mut stop_locations = vec![];
@@ -786,6 +973,7 @@ where
             StopLocationType::StopPoint => {
                 let mut stop_point =
                     skip_error_and_warn!(objects::StopPoint::try_from(stop.clone()));
+                restore_source_code(&mut stop_point.codes, &stop_point.id, &renamed_ids);
                 if stop.parent_station.is_none() {
                     let stop_area = objects::StopArea::from(stop_point.clone());
                     stop_point.stop_area_id = stop_area.id.clone();
@@ -797,12 +985,14 @@ where
             }
             StopLocationType::StopArea => {
                 let mut stop_area = skip_error_and_warn!(objects::StopArea::try_from(stop));
+                restore_source_code(&mut stop_area.codes, &stop_area.id, &renamed_ids);
                 stop_area.comment_links = comment_links;
                 stop_area.equipment_id = equipment_id;
                 stop_areas.push(stop_area);
             }
             _ => {
                 let mut stop_location = skip_error_and_warn!(objects::StopLocation::try_from(stop));
+                restore_source_code(&mut stop_location.codes, &stop_location.id, &renamed_ids);
                 stop_location.comment_links = comment_links;
                 stop_location.equipment_id = equipment_id;
                 stop_locations.push(stop_location);
@@ -928,6 +1118,8 @@ where
                     min_transfer_time,
                     real_min_transfer_time,
                     equipment_id: None,
+                    transfer_mode: None,
+                    ..Default::default()
                 });
             }
         }
@@ -1045,7 +1237,11 @@ fn make_lines(
     Ok(lines)
 }
 
-fn make_routes(gtfs_trips: &[Trip], map_line_routes: &MapLineRoutes<'_>) -> Vec<objects::Route> {
+fn make_routes(
+    gtfs_trips: &[Trip],
+    map_line_routes: &MapLineRoutes<'_>,
+    merge_directions: bool,
+) -> Vec<objects::Route> {
     let mut routes = vec![];
 
     let get_direction_name = |d: DirectionType| match d {
@@ -1062,11 +1258,30 @@ fn make_routes(gtfs_trips: &[Trip], map_line_routes: &MapLineRoutes<'_>) -> Vec<
             }
 
             let has_one_direction = route_directions.len() <= 1;
+            if merge_directions && !has_one_direction {
+                let mut codes = KeysValues::default();
+                codes.insert(("source".to_string(), r.id.clone()));
+                // the name and the direction of the route are generated later
+                // in `Collections::enhance_route_names()` and
+                // `Collections::enhance_route_directions()`
+                routes.push(objects::Route {
+                    id: r.id.clone(),
+                    name: String::new(),
+                    direction_type: None,
+                    codes,
+                    object_properties: PropertiesMap::default(),
+                    comment_links: CommentLinksT::default(),
+                    line_id: sr.id.clone(),
+                    geometry_id: None,
+                    destination_id: None,
+                });
+                continue;
+            }
             for d in route_directions {
                 let mut codes = KeysValues::default();
                 codes.insert(("source".to_string(), r.id.clone()));
                 routes.push(objects::Route {
-                    id: r.get_id_by_direction(d),
+                    id: r.get_id_by_direction(d, merge_directions),
                     // When only one direction, keep the route name. When
                     // multiple directions are possible, leave the `route_name`
                     // empty, it'll be auto-generated later in
@@ -1099,6 +1314,7 @@ fn make_ntfs_vehicle_journeys(
     routes: &CollectionWithId<Route>,
     datasets: &CollectionWithId<objects::Dataset>,
     networks: &CollectionWithId<objects::Network>,
+    merge_directions: bool,
 ) -> (Vec<objects::VehicleJourney>, Vec<objects::TripProperty>) {
     // there always is one dataset from config or a default one
     let (_, dataset) = datasets.iter().next().unwrap();
@@ -1137,7 +1353,9 @@ fn make_ntfs_vehicle_journeys(
         }
         trips
             .iter()
-            .map(|t| t.to_ntfs_vehicle_journey(routes, dataset, &property_id, networks))
+            .map(|t| {
+                t.to_ntfs_vehicle_journey(routes, dataset, &property_id, networks, merge_directions)
+            })
             .skip_error_and_warn()
             .for_each(|vj| vehicle_journeys.push(vj));
     }
@@ -1146,11 +1364,20 @@ fn make_ntfs_vehicle_journeys(
 }
 
 /// Reading transit routes. A route is a group of trips that are displayed to riders as a single service.
+/// Each GTFS route is split into one NTFS route per `direction_id` of its trips,
+/// unless `merge_directions` is true.
+/// Trips sharing the same `trip_id` are handled according to the `duplicate_id_strategy`,
+/// the decisions being added to the `report`. The returned `DuplicatedTrips`
+/// is used by `manage_stop_times` to assign the stop times of the duplicated
+/// trips to their occurrences.
 pub fn read_routes<H>(
     file_handler: &mut H,
     collections: &mut Collections,
     read_as_line: bool,
-) -> Result<()>
+    merge_directions: bool,
+    duplicate_id_strategy: DuplicateIdStrategy,
+    report: &mut Report<ReportCategory>,
+) -> Result<DuplicatedTrips>
 where
     for<'a> &'a mut H: FileHandler,
 {
@@ -1161,11 +1388,18 @@ where
     collections.physical_modes = CollectionWithId::new(physical_modes)?;
 
     let gtfs_trips = read_objects(file_handler, "trips.txt", true)?;
+    let (gtfs_trips, renamed_ids, occurrence_ids) = deduplicate_ids(
+        gtfs_trips,
+        "trips.txt",
+        duplicate_id_strategy,
+        report,
+        |trip: &mut Trip| &mut trip.id,
+    );
     let map_line_routes = map_line_routes(&gtfs_routes_collection, &gtfs_trips, read_as_line);
     let lines = make_lines(&map_line_routes, &collections.networks)?;
     collections.lines = CollectionWithId::new(lines)?;
 
-    let routes = make_routes(&gtfs_trips, &map_line_routes);
+    let routes = make_routes(&gtfs_trips, &map_line_routes, merge_directions);
     collections.routes = CollectionWithId::new(routes)?;
 
     gtfs_routes_collection.iter().for_each(|(_id, gtfs_route)| {
@@ -1186,15 +1420,88 @@ where
         };
     });
 
-    let (vehicle_journeys, trip_properties) = make_ntfs_vehicle_journeys(
+    let (mut vehicle_journeys, trip_properties) = make_ntfs_vehicle_journeys(
         &gtfs_trips,
         &gtfs_routes_collection,
         &collections.datasets,
         &collections.networks,
+        merge_directions,
     );
+    for vehicle_journey in &mut vehicle_journeys {
+        restore_source_code(
+            &mut vehicle_journey.codes,
+            &vehicle_journey.id,
+            &renamed_ids,
+        );
+    }
     collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
     collections.trip_properties = CollectionWithId::new(trip_properties)?;
 
+    Ok(DuplicatedTrips(occurrence_ids))
+}
+
+/// Move the lines matching the `network_rules` to the networks of the rules,
+/// created from the network of the agency of the first line moved when they
+/// don't exist. Each line moved is added to the `report`.
+pub fn apply_network_rules(
+    collections: &mut Collections,
+    network_rules: &[NetworkRule],
+    report: &mut Report<ReportCategory>,
+) -> Result<()> {
+    let patterns = network_rules
+        .iter()
+        .map(|rule| {
+            Regex::new(&format!("^(?:{})$", rule.route_code_pattern)).with_context(|| {
+                format!("invalid route code pattern {:?}", rule.route_code_pattern)
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for line_idx in collections.lines.indexes() {
+        let line = &collections.lines[line_idx];
+        let code = match &line.code {
+            Some(code) => code,
+            None => continue,
+        };
+        let rule = network_rules
+            .iter()
+            .zip(&patterns)
+            .find(|(rule, pattern)| {
+                rule.agency_id
+                    .as_ref()
+                    .map_or(true, |agency_id| *agency_id == line.network_id)
+                    && pattern.is_match(code)
+            })
+            .map(|(rule, _)| rule);
+        let rule = match rule {
+            Some(rule) if rule.network_id != line.network_id => rule,
+            _ => continue,
+        };
+        if !collections.networks.contains_id(&rule.network_id) {
+            let agency_network = collections
+                .networks
+                .get(&line.network_id)
+                .cloned()
+                .unwrap_or_default();
+            collections.networks.push(objects::Network {
+                id: rule.network_id.clone(),
+                name: rule.network_name.clone(),
+                codes: KeysValues::default(),
+                ..agency_network
+            })?;
+        }
+        report.add_entry(
+            Severity::Info,
+            ReportEntry::new(
+                ReportCategory::LineMovedToNetwork,
+                format!(
+                    "line {} of network {} moved to network {}",
+                    line.id, line.network_id, rule.network_id
+                ),
+            )
+            .with_object_id(line.id.clone()),
+        );
+        collections.lines.index_mut(line_idx).network_id = rule.network_id.clone();
+    }
     Ok(())
 }
 
@@ -1245,6 +1552,7 @@ mod tests {
         objects::*,
         objects::{Calendar, Comment, CommentType, Equipment, Geometry, Rgb, StopTime, Transfer},
         parser::read_opt_collection,
+        report::Severity,
         test_utils::*,
         AddPrefix, PrefixConfiguration,
     };
@@ -1357,8 +1665,14 @@ mod tests {
             let mut equipments = EquipmentList::default();
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
 
-            let (stop_areas, stop_points, stop_locations) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (stop_areas, stop_points, stop_locations) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(1, stop_areas.len());
             assert_eq!(1, stop_points.len());
             assert_eq!(0, stop_locations.len());
@@ -1391,8 +1705,14 @@ mod tests {
             let mut equipments = EquipmentList::default();
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             // let stop_file = File::open(path.join("stops.txt")).unwrap();
-            let (stop_areas, stop_points, stop_locations) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (stop_areas, stop_points, stop_locations) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections.stop_areas = stop_areas;
             collections.stop_points = stop_points;
             collections.stop_locations = stop_locations;
@@ -1426,8 +1746,14 @@ mod tests {
             create_file_with_content(path, "stops.txt", stops_content);
             let mut equipments = EquipmentList::default();
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
-            let (stop_areas, stop_points, stop_locations) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (stop_areas, stop_points, stop_locations) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             //validate stop_point code
             assert_eq!(1, stop_points.len());
             let stop_point = stop_points.iter().next().unwrap().1;
@@ -1467,8 +1793,14 @@ mod tests {
             create_file_with_content(path, "stops.txt", stops_content);
             let mut equipments = EquipmentList::default();
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
-            let (stop_areas, _, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (stop_areas, _, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             //validate stop_area code
             assert_eq!(1, stop_areas.len());
             let stop_area = stop_areas.iter().next().unwrap().1;
@@ -1476,6 +1808,255 @@ mod tests {
         });
     }
 
+    mod duplicate_ids {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn read_duplicated_stops(
+            strategy: DuplicateIdStrategy,
+        ) -> Result<(CollectionWithId<StopPoint>, Report<ReportCategory>)> {
+            let stops_content = "stop_id,stop_name,stop_lat,stop_lon\n\
+                                 sp1,first stop,0.1,1.2\n\
+                                 sp2,other stop,0.1,1.2\n\
+                                 sp1,second stop,0.1,1.2\n\
+                                 sp1,third stop,0.1,1.2";
+            let mut result = None;
+            test_in_tmp_dir(|path| {
+                let mut handler = PathFileHandler::new(path.to_path_buf());
+                create_file_with_content(path, "stops.txt", stops_content);
+                let mut equipments = EquipmentList::default();
+                let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+                let mut report = Report::default();
+                let stops = read_stops(
+                    &mut handler,
+                    &mut comments,
+                    &mut equipments,
+                    strategy,
+                    &mut report,
+                );
+                result = Some(stops.map(|(_, stop_points, _)| (stop_points, report)));
+            });
+            result.unwrap()
+        }
+
+        #[test]
+        fn fail() {
+            let error = read_duplicated_stops(DuplicateIdStrategy::Fail).unwrap_err();
+            assert_eq!("identifier sp1 already exists", error.to_string());
+        }
+
+        #[test]
+        fn keep_first() {
+            let (stop_points, report) =
+                read_duplicated_stops(DuplicateIdStrategy::KeepFirst).unwrap();
+            assert_eq!(vec!["sp1", "sp2"], extract_ids(&stop_points));
+            assert_eq!("first stop", stop_points.get("sp1").unwrap().name);
+            assert_eq!(2, report.warnings().len());
+        }
+
+        #[test]
+        fn keep_last() {
+            let (stop_points, report) =
+                read_duplicated_stops(DuplicateIdStrategy::KeepLast).unwrap();
+            assert_eq!(vec!["sp1", "sp2"], extract_ids(&stop_points));
+            assert_eq!("third stop", stop_points.get("sp1").unwrap().name);
+            assert_eq!(2, report.warnings().len());
+        }
+
+        #[test]
+        fn suffix_fails_for_stops() {
+            let error = read_duplicated_stops(DuplicateIdStrategy::Suffix).unwrap_err();
+            assert_eq!(
+                "stops.txt: duplicated identifier \"sp1\" cannot be suffixed, the rows referencing it could not be assigned to its occurrences (use 'keep-first' or 'keep-last')",
+                error.to_string()
+            );
+        }
+
+        #[test]
+        fn suffix_trips() {
+            let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type\n\
+                                  route_1,agency_1,1,My line 1,3";
+            let trips_content = "trip_id,route_id,service_id\n\
+                                 trip_1,route_1,service_1\n\
+                                 trip_1,route_1,service_2";
+            test_in_tmp_dir(|path| {
+                let mut handler = PathFileHandler::new(path.to_path_buf());
+                create_file_with_content(path, "routes.txt", routes_content);
+                create_file_with_content(path, "trips.txt", trips_content);
+                let mut collections = Collections::default();
+                let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+                collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+                collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+                let mut report = Report::default();
+                read_routes(
+                    &mut handler,
+                    &mut collections,
+                    false,
+                    false,
+                    DuplicateIdStrategy::Suffix,
+                    &mut report,
+                )
+                .unwrap();
+                assert_eq!(
+                    vec!["trip_1", "trip_1:1"],
+                    extract_ids(&collections.vehicle_journeys)
+                );
+                let vehicle_journey = collections.vehicle_journeys.get("trip_1:1").unwrap();
+                assert_eq!("service_2", vehicle_journey.service_id);
+                assert_eq!(1, vehicle_journey.codes.len());
+                assert!(vehicle_journey
+                    .codes
+                    .contains(&("source".to_string(), "trip_1".to_string())));
+                assert_eq!(1, report.warnings().len());
+            });
+        }
+
+        // the stop times of the duplicated trips are sorted by trip_id
+        const SORTED_STOP_TIMES: &str =
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+             trip_1,08:00:00,08:00:00,sp1,1\n\
+             trip_1,08:10:00,08:10:00,sp2,2\n\
+             trip_1,09:00:00,09:00:00,sp1,1\n\
+             trip_1,09:10:00,09:10:00,sp2,2";
+
+        fn read_duplicated_trips(
+            strategy: DuplicateIdStrategy,
+            stop_times_content: &str,
+        ) -> (Collections, Report<ReportCategory>) {
+            let mut result = None;
+            test_in_tmp_dir(|path| {
+                create_file_with_content(
+                    path,
+                    "agency.txt",
+                    "agency_id,agency_name,agency_url,agency_timezone\n\
+                     agency_1,Agency,http://example.com,Europe/Paris",
+                );
+                create_file_with_content(
+                    path,
+                    "stops.txt",
+                    "stop_id,stop_name,stop_lat,stop_lon\n\
+                     sp1,A,48.8,2.3\n\
+                     sp2,B,48.9,2.4",
+                );
+                create_file_with_content(
+                    path,
+                    "routes.txt",
+                    "route_id,agency_id,route_short_name,route_long_name,route_type\n\
+                     route_1,agency_1,1,Line 1,3",
+                );
+                create_file_with_content(
+                    path,
+                    "calendar.txt",
+                    "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\n\
+                     service_1,1,1,1,1,1,0,0,20200101,20201231\n\
+                     service_2,0,0,0,0,0,1,1,20200101,20201231",
+                );
+                create_file_with_content(
+                    path,
+                    "trips.txt",
+                    "trip_id,route_id,service_id\n\
+                     trip_1,route_1,service_1\n\
+                     trip_1,route_1,service_2",
+                );
+                create_file_with_content(path, "stop_times.txt", stop_times_content);
+                let configuration = crate::gtfs::Configuration {
+                    duplicate_id_strategy: strategy,
+                    ..Default::default()
+                };
+                result = Some(
+                    crate::gtfs::Reader::new(configuration)
+                        .parse_collections_with_report(path)
+                        .unwrap(),
+                );
+            });
+            result.unwrap()
+        }
+
+        fn departures(collections: &Collections, trip_id: &str) -> Vec<Time> {
+            collections
+                .vehicle_journeys
+                .get(trip_id)
+                .unwrap()
+                .stop_times
+                .iter()
+                .map(|stop_time| stop_time.departure_time)
+                .collect()
+        }
+
+        #[test]
+        fn suffix_trips_with_stop_times() {
+            let (collections, report) =
+                read_duplicated_trips(DuplicateIdStrategy::Suffix, SORTED_STOP_TIMES);
+            assert_eq!(
+                vec![Time::new(8, 0, 0), Time::new(8, 10, 0)],
+                departures(&collections, "trip_1")
+            );
+            assert_eq!(
+                vec![Time::new(9, 0, 0), Time::new(9, 10, 0)],
+                departures(&collections, "trip_1:1")
+            );
+            assert_eq!(
+                "service_2",
+                collections
+                    .vehicle_journeys
+                    .get("trip_1:1")
+                    .unwrap()
+                    .service_id
+            );
+            assert_eq!(
+                1,
+                report.count_category(Severity::Warning, &ReportCategory::DuplicateId)
+            );
+        }
+
+        #[test]
+        fn suffix_trips_with_unsorted_stop_times() {
+            let (collections, report) = read_duplicated_trips(
+                DuplicateIdStrategy::Suffix,
+                "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+                 trip_1,08:10:00,08:10:00,sp2,2\n\
+                 trip_1,08:00:00,08:00:00,sp1,1\n\
+                 trip_1,09:10:00,09:10:00,sp2,2\n\
+                 trip_1,09:00:00,09:00:00,sp1,1",
+            );
+            assert_eq!(
+                vec![Time::new(8, 0, 0), Time::new(8, 10, 0)],
+                departures(&collections, "trip_1")
+            );
+            assert_eq!(
+                vec![Time::new(9, 0, 0), Time::new(9, 10, 0)],
+                departures(&collections, "trip_1:1")
+            );
+            assert_eq!(
+                1,
+                report.count_category(Severity::Warning, &ReportCategory::DuplicateId)
+            );
+        }
+
+        #[test]
+        fn keep_last_trip_with_stop_times() {
+            let (collections, report) =
+                read_duplicated_trips(DuplicateIdStrategy::KeepLast, SORTED_STOP_TIMES);
+            assert_eq!(1, collections.vehicle_journeys.len());
+            assert_eq!(
+                vec![Time::new(9, 0, 0), Time::new(9, 10, 0)],
+                departures(&collections, "trip_1")
+            );
+            assert_eq!(
+                "service_2",
+                collections
+                    .vehicle_journeys
+                    .get("trip_1")
+                    .unwrap()
+                    .service_id
+            );
+            assert_eq!(
+                1,
+                report.count_category(Severity::Warning, &ReportCategory::DuplicateId)
+            );
+        }
+    }
+
     #[test]
     fn gtfs_routes_as_line() {
         let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
@@ -1500,7 +2081,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(4, collections.lines.len());
             assert_eq!(
                 vec!["agency_1", "agency_2", "agency_3", "agency_4"],
@@ -1537,6 +2126,122 @@ mod tests {
         });
     }
 
+    #[test]
+    fn gtfs_routes_with_merged_directions() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
+                              route_1,agency_1,1,My line 1,3,8F7A32,FFFFFF\n\
+                              route_2,agency_2,2,My line 2,3,7BC142,000000";
+
+        let trips_content =
+            "trip_id,route_id,direction_id,service_id,wheelchair_accessible,bikes_allowed\n\
+             1,route_1,0,service_1,,\n\
+             2,route_1,1,service_1,,\n\
+             3,route_2,1,service_2,,";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                true,
+                true,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
+
+            assert_eq!(vec!["route_1", "route_2"], extract_ids(&collections.routes));
+            assert_eq!(
+                vec!["route_1", "route_1", "route_2"],
+                extract(|vj| &vj.route_id, &collections.vehicle_journeys)
+            );
+        });
+    }
+
+    #[test]
+    fn gtfs_routes_moved_by_network_rules() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type\n\
+                              route_1,agency_1,1,My line 1,3\n\
+                              route_2,agency_1,N1,My night line 1,3\n\
+                              route_3,agency_2,N2,My line N2,3";
+
+        let trips_content = "trip_id,route_id,direction_id,service_id\n\
+                             1,route_1,0,service_1\n\
+                             2,route_2,0,service_1\n\
+                             3,route_3,0,service_1";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+            let mut collections = Collections::default();
+            let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
+            collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
+            collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
+            collections.networks = CollectionWithId::new(vec![Network {
+                id: "agency_1".to_string(),
+                name: "Agency 1".to_string(),
+                timezone: Some(chrono_tz::Europe::Paris),
+                ..Default::default()
+            }])
+            .unwrap();
+            let mut report = Report::default();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut report,
+            )
+            .unwrap();
+            let network_rules = vec![NetworkRule {
+                agency_id: Some("agency_1".to_string()),
+                route_code_pattern: "N.*".to_string(),
+                network_id: "agency_1_night".to_string(),
+                network_name: "Agency 1 night".to_string(),
+            }];
+            super::apply_network_rules(&mut collections, &network_rules, &mut report).unwrap();
+
+            assert_eq!(
+                vec!["agency_1", "agency_1_night", "agency_2"],
+                extract(|l| &l.network_id, &collections.lines)
+            );
+            let night_network = collections.networks.get("agency_1_night").unwrap();
+            assert_eq!("Agency 1 night", night_network.name);
+            assert_eq!(Some(chrono_tz::Europe::Paris), night_network.timezone);
+            let moved_lines: Vec<_> = report
+                .infos()
+                .iter()
+                .filter(|entry| entry.category == ReportCategory::LineMovedToNetwork)
+                .map(|entry| entry.object_id.as_deref())
+                .collect();
+            assert_eq!(vec![Some("route_2")], moved_lines);
+        });
+    }
+
+    #[test]
+    fn invalid_network_rule_pattern() {
+        let network_rules = vec![NetworkRule {
+            agency_id: None,
+            route_code_pattern: "N(".to_string(),
+            network_id: "night".to_string(),
+            network_name: "Night".to_string(),
+        }];
+        let result = apply_network_rules(
+            &mut Collections::default(),
+            &network_rules,
+            &mut Report::default(),
+        );
+        assert!(result.is_err());
+    }
+
     #[test]
     fn gtfs_routes_without_agency_id_as_line() {
         let agency_content = "agency_id,agency_name,agency_url,agency_timezone\n\
@@ -1569,7 +2274,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(3, collections.lines.len());
 
             assert_eq!(5, collections.routes.len());
@@ -1612,7 +2325,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(3, collections.lines.len());
             assert_eq!(
                 vec![
@@ -1684,7 +2405,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
         });
     }
 
@@ -1715,7 +2444,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
         });
     }
 
@@ -1750,7 +2487,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
 
             assert_eq!(3, collections.lines.len());
             assert_eq!(
@@ -1793,7 +2538,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
 
             assert_eq!(2, collections.lines.len());
 
@@ -1826,7 +2579,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
 
             assert_eq!(2, collections.lines.len());
             assert_eq!(vec!["route_1", "route_3"], extract_ids(&collections.lines));
@@ -1860,7 +2621,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(1, collections.lines.len());
             assert_eq!(1, collections.routes.len());
         });
@@ -1917,8 +2686,14 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            let (stop_areas, stop_points, stop_locations) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (stop_areas, stop_points, stop_locations) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections.equipments = CollectionWithId::new(equipments.into_equipments()).unwrap();
             collections.transfers =
                 super::read_transfers(&mut handler, &stop_points, &stop_areas).unwrap();
@@ -1930,7 +2705,15 @@ mod tests {
             collections.networks = networks;
             collections.companies = companies;
             collections.comments = comments;
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             super::manage_shapes(&mut collections, &mut handler).unwrap();
             calendars::manage_calendars(&mut handler, &mut collections).unwrap();
 
@@ -2097,7 +2880,15 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(3, collections.lines.len());
             assert_eq!(3, collections.routes.len());
             assert_eq!(3, collections.vehicle_journeys.len());
@@ -2138,7 +2929,15 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(3, collections.lines.len());
             assert_eq!(3, collections.routes.len());
             assert_eq!(3, collections.vehicle_journeys.len());
@@ -2171,7 +2970,15 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(3, collections.lines.len());
             assert_eq!(3, collections.routes.len());
 
@@ -2205,7 +3012,15 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(2, collections.vehicle_journeys.len());
             assert_eq!(0, collections.trip_properties.len());
             for vj in collections.vehicle_journeys.values() {
@@ -2251,8 +3066,14 @@ mod tests {
 
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
-            let (stop_areas, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (stop_areas, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             let equipments_collection =
                 CollectionWithId::new(equipments.into_equipments()).unwrap();
             assert_eq!(2, stop_areas.len());
@@ -2317,8 +3138,14 @@ mod tests {
 
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
-            let (_, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (_, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             let equipments_collection =
                 CollectionWithId::new(equipments.into_equipments()).unwrap();
             assert_eq!(2, stop_points.len());
@@ -2388,12 +3215,34 @@ mod tests {
 
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
-            let (_, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (_, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, false, None).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                &DuplicatedTrips::default(),
+                &mut Report::default(),
+            )
+            .unwrap();
 
             assert_eq!(
                 vec![
@@ -2480,12 +3329,39 @@ mod tests {
 
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
-            let (_, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (_, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, false, None).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
+            let mut report = Report::default();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                &DuplicatedTrips::default(),
+                &mut report,
+            )
+            .unwrap();
+            assert_eq!(
+                2,
+                report.count_category(Severity::Warning, &ReportCategory::DuplicateStopSequence)
+            );
 
             assert_eq!(
                 vec![
@@ -2531,6 +3407,99 @@ mod tests {
         });
     }
 
+    #[test]
+    fn gtfs_stop_times_unsorted_and_duplicated() {
+        let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type\n\
+                              route_1,agency_1,1,My line 1,3";
+
+        let stops_content = "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station\n\
+                             sp:01,my stop point name 1,0.1,1.1,0,\n\
+                             sp:02,my stop point name 2,0.2,1.2,0,\n\
+                             sp:03,my stop point name 3,0.3,1.3,0,";
+
+        let trips_content = "trip_id,route_id,direction_id,service_id\n\
+                             1,route_1,0,service_1";
+
+        // rows exported in any order, the stop time 2 being written twice
+        let stop_times_content = "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+                                  1,06:22:00,06:22:00,sp:03,3\n\
+                                  1,06:11:00,06:11:00,sp:02,2\n\
+                                  1,06:00:00,06:00:00,sp:01,1\n\
+                                  1,06:11:00,06:11:00,sp:02,2";
+
+        test_in_tmp_dir(|path| {
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            create_file_with_content(path, "routes.txt", routes_content);
+            create_file_with_content(path, "trips.txt", trips_content);
+            create_file_with_content(path, "stop_times.txt", stop_times_content);
+            create_file_with_content(path, "stops.txt", stops_content);
+
+            let mut collections = Collections::default();
+            let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
+            let mut equipments = EquipmentList::default();
+            let (_, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
+            collections.stop_points = stop_points;
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
+            let mut report = Report::default();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                &DuplicatedTrips::default(),
+                &mut report,
+            )
+            .unwrap();
+
+            let stop_points: Vec<_> = collections
+                .vehicle_journeys
+                .values()
+                .next()
+                .unwrap()
+                .stop_times
+                .iter()
+                .map(|stop_time| {
+                    (
+                        stop_time.sequence,
+                        &collections.stop_points[stop_time.stop_point_idx].id,
+                    )
+                })
+                .collect();
+            assert_eq!(
+                vec![
+                    (1, &"sp:01".to_string()),
+                    (2, &"sp:02".to_string()),
+                    (3, &"sp:03".to_string())
+                ],
+                stop_points
+            );
+            assert!(report.warnings().is_empty());
+            assert_eq!(
+                1,
+                report.count_category(Severity::Info, &ReportCategory::UnsortedStopTimes)
+            );
+            assert_eq!(
+                1,
+                report.count_category(Severity::Info, &ReportCategory::DuplicateStopTime)
+            );
+        });
+    }
+
     #[test]
     fn gtfs_stop_times() {
         let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
@@ -2563,12 +3532,34 @@ mod tests {
 
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
-            let (_, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (_, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, false, None).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                &DuplicatedTrips::default(),
+                &mut Report::default(),
+            )
+            .unwrap();
 
             assert_eq!(
                 vec![
@@ -2630,8 +3621,14 @@ mod tests {
 
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
-            let (stop_areas, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (stop_areas, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
 
             let transfers = super::read_transfers(&mut handler, &stop_points, &stop_areas).unwrap();
             assert_eq!(
@@ -2642,6 +3639,8 @@ mod tests {
                         min_transfer_time: Some(0),
                         real_min_transfer_time: Some(0),
                         equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
                     },
                     &Transfer {
                         from_stop_id: "sp:01".to_string(),
@@ -2649,6 +3648,8 @@ mod tests {
                         min_transfer_time: Some(160),
                         real_min_transfer_time: Some(280),
                         equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
                     },
                     &Transfer {
                         from_stop_id: "sp:01".to_string(),
@@ -2656,6 +3657,8 @@ mod tests {
                         min_transfer_time: Some(60),
                         real_min_transfer_time: Some(60),
                         equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
                     },
                     &Transfer {
                         from_stop_id: "sp:02".to_string(),
@@ -2663,6 +3666,8 @@ mod tests {
                         min_transfer_time: Some(160),
                         real_min_transfer_time: Some(280),
                         equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
                     },
                     &Transfer {
                         from_stop_id: "sp:02".to_string(),
@@ -2670,6 +3675,8 @@ mod tests {
                         min_transfer_time: Some(0),
                         real_min_transfer_time: Some(0),
                         equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
                     },
                     &Transfer {
                         from_stop_id: "sp:02".to_string(),
@@ -2677,6 +3684,8 @@ mod tests {
                         min_transfer_time: Some(86400),
                         real_min_transfer_time: Some(86400),
                         equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
                     },
                     &Transfer {
                         from_stop_id: "sp:03".to_string(),
@@ -2684,6 +3693,8 @@ mod tests {
                         min_transfer_time: Some(247),
                         real_min_transfer_time: Some(367),
                         equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
                     },
                     &Transfer {
                         from_stop_id: "sp:03".to_string(),
@@ -2691,6 +3702,8 @@ mod tests {
                         min_transfer_time: None,
                         real_min_transfer_time: None,
                         equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
                     },
                     &Transfer {
                         from_stop_id: "sp:03".to_string(),
@@ -2698,6 +3711,8 @@ mod tests {
                         min_transfer_time: Some(0),
                         real_min_transfer_time: Some(120),
                         equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
                     },
                 ],
                 transfers.values().collect::<Vec<_>>()
@@ -2878,7 +3893,15 @@ mod tests {
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             // physical mode file should contain only three modes
             // (5,7 => funicular; 2 => train; 6 => suspended cable car)
             assert_eq!(4, collections.lines.len());
@@ -2900,8 +3923,14 @@ mod tests {
             create_file_with_content(path, "stops.txt", stops_content);
             let mut equipments = EquipmentList::default();
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
-            let (stop_areas, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (stop_areas, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(1, stop_points.len());
             assert_eq!(1, stop_areas.len());
             let stop_area = stop_areas.iter().next().unwrap().1;
@@ -2923,8 +3952,14 @@ mod tests {
             create_file_with_content(path, "stops.txt", stops_content);
             let mut equipments = EquipmentList::default();
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
-            let (_, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (_, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             assert_eq!(3, stop_points.len());
             let longitudes: Vec<f64> = stop_points
                 .values()
@@ -2985,12 +4020,34 @@ mod tests {
 
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
-            let (_, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (_, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, false, None).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                &DuplicatedTrips::default(),
+                &mut Report::default(),
+            )
+            .unwrap();
 
             assert_eq!(
                 vec![
@@ -3012,6 +4069,43 @@ mod tests {
         });
     }
 
+    #[test]
+    fn gtfs_stop_times_without_first_arrival_and_last_departure() {
+        let stop_time = |sequence, arrival_time: Option<&str>, departure_time: Option<&str>| {
+            crate::gtfs::StopTime {
+                trip_id: "1".to_string(),
+                arrival_time: arrival_time.map(|time| time.parse().unwrap()),
+                departure_time: departure_time.map(|time| time.parse().unwrap()),
+                stop_id: format!("sp:0{}", sequence),
+                stop_sequence: sequence,
+                pickup_type: 0,
+                drop_off_type: 0,
+                local_zone_id: None,
+                stop_headsign: None,
+                timepoint: true,
+            }
+        };
+        let stop_times = vec![
+            stop_time(1, None, Some("06:00:00")),
+            stop_time(2, Some("07:00:00"), Some("07:01:00")),
+            stop_time(3, Some("08:00:00"), None),
+        ];
+
+        let values = super::interpolate_undefined_stop_times("1", &stop_times).unwrap();
+
+        assert_eq!(
+            vec![
+                (Time::new(6, 0, 0), Time::new(6, 0, 0)),
+                (Time::new(7, 0, 0), Time::new(7, 1, 0)),
+                (Time::new(8, 0, 0), Time::new(8, 0, 0)),
+            ],
+            values
+                .iter()
+                .map(|value| (value.arrival_time, value.departure_time))
+                .collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn gtfs_invalid_undefined_stop_times() {
         let routes_content = "route_id,agency_id,route_short_name,route_long_name,route_type,route_color,route_text_color\n\
@@ -3056,12 +4150,33 @@ mod tests {
 
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
-            let (_, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (_, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            let val = super::manage_stop_times(&mut collections, &mut handler, false, None);
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
+            let val = super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                false,
+                None,
+                &DuplicatedTrips::default(),
+                &mut Report::default(),
+            );
 
             // the first stop time of the vj has no departure/arrival, it's an error
             let err = val.unwrap_err();
@@ -3082,8 +4197,14 @@ mod tests {
             create_file_with_content(path, "stops.txt", stops_content);
             let mut equipments = EquipmentList::default();
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
-            let (_, _, stop_locations) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (_, _, stop_locations) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             let stop_entrance = stop_locations
                 .values()
                 .filter(|sl| sl.stop_type == StopType::StopEntrance);
@@ -3119,9 +4240,14 @@ mod tests {
             create_file_with_content(path, "pathways.txt", pathway_content);
             let mut collections = Collections::default();
             let mut equipments = EquipmentList::default();
-            let (_, stop_points, stop_locations) =
-                super::read_stops(&mut handler, &mut collections.comments, &mut equipments)
-                    .unwrap();
+            let (_, stop_points, stop_locations) = super::read_stops(
+                &mut handler,
+                &mut collections.comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections.stop_points = stop_points;
             collections.stop_locations = stop_locations;
 
@@ -3186,12 +4312,34 @@ mod tests {
 
             let mut comments: CollectionWithId<Comment> = CollectionWithId::default();
             let mut equipments = EquipmentList::default();
-            let (_, stop_points, _) =
-                super::read_stops(&mut handler, &mut comments, &mut equipments).unwrap();
+            let (_, stop_points, _) = super::read_stops(
+                &mut handler,
+                &mut comments,
+                &mut equipments,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections.stop_points = stop_points;
 
-            super::read_routes(&mut handler, &mut collections, false).unwrap();
-            super::manage_stop_times(&mut collections, &mut handler, true, None).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                false,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
+            super::manage_stop_times(
+                &mut collections,
+                &mut handler,
+                true,
+                None,
+                &DuplicatedTrips::default(),
+                &mut Report::default(),
+            )
+            .unwrap();
 
             assert_eq!(
                 vec![
@@ -3272,7 +4420,15 @@ mod tests {
             let (contributor, dataset, _) = read_config(None::<&str>).unwrap();
             collections.contributors = CollectionWithId::new(vec![contributor]).unwrap();
             collections.datasets = CollectionWithId::new(vec![dataset]).unwrap();
-            super::read_routes(&mut handler, &mut collections, read_as_line).unwrap();
+            super::read_routes(
+                &mut handler,
+                &mut collections,
+                read_as_line,
+                false,
+                DuplicateIdStrategy::Fail,
+                &mut Report::default(),
+            )
+            .unwrap();
             collections
         }
 
@@ -0,0 +1,236 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Split of a model into several GTFS, one per network or per physical mode,
+//! as required by the open data portals publishing a feed per operator.
+
+use super::{write_to_zip_with_configuration, write_with_configuration, WriteConfiguration};
+use crate::{model::Model, Result};
+use std::{path::Path, str::FromStr};
+use tracing::info;
+
+/// How a model is split into several GTFS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitBy {
+    /// One GTFS per network, with the trips of its lines
+    Network,
+    /// One GTFS per physical mode, with the trips of this mode
+    PhysicalMode,
+}
+
+impl FromStr for SplitBy {
+    type Err = anyhow::Error;
+
+    fn from_str(split_by: &str) -> Result<Self> {
+        match split_by {
+            "network" => Ok(SplitBy::Network),
+            "physical-mode" => Ok(SplitBy::PhysicalMode),
+            _ => Err(anyhow::anyhow!(
+                "unknown split {:?}, expected 'network' or 'physical-mode'",
+                split_by
+            )),
+        }
+    }
+}
+
+// Name of the file or directory of a part, the characters which are not
+// allowed in a file name being replaced
+fn file_name(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Split `model` into one model per network or per physical mode, by
+/// identifier of the network or of the physical mode.
+///
+/// Each part keeps the trips of its network or its physical mode, and the
+/// objects they use. A line with trips of several physical modes is in the
+/// part of each of them.
+pub fn split_model(model: &Model, split_by: SplitBy) -> Result<Vec<(String, Model)>> {
+    let ids: Vec<String> = match split_by {
+        SplitBy::Network => model.networks.values().map(|n| n.id.clone()).collect(),
+        SplitBy::PhysicalMode => model
+            .physical_modes
+            .values()
+            .map(|physical_mode| physical_mode.id.clone())
+            .collect(),
+    };
+    let mut parts = Vec::new();
+    for id in ids {
+        let mut collections = (**model).clone();
+        match split_by {
+            SplitBy::Network => {
+                let routes = &model.routes;
+                let lines = &model.lines;
+                collections.vehicle_journeys.retain(|vj| {
+                    routes
+                        .get(&vj.route_id)
+                        .and_then(|route| lines.get(&route.line_id))
+                        .map_or(false, |line| line.network_id == id)
+                });
+            }
+            SplitBy::PhysicalMode => collections
+                .vehicle_journeys
+                .retain(|vj| vj.physical_mode_id == id),
+        }
+        if collections.vehicle_journeys.is_empty() {
+            continue;
+        }
+        parts.push((id, Model::new(collections)?));
+    }
+    Ok(parts)
+}
+
+fn write_split<F>(model: &Model, split_by: SplitBy, mut write_part: F) -> Result<()>
+where
+    F: FnMut(Model, &str) -> Result<()>,
+{
+    let parts = split_model(model, split_by)?;
+    info!("Writing {} GTFS split by {:?}", parts.len(), split_by);
+    for (id, part) in parts {
+        write_part(part, &file_name(&id))?;
+    }
+    Ok(())
+}
+
+/// Exports `model` to one GTFS per network or per physical mode, each of them
+/// in the subdirectory of `path` named after the identifier of the network
+/// or of the physical mode, following the `config`.
+pub fn write_split_with_configuration<P: AsRef<Path>>(
+    model: &Model,
+    path: P,
+    split_by: SplitBy,
+    config: WriteConfiguration,
+) -> Result<()> {
+    let path = path.as_ref();
+    write_split(model, split_by, |part, name| {
+        write_with_configuration(part, path.join(name), config.clone())
+    })
+}
+
+/// Exports `model` to one GTFS per network or per physical mode, each of them
+/// in the ZIP archive of the directory `path` named after the identifier of
+/// the network or of the physical mode, following the `config`.
+pub fn write_split_to_zip_with_configuration<P: AsRef<Path>>(
+    model: &Model,
+    path: P,
+    split_by: SplitBy,
+    config: WriteConfiguration,
+) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path)?;
+    write_split(model, split_by, |part, name| {
+        write_to_zip_with_configuration(part, path.join(format!("{}.zip", name)), config.clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::Collections, test_fixtures::BuildModel};
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+    use typed_index_collection::{CollectionWithId, Id};
+
+    fn ids<T: Id<T>>(collection: &CollectionWithId<T>) -> Vec<&str> {
+        collection.values().map(|object| object.id()).collect()
+    }
+
+    fn two_routes() -> Collections {
+        ModelBuilder::default()
+            .route("r1", |route| {
+                route.line_id = "l1".to_string();
+            })
+            .route("r2", |route| {
+                route.line_id = "l2".to_string();
+            })
+            .vj("vj1", |vj| {
+                vj.route("r1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("r2")
+                    .st("B", "12:00:00", "12:01:00")
+                    .st("C", "13:00:00", "13:01:00");
+            })
+            .vj("vj3", |vj| {
+                vj.route("r2")
+                    .st("C", "14:00:00", "14:01:00")
+                    .st("D", "15:00:00", "15:01:00");
+            })
+            .build_model()
+            .into_collections()
+    }
+
+    #[test]
+    fn split_by_physical_mode() {
+        let mut collections = two_routes();
+        for (vj_id, physical_mode_id) in [("vj1", "Bus"), ("vj2", "Tramway"), ("vj3", "Bus")] {
+            collections.physical_modes.get_or_create(physical_mode_id);
+            collections
+                .vehicle_journeys
+                .get_mut(vj_id)
+                .unwrap()
+                .physical_mode_id = physical_mode_id.to_string();
+        }
+        let model = Model::new(collections).unwrap();
+        let parts = split_model(&model, SplitBy::PhysicalMode).unwrap();
+
+        let part_ids: Vec<_> = parts.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(vec!["Bus", "Tramway"], part_ids);
+        let bus = &parts[0].1;
+        assert_eq!(vec!["vj1", "vj3"], ids(&bus.vehicle_journeys));
+        assert_eq!(vec!["A", "B", "C", "D"], ids(&bus.stop_points));
+        let tramway = &parts[1].1;
+        assert_eq!(vec!["vj2"], ids(&tramway.vehicle_journeys));
+        assert_eq!(vec!["l2"], ids(&tramway.lines));
+        assert_eq!(vec!["B", "C"], ids(&tramway.stop_points));
+    }
+
+    #[test]
+    fn split_by_network() {
+        let mut collections = two_routes();
+        collections.networks.get_or_create("n2");
+        collections.lines.get_mut("l2").unwrap().network_id = "n2".to_string();
+        let model = Model::new(collections).unwrap();
+        let parts = split_model(&model, SplitBy::Network).unwrap();
+
+        assert_eq!(2, parts.len());
+        let (network_id, n2) = &parts[1];
+        assert_eq!("n2", network_id);
+        assert_eq!(vec!["vj2", "vj3"], ids(&n2.vehicle_journeys));
+        assert_eq!(vec!["l2"], ids(&n2.lines));
+        assert_eq!(vec!["n2"], ids(&n2.networks));
+    }
+
+    #[test]
+    fn parse_split_by() {
+        assert_eq!(SplitBy::Network, "network".parse().unwrap());
+        assert_eq!(SplitBy::PhysicalMode, "physical-mode".parse().unwrap());
+        assert!("mode".parse::<SplitBy>().is_err());
+    }
+
+    #[test]
+    fn escaped_file_name() {
+        assert_eq!("OIF_101-a", file_name("OIF:101-a"));
+    }
+}
@@ -13,35 +13,90 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
 use super::{
-    Agency, DirectionType, Route, RouteType, Shape, Stop, StopLocationType, StopTime, Transfer,
-    Trip,
+    Agency, DirectionType, Route, RouteNameStrategy, RouteType, RouteTypeOverrides, Shape, Stop,
+    StopLocationType, StopTime, Transfer, Trip, TripTransfer,
 };
+use crate::csv_format::CsvFormat;
+use crate::export::timetable::{day_types, DayType};
+use crate::file_handler::FileWriter;
 use crate::gtfs::ExtendedRoute;
 use crate::model::{GetCorresponding, Model};
 use crate::objects;
 use crate::objects::Transfer as NtfsTransfer;
 use crate::objects::*;
+use crate::utils::{sorted_by_id, write_collection_to};
 use crate::Result;
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use geo::Geometry as GeoGeometry;
 use relational_types::IdxSet;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path;
-use tracing::{info, warn};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tracing::{info, info_span, warn};
 use typed_index_collection::{Collection, CollectionWithId, Id, Idx};
 
-pub fn write_transfers(path: &path::Path, transfers: &Collection<NtfsTransfer>) -> Result<()> {
-    if transfers.is_empty() {
+/// Write the transfers and the interchanges into `transfers.txt`.
+///
+/// The transfers only possible on some days or at some times of the day are
+/// not written. The columns `from_trip_id` and `to_trip_id` are only written
+/// when there are interchanges: a guaranteed interchange is a timed transfer
+/// (`1`) between the two trips.
+pub fn write_transfers<H>(
+    file_writer: &mut H,
+    transfers: &Collection<NtfsTransfer>,
+    interchanges: &Collection<Interchange>,
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    if transfers.is_empty() && interchanges.is_empty() {
         return Ok(());
     }
+    let _span = info_span!(
+        "write_file",
+        file = "transfers.txt",
+        objects = transfers.len()
+    )
+    .entered();
     info!("Writing transfers.txt");
-    let path = path.join("transfers.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
-    for t in transfers.values() {
-        if t.from_stop_id != t.to_stop_id {
-            wtr.serialize(Transfer::from(t))
+    let (writer, path) = file_writer.create_file("transfers.txt")?;
+    let mut wtr = csv_format.writer(writer);
+    let time_dependent_transfers = transfers.values().filter(|t| t.is_time_dependent()).count();
+    if time_dependent_transfers > 0 {
+        warn!(
+            "{} time dependent transfers are not written, they are not supported by the GTFS",
+            time_dependent_transfers
+        );
+    }
+    let mut transfers: Vec<&NtfsTransfer> = transfers
+        .values()
+        .filter(|t| t.from_stop_id != t.to_stop_id && !t.is_time_dependent())
+        .collect();
+    transfers.sort_unstable_by(|left, right| {
+        (&left.from_stop_id, &left.to_stop_id).cmp(&(&right.from_stop_id, &right.to_stop_id))
+    });
+    let transfers = transfers.into_iter().map(Transfer::from);
+    let mut interchanges: Vec<&Interchange> = interchanges.values().collect();
+    fn interchange_key(interchange: &Interchange) -> (&str, &str, &str, &str) {
+        (
+            &interchange.from_vehicle_journey_id,
+            &interchange.from_stop_point_id,
+            &interchange.to_vehicle_journey_id,
+            &interchange.to_stop_point_id,
+        )
+    }
+    interchanges.sort_unstable_by(|left, right| interchange_key(left).cmp(&interchange_key(right)));
+    if interchanges.is_empty() {
+        for transfer in transfers {
+            wtr.serialize(transfer)
+                .with_context(|| format!("Error reading {:?}", path))?;
+        }
+    } else {
+        for transfer in transfers
+            .map(TripTransfer::from)
+            .chain(interchanges.into_iter().map(TripTransfer::from))
+        {
+            wtr.serialize(transfer)
                 .with_context(|| format!("Error reading {:?}", path))?;
         }
     }
@@ -52,15 +107,19 @@ pub fn write_transfers(path: &path::Path, transfers: &Collection<NtfsTransfer>)
     Ok(())
 }
 
-pub fn write_agencies(
-    path: &path::Path,
+pub fn write_agencies<H>(
+    file_writer: &mut H,
     networks: &CollectionWithId<objects::Network>,
-) -> Result<()> {
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    let _span = info_span!("write_file", file = "agency.txt", objects = networks.len()).entered();
     info!("Writing agency.txt");
-    let path = path.join("agency.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
-    for n in networks.values() {
+    let (writer, path) = file_writer.create_file("agency.txt")?;
+    let mut wtr = csv_format.writer(writer);
+    for n in sorted_by_id(networks) {
         wtr.serialize(Agency::from(n))
             .with_context(|| format!("Error reading {:?}", path))?;
     }
@@ -173,31 +232,40 @@ fn ntfs_stop_location_to_gtfs_stop(
     }
 }
 
-pub fn write_stops(
-    path: &path::Path,
+pub fn write_stops<H>(
+    file_writer: &mut H,
     stop_points: &CollectionWithId<objects::StopPoint>,
     stop_areas: &CollectionWithId<objects::StopArea>,
     stop_locations: &CollectionWithId<objects::StopLocation>,
     comments: &CollectionWithId<objects::Comment>,
     equipments: &CollectionWithId<objects::Equipment>,
-) -> Result<()> {
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
     let file = "stops.txt";
+    let _span = info_span!(
+        "write_file",
+        file,
+        objects = stop_points.len() + stop_areas.len() + stop_locations.len()
+    )
+    .entered();
     info!("Writing {}", file);
-    let path = path.join(file);
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let (writer, path) = file_writer.create_file(file)?;
+    let mut wtr = csv_format.writer(writer);
     info!("Writing {} from StopPoint", file);
-    for sp in stop_points.values() {
+    for sp in sorted_by_id(stop_points) {
         wtr.serialize(ntfs_stop_point_to_gtfs_stop(sp, comments, equipments))
             .with_context(|| format!("Error reading {:?}", path))?;
     }
     info!("Writing {} from StopArea", file);
-    for sa in stop_areas.values() {
+    for sa in sorted_by_id(stop_areas) {
         wtr.serialize(ntfs_stop_area_to_gtfs_stop(sa, comments, equipments))
             .with_context(|| format!("Error reading {:?}", path))?;
     }
     info!("Writing {} from StopLocation", file);
-    for sl in stop_locations.values() {
+    for sl in sorted_by_id(stop_locations) {
         wtr.serialize(ntfs_stop_location_to_gtfs_stop(sl, comments, equipments))
             .with_context(|| format!("Error reading {:?}", path))?;
     }
@@ -215,22 +283,38 @@ fn get_gtfs_direction_id_from_ntfs_route(route: &objects::Route) -> DirectionTyp
     }
 }
 
-fn make_gtfs_trip_from_ntfs_vj(vj: &objects::VehicleJourney, model: &Model) -> Trip {
+fn make_gtfs_trip_from_ntfs_vj(vj: &objects::VehicleJourney, model: &Model) -> Result<Trip> {
     let mut wheelchair_and_bike = (Availability::default(), Availability::default());
     if let Some(tp_id) = &vj.trip_property_id {
         if let Some(tp) = &model.trip_properties.get(tp_id) {
             wheelchair_and_bike = (tp.wheelchair_accessible, tp.bike_accepted);
         };
     }
-    let route = &model.routes.get(&vj.route_id).unwrap();
-    let line_idx = &model.lines.get_idx(&route.line_id).unwrap();
-    let route_id = &get_line_physical_modes(*line_idx, &model.physical_modes, model)
+    let route = model.routes.get(&vj.route_id).ok_or_else(|| {
+        anyhow!(
+            "route {} of vehicle journey {} not found",
+            vj.route_id,
+            vj.id
+        )
+    })?;
+    let line_idx = model
+        .lines
+        .get_idx(&route.line_id)
+        .ok_or_else(|| anyhow!("line {} of route {} not found", route.line_id, route.id))?;
+    let route_id = get_line_physical_modes(line_idx, &model.physical_modes, model)
         .into_iter()
         .find(|pmo| pmo.inner.id == vj.physical_mode_id)
         .map(|pm| get_gtfs_route_id_from_ntfs_line_id(&route.line_id, &pm))
-        .unwrap();
-
-    Trip {
+        .ok_or_else(|| {
+            anyhow!(
+                "physical mode {} of vehicle journey {} not found in line {}",
+                vj.physical_mode_id,
+                vj.id,
+                route.line_id
+            )
+        })?;
+
+    Ok(Trip {
         route_id: route_id.to_string(),
         service_id: vj.service_id.clone(),
         id: vj.id.clone(),
@@ -241,16 +325,24 @@ fn make_gtfs_trip_from_ntfs_vj(vj: &objects::VehicleJourney, model: &Model) -> T
         shape_id: vj.geometry_id.clone(),
         wheelchair_accessible: wheelchair_and_bike.0,
         bikes_allowed: wheelchair_and_bike.1,
-    }
+    })
 }
 
-pub fn write_trips(path: &path::Path, model: &Model) -> Result<()> {
+pub fn write_trips<H>(file_writer: &mut H, model: &Model, csv_format: &CsvFormat) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    let _span = info_span!(
+        "write_file",
+        file = "trips.txt",
+        objects = model.vehicle_journeys.len()
+    )
+    .entered();
     info!("Writing trips.txt");
-    let path = path.join("trips.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
-    for vj in model.vehicle_journeys.values() {
-        wtr.serialize(make_gtfs_trip_from_ntfs_vj(vj, model))
+    let (writer, path) = file_writer.create_file("trips.txt")?;
+    let mut wtr = csv_format.writer(writer);
+    for vj in sorted_by_id(&model.vehicle_journeys) {
+        wtr.serialize(make_gtfs_trip_from_ntfs_vj(vj, model)?)
             .with_context(|| format!("Error reading {:?}", path))?;
     }
 
@@ -275,8 +367,8 @@ fn stop_extensions_from_collection_with_id<T>(
 where
     T: Id<T> + Codes,
 {
-    collections
-        .values()
+    sorted_by_id(collections)
+        .into_iter()
         .flat_map(|obj| obj.codes().iter().map(move |c| (obj.id(), c)))
         .map(|(id, (name, code))| StopExtension {
             id: id.to_string(),
@@ -285,21 +377,25 @@ where
         })
 }
 
-pub fn write_stop_extensions(
-    path: &path::Path,
+pub fn write_stop_extensions<H>(
+    file_writer: &mut H,
     stop_points: &CollectionWithId<StopPoint>,
     stop_areas: &CollectionWithId<StopArea>,
-) -> Result<()> {
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
     let mut stop_extensions = Vec::new();
     stop_extensions.extend(stop_extensions_from_collection_with_id(stop_points));
     stop_extensions.extend(stop_extensions_from_collection_with_id(stop_areas));
     if stop_extensions.is_empty() {
         return Ok(());
     }
+    let _span = info_span!("write_file", file = "stop_extensions.txt").entered();
     info!("Writing stop_extensions.txt");
-    let path = path.join("stop_extensions.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let (writer, path) = file_writer.create_file("stop_extensions.txt")?;
+    let mut wtr = csv_format.writer(writer);
     for se in stop_extensions {
         wtr.serialize(se)
             .with_context(|| format!("Error reading {:?}", path))?;
@@ -309,6 +405,211 @@ pub fn write_stop_extensions(
 
     Ok(())
 }
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum CommentedObjectType {
+    Stop,
+    Route,
+    Trip,
+    StopTime,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+struct CommentLink {
+    object_type: CommentedObjectType,
+    object_id: String,
+    stop_sequence: Option<u32>,
+    comment_id: String,
+}
+
+fn comment_links_from_collection_with_id<'a, T>(
+    collection: &'a CollectionWithId<T>,
+    object_type: CommentedObjectType,
+) -> impl Iterator<Item = CommentLink> + 'a
+where
+    T: Id<T> + CommentLinks,
+{
+    collection.values().flat_map(move |obj| {
+        obj.comment_links()
+            .iter()
+            .map(move |comment_id| CommentLink {
+                object_type,
+                object_id: obj.id().to_string(),
+                stop_sequence: None,
+                comment_id: comment_id.clone(),
+            })
+    })
+}
+
+/// Write the comments and their links to the stops, the routes, the trips
+/// and the stop times into the `comments.txt` and `comment_links.txt`
+/// extension files, with the columns of the NTFS.
+///
+/// The comments of a line are linked to all the GTFS routes of the line. The
+/// NTFS routes having no equivalent in GTFS, their comments are not linked.
+pub fn write_comments<H>(file_writer: &mut H, model: &Model, csv_format: &CsvFormat) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    if model.comments.is_empty() {
+        return Ok(());
+    }
+    let mut comment_links: Vec<CommentLink> = Vec::new();
+    comment_links.extend(comment_links_from_collection_with_id(
+        &model.stop_points,
+        CommentedObjectType::Stop,
+    ));
+    comment_links.extend(comment_links_from_collection_with_id(
+        &model.stop_areas,
+        CommentedObjectType::Stop,
+    ));
+    comment_links.extend(comment_links_from_collection_with_id(
+        &model.stop_locations,
+        CommentedObjectType::Stop,
+    ));
+    for (line_idx, line) in model.lines.iter() {
+        for pm in get_line_physical_modes(line_idx, &model.physical_modes, model) {
+            let route_id = get_gtfs_route_id_from_ntfs_line_id(&line.id, &pm);
+            comment_links.extend(line.comment_links.iter().map(|comment_id| CommentLink {
+                object_type: CommentedObjectType::Route,
+                object_id: route_id.clone(),
+                stop_sequence: None,
+                comment_id: comment_id.clone(),
+            }));
+        }
+    }
+    comment_links.extend(comment_links_from_collection_with_id(
+        &model.vehicle_journeys,
+        CommentedObjectType::Trip,
+    ));
+    comment_links.extend(
+        model
+            .stop_time_comments
+            .iter()
+            .map(|((vj_id, sequence), comment_id)| CommentLink {
+                object_type: CommentedObjectType::StopTime,
+                object_id: vj_id.clone(),
+                stop_sequence: Some(*sequence),
+                comment_id: comment_id.clone(),
+            }),
+    );
+    comment_links.sort_unstable();
+
+    let _span = info_span!(
+        "write_file",
+        file = "comments.txt and comment_links.txt",
+        objects = model.comments.len()
+    )
+    .entered();
+    info!("Writing comments.txt and comment_links.txt");
+    {
+        let (writer, path) = file_writer.create_file("comments.txt")?;
+        let mut wtr = csv_format.writer(writer);
+        for comment in sorted_by_id(&model.comments) {
+            wtr.serialize(comment)
+                .with_context(|| format!("Error reading {:?}", path))?;
+        }
+        wtr.flush()
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+
+    let (writer, path) = file_writer.create_file("comment_links.txt")?;
+    let mut wtr = csv_format.writer(writer);
+    for comment_link in comment_links {
+        wtr.serialize(comment_link)
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", path))?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+struct LineOperatingHours {
+    route_id: String,
+    day_type: DayType,
+    first_departure_time: Time,
+    last_departure_time: Time,
+}
+
+/// Write the operating hours of the lines by day type (weekday, Saturday or
+/// Sunday) into the `line_operating_hours.txt` extension file.
+///
+/// The first and last departure times of a line on a day type are the
+/// earliest and the latest departures from the first stop of its trips
+/// running at least once on this day type. They are written for all the GTFS
+/// routes of the line.
+pub fn write_line_operating_hours<H>(
+    file_writer: &mut H,
+    model: &Model,
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    let day_types_by_calendar: HashMap<&str, BTreeSet<DayType>> = model
+        .calendars
+        .values()
+        .map(|calendar| (calendar.id.as_str(), day_types(calendar)))
+        .collect();
+    let mut departures: BTreeMap<(&str, DayType), (Time, Time)> = BTreeMap::new();
+    for vj in model.vehicle_journeys.values() {
+        let first_departure = match vj.stop_times.first() {
+            Some(stop_time) => stop_time.departure_time,
+            None => continue,
+        };
+        let line_id = match model.routes.get(&vj.route_id) {
+            Some(route) => route.line_id.as_str(),
+            None => continue,
+        };
+        let day_types = match day_types_by_calendar.get(vj.service_id.as_str()) {
+            Some(day_types) => day_types,
+            None => continue,
+        };
+        for day_type in day_types {
+            let (first, last) = departures
+                .entry((line_id, *day_type))
+                .or_insert((first_departure, first_departure));
+            *first = (*first).min(first_departure);
+            *last = (*last).max(first_departure);
+        }
+    }
+    if departures.is_empty() {
+        return Ok(());
+    }
+
+    let mut operating_hours = Vec::new();
+    for ((line_id, day_type), (first, last)) in departures {
+        let line_idx = match model.lines.get_idx(line_id) {
+            Some(line_idx) => line_idx,
+            None => continue,
+        };
+        for pm in get_line_physical_modes(line_idx, &model.physical_modes, model) {
+            operating_hours.push(LineOperatingHours {
+                route_id: get_gtfs_route_id_from_ntfs_line_id(line_id, &pm),
+                day_type,
+                first_departure_time: first,
+                last_departure_time: last,
+            });
+        }
+    }
+    operating_hours.sort_unstable();
+
+    let _span = info_span!("write_file", file = "line_operating_hours.txt").entered();
+    info!("Writing line_operating_hours.txt");
+    let (writer, path) = file_writer.create_file("line_operating_hours.txt")?;
+    let mut wtr = csv_format.writer(writer);
+    for line_operating_hours in operating_hours {
+        wtr.serialize(line_operating_hours)
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", path))?;
+
+    Ok(())
+}
+
 #[derive(Debug)]
 struct PhysicalModeWithOrder<'a> {
     inner: &'a objects::PhysicalMode,
@@ -403,14 +704,40 @@ fn make_gtfs_route_from_ntfs_line(line: &objects::Line, pm: &PhysicalModeWithOrd
     }
 }
 
-pub fn write_routes(path: &path::Path, model: &Model, extend_route_type: bool) -> Result<()> {
+pub fn write_routes<H>(
+    file_writer: &mut H,
+    model: &Model,
+    extend_route_type: bool,
+    route_type_overrides: &RouteTypeOverrides,
+    route_name_strategy: RouteNameStrategy,
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    let _span = info_span!("write_file", file = "routes.txt").entered();
     info!("Writing routes.txt");
-    let path = path.join("routes.txt");
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
-    for (from, l) in &model.lines {
+    for line_id in route_type_overrides.keys() {
+        if !model.lines.contains_id(line_id) {
+            warn!(
+                "line {} of the route type overrides doesn't exist, its override is ignored",
+                line_id
+            );
+        }
+    }
+    let (writer, path) = file_writer.create_file("routes.txt")?;
+    let mut wtr = csv_format.writer(writer);
+    let mut lines: Vec<_> = model.lines.iter().collect();
+    lines.sort_unstable_by(|(_, left), (_, right)| left.id.cmp(&right.id));
+    for (from, l) in lines {
         for pm in &get_line_physical_modes(from, &model.physical_modes, model) {
-            let route = make_gtfs_route_from_ntfs_line(l, pm);
+            let mut route = make_gtfs_route_from_ntfs_line(l, pm);
+            let (short_name, long_name) = route_name_strategy.route_names(l);
+            route.short_name = short_name;
+            route.long_name = long_name;
+            if let Some(route_type) = route_type_overrides.get(&l.id) {
+                route.route_type = RouteType::from_gtfs_code(*route_type);
+            }
             if extend_route_type {
                 wtr.serialize(ExtendedRoute::from(route))
                     .with_context(|| format!("Error reading {:?}", path))?;
@@ -427,34 +754,95 @@ pub fn write_routes(path: &path::Path, model: &Model, extend_route_type: bool) -
     Ok(())
 }
 
-pub fn write_stop_times(
-    path: &path::Path,
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct Frequency<'a> {
+    trip_id: &'a str,
+    start_time: Time,
+    end_time: Time,
+    headway_secs: u32,
+    exact_times: u8,
+}
+
+/// Write the frequencies of the model and the `exact_frequencies` detected
+/// from the trips running at a regular interval into `frequencies.txt`.
+///
+/// The column `exact_times` is only written when there are exact frequencies.
+pub fn write_frequencies<H>(
+    file_writer: &mut H,
+    frequencies: &Collection<objects::Frequency>,
+    exact_frequencies: &Collection<objects::Frequency>,
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    if exact_frequencies.is_empty() {
+        return write_collection_to(file_writer, "frequencies.txt", frequencies, csv_format);
+    }
+    let _span = info_span!("write_file", file = "frequencies.txt").entered();
+    info!("Writing frequencies.txt");
+    let (writer, path) = file_writer.create_file("frequencies.txt")?;
+    let mut wtr = csv_format.writer(writer);
+    let frequencies = frequencies
+        .values()
+        .map(|frequency| (frequency, 0))
+        .chain(exact_frequencies.values().map(|frequency| (frequency, 1)));
+    for (frequency, exact_times) in frequencies {
+        wtr.serialize(Frequency {
+            trip_id: &frequency.vehicle_journey_id,
+            start_time: frequency.start_time,
+            end_time: frequency.end_time,
+            headway_secs: frequency.headway_secs,
+            exact_times,
+        })
+        .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", path))?;
+    Ok(())
+}
+
+pub fn write_stop_times<H>(
+    file_writer: &mut H,
     vehicle_journeys: &CollectionWithId<VehicleJourney>,
     stop_points: &CollectionWithId<StopPoint>,
     stop_times_headsigns: &HashMap<(String, u32), String>,
-) -> Result<()> {
+    blank_redundant_times: bool,
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    let _span = info_span!("write_file", file = "stop_times.txt").entered();
     info!("Writing stop_times.txt");
-    let stop_times_path = path.join("stop_times.txt");
-    let mut st_wtr = csv::Writer::from_path(&stop_times_path)
-        .with_context(|| format!("Error reading {:?}", stop_times_path))?;
-    for (vj_idx, vj) in vehicle_journeys {
-        for st in &vj.stop_times {
+    let (writer, stop_times_path) = file_writer.create_file("stop_times.txt")?;
+    let mut st_wtr = csv_format.writer(writer);
+    for vj in sorted_by_id(vehicle_journeys) {
+        let last_position = vj.stop_times.len().saturating_sub(1);
+        for (position, st) in vj.stop_times.iter().enumerate() {
+            // the arrival at the first stop and the departure from the last
+            // stop are read as the other time when they are missing
+            let is_redundant = blank_redundant_times
+                && vj.stop_times.len() > 1
+                && st.arrival_time == st.departure_time;
             st_wtr
                 .serialize(StopTime {
                     stop_id: stop_points[st.stop_point_idx].id.clone(),
                     trip_id: vj.id.clone(),
                     stop_sequence: st.sequence,
-                    arrival_time: Some(st.arrival_time),
-                    departure_time: Some(st.departure_time),
+                    arrival_time: Some(st.arrival_time)
+                        .filter(|_| !(is_redundant && position == 0)),
+                    departure_time: Some(st.departure_time)
+                        .filter(|_| !(is_redundant && position == last_position)),
                     pickup_type: st.pickup_type,
                     drop_off_type: st.drop_off_type,
                     local_zone_id: st.local_zone_id,
                     stop_headsign: stop_times_headsigns
-                        .get(&(vehicle_journeys[vj_idx].id.clone(), st.sequence))
+                        .get(&(vj.id.clone(), st.sequence))
                         .cloned(),
                     timepoint: matches!(st.precision, None | Some(StopTimePrecision::Exact)),
                 })
-                .with_context(|| format!("Error reading {:?}", st_wtr))?;
+                .with_context(|| format!("Error reading {:?}", stop_times_path))?;
         }
     }
     st_wtr
@@ -483,19 +871,112 @@ fn ntfs_geometry_to_gtfs_shapes(g: &objects::Geometry) -> impl Iterator<Item = S
     })
 }
 
-pub fn write_shapes(
-    path: &path::Path,
+// Optional files of a GTFS, with their header
+const OPTIONAL_FILES: &[(&str, &[&str])] = &[
+    (
+        "calendar.txt",
+        &[
+            "service_id",
+            "monday",
+            "tuesday",
+            "wednesday",
+            "thursday",
+            "friday",
+            "saturday",
+            "sunday",
+            "start_date",
+            "end_date",
+        ],
+    ),
+    (
+        "calendar_dates.txt",
+        &["service_id", "date", "exception_type"],
+    ),
+    (
+        "frequencies.txt",
+        &["trip_id", "start_time", "end_time", "headway_secs"],
+    ),
+    (
+        "transfers.txt",
+        &[
+            "from_stop_id",
+            "to_stop_id",
+            "transfer_type",
+            "min_transfer_time",
+        ],
+    ),
+    (
+        "shapes.txt",
+        &[
+            "shape_id",
+            "shape_pt_lat",
+            "shape_pt_lon",
+            "shape_pt_sequence",
+        ],
+    ),
+    (
+        "pathways.txt",
+        &[
+            "pathway_id",
+            "from_stop_id",
+            "to_stop_id",
+            "pathway_mode",
+            "is_bidirectional",
+            "length",
+            "traversal_time",
+            "stair_count",
+            "max_slope",
+            "min_width",
+            "signposted_as",
+            "reversed_signposted_as",
+        ],
+    ),
+    ("levels.txt", &["level_id", "level_index", "level_name"]),
+];
+
+/// Write the header alone of the optional files that have not been written
+/// because they have no record
+pub fn write_empty_files_header<H>(
+    file_writer: &mut H,
+    written_files: &[&str],
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    for (file, header) in OPTIONAL_FILES {
+        if !written_files.contains(file) || (&mut *file_writer).contains_file(file) {
+            continue;
+        }
+        let _span = info_span!("write_file", file).entered();
+        info!("Writing empty {}", file);
+        let (writer, path) = (&mut *file_writer).create_file(file)?;
+        let mut wtr = csv_format.writer(writer);
+        wtr.write_record(header.iter())
+            .with_context(|| format!("Error reading {:?}", path))?;
+        wtr.flush()
+            .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    Ok(())
+}
+
+pub fn write_shapes<H>(
+    file_writer: &mut H,
     geometries: &CollectionWithId<objects::Geometry>,
-) -> Result<()> {
-    let shapes: Vec<_> = geometries
-        .values()
+    csv_format: &CsvFormat,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+{
+    let shapes: Vec<_> = sorted_by_id(geometries)
+        .into_iter()
         .flat_map(ntfs_geometry_to_gtfs_shapes)
         .collect();
     if !shapes.is_empty() {
+        let _span = info_span!("write_file", file = "shapes.txt", objects = shapes.len()).entered();
         info!("Writing shapes.txt");
-        let path = path.join("shapes.txt");
-        let mut wtr =
-            csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+        let (writer, path) = file_writer.create_file("shapes.txt")?;
+        let mut wtr = csv_format.writer(writer);
         wtr.flush()
             .with_context(|| format!("Error reading {:?}", path))?;
         for shape in shapes {
@@ -512,9 +993,11 @@ mod tests {
     use super::*;
     use crate::{
         calendars::write_calendar_dates,
+        file_handler::PathFileWriter,
         gtfs::{Route, RouteType, StopLocationType, Transfer, TransferType},
         model::Collections,
         objects::{Calendar, CommentLinksT, Coord, StopPoint, StopTime, Transfer as NtfsTransfer},
+        test_fixtures::{one_vehicle_journey, BuildModel},
     };
     use geo::{line_string, point};
     use pretty_assertions::assert_eq;
@@ -994,11 +1477,52 @@ mod tests {
             bikes_allowed: Availability::NotAvailable,
         };
         let model = Model::new(collections).unwrap();
-        assert_eq!(expected, make_gtfs_trip_from_ntfs_vj(&vj, &model));
+        assert_eq!(expected, make_gtfs_trip_from_ntfs_vj(&vj, &model).unwrap());
 
         expected.route_id = "OIF:002002002:BDEOIF829:Coach".to_string();
         expected.id = "OIF:87604986-1_11595-1:Coach".to_string();
-        assert_eq!(expected, make_gtfs_trip_from_ntfs_vj(&vj_coach, &model));
+        assert_eq!(
+            expected,
+            make_gtfs_trip_from_ntfs_vj(&vj_coach, &model).unwrap()
+        );
+    }
+
+    #[test]
+    fn ntfs_vehicle_journey_with_unknown_route_to_gtfs_trip() {
+        let model = one_vehicle_journey();
+        let vj = VehicleJourney {
+            id: "vj2".to_string(),
+            route_id: "unknown".to_string(),
+            ..model.vehicle_journeys.get("vj1").unwrap().clone()
+        };
+        let error = make_gtfs_trip_from_ntfs_vj(&vj, &model).unwrap_err();
+        assert_eq!(
+            "route unknown of vehicle journey vj2 not found",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn write_vehicle_journey_with_unknown_route() {
+        let mut model = one_vehicle_journey();
+        model
+            .collections_mut()
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .route_id = "unknown".to_string();
+        let tmp_dir = tempdir().expect("create temp dir");
+        let error = crate::gtfs::write(
+            model,
+            tmp_dir.path(),
+            false,
+            crate::gtfs::EmptyFileStrategy::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            "route unknown of vehicle journey vj1 not found",
+            error.to_string()
+        );
     }
 
     #[test]
@@ -1041,7 +1565,13 @@ mod tests {
             ..Default::default()
         });
         let tmp_dir = tempdir().expect("create temp dir");
-        write_stop_extensions(tmp_dir.path(), &stop_points, &stop_areas).unwrap();
+        write_stop_extensions(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &stop_points,
+            &stop_areas,
+            &CsvFormat::default(),
+        )
+        .unwrap();
         let output_file_path = tmp_dir.path().join("stop_extensions.txt");
         let mut output_file = File::open(output_file_path.clone())
             .unwrap_or_else(|_| panic!("file {:?} not found", output_file_path));
@@ -1064,7 +1594,13 @@ mod tests {
         let stop_areas = CollectionWithId::default();
         let stop_points = CollectionWithId::default();
         let tmp_dir = tempdir().expect("create temp dir");
-        write_stop_extensions(tmp_dir.path(), &stop_points, &stop_areas).unwrap();
+        write_stop_extensions(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &stop_points,
+            &stop_areas,
+            &CsvFormat::default(),
+        )
+        .unwrap();
         let output_file_path = tmp_dir.path().join("stop_extensions.txt");
         assert!(!output_file_path.exists());
         tmp_dir.close().expect("delete temp dir");
@@ -1108,6 +1644,61 @@ mod tests {
         assert!(ntfs_geometry_to_gtfs_shapes(&geo).next().is_none());
     }
 
+    #[test]
+    fn empty_files_header() {
+        let tmp_dir = tempdir().expect("create temp dir failed");
+        let levels_path = tmp_dir.path().join("levels.txt");
+        std::fs::write(&levels_path, "level_id,level_index,level_name\nL1,0,\n").unwrap();
+        let optional_files: Vec<_> = OPTIONAL_FILES.iter().map(|(file, _)| *file).collect();
+        write_empty_files_header(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &optional_files,
+            &CsvFormat::default(),
+        )
+        .unwrap();
+
+        let read = |file: &str| std::fs::read_to_string(tmp_dir.path().join(file)).unwrap();
+        assert_eq!(
+            "trip_id,start_time,end_time,headway_secs\n",
+            read("frequencies.txt")
+        );
+        assert_eq!(
+            "from_stop_id,to_stop_id,transfer_type,min_transfer_time\n",
+            read("transfers.txt")
+        );
+        assert_eq!(
+            "level_id,level_index,level_name\nL1,0,\n",
+            read("levels.txt")
+        );
+        tmp_dir.close().expect("delete temp dir");
+    }
+
+    #[test]
+    fn write_exact_frequencies() {
+        let tmp_dir = tempdir().expect("create temp dir failed");
+        let frequency = |vehicle_journey_id: &str| objects::Frequency {
+            vehicle_journey_id: vehicle_journey_id.to_string(),
+            start_time: Time::new(8, 0, 0),
+            end_time: Time::new(9, 0, 0),
+            headway_secs: 600,
+        };
+        write_frequencies(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &Collection::new(vec![frequency("vj1")]),
+            &Collection::new(vec![frequency("vj2")]),
+            &CsvFormat::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            "trip_id,start_time,end_time,headway_secs,exact_times\n\
+             vj1,08:00:00,09:00:00,600,0\n\
+             vj2,08:00:00,09:00:00,600,1\n",
+            std::fs::read_to_string(tmp_dir.path().join("frequencies.txt")).unwrap()
+        );
+        tmp_dir.close().expect("delete temp dir");
+    }
+
     #[test]
     fn ntfs_transfers_to_gtfs_transfers() {
         let transfer = Transfer::from(&NtfsTransfer {
@@ -1116,6 +1707,8 @@ mod tests {
             min_transfer_time: Some(42),
             real_min_transfer_time: None,
             equipment_id: None,
+            transfer_mode: None,
+            ..Default::default()
         });
 
         let expected = Transfer {
@@ -1147,7 +1740,7 @@ mod tests {
         ])
         .unwrap();
         let tmp_dir = tempdir().expect("create temp dir");
-        write_calendar_dates(tmp_dir.path(), &calendar).unwrap();
+        write_calendar_dates(tmp_dir.path(), &calendar, &CsvFormat::default()).unwrap();
         assert!(!tmp_dir.path().join("calendar_dates.txt").exists());
 
         let output_file_path = tmp_dir.path().join("calendar.txt");
@@ -1226,10 +1819,12 @@ mod tests {
         stop_times_headsigns.insert(("vj:01".to_string(), 1), "somewhere".to_string());
         let tmp_dir = tempdir().expect("create temp dir");
         write_stop_times(
-            tmp_dir.path(),
+            &mut PathFileWriter::new(tmp_dir.path()),
             &vehicle_journeys,
             &stop_points,
             &stop_times_headsigns,
+            false,
+            &CsvFormat::default(),
         )
         .unwrap();
         let output_file_path = tmp_dir.path().join("stop_times.txt");
@@ -1243,6 +1838,23 @@ mod tests {
             vj:01,06:06:27,06:06:27,sp:01,2,2,1,3,,0\n",
             output_contents
         );
+
+        write_stop_times(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &vehicle_journeys,
+            &stop_points,
+            &stop_times_headsigns,
+            true,
+            &CsvFormat::default(),
+        )
+        .unwrap();
+        let output_contents = std::fs::read_to_string(&output_file_path).unwrap();
+        assert_eq!(
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence,pickup_type,drop_off_type,local_zone_id,stop_headsign,timepoint\n\
+            vj:01,,06:00:00,sp:01,1,0,0,,somewhere,1\n\
+            vj:01,06:06:27,,sp:01,2,2,1,3,,0\n",
+            output_contents
+        );
         tmp_dir.close().expect("delete temp dir");
     }
 
@@ -1265,6 +1877,199 @@ mod tests {
         assert_eq!(RouteType::UnknownMode, route_type);
     }
 
+    #[test]
+    fn write_routes_with_route_type_overrides() {
+        let model = transit_model_builder::ModelBuilder::default()
+            .route("r1", |r| {
+                r.line_id = "l1".to_string();
+            })
+            .route("r2", |r| {
+                r.line_id = "l2".to_string();
+            })
+            .vj("vj1", |vj| {
+                vj.route("r1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("r2")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .build_model();
+        let mut overrides = RouteTypeOverrides::new();
+        overrides.insert("l2".to_string(), 0);
+        overrides.insert("unknown".to_string(), 2);
+        crate::test_utils::test_in_tmp_dir(|path| {
+            write_routes(
+                &mut PathFileWriter::new(path),
+                &model,
+                false,
+                &overrides,
+                RouteNameStrategy::default(),
+                &CsvFormat::default(),
+            )
+            .unwrap();
+            let mut rdr = csv::Reader::from_path(path.join("routes.txt")).unwrap();
+            let route_types: Vec<(String, RouteType)> = rdr
+                .deserialize()
+                .map(|route: std::result::Result<Route, _>| {
+                    let route = route.unwrap();
+                    (route.id, route.route_type)
+                })
+                .collect();
+            assert_eq!(
+                vec![
+                    ("l1".to_string(), RouteType::Bus),
+                    ("l2".to_string(), RouteType::Tramway),
+                ],
+                route_types
+            );
+        });
+    }
+
+    #[test]
+    fn write_comments_to_extension_files() {
+        let mut collections = transit_model_builder::ModelBuilder::default()
+            .route("r1", |r| {
+                r.line_id = "l1".to_string();
+            })
+            .vj("vj1", |vj| {
+                vj.route("r1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections();
+        collections.comments = CollectionWithId::new(vec![
+            Comment {
+                id: "c1".to_string(),
+                comment_type: CommentType::Information,
+                label: Some("Info".to_string()),
+                name: "Closed on sundays".to_string(),
+                url: Some("http://www.foo.bar".to_string()),
+            },
+            Comment {
+                id: "c2".to_string(),
+                comment_type: CommentType::OnDemandTransport,
+                label: None,
+                name: "Call 0123456789".to_string(),
+                url: None,
+            },
+        ])
+        .unwrap();
+        collections
+            .stop_points
+            .get_mut("A")
+            .unwrap()
+            .comment_links
+            .insert("c1".to_string());
+        collections
+            .lines
+            .get_mut("l1")
+            .unwrap()
+            .comment_links
+            .insert("c2".to_string());
+        collections
+            .routes
+            .get_mut("r1")
+            .unwrap()
+            .comment_links
+            .insert("c1".to_string());
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .comment_links
+            .insert("c1".to_string());
+        collections
+            .stop_time_comments
+            .insert(("vj1".to_string(), 1), "c2".to_string());
+        let model = Model::new(collections).unwrap();
+
+        let tmp_dir = tempdir().expect("create temp dir");
+        write_comments(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &model,
+            &CsvFormat::default(),
+        )
+        .unwrap();
+        let mut output_contents = String::new();
+        File::open(tmp_dir.path().join("comments.txt"))
+            .unwrap()
+            .read_to_string(&mut output_contents)
+            .unwrap();
+        assert_eq!(
+            "comment_id,comment_type,comment_label,comment_name,comment_url\n\
+             c1,information,Info,Closed on sundays,http://www.foo.bar\n\
+             c2,on_demand_transport,,Call 0123456789,\n",
+            output_contents
+        );
+        output_contents.clear();
+        File::open(tmp_dir.path().join("comment_links.txt"))
+            .unwrap()
+            .read_to_string(&mut output_contents)
+            .unwrap();
+        assert_eq!(
+            "object_type,object_id,stop_sequence,comment_id\n\
+             stop,A,,c1\n\
+             route,l1,,c2\n\
+             trip,vj1,,c1\n\
+             stop_time,vj1,1,c2\n",
+            output_contents
+        );
+        tmp_dir.close().expect("delete temp dir");
+    }
+
+    #[test]
+    fn write_line_operating_hours_by_day_type() {
+        let model = transit_model_builder::ModelBuilder::default()
+            // a Friday and a Saturday
+            .calendar("c1", &["2020-01-03", "2020-01-04"])
+            .calendar("c2", &["2020-01-04"])
+            .route("r1", |r| {
+                r.line_id = "l1".to_string();
+            })
+            .vj("vj1", |vj| {
+                vj.route("r1")
+                    .calendar("c1")
+                    .st("A", "06:00:00", "06:01:00")
+                    .st("B", "07:00:00", "07:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("r1")
+                    .calendar("c1")
+                    .st("A", "22:00:00", "22:01:00")
+                    .st("B", "23:00:00", "23:01:00");
+            })
+            .vj("vj3", |vj| {
+                vj.route("r1")
+                    .calendar("c2")
+                    .st("A", "24:30:00", "24:31:00")
+                    .st("B", "25:00:00", "25:01:00");
+            })
+            .build_model();
+        let tmp_dir = tempdir().expect("create temp dir");
+        write_line_operating_hours(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &model,
+            &CsvFormat::default(),
+        )
+        .unwrap();
+        let mut output_contents = String::new();
+        File::open(tmp_dir.path().join("line_operating_hours.txt"))
+            .unwrap()
+            .read_to_string(&mut output_contents)
+            .unwrap();
+        assert_eq!(
+            "route_id,day_type,first_departure_time,last_departure_time\n\
+             l1,weekday,06:01:00,22:01:00\n\
+             l1,saturday,06:01:00,24:31:00\n",
+            output_contents
+        );
+        tmp_dir.close().expect("delete temp dir");
+    }
+
     #[test]
     fn ntfs_minial_line_to_gtfs_route() {
         let pm = PhysicalModeWithOrder {
@@ -1384,6 +2189,8 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: None,
+                transfer_mode: None,
+                ..Default::default()
             },
             NtfsTransfer {
                 from_stop_id: String::from("101938"),
@@ -1391,6 +2198,8 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: None,
+                transfer_mode: None,
+                ..Default::default()
             },
             NtfsTransfer {
                 from_stop_id: String::from("101937"),
@@ -1398,6 +2207,8 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: None,
+                transfer_mode: None,
+                ..Default::default()
             },
             NtfsTransfer {
                 from_stop_id: String::from("101938"),
@@ -1405,10 +2216,18 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: None,
+                transfer_mode: None,
+                ..Default::default()
             },
         ]);
 
-        write_transfers(tmp_dir.path(), &transfers).unwrap();
+        write_transfers(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &transfers,
+            &Collection::default(),
+            &CsvFormat::default(),
+        )
+        .unwrap();
         let output_file_path = tmp_dir.path().join("transfers.txt");
         let mut output_file = File::open(output_file_path.clone())
             .unwrap_or_else(|_| panic!("file {:?} not found", output_file_path));
@@ -1422,4 +2241,91 @@ mod tests {
         );
         tmp_dir.close().expect("delete temp dir");
     }
+
+    #[test]
+    fn time_dependent_transfers_not_written() {
+        let tmp_dir = tempdir().expect("create temp dir");
+        let transfer = |to_stop_id: &str, days: Option<DaysOfWeek>| NtfsTransfer {
+            from_stop_id: String::from("101937"),
+            to_stop_id: String::from(to_stop_id),
+            min_transfer_time: None,
+            real_min_transfer_time: None,
+            equipment_id: None,
+            transfer_mode: None,
+            start_time: None,
+            end_time: None,
+            days,
+        };
+        let transfers = Collection::new(vec![
+            transfer("101938", None),
+            transfer("101939", Some("1111100".parse().unwrap())),
+        ]);
+
+        write_transfers(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &transfers,
+            &Collection::default(),
+            &CsvFormat::default(),
+        )
+        .unwrap();
+        let output_contents =
+            std::fs::read_to_string(tmp_dir.path().join("transfers.txt")).unwrap();
+        assert_eq!(
+            "from_stop_id,to_stop_id,transfer_type,min_transfer_time\n\
+            101937,101938,2,\n",
+            output_contents
+        );
+        tmp_dir.close().expect("delete temp dir");
+    }
+
+    #[test]
+    fn ntfs_interchanges_to_trip_transfers() {
+        let tmp_dir = tempdir().expect("create temp dir");
+
+        let transfers = Collection::new(vec![NtfsTransfer {
+            from_stop_id: String::from("101937"),
+            to_stop_id: String::from("101938"),
+            min_transfer_time: Some(120),
+            real_min_transfer_time: None,
+            equipment_id: None,
+            transfer_mode: None,
+            ..Default::default()
+        }]);
+        let interchanges = Collection::new(vec![
+            Interchange {
+                from_vehicle_journey_id: String::from("vj1"),
+                from_stop_point_id: String::from("101937"),
+                to_vehicle_journey_id: String::from("vj2"),
+                to_stop_point_id: String::from("101938"),
+                min_transfer_time: Some(180),
+                guaranteed: true,
+            },
+            Interchange {
+                from_vehicle_journey_id: String::from("vj2"),
+                from_stop_point_id: String::from("101938"),
+                to_vehicle_journey_id: String::from("vj3"),
+                to_stop_point_id: String::from("101938"),
+                min_transfer_time: None,
+                guaranteed: false,
+            },
+        ]);
+
+        write_transfers(
+            &mut PathFileWriter::new(tmp_dir.path()),
+            &transfers,
+            &interchanges,
+            &CsvFormat::default(),
+        )
+        .unwrap();
+        let output_contents =
+            std::fs::read_to_string(tmp_dir.path().join("transfers.txt")).unwrap();
+        assert_eq!(
+            "from_stop_id,to_stop_id,from_trip_id,to_trip_id,transfer_type,min_transfer_time\n\
+            101937,101938,,,2,120\n\
+            101937,101938,vj1,vj2,1,180\n\
+            101938,101938,vj2,vj3,0,\n",
+            output_contents
+        );
+        tmp_dir.close().expect("delete temp dir");
+    }
 }
@@ -0,0 +1,359 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Holidays used to interpret the operating profiles of sources like
+//! TransXChange or HAFAS, which describe the service with days of the week
+//! and references to "bank holidays" instead of exact dates.
+//!
+//! The holidays are either computed from built-in tables (see
+//! [HolidayCalendar::builtin]) or read from a CSV file with the columns `date`
+//! (`YYYYMMDD`) and `name` (see [HolidayCalendar::from_csv]).
+
+use crate::{
+    calendars::dates_between,
+    objects::{Calendar, Date},
+    serde_utils::de_from_date_string,
+    Result,
+};
+use anyhow::{bail, Context};
+use chrono::{Datelike, Duration, Weekday};
+use derivative::Derivative;
+use serde::Deserialize;
+use std::{collections::BTreeMap, ops::RangeInclusive, path::Path};
+use tracing::info;
+
+/// Countries with built-in holidays, as ISO 3166-1 alpha-2 codes
+pub const BUILTIN_COUNTRIES: &[&str] = &["DE", "FR", "GB"];
+
+// Only called with valid dates
+fn ymd(year: i32, month: u32, day: u32) -> Date {
+    Date::from_ymd_opt(year, month, day).unwrap()
+}
+
+// Easter Sunday in the Gregorian calendar (anonymous Gregorian algorithm)
+fn easter_sunday(year: i32) -> Date {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    ymd(year, month as u32, day as u32)
+}
+
+// First `weekday` of the month
+fn first_weekday(year: i32, month: u32, weekday: Weekday) -> Date {
+    let mut date = ymd(year, month, 1);
+    while date.weekday() != weekday {
+        date += Duration::days(1);
+    }
+    date
+}
+
+// Last `weekday` of the month
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> Date {
+    let mut date = first_weekday(year, month, weekday) + Duration::days(28);
+    while date.month() != month {
+        date -= Duration::days(7);
+    }
+    date
+}
+
+fn is_weekend(date: Date) -> bool {
+    matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+fn france(year: i32) -> Vec<(Date, &'static str)> {
+    let easter = easter_sunday(year);
+    vec![
+        (ymd(year, 1, 1), "Jour de l'an"),
+        (easter + Duration::days(1), "Lundi de Pâques"),
+        (ymd(year, 5, 1), "Fête du travail"),
+        (ymd(year, 5, 8), "Victoire 1945"),
+        (easter + Duration::days(39), "Ascension"),
+        (easter + Duration::days(50), "Lundi de Pentecôte"),
+        (ymd(year, 7, 14), "Fête nationale"),
+        (ymd(year, 8, 15), "Assomption"),
+        (ymd(year, 11, 1), "Toussaint"),
+        (ymd(year, 11, 11), "Armistice 1918"),
+        (ymd(year, 12, 25), "Noël"),
+    ]
+}
+
+fn germany(year: i32) -> Vec<(Date, &'static str)> {
+    let easter = easter_sunday(year);
+    vec![
+        (ymd(year, 1, 1), "Neujahr"),
+        (easter - Duration::days(2), "Karfreitag"),
+        (easter + Duration::days(1), "Ostermontag"),
+        (ymd(year, 5, 1), "Tag der Arbeit"),
+        (easter + Duration::days(39), "Christi Himmelfahrt"),
+        (easter + Duration::days(50), "Pfingstmontag"),
+        (ymd(year, 10, 3), "Tag der Deutschen Einheit"),
+        (ymd(year, 12, 25), "Erster Weihnachtstag"),
+        (ymd(year, 12, 26), "Zweiter Weihnachtstag"),
+    ]
+}
+
+// Bank holidays of England and Wales, the holidays falling on a weekend being
+// substituted by the next available week day
+fn great_britain(year: i32) -> Vec<(Date, &'static str)> {
+    let easter = easter_sunday(year);
+    let mut holidays = vec![
+        (easter - Duration::days(2), "Good Friday"),
+        (easter + Duration::days(1), "Easter Monday"),
+        (
+            first_weekday(year, 5, Weekday::Mon),
+            "Early May bank holiday",
+        ),
+        (last_weekday(year, 5, Weekday::Mon), "Spring bank holiday"),
+        (last_weekday(year, 8, Weekday::Mon), "Summer bank holiday"),
+    ];
+    let fixed_holidays = [
+        (ymd(year, 1, 1), "New Year's Day"),
+        (ymd(year, 12, 25), "Christmas Day"),
+        (ymd(year, 12, 26), "Boxing Day"),
+    ];
+    let (weekend_holidays, week_holidays): (Vec<_>, Vec<_>) = fixed_holidays
+        .iter()
+        .copied()
+        .partition(|(date, _)| is_weekend(*date));
+    holidays.extend(week_holidays);
+    for (mut date, name) in weekend_holidays {
+        while is_weekend(date) || holidays.iter().any(|(holiday, _)| *holiday == date) {
+            date += Duration::days(1);
+        }
+        holidays.push((date, name));
+    }
+    holidays
+}
+
+#[derive(Deserialize)]
+struct HolidayRecord {
+    #[serde(deserialize_with = "de_from_date_string")]
+    date: Date,
+    name: Option<String>,
+}
+
+/// Set of holidays, each one with its name
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HolidayCalendar {
+    holidays: BTreeMap<Date, String>,
+}
+
+impl HolidayCalendar {
+    /// Computes the public holidays of the `country` (an ISO 3166-1 alpha-2
+    /// code among [BUILTIN_COUNTRIES]) for the `years`.
+    ///
+    /// For `GB`, these are the bank holidays of England and Wales, without the
+    /// exceptional ones (jubilees, royal events, ...) which can be added from
+    /// a CSV file.
+    pub fn builtin(country: &str, years: RangeInclusive<i32>) -> Result<Self> {
+        let holidays_of_year: fn(i32) -> Vec<(Date, &'static str)> =
+            match country.to_uppercase().as_str() {
+                "DE" => germany,
+                "FR" => france,
+                "GB" => great_britain,
+                _ => bail!(
+                    "no built-in holidays for country {:?} (available: {})",
+                    country,
+                    BUILTIN_COUNTRIES.join(", ")
+                ),
+            };
+        let holidays = years
+            .flat_map(holidays_of_year)
+            .map(|(date, name)| (date, name.to_string()))
+            .collect();
+        Ok(HolidayCalendar { holidays })
+    }
+
+    /// Reads the holidays from a CSV file with the columns `date` (`YYYYMMDD`)
+    /// and `name` (optional).
+    pub fn from_csv<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        info!("Reading holidays from {:?}", path);
+        let mut reader =
+            csv::Reader::from_path(path).with_context(|| format!("Error reading {:?}", path))?;
+        let mut holidays = BTreeMap::new();
+        for record in reader.deserialize() {
+            let record: HolidayRecord =
+                record.with_context(|| format!("Error reading {:?}", path))?;
+            holidays.insert(record.date, record.name.unwrap_or_default());
+        }
+        Ok(HolidayCalendar { holidays })
+    }
+
+    /// Adds the holidays of `other`, its names being kept for the holidays
+    /// present in both
+    pub fn extend(&mut self, other: HolidayCalendar) {
+        self.holidays.extend(other.holidays);
+    }
+
+    /// Returns true if the `date` is a holiday
+    pub fn is_holiday(&self, date: &Date) -> bool {
+        self.holidays.contains_key(date)
+    }
+
+    /// Returns the name of the holiday at `date`, if any
+    pub fn name(&self, date: &Date) -> Option<&str> {
+        self.holidays.get(date).map(String::as_str)
+    }
+
+    /// Iterates over the holidays in chronological order
+    pub fn dates(&self) -> impl Iterator<Item = &Date> {
+        self.holidays.keys()
+    }
+}
+
+/// Operation of a service on holidays
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum HolidayOperation {
+    /// The service runs on the holidays as on any other day
+    #[derivative(Default)]
+    AsRegularDays,
+    /// The service runs on all the holidays, in addition to its regular days
+    Operating,
+    /// The service doesn't run on holidays
+    NotOperating,
+    /// The service runs only on holidays
+    OnlyOperating,
+}
+
+/// Operating profile of a service as described by TransXChange or HAFAS-like
+/// sources: days of the week, and the operation on holidays.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OperatingProfile {
+    /// Days of the week the service runs
+    pub days_of_week: Vec<Weekday>,
+    /// Operation of the service on holidays
+    pub holidays: HolidayOperation,
+}
+
+impl OperatingProfile {
+    /// Expands the operating profile into a `Calendar` with the exact dates of
+    /// service between `start_date` and `end_date` (included).
+    pub fn to_calendar(
+        &self,
+        calendar_id: &str,
+        start_date: Date,
+        end_date: Date,
+        holidays: &HolidayCalendar,
+    ) -> Calendar {
+        let dates = dates_between(start_date, end_date)
+            .into_iter()
+            .filter(|date| {
+                let regular_day = self.days_of_week.contains(&date.weekday());
+                match self.holidays {
+                    HolidayOperation::AsRegularDays => regular_day,
+                    HolidayOperation::Operating => regular_day || holidays.is_holiday(date),
+                    HolidayOperation::NotOperating => regular_day && !holidays.is_holiday(date),
+                    HolidayOperation::OnlyOperating => holidays.is_holiday(date),
+                }
+            })
+            .collect();
+        Calendar {
+            id: calendar_id.to_string(),
+            dates,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    fn date(date: &str) -> Date {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn easter() {
+        assert_eq!(date("2023-04-09"), easter_sunday(2023));
+        assert_eq!(date("2024-03-31"), easter_sunday(2024));
+        assert_eq!(date("2025-04-20"), easter_sunday(2025));
+    }
+
+    #[test]
+    fn french_holidays() {
+        let holidays = HolidayCalendar::builtin("fr", 2023..=2023).unwrap();
+        assert_eq!(11, holidays.dates().count());
+        assert_eq!(Some("Ascension"), holidays.name(&date("2023-05-18")));
+        assert!(holidays.is_holiday(&date("2023-05-29")));
+    }
+
+    #[test]
+    fn british_substitute_days() {
+        let holidays = HolidayCalendar::builtin("GB", 2022..=2022).unwrap();
+        // Christmas Day 2022 is a Sunday
+        assert_eq!(Some("Boxing Day"), holidays.name(&date("2022-12-26")));
+        assert_eq!(Some("Christmas Day"), holidays.name(&date("2022-12-27")));
+        assert_eq!(
+            Some("Spring bank holiday"),
+            holidays.name(&date("2022-05-30"))
+        );
+    }
+
+    #[test]
+    fn unknown_country() {
+        assert!(HolidayCalendar::builtin("XX", 2022..=2022).is_err());
+    }
+
+    #[test]
+    fn holidays_from_csv() {
+        test_in_tmp_dir(|path| {
+            create_file_with_content(
+                path,
+                "holidays.csv",
+                "date,name\n20220603,Platinum Jubilee\n20220919,\n",
+            );
+            let holidays = HolidayCalendar::from_csv(path.join("holidays.csv")).unwrap();
+            assert_eq!(Some("Platinum Jubilee"), holidays.name(&date("2022-06-03")));
+            assert!(holidays.is_holiday(&date("2022-09-19")));
+        });
+    }
+
+    #[test]
+    fn expand_operating_profile() {
+        let holidays = HolidayCalendar::builtin("FR", 2023..=2023).unwrap();
+        let profile = OperatingProfile {
+            days_of_week: vec![Weekday::Mon, Weekday::Tue],
+            holidays: HolidayOperation::NotOperating,
+        };
+        // 2023-05-01 and 2023-05-08 are holidays (Mondays)
+        let calendar =
+            profile.to_calendar("service", date("2023-05-01"), date("2023-05-14"), &holidays);
+        let expected: Vec<Date> = vec![date("2023-05-02"), date("2023-05-09")];
+        assert_eq!(expected, calendar.dates.into_iter().collect::<Vec<_>>());
+
+        let profile = OperatingProfile {
+            days_of_week: vec![Weekday::Sun],
+            holidays: HolidayOperation::Operating,
+        };
+        let calendar =
+            profile.to_calendar("service", date("2023-05-01"), date("2023-05-08"), &holidays);
+        let expected: Vec<Date> = vec![date("2023-05-01"), date("2023-05-07"), date("2023-05-08")];
+        assert_eq!(expected, calendar.dates.into_iter().collect::<Vec<_>>());
+    }
+}
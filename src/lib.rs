@@ -18,7 +18,7 @@
 //! [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md).
 //!
 //! # Features
-//! `transit_model` has 2 possible features: `proj` and `xmllint`.
+//! `transit_model` has several optional features, all disabled by default.
 //!
 //! ## `proj`
 //! `proj` feature is used for geolocation conversion (see
@@ -41,6 +41,42 @@
 //! Some utilities to turn csv files into vector of objects or CollectionWithId (See
 //! https://github.com/hove-io/typed_index_collection/)
 //!
+//! ## `parquet`
+//! Export of the stops, the trips and the stop times as
+//! [Parquet](https://parquet.apache.org/) files (see `export::parquet`)
+//!
+//! ## `sqlite`
+//! Export of the model into a [SQLite](https://www.sqlite.org) database (see
+//! `export::sqlite`)
+//!
+//! ## `postgis`
+//! Export of the referential as a SQL dump to load into
+//! [PostGIS](https://postgis.net) (see `export::postgis`)
+//!
+//! ## `protobuf`
+//! Encoding and decoding of the collections with Protocol Buffers (see
+//! `src/protobuf/transit_model.proto` for the schema)
+//!
+//! ## `async`
+//! Reading and writing of the ZIP archives of the datasets from a
+//! `tokio::io::AsyncRead` and into a `tokio::io::AsyncWrite` (see `async_io`)
+//!
+//! ## `timezone_lookup`
+//! Assignment of a timezone to the stops and the networks without one, from
+//! the timezone boundaries embedded in the crate (see `timezones`)
+//!
+//! ## `wasm`
+//! Compilation to `wasm32-unknown-unknown` (e.g. for a validation tool running
+//! in a browser), the current date and time being provided by JavaScript.
+//! Without a file system, a GTFS is read in memory with `gtfs::from_zip_reader`
+//! (or `gtfs::from_memory` for the extracted files) and written with
+//! `gtfs::write_to_zip_writer` (e.g. on a `std::io::Cursor<Vec<u8>>`). The
+//! compilation is checked by the CI with `make wasm`.
+//!
+//! ## `logger`
+//! Initialization of the logs shared by the binaries, with the spans of the
+//! conversion stages and their duration (see `logger::init_logger`).
+//!
 //! [`CONTRIBUTING.md`]: https://github.com/hove-io/transit_model/blob/master/CONTRIBUTING.md
 
 #![deny(missing_docs)]
@@ -50,29 +86,72 @@ mod utils;
 mod add_prefix;
 pub mod serde_utils;
 pub use add_prefix::{AddPrefix, PrefixConfiguration};
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod calendar_check;
 pub mod calendars;
+pub mod codes;
+pub mod company_rules;
 #[macro_use]
 pub mod objects;
 pub mod configuration;
+pub mod connectivity;
+pub mod coord_check;
+pub mod csv_format;
+pub mod disruptions;
 mod enhancers;
+pub mod entrance_pathways;
+pub mod export;
 #[cfg(not(feature = "parser"))]
 pub(crate) mod file_handler;
 #[cfg(feature = "parser")]
 pub mod file_handler;
+pub mod gbfs;
 pub mod gtfs;
+pub mod holidays;
+pub mod line_aggregation;
+pub mod line_branding;
+#[cfg(feature = "logger")]
+pub mod logger;
+pub mod manifest;
+pub mod memory;
+pub mod mode_rules;
 pub mod model;
+pub mod name_normalization;
 #[cfg(feature = "proj")]
 pub mod netex_france;
 pub mod netex_utils;
+pub mod network_update;
 pub mod ntfs;
 #[cfg(not(feature = "parser"))]
 pub(crate) mod parser;
 #[cfg(feature = "parser")]
 pub mod parser;
+pub mod pipeline;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod renumbering;
+pub mod report;
+pub mod run_time_check;
+pub mod snapshot;
+pub mod speed_check;
+pub mod ssim;
+pub mod stamp;
+pub mod stop_area_merge;
+pub mod stop_relocation;
+pub mod stop_time_occupancy;
+pub mod street_network;
+#[cfg(test)]
+mod test_fixtures;
 #[doc(hidden)]
 pub mod test_utils;
+pub mod testing;
+#[cfg(feature = "timezone_lookup")]
+pub mod timezones;
 pub mod transfers;
 pub mod validity_period;
+pub mod vdv452;
+pub mod vehicle_journey_check;
 mod version_utils;
 pub mod vptranslator;
 
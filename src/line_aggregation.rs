@@ -0,0 +1,403 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Aggregation of several lines into a single one (e.g. the lines `12A` and
+//! `12B` of a network merged into the line `12`).
+
+use crate::{
+    codes::{CodeIndex, ObjectCode},
+    model::Collections,
+    objects::{ObjectType, Rgb},
+    report::Report,
+    Result,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs::File,
+    path::Path,
+};
+use tracing::info;
+
+/// A rule merging the lines `merged_line_ids` into the line `line_id`, the
+/// lines being selected by identifier or by code
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LineAggregationRule {
+    /// Identifier of the line kept after the aggregation
+    #[serde(default)]
+    pub line_id: String,
+    /// Code of the line kept, selecting it instead of `line_id`
+    #[serde(default)]
+    pub line_object_code: Option<ObjectCode>,
+    /// Identifiers of the lines merged into `line_id` and then removed
+    #[serde(default)]
+    pub merged_line_ids: Vec<String>,
+    /// Codes of other lines merged into the kept line
+    #[serde(default)]
+    pub merged_line_object_codes: Vec<ObjectCode>,
+    /// Name of the aggregated line, the name of `line_id` is kept if empty
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Code of the aggregated line, the code of `line_id` is kept if empty
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Color of the aggregated line, the color of `line_id` is kept if empty
+    #[serde(default)]
+    pub color: Option<Rgb>,
+    /// Text color of the aggregated line, the text color of `line_id` is kept
+    /// if empty
+    #[serde(default)]
+    pub text_color: Option<Rgb>,
+}
+
+/// Categories of the `Report` produced by the aggregation of lines
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineAggregationReportCategory {
+    /// A line has been merged into another one
+    LineMerged,
+    /// A line of a rule doesn't exist, the rule is ignored or partially applied
+    UnknownLine,
+    /// A line is used by several rules, only the first one is applied
+    LineAlreadyMerged,
+    /// The merged line doesn't belong to the network of the aggregated line
+    DifferentNetwork,
+}
+
+/// Read the aggregation rules from the JSON file `path`, containing an array
+/// of `LineAggregationRule`
+pub fn read_rules<P: AsRef<Path>>(path: P) -> Result<Vec<LineAggregationRule>> {
+    let path = path.as_ref();
+    info!("Reading line aggregation rules from {:?}", path);
+    let file = File::open(path).with_context(|| format!("Error reading {:?}", path))?;
+    let rules =
+        serde_json::from_reader(file).with_context(|| format!("Error parsing {:?}", path))?;
+    Ok(rules)
+}
+
+// `rule` with the lines selected by code replaced by their identifier, `None`
+// if the kept line is unknown
+fn select_by_code<'a>(
+    rule: &'a LineAggregationRule,
+    collections: &Collections,
+    codes: &mut Option<CodeIndex>,
+    report: &mut Report<LineAggregationReportCategory>,
+) -> Option<Cow<'a, LineAggregationRule>> {
+    if rule.line_object_code.is_none() && rule.merged_line_object_codes.is_empty() {
+        return Some(Cow::Borrowed(rule));
+    }
+    let codes = codes.get_or_insert_with(|| CodeIndex::new(collections));
+    let mut rule = rule.clone();
+    if let Some(object_code) = rule.line_object_code.take() {
+        match object_code.object_id(codes, &collections.lines) {
+            Some(line_id) => rule.line_id = line_id.to_string(),
+            None => {
+                report.add_error(
+                    format!("no line has the {}, the rule is ignored", object_code),
+                    LineAggregationReportCategory::UnknownLine,
+                );
+                return None;
+            }
+        }
+    }
+    for merged_object_code in std::mem::take(&mut rule.merged_line_object_codes) {
+        match merged_object_code.object_id(codes, &collections.lines) {
+            Some(merged_line_id) => rule.merged_line_ids.push(merged_line_id.to_string()),
+            None => report.add_error(
+                format!(
+                    "no line has the {}, it is not merged into line {}",
+                    merged_object_code, rule.line_id
+                ),
+                LineAggregationReportCategory::UnknownLine,
+            ),
+        }
+    }
+    Some(Cow::Owned(rule))
+}
+
+/// Merge the lines of `collections` following `rules`.
+///
+/// The routes (and thus the trips) of the merged lines are attached to the
+/// aggregated line, which also receives their codes, object properties and
+/// comments. The ticket use perimeters and the grid calendars referencing a
+/// merged line are updated, then the merged lines are removed.
+///
+/// Every merge and every ignored part of a rule is listed in the returned
+/// `Report`.
+pub fn aggregate_lines(
+    collections: &mut Collections,
+    rules: &[LineAggregationRule],
+) -> Report<LineAggregationReportCategory> {
+    let mut report = Report::default();
+    // merged line id => aggregated line id
+    let mut replacements: HashMap<String, String> = HashMap::new();
+    let mut aggregated_line_ids: HashSet<String> = HashSet::new();
+    // built for the first rule selecting lines by code
+    let mut codes = None;
+    for rule in rules {
+        let rule = match select_by_code(rule, collections, &mut codes, &mut report) {
+            Some(rule) => rule,
+            None => continue,
+        };
+        let line_idx = match collections.lines.get_idx(&rule.line_id) {
+            Some(line_idx) => line_idx,
+            None => {
+                report.add_error(
+                    format!("line {} doesn't exist, the rule is ignored", rule.line_id),
+                    LineAggregationReportCategory::UnknownLine,
+                );
+                continue;
+            }
+        };
+        if replacements.contains_key(&rule.line_id) {
+            report.add_error(
+                format!(
+                    "line {} has already been merged, the rule is ignored",
+                    rule.line_id
+                ),
+                LineAggregationReportCategory::LineAlreadyMerged,
+            );
+            continue;
+        }
+        for merged_line_id in &rule.merged_line_ids {
+            if *merged_line_id == rule.line_id {
+                continue;
+            }
+            if replacements.contains_key(merged_line_id)
+                || aggregated_line_ids.contains(merged_line_id)
+            {
+                report.add_error(
+                    format!(
+                        "line {} is already used by another rule, it is not merged into line {}",
+                        merged_line_id, rule.line_id
+                    ),
+                    LineAggregationReportCategory::LineAlreadyMerged,
+                );
+                continue;
+            }
+            let merged_line = match collections.lines.get(merged_line_id) {
+                Some(merged_line) => merged_line.clone(),
+                None => {
+                    report.add_error(
+                        format!(
+                            "line {} doesn't exist, it is not merged into line {}",
+                            merged_line_id, rule.line_id
+                        ),
+                        LineAggregationReportCategory::UnknownLine,
+                    );
+                    continue;
+                }
+            };
+            let mut line = collections.lines.index_mut(line_idx);
+            if merged_line.network_id != line.network_id {
+                report.add_warning(
+                    format!(
+                        "line {} of network {} is merged into line {} of network {}",
+                        merged_line.id, merged_line.network_id, line.id, line.network_id
+                    ),
+                    LineAggregationReportCategory::DifferentNetwork,
+                );
+            }
+            line.codes.extend(merged_line.codes);
+            for (key, value) in merged_line.object_properties {
+                line.object_properties.entry(key).or_insert(value);
+            }
+            line.comment_links.extend(merged_line.comment_links);
+            report.add_warning(
+                format!("line {} is merged into line {}", merged_line.id, line.id),
+                LineAggregationReportCategory::LineMerged,
+            );
+            replacements.insert(merged_line.id, line.id.clone());
+        }
+        aggregated_line_ids.insert(rule.line_id.clone());
+        let mut line = collections.lines.index_mut(line_idx);
+        if let Some(name) = &rule.name {
+            line.name = name.clone();
+        }
+        if let Some(code) = &rule.code {
+            line.code = Some(code.clone());
+        }
+        if let Some(color) = &rule.color {
+            line.color = Some(color.clone());
+        }
+        if let Some(text_color) = &rule.text_color {
+            line.text_color = Some(text_color.clone());
+        }
+    }
+    if replacements.is_empty() {
+        return report;
+    }
+
+    for route_idx in collections.routes.indexes() {
+        let new_line_id = replacements.get(&collections.routes[route_idx].line_id);
+        if let Some(new_line_id) = new_line_id {
+            collections.routes.index_mut(route_idx).line_id = new_line_id.clone();
+        }
+    }
+    for perimeter in collections.ticket_use_perimeters.values_mut() {
+        if perimeter.object_type == ObjectType::Line {
+            if let Some(new_line_id) = replacements.get(&perimeter.object_id) {
+                perimeter.object_id = new_line_id.clone();
+            }
+        }
+    }
+    for grid_rel_calendar_line in collections.grid_rel_calendar_line.values_mut() {
+        if let Some(new_line_id) = replacements.get(&grid_rel_calendar_line.line_id) {
+            grid_rel_calendar_line.line_id = new_line_id.clone();
+        }
+    }
+    collections
+        .lines
+        .retain(|line| !replacements.contains_key(&line.id));
+    info!("{} line(s) merged", replacements.len());
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn rule() -> LineAggregationRule {
+        LineAggregationRule {
+            line_id: "12A".to_string(),
+            line_object_code: None,
+            merged_line_ids: vec!["12B".to_string()],
+            merged_line_object_codes: vec![],
+            name: Some("12".to_string()),
+            code: None,
+            color: Some(Rgb {
+                red: 255,
+                green: 0,
+                blue: 0,
+            }),
+            text_color: None,
+        }
+    }
+
+    fn lines_12a_and_12b() -> Collections {
+        ModelBuilder::default()
+            .route("r12A", |r| {
+                r.line_id = "12A".to_string();
+            })
+            .route("r12B", |r| {
+                r.line_id = "12B".to_string();
+            })
+            .vj("vj1", |vj| {
+                vj.route("r12A")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("r12B")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("C", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections()
+    }
+
+    #[test]
+    fn merge_lines() {
+        let mut collections = lines_12a_and_12b();
+        collections
+            .lines
+            .get_mut("12B")
+            .unwrap()
+            .codes
+            .insert(("source".to_string(), "12B".to_string()));
+        let report = aggregate_lines(&mut collections, &[rule()]);
+
+        assert!(report.errors().is_empty());
+        assert_eq!(1, report.warnings().len());
+        assert_eq!(
+            LineAggregationReportCategory::LineMerged,
+            report.warnings()[0].category
+        );
+        assert!(!collections.lines.contains_id("12B"));
+        let line = collections.lines.get("12A").unwrap();
+        assert_eq!("12", line.name);
+        assert_eq!(
+            Some("FF0000".to_string()),
+            line.color.as_ref().map(Rgb::to_string)
+        );
+        assert!(line
+            .codes
+            .contains(&("source".to_string(), "12B".to_string())));
+        assert_eq!("12A", collections.routes.get("r12B").unwrap().line_id);
+    }
+
+    #[test]
+    fn unknown_line() {
+        let mut collections = lines_12a_and_12b();
+        let mut rule = rule();
+        rule.merged_line_ids = vec!["unknown".to_string()];
+        let report = aggregate_lines(&mut collections, &[rule]);
+
+        assert_eq!(1, report.errors().len());
+        assert_eq!(
+            LineAggregationReportCategory::UnknownLine,
+            report.errors()[0].category
+        );
+        assert!(collections.lines.contains_id("12B"));
+        assert_eq!("12B", collections.routes.get("r12B").unwrap().line_id);
+    }
+
+    #[test]
+    fn read_rules_from_json() {
+        let rules: Vec<LineAggregationRule> = serde_json::from_str(
+            r#"[{"line_id": "12A", "merged_line_ids": ["12B"], "name": "12", "color": "FF0000"}]"#,
+        )
+        .unwrap();
+        assert_eq!(vec![rule()], rules);
+    }
+
+    #[test]
+    fn select_lines_by_code() {
+        let mut collections = lines_12a_and_12b();
+        for (line_id, code) in [("12A", "C01742"), ("12B", "C01743")] {
+            collections
+                .lines
+                .get_mut(line_id)
+                .unwrap()
+                .codes
+                .insert(("SIRI".to_string(), code.to_string()));
+        }
+        let rules: Vec<LineAggregationRule> = serde_json::from_str(
+            r#"[{
+                "line_object_code": {"object_system": "SIRI", "object_code": "C01742"},
+                "merged_line_object_codes": [{"object_system": "SIRI", "object_code": "C01743"}]
+            }]"#,
+        )
+        .unwrap();
+        let report = aggregate_lines(&mut collections, &rules);
+
+        assert!(report.errors().is_empty());
+        assert!(!collections.lines.contains_id("12B"));
+        assert_eq!("12A", collections.routes.get("r12B").unwrap().line_id);
+
+        let mut collections = lines_12a_and_12b();
+        let report = aggregate_lines(&mut collections, &rules);
+        assert_eq!(1, report.errors().len());
+        assert_eq!(
+            "no line has the code \"C01742\" of SIRI, the rule is ignored",
+            report.errors()[0].message
+        );
+        assert!(collections.lines.contains_id("12B"));
+    }
+}
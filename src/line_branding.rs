@@ -0,0 +1,249 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Branding of the lines (colors and logo) from a table provided by the
+//! networks, for the sources without branding (e.g. most of the GTFS).
+
+use crate::{model::Collections, objects::Rgb, report::Report, Result};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
+use tracing::info;
+
+/// Key of the object property of a line holding the url of its logo
+pub const LOGO_URL_PROPERTY: &str = "logo_url";
+
+/// Branding of the line of code `line_code` in the network `network_id`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LineBranding {
+    /// Identifier of the network of the line
+    pub network_id: String,
+    /// Code of the line in its network
+    pub line_code: String,
+    /// Color of the line
+    #[serde(rename = "line_color", default)]
+    pub color: Option<Rgb>,
+    /// Color of the text on the color of the line
+    #[serde(rename = "line_text_color", default)]
+    pub text_color: Option<Rgb>,
+    /// Url of the logo of the line
+    #[serde(default)]
+    pub logo_url: Option<String>,
+}
+
+/// Categories of the `Report` produced by the branding of lines
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineBrandingReportCategory {
+    /// No line matches a branding
+    UnknownLine,
+    /// Several brandings match the same line, only the first one is applied
+    DuplicateBranding,
+    /// The color of a line differs from its branding and is kept
+    ColorMismatch,
+}
+
+/// Read the brandings from the CSV file `path`, with the columns
+/// `network_id`, `line_code`, `line_color`, `line_text_color` and `logo_url`
+pub fn read_brandings<P: AsRef<Path>>(path: P) -> Result<Vec<LineBranding>> {
+    let path = path.as_ref();
+    info!("Reading line brandings from {:?}", path);
+    let mut rdr =
+        csv::Reader::from_path(path).with_context(|| format!("Error reading {:?}", path))?;
+    let brandings = rdr
+        .deserialize()
+        .collect::<std::result::Result<_, _>>()
+        .with_context(|| format!("Error parsing {:?}", path))?;
+    Ok(brandings)
+}
+
+/// Fill the missing colors and text colors of the lines of `collections`
+/// from the `brandings` matching their network and code, and store the urls
+/// of the logos in the object property `logo_url` of the lines.
+///
+/// The colors already set on a line are never overwritten.
+pub fn apply_brandings(
+    collections: &mut Collections,
+    brandings: &[LineBranding],
+) -> Report<LineBrandingReportCategory> {
+    let mut report = Report::default();
+    // network id => line code => position of the first branding of the line
+    let mut brandings_by_line: HashMap<&str, HashMap<&str, usize>> = HashMap::new();
+    for (position, branding) in brandings.iter().enumerate() {
+        let network_brandings = brandings_by_line
+            .entry(branding.network_id.as_str())
+            .or_default();
+        if network_brandings.contains_key(branding.line_code.as_str()) {
+            report.add_warning(
+                format!(
+                    "several brandings for the line {} of network {}, only the first one is applied",
+                    branding.line_code, branding.network_id
+                ),
+                LineBrandingReportCategory::DuplicateBranding,
+            );
+        } else {
+            network_brandings.insert(branding.line_code.as_str(), position);
+        }
+    }
+
+    let mut used_brandings = vec![false; brandings.len()];
+    let mut branded_lines = 0;
+    for line_idx in collections.lines.indexes() {
+        let mut line = collections.lines.index_mut(line_idx);
+        let branding = match (brandings_by_line.get(line.network_id.as_str()), &line.code) {
+            (Some(network_brandings), Some(code)) => network_brandings.get(code.as_str()).copied(),
+            _ => None,
+        };
+        let branding = match branding {
+            Some(position) => {
+                used_brandings[position] = true;
+                &brandings[position]
+            }
+            None => continue,
+        };
+        let mut branded = false;
+        if let Some(color) = &branding.color {
+            match line.color.clone() {
+                None => {
+                    line.color = Some(color.clone());
+                    branded = true;
+                }
+                Some(line_color) if line_color != *color => {
+                    report.add_warning(
+                        format!(
+                            "line {} has the color {} instead of {}, it is kept",
+                            line.id, line_color, color
+                        ),
+                        LineBrandingReportCategory::ColorMismatch,
+                    );
+                }
+                _ => {}
+            }
+        }
+        if line.text_color.is_none() && branding.text_color.is_some() {
+            line.text_color = branding.text_color.clone();
+            branded = true;
+        }
+        if let Some(logo_url) = &branding.logo_url {
+            line.object_properties
+                .insert(LOGO_URL_PROPERTY.to_string(), logo_url.clone());
+            branded = true;
+        }
+        if branded {
+            branded_lines += 1;
+        }
+    }
+    for (position, branding) in brandings.iter().enumerate() {
+        let first_position =
+            brandings_by_line[branding.network_id.as_str()][branding.line_code.as_str()];
+        if position == first_position && !used_brandings[position] {
+            report.add_warning(
+                format!(
+                    "no line {} in network {}, the branding is ignored",
+                    branding.line_code, branding.network_id
+                ),
+                LineBrandingReportCategory::UnknownLine,
+            );
+        }
+    }
+    info!(
+        "{} line(s) branded with {} branding(s)",
+        branded_lines,
+        used_brandings.iter().filter(|used| **used).count()
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn rgb(red: u8, green: u8, blue: u8) -> Option<Rgb> {
+        Some(Rgb { red, green, blue })
+    }
+
+    #[test]
+    fn fill_missing_colors() {
+        let mut collections = ModelBuilder::default()
+            .route("r1", |r| {
+                r.line_id = "l1".to_string();
+            })
+            .route("r2", |r| {
+                r.line_id = "l2".to_string();
+            })
+            .vj("vj1", |vj| {
+                vj.route("r1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("r2")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("C", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections();
+        let mut line = collections.lines.get_mut("l1").unwrap();
+        line.code = Some("1".to_string());
+        drop(line);
+        let mut line = collections.lines.get_mut("l2").unwrap();
+        line.code = Some("2".to_string());
+        line.color = rgb(0, 0, 255);
+        drop(line);
+        let network_id = collections.lines.get("l1").unwrap().network_id.clone();
+        let branding = |line_code: &str, color, logo_url: Option<&str>| LineBranding {
+            network_id: network_id.clone(),
+            line_code: line_code.to_string(),
+            color,
+            text_color: rgb(255, 255, 255),
+            logo_url: logo_url.map(str::to_string),
+        };
+        let brandings = vec![
+            branding("1", rgb(255, 0, 0), Some("https://example.com/1.png")),
+            branding("2", rgb(0, 255, 0), None),
+            branding("1", rgb(0, 0, 0), None),
+            branding("3", rgb(0, 0, 0), None),
+        ];
+
+        let report = apply_brandings(&mut collections, &brandings);
+
+        let line = collections.lines.get("l1").unwrap();
+        assert_eq!(rgb(255, 0, 0), line.color);
+        assert_eq!(rgb(255, 255, 255), line.text_color);
+        assert_eq!(
+            Some("https://example.com/1.png"),
+            line.object_properties
+                .get(LOGO_URL_PROPERTY)
+                .map(String::as_str)
+        );
+        let line = collections.lines.get("l2").unwrap();
+        assert_eq!(rgb(0, 0, 255), line.color);
+        assert_eq!(rgb(255, 255, 255), line.text_color);
+        assert_eq!(
+            vec![
+                LineBrandingReportCategory::DuplicateBranding,
+                LineBrandingReportCategory::ColorMismatch,
+                LineBrandingReportCategory::UnknownLine,
+            ],
+            report
+                .warnings()
+                .iter()
+                .map(|warning| warning.category)
+                .collect::<Vec<_>>()
+        );
+    }
+}
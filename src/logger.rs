@@ -0,0 +1,54 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Initialization of the logs of the binaries.
+//!
+//! The logs are written on the standard output, the level being set with the
+//! `RUST_LOG` environment variable (`info` by default). The closing of the
+//! spans of the conversion stages is logged with their duration, in JSON lines
+//! with `LOG_FORMAT=json`.
+
+use tracing_subscriber::{
+    filter::{EnvFilter, LevelFilter},
+    fmt::format::FmtSpan,
+    layer::SubscriberExt as _,
+    util::SubscriberInitExt as _,
+};
+
+/// Install the global subscriber of the logs, following the `RUST_LOG` and
+/// `LOG_FORMAT` environment variables
+pub fn init_logger() {
+    let default_level = LevelFilter::INFO;
+    let rust_log =
+        std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
+    let env_filter_subscriber = EnvFilter::try_new(rust_log).unwrap_or_else(|e| {
+        eprintln!(
+            "invalid {}, falling back to level '{}' - {}",
+            EnvFilter::DEFAULT_ENV,
+            default_level,
+            e,
+        );
+        EnvFilter::new(default_level.to_string())
+    });
+    let json = std::env::var("LOG_FORMAT").map_or(false, |format| format == "json");
+    tracing_subscriber::registry()
+        .with(json.then(|| {
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_span_events(FmtSpan::CLOSE)
+        }))
+        .with((!json).then(|| tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE)))
+        .with(env_filter_subscriber)
+        .init();
+}
@@ -0,0 +1,412 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Manifest of a dataset written in a directory or in a ZIP archive (e.g. a
+//! NTFS or a GTFS), listing the SHA-256 checksum of each file and the number of rows of the
+//! CSV files, to check the integrity of the dataset between the steps of a
+//! pipeline.
+
+use crate::{report::Report, Result};
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+use tracing::info;
+
+/// Name of the manifest file in the directory or the archive of the dataset
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// A file of a `Manifest`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Name of the file in the directory or the archive of the dataset
+    pub file: String,
+    /// SHA-256 checksum of the file, in lowercase hexadecimal
+    pub sha256: String,
+    /// Number of rows (header excluded) of a CSV file (`.txt` or `.csv`)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub rows: Option<usize>,
+}
+
+/// Checksums and row counts of the files of a dataset
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// The files of the dataset, sorted by name
+    pub files: Vec<ManifestEntry>,
+}
+
+/// Category of the entries of the report of `verify`
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestReportCategory {
+    /// A file of the manifest is missing from the dataset
+    MissingFile,
+    /// The checksum of a file differs from the manifest
+    ChecksumMismatch,
+    /// The number of rows of a file differs from the manifest
+    RowCountMismatch,
+    /// A file of the dataset is not in the manifest
+    UnexpectedFile,
+}
+
+fn is_csv_file(file_name: &str) -> bool {
+    file_name.ends_with(".txt") || file_name.ends_with(".csv")
+}
+
+// `source` is the path of the file (or of the archive containing it), only
+// used in the error messages
+fn make_entry(file_name: &str, content: &[u8], source: &Path) -> Result<ManifestEntry> {
+    let rows = if is_csv_file(file_name) {
+        let mut rdr = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(content);
+        let mut rows = 0;
+        for record in rdr.records() {
+            record.with_context(|| format!("Error reading {:?}", source))?;
+            rows += 1;
+        }
+        Some(rows)
+    } else {
+        None
+    };
+    Ok(ManifestEntry {
+        file: file_name.to_string(),
+        sha256: format!("{:x}", Sha256::digest(content)),
+        rows,
+    })
+}
+
+// Names of the files of the directory `path`, the manifest excluded
+fn file_names(path: &Path) -> Result<Vec<String>> {
+    let mut file_names = Vec::new();
+    for entry in fs::read_dir(path).with_context(|| format!("Error reading {:?}", path))? {
+        let entry = entry?;
+        if !entry.path().is_file() {
+            continue;
+        }
+        let file_name = match entry.file_name().into_string() {
+            Ok(file_name) => file_name,
+            Err(file_name) => bail!("File name {:?} is not valid UTF-8", file_name),
+        };
+        if file_name != MANIFEST_FILE {
+            file_names.push(file_name);
+        }
+    }
+    file_names.sort();
+    Ok(file_names)
+}
+
+/// Compute the manifest of the files of the directory `path` (the
+/// sub-directories and the manifest file itself are ignored)
+pub fn generate<P: AsRef<Path>>(path: P) -> Result<Manifest> {
+    let path = path.as_ref();
+    let files = file_names(path)?
+        .iter()
+        .map(|file_name| {
+            let file_path = path.join(file_name);
+            let content =
+                fs::read(&file_path).with_context(|| format!("Error reading {:?}", file_path))?;
+            make_entry(file_name, &content, &file_path)
+        })
+        .collect::<Result<_>>()?;
+    Ok(Manifest { files })
+}
+
+/// Compute the manifest of the files of the ZIP archive `path` (the
+/// directories and the manifest file itself are ignored)
+pub fn generate_zip<P: AsRef<Path>>(path: P) -> Result<Manifest> {
+    let path = path.as_ref();
+    let file = fs::File::open(path).with_context(|| format!("Error reading {:?}", path))?;
+    let mut archive =
+        zip::ZipArchive::new(file).with_context(|| format!("Error reading {:?}", path))?;
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let mut zip_file = archive
+            .by_index(index)
+            .with_context(|| format!("Error reading {:?}", path))?;
+        if zip_file.is_dir() || zip_file.name() == MANIFEST_FILE {
+            continue;
+        }
+        let file_name = zip_file.name().to_string();
+        let mut content = Vec::new();
+        zip_file
+            .read_to_end(&mut content)
+            .with_context(|| format!("Error reading {:?}", path))?;
+        files.push(make_entry(&file_name, &content, path)?);
+    }
+    files.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(Manifest { files })
+}
+
+/// Write the manifest of the files of the directory `path` into its
+/// `manifest.json` file
+pub fn write<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+    info!("Writing {}", MANIFEST_FILE);
+    let manifest = generate(path)?;
+    let manifest_path = path.join(MANIFEST_FILE);
+    let file = fs::File::create(&manifest_path)
+        .with_context(|| format!("Error creating {:?}", manifest_path))?;
+    serde_json::to_writer_pretty(file, &manifest)
+        .with_context(|| format!("Error writing {:?}", manifest_path))?;
+    Ok(())
+}
+
+/// Add the manifest of the files of the ZIP archive `path` into the archive,
+/// as its `manifest.json` file
+pub fn write_to_zip<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+    info!("Writing {} into {:?}", MANIFEST_FILE, path);
+    let archive = zip::ZipArchive::new(
+        fs::File::open(path).with_context(|| format!("Error reading {:?}", path))?,
+    )
+    .with_context(|| format!("Error reading {:?}", path))?;
+    if archive
+        .file_names()
+        .any(|file_name| file_name == MANIFEST_FILE)
+    {
+        bail!("{:?} already contains a {} file", path, MANIFEST_FILE);
+    }
+    let manifest = generate_zip(path)?;
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Error reading {:?}", path))?;
+    let mut zip =
+        zip::ZipWriter::new_append(file).with_context(|| format!("Error reading {:?}", path))?;
+    zip.start_file(MANIFEST_FILE, zip::write::FileOptions::default())
+        .with_context(|| format!("Error writing {:?}", path))?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)
+        .with_context(|| format!("Error writing {:?}", path))?;
+    zip.finish()
+        .with_context(|| format!("Error writing {:?}", path))?;
+    Ok(())
+}
+
+// Compare the `actual` manifest of a dataset with its `expected` manifest
+fn compare(expected: Manifest, actual: Manifest) -> Report<ManifestReportCategory> {
+    let mut actual: BTreeMap<String, ManifestEntry> = actual
+        .files
+        .into_iter()
+        .map(|entry| (entry.file.clone(), entry))
+        .collect();
+
+    let mut report = Report::default();
+    for expected_entry in expected.files {
+        let actual_entry = match actual.remove(&expected_entry.file) {
+            Some(actual_entry) => actual_entry,
+            None => {
+                report.add_error(
+                    format!("file {} is missing", expected_entry.file),
+                    ManifestReportCategory::MissingFile,
+                );
+                continue;
+            }
+        };
+        if actual_entry.rows != expected_entry.rows {
+            report.add_error(
+                format!(
+                    "file {} has {:?} rows instead of {:?}",
+                    expected_entry.file, actual_entry.rows, expected_entry.rows
+                ),
+                ManifestReportCategory::RowCountMismatch,
+            );
+        }
+        if actual_entry.sha256 != expected_entry.sha256 {
+            report.add_error(
+                format!("checksum of file {} differs", expected_entry.file),
+                ManifestReportCategory::ChecksumMismatch,
+            );
+        }
+    }
+    for file_name in actual.keys() {
+        report.add_warning(
+            format!("file {} is not in the manifest", file_name),
+            ManifestReportCategory::UnexpectedFile,
+        );
+    }
+    report
+}
+
+/// Check the files of the directory `path` against its `manifest.json` file.
+/// The missing files and the files whose checksum or number of rows differ
+/// are errors, the files not in the manifest are warnings.
+pub fn verify<P: AsRef<Path>>(path: P) -> Result<Report<ManifestReportCategory>> {
+    let path = path.as_ref();
+    let manifest_path = path.join(MANIFEST_FILE);
+    let file = fs::File::open(&manifest_path)
+        .with_context(|| format!("Error reading {:?}", manifest_path))?;
+    let expected: Manifest = serde_json::from_reader(file)
+        .with_context(|| format!("Error reading {:?}", manifest_path))?;
+    Ok(compare(expected, generate(path)?))
+}
+
+/// Check the files of the ZIP archive `path` against its `manifest.json`
+/// file, like `verify` does for a directory
+pub fn verify_zip<P: AsRef<Path>>(path: P) -> Result<Report<ManifestReportCategory>> {
+    let path = path.as_ref();
+    let file = fs::File::open(path).with_context(|| format!("Error reading {:?}", path))?;
+    let mut archive =
+        zip::ZipArchive::new(file).with_context(|| format!("Error reading {:?}", path))?;
+    let manifest_file = archive
+        .by_name(MANIFEST_FILE)
+        .with_context(|| format!("Error reading {} of {:?}", MANIFEST_FILE, path))?;
+    let expected: Manifest = serde_json::from_reader(manifest_file)
+        .with_context(|| format!("Error reading {} of {:?}", MANIFEST_FILE, path))?;
+    Ok(compare(expected, generate_zip(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn write_and_verify() {
+        test_in_tmp_dir(|path| {
+            fs::write(
+                path.join("stops.txt"),
+                "stop_id,stop_name\nA,Gare\nB,Mairie\n",
+            )
+            .unwrap();
+            fs::write(path.join("routes.txt"), "route_id\nR1\n").unwrap();
+            write(path).unwrap();
+
+            let manifest = generate(path).unwrap();
+            assert_eq!(
+                vec![("routes.txt", Some(1)), ("stops.txt", Some(2))],
+                manifest
+                    .files
+                    .iter()
+                    .map(|entry| (entry.file.as_str(), entry.rows))
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(64, manifest.files[0].sha256.len());
+            assert!(verify(path).unwrap().is_empty());
+
+            fs::write(path.join("stops.txt"), "stop_id,stop_name\nA,Gare\n").unwrap();
+            fs::remove_file(path.join("routes.txt")).unwrap();
+            fs::write(path.join("extra.txt"), "extra_id\n").unwrap();
+            let report = verify(path).unwrap();
+            let categories: Vec<_> = report.errors().iter().map(|entry| entry.category).collect();
+            assert_eq!(
+                vec![
+                    ManifestReportCategory::MissingFile,
+                    ManifestReportCategory::RowCountMismatch,
+                    ManifestReportCategory::ChecksumMismatch,
+                ],
+                categories
+            );
+            assert_eq!(1, report.warnings().len());
+            assert_eq!(
+                ManifestReportCategory::UnexpectedFile,
+                report.warnings()[0].category
+            );
+        });
+    }
+
+    #[test]
+    fn write_to_zip_archive() {
+        test_in_tmp_dir(|path| {
+            let zip_path = path.join("gtfs.zip");
+            let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+            let options = zip::write::FileOptions::default();
+            zip.start_file("stops.txt", options).unwrap();
+            zip.write_all(b"stop_id,stop_name\nA,Gare\nB,Mairie\n")
+                .unwrap();
+            zip.start_file("routes.txt", options).unwrap();
+            zip.write_all(b"route_id\nR1\n").unwrap();
+            zip.finish().unwrap();
+
+            write_to_zip(&zip_path).unwrap();
+
+            let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+            let mut file_names: Vec<_> = archive.file_names().collect();
+            file_names.sort_unstable();
+            assert_eq!(vec![MANIFEST_FILE, "routes.txt", "stops.txt"], file_names);
+            let manifest: Manifest =
+                serde_json::from_reader(archive.by_name(MANIFEST_FILE).unwrap()).unwrap();
+            assert_eq!(
+                vec![("routes.txt", Some(1)), ("stops.txt", Some(2))],
+                manifest
+                    .files
+                    .iter()
+                    .map(|entry| (entry.file.as_str(), entry.rows))
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                format!("{:x}", Sha256::digest(b"route_id\nR1\n")),
+                manifest.files[0].sha256
+            );
+            assert_eq!(generate_zip(&zip_path).unwrap(), manifest);
+
+            let error = write_to_zip(&zip_path).unwrap_err();
+            assert!(error.to_string().contains("already contains"));
+        });
+    }
+
+    #[test]
+    fn write_to_zip_and_verify() {
+        let write_archive = |zip_path: &Path, stops: &[u8], manifest: Option<&[u8]>| {
+            let mut zip = zip::ZipWriter::new(fs::File::create(zip_path).unwrap());
+            let options = zip::write::FileOptions::default();
+            zip.start_file("stops.txt", options).unwrap();
+            zip.write_all(stops).unwrap();
+            zip.start_file("routes.txt", options).unwrap();
+            zip.write_all(b"route_id\nR1\n").unwrap();
+            if let Some(manifest) = manifest {
+                zip.start_file(MANIFEST_FILE, options).unwrap();
+                zip.write_all(manifest).unwrap();
+            }
+            zip.finish().unwrap();
+        };
+        test_in_tmp_dir(|path| {
+            let zip_path = path.join("gtfs.zip");
+            write_archive(&zip_path, b"stop_id,stop_name\nA,Gare\nB,Mairie\n", None);
+            write_to_zip(&zip_path).unwrap();
+            assert!(verify_zip(&zip_path).unwrap().is_empty());
+
+            // the same archive with a stop less
+            let mut manifest = Vec::new();
+            zip::ZipArchive::new(fs::File::open(&zip_path).unwrap())
+                .unwrap()
+                .by_name(MANIFEST_FILE)
+                .unwrap()
+                .read_to_end(&mut manifest)
+                .unwrap();
+            write_archive(&zip_path, b"stop_id,stop_name\nA,Gare\n", Some(&manifest));
+            let report = verify_zip(&zip_path).unwrap();
+            let categories: Vec<_> = report.errors().iter().map(|entry| entry.category).collect();
+            assert_eq!(
+                vec![
+                    ManifestReportCategory::RowCountMismatch,
+                    ManifestReportCategory::ChecksumMismatch,
+                ],
+                categories
+            );
+            assert!(report.warnings().is_empty());
+
+            write_archive(&zip_path, b"stop_id,stop_name\n", None);
+            let error = verify_zip(&zip_path).unwrap_err();
+            assert!(error.to_string().contains(MANIFEST_FILE));
+        });
+    }
+}
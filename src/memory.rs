@@ -0,0 +1,236 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Estimation of the number of rows and of the memory used by each
+//! collection, to find which structures dominate the memory of a large
+//! dataset before choosing how to filter it.
+//!
+//! ```
+//! # fn main() -> transit_model::Result<()> {
+//! let model = transit_model::ntfs::read("tests/fixtures/minimal_ntfs")?;
+//! let memory_report = model.memory_report();
+//! let largest = memory_report.largest()[0];
+//! assert!(largest.bytes > 0);
+//! let summary = memory_report.to_string();
+//! assert!(summary.starts_with("collection"));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{model::Collections, objects::StopTime};
+use geo::algorithm::coords_iter::CoordsIter;
+use serde::Serialize;
+use std::{collections::HashMap, fmt, mem::size_of};
+use typed_index_collection::{Collection, CollectionWithId, Id, Idx};
+
+/// Rows and estimated memory of a collection
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct CollectionMemory {
+    /// Name of the collection (the field of `Collections`)
+    pub name: &'static str,
+    /// Number of objects
+    pub rows: usize,
+    /// Estimation of the memory used in bytes
+    pub bytes: usize,
+}
+
+/// Rows and estimated memory of all the collections (see
+/// `Collections::memory_report`)
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The collections, in the order of the fields of `Collections`
+    pub collections: Vec<CollectionMemory>,
+}
+
+impl MemoryReport {
+    /// Estimation of the memory used by all the collections in bytes
+    pub fn total_bytes(&self) -> usize {
+        self.collections.iter().map(|c| c.bytes).sum()
+    }
+
+    /// The collections sorted from the largest to the smallest
+    pub fn largest(&self) -> Vec<&CollectionMemory> {
+        let mut collections: Vec<_> = self.collections.iter().collect();
+        collections.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.name.cmp(b.name)));
+        collections
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<25} {:>12} {:>15}", "collection", "rows", "bytes")?;
+        for collection in self.largest() {
+            writeln!(
+                f,
+                "{:<25} {:>12} {:>15}",
+                collection.name, collection.rows, collection.bytes
+            )?;
+        }
+        write!(f, "{:<25} {:>12} {:>15}", "total", "", self.total_bytes())
+    }
+}
+
+// The identifiers are stored in the objects and in the index of the
+// collection
+fn collection_with_id<T: Id<T>>(
+    name: &'static str,
+    collection: &CollectionWithId<T>,
+    heap_bytes: impl Fn(&T) -> usize,
+) -> CollectionMemory {
+    let row_bytes = size_of::<T>() + size_of::<String>() + size_of::<Idx<T>>();
+    CollectionMemory {
+        name,
+        rows: collection.len(),
+        bytes: collection
+            .values()
+            .map(|object| row_bytes + 2 * object.id().len() + heap_bytes(object))
+            .sum(),
+    }
+}
+
+fn collection<T>(name: &'static str, collection: &Collection<T>) -> CollectionMemory {
+    CollectionMemory {
+        name,
+        rows: collection.len(),
+        bytes: collection.len() * size_of::<T>(),
+    }
+}
+
+fn stop_time_map(name: &'static str, values: &HashMap<(String, u32), String>) -> CollectionMemory {
+    let row_bytes = size_of::<((String, u32), String)>();
+    CollectionMemory {
+        name,
+        rows: values.len(),
+        bytes: values
+            .iter()
+            .map(|((vj_id, _), value)| row_bytes + vj_id.len() + value.len())
+            .sum(),
+    }
+}
+
+fn no_heap<T>(_: &T) -> usize {
+    0
+}
+
+impl Collections {
+    /// Estimate the number of rows and the memory used by each collection.
+    ///
+    /// The estimation counts the size of the objects, of their identifiers,
+    /// of the stop times of the vehicle journeys, of the dates of the
+    /// calendars and of the points of the geometries, which are the largest
+    /// structures of a dataset. The other strings and the relations built by
+    /// `Model` are not counted.
+    pub fn memory_report(&self) -> MemoryReport {
+        let collections = vec![
+            collection_with_id("contributors", &self.contributors, no_heap),
+            collection_with_id("datasets", &self.datasets, no_heap),
+            collection_with_id("networks", &self.networks, no_heap),
+            collection_with_id("commercial_modes", &self.commercial_modes, no_heap),
+            collection_with_id("lines", &self.lines, no_heap),
+            collection_with_id("routes", &self.routes, no_heap),
+            collection_with_id("vehicle_journeys", &self.vehicle_journeys, |vj| {
+                vj.stop_times.capacity() * size_of::<StopTime>()
+            }),
+            collection("frequencies", &self.frequencies),
+            collection_with_id("physical_modes", &self.physical_modes, no_heap),
+            collection_with_id("stop_areas", &self.stop_areas, no_heap),
+            collection_with_id("stop_points", &self.stop_points, no_heap),
+            collection_with_id("stop_locations", &self.stop_locations, no_heap),
+            collection_with_id("calendars", &self.calendars, |calendar| {
+                calendar.dates.len() * size_of::<crate::objects::Date>()
+            }),
+            collection_with_id("companies", &self.companies, no_heap),
+            collection_with_id("comments", &self.comments, |comment| comment.name.len()),
+            collection_with_id("equipments", &self.equipments, no_heap),
+            collection("transfers", &self.transfers),
+            collection("interchanges", &self.interchanges),
+            collection_with_id("trip_properties", &self.trip_properties, no_heap),
+            collection_with_id("geometries", &self.geometries, |geometry| {
+                geometry.geometry.coords_count() * size_of::<geo::Coord<f64>>()
+            }),
+            collection("admin_stations", &self.admin_stations),
+            stop_time_map("stop_time_headsigns", &self.stop_time_headsigns),
+            stop_time_map("stop_time_ids", &self.stop_time_ids),
+            stop_time_map("stop_time_comments", &self.stop_time_comments),
+            collection("prices_v1", &self.prices_v1),
+            collection("od_fares_v1", &self.od_fares_v1),
+            collection("fares_v1", &self.fares_v1),
+            collection_with_id("tickets", &self.tickets, no_heap),
+            collection_with_id("ticket_uses", &self.ticket_uses, no_heap),
+            collection("ticket_prices", &self.ticket_prices),
+            collection("ticket_use_perimeters", &self.ticket_use_perimeters),
+            collection("ticket_use_restrictions", &self.ticket_use_restrictions),
+            collection_with_id("pathways", &self.pathways, no_heap),
+            collection_with_id("levels", &self.levels, no_heap),
+            collection_with_id("grid_calendars", &self.grid_calendars, no_heap),
+            collection("grid_exception_dates", &self.grid_exception_dates),
+            collection("grid_periods", &self.grid_periods),
+            collection("grid_rel_calendar_line", &self.grid_rel_calendar_line),
+            collection_with_id("addresses", &self.addresses, no_heap),
+            collection_with_id(
+                "administrative_regions",
+                &self.administrative_regions,
+                no_heap,
+            ),
+            collection_with_id("poi_types", &self.poi_types, no_heap),
+            collection_with_id("pois", &self.pois, no_heap),
+            collection("occupancies", &self.occupancies),
+            collection("stop_time_occupancies", &self.stop_time_occupancies),
+        ];
+        MemoryReport { collections }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    #[test]
+    fn count_rows_and_stop_times() {
+        let collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00")
+                    .st("C", "12:00:00", "12:01:00");
+            })
+            .build_model()
+            .into_collections();
+        let report = collections.memory_report();
+
+        let vehicle_journeys = report
+            .collections
+            .iter()
+            .find(|c| c.name == "vehicle_journeys")
+            .unwrap();
+        assert_eq!(2, vehicle_journeys.rows);
+        assert!(vehicle_journeys.bytes >= 5 * size_of::<StopTime>());
+        let stop_points = report
+            .collections
+            .iter()
+            .find(|c| c.name == "stop_points")
+            .unwrap();
+        assert_eq!(3, stop_points.rows);
+        assert_eq!(
+            report.total_bytes(),
+            report.collections.iter().map(|c| c.bytes).sum::<usize>()
+        );
+    }
+}
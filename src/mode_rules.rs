@@ -0,0 +1,330 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Renaming of the physical and commercial modes, and merging of the near
+//! duplicate modes of the source feeds (e.g. `Bus`, `bus` and `Autobus`
+//! merged into `Bus`).
+
+use crate::{
+    model::Collections,
+    objects::{CommercialMode, PhysicalMode},
+    report::Report,
+    Result,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    path::Path,
+};
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Id};
+
+/// Type of the modes of a `ModeRule`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModeType {
+    /// The physical modes, referenced by the vehicle journeys
+    PhysicalMode,
+    /// The commercial modes, referenced by the lines
+    CommercialMode,
+}
+
+/// A rule renaming the mode `mode_id` and merging the modes `merged_mode_ids`
+/// into it.
+///
+/// Unlike the companies and the lines, the modes have no codes in external
+/// systems (`object_codes.txt` doesn't reference them), so they are selected
+/// by identifier only.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModeRule {
+    /// Type of the modes of the rule
+    pub object_type: ModeType,
+    /// Identifier of the mode kept
+    pub mode_id: String,
+    /// Identifiers of the modes merged into `mode_id` and then removed
+    #[serde(default)]
+    pub merged_mode_ids: Vec<String>,
+    /// New name of the mode, the name of `mode_id` is kept if empty
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Categories of the `Report` produced by the application of the mode rules
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModeRulesReportCategory {
+    /// A mode has been merged into another one
+    ModeMerged,
+    /// A mode has been renamed
+    ModeRenamed,
+    /// A mode of a rule doesn't exist, the rule is ignored or partially applied
+    UnknownMode,
+    /// A mode is used by several rules, only the first one is applied
+    ModeAlreadyMerged,
+}
+
+/// Read the mode rules from the JSON file `path`, containing an array of
+/// `ModeRule`
+pub fn read_rules<P: AsRef<Path>>(path: P) -> Result<Vec<ModeRule>> {
+    let path = path.as_ref();
+    info!("Reading mode rules from {:?}", path);
+    let file = File::open(path).with_context(|| format!("Error reading {:?}", path))?;
+    let rules =
+        serde_json::from_reader(file).with_context(|| format!("Error parsing {:?}", path))?;
+    Ok(rules)
+}
+
+// Apply the `rules` on the `modes`, returning the identifier of the kept
+// mode by identifier of merged mode
+fn apply_rules_on<'a, T: Id<T> + Clone>(
+    modes: &mut CollectionWithId<T>,
+    rules: impl Iterator<Item = &'a ModeRule>,
+    set_name: impl Fn(&mut T, &str),
+    merge: impl Fn(&mut T, &T),
+    report: &mut Report<ModeRulesReportCategory>,
+) -> HashMap<String, String> {
+    // merged mode id => kept mode id
+    let mut replacements: HashMap<String, String> = HashMap::new();
+    let mut kept_mode_ids: HashSet<String> = HashSet::new();
+    for rule in rules {
+        let mode_idx = match modes.get_idx(&rule.mode_id) {
+            Some(mode_idx) => mode_idx,
+            None => {
+                report.add_error(
+                    format!("mode {} doesn't exist, the rule is ignored", rule.mode_id),
+                    ModeRulesReportCategory::UnknownMode,
+                );
+                continue;
+            }
+        };
+        if replacements.contains_key(&rule.mode_id) {
+            report.add_error(
+                format!(
+                    "mode {} has already been merged, the rule is ignored",
+                    rule.mode_id
+                ),
+                ModeRulesReportCategory::ModeAlreadyMerged,
+            );
+            continue;
+        }
+        for merged_mode_id in &rule.merged_mode_ids {
+            if *merged_mode_id == rule.mode_id {
+                continue;
+            }
+            if replacements.contains_key(merged_mode_id) || kept_mode_ids.contains(merged_mode_id) {
+                report.add_error(
+                    format!(
+                        "mode {} is already used by another rule, it is not merged into mode {}",
+                        merged_mode_id, rule.mode_id
+                    ),
+                    ModeRulesReportCategory::ModeAlreadyMerged,
+                );
+                continue;
+            }
+            let merged_mode_idx = match modes.get_idx(merged_mode_id) {
+                Some(merged_mode_idx) => merged_mode_idx,
+                None => {
+                    report.add_error(
+                        format!(
+                            "mode {} doesn't exist, it is not merged into mode {}",
+                            merged_mode_id, rule.mode_id
+                        ),
+                        ModeRulesReportCategory::UnknownMode,
+                    );
+                    continue;
+                }
+            };
+            let merged_mode = modes[merged_mode_idx].clone();
+            merge(&mut modes.index_mut(mode_idx), &merged_mode);
+            report.add_info(
+                format!(
+                    "mode {} is merged into mode {}",
+                    merged_mode_id, rule.mode_id
+                ),
+                ModeRulesReportCategory::ModeMerged,
+            );
+            replacements.insert(merged_mode_id.clone(), rule.mode_id.clone());
+        }
+        kept_mode_ids.insert(rule.mode_id.clone());
+        if let Some(name) = &rule.name {
+            set_name(&mut modes.index_mut(mode_idx), name);
+            report.add_info(
+                format!("mode {} is renamed {:?}", rule.mode_id, name),
+                ModeRulesReportCategory::ModeRenamed,
+            );
+        }
+    }
+    modes.retain(|mode| !replacements.contains_key(mode.id()));
+    replacements
+}
+
+/// Rename and merge the physical and commercial modes of `collections`
+/// following `rules`.
+///
+/// The vehicle journeys of a merged physical mode, and the lines of a merged
+/// commercial mode, are attached to the kept mode, then the merged modes are
+/// removed. A kept physical mode without CO2 emission receives the one of a
+/// merged mode.
+///
+/// Every merge and every ignored part of a rule is listed in the returned
+/// `Report`.
+pub fn apply_mode_rules(
+    collections: &mut Collections,
+    rules: &[ModeRule],
+) -> Report<ModeRulesReportCategory> {
+    let mut report = Report::default();
+    let of_type = |object_type| {
+        rules
+            .iter()
+            .filter(move |rule| rule.object_type == object_type)
+    };
+
+    let physical_mode_replacements = apply_rules_on(
+        &mut collections.physical_modes,
+        of_type(ModeType::PhysicalMode),
+        |mode: &mut PhysicalMode, name| mode.name = name.to_string(),
+        |mode: &mut PhysicalMode, merged_mode| {
+            mode.co2_emission = mode.co2_emission.or(merged_mode.co2_emission);
+        },
+        &mut report,
+    );
+    if !physical_mode_replacements.is_empty() {
+        for vj_idx in collections.vehicle_journeys.indexes() {
+            let new_mode_id = physical_mode_replacements
+                .get(&collections.vehicle_journeys[vj_idx].physical_mode_id);
+            if let Some(new_mode_id) = new_mode_id {
+                collections
+                    .vehicle_journeys
+                    .index_mut(vj_idx)
+                    .physical_mode_id = new_mode_id.clone();
+            }
+        }
+    }
+
+    let commercial_mode_replacements = apply_rules_on(
+        &mut collections.commercial_modes,
+        of_type(ModeType::CommercialMode),
+        |mode: &mut CommercialMode, name| mode.name = name.to_string(),
+        |_, _| {},
+        &mut report,
+    );
+    if !commercial_mode_replacements.is_empty() {
+        for line_idx in collections.lines.indexes() {
+            let new_mode_id =
+                commercial_mode_replacements.get(&collections.lines[line_idx].commercial_mode_id);
+            if let Some(new_mode_id) = new_mode_id {
+                collections.lines.index_mut(line_idx).commercial_mode_id = new_mode_id.clone();
+            }
+        }
+    }
+    info!(
+        "{} physical mode(s) and {} commercial mode(s) merged",
+        physical_mode_replacements.len(),
+        commercial_mode_replacements.len()
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::one_vehicle_journey;
+    use pretty_assertions::assert_eq;
+
+    fn rule() -> ModeRule {
+        ModeRule {
+            object_type: ModeType::PhysicalMode,
+            mode_id: "Bus".to_string(),
+            merged_mode_ids: vec!["Autobus".to_string()],
+            name: Some("Bus".to_string()),
+        }
+    }
+
+    #[test]
+    fn merge_physical_modes() {
+        let mut collections = one_vehicle_journey().into_collections();
+        collections
+            .physical_modes
+            .push(PhysicalMode {
+                id: "Bus".to_string(),
+                name: "bus".to_string(),
+                co2_emission: None,
+            })
+            .unwrap();
+        collections
+            .physical_modes
+            .push(PhysicalMode {
+                id: "Autobus".to_string(),
+                name: "Autobus".to_string(),
+                co2_emission: Some(42.0),
+            })
+            .unwrap();
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .physical_mode_id = "Autobus".to_string();
+        let report = apply_mode_rules(&mut collections, &[rule()]);
+
+        assert!(report.errors().is_empty());
+        assert!(!collections.physical_modes.contains_id("Autobus"));
+        let bus = collections.physical_modes.get("Bus").unwrap();
+        assert_eq!("Bus", bus.name);
+        assert_eq!(Some(42.0), bus.co2_emission);
+        assert_eq!(
+            "Bus",
+            collections
+                .vehicle_journeys
+                .get("vj1")
+                .unwrap()
+                .physical_mode_id
+        );
+    }
+
+    #[test]
+    fn unknown_mode() {
+        let mut collections = one_vehicle_journey().into_collections();
+        for mode_id in ["Bus", "Autobus"].iter() {
+            collections
+                .physical_modes
+                .push(PhysicalMode {
+                    id: mode_id.to_string(),
+                    name: mode_id.to_string(),
+                    co2_emission: None,
+                })
+                .unwrap();
+        }
+        let mut rule = rule();
+        rule.merged_mode_ids = vec!["unknown".to_string()];
+        let report = apply_mode_rules(&mut collections, &[rule]);
+
+        assert_eq!(1, report.errors().len());
+        assert_eq!(
+            ModeRulesReportCategory::UnknownMode,
+            report.errors()[0].category
+        );
+        assert!(collections.physical_modes.contains_id("Autobus"));
+    }
+
+    #[test]
+    fn read_rules_from_json() {
+        let rules: Vec<ModeRule> = serde_json::from_str(
+            r#"[{"object_type": "physical_mode", "mode_id": "Bus", "merged_mode_ids": ["Autobus"], "name": "Bus"}]"#,
+        )
+        .unwrap();
+        assert_eq!(vec![rule()], rules);
+    }
+}
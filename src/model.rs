@@ -14,12 +14,19 @@
 
 //! Definition of the navitia transit model.
 
-use crate::{enhancers, objects::*, Error, Result};
-use anyhow::{anyhow, bail};
+use crate::{
+    codes::{CodeIndex, CodedObject, CodedType},
+    enhancers,
+    objects::*,
+    report::Report,
+    Error, Result,
+};
+use anyhow::{anyhow, bail, Context};
 use chrono::NaiveDate;
 use derivative::Derivative;
 use geo::algorithm::centroid::Centroid;
-use geo::MultiPoint;
+use geo::{BoundingRect, ConvexHull, MultiPoint, Rect};
+use once_cell::sync::OnceCell;
 use relational_types::{GetCorresponding, IdxSet, ManyToMany, OneToMany, Relation};
 use serde::{Deserialize, Serialize};
 use skip_error::skip_error_and_warn;
@@ -66,8 +73,18 @@ pub const TRAIN_PHYSICAL_MODE: &str = "Train";
 /// Physical mode for Tramway
 pub const TRAMWAY_PHYSICAL_MODE: &str = "Tramway";
 
+/// Categories of the `Report` produced by the enhancements of routes
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteReportCategory {
+    /// The name of the route was empty and has been generated
+    NameGenerated,
+    /// The destination of the route was empty or unknown and has been generated
+    DestinationGenerated,
+}
+
 /// The set of collections representing the model.
-#[derive(Derivative, Serialize, Deserialize, Debug)]
+#[derive(Derivative, Serialize, Deserialize, Debug, Clone)]
 #[derivative(Default)]
 #[allow(missing_docs)]
 pub struct Collections {
@@ -83,12 +100,13 @@ pub struct Collections {
     pub stop_areas: CollectionWithId<StopArea>,
     pub stop_points: CollectionWithId<StopPoint>,
     pub stop_locations: CollectionWithId<StopLocation>,
-    pub feed_infos: BTreeMap<String, String>,
+    pub feed_infos: FeedInfos,
     pub calendars: CollectionWithId<Calendar>,
     pub companies: CollectionWithId<Company>,
     pub comments: CollectionWithId<Comment>,
     pub equipments: CollectionWithId<Equipment>,
     pub transfers: Collection<Transfer>,
+    pub interchanges: Collection<Interchange>,
     pub trip_properties: CollectionWithId<TripProperty>,
     pub geometries: CollectionWithId<Geometry>,
     pub admin_stations: Collection<AdminStation>,
@@ -117,10 +135,68 @@ pub struct Collections {
     pub grid_rel_calendar_line: Collection<GridRelCalendarLine>,
     pub addresses: CollectionWithId<Address>,
     pub administrative_regions: CollectionWithId<AdministrativeRegion>,
+    pub poi_types: CollectionWithId<PoiType>,
+    pub pois: CollectionWithId<Poi>,
     pub occupancies: Collection<Occupancy>,
+    pub stop_time_occupancies: Collection<StopTimeOccupancy>,
+}
+
+// Transliterate to ASCII the texts of each object of `collection` returned
+// by `texts`
+fn transliterate_texts<T>(
+    collection: &mut CollectionWithId<T>,
+    texts: for<'a> fn(&'a mut T) -> Vec<&'a mut String>,
+) {
+    for idx in collection.indexes() {
+        let mut object = collection.index_mut(idx);
+        for text in texts(&mut object) {
+            if !text.is_ascii() {
+                *text = deunicode::deunicode(text);
+            }
+        }
+    }
 }
 
 impl Collections {
+    /// Transliterate to ASCII the texts displayed to the passengers (e.g. `é`
+    /// written `e`), for the consumers not supporting UTF-8: the names of the
+    /// objects, the headsigns and the comments. The identifiers, the codes and
+    /// the URLs are left untouched.
+    pub fn transliterate_to_ascii(&mut self) {
+        transliterate_texts(&mut self.networks, |network| vec![&mut network.name]);
+        transliterate_texts(&mut self.companies, |company| vec![&mut company.name]);
+        transliterate_texts(&mut self.commercial_modes, |mode| vec![&mut mode.name]);
+        transliterate_texts(&mut self.physical_modes, |mode| vec![&mut mode.name]);
+        transliterate_texts(&mut self.lines, |line| {
+            std::iter::once(&mut line.name)
+                .chain(line.forward_name.as_mut())
+                .chain(line.backward_name.as_mut())
+                .collect()
+        });
+        transliterate_texts(&mut self.routes, |route| vec![&mut route.name]);
+        transliterate_texts(&mut self.stop_areas, |stop_area| vec![&mut stop_area.name]);
+        transliterate_texts(&mut self.stop_points, |stop_point| {
+            vec![&mut stop_point.name]
+        });
+        transliterate_texts(&mut self.stop_locations, |stop_location| {
+            vec![&mut stop_location.name]
+        });
+        transliterate_texts(&mut self.vehicle_journeys, |vj| {
+            vj.headsign.iter_mut().collect()
+        });
+        transliterate_texts(&mut self.comments, |comment| {
+            std::iter::once(&mut comment.name)
+                .chain(comment.label.as_mut())
+                .collect()
+        });
+        transliterate_texts(&mut self.pois, |poi| vec![&mut poi.name]);
+        for headsign in self.stop_time_headsigns.values_mut() {
+            if !headsign.is_ascii() {
+                *headsign = deunicode::deunicode(headsign);
+            }
+        }
+    }
+
     /// Remove associated schedules with route points
     pub fn remove_route_points(&mut self) {
         let is_route_point = |stop_time: &StopTime| -> bool {
@@ -556,8 +632,16 @@ impl Collections {
         self.transfers.retain(|t| {
             stop_points_used.contains(&t.from_stop_id) && stop_points_used.contains(&t.to_stop_id)
         });
+        self.interchanges.retain(|interchange| {
+            vehicle_journeys_used.contains(&interchange.from_vehicle_journey_id)
+                && vehicle_journeys_used.contains(&interchange.to_vehicle_journey_id)
+                && stop_points_used.contains(&interchange.from_stop_point_id)
+                && stop_points_used.contains(&interchange.to_stop_point_id)
+        });
         self.frequencies
             .retain(|frequency| vehicle_journeys_used.contains(&frequency.vehicle_journey_id));
+        self.stop_time_occupancies
+            .retain(|occupancy| vehicle_journeys_used.contains(&occupancy.vehicle_journey_id));
         self.levels
             .retain(|level| level_id_used.contains(&level.id));
         self.calendars.retain(|c| calendars_used.contains(&c.id));
@@ -612,6 +696,7 @@ impl Collections {
 
         self.frequencies = dedup_collection(&mut self.frequencies);
         self.transfers = dedup_collection(&mut self.transfers);
+        self.interchanges = dedup_collection(&mut self.interchanges);
         self.admin_stations = dedup_collection(&mut self.admin_stations);
         self.prices_v1 = dedup_collection(&mut self.prices_v1);
         self.od_fares_v1 = dedup_collection(&mut self.od_fares_v1);
@@ -623,6 +708,7 @@ impl Collections {
         self.grid_periods = dedup_collection(&mut self.grid_periods);
         self.grid_rel_calendar_line = dedup_collection(&mut self.grid_rel_calendar_line);
         self.occupancies = dedup_collection(&mut self.occupancies);
+        self.stop_time_occupancies = dedup_collection(&mut self.stop_time_occupancies);
 
         Ok(())
     }
@@ -1037,6 +1123,16 @@ impl Collections {
         duplicate2ref
     }
 
+    /// Generate the empty names and destinations of the routes like
+    /// `enhance_route_names_with_report`, without the report.
+    #[deprecated(note = "please use `Collections::enhance_route_names_with_report()` instead")]
+    pub fn enhance_route_names(
+        &mut self,
+        routes_to_vehicle_journeys: &impl Relation<From = Route, To = VehicleJourney>,
+    ) {
+        self.enhance_route_names_with_report(routes_to_vehicle_journeys);
+    }
+
     /// If the route name is empty, it is derived from the most frequent
     /// `stop_area` origin and `stop_area` destination of all the associated
     /// trips.  The `stop_area` name is used to create the following `String`:
@@ -1049,11 +1145,16 @@ impl Collections {
     /// of `stop_area`'s name is used.
     ///
     /// `route.destination_id` is also replaced with the destination stop area
-    /// found with the above rules.
-    pub fn enhance_route_names(
+    /// found with the above rules when it is empty or doesn't reference an
+    /// existing `stop_area`.
+    ///
+    /// Every generated name or destination is listed in the returned `Report`,
+    /// as an info, or as a warning when the destination replaces an unknown
+    /// `stop_area`.
+    pub fn enhance_route_names_with_report(
         &mut self,
         routes_to_vehicle_journeys: &impl Relation<From = Route, To = VehicleJourney>,
-    ) {
+    ) -> Report<RouteReportCategory> {
         fn find_best_origin_destination<'a>(
             route_idx: Idx<Route>,
             collections: &'a Collections,
@@ -1184,11 +1285,23 @@ impl Collections {
             }
         }
 
+        let mut report = Report::default();
         let mut route_names: BTreeMap<Idx<Route>, String> = BTreeMap::new();
         let mut route_destination_ids: BTreeMap<Idx<Route>, Option<String>> = BTreeMap::new();
+        let mut unknown_destination_routes: HashSet<Idx<Route>> = HashSet::new();
         for (route_idx, route) in &self.routes {
             let no_route_name = route.name.is_empty();
-            let no_destination_id = route.destination_id.is_none();
+            let no_destination_id = match &route.destination_id {
+                Some(destination_id) if !self.stop_areas.contains_id(destination_id) => {
+                    warn!(
+                        "route {} references an unknown destination {}",
+                        route.id, destination_id
+                    );
+                    unknown_destination_routes.insert(route_idx);
+                    true
+                }
+                destination_id => destination_id.is_none(),
+            };
             if no_route_name || no_destination_id {
                 let (origin, destination) = skip_error_and_warn!(find_best_origin_destination(
                     route_idx,
@@ -1208,11 +1321,29 @@ impl Collections {
             }
         }
         for (route_idx, route_name) in route_names {
+            report.add_info(
+                format!(
+                    "name of route {} generated as {:?}",
+                    self.routes[route_idx].id, route_name
+                ),
+                RouteReportCategory::NameGenerated,
+            );
             self.routes.index_mut(route_idx).name = route_name;
         }
         for (route_idx, destination_id) in route_destination_ids {
+            let message = format!(
+                "destination of route {} set to stop area {}",
+                self.routes[route_idx].id,
+                destination_id.as_deref().unwrap_or_default()
+            );
+            if unknown_destination_routes.contains(&route_idx) {
+                report.add_warning(message, RouteReportCategory::DestinationGenerated);
+            } else {
+                report.add_info(message, RouteReportCategory::DestinationGenerated);
+            }
             self.routes.index_mut(route_idx).destination_id = destination_id;
         }
+        report
     }
 
     /// If a route direction is empty, it's set by default with the "forward" value
@@ -1311,6 +1442,48 @@ impl Collections {
             .ok_or_else(|| anyhow!("Cannot calculate validity period because there is no dataset"))
     }
 
+    // The located stop points, as a multipoint
+    fn stop_point_multipoint(&self) -> MultiPoint<f64> {
+        self.stop_points
+            .values()
+            .filter(|stop_point| stop_point.coord != Coord::default())
+            .map(|stop_point| (stop_point.coord.lon, stop_point.coord.lat))
+            .collect()
+    }
+
+    /// Geographic bounding box of the stop points, `None` if no stop point
+    /// is located. The stop points at (0, 0) are considered not located.
+    ///
+    /// ```
+    /// # use transit_model::objects::Coord;
+    /// let mut collections = transit_model_builder::ModelBuilder::default()
+    ///     .vj("vj1", |vj| {
+    ///         vj.st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .build()
+    ///     .into_collections();
+    /// assert!(collections.bounding_box().is_none());
+    /// collections.stop_points.get_mut("A").unwrap().coord = Coord { lon: 2.37, lat: 48.84 };
+    /// collections.stop_points.get_mut("B").unwrap().coord = Coord { lon: 2.35, lat: 48.88 };
+    /// let bounding_box = collections.bounding_box().unwrap();
+    /// assert_eq!((2.35, 48.84), bounding_box.min().x_y());
+    /// assert_eq!((2.37, 48.88), bounding_box.max().x_y());
+    /// ```
+    pub fn bounding_box(&self) -> Option<Rect<f64>> {
+        self.stop_point_multipoint().bounding_rect()
+    }
+
+    /// Convex hull of the stop points, as a polygon, `None` if no stop point
+    /// is located. The stop points at (0, 0) are considered not located.
+    pub fn convex_hull(&self) -> Option<geo::Geometry<f64>> {
+        let multipoint = self.stop_point_multipoint();
+        if multipoint.0.is_empty() {
+            return None;
+        }
+        Some(multipoint.convex_hull().into())
+    }
+
     /// Convert given frequencies to stoptimes
     /// by creating new duplicated vehicle_journeys, calendars and comments if necessary
     pub fn convert_frequencies_to_stoptimes(&mut self, frequencies: Vec<Frequency>) -> Result<()> {
@@ -1457,12 +1630,358 @@ impl Collections {
         let frequencies = self.frequencies.take();
         self.convert_frequencies_to_stoptimes(frequencies)
     }
+
+    /// Split each vehicle journey of `vehicle_journey_ids` in two journeys at
+    /// the stop point `stop_point_id`.
+    ///
+    /// The first journey keeps the identifier of the original one and ends at
+    /// the stop point, the second one (identified by `<id>:2`) starts at the
+    /// stop point. The passengers can't board the first journey nor alight
+    /// from the second one at the stop point. Both journeys share the same
+    /// `block_id` (the identifier of the original journey if it had none) to
+    /// keep the through service.
+    ///
+    /// The vehicle journeys which don't serve the stop point between their
+    /// first and last stops are not split. Returns the identifiers of the
+    /// created vehicle journeys.
+    pub fn split_vehicle_journeys(
+        &mut self,
+        vehicle_journey_ids: &BTreeSet<String>,
+        stop_point_id: &str,
+    ) -> Result<Vec<String>> {
+        let stop_point_idx = self
+            .stop_points
+            .get_idx(stop_point_id)
+            .ok_or_else(|| anyhow!("stop point {} not found", stop_point_id))?;
+        let mut new_vehicle_journeys = Vec::new();
+        for vehicle_journey_id in vehicle_journey_ids {
+            let vj_idx = self
+                .vehicle_journeys
+                .get_idx(vehicle_journey_id)
+                .ok_or_else(|| anyhow!("vehicle journey {} not found", vehicle_journey_id))?;
+            let new_id = format!("{}:2", vehicle_journey_id);
+            if self.vehicle_journeys.contains_id(&new_id) {
+                bail!(
+                    "vehicle journey {} cannot be split, {} already exists",
+                    vehicle_journey_id,
+                    new_id
+                );
+            }
+            let mut vj = self.vehicle_journeys.index_mut(vj_idx);
+            let last_position = vj.stop_times.len().saturating_sub(1);
+            let position = match vj
+                .stop_times
+                .iter()
+                .position(|stop_time| stop_time.stop_point_idx == stop_point_idx)
+            {
+                Some(position) if position > 0 && position < last_position => position,
+                _ => {
+                    warn!(
+                        "vehicle journey {} is not split, it doesn't serve stop point {} between its first and last stops",
+                        vehicle_journey_id, stop_point_id
+                    );
+                    continue;
+                }
+            };
+            let block_id = vj
+                .block_id
+                .clone()
+                .unwrap_or_else(|| vehicle_journey_id.clone());
+            let mut stop_times = vj.stop_times.split_off(position);
+            let split_sequence = stop_times[0].sequence;
+            // the first journey ends at the split stop point, where passengers
+            // can't board anymore
+            vj.stop_times.push(StopTime {
+                pickup_type: 1,
+                ..stop_times[0].clone()
+            });
+            stop_times[0].drop_off_type = 1;
+            vj.block_id = Some(block_id.clone());
+            vj.journey_pattern_id = None;
+            let new_vj = VehicleJourney {
+                id: new_id.clone(),
+                block_id: Some(block_id),
+                stop_times,
+                ..vj.clone()
+            };
+            drop(vj);
+
+            for map in &mut [&mut self.stop_time_headsigns, &mut self.stop_time_comments] {
+                let moved: Vec<_> = map
+                    .iter()
+                    .filter(|((vj_id, sequence), _)| {
+                        vj_id == vehicle_journey_id && *sequence >= split_sequence
+                    })
+                    .map(|((_, sequence), value)| (*sequence, value.clone()))
+                    .collect();
+                for (sequence, value) in moved {
+                    if sequence > split_sequence {
+                        map.remove(&(vehicle_journey_id.clone(), sequence));
+                    }
+                    map.insert((new_id.clone(), sequence), value);
+                }
+            }
+            let moved_ids: Vec<_> = self
+                .stop_time_ids
+                .keys()
+                .filter(|(vj_id, sequence)| {
+                    vj_id == vehicle_journey_id && *sequence > split_sequence
+                })
+                .cloned()
+                .collect();
+            for key in moved_ids {
+                if let Some(stop_time_id) = self.stop_time_ids.remove(&key) {
+                    self.stop_time_ids
+                        .insert((new_id.clone(), key.1), stop_time_id);
+                }
+            }
+            let frequencies: Vec<_> = self
+                .frequencies
+                .values()
+                .filter(|frequency| &frequency.vehicle_journey_id == vehicle_journey_id)
+                .map(|frequency| Frequency {
+                    vehicle_journey_id: new_id.clone(),
+                    ..frequency.clone()
+                })
+                .collect();
+            for frequency in frequencies {
+                self.frequencies.push(frequency);
+            }
+            let occupancies: Vec<_> = self
+                .stop_time_occupancies
+                .values()
+                .filter(|occupancy| {
+                    &occupancy.vehicle_journey_id == vehicle_journey_id
+                        && occupancy.stop_sequence >= split_sequence
+                })
+                .map(|occupancy| StopTimeOccupancy {
+                    vehicle_journey_id: new_id.clone(),
+                    ..occupancy.clone()
+                })
+                .collect();
+            self.stop_time_occupancies.retain(|occupancy| {
+                &occupancy.vehicle_journey_id != vehicle_journey_id
+                    || occupancy.stop_sequence <= split_sequence
+            });
+            for occupancy in occupancies {
+                self.stop_time_occupancies.push(occupancy);
+            }
+            new_vehicle_journeys.push(new_vj);
+        }
+        let new_ids = new_vehicle_journeys
+            .iter()
+            .map(|vj| vj.id.clone())
+            .collect();
+        for vj in new_vehicle_journeys {
+            self.vehicle_journeys.push(vj)?;
+        }
+        Ok(new_ids)
+    }
+
+    /// Merge the `Collections` parameter into the current `Collections`.
+    ///
+    /// An identifier used in both `Collections` is an error: the `Collections`
+    /// to merge should be read with different prefixes (see
+    /// `PrefixConfiguration`). The exception are the objects which are not
+    /// prefixed (commercial modes, physical modes, addresses and administrative
+    /// regions): those already present in the current `Collections` are kept.
+    pub fn try_merge(&mut self, collections: Collections) -> Result<()> {
+        fn merge_with_id<T: Id<T>>(
+            collection: &mut CollectionWithId<T>,
+            other: CollectionWithId<T>,
+            object_type: &str,
+        ) -> Result<()> {
+            for object in other.take() {
+                let id = object.id().to_string();
+                collection
+                    .push(object)
+                    .with_context(|| format!("{} {} cannot be merged", object_type, id))?;
+            }
+            Ok(())
+        }
+        fn merge_missing<T: Id<T>>(
+            collection: &mut CollectionWithId<T>,
+            other: CollectionWithId<T>,
+        ) {
+            for object in other.take() {
+                if !collection.contains_id(object.id()) {
+                    // the identifier has been checked just above
+                    collection.push(object).unwrap();
+                }
+            }
+        }
+        fn merge<T>(collection: &mut Collection<T>, other: Collection<T>) {
+            for object in other.take() {
+                collection.push(object);
+            }
+        }
+
+        let Collections {
+            contributors,
+            datasets,
+            networks,
+            commercial_modes,
+            lines,
+            routes,
+            vehicle_journeys,
+            frequencies,
+            physical_modes,
+            stop_areas,
+            stop_points,
+            stop_locations,
+            feed_infos,
+            calendars,
+            companies,
+            comments,
+            equipments,
+            transfers,
+            interchanges,
+            trip_properties,
+            geometries,
+            admin_stations,
+            stop_time_headsigns,
+            stop_time_ids,
+            stop_time_comments,
+            prices_v1,
+            od_fares_v1,
+            fares_v1,
+            tickets,
+            ticket_uses,
+            ticket_prices,
+            ticket_use_perimeters,
+            ticket_use_restrictions,
+            pathways,
+            levels,
+            grid_calendars,
+            grid_exception_dates,
+            grid_periods,
+            grid_rel_calendar_line,
+            addresses,
+            administrative_regions,
+            poi_types,
+            pois,
+            occupancies,
+            stop_time_occupancies,
+        } = collections;
+
+        merge_with_id(&mut self.contributors, contributors, "contributor")?;
+        merge_with_id(&mut self.datasets, datasets, "dataset")?;
+        merge_with_id(&mut self.networks, networks, "network")?;
+        merge_missing(&mut self.commercial_modes, commercial_modes);
+        merge_with_id(&mut self.lines, lines, "line")?;
+        merge_with_id(&mut self.routes, routes, "route")?;
+        merge(&mut self.frequencies, frequencies);
+        merge_missing(&mut self.physical_modes, physical_modes);
+        merge_with_id(&mut self.stop_areas, stop_areas, "stop area")?;
+        merge_with_id(&mut self.stop_locations, stop_locations, "stop location")?;
+        self.feed_infos.extend(feed_infos);
+        merge_with_id(&mut self.calendars, calendars, "calendar")?;
+        merge_with_id(&mut self.companies, companies, "company")?;
+        merge_with_id(&mut self.comments, comments, "comment")?;
+        merge_with_id(&mut self.equipments, equipments, "equipment")?;
+        merge(&mut self.transfers, transfers);
+        merge(&mut self.interchanges, interchanges);
+        merge_with_id(&mut self.trip_properties, trip_properties, "trip property")?;
+        merge_with_id(&mut self.geometries, geometries, "geometry")?;
+        merge(&mut self.admin_stations, admin_stations);
+        self.stop_time_headsigns.extend(stop_time_headsigns);
+        self.stop_time_ids.extend(stop_time_ids);
+        self.stop_time_comments.extend(stop_time_comments);
+        merge(&mut self.prices_v1, prices_v1);
+        merge(&mut self.od_fares_v1, od_fares_v1);
+        merge(&mut self.fares_v1, fares_v1);
+        merge_with_id(&mut self.tickets, tickets, "ticket")?;
+        merge_with_id(&mut self.ticket_uses, ticket_uses, "ticket use")?;
+        merge(&mut self.ticket_prices, ticket_prices);
+        merge(&mut self.ticket_use_perimeters, ticket_use_perimeters);
+        merge(&mut self.ticket_use_restrictions, ticket_use_restrictions);
+        merge_with_id(&mut self.pathways, pathways, "pathway")?;
+        merge_with_id(&mut self.levels, levels, "level")?;
+        merge_with_id(&mut self.grid_calendars, grid_calendars, "grid calendar")?;
+        merge(&mut self.grid_exception_dates, grid_exception_dates);
+        merge(&mut self.grid_periods, grid_periods);
+        merge(&mut self.grid_rel_calendar_line, grid_rel_calendar_line);
+        merge_missing(&mut self.addresses, addresses);
+        merge_missing(&mut self.administrative_regions, administrative_regions);
+        merge_missing(&mut self.poi_types, poi_types);
+        merge_with_id(&mut self.pois, pois, "point of interest")?;
+        merge(&mut self.occupancies, occupancies);
+        merge(&mut self.stop_time_occupancies, stop_time_occupancies);
+
+        // the stop times reference the stop points by index, they are
+        // remapped on the indices of the merged stop points
+        let stop_point_ids: HashMap<Idx<StopPoint>, String> = stop_points
+            .iter()
+            .map(|(idx, stop_point)| (idx, stop_point.id.clone()))
+            .collect();
+        merge_with_id(&mut self.stop_points, stop_points, "stop point")?;
+        let mut vehicle_journeys = vehicle_journeys.take();
+        for vehicle_journey in &mut vehicle_journeys {
+            for stop_time in &mut vehicle_journey.stop_times {
+                // all the stop points have been merged just above
+                stop_time.stop_point_idx = self
+                    .stop_points
+                    .get_idx(&stop_point_ids[&stop_time.stop_point_idx])
+                    .unwrap();
+            }
+        }
+        merge_with_id(
+            &mut self.vehicle_journeys,
+            CollectionWithId::new(vehicle_journeys)?,
+            "vehicle journey",
+        )?;
+        Ok(())
+    }
+}
+
+/// A departure of a vehicle journey from a stop point, as returned by
+/// `Model::departures_at`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Departure {
+    /// The departing vehicle journey
+    pub vehicle_journey_idx: Idx<VehicleJourney>,
+    /// Position of the stop time in the stop times of the vehicle journey
+    pub stop_time_position: usize,
+    /// Day of service of the vehicle journey, the day before the requested
+    /// date for a stop time after midnight
+    pub service_date: Date,
+    /// Time of the departure on the requested date
+    pub departure_time: Time,
+}
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+// A departure of the index built by `Model::departures_at`
+#[derive(Debug, Clone, Copy)]
+struct IndexedDeparture {
+    time_of_day: Time,
+    days_offset: u32,
+    vehicle_journey_idx: Idx<VehicleJourney>,
+    stop_time_position: usize,
+}
+
+// Departures from each stop point, sorted by time of the day
+type DepartureIndex = HashMap<Idx<StopPoint>, Vec<IndexedDeparture>>;
+
+// The set of the index `idx` alone, to navigate a relation from one object
+fn one<T>(idx: Idx<T>) -> IdxSet<T> {
+    std::iter::once(idx).collect()
+}
+
+// The first index of `idxs`, the only one when navigating a relation
+// towards the parent of an object
+fn first<T>(idxs: IdxSet<T>) -> Option<Idx<T>> {
+    idxs.into_iter().next()
 }
 
 /// The navitia transit model.
 #[derive(GetCorresponding)]
 pub struct Model {
     collections: Collections,
+    // built on demand by `departures_at`
+    departures: OnceCell<DepartureIndex>,
+    // built on demand by `objects_by_code`
+    codes: OnceCell<CodeIndex>,
 
     // original relations
     networks_to_lines: OneToMany<Network, Line>,
@@ -1521,10 +2040,33 @@ impl Model {
     ///     min_transfer_time: None,
     ///     real_min_transfer_time: None,
     ///     equipment_id: None,
+    ///     transfer_mode: None,
+    ///     ..Default::default()
     /// });
     /// assert!(Model::new(collections).is_ok());
     /// ```
-    pub fn new(mut c: Collections) -> Result<Self> {
+    pub fn new(c: Collections) -> Result<Self> {
+        let (model, route_report) = Self::new_with_report(c)?;
+        for entry in route_report.infos().iter().chain(route_report.warnings()) {
+            debug!("{}", entry.message);
+        }
+        Ok(model)
+    }
+
+    /// Constructs a model like `Model::new`, also returning the `Report` of
+    /// the route names and destinations generated from the stop areas of the
+    /// trips (see `Collections::enhance_route_names_with_report`).
+    ///
+    /// ```
+    /// # use transit_model::model::*;
+    /// # fn run() -> transit_model::Result<()> {
+    /// let (_, route_report) = Model::new_with_report(Collections::default())?;
+    /// assert!(route_report.is_empty());
+    /// # Ok(())
+    /// # }
+    /// # run().unwrap()
+    /// ```
+    pub fn new_with_report(mut c: Collections) -> Result<(Self, Report<RouteReportCategory>)> {
         enhancers::check_stop_times_order(&mut c);
         c.comment_deduplication();
         c.clean_comments();
@@ -1613,7 +2155,8 @@ impl Model {
         c.update_stop_area_coords();
         enhancers::fill_co2(&mut c);
         c.enhance_trip_headsign();
-        c.enhance_route_names(&routes_to_vehicle_journeys);
+        let route_report = c.enhance_route_names_with_report(&routes_to_vehicle_journeys);
+        enhancers::infer_route_directions(&mut c, &routes_to_vehicle_journeys);
         c.enhance_route_directions();
         c.check_geometries_coherence();
         enhancers::adjust_lines_names(&mut c, &lines_to_routes);
@@ -1622,7 +2165,7 @@ impl Model {
         enhancers::enhance_pickup_dropoff(&mut c);
         enhancers::memory_shrink(&mut c);
 
-        Ok(Model {
+        let model = Model {
             routes_to_stop_points,
             physical_modes_to_stop_points,
             physical_modes_to_routes,
@@ -1642,7 +2185,514 @@ impl Model {
             companies_to_vehicle_journeys,
             calendars_to_vehicle_journeys,
             collections: c,
-        })
+            departures: OnceCell::new(),
+            codes: OnceCell::new(),
+        };
+        Ok((model, route_report))
+    }
+
+    /// Departures from the stop point `stop_point_idx` on `date` whose time
+    /// of the day is in `time_window`, in chronological order.
+    ///
+    /// The stop times after midnight of the vehicle journeys running the day
+    /// before `date` are included. The last stop times of the vehicle journeys
+    /// and the ones not allowing the boarding are excluded; the frequencies
+    /// are not expanded.
+    ///
+    /// The index of the departures of all the stop points is built by the
+    /// first call and kept for the next ones.
+    ///
+    /// ```
+    /// # use transit_model::objects::{Date, Time};
+    /// # fn main() {
+    /// let model = transit_model_builder::ModelBuilder::default()
+    ///     .calendar("c1", &["2020-01-01"])
+    ///     .vj("vj1", |vj| {
+    ///         vj.calendar("c1")
+    ///             .st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .build();
+    /// let departures = model.departures_at(
+    ///     model.stop_points.get_idx("A").unwrap(),
+    ///     Date::from_ymd_opt(2020, 1, 1).unwrap(),
+    ///     Time::new(10, 0, 0)..Time::new(11, 0, 0),
+    /// );
+    /// assert_eq!(1, departures.len());
+    /// assert_eq!(Time::new(10, 1, 0), departures[0].departure_time);
+    /// # }
+    /// ```
+    pub fn departures_at(
+        &self,
+        stop_point_idx: Idx<StopPoint>,
+        date: Date,
+        time_window: ops::Range<Time>,
+    ) -> Vec<Departure> {
+        let departures = match self
+            .departures
+            .get_or_init(|| self.build_departure_index())
+            .get(&stop_point_idx)
+        {
+            Some(departures) => departures,
+            None => return Vec::new(),
+        };
+        let start =
+            departures.partition_point(|departure| departure.time_of_day < time_window.start);
+        departures[start..]
+            .iter()
+            .take_while(|departure| departure.time_of_day < time_window.end)
+            .filter_map(|departure| {
+                let service_date = date - chrono::Duration::days(i64::from(departure.days_offset));
+                let vehicle_journey = &self.vehicle_journeys[departure.vehicle_journey_idx];
+                self.calendars
+                    .get(&vehicle_journey.service_id)
+                    .filter(|calendar| calendar.dates.contains(&service_date))
+                    .map(|_| Departure {
+                        vehicle_journey_idx: departure.vehicle_journey_idx,
+                        stop_time_position: departure.stop_time_position,
+                        service_date,
+                        departure_time: departure.time_of_day,
+                    })
+            })
+            .collect()
+    }
+
+    /// Objects having the code `code` in the system `system` (e.g. `UIC`),
+    /// among the networks, companies, lines, routes, vehicle journeys, stop
+    /// areas and stop points.
+    ///
+    /// The index of the codes of all the objects is built by the first call
+    /// and kept for the next ones.
+    ///
+    /// ```
+    /// # use transit_model::codes::CodedObject;
+    /// # fn main() {
+    /// let model = transit_model_builder::ModelBuilder::default()
+    ///     .vj("vj1", |vj| {
+    ///         vj.st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .build();
+    /// assert!(model.objects_by_code("UIC", "87686006").is_empty());
+    /// # }
+    /// ```
+    pub fn objects_by_code(&self, system: &str, code: &str) -> &[CodedObject] {
+        self.codes
+            .get_or_init(|| CodeIndex::new(&self.collections))
+            .get(system, code)
+    }
+
+    /// The object of type `T` having the code `code` in the system `system`,
+    /// the first one in the order of the collection if several objects share
+    /// the code. Useful to match external identifiers (e.g. of real-time
+    /// feeds) with the objects of the model.
+    ///
+    /// ```
+    /// # use transit_model::objects::StopPoint;
+    /// # fn main() {
+    /// let mut collections = transit_model_builder::ModelBuilder::default()
+    ///     .vj("vj1", |vj| {
+    ///         vj.st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .build()
+    ///     .into_collections();
+    /// collections
+    ///     .stop_points
+    ///     .get_mut("A")
+    ///     .unwrap()
+    ///     .codes
+    ///     .insert(("UIC".to_string(), "87686006".to_string()));
+    /// let model = transit_model::Model::new(collections).unwrap();
+    /// let stop_point_idx = model.object_by_code::<StopPoint>("UIC", "87686006");
+    /// assert_eq!(model.stop_points.get_idx("A"), stop_point_idx);
+    /// # }
+    /// ```
+    pub fn object_by_code<T: CodedType>(&self, system: &str, code: &str) -> Option<Idx<T>> {
+        self.codes
+            .get_or_init(|| CodeIndex::new(&self.collections))
+            .object(system, code)
+    }
+
+    /// Lines of the network `network_idx`.
+    ///
+    /// The navigation methods of the model (`lines_of_network`,
+    /// `network_of_line`, `vehicle_journeys_of_line`...) read the relations
+    /// built with the model, without any scan of the collections.
+    ///
+    /// ```
+    /// # fn main() {
+    /// let model = transit_model_builder::ModelBuilder::default()
+    ///     .vj("vj1", |vj| {
+    ///         vj.st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .build();
+    /// let network_idx = model.networks.get_idx("default_network").unwrap();
+    /// let line_idxs = model.lines_of_network(network_idx);
+    /// assert_eq!(model.lines.get_idx("default_line"), line_idxs.into_iter().next());
+    /// # }
+    /// ```
+    pub fn lines_of_network(&self, network_idx: Idx<Network>) -> IdxSet<Line> {
+        self.networks_to_lines
+            .get_corresponding_forward(&one(network_idx))
+    }
+
+    /// Network of the line `line_idx`.
+    pub fn network_of_line(&self, line_idx: Idx<Line>) -> Option<Idx<Network>> {
+        first(
+            self.networks_to_lines
+                .get_corresponding_backward(&one(line_idx)),
+        )
+    }
+
+    /// Lines of the commercial mode `commercial_mode_idx`.
+    pub fn lines_of_commercial_mode(
+        &self,
+        commercial_mode_idx: Idx<CommercialMode>,
+    ) -> IdxSet<Line> {
+        self.commercial_modes_to_lines
+            .get_corresponding_forward(&one(commercial_mode_idx))
+    }
+
+    /// Routes of the line `line_idx`.
+    pub fn routes_of_line(&self, line_idx: Idx<Line>) -> IdxSet<Route> {
+        self.lines_to_routes
+            .get_corresponding_forward(&one(line_idx))
+    }
+
+    /// Line of the route `route_idx`.
+    pub fn line_of_route(&self, route_idx: Idx<Route>) -> Option<Idx<Line>> {
+        first(
+            self.lines_to_routes
+                .get_corresponding_backward(&one(route_idx)),
+        )
+    }
+
+    /// Vehicle journeys of the route `route_idx`.
+    pub fn vehicle_journeys_of_route(&self, route_idx: Idx<Route>) -> IdxSet<VehicleJourney> {
+        self.routes_to_vehicle_journeys
+            .get_corresponding_forward(&one(route_idx))
+    }
+
+    /// Route of the vehicle journey `vehicle_journey_idx`.
+    pub fn route_of_vehicle_journey(
+        &self,
+        vehicle_journey_idx: Idx<VehicleJourney>,
+    ) -> Option<Idx<Route>> {
+        first(
+            self.routes_to_vehicle_journeys
+                .get_corresponding_backward(&one(vehicle_journey_idx)),
+        )
+    }
+
+    /// Vehicle journeys of all the routes of the line `line_idx`.
+    ///
+    /// ```
+    /// # fn main() {
+    /// let model = transit_model_builder::ModelBuilder::default()
+    ///     .vj("vj1", |vj| {
+    ///         vj.st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .vj("vj2", |vj| {
+    ///         vj.st("B", "12:00:00", "12:01:00")
+    ///             .st("A", "13:00:00", "13:01:00");
+    ///     })
+    ///     .build();
+    /// let line_idx = model.lines.get_idx("default_line").unwrap();
+    /// assert_eq!(2, model.vehicle_journeys_of_line(line_idx).len());
+    /// # }
+    /// ```
+    pub fn vehicle_journeys_of_line(&self, line_idx: Idx<Line>) -> IdxSet<VehicleJourney> {
+        self.routes_to_vehicle_journeys
+            .get_corresponding_forward(&self.routes_of_line(line_idx))
+    }
+
+    /// Vehicle journeys of the physical mode `physical_mode_idx`.
+    pub fn vehicle_journeys_of_physical_mode(
+        &self,
+        physical_mode_idx: Idx<PhysicalMode>,
+    ) -> IdxSet<VehicleJourney> {
+        self.physical_modes_to_vehicle_journeys
+            .get_corresponding_forward(&one(physical_mode_idx))
+    }
+
+    /// Vehicle journeys operated by the company `company_idx`.
+    pub fn vehicle_journeys_of_company(&self, company_idx: Idx<Company>) -> IdxSet<VehicleJourney> {
+        self.companies_to_vehicle_journeys
+            .get_corresponding_forward(&one(company_idx))
+    }
+
+    /// Vehicle journeys stopping at the stop point `stop_point_idx`.
+    pub fn vehicle_journeys_of_stop_point(
+        &self,
+        stop_point_idx: Idx<StopPoint>,
+    ) -> IdxSet<VehicleJourney> {
+        self.vehicle_journeys_to_stop_points
+            .get_corresponding_backward(&one(stop_point_idx))
+    }
+
+    /// Stop points of the stop area `stop_area_idx`.
+    ///
+    /// ```
+    /// # fn main() {
+    /// let model = transit_model_builder::ModelBuilder::default()
+    ///     .vj("vj1", |vj| {
+    ///         vj.st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .build();
+    /// let stop_point_idx = model.stop_points.get_idx("A").unwrap();
+    /// let stop_area_idx = model.stop_area_of_stop_point(stop_point_idx).unwrap();
+    /// assert!(model
+    ///     .stop_points_of_stop_area(stop_area_idx)
+    ///     .contains(&stop_point_idx));
+    /// # }
+    /// ```
+    pub fn stop_points_of_stop_area(&self, stop_area_idx: Idx<StopArea>) -> IdxSet<StopPoint> {
+        self.stop_areas_to_stop_points
+            .get_corresponding_forward(&one(stop_area_idx))
+    }
+
+    /// Stop area of the stop point `stop_point_idx`.
+    pub fn stop_area_of_stop_point(&self, stop_point_idx: Idx<StopPoint>) -> Option<Idx<StopArea>> {
+        first(
+            self.stop_areas_to_stop_points
+                .get_corresponding_backward(&one(stop_point_idx)),
+        )
+    }
+
+    /// Stop points served by the vehicle journeys of the route `route_idx`.
+    pub fn stop_points_of_route(&self, route_idx: Idx<Route>) -> IdxSet<StopPoint> {
+        self.routes_to_stop_points
+            .get_corresponding_forward(&one(route_idx))
+    }
+
+    /// Routes whose vehicle journeys serve the stop point `stop_point_idx`.
+    pub fn routes_of_stop_point(&self, stop_point_idx: Idx<StopPoint>) -> IdxSet<Route> {
+        self.routes_to_stop_points
+            .get_corresponding_backward(&one(stop_point_idx))
+    }
+
+    /// Lines whose vehicle journeys serve the stop point `stop_point_idx`.
+    pub fn lines_of_stop_point(&self, stop_point_idx: Idx<StopPoint>) -> IdxSet<Line> {
+        self.lines_to_routes
+            .get_corresponding_backward(&self.routes_of_stop_point(stop_point_idx))
+    }
+
+    /// Datasets of the contributor `contributor_idx`.
+    pub fn datasets_of_contributor(&self, contributor_idx: Idx<Contributor>) -> IdxSet<Dataset> {
+        self.contributors_to_datasets
+            .get_corresponding_forward(&one(contributor_idx))
+    }
+
+    fn line_id(&self, vehicle_journey: &VehicleJourney) -> Option<&str> {
+        self.routes
+            .get(&vehicle_journey.route_id)
+            .map(|route| route.line_id.as_str())
+    }
+
+    /// Number of trips of each line (by identifier) on each day of service.
+    ///
+    /// ```
+    /// # use transit_model::objects::Date;
+    /// # fn main() {
+    /// let model = transit_model_builder::ModelBuilder::default()
+    ///     .calendar("c1", &["2020-01-01", "2020-01-02"])
+    ///     .calendar("c2", &["2020-01-02"])
+    ///     .vj("vj1", |vj| {
+    ///         vj.calendar("c1")
+    ///             .st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .vj("vj2", |vj| {
+    ///         vj.calendar("c2")
+    ///             .st("A", "12:00:00", "12:01:00")
+    ///             .st("B", "13:00:00", "13:01:00");
+    ///     })
+    ///     .build();
+    /// let trips = model.trips_per_line_per_day();
+    /// let date = Date::from_ymd_opt(2020, 1, 2).unwrap();
+    /// assert_eq!(Some(&2), trips[&date].get("default_line"));
+    /// # }
+    /// ```
+    pub fn trips_per_line_per_day(&self) -> BTreeMap<Date, BTreeMap<&str, usize>> {
+        let mut trips: BTreeMap<Date, BTreeMap<&str, usize>> = BTreeMap::new();
+        for vehicle_journey in self.vehicle_journeys.values() {
+            let line_id = match self.line_id(vehicle_journey) {
+                Some(line_id) => line_id,
+                None => continue,
+            };
+            if let Some(calendar) = self.calendars.get(&vehicle_journey.service_id) {
+                for date in &calendar.dates {
+                    *trips.entry(*date).or_default().entry(line_id).or_default() += 1;
+                }
+            }
+        }
+        trips
+    }
+
+    /// Number of journey patterns of each line (by identifier), a journey
+    /// pattern being a distinct sequence of stop points served by trips of
+    /// the line.
+    ///
+    /// ```
+    /// # fn main() {
+    /// let model = transit_model_builder::ModelBuilder::default()
+    ///     .vj("vj1", |vj| {
+    ///         vj.st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .vj("vj2", |vj| {
+    ///         vj.st("A", "12:00:00", "12:01:00")
+    ///             .st("B", "13:00:00", "13:01:00");
+    ///     })
+    ///     .vj("vj3", |vj| {
+    ///         vj.st("B", "12:00:00", "12:01:00")
+    ///             .st("A", "13:00:00", "13:01:00");
+    ///     })
+    ///     .build();
+    /// assert_eq!(Some(&2), model.journey_patterns_per_line().get("default_line"));
+    /// # }
+    /// ```
+    pub fn journey_patterns_per_line(&self) -> BTreeMap<&str, usize> {
+        let mut patterns: BTreeMap<&str, HashSet<Vec<Idx<StopPoint>>>> = BTreeMap::new();
+        for vehicle_journey in self.vehicle_journeys.values() {
+            if let Some(line_id) = self.line_id(vehicle_journey) {
+                patterns.entry(line_id).or_default().insert(
+                    vehicle_journey
+                        .stop_times
+                        .iter()
+                        .map(|stop_time| stop_time.stop_point_idx)
+                        .collect(),
+                );
+            }
+        }
+        patterns
+            .into_iter()
+            .map(|(line_id, patterns)| (line_id, patterns.len()))
+            .collect()
+    }
+
+    /// Estimation of the number of vehicles required at the peak of each
+    /// line (by identifier) on each day of service.
+    ///
+    /// The trips of a line sharing a `block_id` are run by the same vehicle,
+    /// from the first departure to the last arrival of the trips of the
+    /// block; the other trips are each run by their own vehicle. The peak is
+    /// the maximum number of vehicles running at the same time, a vehicle
+    /// arriving being available for a departure at the same time. The
+    /// frequencies are not expanded.
+    ///
+    /// ```
+    /// # use transit_model::objects::Date;
+    /// # fn main() {
+    /// let model = transit_model_builder::ModelBuilder::default()
+    ///     .calendar("c1", &["2020-01-01"])
+    ///     .vj("vj1", |vj| {
+    ///         vj.calendar("c1")
+    ///             .st("A", "10:00:00", "10:01:00")
+    ///             .st("B", "11:00:00", "11:01:00");
+    ///     })
+    ///     .vj("vj2", |vj| {
+    ///         vj.calendar("c1")
+    ///             .st("B", "10:30:00", "10:31:00")
+    ///             .st("A", "11:30:00", "11:31:00");
+    ///     })
+    ///     .build();
+    /// let vehicles = model.peak_vehicles_per_line_per_day();
+    /// let date = Date::from_ymd_opt(2020, 1, 1).unwrap();
+    /// assert_eq!(Some(&2), vehicles[&date].get("default_line"));
+    /// # }
+    /// ```
+    pub fn peak_vehicles_per_line_per_day(&self) -> BTreeMap<Date, BTreeMap<&str, usize>> {
+        // time span of each block of each line on each day
+        let mut blocks: BTreeMap<(Date, &str), HashMap<&str, (Time, Time)>> = BTreeMap::new();
+        for vehicle_journey in self.vehicle_journeys.values() {
+            let (first, last) = match (
+                vehicle_journey.stop_times.first(),
+                vehicle_journey.stop_times.last(),
+            ) {
+                (Some(first), Some(last)) => (first.departure_time, last.arrival_time),
+                _ => continue,
+            };
+            let line_id = match self.line_id(vehicle_journey) {
+                Some(line_id) => line_id,
+                None => continue,
+            };
+            let calendar = match self.calendars.get(&vehicle_journey.service_id) {
+                Some(calendar) => calendar,
+                None => continue,
+            };
+            let block_id = vehicle_journey
+                .block_id
+                .as_deref()
+                .unwrap_or(&vehicle_journey.id);
+            for date in &calendar.dates {
+                let span = blocks
+                    .entry((*date, line_id))
+                    .or_default()
+                    .entry(block_id)
+                    .or_insert((first, last));
+                span.0 = cmp::min(span.0, first);
+                span.1 = cmp::max(span.1, last);
+            }
+        }
+
+        let mut vehicles: BTreeMap<Date, BTreeMap<&str, usize>> = BTreeMap::new();
+        for ((date, line_id), spans) in blocks {
+            // the arrivals (`false`) are sorted before the departures at the
+            // same time
+            let mut events: Vec<(Time, bool)> = spans
+                .values()
+                .flat_map(|(start, end)| vec![(*start, true), (*end, false)])
+                .collect();
+            events.sort_unstable();
+            let mut running = 0usize;
+            let mut peak = 0;
+            for (_, is_departure) in events {
+                if is_departure {
+                    running += 1;
+                    peak = cmp::max(peak, running);
+                } else {
+                    running -= 1;
+                }
+            }
+            vehicles.entry(date).or_default().insert(line_id, peak);
+        }
+        vehicles
+    }
+
+    fn build_departure_index(&self) -> DepartureIndex {
+        let mut index = DepartureIndex::new();
+        for (vehicle_journey_idx, vehicle_journey) in self.vehicle_journeys.iter() {
+            let last_position = vehicle_journey.stop_times.len().saturating_sub(1);
+            for (stop_time_position, stop_time) in vehicle_journey
+                .stop_times
+                .iter()
+                .enumerate()
+                .take(last_position)
+            {
+                if stop_time.pickup_type == 1 {
+                    continue;
+                }
+                let seconds = stop_time.departure_time.total_seconds();
+                index
+                    .entry(stop_time.stop_point_idx)
+                    .or_default()
+                    .push(IndexedDeparture {
+                        time_of_day: Time::new(0, 0, seconds % SECONDS_PER_DAY),
+                        days_offset: seconds / SECONDS_PER_DAY,
+                        vehicle_journey_idx,
+                        stop_time_position,
+                    });
+            }
+        }
+        for departures in index.values_mut() {
+            departures.sort_by_key(|departure| departure.time_of_day);
+        }
+        index
     }
 
     /// Consumes collections,
@@ -1670,6 +2720,13 @@ impl Model {
     pub fn into_collections(self) -> Collections {
         self.collections
     }
+
+    // Mutable access to the collections without checking them again, to build
+    // an inconsistent `Model` in the tests
+    #[cfg(test)]
+    pub(crate) fn collections_mut(&mut self) -> &mut Collections {
+        &mut self.collections
+    }
 }
 
 impl ::serde::Serialize for Model {
@@ -2003,8 +3060,11 @@ mod tests {
         }
     }
 
+    // most of the tests do not need the report of `enhance_route_names`
+    #[allow(deprecated)]
     mod enhance_route_names {
         use super::*;
+        use crate::test_fixtures::one_vehicle_journey;
         use pretty_assertions::assert_eq;
 
         fn stop_areas() -> CollectionWithId<StopArea> {
@@ -2162,6 +3222,62 @@ mod tests {
             assert_eq!("stop_area:2", route.destination_id.as_ref().unwrap());
         }
 
+        #[test]
+        fn replace_unknown_destination_id() {
+            let mut collections = collections();
+            collections
+                .vehicle_journeys
+                .push(create_vehicle_journey_with(
+                    "trip:1",
+                    vec!["stop_point:1", "stop_point:2"],
+                    &collections,
+                ))
+                .unwrap();
+            let route_idx = collections.routes.get_idx("route_id").unwrap();
+            collections.routes.index_mut(route_idx).name = String::from("Route to Mordor");
+            collections.routes.index_mut(route_idx).destination_id =
+                Some(String::from("unknown_stop_area"));
+            let routes_to_vehicle_journeys = OneToMany::new(
+                &collections.routes,
+                &collections.vehicle_journeys,
+                "routes_to_vehicle_journeys",
+            )
+            .unwrap();
+            let report = collections.enhance_route_names_with_report(&routes_to_vehicle_journeys);
+            let route = collections.routes.get("route_id").unwrap();
+            assert_eq!("stop_area:2", route.destination_id.as_ref().unwrap());
+            assert_eq!(1, report.warnings().len());
+            assert_eq!(
+                RouteReportCategory::DestinationGenerated,
+                report.warnings()[0].category
+            );
+        }
+
+        #[test]
+        fn model_with_route_report() {
+            let mut collections = one_vehicle_journey().into_collections();
+            let route_idx = collections.routes.get_idx("default_route").unwrap();
+            collections.routes.index_mut(route_idx).destination_id = None;
+            let (model, report) = Model::new_with_report(collections).unwrap();
+            assert_eq!(
+                "sa:B",
+                model
+                    .routes
+                    .get("default_route")
+                    .unwrap()
+                    .destination_id
+                    .as_ref()
+                    .unwrap()
+            );
+            // a normal inference, not an issue of the dataset
+            assert!(report.warnings().is_empty());
+            assert_eq!(1, report.infos().len());
+            assert_eq!(
+                "destination of route default_route set to stop area sa:B",
+                report.infos()[0].message
+            );
+        }
+
         #[test]
         fn most_frequent_origin_destination() {
             let mut collections = collections();
@@ -2666,4 +3782,439 @@ mod tests {
             assert_eq!(Some(Time::new(26, 30, 0)), line.closing_time); // 25:30 + 1h trip duration
         }
     }
+
+    mod object_by_code {
+        use super::*;
+        use crate::test_fixtures::one_vehicle_journey;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn lookup_by_type() {
+            let mut collections = one_vehicle_journey().into_collections();
+            let code = ("source".to_string(), "12".to_string());
+            collections
+                .stop_points
+                .get_mut("B")
+                .unwrap()
+                .codes
+                .insert(code.clone());
+            collections
+                .vehicle_journeys
+                .get_mut("vj1")
+                .unwrap()
+                .codes
+                .insert(code);
+            let model = Model::new(collections).unwrap();
+
+            assert_eq!(2, model.objects_by_code("source", "12").len());
+            assert_eq!(
+                model.stop_points.get_idx("B"),
+                model.object_by_code::<StopPoint>("source", "12")
+            );
+            assert_eq!(
+                model.vehicle_journeys.get_idx("vj1"),
+                model.object_by_code::<VehicleJourney>("source", "12")
+            );
+            assert_eq!(None, model.object_by_code::<Line>("source", "12"));
+        }
+    }
+
+    mod peak_vehicles_per_line_per_day {
+        use super::*;
+        use crate::test_fixtures::BuildModel;
+        use pretty_assertions::assert_eq;
+        use transit_model_builder::ModelBuilder;
+
+        #[test]
+        fn blocks_share_a_vehicle() {
+            let date = Date::from_ymd_opt(2020, 1, 1).unwrap();
+            let model = ModelBuilder::default()
+                .calendar("c1", &["2020-01-01"])
+                .vj("vj1", |vj| {
+                    vj.calendar("c1")
+                        .block_id("b1")
+                        .st("A", "10:00:00", "10:01:00")
+                        .st("B", "11:00:00", "11:00:00");
+                })
+                .vj("vj2", |vj| {
+                    vj.calendar("c1")
+                        .block_id("b1")
+                        .st("B", "11:10:00", "11:10:00")
+                        .st("A", "12:00:00", "12:00:00");
+                })
+                .vj("vj3", |vj| {
+                    vj.calendar("c1")
+                        .st("A", "11:30:00", "11:30:00")
+                        .st("B", "12:30:00", "12:30:00");
+                })
+                // departing when vj2 arrives
+                .vj("vj4", |vj| {
+                    vj.calendar("c1")
+                        .st("A", "12:00:00", "12:00:00")
+                        .st("B", "13:00:00", "13:00:00");
+                })
+                .build_model();
+            assert_eq!(
+                Some(&2),
+                model.peak_vehicles_per_line_per_day()[&date].get("default_line")
+            );
+            assert_eq!(
+                Some(&4),
+                model.trips_per_line_per_day()[&date].get("default_line")
+            );
+        }
+    }
+
+    mod departures_at {
+        use super::*;
+        use crate::test_fixtures::BuildModel;
+        use pretty_assertions::assert_eq;
+        use transit_model_builder::ModelBuilder;
+
+        fn departures(
+            model: &Model,
+            stop_point_id: &str,
+            date: &str,
+            time_window: ops::Range<Time>,
+        ) -> Vec<(String, String, Time)> {
+            model
+                .departures_at(
+                    model.stop_points.get_idx(stop_point_id).unwrap(),
+                    date.parse().unwrap(),
+                    time_window,
+                )
+                .into_iter()
+                .map(|departure| {
+                    (
+                        model.vehicle_journeys[departure.vehicle_journey_idx]
+                            .id
+                            .clone(),
+                        departure.service_date.to_string(),
+                        departure.departure_time,
+                    )
+                })
+                .collect()
+        }
+
+        // vj2 leaving A before midnight and serving B and C after midnight
+        fn model_with_night_service() -> Model {
+            ModelBuilder::default()
+                .calendar("c1", &["2020-01-01"])
+                .vj("vj1", |vj| {
+                    vj.calendar("c1")
+                        .st("A", "10:00:00", "10:00:00")
+                        .st("B", "10:30:00", "10:30:00");
+                })
+                .vj("vj2", |vj| {
+                    vj.calendar("c1")
+                        .st("A", "23:50:00", "23:50:00")
+                        .st("B", "24:10:00", "24:10:00")
+                        .st("C", "24:30:00", "24:30:00");
+                })
+                .build_model()
+        }
+
+        #[test]
+        fn departures_of_the_day() {
+            let model = model_with_night_service();
+            assert_eq!(
+                vec![
+                    (
+                        "vj1".to_string(),
+                        "2020-01-01".to_string(),
+                        Time::new(10, 0, 0)
+                    ),
+                    (
+                        "vj2".to_string(),
+                        "2020-01-01".to_string(),
+                        Time::new(23, 50, 0)
+                    ),
+                ],
+                departures(
+                    &model,
+                    "A",
+                    "2020-01-01",
+                    Time::new(0, 0, 0)..Time::new(24, 0, 0)
+                )
+            );
+            assert!(departures(
+                &model,
+                "A",
+                "2020-01-02",
+                Time::new(0, 0, 0)..Time::new(24, 0, 0)
+            )
+            .is_empty());
+        }
+
+        #[test]
+        fn departures_in_time_window() {
+            let model = model_with_night_service();
+            assert_eq!(
+                vec![(
+                    "vj2".to_string(),
+                    "2020-01-01".to_string(),
+                    Time::new(23, 50, 0)
+                )],
+                departures(
+                    &model,
+                    "A",
+                    "2020-01-01",
+                    Time::new(11, 0, 0)..Time::new(24, 0, 0)
+                )
+            );
+        }
+
+        #[test]
+        fn departures_after_midnight() {
+            let model = model_with_night_service();
+            assert_eq!(
+                vec![(
+                    "vj2".to_string(),
+                    "2020-01-01".to_string(),
+                    Time::new(0, 10, 0)
+                )],
+                departures(
+                    &model,
+                    "B",
+                    "2020-01-02",
+                    Time::new(0, 0, 0)..Time::new(1, 0, 0)
+                )
+            );
+            // last stop of the vehicle journeys
+            assert!(departures(
+                &model,
+                "C",
+                "2020-01-02",
+                Time::new(0, 0, 0)..Time::new(24, 0, 0)
+            )
+            .is_empty());
+        }
+    }
+
+    mod split_vehicle_journeys {
+        use super::*;
+        use crate::test_fixtures::BuildModel;
+        use pretty_assertions::assert_eq;
+        use transit_model_builder::ModelBuilder;
+
+        fn stop_point_ids(collections: &Collections, vj_id: &str) -> Vec<String> {
+            collections
+                .vehicle_journeys
+                .get(vj_id)
+                .unwrap()
+                .stop_times
+                .iter()
+                .map(|stop_time| collections.stop_points[stop_time.stop_point_idx].id.clone())
+                .collect()
+        }
+
+        fn four_stops() -> Collections {
+            ModelBuilder::default()
+                .vj("vj1", |vj| {
+                    vj.st("A", "10:00:00", "10:01:00")
+                        .st("B", "11:00:00", "11:01:00")
+                        .st("C", "12:00:00", "12:01:00")
+                        .st("D", "13:00:00", "13:01:00");
+                })
+                .build_model()
+                .into_collections()
+        }
+
+        #[test]
+        fn split_at_stop() {
+            let mut collections = four_stops();
+            collections
+                .stop_time_headsigns
+                .insert(("vj1".to_string(), 3), "D".to_string());
+            let vj_ids = vec!["vj1".to_string()].into_iter().collect();
+            let new_ids = collections.split_vehicle_journeys(&vj_ids, "B").unwrap();
+
+            assert_eq!(vec!["vj1:2".to_string()], new_ids);
+            assert_eq!(vec!["A", "B"], stop_point_ids(&collections, "vj1"));
+            assert_eq!(vec!["B", "C", "D"], stop_point_ids(&collections, "vj1:2"));
+            let vj1 = collections.vehicle_journeys.get("vj1").unwrap();
+            let vj1_2 = collections.vehicle_journeys.get("vj1:2").unwrap();
+            assert_eq!(Some("vj1".to_string()), vj1.block_id);
+            assert_eq!(vj1.block_id, vj1_2.block_id);
+            assert_eq!(1, vj1.stop_times[1].pickup_type);
+            assert_eq!(1, vj1_2.stop_times[0].drop_off_type);
+            assert_eq!(
+                Some(&"D".to_string()),
+                collections
+                    .stop_time_headsigns
+                    .get(&("vj1:2".to_string(), 3))
+            );
+            assert!(!collections
+                .stop_time_headsigns
+                .contains_key(&("vj1".to_string(), 3)));
+        }
+
+        #[test]
+        fn not_split_at_terminus() {
+            let mut collections = ModelBuilder::default()
+                .vj("vj2", |vj| {
+                    vj.st("A", "10:00:00", "10:01:00")
+                        .st("B", "11:00:00", "11:01:00");
+                })
+                .build_model()
+                .into_collections();
+            let vj_ids = vec!["vj2".to_string()].into_iter().collect();
+            let new_ids = collections.split_vehicle_journeys(&vj_ids, "B").unwrap();
+
+            assert!(new_ids.is_empty());
+            assert_eq!(vec!["A", "B"], stop_point_ids(&collections, "vj2"));
+        }
+
+        #[test]
+        fn unknown_stop_point() {
+            let mut collections = four_stops();
+            let vj_ids = vec!["vj1".to_string()].into_iter().collect();
+            assert!(collections
+                .split_vehicle_journeys(&vj_ids, "unknown")
+                .is_err());
+        }
+    }
+
+    mod try_merge {
+        use super::*;
+        use crate::test_fixtures::{one_vehicle_journey, BuildModel};
+        use crate::{AddPrefix, PrefixConfiguration};
+        use pretty_assertions::assert_eq;
+        use transit_model_builder::ModelBuilder;
+
+        #[test]
+        fn merge_and_remap_stop_times() {
+            let mut collections = one_vehicle_journey().into_collections();
+            let mut other = ModelBuilder::default()
+                .vj("vj2", |vj| {
+                    vj.st("C", "10:00:00", "10:01:00")
+                        .st("D", "11:00:00", "11:01:00");
+                })
+                .build_model()
+                .into_collections();
+            other.prefix(&{
+                let mut prefix_conf = PrefixConfiguration::default();
+                prefix_conf.set_data_prefix("other");
+                prefix_conf
+            });
+            collections.try_merge(other).unwrap();
+
+            assert_eq!(4, collections.stop_points.len());
+            assert_eq!(2, collections.networks.len());
+            let vj = collections.vehicle_journeys.get("other:vj2").unwrap();
+            let stop_point_ids: Vec<_> = vj
+                .stop_times
+                .iter()
+                .map(|stop_time| {
+                    collections.stop_points[stop_time.stop_point_idx]
+                        .id
+                        .as_str()
+                })
+                .collect();
+            assert_eq!(vec!["other:C", "other:D"], stop_point_ids);
+        }
+
+        #[test]
+        fn conflicting_identifiers() {
+            let model = || {
+                ModelBuilder::default()
+                    .vj("vj1", |vj| {
+                        vj.st("A", "10:00:00", "10:01:00");
+                    })
+                    .build_model()
+                    .into_collections()
+            };
+            let mut collections = model();
+            let error = collections.try_merge(model()).unwrap_err();
+            assert_eq!(
+                "contributor default_contributor cannot be merged",
+                error.to_string()
+            );
+        }
+    }
+
+    mod transliterate_to_ascii {
+        use super::*;
+        use crate::test_fixtures::BuildModel;
+        use pretty_assertions::assert_eq;
+        use transit_model_builder::ModelBuilder;
+
+        #[test]
+        fn only_texts_transliterated() {
+            let mut collections = ModelBuilder::default()
+                .vj("vj1", |vj| {
+                    vj.st("Opéra", "10:00:00", "10:01:00")
+                        .st("B", "11:00:00", "11:01:00");
+                })
+                .build_model()
+                .into_collections();
+            {
+                let mut stop_point = collections.stop_points.get_mut("Opéra").unwrap();
+                stop_point.name = "Opéra – Hôtel de Ville".to_string();
+            }
+            {
+                let mut vj = collections.vehicle_journeys.get_mut("vj1").unwrap();
+                vj.headsign = Some("Straße".to_string());
+                vj.codes
+                    .insert(("source".to_string(), "Brücke".to_string()));
+            }
+            collections
+                .stop_time_headsigns
+                .insert(("vj1".to_string(), 1), "Gare de l'Est → Nation".to_string());
+
+            collections.transliterate_to_ascii();
+
+            let stop_point = collections.stop_points.get("Opéra").unwrap();
+            assert_eq!("Opera - Hotel de Ville", stop_point.name);
+            let vj = collections.vehicle_journeys.get("vj1").unwrap();
+            assert_eq!(Some("Strasse"), vj.headsign.as_deref());
+            assert!(vj
+                .codes
+                .contains(&("source".to_string(), "Brücke".to_string())));
+            assert_eq!(
+                "Gare de l'Est -> Nation",
+                collections.stop_time_headsigns[&("vj1".to_string(), 1)]
+            );
+        }
+    }
+
+    mod transfer_deduplication {
+        use super::*;
+        use crate::test_fixtures::one_vehicle_journey;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn keep_the_windows_of_the_transfers_between_the_same_stops() {
+            let mut collections = one_vehicle_journey().into_collections();
+            let transfer = |start_hour: u32, end_hour: u32| Transfer {
+                from_stop_id: "A".to_string(),
+                to_stop_id: "B".to_string(),
+                min_transfer_time: Some(60),
+                start_time: Some(Time::new(start_hour, 0, 0)),
+                end_time: Some(Time::new(end_hour, 0, 0)),
+                ..Default::default()
+            };
+            collections.transfers = Collection::new(vec![
+                transfer(7, 12),
+                transfer(12, 21),
+                // a duplicate of the first transfer
+                transfer(7, 12),
+            ]);
+            let model = Model::new(collections).unwrap();
+
+            let mut windows: Vec<_> = model
+                .transfers
+                .values()
+                .map(|transfer| (transfer.start_time, transfer.end_time))
+                .collect();
+            windows.sort();
+            assert_eq!(
+                vec![
+                    (Some(Time::new(7, 0, 0)), Some(Time::new(12, 0, 0))),
+                    (Some(Time::new(12, 0, 0)), Some(Time::new(21, 0, 0))),
+                ],
+                windows
+            );
+        }
+    }
 }
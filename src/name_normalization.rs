@@ -0,0 +1,301 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Normalization of the names of the stops and of the lines (whitespaces,
+//! operator prefixes, abbreviations and casing), for the sources with dirty
+//! names (e.g. the exports of AVL systems).
+
+use crate::{model::Collections, report::Report, Result};
+use anyhow::Context;
+use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs::File, path::Path};
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Id};
+
+/// Casing applied to the names
+#[derive(Derivative, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Casing {
+    /// The casing is kept
+    #[derivative(Default)]
+    Keep,
+    /// The first letter of each word is uppercase, the others lowercase
+    Title,
+    /// All the letters are uppercase
+    Upper,
+}
+
+/// Rules of the normalization of the names, applied in this order: removal of
+/// the operator prefixes, expansion of the abbreviations, casing and cleanup
+/// of the whitespaces.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NormalizationRules {
+    /// Prefixes removed from the beginning of the names, ignoring the case
+    /// (e.g. `"RATP - "`)
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+    /// Words replaced by their expansion, ignoring the case (e.g. `"Av."` by
+    /// `"Avenue"`)
+    #[serde(default)]
+    pub abbreviations: BTreeMap<String, String>,
+    /// Casing of the names
+    #[serde(default)]
+    pub casing: Casing,
+    /// Words kept lowercase by the `title` casing, except at the beginning of
+    /// a name (e.g. `"de"`, `"la"`, or `"l"` before an apostrophe as in
+    /// `"l'Est"`)
+    #[serde(default)]
+    pub lowercase_words: Vec<String>,
+}
+
+/// Categories of the `Report` produced by the normalization of the names
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NameNormalizationReportCategory {
+    /// The name of a stop area has been changed
+    StopAreaRenamed,
+    /// The name of a stop point has been changed
+    StopPointRenamed,
+    /// The name of a line has been changed
+    LineRenamed,
+}
+
+/// Read the normalization rules from the JSON file `path`
+pub fn read_rules<P: AsRef<Path>>(path: P) -> Result<NormalizationRules> {
+    let path = path.as_ref();
+    info!("Reading name normalization rules from {:?}", path);
+    let file = File::open(path).with_context(|| format!("Error reading {:?}", path))?;
+    let rules =
+        serde_json::from_reader(file).with_context(|| format!("Error parsing {:?}", path))?;
+    Ok(rules)
+}
+
+fn clean_whitespaces(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+impl NormalizationRules {
+    fn remove_prefixes(&self, name: &str) -> String {
+        let mut name = name.to_string();
+        for prefix in &self.prefixes {
+            let matches = name
+                .get(..prefix.len())
+                .map_or(false, |start| start.eq_ignore_ascii_case(prefix));
+            if matches {
+                name = name[prefix.len()..].trim_start().to_string();
+            }
+        }
+        name
+    }
+
+    fn expand_abbreviations(&self, name: &str) -> String {
+        name.split(' ')
+            .map(|word| {
+                self.abbreviations
+                    .iter()
+                    .find(|(abbreviation, _)| abbreviation.to_lowercase() == word.to_lowercase())
+                    .map_or(word, |(_, expansion)| expansion.as_str())
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn is_lowercase_word(&self, word: &str) -> bool {
+        self.lowercase_words
+            .iter()
+            .any(|lowercase_word| lowercase_word.to_lowercase() == word.to_lowercase())
+    }
+
+    // the words after an apostrophe are always capitalized (e.g. "l'Est")
+    fn title_word(&self, word: &str, is_first: bool) -> String {
+        let keep_lowercase = |word: &str| !is_first && self.is_lowercase_word(word);
+        match word.split_once('\'') {
+            Some((article, rest)) if keep_lowercase(article) => {
+                format!("{}'{}", article.to_lowercase(), capitalize(rest))
+            }
+            Some((article, rest)) => format!("{}'{}", capitalize(article), capitalize(rest)),
+            None if keep_lowercase(word) => word.to_lowercase(),
+            None => capitalize(word),
+        }
+    }
+
+    fn apply_casing(&self, name: &str) -> String {
+        match self.casing {
+            Casing::Keep => name.to_string(),
+            Casing::Upper => name.to_uppercase(),
+            Casing::Title => name
+                .split(' ')
+                .enumerate()
+                .map(|(position, word)| {
+                    word.split('-')
+                        .enumerate()
+                        .map(|(part_position, part)| {
+                            self.title_word(part, position == 0 && part_position == 0)
+                        })
+                        .collect::<Vec<_>>()
+                        .join("-")
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    /// Normalize the name `name`
+    pub fn normalize(&self, name: &str) -> String {
+        let name = clean_whitespaces(name);
+        let name = self.remove_prefixes(&name);
+        let name = self.expand_abbreviations(&name);
+        let name = self.apply_casing(&name);
+        clean_whitespaces(&name)
+    }
+}
+
+fn normalize_collection<T: Id<T>>(
+    collection: &mut CollectionWithId<T>,
+    rules: &NormalizationRules,
+    name: fn(&mut T) -> &mut String,
+    object_type: &str,
+    category: NameNormalizationReportCategory,
+    report: &mut Report<NameNormalizationReportCategory>,
+) {
+    for idx in collection.indexes() {
+        let mut object = collection.index_mut(idx);
+        let before = name(&mut object).clone();
+        let after = rules.normalize(&before);
+        if after != before {
+            report.add_warning(
+                format!(
+                    "{} {}: {:?} renamed {:?}",
+                    object_type,
+                    object.id(),
+                    before,
+                    after
+                ),
+                category,
+            );
+            *name(&mut object) = after;
+        }
+    }
+}
+
+/// Normalize the names of the stop areas, the stop points and the lines of
+/// `collections` following `rules`. Each renaming is listed in the returned
+/// `Report` with the names before and after the normalization.
+pub fn normalize_names(
+    collections: &mut Collections,
+    rules: &NormalizationRules,
+) -> Report<NameNormalizationReportCategory> {
+    let mut report = Report::default();
+    normalize_collection(
+        &mut collections.stop_areas,
+        rules,
+        |stop_area| &mut stop_area.name,
+        "stop area",
+        NameNormalizationReportCategory::StopAreaRenamed,
+        &mut report,
+    );
+    normalize_collection(
+        &mut collections.stop_points,
+        rules,
+        |stop_point| &mut stop_point.name,
+        "stop point",
+        NameNormalizationReportCategory::StopPointRenamed,
+        &mut report,
+    );
+    normalize_collection(
+        &mut collections.lines,
+        rules,
+        |line| &mut line.name,
+        "line",
+        NameNormalizationReportCategory::LineRenamed,
+        &mut report,
+    );
+    info!("{} name(s) normalized", report.warnings().len());
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::one_vehicle_journey;
+    use pretty_assertions::assert_eq;
+
+    fn rules() -> NormalizationRules {
+        NormalizationRules {
+            prefixes: vec!["RATP -".to_string()],
+            abbreviations: vec![
+                ("Av.".to_string(), "Avenue".to_string()),
+                ("St".to_string(), "Saint".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            casing: Casing::Title,
+            lowercase_words: vec!["de".to_string(), "l".to_string()],
+        }
+    }
+
+    #[test]
+    fn normalize_name() {
+        let rules = rules();
+        assert_eq!(
+            "Avenue de la Gare",
+            NormalizationRules {
+                casing: Casing::Keep,
+                ..rules.clone()
+            }
+            .normalize("  av.   de la Gare ")
+        );
+        assert_eq!("Gare de l'Est", rules.normalize("RATP - GARE DE L'EST"));
+        assert_eq!("Saint-Michel", rules.normalize("SAINT-MICHEL"));
+        assert_eq!("Saint Michel", rules.normalize("st michel"));
+        assert_eq!("De Gaulle", rules.normalize("de gaulle"));
+    }
+
+    #[test]
+    fn report_renamed_objects() {
+        let mut collections = one_vehicle_journey().into_collections();
+        collections.stop_points.get_mut("A").unwrap().name = "RATP - AV. FOCH".to_string();
+        collections.stop_points.get_mut("B").unwrap().name = "B".to_string();
+
+        let report = normalize_names(&mut collections, &rules());
+
+        assert_eq!(
+            "Avenue Foch",
+            collections.stop_points.get("A").unwrap().name
+        );
+        let renamed_stop_points: Vec<_> = report
+            .warnings()
+            .iter()
+            .filter(|warning| warning.category == NameNormalizationReportCategory::StopPointRenamed)
+            .map(|warning| warning.message.as_str())
+            .collect();
+        assert_eq!(
+            vec!["stop point A: \"RATP - AV. FOCH\" renamed \"Avenue Foch\""],
+            renamed_stop_points
+        );
+    }
+}
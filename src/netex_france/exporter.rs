@@ -58,6 +58,7 @@ pub(in crate::netex_france) enum ObjectType {
     RoutePoint,
     ScheduledStopPoint,
     ServiceJourney,
+    ServiceJourneyInterchange,
     ServiceJourneyPattern,
     SiteConnection,
     StopPlace,
@@ -84,6 +85,7 @@ impl Display for ObjectType {
             RoutePoint => write!(f, "RoutePoint"),
             ScheduledStopPoint => write!(f, "ScheduledStopPoint"),
             ServiceJourney => write!(f, "ServiceJourney"),
+            ServiceJourneyInterchange => write!(f, "ServiceJourneyInterchange"),
             ServiceJourneyPattern => write!(f, "ServiceJourneyPattern"),
             SiteConnection => write!(f, "SiteConnection"),
             StopPlace => write!(f, "StopPlace"),
@@ -157,7 +159,7 @@ impl<'a> Exporter<'a> {
         self.write_lines(&path)?;
         self.write_stops(&path)?;
         self.write_calendars(&path)?;
-        if !self.model.transfers.is_empty() {
+        if !self.model.transfers.is_empty() || !self.model.interchanges.is_empty() {
             self.write_transfers(&path)?;
         } else {
             info!("Skipping '{}'", NETEX_FRANCE_TRANSFERS_FILENAME);
@@ -408,7 +410,8 @@ impl Exporter<'_> {
         Ok(())
     }
 
-    // Returns a 'GeneralFrame' containing all 'SiteConnection'
+    // Returns a 'GeneralFrame' containing all 'SiteConnection' and
+    // 'ServiceJourneyInterchange'
     fn create_transfers_frame(&self) -> Result<Element> {
         let transfer_exporter = TransferExporter::new(self.model);
         let transfers = transfer_exporter.export()?;
@@ -429,7 +432,7 @@ impl Exporter<'_> {
     where
         P: AsRef<Path>,
     {
-        for network in self.model.networks.values() {
+        for (network_idx, network) in self.model.networks.iter() {
             let network_id_md5 = md5::compute(network.id.as_bytes());
             let folder_name = format!(
                 "reseau_{}_{:x}",
@@ -439,8 +442,6 @@ impl Exporter<'_> {
             let network_path = path.as_ref().join(folder_name);
             fs::create_dir(&network_path)?;
 
-            // Unwrap is safe because we're iterating over existing networks
-            let network_idx = self.model.networks.get_idx(&network.id).unwrap();
             self.write_network_offers(&network_path, network_idx)?;
         }
         Ok(())
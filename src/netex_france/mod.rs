@@ -36,18 +36,20 @@ use stops::StopExporter;
 mod transfers;
 use transfers::TransferExporter;
 
-use crate::{model::Model, Result};
+use crate::{model::Model, stamp::Stamp, Result};
 use chrono::{DateTime, FixedOffset};
 
 /// Configuration options for exporting a NeTEx France.
-/// 3 options can be configured:
+/// 4 options can be configured:
 /// - participant (required): see [specifications](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_to_netex_france_specs.md) for more details
 /// - stop_provider (optional): see [specifications](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_to_netex_france_specs.md) for more details. Default to no stop provider.
 /// - current_datetime (optional): date of the export. Default to the current date of execution in UTC.
+/// - stamp (optional): production metadata written in a 'stamp.json' sidecar file. Default to no stamp.
 pub struct WriteConfiguration {
     participant: String,
     stop_provider: Option<String>,
     current_datetime: DateTime<FixedOffset>,
+    stamp: Option<Stamp>,
 }
 
 impl WriteConfiguration {
@@ -57,6 +59,7 @@ impl WriteConfiguration {
             participant: participant.into(),
             stop_provider: None,
             current_datetime: chrono::Utc::now().with_timezone(&FixedOffset::east_opt(0).unwrap()),
+            stamp: None,
         }
     }
     /// Setup the Stop Provider (see [specifications](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_to_netex_france_specs.md) for more details)
@@ -73,6 +76,13 @@ impl WriteConfiguration {
             ..self
         }
     }
+    /// Setup the production metadata stamped on the export.
+    pub fn stamp(self, stamp: Stamp) -> Self {
+        WriteConfiguration {
+            stamp: Some(stamp),
+            ..self
+        }
+    }
 }
 
 /// Exports a `Model` to the
@@ -89,7 +99,10 @@ pub fn write<P: AsRef<std::path::Path>>(
         config.stop_provider,
         config.current_datetime,
     );
-    exporter.write(path)?;
+    exporter.write(&path)?;
+    if let Some(stamp) = &config.stamp {
+        stamp.write_sidecar(&path)?;
+    }
     Ok(())
 }
 
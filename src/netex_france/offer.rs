@@ -31,7 +31,7 @@ use typed_index_collection::Idx;
 
 // A journey pattern is the sequence of stops of a particular trip.
 // Modelization of JourneyPattern by a VehicleJourney is sufficient for now.
-type JourneyPattern = VehicleJourney;
+pub(in crate::netex_france) type JourneyPattern = VehicleJourney;
 
 pub struct OfferExporter<'a> {
     model: &'a Model,
@@ -71,6 +71,55 @@ fn calculate_route_points(model: &Model) -> BTreeMap<&str, Vec<Idx<StopPoint>>>
         .collect()
 }
 
+// Group the vehicle journeys of a route by journey pattern, a journey pattern
+// being identified by its first vehicle journey (ordered by identifier)
+pub(in crate::netex_france) fn calculate_journey_patterns(
+    model: &Model,
+    route_idx: Idx<Route>,
+) -> Vec<(Idx<JourneyPattern>, Vec<Idx<VehicleJourney>>)> {
+    let same_stop_time = |a: &StopTime, b: &StopTime| {
+        a.stop_point_idx == b.stop_point_idx
+            && a.pickup_type == b.pickup_type
+            && a.drop_off_type == b.drop_off_type
+            && a.local_zone_id == b.local_zone_id
+    };
+    let mut vehicle_journey_indexes: Vec<Idx<VehicleJourney>> = model
+        .get_corresponding_from_idx(route_idx)
+        .into_iter()
+        .collect();
+    vehicle_journey_indexes.sort_unstable_by_key(|vehicle_journey_idx| {
+        &model.vehicle_journeys[*vehicle_journey_idx].id
+    });
+    let mut journey_patterns: Vec<(Idx<JourneyPattern>, Vec<Idx<VehicleJourney>>)> = Vec::new();
+    for vehicle_journey_idx in vehicle_journey_indexes {
+        let vehicle_journey = &model.vehicle_journeys[vehicle_journey_idx];
+        let is_same_journey_pattern = |journey_pattern_idx: Idx<VehicleJourney>| {
+            let journey_pattern_vj = &model.vehicle_journeys[journey_pattern_idx];
+            vehicle_journey.stop_times.len() == journey_pattern_vj.stop_times.len()
+                && vehicle_journey
+                    .stop_times
+                    .iter()
+                    .zip(&journey_pattern_vj.stop_times)
+                    .all(|(stop_time_a, stop_time_b)| same_stop_time(stop_time_a, stop_time_b))
+        };
+        let mut is_new = true;
+        for journey_pattern in &mut journey_patterns {
+            let journey_pattern_idx = journey_pattern.0;
+            let vehicle_journeys = &mut journey_pattern.1;
+            if is_same_journey_pattern(journey_pattern_idx) {
+                is_new = false;
+                vehicle_journeys.push(vehicle_journey_idx);
+            }
+        }
+        if is_new {
+            // If no existing Journey Pattern could be found,
+            // then the current Vehicle Journey become a new Journey Pattern
+            journey_patterns.push((vehicle_journey_idx, vec![vehicle_journey_idx]));
+        }
+    }
+    journey_patterns
+}
+
 // Publicly exposed methods
 impl<'a> OfferExporter<'a> {
     pub fn new(model: &'a Model) -> Result<Self> {
@@ -606,7 +655,11 @@ impl<'a> OfferExporter<'a> {
             })
     }
 
-    fn generate_stop_sequence_id(id: &str, sequence: u32, object_type: ObjectType) -> String {
+    pub(in crate::netex_france) fn generate_stop_sequence_id(
+        id: &str,
+        sequence: u32,
+        object_type: ObjectType,
+    ) -> String {
         let order_id = format!("{}_{}", id, sequence);
         Exporter::generate_id(&order_id, object_type)
     }
@@ -667,48 +720,7 @@ impl<'a> OfferExporter<'a> {
         &self,
         route_idx: Idx<Route>,
     ) -> Vec<(Idx<JourneyPattern>, Vec<Idx<VehicleJourney>>)> {
-        let same_stop_time = |a: &StopTime, b: &StopTime| {
-            a.stop_point_idx == b.stop_point_idx
-                && a.pickup_type == b.pickup_type
-                && a.drop_off_type == b.drop_off_type
-                && a.local_zone_id == b.local_zone_id
-        };
-        let mut vehicle_journey_indexes: Vec<Idx<VehicleJourney>> = self
-            .model
-            .get_corresponding_from_idx(route_idx)
-            .into_iter()
-            .collect();
-        vehicle_journey_indexes.sort_unstable_by_key(|vehicle_journey_idx| {
-            &self.model.vehicle_journeys[*vehicle_journey_idx].id
-        });
-        let mut journey_patterns: Vec<(Idx<JourneyPattern>, Vec<Idx<VehicleJourney>>)> = Vec::new();
-        for vehicle_journey_idx in vehicle_journey_indexes {
-            let vehicle_journey = &self.model.vehicle_journeys[vehicle_journey_idx];
-            let is_same_journey_pattern = |journey_pattern_idx: Idx<VehicleJourney>| {
-                let journey_pattern_vj = &self.model.vehicle_journeys[journey_pattern_idx];
-                vehicle_journey.stop_times.len() == journey_pattern_vj.stop_times.len()
-                    && vehicle_journey
-                        .stop_times
-                        .iter()
-                        .zip(&journey_pattern_vj.stop_times)
-                        .all(|(stop_time_a, stop_time_b)| same_stop_time(stop_time_a, stop_time_b))
-            };
-            let mut is_new = true;
-            for journey_pattern in &mut journey_patterns {
-                let journey_pattern_idx = journey_pattern.0;
-                let vehicle_journeys = &mut journey_pattern.1;
-                if is_same_journey_pattern(journey_pattern_idx) {
-                    is_new = false;
-                    vehicle_journeys.push(vehicle_journey_idx);
-                }
-            }
-            if is_new {
-                // If no existing Journey Pattern could be found,
-                // then the current Vehicle Journey become a new Journey Pattern
-                journey_patterns.push((vehicle_journey_idx, vec![vehicle_journey_idx]));
-            }
-        }
-        journey_patterns
+        calculate_journey_patterns(self.model, route_idx)
     }
 }
 
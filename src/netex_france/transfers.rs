@@ -13,12 +13,18 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
 use crate::{
-    netex_france::exporter::{Exporter, ObjectType},
-    objects::Transfer,
+    netex_france::{
+        exporter::{Exporter, ObjectType},
+        offer::{self, JourneyPattern, OfferExporter},
+    },
+    objects::{Interchange, Transfer, VehicleJourney},
     Model, Result,
 };
 use anyhow::anyhow;
 use minidom::{Element, Node};
+use std::collections::{BTreeSet, HashMap};
+use tracing::warn;
+use typed_index_collection::Idx;
 
 pub struct TransferExporter<'a> {
     model: &'a Model,
@@ -30,11 +36,29 @@ impl<'a> TransferExporter<'a> {
         TransferExporter { model }
     }
     pub fn export(&self) -> Result<Vec<Element>> {
-        self.model
+        let mut elements = self
+            .model
             .transfers
             .values()
+            // the validity of the transfers depending on the time is not exported
+            .filter(|transfer| !transfer.is_time_dependent())
             .map(|transfer| self.export_transfer(transfer))
-            .collect()
+            .collect::<Result<Vec<Element>>>()?;
+        let journey_patterns = self.journey_patterns();
+        elements.extend(self.model.interchanges.values().filter_map(|interchange| {
+            let element = self.export_interchange(interchange, &journey_patterns);
+            if element.is_none() {
+                warn!(
+                    "Interchange from vehicle journey '{}' at stop point '{}' to vehicle journey '{}' at stop point '{}' is ignored, the vehicle journeys don't serve these stop points",
+                    interchange.from_vehicle_journey_id,
+                    interchange.from_stop_point_id,
+                    interchange.to_vehicle_journey_id,
+                    interchange.to_stop_point_id
+                );
+            }
+            element
+        }));
+        Ok(elements)
     }
 }
 
@@ -56,6 +80,143 @@ impl<'a> TransferExporter<'a> {
         Ok(element_builder.build())
     }
 
+    // Journey pattern of the vehicle journeys of the routes having an
+    // interchange, the scheduled stop points being defined by journey pattern
+    fn journey_patterns(&self) -> HashMap<Idx<VehicleJourney>, Idx<JourneyPattern>> {
+        let route_indexes: BTreeSet<_> = self
+            .model
+            .interchanges
+            .values()
+            .flat_map(|interchange| {
+                vec![
+                    &interchange.from_vehicle_journey_id,
+                    &interchange.to_vehicle_journey_id,
+                ]
+            })
+            .filter_map(|vehicle_journey_id| self.model.vehicle_journeys.get(vehicle_journey_id))
+            .filter_map(|vehicle_journey| self.model.routes.get_idx(&vehicle_journey.route_id))
+            .collect();
+        route_indexes
+            .into_iter()
+            .flat_map(|route_idx| offer::calculate_journey_patterns(self.model, route_idx))
+            .flat_map(|(journey_pattern_idx, vehicle_journey_indexes)| {
+                vehicle_journey_indexes
+                    .into_iter()
+                    .map(move |vehicle_journey_idx| (vehicle_journey_idx, journey_pattern_idx))
+            })
+            .collect()
+    }
+
+    // The passengers alight at the last passage of the vehicle journey at
+    // the stop point, and board at the first one
+    fn export_interchange(
+        &self,
+        interchange: &'a Interchange,
+        journey_patterns: &HashMap<Idx<VehicleJourney>, Idx<JourneyPattern>>,
+    ) -> Option<Element> {
+        let from_point_ref = self.generate_scheduled_stop_point_ref(
+            "FromPointRef",
+            &interchange.from_vehicle_journey_id,
+            &interchange.from_stop_point_id,
+            journey_patterns,
+            true,
+        )?;
+        let to_point_ref = self.generate_scheduled_stop_point_ref(
+            "ToPointRef",
+            &interchange.to_vehicle_journey_id,
+            &interchange.to_stop_point_id,
+            journey_patterns,
+            false,
+        )?;
+        let element_builder = Element::builder(ObjectType::ServiceJourneyInterchange.to_string())
+            .attr("id", Self::generate_interchange_id(interchange))
+            .attr("version", "any")
+            .append(
+                Element::builder("Guaranteed")
+                    .append(Node::Text(interchange.guaranteed.to_string()))
+                    .build(),
+            );
+        let element_builder = if let Some(min_transfer_time) = interchange.min_transfer_time {
+            element_builder.append(
+                Element::builder("MinimumTransferTime")
+                    .append(Node::Text(format!("PT{}S", min_transfer_time)))
+                    .build(),
+            )
+        } else {
+            element_builder
+        };
+        let element = element_builder
+            .append(from_point_ref)
+            .append(to_point_ref)
+            .append(Self::generate_service_journey_ref(
+                "FromJourneyRef",
+                &interchange.from_vehicle_journey_id,
+            ))
+            .append(Self::generate_service_journey_ref(
+                "ToJourneyRef",
+                &interchange.to_vehicle_journey_id,
+            ))
+            .build();
+        Some(element)
+    }
+
+    fn generate_interchange_id(interchange: &'a Interchange) -> String {
+        Exporter::generate_id(
+            &format!(
+                "{}_{}_{}_{}",
+                interchange.from_vehicle_journey_id,
+                interchange.from_stop_point_id,
+                interchange.to_vehicle_journey_id,
+                interchange.to_stop_point_id
+            ),
+            ObjectType::ServiceJourneyInterchange,
+        )
+    }
+
+    fn generate_scheduled_stop_point_ref(
+        &self,
+        element_name: &str,
+        vehicle_journey_id: &'a str,
+        stop_point_id: &'a str,
+        journey_patterns: &HashMap<Idx<VehicleJourney>, Idx<JourneyPattern>>,
+        is_last_passage: bool,
+    ) -> Option<Element> {
+        let vehicle_journey_idx = self.model.vehicle_journeys.get_idx(vehicle_journey_id)?;
+        let journey_pattern_idx = journey_patterns.get(&vehicle_journey_idx)?;
+        let stop_point_idx = self.model.stop_points.get_idx(stop_point_id)?;
+        let mut stop_times = self.model.vehicle_journeys[vehicle_journey_idx]
+            .stop_times
+            .iter()
+            .filter(|stop_time| stop_time.stop_point_idx == stop_point_idx);
+        let stop_time = if is_last_passage {
+            stop_times.last()
+        } else {
+            stop_times.next()
+        }?;
+        let element = Element::builder(element_name)
+            .attr(
+                "ref",
+                OfferExporter::generate_stop_sequence_id(
+                    &self.model.vehicle_journeys[*journey_pattern_idx].id,
+                    stop_time.sequence,
+                    ObjectType::ScheduledStopPoint,
+                ),
+            )
+            .attr("version", "any")
+            .build();
+        Some(element)
+    }
+
+    fn generate_service_journey_ref(element_name: &str, vehicle_journey_id: &'a str) -> Element {
+        Element::builder(element_name)
+            .attr(
+                "ref",
+                Exporter::generate_id(vehicle_journey_id, ObjectType::ServiceJourney),
+            )
+            .attr("version", "any")
+            .build()
+    }
+
     fn generate_id(&self, transfer: &'a Transfer) -> String {
         Exporter::generate_id(
             &format!("{}_{}", transfer.from_stop_id, transfer.to_stop_id),
@@ -127,3 +288,75 @@ impl<'a> TransferExporter<'a> {
             .build()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    #[test]
+    fn export_service_journey_interchange() {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "10:10:00", "10:11:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("B", "10:15:00", "10:16:00")
+                    .st("C", "10:30:00", "10:31:00");
+            })
+            .vj("vj3", |vj| {
+                vj.st("B", "11:15:00", "11:16:00")
+                    .st("C", "11:30:00", "11:31:00");
+            })
+            .build_model()
+            .into_collections();
+        collections.interchanges.push(Interchange {
+            from_vehicle_journey_id: "vj1".to_string(),
+            from_stop_point_id: "B".to_string(),
+            to_vehicle_journey_id: "vj3".to_string(),
+            to_stop_point_id: "B".to_string(),
+            min_transfer_time: Some(120),
+            guaranteed: true,
+        });
+        let model = Model::new(collections).unwrap();
+        let elements = TransferExporter::new(&model).export().unwrap();
+
+        assert_eq!(1, elements.len());
+        assert_eq!("ServiceJourneyInterchange", elements[0].name());
+        let children: Vec<(&str, Option<&str>, String)> = elements[0]
+            .children()
+            .map(|child| (child.name(), child.attr("ref"), child.text()))
+            .collect();
+        // 'vj3' shares the journey pattern of 'vj2'
+        assert_eq!(
+            vec![
+                ("Guaranteed", None, "true".to_string()),
+                ("MinimumTransferTime", None, "PT120S".to_string()),
+                (
+                    "FromPointRef",
+                    Some("FR:ScheduledStopPoint:vj1_1:"),
+                    String::new()
+                ),
+                (
+                    "ToPointRef",
+                    Some("FR:ScheduledStopPoint:vj2_0:"),
+                    String::new()
+                ),
+                (
+                    "FromJourneyRef",
+                    Some("FR:ServiceJourney:vj1:"),
+                    String::new()
+                ),
+                (
+                    "ToJourneyRef",
+                    Some("FR:ServiceJourney:vj3:"),
+                    String::new()
+                ),
+            ],
+            children
+        );
+    }
+}
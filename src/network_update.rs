@@ -0,0 +1,452 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Update of an existing model with a new version of one of its networks
+//! (e.g. an NTFS referential updated with the GTFS of a single network).
+
+use crate::{
+    model::{Collections, Model},
+    objects::{Codes, StopPoint},
+    Result,
+};
+use anyhow::{anyhow, bail, Context};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, info};
+use typed_index_collection::{CollectionWithId, Id, Idx};
+
+// For each object of `update`, the identifier of the first object of `base`
+// sharing at least one code with it
+fn match_by_codes<T: Id<T> + Codes>(
+    base: &CollectionWithId<T>,
+    update: &CollectionWithId<T>,
+) -> HashMap<String, String> {
+    let mut ids_by_code: HashMap<&(String, String), &str> = HashMap::new();
+    for object in base.values() {
+        for code in object.codes() {
+            ids_by_code.entry(code).or_insert_with(|| object.id());
+        }
+    }
+    update
+        .values()
+        .filter_map(|object| {
+            object
+                .codes()
+                .iter()
+                .find_map(|code| ids_by_code.get(code))
+                .map(|base_id| (object.id().to_string(), base_id.to_string()))
+        })
+        .collect()
+}
+
+// Add the objects of `update` whose identifier is unknown in `collection`
+fn push_missing<T: Id<T>>(collection: &mut CollectionWithId<T>, update: Vec<T>) {
+    for object in update {
+        if !collection.contains_id(object.id()) {
+            // the identifier has been checked just above
+            collection.push(object).unwrap();
+        }
+    }
+}
+
+// Add all the objects of `update`, an identifier already used in `collection`
+// being an error
+fn push_all<T: Id<T>>(
+    collection: &mut CollectionWithId<T>,
+    update: Vec<T>,
+    object_type: &str,
+) -> Result<()> {
+    for object in update {
+        let id = object.id().to_string();
+        collection.push(object).with_context(|| {
+            format!(
+                "{} {} of the update conflicts with an existing one (the update may be read with a different prefix)",
+                object_type, id
+            )
+        })?;
+    }
+    Ok(())
+}
+
+// Remove the lines, routes and vehicle journeys of the network `network_id`
+fn remove_network_objects(collections: &mut Collections, network_id: &str) {
+    let line_ids: HashSet<String> = collections
+        .lines
+        .values()
+        .filter(|line| line.network_id == network_id)
+        .map(|line| line.id.clone())
+        .collect();
+    collections
+        .lines
+        .retain(|line| !line_ids.contains(&line.id));
+    collections
+        .grid_rel_calendar_line
+        .retain(|rel| !line_ids.contains(&rel.line_id));
+    let route_ids: HashSet<String> = collections
+        .routes
+        .values()
+        .filter(|route| line_ids.contains(&route.line_id))
+        .map(|route| route.id.clone())
+        .collect();
+    collections
+        .routes
+        .retain(|route| !route_ids.contains(&route.id));
+    let vehicle_journey_ids: HashSet<String> = collections
+        .vehicle_journeys
+        .values()
+        .filter(|vj| route_ids.contains(&vj.route_id))
+        .map(|vj| vj.id.clone())
+        .collect();
+    collections
+        .vehicle_journeys
+        .retain(|vj| !vehicle_journey_ids.contains(&vj.id));
+    collections
+        .frequencies
+        .retain(|frequency| !vehicle_journey_ids.contains(&frequency.vehicle_journey_id));
+    collections
+        .stop_time_headsigns
+        .retain(|(vj_id, _), _| !vehicle_journey_ids.contains(vj_id));
+    collections
+        .stop_time_ids
+        .retain(|(vj_id, _), _| !vehicle_journey_ids.contains(vj_id));
+    collections
+        .stop_time_comments
+        .retain(|(vj_id, _), _| !vehicle_journey_ids.contains(vj_id));
+    debug!(
+        "{} lines, {} routes and {} vehicle journeys removed from network {}",
+        line_ids.len(),
+        route_ids.len(),
+        vehicle_journey_ids.len(),
+        network_id
+    );
+}
+
+/// Replace the network `network_id` of `model` by the one of `update`.
+///
+/// All the lines, routes and trips of the network are removed from `model`
+/// and replaced by the ones of `update`; everything else in `model` is
+/// preserved. The stop areas and stop points of `update` sharing a code with
+/// an existing one are matched with it and keep the identifier of `model`,
+/// the others are added.
+///
+/// Apart from the matched stops and the shared referential objects
+/// (contributors, datasets, companies, modes, equipments), an object of
+/// `update` with an identifier already used in `model` is an error: the
+/// update should be read with a prefix ensuring the uniqueness of its
+/// identifiers.
+pub fn update_network(model: Model, update: Model, network_id: &str) -> Result<Model> {
+    let mut collections = model.into_collections();
+    let mut update = update.into_collections();
+    let network = update
+        .networks
+        .get(network_id)
+        .cloned()
+        .ok_or_else(|| anyhow!("network {} not found in the update", network_id))?;
+    info!("Updating network {}", network_id);
+
+    remove_network_objects(&mut collections, network_id);
+    let stop_area_ids = match_by_codes(&collections.stop_areas, &update.stop_areas);
+    let stop_point_ids = match_by_codes(&collections.stop_points, &update.stop_points);
+    info!(
+        "{} stop areas and {} stop points of the update matched with existing ones",
+        stop_area_ids.len(),
+        stop_point_ids.len()
+    );
+    // objects which are not used anymore by the other networks are removed
+    collections.sanitize()?;
+
+    if collections.networks.contains_id(network_id) {
+        *collections.networks.get_mut(network_id).unwrap() = network;
+    } else {
+        collections.networks.push(network)?;
+    }
+
+    // a matched stop removed while cleaning the previous network is restored
+    // with its previous identifier
+    for mut stop_area in update.stop_areas.take() {
+        match stop_area_ids.get(&stop_area.id) {
+            Some(base_id) if collections.stop_areas.contains_id(base_id) => {}
+            Some(base_id) => {
+                stop_area.id = base_id.clone();
+                collections.stop_areas.push(stop_area)?;
+            }
+            None => push_all(&mut collections.stop_areas, vec![stop_area], "stop area")?,
+        }
+    }
+    let final_stop_point_ids: HashMap<Idx<StopPoint>, String> = update
+        .stop_points
+        .iter()
+        .map(|(idx, stop_point)| {
+            let id = stop_point_ids
+                .get(&stop_point.id)
+                .unwrap_or(&stop_point.id)
+                .clone();
+            (idx, id)
+        })
+        .collect();
+    for mut stop_point in update.stop_points.take() {
+        if let Some(stop_area_id) = stop_area_ids.get(&stop_point.stop_area_id) {
+            stop_point.stop_area_id = stop_area_id.clone();
+        }
+        match stop_point_ids.get(&stop_point.id) {
+            Some(base_id) if collections.stop_points.contains_id(base_id) => {}
+            Some(base_id) => {
+                stop_point.id = base_id.clone();
+                collections.stop_points.push(stop_point)?;
+            }
+            None => push_all(&mut collections.stop_points, vec![stop_point], "stop point")?,
+        }
+    }
+    let existing_transfers: HashSet<(String, String)> = collections
+        .transfers
+        .values()
+        .map(|transfer| (transfer.from_stop_id.clone(), transfer.to_stop_id.clone()))
+        .collect();
+    for mut transfer in update.transfers.take() {
+        if let Some(id) = stop_point_ids.get(&transfer.from_stop_id) {
+            transfer.from_stop_id = id.clone();
+        }
+        if let Some(id) = stop_point_ids.get(&transfer.to_stop_id) {
+            transfer.to_stop_id = id.clone();
+        }
+        if !existing_transfers
+            .contains(&(transfer.from_stop_id.clone(), transfer.to_stop_id.clone()))
+        {
+            collections.transfers.push(transfer);
+        }
+    }
+
+    push_missing(&mut collections.contributors, update.contributors.take());
+    push_missing(&mut collections.datasets, update.datasets.take());
+    push_missing(&mut collections.companies, update.companies.take());
+    push_missing(
+        &mut collections.commercial_modes,
+        update.commercial_modes.take(),
+    );
+    push_missing(
+        &mut collections.physical_modes,
+        update.physical_modes.take(),
+    );
+    push_missing(&mut collections.equipments, update.equipments.take());
+    push_all(
+        &mut collections.calendars,
+        update.calendars.take(),
+        "calendar",
+    )?;
+    push_all(&mut collections.comments, update.comments.take(), "comment")?;
+    push_all(
+        &mut collections.geometries,
+        update.geometries.take(),
+        "geometry",
+    )?;
+    push_all(
+        &mut collections.trip_properties,
+        update.trip_properties.take(),
+        "trip property",
+    )?;
+
+    let lines: Vec<_> = update
+        .lines
+        .take()
+        .into_iter()
+        .filter(|line| line.network_id == network_id)
+        .collect();
+    let line_ids: HashSet<String> = lines.iter().map(|line| line.id.clone()).collect();
+    push_all(&mut collections.lines, lines, "line")?;
+    let routes: Vec<_> = update
+        .routes
+        .take()
+        .into_iter()
+        .filter(|route| line_ids.contains(&route.line_id))
+        .collect();
+    let route_ids: HashSet<String> = routes.iter().map(|route| route.id.clone()).collect();
+    push_all(&mut collections.routes, routes, "route")?;
+    let mut vehicle_journeys = Vec::new();
+    for mut vehicle_journey in update.vehicle_journeys.take() {
+        if !route_ids.contains(&vehicle_journey.route_id) {
+            continue;
+        }
+        for stop_time in &mut vehicle_journey.stop_times {
+            let stop_point_id = &final_stop_point_ids[&stop_time.stop_point_idx];
+            stop_time.stop_point_idx = match collections.stop_points.get_idx(stop_point_id) {
+                Some(idx) => idx,
+                None => bail!(
+                    "stop point {} of vehicle journey {} not found",
+                    stop_point_id,
+                    vehicle_journey.id
+                ),
+            };
+        }
+        vehicle_journeys.push(vehicle_journey);
+    }
+    let vehicle_journey_ids: HashSet<String> =
+        vehicle_journeys.iter().map(|vj| vj.id.clone()).collect();
+    push_all(
+        &mut collections.vehicle_journeys,
+        vehicle_journeys,
+        "vehicle journey",
+    )?;
+    let is_updated = |(vj_id, _): &(String, u32)| vehicle_journey_ids.contains(vj_id);
+    collections.stop_time_headsigns.extend(
+        update
+            .stop_time_headsigns
+            .into_iter()
+            .filter(|(key, _)| is_updated(key)),
+    );
+    collections.stop_time_ids.extend(
+        update
+            .stop_time_ids
+            .into_iter()
+            .filter(|(key, _)| is_updated(key)),
+    );
+    collections.stop_time_comments.extend(
+        update
+            .stop_time_comments
+            .into_iter()
+            .filter(|(key, _)| is_updated(key)),
+    );
+    for occupancy in update.stop_time_occupancies.take() {
+        if vehicle_journey_ids.contains(&occupancy.vehicle_journey_id) {
+            collections.stop_time_occupancies.push(occupancy);
+        }
+    }
+
+    Model::new(collections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Network;
+    use crate::test_fixtures::{one_vehicle_journey, BuildModel};
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    // Each stop point gets a `source` code, its identifier without the `new:` prefix
+    fn with_source_codes(collections: &mut Collections) {
+        let mut stop_points = collections.stop_points.take();
+        for stop_point in &mut stop_points {
+            let code = stop_point.id.trim_start_matches("new:").to_string();
+            stop_point.codes.insert(("source".to_string(), code));
+        }
+        collections.stop_points = CollectionWithId::new(stop_points).unwrap();
+    }
+
+    #[test]
+    fn update_one_network() {
+        let base_model = ModelBuilder::default()
+            .route("route1", |r| {
+                r.id = "route1".to_string();
+                r.line_id = "line1".to_string();
+            })
+            .route("route2", |r| {
+                r.id = "route2".to_string();
+                r.line_id = "line2".to_string();
+            })
+            .vj("vj1", |vj| {
+                vj.route("route1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.route("route2")
+                    .st("C", "10:00:00", "10:01:00")
+                    .st("D", "11:00:00", "11:01:00");
+            })
+            .build_model();
+        let mut collections = base_model.into_collections();
+        collections.lines.get_mut("line2").unwrap().network_id = "network2".to_string();
+        collections
+            .networks
+            .push(Network {
+                id: "network2".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        with_source_codes(&mut collections);
+        let base_model = Model::new(collections).unwrap();
+        let update = ModelBuilder::default()
+            .calendar("new:service", &["2020-01-01"])
+            .route("new:route1", |r| {
+                r.id = "new:route1".to_string();
+                r.line_id = "new:line1".to_string();
+            })
+            .vj("new:vj1", |vj| {
+                vj.route("new:route1")
+                    .calendar("new:service")
+                    .st("new:A", "12:00:00", "12:01:00")
+                    .st("new:E", "13:00:00", "13:01:00");
+            })
+            .build_model();
+        let mut update = update.into_collections();
+        update.networks.get_mut("default_network").unwrap().name = "New network".to_string();
+        with_source_codes(&mut update);
+        let update = Model::new(update).unwrap();
+
+        let model = update_network(base_model, update, "default_network").unwrap();
+        assert_eq!(
+            "New network",
+            model.networks.get("default_network").unwrap().name
+        );
+        assert_eq!(
+            vec!["line2", "new:line1"],
+            model
+                .lines
+                .values()
+                .map(|l| l.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["vj2", "new:vj1"],
+            model
+                .vehicle_journeys
+                .values()
+                .map(|vj| vj.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        let vj = model.vehicle_journeys.get("new:vj1").unwrap();
+        let stop_point_ids: Vec<&str> = vj
+            .stop_times
+            .iter()
+            .map(|st| model.stop_points[st.stop_point_idx].id.as_str())
+            .collect();
+        // A is matched by its code, E is a new stop point
+        assert_eq!(vec!["A", "new:E"], stop_point_ids);
+        assert!(model.stop_points.get("B").is_none());
+        assert!(model.stop_points.get("C").is_some());
+    }
+
+    #[test]
+    fn conflicting_identifiers() {
+        let base_model = one_vehicle_journey();
+        let update = ModelBuilder::default()
+            .vj("vj2", |vj| {
+                vj.st("new:A", "10:00:00", "10:01:00")
+                    .st("new:B", "11:00:00", "11:01:00");
+            })
+            .build_model();
+        let error = update_network(base_model, update, "default_network").unwrap_err();
+        assert_eq!(
+            "calendar default_service of the update conflicts with an existing one (the update may be read with a different prefix)",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn unknown_network() {
+        let base_model = one_vehicle_journey();
+        let update = one_vehicle_journey();
+        let error = update_network(base_model, update, "unknown").unwrap_err();
+        assert_eq!("network unknown not found in the update", error.to_string());
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! NTFS bundled into a single [SQLite](https://www.sqlite.org) file, with
+//! one table by NTFS file, for the pipelines where one artifact is easier to
+//! handle than dozens of CSV files.
+//!
+//! Unlike the export of `crate::export::sqlite`, the tables have the columns
+//! of the NTFS files and all the values are stored as text, exactly as
+//! written in the files, so that the bundle is read back into the same
+//! `Model`.
+
+use super::{read_file_handler, write_with_configuration, WriteConfiguration};
+use crate::{file_handler::MemoryFileHandler, model::Model, Result};
+use anyhow::Context;
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use std::{collections::BTreeMap, path::Path};
+use tempfile::tempdir;
+use tracing::info;
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+// Insert the CSV file `path` into the new table `table`
+fn insert_file(connection: &Connection, table: &str, path: &Path) -> Result<()> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .with_context(|| format!("Error reading {:?}", path))?;
+    let columns: Vec<String> = rdr
+        .headers()
+        .with_context(|| format!("Error reading {:?}", path))?
+        .iter()
+        .map(quote)
+        .collect();
+    let column_definitions: Vec<String> = columns
+        .iter()
+        .map(|column| format!("{} TEXT", column))
+        .collect();
+    connection.execute(
+        &format!(
+            "CREATE TABLE {} ({})",
+            quote(table),
+            column_definitions.join(", ")
+        ),
+        [],
+    )?;
+    let mut statement = connection.prepare(&format!(
+        "INSERT INTO {} VALUES ({})",
+        quote(table),
+        vec!["?"; columns.len()].join(", ")
+    ))?;
+    for record in rdr.records() {
+        let record = record.with_context(|| format!("Error reading {:?}", path))?;
+        statement.execute(params_from_iter(
+            (0..columns.len()).map(|position| record.get(position).unwrap_or_default()),
+        ))?;
+    }
+    Ok(())
+}
+
+// The content of the table `table` as a CSV file
+fn read_table(connection: &Connection, table: &str) -> Result<Vec<u8>> {
+    let mut statement =
+        connection.prepare(&format!("SELECT * FROM {} ORDER BY rowid", quote(table)))?;
+    let columns: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    wtr.write_record(&columns)?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let fields = (0..columns.len())
+            .map(|position| row.get::<_, Option<String>>(position))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        wtr.write_record(
+            fields
+                .iter()
+                .map(|field| field.as_deref().unwrap_or_default()),
+        )?;
+    }
+    Ok(wtr.into_inner()?)
+}
+
+/// Exports a `Model` to a NTFS bundled into the new SQLite file `path` (an
+/// existing file is replaced), following the `config`. The files other than
+/// the NTFS files (e.g. the `stamp.json` sidecar) are not bundled.
+pub fn write_to_sqlite<P: AsRef<Path>>(
+    model: &Model,
+    path: P,
+    config: WriteConfiguration,
+) -> Result<()> {
+    let path = path.as_ref();
+    info!("Writing NTFS to SQLite bundle {:?}", path);
+    let tmp_dir = tempdir()?;
+    write_with_configuration(model, tmp_dir.path(), config)?;
+    if path.exists() {
+        std::fs::remove_file(path).with_context(|| format!("Error removing {:?}", path))?;
+    }
+    let mut connection =
+        Connection::open(path).with_context(|| format!("Error creating {:?}", path))?;
+    let mut file_paths = Vec::new();
+    for entry in tmp_dir.path().read_dir()? {
+        let file_path = entry?.path();
+        if file_path.extension().map_or(false, |ext| ext == "txt") {
+            file_paths.push(file_path);
+        }
+    }
+    file_paths.sort();
+
+    let tx = connection.transaction()?;
+    for file_path in &file_paths {
+        if let Some(table) = file_path.file_stem().and_then(|stem| stem.to_str()) {
+            insert_file(&tx, table, file_path)?;
+        }
+    }
+    tx.commit()?;
+    tmp_dir.close()?;
+    Ok(())
+}
+
+/// Imports a `Model` from a NTFS bundled into the SQLite file `path` by
+/// [write_to_sqlite].
+pub fn from_sqlite<P: AsRef<Path>>(path: P) -> Result<Model> {
+    let path = path.as_ref();
+    info!("Reading NTFS from SQLite bundle {:?}", path);
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Error reading {:?}", path))?;
+    let tables = connection
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    let mut files = BTreeMap::new();
+    for table in tables {
+        let content =
+            read_table(&connection, &table).with_context(|| format!("Error reading {:?}", path))?;
+        files.insert(format!("{}.txt", table), content);
+    }
+    let mut file_handler = MemoryFileHandler::new(files, path.to_string_lossy());
+    read_file_handler(&mut file_handler)
+        .with_context(|| format!("impossible to read ntfs bundle {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use crate::{objects::Time, test_utils::*};
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    #[test]
+    fn write_and_read_sqlite_bundle() {
+        let model = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("B", "10:00:00", "10:01:00")
+                    .st("C", "25:00:00", "25:01:00");
+            })
+            .build_model();
+        test_in_tmp_dir(|path| {
+            let bundle = path.join("ntfs.sqlite");
+            write_to_sqlite(
+                &model,
+                &bundle,
+                WriteConfiguration::new(get_test_datetime()),
+            )
+            .unwrap();
+            let read_model = from_sqlite(&bundle).unwrap();
+
+            let ids = |ids: Vec<&str>| ids.join(",");
+            assert_eq!(
+                "vj1,vj2",
+                ids(read_model
+                    .vehicle_journeys
+                    .values()
+                    .map(|vj| vj.id.as_str())
+                    .collect())
+            );
+            assert_eq!(
+                "A,B,C",
+                ids(read_model
+                    .stop_points
+                    .values()
+                    .map(|sp| sp.id.as_str())
+                    .collect())
+            );
+            let vj2 = read_model.vehicle_journeys.get("vj2").unwrap();
+            assert_eq!(
+                vec![Time::new(10, 1, 0), Time::new(25, 1, 0)],
+                vj2.stop_times
+                    .iter()
+                    .map(|stop_time| stop_time.departure_time)
+                    .collect::<Vec<_>>()
+            );
+        });
+    }
+
+    #[test]
+    fn missing_sqlite_bundle() {
+        test_in_tmp_dir(|path| {
+            assert!(from_sqlite(path.join("missing.sqlite")).is_err());
+        });
+    }
+}
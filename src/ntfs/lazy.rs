@@ -0,0 +1,154 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use super::{read, read_collections_file_handler};
+use crate::{
+    file_handler::{PathFileHandler, ZipHandler},
+    model::{Collections, Model},
+    objects::{Geometry, StopTime},
+    Result,
+};
+use anyhow::{anyhow, Context};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+// Evaluate `$read` with `$file_handler` bound to a file handler on the NTFS
+// directory or ZIP archive `$path`
+macro_rules! with_file_handler {
+    ($path:expr, $file_handler:ident => $read:expr) => {{
+        let path: &Path = $path;
+        if path.is_file() {
+            let reader = std::fs::File::open(path)?;
+            let $file_handler = &mut ZipHandler::new(reader, path)?;
+            $read.with_context(|| format!("impossible to read zipped ntfs {:?}", path))
+        } else if path.is_dir() {
+            let $file_handler = &mut PathFileHandler::new(path.to_path_buf());
+            $read.with_context(|| format!("impossible to read ntfs directory from {:?}", path))
+        } else {
+            Err(anyhow!(
+                "file {:?} is neither a file nor a directory, cannot read a ntfs from it",
+                path
+            ))
+        }
+    }};
+}
+
+/// A [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+/// read without its heaviest files, `stop_times.txt` and `geometries.txt`,
+/// which are read on demand.
+///
+/// The metadata-only operations (e.g. statistics or the validation of the
+/// lines and the stops) don't need to hold the stop times of a huge dataset in
+/// memory. The headsigns, the identifiers, the comments and the occupancies of
+/// the stop times are not read.
+///
+/// ```
+/// # fn main() -> transit_model::Result<()> {
+/// let ntfs = transit_model::ntfs::LazyNtfs::read("tests/fixtures/minimal_ntfs")?;
+/// assert_eq!(3, ntfs.collections().lines.len());
+/// let stop_times = ntfs.stop_times(vec!["M1F1"])?;
+/// assert_eq!(4, stop_times["M1F1"].len());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LazyNtfs {
+    path: PathBuf,
+    collections: Collections,
+}
+
+impl LazyNtfs {
+    /// Read the NTFS directory or ZIP archive at `path`, except its stop times
+    /// and its geometries.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        info!("Loading NTFS from {:?} without stop times", path);
+        let collections = with_file_handler!(path, file_handler => {
+            read_collections_file_handler(file_handler, false)
+        })?;
+        Ok(LazyNtfs {
+            path: path.to_path_buf(),
+            collections,
+        })
+    }
+
+    /// The collections, without the stop times of the vehicle journeys and
+    /// without the geometries. They are not sanitized: a vehicle journey
+    /// without stop times is kept.
+    pub fn collections(&self) -> &Collections {
+        &self.collections
+    }
+
+    /// Move out the collections (see [LazyNtfs::collections]).
+    pub fn into_collections(self) -> Collections {
+        self.collections
+    }
+
+    /// Read the stop times of the vehicle journeys `vj_ids`, sorted by
+    /// sequence, by vehicle journey identifier. Each call reads the whole
+    /// `stop_times.txt`: the vehicle journeys should be requested in batches.
+    pub fn stop_times<'a, I>(&self, vj_ids: I) -> Result<HashMap<String, Vec<StopTime>>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let vj_ids: HashSet<&str> = vj_ids.into_iter().collect();
+        with_file_handler!(&self.path, file_handler => {
+            read::read_stop_times_of(&self.collections, file_handler, &vj_ids)
+        })
+    }
+
+    /// Read the geometries `geometry_ids`. Each call reads the whole
+    /// `geometries.txt`.
+    pub fn geometries<'a, I>(&self, geometry_ids: I) -> Result<Vec<Geometry>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let geometry_ids: HashSet<&str> = geometry_ids.into_iter().collect();
+        with_file_handler!(&self.path, file_handler => {
+            read::read_geometries_of(file_handler, &geometry_ids)
+        })
+    }
+
+    /// Read the whole NTFS, with its stop times and its geometries.
+    pub fn load(self) -> Result<Model> {
+        super::read(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn read_stop_times_on_demand() {
+        let ntfs = LazyNtfs::read("tests/fixtures/minimal_ntfs").unwrap();
+        let collections = ntfs.collections();
+        assert!(collections
+            .vehicle_journeys
+            .values()
+            .all(|vj| vj.stop_times.is_empty()));
+
+        let model = crate::ntfs::read("tests/fixtures/minimal_ntfs").unwrap();
+        let vj = model.vehicle_journeys.values().next().unwrap();
+        let stop_times = ntfs.stop_times(vec![vj.id.as_str(), "unknown"]).unwrap();
+        assert_eq!(1, stop_times.len());
+        let sequences: Vec<u32> = stop_times[&vj.id].iter().map(|st| st.sequence).collect();
+        let expected: Vec<u32> = vj.stop_times.iter().map(|st| st.sequence).collect();
+        assert_eq!(expected, sequences);
+    }
+}
@@ -15,15 +15,23 @@
 //! [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
 //! format management.
 
+#[cfg(feature = "sqlite")]
+mod bundle;
+mod lazy;
 mod read;
+mod version;
 mod write;
 
 use crate::{
     calendars::{manage_calendars, write_calendar_dates},
-    file_handler::{FileHandler, PathFileHandler, ZipHandler},
+    csv_format::{CsvDialect, CsvFormat},
+    file_handler::{DialectFileHandler, FileHandler, PathFileHandler, ZipHandler},
     model::{Collections, Model},
     objects::*,
+    report::Report,
     serde_utils::*,
+    stamp::Stamp,
+    stop_time_occupancy,
     utils::*,
     Result,
 };
@@ -34,7 +42,21 @@ use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 use std::path;
 use tempfile::tempdir;
-use tracing::info;
+use tracing::{field, info, info_span};
+
+#[cfg(feature = "sqlite")]
+pub use bundle::{from_sqlite, write_to_sqlite};
+pub use lazy::LazyNtfs;
+pub use version::{NtfsVersion, UpgradeReportCategory};
+
+/// The extension columns of `transfers.txt`, only written when at least one
+/// transfer has a value
+const TRANSFERS_EXTENSION_COLUMNS: &[&str] = &[
+    "transfer_mode",
+    "transfer_start_time",
+    "transfer_end_time",
+    "transfer_days",
+];
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct StopTime {
@@ -157,6 +179,39 @@ struct ObjectProperty {
     object_property_value: String,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Poi {
+    #[serde(rename = "poi_id")]
+    id: String,
+    #[serde(rename = "poi_name")]
+    name: String,
+    #[serde(rename = "poi_lat")]
+    lat: f64,
+    #[serde(rename = "poi_lon")]
+    lon: f64,
+    poi_type_id: String,
+    #[serde(
+        rename = "poi_weight",
+        default,
+        deserialize_with = "de_with_empty_default"
+    )]
+    weight: u32,
+    #[serde(
+        rename = "poi_visible",
+        default = "default_visible",
+        deserialize_with = "de_from_u8",
+        serialize_with = "ser_from_bool"
+    )]
+    visible: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PoiProperty {
+    poi_id: String,
+    key: String,
+    value: String,
+}
+
 fn default_visible() -> bool {
     true
 }
@@ -196,7 +251,7 @@ pub fn from_zip<P: AsRef<path::Path>>(p: P) -> Result<Model> {
 pub fn collections_from_zip<P: AsRef<path::Path>>(p: P) -> Result<Collections> {
     let reader = std::fs::File::open(p.as_ref())?;
     let mut file_handler = ZipHandler::new(reader, p)?;
-    read_collections_file_handler(&mut file_handler)
+    read_collections_file_handler(&mut file_handler, true)
 }
 
 /// Imports `Collections` from the
@@ -204,7 +259,7 @@ pub fn collections_from_zip<P: AsRef<path::Path>>(p: P) -> Result<Collections> {
 /// files in the given directory.
 pub fn collections_from_dir<P: AsRef<path::Path>>(p: P) -> Result<Collections> {
     let mut file_handle = PathFileHandler::new(p.as_ref().to_path_buf());
-    read_collections_file_handler(&mut file_handle)
+    read_collections_file_handler(&mut file_handle, true)
 }
 
 /// Imports a `Model` from an object implementing `Read` and `Seek` and containing a zip file with a
@@ -275,21 +330,89 @@ pub fn read_collections<P: AsRef<path::Path>>(path: P) -> Result<Collections> {
     }
 }
 
+/// Imports a `Model` from the
+/// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+/// files in the given directory or ZIP archive, like [read], with a report of
+/// the aspects of an older version of the NTFS upgraded to the current model
+/// (missing files and columns read with their default values, deprecated
+/// columns converted).
+pub fn read_with_upgrade_report<P: AsRef<path::Path>>(
+    path: P,
+) -> Result<(Model, Report<UpgradeReportCategory>)> {
+    fn read_with_report<H>(file_handler: &mut H) -> Result<(Model, Report<UpgradeReportCategory>)>
+    where
+        for<'a> &'a mut H: FileHandler,
+    {
+        let report = version::upgrade_report(file_handler)?;
+        Ok((read_file_handler(file_handler)?, report))
+    }
+
+    let p = path.as_ref();
+    if p.is_file() {
+        let reader = std::fs::File::open(p)?;
+        read_with_report(&mut ZipHandler::new(reader, p)?)
+            .with_context(|| format!("impossible to read zipped ntfs {:?}", p))
+    } else if p.is_dir() {
+        read_with_report(&mut PathFileHandler::new(p.to_path_buf()))
+            .with_context(|| format!("impossible to read ntfs directory from {:?}", p))
+    } else {
+        Err(anyhow!(
+            "file {:?} is neither a file nor a directory, cannot read a ntfs from it",
+            p
+        ))
+    }
+}
+
+/// Imports a `Model` from the NTFS-like files in the given directory or ZIP
+/// archive, like [read], whose CSV files are written in the `dialect` (e.g.
+/// semicolon separated fields and decimal commas).
+pub fn read_with_dialect<P: AsRef<path::Path>>(path: P, dialect: CsvDialect) -> Result<Model> {
+    let p = path.as_ref();
+    if p.is_file() {
+        let reader = std::fs::File::open(p)?;
+        let file_handler = ZipHandler::new(reader, p)?;
+        read_file_handler(&mut DialectFileHandler::new(file_handler, dialect))
+            .with_context(|| format!("impossible to read zipped ntfs {:?}", p))
+    } else if p.is_dir() {
+        let file_handler = PathFileHandler::new(p.to_path_buf());
+        read_file_handler(&mut DialectFileHandler::new(file_handler, dialect))
+            .with_context(|| format!("impossible to read ntfs directory from {:?}", p))
+    } else {
+        Err(anyhow!(
+            "file {:?} is neither a file nor a directory, cannot read a ntfs from it",
+            p
+        ))
+    }
+}
+
 fn read_file_handler<H>(file_handler: &mut H) -> Result<Model>
 where
     for<'a> &'a mut H: FileHandler,
 {
-    let collections = read_collections_file_handler(file_handler)?;
+    let collections = read_collections_file_handler(file_handler, true)?;
     info!("Indexing");
     let res = Model::new(collections)?;
     info!("Loading NTFS done");
     Ok(res)
 }
 
-fn read_collections_file_handler<H>(file_handler: &mut H) -> Result<Collections>
+// Without the `heavy_collections`, the stop times (with their headsigns,
+// identifiers, comments and occupancies) and the geometries are not read
+fn read_collections_file_handler<H>(
+    file_handler: &mut H,
+    heavy_collections: bool,
+) -> Result<Collections>
 where
     for<'a> &'a mut H: FileHandler,
 {
+    let span = info_span!(
+        "read_ntfs",
+        source = file_handler.source_name(),
+        stop_points = field::Empty,
+        vehicle_journeys = field::Empty,
+        stop_times = field::Empty,
+    );
+    let _enter = span.enter();
     info!("Loading NTFS from {:?}", file_handler.source_name());
     let mut collections = Collections {
         contributors: make_collection_with_id(file_handler, "contributors.txt")?,
@@ -305,6 +428,7 @@ where
         equipments: make_opt_collection_with_id(file_handler, "equipments.txt")?,
         trip_properties: make_opt_collection_with_id(file_handler, "trip_properties.txt")?,
         transfers: make_opt_collection(file_handler, "transfers.txt")?,
+        interchanges: make_opt_collection(file_handler, "interchanges.txt")?,
         admin_stations: make_opt_collection(file_handler, "admin_stations.txt")?,
         tickets: make_opt_collection_with_id(file_handler, "tickets.txt")?,
         ticket_uses: make_opt_collection_with_id(file_handler, "ticket_uses.txt")?,
@@ -324,20 +448,87 @@ where
         ..Default::default()
     };
     manage_calendars(file_handler, &mut collections)?;
-    read::manage_geometries(&mut collections, file_handler)?;
+    if heavy_collections {
+        read::manage_geometries(&mut collections, file_handler)?;
+    }
     read::manage_feed_infos(&mut collections, file_handler)?;
     read::manage_stops(&mut collections, file_handler)?;
     read::manage_pathways(&mut collections, file_handler)?;
-    read::manage_stop_times(&mut collections, file_handler)?;
+    if heavy_collections {
+        read::manage_stop_times(&mut collections, file_handler)?;
+    }
     read::manage_codes(&mut collections, file_handler)?;
     read::manage_comments(&mut collections, file_handler)?;
     read::manage_object_properties(&mut collections, file_handler)?;
     read::manage_fares_v1(&mut collections, file_handler)?;
     read::manage_companies_on_vj(&mut collections)?;
     read::manage_occupancies(&mut collections, file_handler)?;
+    read::manage_pois(&mut collections, file_handler)?;
+    if heavy_collections {
+        stop_time_occupancy::manage_stop_time_occupancies(&mut collections, file_handler)?;
+    }
+    record_dataset_size(&span, &collections);
     Ok(collections)
 }
 
+/// Configuration options for exporting a NTFS.
+/// 5 options can be configured:
+/// - current_datetime (required): date of the export, written in 'feed_infos.txt'.
+/// - stamp (optional): production metadata written in 'feed_infos.txt' and in a
+///   'stamp.json' sidecar file. Default to no stamp.
+/// - version (optional): version of the NTFS specification, the files and
+///   columns not available in this version are not written. Default to the
+///   current version.
+/// - geographic extent (optional): the bounding box and the convex hull of the
+///   stop points, written in 'feed_infos.txt'. Default to not written.
+/// - csv format (optional): quoting, line terminator and precision of the
+///   coordinates of the CSV files. Default to the format of the specification.
+#[derive(Debug, Clone)]
+pub struct WriteConfiguration {
+    current_datetime: DateTime<FixedOffset>,
+    stamp: Option<Stamp>,
+    version: NtfsVersion,
+    geographic_extent: bool,
+    csv_format: CsvFormat,
+}
+
+impl WriteConfiguration {
+    /// Create a new `WriteConfiguration`.
+    pub fn new(current_datetime: DateTime<FixedOffset>) -> Self {
+        WriteConfiguration {
+            current_datetime,
+            stamp: None,
+            version: NtfsVersion::default(),
+            geographic_extent: false,
+            csv_format: CsvFormat::default(),
+        }
+    }
+    /// Setup the production metadata stamped on the NTFS.
+    pub fn stamp(self, stamp: Stamp) -> Self {
+        WriteConfiguration {
+            stamp: Some(stamp),
+            ..self
+        }
+    }
+    /// Setup the version of the NTFS specification to write.
+    pub fn version(self, version: NtfsVersion) -> Self {
+        WriteConfiguration { version, ..self }
+    }
+    /// Write the bounding box and the convex hull of the stop points in
+    /// 'feed_infos.txt'.
+    pub fn geographic_extent(self) -> Self {
+        WriteConfiguration {
+            geographic_extent: true,
+            ..self
+        }
+    }
+    /// Setup the quoting, the line terminator and the precision of the
+    /// coordinates of the CSV files.
+    pub fn csv_format(self, csv_format: CsvFormat) -> Self {
+        WriteConfiguration { csv_format, ..self }
+    }
+}
+
 /// Exports a `Model` to the
 /// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
 /// files in the given directory.
@@ -345,49 +536,114 @@ pub fn write<P: AsRef<path::Path>>(
     model: &Model,
     path: P,
     current_datetime: DateTime<FixedOffset>,
+) -> Result<()> {
+    write_with_configuration(model, path, WriteConfiguration::new(current_datetime))
+}
+
+/// Exports a `Model` to the
+/// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+/// files in the given directory, following the `config`.
+pub fn write_with_configuration<P: AsRef<path::Path>>(
+    model: &Model,
+    path: P,
+    config: WriteConfiguration,
 ) -> Result<()> {
     let path = path.as_ref();
     std::fs::create_dir_all(path)?;
+    let span = info_span!(
+        "write_ntfs",
+        destination = ?path,
+        stop_points = field::Empty,
+        vehicle_journeys = field::Empty,
+        stop_times = field::Empty,
+    );
+    let _enter = span.enter();
+    record_dataset_size(&span, model);
     info!("Writing NTFS to {:?}", path);
+    let csv_format = &config.csv_format;
 
-    write::write_feed_infos(path, model, current_datetime)?;
-    write_collection_with_id(path, "contributors.txt", &model.contributors)?;
-    write_collection_with_id(path, "datasets.txt", &model.datasets)?;
-    write_collection_with_id(path, "networks.txt", &model.networks)?;
-    write_collection_with_id(path, "commercial_modes.txt", &model.commercial_modes)?;
-    write_collection_with_id(path, "companies.txt", &model.companies)?;
-    write_collection_with_id(path, "lines.txt", &model.lines)?;
-    write_collection_with_id(path, "physical_modes.txt", &model.physical_modes)?;
-    write_collection_with_id(path, "equipments.txt", &model.equipments)?;
-    write_collection_with_id(path, "routes.txt", &model.routes)?;
-    write_collection_with_id(path, "trip_properties.txt", &model.trip_properties)?;
-    write_collection_with_id(path, "geometries.txt", &model.geometries)?;
-    write_collection(path, "transfers.txt", &model.transfers)?;
-    write_collection(path, "admin_stations.txt", &model.admin_stations)?;
-    write_collection_with_id(path, "tickets.txt", &model.tickets)?;
-    write_collection_with_id(path, "ticket_uses.txt", &model.ticket_uses)?;
-    write_collection(path, "ticket_prices.txt", &model.ticket_prices)?;
+    write::write_feed_infos(
+        path,
+        model,
+        config.current_datetime,
+        config.version,
+        config.stamp.as_ref(),
+        config.geographic_extent,
+        csv_format,
+    )?;
+    write_collection_with_id(path, "contributors.txt", &model.contributors, csv_format)?;
+    write_collection_with_id(path, "datasets.txt", &model.datasets, csv_format)?;
+    write_collection_with_id(path, "networks.txt", &model.networks, csv_format)?;
+    write_collection_with_id(
+        path,
+        "commercial_modes.txt",
+        &model.commercial_modes,
+        csv_format,
+    )?;
+    write_collection_with_id(path, "companies.txt", &model.companies, csv_format)?;
+    write_collection_with_id(path, "lines.txt", &model.lines, csv_format)?;
+    write_collection_with_id(
+        path,
+        "physical_modes.txt",
+        &model.physical_modes,
+        csv_format,
+    )?;
+    write_collection_with_id(path, "equipments.txt", &model.equipments, csv_format)?;
+    write_collection_with_id(path, "routes.txt", &model.routes, csv_format)?;
+    write_collection_with_id(
+        path,
+        "trip_properties.txt",
+        &model.trip_properties,
+        csv_format,
+    )?;
+    write_collection_with_id(path, "geometries.txt", &model.geometries, csv_format)?;
+    write_collection_with_optional_columns(
+        path,
+        "transfers.txt",
+        &model.transfers,
+        TRANSFERS_EXTENSION_COLUMNS,
+        csv_format,
+    )?;
+    write_collection(path, "interchanges.txt", &model.interchanges, csv_format)?;
+    write_collection(
+        path,
+        "admin_stations.txt",
+        &model.admin_stations,
+        csv_format,
+    )?;
+    write_collection_with_id(path, "tickets.txt", &model.tickets, csv_format)?;
+    write_collection_with_id(path, "ticket_uses.txt", &model.ticket_uses, csv_format)?;
+    write_collection(path, "ticket_prices.txt", &model.ticket_prices, csv_format)?;
     write_collection(
         path,
         "ticket_use_perimeters.txt",
         &model.ticket_use_perimeters,
+        csv_format,
     )?;
     write_collection(
         path,
         "ticket_use_restrictions.txt",
         &model.ticket_use_restrictions,
+        csv_format,
+    )?;
+    write_collection_with_id(
+        path,
+        "grid_calendars.txt",
+        &model.grid_calendars,
+        csv_format,
     )?;
-    write_collection_with_id(path, "grid_calendars.txt", &model.grid_calendars)?;
     write_collection(
         path,
         "grid_exception_dates.txt",
         &model.grid_exception_dates,
+        csv_format,
     )?;
-    write_collection(path, "grid_periods.txt", &model.grid_periods)?;
+    write_collection(path, "grid_periods.txt", &model.grid_periods, csv_format)?;
     write_collection(
         path,
         "grid_rel_calendar_line.txt",
         &model.grid_rel_calendar_line,
+        csv_format,
     )?;
     write::write_vehicle_journeys_and_stop_times(
         path,
@@ -395,28 +651,43 @@ pub fn write<P: AsRef<path::Path>>(
         &model.stop_points,
         &model.stop_time_headsigns,
         &model.stop_time_ids,
+        &model.stop_time_comments,
+        csv_format,
     )?;
-    write_collection(path, "frequencies.txt", &model.frequencies)?;
-    write_calendar_dates(path, &model.calendars)?;
+    write_collection(path, "frequencies.txt", &model.frequencies, csv_format)?;
+    write_calendar_dates(path, &model.calendars, csv_format)?;
     write::write_stops(
         path,
         &model.stop_points,
         &model.stop_areas,
         &model.stop_locations,
+        csv_format,
     )?;
-    write::write_comments(path, model)?;
-    write::write_codes(path, model)?;
-    write::write_object_properties(path, model)?;
+    write::write_comments(path, model, csv_format)?;
+    write::write_codes(path, model, csv_format)?;
+    write::write_object_properties(path, model, csv_format)?;
     write::write_fares_v1(path, model)?;
-    write_collection_with_id(path, "pathways.txt", &model.pathways)?;
-    write_collection_with_id(path, "levels.txt", &model.levels)?;
-    write_collection_with_id(path, "addresses.txt", &model.addresses)?;
+    write_collection_with_id(path, "pathways.txt", &model.pathways, csv_format)?;
+    write_collection_with_id(path, "levels.txt", &model.levels, csv_format)?;
+    write_collection_with_id(path, "addresses.txt", &model.addresses, csv_format)?;
     write_collection_with_id(
         path,
         "administrative_regions.txt",
         &model.administrative_regions,
+        csv_format,
+    )?;
+    write_collection(path, "occupancies.txt", &model.occupancies, csv_format)?;
+    write::write_pois(path, model, csv_format)?;
+    write_collection(
+        path,
+        stop_time_occupancy::STOP_TIME_OCCUPANCIES_FILE,
+        &model.stop_time_occupancies,
+        csv_format,
     )?;
-    write_collection(path, "occupancies.txt", &model.occupancies)?;
+    version::downgrade(path, config.version, csv_format)?;
+    if let Some(stamp) = &config.stamp {
+        stamp.write_sidecar(path)?;
+    }
 
     Ok(())
 }
@@ -428,11 +699,22 @@ pub fn write_to_zip<P: AsRef<path::Path>>(
     model: &Model,
     path: P,
     current_datetime: DateTime<FixedOffset>,
+) -> Result<()> {
+    write_to_zip_with_configuration(model, path, WriteConfiguration::new(current_datetime))
+}
+
+/// Exports a `Model` to a
+/// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+/// ZIP archive at the given full path, following the `config`.
+pub fn write_to_zip_with_configuration<P: AsRef<path::Path>>(
+    model: &Model,
+    path: P,
+    config: WriteConfiguration,
 ) -> Result<()> {
     let path = path.as_ref();
     info!("Writing NTFS to ZIP File {:?}", path);
     let input_tmp_dir = tempdir()?;
-    write(model, input_tmp_dir.path(), current_datetime)?;
+    write_with_configuration(model, input_tmp_dir.path(), config)?;
     zip_to(input_tmp_dir.path(), path)?;
     input_tmp_dir.close()?;
     Ok(())
@@ -445,7 +727,7 @@ mod tests {
     use super::{read, write};
     use crate::calendars::{manage_calendars, write_calendar_dates};
     use crate::objects;
-    use crate::{file_handler::PathFileHandler, test_utils::*};
+    use crate::{file_handler::PathFileHandler, test_fixtures::BuildModel, test_utils::*};
     use geo::line_string;
     use pretty_assertions::assert_eq;
     use std::{
@@ -461,7 +743,7 @@ mod tests {
     {
         let collection = CollectionWithId::new(objects).unwrap();
         test_in_tmp_dir(|path| {
-            write_collection_with_id(path, "file.txt", &collection).unwrap();
+            write_collection_with_id(path, "file.txt", &collection, &CsvFormat::default()).unwrap();
             let mut handler = PathFileHandler::new(path.to_path_buf());
             let des_collection = make_collection_with_id(&mut handler, "file.txt").unwrap();
             assert_eq!(collection, des_collection);
@@ -475,7 +757,7 @@ mod tests {
     {
         let collection = Collection::new(objects);
         test_in_tmp_dir(|path| {
-            write_collection(path, "file.txt", &collection).unwrap();
+            write_collection(path, "file.txt", &collection, &CsvFormat::default()).unwrap();
             let mut handler = PathFileHandler::new(path.to_path_buf());
             let des_collection = make_opt_collection(&mut handler, "file.txt").unwrap();
             assert_eq!(collection, des_collection);
@@ -505,14 +787,40 @@ mod tests {
 
         let mut collections = Collections {
             datasets: CollectionWithId::from(dataset),
-            feed_infos,
+            feed_infos: feed_infos.into(),
             ..Default::default()
         };
 
         test_in_tmp_dir(|path| {
-            write::write_feed_infos(path, &collections, get_test_datetime()).unwrap();
+            write::write_feed_infos(
+                path,
+                &collections,
+                get_test_datetime(),
+                NtfsVersion::default(),
+                None,
+                false,
+                &CsvFormat::default(),
+            )
+            .unwrap();
             let mut handler = PathFileHandler::new(path.to_path_buf());
             read::manage_feed_infos(&mut collections, &mut handler).unwrap();
+            let feed_infos = &collections.feed_infos;
+            assert_eq!(Some("0.14.0"), feed_infos.ntfs_version());
+            assert_eq!(
+                Some(chrono::NaiveDate::from_ymd_opt(2019, 4, 3).unwrap()),
+                feed_infos.creation_date()
+            );
+            assert_eq!(Some(get_test_datetime()), feed_infos.creation_datetime());
+            assert_eq!(
+                Some(chrono::NaiveDate::from_ymd_opt(2018, 1, 30).unwrap()),
+                feed_infos.start_date()
+            );
+            assert_eq!(
+                Some(chrono::NaiveDate::from_ymd_opt(2018, 1, 31).unwrap()),
+                feed_infos.end_date()
+            );
+            assert_eq!(Some("Nicaragua"), feed_infos.publisher_name());
+            assert_eq!(None, feed_infos.license());
             assert_eq!(
                 vec![
                     ("feed_creation_date".to_string(), "20190403".to_string()),
@@ -536,6 +844,60 @@ mod tests {
         });
     }
 
+    #[test]
+    fn feed_infos_geographic_extent() {
+        let stop_point = |id: &str, lon: f64, lat: f64| StopPoint {
+            id: id.to_string(),
+            coord: Coord { lon, lat },
+            ..Default::default()
+        };
+        let dataset = Dataset {
+            id: "Foo:0".to_string(),
+            contributor_id: "Foo".to_string(),
+            start_date: chrono::NaiveDate::from_ymd_opt(2018, 1, 30).unwrap(),
+            end_date: chrono::NaiveDate::from_ymd_opt(2018, 1, 31).unwrap(),
+            ..Default::default()
+        };
+        let mut collections = Collections {
+            datasets: CollectionWithId::from(dataset),
+            stop_points: CollectionWithId::new(vec![
+                stop_point("sp1", 2.37, 48.84),
+                stop_point("sp2", 2.35, 48.88),
+                stop_point("sp3", 2.34, 48.85),
+                stop_point("sp4", 2.30, 48.80),
+                stop_point("not_located", 0.0, 0.0),
+            ])
+            .unwrap(),
+            ..Default::default()
+        };
+
+        test_in_tmp_dir(|path| {
+            write::write_feed_infos(
+                path,
+                &collections,
+                get_test_datetime(),
+                NtfsVersion::default(),
+                None,
+                true,
+                &CsvFormat::default(),
+            )
+            .unwrap();
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            read::manage_feed_infos(&mut collections, &mut handler).unwrap();
+            let feed_infos = &collections.feed_infos;
+            assert_eq!(
+                Some("2.3,48.8,2.37,48.88"),
+                feed_infos.get(FeedInfos::BOUNDING_BOX).map(String::as_str)
+            );
+            assert_eq!(collections.bounding_box(), feed_infos.bounding_box());
+            match feed_infos.convex_hull() {
+                // sp3 is inside the hull of the 3 other stop points
+                Some(geo::Geometry::Polygon(polygon)) => assert_eq!(4, polygon.exterior().0.len()),
+                convex_hull => panic!("unexpected convex hull {:?}", convex_hull),
+            }
+        });
+    }
+
     #[test]
     fn networks_serialization_deserialization() {
         test_serialize_deserialize_collection_with_id(vec![
@@ -816,6 +1178,8 @@ mod tests {
                 &stop_points,
                 &headsigns,
                 &stop_time_ids,
+                &HashMap::new(),
+                &CsvFormat::default(),
             )
             .unwrap();
 
@@ -836,34 +1200,24 @@ mod tests {
     #[test]
     fn contributors_serialization_deserialization() {
         test_serialize_deserialize_collection_with_id(vec![
-            Contributor {
-                id: "Foo".to_string(),
-                name: "Foo".to_string(),
-                license: Some("ODbL".to_string()),
-                website: Some("http://www.foo.com".to_string()),
-            },
             Contributor {
                 id: "Bar".to_string(),
                 name: "Bar".to_string(),
                 license: None,
                 website: None,
             },
+            Contributor {
+                id: "Foo".to_string(),
+                name: "Foo".to_string(),
+                license: Some("ODbL".to_string()),
+                website: Some("http://www.foo.com".to_string()),
+            },
         ]);
     }
 
     #[test]
     fn datasets_serialization_deserialization() {
         test_serialize_deserialize_collection_with_id(vec![
-            Dataset {
-                id: "Foo:0".to_string(),
-                contributor_id: "Foo".to_string(),
-                start_date: chrono::NaiveDate::from_ymd_opt(2018, 1, 30).unwrap(),
-                end_date: chrono::NaiveDate::from_ymd_opt(2018, 1, 31).unwrap(),
-                dataset_type: Some(DatasetType::Theorical),
-                extrapolation: false,
-                desc: Some("description".to_string()),
-                system: Some("GTFS V2".to_string()),
-            },
             Dataset {
                 id: "Bar:0".to_string(),
                 contributor_id: "Bar".to_string(),
@@ -874,6 +1228,16 @@ mod tests {
                 desc: None,
                 system: None,
             },
+            Dataset {
+                id: "Foo:0".to_string(),
+                contributor_id: "Foo".to_string(),
+                start_date: chrono::NaiveDate::from_ymd_opt(2018, 1, 30).unwrap(),
+                end_date: chrono::NaiveDate::from_ymd_opt(2018, 1, 31).unwrap(),
+                dataset_type: Some(DatasetType::Theorical),
+                extrapolation: false,
+                desc: Some("description".to_string()),
+                system: Some("GTFS V2".to_string()),
+            },
         ]);
     }
 
@@ -903,6 +1267,8 @@ mod tests {
                 min_transfer_time: Some(20),
                 real_min_transfer_time: Some(30),
                 equipment_id: Some("eq_1".to_string()),
+                transfer_mode: None,
+                ..Default::default()
             },
             Transfer {
                 from_stop_id: "st_1".to_string(),
@@ -910,6 +1276,13 @@ mod tests {
                 min_transfer_time: None,
                 real_min_transfer_time: None,
                 equipment_id: Some("eq_1".to_string()),
+                transfer_mode: None,
+                start_time: Some(Time::new(7, 0, 0)),
+                end_time: Some(Time::new(21, 0, 0)),
+                days: Some(DaysOfWeek::new(&[
+                    chrono::Weekday::Mon,
+                    chrono::Weekday::Sat,
+                ])),
             },
         ];
         let expected_transfers = vec![
@@ -919,6 +1292,8 @@ mod tests {
                 min_transfer_time: Some(20),
                 real_min_transfer_time: Some(30),
                 equipment_id: Some("eq_1".to_string()),
+                transfer_mode: None,
+                ..Default::default()
             },
             Transfer {
                 from_stop_id: "st_1".to_string(),
@@ -926,15 +1301,41 @@ mod tests {
                 min_transfer_time: Some(0),
                 real_min_transfer_time: Some(0),
                 equipment_id: Some("eq_1".to_string()),
+                transfer_mode: None,
+                start_time: Some(Time::new(7, 0, 0)),
+                end_time: Some(Time::new(21, 0, 0)),
+                days: Some(DaysOfWeek::new(&[
+                    chrono::Weekday::Mon,
+                    chrono::Weekday::Sat,
+                ])),
             },
         ];
         let collection = Collection::new(transfers);
         let expected_collection = Collection::new(expected_transfers);
         test_in_tmp_dir(|path| {
-            write_collection(path, "file.txt", &collection).unwrap();
+            write_collection_with_optional_columns(
+                path,
+                "file.txt",
+                &collection,
+                TRANSFERS_EXTENSION_COLUMNS,
+                &CsvFormat::default(),
+            )
+            .unwrap();
+            // the unused extension columns are not written
+            assert_eq!(
+                Some("from_stop_id,to_stop_id,min_transfer_time,real_min_transfer_time,equipment_id,transfer_start_time,transfer_end_time,transfer_days"),
+                std::fs::read_to_string(path.join("file.txt")).unwrap().lines().next()
+            );
             let mut handler = PathFileHandler::new(path.to_path_buf());
             let des_collection = make_opt_collection(&mut handler, "file.txt").unwrap();
             assert_eq!(expected_collection, des_collection);
+            let validity = |collection: &Collection<Transfer>| -> Vec<_> {
+                collection
+                    .values()
+                    .map(|transfer| (transfer.start_time, transfer.end_time, transfer.days))
+                    .collect()
+            };
+            assert_eq!(validity(&expected_collection), validity(&des_collection));
         });
     }
 
@@ -961,7 +1362,7 @@ mod tests {
 
         test_in_tmp_dir(|path| {
             let mut handler = PathFileHandler::new(path.to_path_buf());
-            write_calendar_dates(path, &calendars).unwrap();
+            write_calendar_dates(path, &calendars, &CsvFormat::default()).unwrap();
 
             let mut collections = Collections::default();
             manage_calendars(&mut handler, &mut collections).unwrap();
@@ -973,6 +1374,19 @@ mod tests {
     #[test]
     fn stops_serialization_deserialization() {
         let stop_points = CollectionWithId::new(vec![
+            // stop point with no parent station
+            StopPoint {
+                id: "sa_2".to_string(),
+                name: "sa_name_2".to_string(),
+                visible: true,
+                coord: Coord {
+                    lon: 2.173_034,
+                    lat: 47.899_115,
+                },
+                stop_area_id: "Navitia:sa_2".to_string(),
+                stop_type: StopType::Point,
+                ..Default::default()
+            },
             StopPoint {
                 id: "sp_1".to_string(),
                 name: "sp_name_1".to_string(),
@@ -989,19 +1403,6 @@ mod tests {
                 stop_type: StopType::Point,
                 ..Default::default()
             },
-            // stop point with no parent station
-            StopPoint {
-                id: "sa_2".to_string(),
-                name: "sa_name_2".to_string(),
-                visible: true,
-                coord: Coord {
-                    lon: 2.173_034,
-                    lat: 47.899_115,
-                },
-                stop_area_id: "Navitia:sa_2".to_string(),
-                stop_type: StopType::Point,
-                ..Default::default()
-            },
         ])
         .unwrap();
 
@@ -1046,7 +1447,14 @@ mod tests {
         let stop_locations: CollectionWithId<StopLocation> = CollectionWithId::default();
 
         test_in_tmp_dir(|path| {
-            write::write_stops(path, &stop_points, &stop_areas, &stop_locations).unwrap();
+            write::write_stops(
+                path,
+                &stop_points,
+                &stop_areas,
+                &stop_locations,
+                &CsvFormat::default(),
+            )
+            .unwrap();
 
             let mut collections = Collections::default();
             let mut handler = PathFileHandler::new(path.to_path_buf());
@@ -1057,6 +1465,43 @@ mod tests {
         });
     }
 
+    #[test]
+    fn stops_with_csv_format() {
+        let stop_points = CollectionWithId::from(StopPoint {
+            id: "sp_1".to_string(),
+            name: "Gare, Nord".to_string(),
+            coord: Coord {
+                lon: 2.073_034,
+                lat: 48.8,
+            },
+            stop_area_id: "sa_1".to_string(),
+            ..Default::default()
+        });
+        let csv_format = CsvFormat {
+            quote_style: crate::csv_format::QuoteStyle::Always,
+            line_terminator: crate::csv_format::LineTerminator::Crlf,
+            coord_precision: Some(3),
+        };
+
+        test_in_tmp_dir(|path| {
+            write::write_stops(
+                path,
+                &stop_points,
+                &CollectionWithId::default(),
+                &CollectionWithId::default(),
+                &csv_format,
+            )
+            .unwrap();
+
+            let stops = std::fs::read_to_string(path.join("stops.txt")).unwrap();
+            let lines: Vec<&str> = stops.split_terminator("\r\n").collect();
+            assert_eq!(2, lines.len());
+            assert!(lines[0].starts_with("\"stop_id\",\"stop_name\""));
+            assert!(lines[1].starts_with("\"sp_1\",\"Gare, Nord\""));
+            assert!(lines[1].contains("\"2.073\",\"48.800\""));
+        });
+    }
+
     #[test]
     fn comments_codes_object_properties_serialization_deserialization() {
         let mut ser_collections = Collections::default();
@@ -1131,7 +1576,19 @@ mod tests {
             address_id: None,
         });
 
-        let stop_locations: CollectionWithId<StopLocation> = CollectionWithId::default();
+        let stop_locations = CollectionWithId::from(StopLocation {
+            id: "sl_1".to_string(),
+            name: "sl_name_1".to_string(),
+            comment_links: btree_set_from_vec(vec!["c:3".to_string()]),
+            visible: true,
+            coord: Coord {
+                lon: 2.073_034,
+                lat: 48.799_115,
+            },
+            parent_id: Some("sa_1".to_string()),
+            stop_type: StopType::StopEntrance,
+            ..Default::default()
+        });
 
         let lines = CollectionWithId::from(Line {
             id: "OIF:002002003:3OIF829".to_string(),
@@ -1186,7 +1643,7 @@ mod tests {
                 "prop_name:6".to_string(),
                 "prop_value:6".to_string()
             )],
-            comment_links: CommentLinksT::default(),
+            comment_links: btree_set_from_vec(vec!["c:1".to_string()]),
             route_id: "OIF:800:TER".to_string(),
             physical_mode_id: "Bus".to_string(),
             dataset_id: "OIF:0".to_string(),
@@ -1241,27 +1698,48 @@ mod tests {
         ser_collections.stop_time_comments = stop_time_comments;
 
         test_in_tmp_dir(|path| {
-            write_collection_with_id(path, "lines.txt", &ser_collections.lines).unwrap();
+            write_collection_with_id(
+                path,
+                "lines.txt",
+                &ser_collections.lines,
+                &CsvFormat::default(),
+            )
+            .unwrap();
             write::write_stops(
                 path,
                 &ser_collections.stop_points,
                 &ser_collections.stop_areas,
                 &ser_collections.stop_locations,
+                &CsvFormat::default(),
+            )
+            .unwrap();
+            write_collection_with_id(
+                path,
+                "routes.txt",
+                &ser_collections.routes,
+                &CsvFormat::default(),
+            )
+            .unwrap();
+            write_collection_with_id(
+                path,
+                "networks.txt",
+                &ser_collections.networks,
+                &CsvFormat::default(),
             )
             .unwrap();
-            write_collection_with_id(path, "routes.txt", &ser_collections.routes).unwrap();
-            write_collection_with_id(path, "networks.txt", &ser_collections.networks).unwrap();
             write::write_vehicle_journeys_and_stop_times(
                 path,
                 &ser_collections.vehicle_journeys,
                 &ser_collections.stop_points,
                 &ser_collections.stop_time_headsigns,
                 &ser_collections.stop_time_ids,
+                &ser_collections.stop_time_comments,
+                &CsvFormat::default(),
             )
             .unwrap();
-            write::write_comments(path, &ser_collections).unwrap();
-            write::write_codes(path, &ser_collections).unwrap();
-            write::write_object_properties(path, &ser_collections).unwrap();
+            write::write_comments(path, &ser_collections, &CsvFormat::default()).unwrap();
+            write::write_codes(path, &ser_collections, &CsvFormat::default()).unwrap();
+            write::write_object_properties(path, &ser_collections, &CsvFormat::default()).unwrap();
             let mut handler = PathFileHandler::new(path.to_path_buf());
 
             let mut des_collections = Collections {
@@ -1319,6 +1797,19 @@ mod tests {
                     .comment_links
             );
 
+            assert_eq!(
+                ser_collections
+                    .stop_locations
+                    .get("sl_1")
+                    .unwrap()
+                    .comment_links,
+                des_collections
+                    .stop_locations
+                    .get("sl_1")
+                    .unwrap()
+                    .comment_links
+            );
+
             assert_eq!(
                 ser_collections
                     .stop_areas
@@ -1616,18 +2107,18 @@ mod tests {
     #[test]
     fn ticket_use_perimeters_serialization_deserialization() {
         test_serialize_deserialize_collection(vec![
-            TicketUsePerimeter {
-                ticket_use_id: "PF1:TicketUse1".to_string(),
-                object_type: ObjectType::Network,
-                object_id: "PF1:Network1".to_string(),
-                perimeter_action: PerimeterAction::Included,
-            },
             TicketUsePerimeter {
                 ticket_use_id: "PF1:TicketUse1".to_string(),
                 object_type: ObjectType::Line,
                 object_id: "PF2:Line2".to_string(),
                 perimeter_action: PerimeterAction::Excluded,
             },
+            TicketUsePerimeter {
+                ticket_use_id: "PF1:TicketUse1".to_string(),
+                object_type: ObjectType::Network,
+                object_id: "PF1:Network1".to_string(),
+                perimeter_action: PerimeterAction::Included,
+            },
         ]);
     }
 
@@ -1648,4 +2139,166 @@ mod tests {
             },
         ]);
     }
+
+    #[test]
+    fn pois_serialization_deserialization() {
+        let mut ser_collections = Collections::default();
+        ser_collections.poi_types = CollectionWithId::from(PoiType {
+            id: "amenity:bicycle_rental".to_string(),
+            name: "Bike-sharing station".to_string(),
+        });
+        ser_collections.pois = CollectionWithId::new(vec![
+            objects::Poi {
+                id: "poi:1".to_string(),
+                name: "Station Bastille".to_string(),
+                coord: Coord {
+                    lon: 2.369,
+                    lat: 48.853,
+                },
+                poi_type_id: "amenity:bicycle_rental".to_string(),
+                weight: 0,
+                visible: true,
+                properties: vec![("capacity".to_string(), "20".to_string())]
+                    .into_iter()
+                    .collect(),
+            },
+            objects::Poi {
+                id: "poi:2".to_string(),
+                name: "Station Nation".to_string(),
+                coord: Coord {
+                    lon: 2.395,
+                    lat: 48.848,
+                },
+                poi_type_id: "amenity:bicycle_rental".to_string(),
+                weight: 1,
+                visible: false,
+                properties: BTreeMap::new(),
+            },
+        ])
+        .unwrap();
+
+        test_in_tmp_dir(|path| {
+            write::write_pois(path, &ser_collections, &CsvFormat::default()).unwrap();
+            let mut handler = PathFileHandler::new(path.to_path_buf());
+            let mut des_collections = Collections::default();
+            read::manage_pois(&mut des_collections, &mut handler).unwrap();
+
+            assert_eq!(ser_collections.poi_types, des_collections.poi_types);
+            assert_eq!(ser_collections.pois, des_collections.pois);
+        });
+    }
+
+    #[test]
+    fn stop_time_comment_without_stop_time_id() {
+        let mut collections = transit_model_builder::ModelBuilder::default()
+            .calendar("c1", &["2020-01-01"])
+            .vj("vj1", |vj| {
+                vj.calendar("c1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections();
+        collections.comments = CollectionWithId::from(Comment {
+            id: "on_request".to_string(),
+            comment_type: CommentType::Information,
+            label: Some("R".to_string()),
+            name: "Stops only on request".to_string(),
+            url: None,
+        });
+        collections
+            .stop_time_comments
+            .insert(("vj1".to_string(), 1), "on_request".to_string());
+        let model = Model::new(collections).unwrap();
+
+        test_in_tmp_dir(|path| {
+            write(&model, path, get_test_datetime()).unwrap();
+            let model = read(path).unwrap();
+            assert_eq!(
+                Some(&"on_request".to_string()),
+                model.stop_time_comments.get(&("vj1".to_string(), 1))
+            );
+            assert_eq!(
+                Some(&"vj1-1".to_string()),
+                model.stop_time_ids.get(&("vj1".to_string(), 1))
+            );
+            assert_eq!(None, model.stop_time_ids.get(&("vj1".to_string(), 0)));
+        });
+    }
+
+    #[test]
+    fn output_order_does_not_depend_on_collection_order() {
+        let write_ntfs = |reversed: bool, path: &path::Path| {
+            let mut vehicle_journeys = vec![("vj1", "A", "B"), ("vj2", "C", "D")];
+            let mut transfers = vec![("A", "C"), ("C", "A")];
+            if reversed {
+                vehicle_journeys.reverse();
+                transfers.reverse();
+            }
+            let mut builder =
+                transit_model_builder::ModelBuilder::default().calendar("c1", &["2020-01-01"]);
+            for (vj_id, from, to) in vehicle_journeys {
+                builder = builder.vj(vj_id, |vj| {
+                    vj.calendar("c1")
+                        .st(from, "10:00:00", "10:01:00")
+                        .st(to, "11:00:00", "11:01:00");
+                });
+            }
+            let mut collections = builder.build_model().into_collections();
+            collections.transfers = Collection::new(
+                transfers
+                    .into_iter()
+                    .map(|(from, to)| objects::Transfer {
+                        from_stop_id: from.to_string(),
+                        to_stop_id: to.to_string(),
+                        min_transfer_time: Some(60),
+                        real_min_transfer_time: Some(60),
+                        equipment_id: None,
+                        transfer_mode: None,
+                        ..Default::default()
+                    })
+                    .collect(),
+            );
+            let model = Model::new(collections).unwrap();
+            write(&model, path, get_test_datetime()).unwrap();
+        };
+        test_in_tmp_dir(|path| {
+            write_ntfs(false, &path.join("ntfs"));
+            write_ntfs(true, &path.join("reversed_ntfs"));
+            compare_output_dir_with_expected_content(
+                path.join("reversed_ntfs"),
+                None,
+                path.join("ntfs"),
+            );
+            assert_eq!(
+                "from_stop_id,to_stop_id,min_transfer_time,real_min_transfer_time,equipment_id\n\
+                 A,C,60,60,\n\
+                 C,A,60,60,\n",
+                std::fs::read_to_string(path.join("ntfs/transfers.txt")).unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn read_older_ntfs_with_upgrade_report() {
+        // the fixture is a NTFS 0.10.0 using the deprecated 'datetime_estimated'
+        let (model, report) = read_with_upgrade_report("tests/fixtures/minimal_ntfs").unwrap();
+        assert!(!model.vehicle_journeys.is_empty());
+        assert!(report.warnings().is_empty());
+        // occupancies.txt, the extensions of the crate not being reported
+        assert_eq!(
+            1,
+            report.count_category(
+                crate::report::Severity::Info,
+                &UpgradeReportCategory::MissingFile
+            )
+        );
+        assert_eq!(
+            1,
+            report.count_category(
+                crate::report::Severity::Info,
+                &UpgradeReportCategory::DeprecatedColumn
+            )
+        );
+    }
 }
@@ -12,7 +12,9 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
-use super::{Code, CommentLink, ObjectProperty, Stop, StopLocationType, StopTime};
+use super::{
+    Code, CommentLink, ObjectProperty, Poi, PoiProperty, Stop, StopLocationType, StopTime,
+};
 use crate::file_handler::FileHandler;
 use crate::model::Collections;
 use crate::ntfs::has_fares_v2;
@@ -23,9 +25,9 @@ use crate::Result;
 use anyhow::{anyhow, bail, ensure, Context};
 use serde::{Deserialize, Serialize};
 use skip_error::skip_error_and_warn;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
-use tracing::{error, info, warn};
+use tracing::{error, field, info, info_span, warn};
 use typed_index_collection::{Collection, CollectionWithId, Id, Idx};
 
 impl TryFrom<Stop> for StopArea {
@@ -209,6 +211,8 @@ where
             Ok(Collection::default())
         }
         Some(reader) => {
+            let span = info_span!("read_file", file = %basename, objects = field::Empty);
+            let _enter = span.enter();
             info!("Reading {}", basename);
             let mut rdr = csv::ReaderBuilder::new()
                 .flexible(true)
@@ -220,7 +224,9 @@ where
                 .deserialize()
                 .collect::<Result<_, _>>()
                 .with_context(|| format!("Error reading {:?}", path))?;
-            Ok(Collection::new(res))
+            let collection = Collection::new(res);
+            span.record("objects", collection.len());
+            Ok(collection)
         }
     }
 }
@@ -254,6 +260,55 @@ where
     Ok(())
 }
 
+fn make_stop_time(
+    collections: &Collections,
+    stop_time: &StopTime,
+    stop_point_idx: Idx<StopPoint>,
+) -> crate::objects::StopTime {
+    let datetime_estimated = stop_time.datetime_estimated.map_or_else(
+        || collections.stop_points[stop_point_idx].stop_type == StopType::Zone,
+        |v| v != 0,
+    );
+
+    let precision = stop_time.precision.clone().or({
+        if datetime_estimated {
+            Some(StopTimePrecision::Estimated)
+        } else {
+            Some(StopTimePrecision::Exact)
+        }
+    });
+
+    crate::objects::StopTime {
+        stop_point_idx,
+        sequence: stop_time.stop_sequence,
+        arrival_time: stop_time.arrival_time,
+        departure_time: stop_time.departure_time,
+        boarding_duration: stop_time.boarding_duration,
+        alighting_duration: stop_time.alighting_duration,
+        pickup_type: stop_time.pickup_type,
+        drop_off_type: stop_time.drop_off_type,
+        local_zone_id: stop_time.local_zone_id,
+        precision,
+    }
+}
+
+fn get_stop_point_idx(
+    collections: &Collections,
+    source_name: &str,
+    stop_time: &StopTime,
+) -> Result<Idx<StopPoint>> {
+    collections
+        .stop_points
+        .get_idx(&stop_time.stop_id)
+        .ok_or_else(|| {
+            anyhow!(
+                "Problem reading {:?}: stop_id={:?} not found",
+                source_name,
+                stop_time.stop_id
+            )
+        })
+}
+
 pub(crate) fn manage_stop_times<H>(
     collections: &mut Collections,
     file_handler: &mut H,
@@ -265,16 +320,8 @@ where
     let mut headsigns = HashMap::new();
     let mut stop_time_ids = HashMap::new();
     for stop_time in stop_times {
-        let stop_point_idx = collections
-            .stop_points
-            .get_idx(&stop_time.stop_id)
-            .ok_or_else(|| {
-                anyhow!(
-                    "Problem reading {:?}: stop_id={:?} not found",
-                    file_handler.source_name(),
-                    stop_time.stop_id
-                )
-            })?;
+        let stop_point_idx =
+            get_stop_point_idx(collections, file_handler.source_name(), &stop_time)?;
         let vj_idx = collections
             .vehicle_journeys
             .get_idx(&stop_time.trip_id)
@@ -286,25 +333,13 @@ where
                 )
             })?;
 
+        let new_stop_time = make_stop_time(collections, &stop_time, stop_point_idx);
         if let Some(headsign) = stop_time.stop_headsign {
             headsigns.insert(
                 (stop_time.trip_id.clone(), stop_time.stop_sequence),
                 headsign,
             );
         }
-        let datetime_estimated = stop_time.datetime_estimated.map_or_else(
-            || collections.stop_points[stop_point_idx].stop_type == StopType::Zone,
-            |v| v != 0,
-        );
-
-        let precision = stop_time.precision.or({
-            if datetime_estimated {
-                Some(StopTimePrecision::Estimated)
-            } else {
-                Some(StopTimePrecision::Exact)
-            }
-        });
-
         if let Some(stop_time_id) = stop_time.stop_time_id {
             stop_time_ids.insert(
                 (stop_time.trip_id.clone(), stop_time.stop_sequence),
@@ -316,24 +351,49 @@ where
             .vehicle_journeys
             .index_mut(vj_idx)
             .stop_times
-            .push(crate::objects::StopTime {
-                stop_point_idx,
-                sequence: stop_time.stop_sequence,
-                arrival_time: stop_time.arrival_time,
-                departure_time: stop_time.departure_time,
-                boarding_duration: stop_time.boarding_duration,
-                alighting_duration: stop_time.alighting_duration,
-                pickup_type: stop_time.pickup_type,
-                drop_off_type: stop_time.drop_off_type,
-                local_zone_id: stop_time.local_zone_id,
-                precision,
-            });
+            .push(new_stop_time);
     }
     collections.stop_time_headsigns = headsigns;
     collections.stop_time_ids = stop_time_ids;
     Ok(())
 }
 
+/// Read the stop times of the vehicle journeys `vj_ids` only, streaming
+/// `stop_times.txt` instead of loading it. The stop times are sorted by
+/// sequence.
+pub(crate) fn read_stop_times_of<H>(
+    collections: &Collections,
+    file_handler: &mut H,
+    vj_ids: &HashSet<&str>,
+) -> Result<HashMap<String, Vec<crate::objects::StopTime>>>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let source_name = file_handler.source_name().to_string();
+    let (reader, path) = file_handler.get_file("stop_times.txt")?;
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut stop_times: HashMap<String, Vec<crate::objects::StopTime>> = HashMap::new();
+    for stop_time in rdr.deserialize::<StopTime>() {
+        let stop_time = stop_time.with_context(|| format!("Error reading {:?}", path))?;
+        if !vj_ids.contains(stop_time.trip_id.as_str()) {
+            continue;
+        }
+        let stop_point_idx = get_stop_point_idx(collections, &source_name, &stop_time)?;
+        let new_stop_time = make_stop_time(collections, &stop_time, stop_point_idx);
+        stop_times
+            .entry(stop_time.trip_id)
+            .or_default()
+            .push(new_stop_time);
+    }
+    for vj_stop_times in stop_times.values_mut() {
+        vj_stop_times.sort_unstable_by_key(|stop_time| stop_time.sequence);
+    }
+    Ok(stop_times)
+}
+
 fn insert_code_with_idx<T>(collection: &mut CollectionWithId<T>, idx: Idx<T>, code: Code)
 where
     T: Codes + Id<T>,
@@ -456,7 +516,14 @@ fn insert_stop_time_comment_link(
 ) -> Result<()> {
     if let Some(vehicle_journey_id) = stop_time_ids.get(&comment_link.object_id) {
         if comments.contains_id(&comment_link.comment_id) {
-            stop_time_comments.insert(vehicle_journey_id.clone(), comment_link.comment_id.clone());
+            if let Some(replaced_comment_id) = stop_time_comments
+                .insert(vehicle_journey_id.clone(), comment_link.comment_id.clone())
+            {
+                warn!(
+                    "comment_links.txt: stop_time {} has several comments, {} replaces {}",
+                    comment_link.object_id, comment_link.comment_id, replaced_comment_id
+                );
+            }
         } else {
             bail!(
                 "comment.txt: comment_id={} not found",
@@ -499,6 +566,18 @@ where
                 &collections.comments,
                 &comment_link,
             )),
+            // the stop locations are referenced as stop points
+            ObjectType::StopPoint
+                if collections
+                    .stop_locations
+                    .contains_id(&comment_link.object_id) =>
+            {
+                skip_error_and_warn!(insert_comment_link(
+                    &mut collections.stop_locations,
+                    &collections.comments,
+                    &comment_link,
+                ))
+            }
             ObjectType::StopPoint => skip_error_and_warn!(insert_comment_link(
                 &mut collections.stop_points,
                 &collections.comments,
@@ -601,6 +680,35 @@ where
     Ok(())
 }
 
+/// Read the geometries `geometry_ids` only, streaming `geometries.txt`
+/// instead of loading it. The invalid geometries are ignored.
+pub(crate) fn read_geometries_of<H>(
+    file_handler: &mut H,
+    geometry_ids: &HashSet<&str>,
+) -> Result<Vec<Geometry>>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let (reader, path) = file_handler.get_file_if_exists("geometries.txt")?;
+    let reader = match reader {
+        Some(reader) => reader,
+        None => return Ok(vec![]),
+    };
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut geometries = Vec::new();
+    for geometry in rdr.deserialize::<Geometry>() {
+        let geometry: Geometry =
+            skip_error_and_warn!(geometry.with_context(|| format!("Error reading {:?}", path)));
+        if geometry_ids.contains(geometry.id.as_str()) {
+            geometries.push(geometry);
+        }
+    }
+    Ok(geometries)
+}
+
 pub fn manage_companies_on_vj(collections: &mut Collections) -> Result<()> {
     let vjs_without_company: Vec<Idx<VehicleJourney>> = collections
         .vehicle_journeys
@@ -669,6 +777,53 @@ where
     Ok(())
 }
 
+pub(crate) fn manage_pois<H>(collections: &mut Collections, file_handler: &mut H) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let poi_types: CollectionWithId<PoiType> =
+        make_opt_collection_with_id(file_handler, "poi_type.txt")?;
+    let mut properties: HashMap<String, PropertiesMap> = HashMap::new();
+    for property in read_objects::<_, PoiProperty>(file_handler, "poi_properties.txt", false)? {
+        properties
+            .entry(property.poi_id)
+            .or_default()
+            .insert(property.key, property.value);
+    }
+    let mut pois = CollectionWithId::default();
+    for poi in read_objects::<_, Poi>(file_handler, "poi.txt", false)? {
+        if !poi_types.contains_id(&poi.poi_type_id) {
+            warn!(
+                "Problem reading 'poi.txt': poi_type_id={} not found",
+                poi.poi_type_id
+            );
+            continue;
+        }
+        let properties = properties.remove(&poi.id).unwrap_or_default();
+        skip_error_and_warn!(pois.push(crate::objects::Poi {
+            id: poi.id,
+            name: poi.name,
+            coord: Coord {
+                lon: poi.lon,
+                lat: poi.lat,
+            },
+            poi_type_id: poi.poi_type_id,
+            weight: poi.weight,
+            visible: poi.visible,
+            properties,
+        }));
+    }
+    for poi_id in properties.keys() {
+        warn!(
+            "Problem reading 'poi_properties.txt': poi_id={} not found",
+            poi_id
+        );
+    }
+    collections.poi_types = poi_types;
+    collections.pois = pois;
+    Ok(())
+}
+
 pub(crate) fn manage_occupancies<H>(
     collections: &mut Collections,
     file_handler: &mut H,
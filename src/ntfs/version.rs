@@ -0,0 +1,537 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Versions of the NTFS written by the NTFS writer, and upgrade of the
+//! datasets of an older version when reading them.
+//!
+//! The files and columns missing from an older version are listed in
+//! `ADDITIONS`, which drives both the report of the upgraded datasets and the
+//! removal of these files and columns when writing an older version.
+
+use crate::{
+    csv_format::CsvFormat, file_handler::FileHandler, objects::FeedInfos, report::Report, Result,
+    NTFS_VERSION,
+};
+use anyhow::{anyhow, Context};
+use derivative::Derivative;
+use serde::Serialize;
+use std::{path::Path, str::FromStr};
+use tracing::info;
+
+/// Version of the NTFS specification targeted by the writer
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derivative(Default)]
+pub enum NtfsVersion {
+    /// NTFS 0.11.x, still expected by many consumers
+    V0_11,
+    /// Current version of the NTFS (see `NTFS_VERSION`)
+    #[derivative(Default)]
+    V0_14,
+}
+
+impl NtfsVersion {
+    /// Version written in the `ntfs_version` field of `feed_infos.txt`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NtfsVersion::V0_11 => "0.11.2",
+            NtfsVersion::V0_14 => NTFS_VERSION,
+        }
+    }
+
+    /// Supported version of a dataset from its `ntfs_version` (e.g.
+    /// `0.11.2`), the versions older than the current one being read as
+    /// 0.11. `None` for an invalid or a newer version.
+    pub fn from_ntfs_version(ntfs_version: &str) -> Option<Self> {
+        let mut numbers = ntfs_version.trim().split('.');
+        let major = numbers.next()?.parse::<u32>().ok()?;
+        let minor = numbers.next()?.parse::<u32>().ok()?;
+        match (major, minor) {
+            (0, minor) if minor < 14 => Some(NtfsVersion::V0_11),
+            (0, 14) => Some(NtfsVersion::V0_14),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for NtfsVersion {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "0.11" => Ok(NtfsVersion::V0_11),
+            "0.14" => Ok(NtfsVersion::V0_14),
+            _ => Err(anyhow!(
+                "unknown NTFS version {:?} (expected '0.11' or '0.14')",
+                s
+            )),
+        }
+    }
+}
+
+/// Availability of a file or a column written by the current writer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Since {
+    /// Part of the NTFS specification since this version
+    Ntfs(NtfsVersion),
+    /// Extension of the NTFS by this crate, optional whatever the version of
+    /// a dataset read, and written only for the current version
+    Extension,
+}
+
+impl Since {
+    /// `true` if the file or the column is not available in the `version`
+    fn is_after(self, version: NtfsVersion) -> bool {
+        match self {
+            Since::Ntfs(since) => since > version,
+            Since::Extension => version < NtfsVersion::default(),
+        }
+    }
+}
+
+/// A file, or a column of a file when `column` is set, written by the current
+/// writer and not available in all the supported versions
+struct Addition {
+    since: Since,
+    file: &'static str,
+    column: Option<&'static str>,
+}
+
+/// The files and columns which are not part of all the supported versions.
+/// This is the only place to update when a version, or an extension of the
+/// crate, adds a column.
+const ADDITIONS: &[Addition] = &[
+    Addition {
+        since: Since::Ntfs(NtfsVersion::V0_14),
+        file: "addresses.txt",
+        column: None,
+    },
+    Addition {
+        since: Since::Ntfs(NtfsVersion::V0_14),
+        file: "administrative_regions.txt",
+        column: None,
+    },
+    Addition {
+        since: Since::Ntfs(NtfsVersion::V0_14),
+        file: "stops.txt",
+        column: Some("address_id"),
+    },
+    Addition {
+        since: Since::Ntfs(NtfsVersion::V0_14),
+        file: "occupancies.txt",
+        column: None,
+    },
+    Addition {
+        since: Since::Extension,
+        file: "interchanges.txt",
+        column: None,
+    },
+    Addition {
+        since: Since::Extension,
+        file: "transfers.txt",
+        column: Some("transfer_mode"),
+    },
+    Addition {
+        since: Since::Extension,
+        file: "transfers.txt",
+        column: Some("transfer_start_time"),
+    },
+    Addition {
+        since: Since::Extension,
+        file: "transfers.txt",
+        column: Some("transfer_end_time"),
+    },
+    Addition {
+        since: Since::Extension,
+        file: "transfers.txt",
+        column: Some("transfer_days"),
+    },
+    Addition {
+        since: Since::Extension,
+        file: crate::stop_time_occupancy::STOP_TIME_OCCUPANCIES_FILE,
+        column: None,
+    },
+];
+
+/// Categories of the `Report` listing the aspects of an older NTFS upgraded
+/// when reading it
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpgradeReportCategory {
+    /// The version of the dataset is missing or unknown, it is read as the
+    /// current version
+    UnknownVersion,
+    /// A file of a newer version is missing and read as empty
+    MissingFile,
+    /// A column of a newer version is missing and read with its default value
+    MissingColumn,
+    /// A deprecated column is converted to the column replacing it
+    DeprecatedColumn,
+}
+
+/// A deprecated column of a file, read in place of the column `replacement`
+/// when the latter is missing
+struct Deprecation {
+    file: &'static str,
+    column: &'static str,
+    replacement: &'static str,
+}
+
+const DEPRECATIONS: &[Deprecation] = &[Deprecation {
+    file: "stop_times.txt",
+    column: "datetime_estimated",
+    replacement: "stop_time_precision",
+}];
+
+fn read_headers<H>(file_handler: &mut H, file: &str) -> Result<Option<csv::StringRecord>>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let (reader, path) = file_handler.get_file_if_exists(file)?;
+    reader
+        .map(|reader| {
+            csv::Reader::from_reader(reader)
+                .headers()
+                .cloned()
+                .with_context(|| format!("Error reading {:?}", path))
+        })
+        .transpose()
+}
+
+fn read_ntfs_version<H>(file_handler: &mut H) -> Result<Option<String>>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let (reader, path) = file_handler.get_file_if_exists("feed_infos.txt")?;
+    let reader = match reader {
+        Some(reader) => reader,
+        None => return Ok(None),
+    };
+    for record in csv::Reader::from_reader(reader).into_records() {
+        let record = record.with_context(|| format!("Error reading {:?}", path))?;
+        if record.get(0) == Some(FeedInfos::NTFS_VERSION) {
+            return Ok(record.get(1).map(str::to_string));
+        }
+    }
+    Ok(None)
+}
+
+/// List the files and columns of a newer version missing from the NTFS of
+/// `file_handler` and read with their default values, and the deprecated
+/// columns converted to the current ones.
+pub(crate) fn upgrade_report<H>(file_handler: &mut H) -> Result<Report<UpgradeReportCategory>>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let mut report = Report::default();
+    let ntfs_version = read_ntfs_version(file_handler)?;
+    let version = match ntfs_version
+        .as_deref()
+        .and_then(NtfsVersion::from_ntfs_version)
+    {
+        Some(version) => version,
+        None => {
+            report.add_warning(
+                format!(
+                    "unknown NTFS version {:?}, read as NTFS {}",
+                    ntfs_version.as_deref().unwrap_or_default(),
+                    NTFS_VERSION
+                ),
+                UpgradeReportCategory::UnknownVersion,
+            );
+            NtfsVersion::default()
+        }
+    };
+    for addition in ADDITIONS {
+        // the extensions of the crate are optional in every version
+        let since = match addition.since {
+            Since::Ntfs(since) if since > version => since,
+            _ => continue,
+        };
+        match (read_headers(file_handler, addition.file)?, addition.column) {
+            (None, None) => report.add_info(
+                format!(
+                    "{} of NTFS {} is missing, read as empty",
+                    addition.file,
+                    since.as_str()
+                ),
+                UpgradeReportCategory::MissingFile,
+            ),
+            (Some(headers), Some(column)) if !headers.iter().any(|name| name == column) => report
+                .add_info(
+                    format!(
+                        "column {} of {} of NTFS {} is missing, read with its default value",
+                        column,
+                        addition.file,
+                        since.as_str()
+                    ),
+                    UpgradeReportCategory::MissingColumn,
+                ),
+            _ => {}
+        }
+    }
+    for deprecation in DEPRECATIONS {
+        if let Some(headers) = read_headers(file_handler, deprecation.file)? {
+            if headers.iter().any(|name| name == deprecation.column)
+                && !headers.iter().any(|name| name == deprecation.replacement)
+            {
+                report.add_info(
+                    format!(
+                        "deprecated column {} of {} converted to {}",
+                        deprecation.column, deprecation.file, deprecation.replacement
+                    ),
+                    UpgradeReportCategory::DeprecatedColumn,
+                );
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn remove_column(path: &Path, column: &str, csv_format: &CsvFormat) -> Result<()> {
+    // the file is read entirely and closed before being overwritten
+    let records = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)
+        .with_context(|| format!("Error reading {:?}", path))?
+        .into_records()
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("Error reading {:?}", path))?;
+    let position = match records
+        .first()
+        .and_then(|header| header.iter().position(|name| name == column))
+    {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+
+    let mut wtr = csv_format
+        .writer_builder()
+        .flexible(true)
+        .from_path(path)
+        .with_context(|| format!("Error reading {:?}", path))?;
+    for record in &records {
+        wtr.write_record(
+            record
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != position)
+                .map(|(_, field)| field),
+        )
+        .with_context(|| format!("Error reading {:?}", path))?;
+    }
+    wtr.flush()
+        .with_context(|| format!("Error reading {:?}", path))?;
+    Ok(())
+}
+
+/// Remove from the NTFS written in the directory `path` the files and the
+/// columns which are not available in the `version`.
+pub(crate) fn downgrade(path: &Path, version: NtfsVersion, csv_format: &CsvFormat) -> Result<()> {
+    for addition in ADDITIONS
+        .iter()
+        .filter(|addition| addition.since.is_after(version))
+    {
+        let file_path = path.join(addition.file);
+        if !file_path.exists() {
+            continue;
+        }
+        match addition.column {
+            Some(column) => {
+                info!(
+                    "Removing column {} of {} for NTFS {}",
+                    column,
+                    addition.file,
+                    version.as_str()
+                );
+                remove_column(&file_path, column, csv_format)?;
+            }
+            None => {
+                info!("Removing {} for NTFS {}", addition.file, version.as_str());
+                std::fs::remove_file(&file_path)
+                    .with_context(|| format!("Error reading {:?}", file_path))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::Model, ntfs::WriteConfiguration, objects::*, test_fixtures::one_vehicle_journey,
+        test_utils::*,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn downgrade_to_0_11() {
+        test_in_tmp_dir(|path| {
+            std::fs::write(
+                path.join("stops.txt"),
+                "stop_id,stop_name,address_id,level_id\n\
+                 sp1,Gare,addr1,lvl1\n",
+            )
+            .unwrap();
+            std::fs::write(path.join("addresses.txt"), "address_id,street_name\n").unwrap();
+            downgrade(path, NtfsVersion::V0_11, &CsvFormat::default()).unwrap();
+
+            assert_eq!(
+                "stop_id,stop_name,level_id\n\
+                 sp1,Gare,lvl1\n",
+                std::fs::read_to_string(path.join("stops.txt")).unwrap()
+            );
+            assert!(!path.join("addresses.txt").exists());
+        });
+    }
+
+    #[test]
+    fn write_without_the_additions_of_newer_versions() {
+        let mut collections = one_vehicle_journey().into_collections();
+        // the model uses every file and column of `ADDITIONS`
+        collections
+            .administrative_regions
+            .push(AdministrativeRegion {
+                id: "admin1".to_string(),
+                insee: None,
+                level: Some(8),
+                name: Some("Paris".to_string()),
+                label: None,
+                zip_codes: None,
+                lon: None,
+                lat: None,
+            })
+            .unwrap();
+        collections
+            .addresses
+            .push(Address {
+                id: "addr1".to_string(),
+                street_name: "Rue de Rivoli".to_string(),
+                admin_level_8_id: Some("admin1".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        collections.stop_points.get_mut("A").unwrap().address_id = Some("addr1".to_string());
+        let line_id = collections.lines.values().next().unwrap().id.clone();
+        collections.occupancies.push(Occupancy {
+            line_id,
+            from_stop_area: "sa:A".to_string(),
+            to_stop_area: "sa:B".to_string(),
+            ..Default::default()
+        });
+        collections.stop_time_occupancies.push(StopTimeOccupancy {
+            vehicle_journey_id: "vj1".to_string(),
+            stop_sequence: 0,
+            occupancy: OccupancyStatus::Full,
+        });
+        collections.interchanges.push(Interchange {
+            from_vehicle_journey_id: "vj1".to_string(),
+            from_stop_point_id: "B".to_string(),
+            to_vehicle_journey_id: "vj1".to_string(),
+            to_stop_point_id: "A".to_string(),
+            ..Default::default()
+        });
+        collections.transfers.push(Transfer {
+            from_stop_id: "A".to_string(),
+            to_stop_id: "B".to_string(),
+            min_transfer_time: Some(60),
+            real_min_transfer_time: Some(60),
+            equipment_id: None,
+            transfer_mode: Some(TransferMode::Bike),
+            start_time: Some(Time::new(7, 0, 0)),
+            end_time: Some(Time::new(21, 0, 0)),
+            days: Some(DaysOfWeek::new(&[chrono::Weekday::Mon])),
+        });
+        let model = Model::new(collections).unwrap();
+
+        test_in_tmp_dir(|path| {
+            for version in [NtfsVersion::V0_11, NtfsVersion::V0_14] {
+                let output = path.join(version.as_str());
+                crate::ntfs::write_with_configuration(
+                    &model,
+                    &output,
+                    WriteConfiguration::new(get_test_datetime()).version(version),
+                )
+                .unwrap();
+                let mut file_handler = crate::file_handler::PathFileHandler::new(output);
+                for addition in ADDITIONS {
+                    let written = match (
+                        read_headers(&mut file_handler, addition.file).unwrap(),
+                        addition.column,
+                    ) {
+                        (None, _) => false,
+                        (Some(_), None) => true,
+                        (Some(headers), Some(column)) => headers.iter().any(|name| name == column),
+                    };
+                    assert_eq!(
+                        !addition.since.is_after(version),
+                        written,
+                        "{} {:?} in NTFS {}",
+                        addition.file,
+                        addition.column,
+                        version.as_str()
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn no_downgrade_for_current_version() {
+        test_in_tmp_dir(|path| {
+            let stops = "stop_id,stop_name,address_id\nsp1,Gare,addr1\n";
+            std::fs::write(path.join("stops.txt"), stops).unwrap();
+            downgrade(path, NtfsVersion::default(), &CsvFormat::default()).unwrap();
+
+            assert_eq!(
+                stops,
+                std::fs::read_to_string(path.join("stops.txt")).unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn detect_version() {
+        assert_eq!(
+            Some(NtfsVersion::V0_11),
+            NtfsVersion::from_ntfs_version("0.10.0")
+        );
+        assert_eq!(
+            Some(NtfsVersion::V0_14),
+            NtfsVersion::from_ntfs_version("0.14.0")
+        );
+        assert_eq!(None, NtfsVersion::from_ntfs_version("1.0.0"));
+        assert_eq!(None, NtfsVersion::from_ntfs_version("latest"));
+    }
+
+    #[test]
+    fn report_missing_version() {
+        test_in_tmp_dir(|path| {
+            create_file_with_content(path, "feed_infos.txt", "feed_info_param,feed_info_value\n");
+            create_file_with_content(path, "stops.txt", "stop_id,stop_name\n");
+            let mut file_handler = crate::file_handler::PathFileHandler::new(path.to_path_buf());
+            let report = upgrade_report(&mut file_handler).unwrap();
+
+            assert_eq!(1, report.warnings().len());
+            assert!(report.infos().is_empty());
+        });
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(NtfsVersion::V0_11, "0.11".parse().unwrap());
+        assert_eq!("0.14.0", "0.14".parse::<NtfsVersion>().unwrap().as_str());
+        assert!("0.9".parse::<NtfsVersion>().is_err());
+    }
+}
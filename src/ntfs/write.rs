@@ -12,54 +12,57 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
-use super::{Code, CommentLink, ObjectProperty, Result, Stop, StopLocationType, StopTime};
+use super::{
+    Code, CommentLink, NtfsVersion, ObjectProperty, Poi, PoiProperty, Result, Stop,
+    StopLocationType, StopTime,
+};
+use crate::csv_format::{CsvFormat, CsvWriter};
 use crate::model::Collections;
 use crate::ntfs::{has_fares_v1, has_fares_v2};
 use crate::objects::*;
-use crate::NTFS_VERSION;
+use crate::stamp::Stamp;
+use crate::utils::{sorted_by_id, write_collection_with_id};
 use anyhow::{anyhow, bail, Context};
 use chrono::{DateTime, Duration, FixedOffset};
-use csv::Writer;
 use rust_decimal::{prelude::ToPrimitive, Decimal};
 use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::path;
-use tracing::{info, warn};
+use tracing::{info, info_span, warn};
 use typed_index_collection::{Collection, CollectionWithId, Id};
 
 pub fn write_feed_infos(
     path: &path::Path,
     collections: &Collections,
     current_datetime: DateTime<FixedOffset>,
+    version: NtfsVersion,
+    stamp: Option<&Stamp>,
+    geographic_extent: bool,
+    csv_format: &CsvFormat,
 ) -> Result<()> {
+    let _span = info_span!("write_file", file = "feed_infos.txt").entered();
     info!("Writing feed_infos.txt");
     let path = path.join("feed_infos.txt");
     let mut feed_infos = collections.feed_infos.clone();
-    feed_infos.insert(
-        "feed_creation_date".to_string(),
-        current_datetime.format("%Y%m%d").to_string(),
-    );
-    feed_infos.insert(
-        "feed_creation_time".to_string(),
-        current_datetime.format("%T").to_string(),
-    );
-    feed_infos.insert(
-        "feed_creation_datetime".to_string(),
-        current_datetime.to_rfc3339(),
-    );
-    feed_infos.insert("ntfs_version".to_string(), NTFS_VERSION.to_string());
+    feed_infos.set_creation_datetime(current_datetime);
+    feed_infos.set_ntfs_version(version.as_str());
     let (start_date, end_date) = collections.calculate_validity_period()?;
-    feed_infos.insert(
-        "feed_start_date".to_string(),
-        start_date.format("%Y%m%d").to_string(),
-    );
-    feed_infos.insert(
-        "feed_end_date".to_string(),
-        end_date.format("%Y%m%d").to_string(),
-    );
-
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    feed_infos.set_validity_period(start_date, end_date);
+    if geographic_extent {
+        if let Some(bounding_box) = collections.bounding_box() {
+            feed_infos.set_bounding_box(&bounding_box);
+        }
+        if let Some(convex_hull) = collections.convex_hull() {
+            feed_infos.set_convex_hull(&convex_hull);
+        }
+    }
+    if let Some(stamp) = stamp {
+        stamp.stamp_feed_infos(&mut feed_infos);
+    }
+
+    let mut wtr = csv_format
+        .writer_from_path(&path)
+        .with_context(|| format!("Error reading {:?}", path))?;
     wtr.write_record(["feed_info_param", "feed_info_value"])
         .with_context(|| format!("Error reading {:?}", path))?;
     for feed_info in feed_infos {
@@ -71,21 +74,46 @@ pub fn write_feed_infos(
     Ok(())
 }
 
+// Identifier of the stop time written in `stop_times.txt`. The stop times with
+// a comment need one to be referenced in `comment_links.txt`, so it is
+// generated from the trip and the stop sequence when missing.
+fn stop_time_id(
+    key: &(String, u32),
+    stop_time_ids: &HashMap<(String, u32), String>,
+    stop_time_comments: &HashMap<(String, u32), String>,
+) -> Option<String> {
+    match stop_time_ids.get(key) {
+        Some(stop_time_id) => Some(stop_time_id.clone()),
+        None if stop_time_comments.contains_key(key) => Some(format!("{}-{}", key.0, key.1)),
+        None => None,
+    }
+}
+
 pub fn write_vehicle_journeys_and_stop_times(
     path: &path::Path,
     vehicle_journeys: &CollectionWithId<VehicleJourney>,
     stop_points: &CollectionWithId<StopPoint>,
     stop_time_headsigns: &HashMap<(String, u32), String>,
     stop_time_ids: &HashMap<(String, u32), String>,
+    stop_time_comments: &HashMap<(String, u32), String>,
+    csv_format: &CsvFormat,
 ) -> Result<()> {
+    let _span = info_span!(
+        "write_file",
+        file = "trips.txt and stop_times.txt",
+        objects = vehicle_journeys.len()
+    )
+    .entered();
     info!("Writing trips.txt and stop_times.txt");
     let trip_path = path.join("trips.txt");
     let stop_times_path = path.join("stop_times.txt");
-    let mut vj_wtr = csv::Writer::from_path(&trip_path)
+    let mut vj_wtr = csv_format
+        .writer_from_path(&trip_path)
         .with_context(|| format!("Error reading {:?}", trip_path))?;
-    let mut st_wtr = csv::Writer::from_path(&stop_times_path)
+    let mut st_wtr = csv_format
+        .writer_from_path(&stop_times_path)
         .with_context(|| format!("Error reading {:?}", stop_times_path))?;
-    for (vj_idx, vj) in vehicle_journeys.iter() {
+    for vj in sorted_by_id(vehicle_journeys) {
         vj_wtr
             .serialize(vj)
             .with_context(|| format!("Error reading {:?}", trip_path))?;
@@ -106,14 +134,16 @@ pub fn write_vehicle_journeys_and_stop_times(
                     datetime_estimated: None,
                     local_zone_id: st.local_zone_id,
                     stop_headsign: stop_time_headsigns
-                        .get(&(vehicle_journeys[vj_idx].id.clone(), st.sequence))
-                        .cloned(),
-                    stop_time_id: stop_time_ids
-                        .get(&(vehicle_journeys[vj_idx].id.clone(), st.sequence))
+                        .get(&(vj.id.clone(), st.sequence))
                         .cloned(),
+                    stop_time_id: stop_time_id(
+                        &(vj.id.clone(), st.sequence),
+                        stop_time_ids,
+                        stop_time_comments,
+                    ),
                     precision,
                 })
-                .with_context(|| format!("Error reading {:?}", st_wtr))?;
+                .with_context(|| format!("Error reading {:?}", stop_times_path))?;
         }
     }
     st_wtr
@@ -132,6 +162,7 @@ fn do_write_fares_v1(
     od_fares_v1: &Collection<OdFareV1>,
     fares_v1: &Collection<FareV1>,
 ) -> Result<()> {
+    let _span = info_span!("write_fares_v1").entered();
     let file_prices = "prices.csv";
     let file_od_fares = "od_fares.csv";
     let file_fares = "fares.csv";
@@ -521,12 +552,13 @@ pub fn write_stops(
     stop_points: &CollectionWithId<StopPoint>,
     stop_areas: &CollectionWithId<StopArea>,
     stop_locations: &CollectionWithId<StopLocation>,
+    csv_format: &CsvFormat,
 ) -> Result<()> {
     fn write_stop_locations(
-        wtr: &mut Writer<File>,
+        wtr: &mut CsvWriter<File>,
         stop_locations: &CollectionWithId<StopLocation>,
     ) -> Result<()> {
-        for sl in stop_locations.values() {
+        for sl in sorted_by_id(stop_locations) {
             let (lon, lat) = sl.coord.into();
             wtr.serialize(Stop {
                 id: sl.id.clone(),
@@ -549,11 +581,18 @@ pub fn write_stops(
         Ok(())
     }
     let file = "stops.txt";
+    let _span = info_span!(
+        "write_file",
+        file,
+        objects = stop_points.len() + stop_areas.len() + stop_locations.len()
+    )
+    .entered();
     info!("Writing {}", file);
     let path = path.join(file);
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
-    for st in stop_points.values() {
+    let mut wtr = csv_format
+        .writer_from_path(&path)
+        .with_context(|| format!("Error reading {:?}", path))?;
+    for st in sorted_by_id(stop_points) {
         let location_type = if st.stop_type == StopType::Zone {
             StopLocationType::GeographicArea
         } else {
@@ -579,7 +618,7 @@ pub fn write_stops(
         .with_context(|| format!("Error reading {:?}", path))?;
     }
 
-    for sa in stop_areas.values() {
+    for sa in sorted_by_id(stop_areas) {
         wtr.serialize(Stop {
             id: sa.id.clone(),
             visible: sa.visible,
@@ -608,7 +647,7 @@ pub fn write_stops(
 }
 
 fn write_comment_links_from_collection_with_id<W, T>(
-    wtr: &mut csv::Writer<W>,
+    wtr: &mut CsvWriter<W>,
     collection: &CollectionWithId<T>,
     path: &path::Path,
 ) -> Result<()>
@@ -616,7 +655,7 @@ where
     T: Id<T> + CommentLinks + GetObjectType,
     W: ::std::io::Write,
 {
-    for obj in collection.values() {
+    for obj in sorted_by_id(collection) {
         for comment_id in obj.comment_links().iter() {
             wtr.serialize(CommentLink {
                 object_id: obj.id().to_string(),
@@ -631,7 +670,7 @@ where
 }
 
 fn write_stop_time_comment_links<W>(
-    wtr: &mut csv::Writer<W>,
+    wtr: &mut CsvWriter<W>,
     stop_time_ids: &HashMap<(String, u32), String>,
     stop_time_comments: &HashMap<(String, u32), String>,
     path: &path::Path,
@@ -639,11 +678,20 @@ fn write_stop_time_comment_links<W>(
 where
     W: ::std::io::Write,
 {
-    for (idx_sequence, id_comment) in stop_time_comments {
-        let st_id = &stop_time_ids[idx_sequence];
+    let mut sorted_stop_time_comments: Vec<_> = stop_time_comments.iter().collect();
+    sorted_stop_time_comments.sort_unstable();
+    for (idx_sequence, id_comment) in sorted_stop_time_comments {
+        let st_id =
+            stop_time_id(idx_sequence, stop_time_ids, stop_time_comments).ok_or_else(|| {
+                anyhow!(
+                    "no identifier for the stop time {} of vehicle journey {}",
+                    idx_sequence.1,
+                    idx_sequence.0
+                )
+            })?;
 
         wtr.serialize(CommentLink {
-            object_id: st_id.to_string(),
+            object_id: st_id,
             object_type: ObjectType::StopTime,
             comment_id: id_comment.to_string(),
         })
@@ -653,20 +701,32 @@ where
     Ok(())
 }
 
-pub fn write_comments(path: &path::Path, collections: &Collections) -> Result<()> {
+pub fn write_comments(
+    path: &path::Path,
+    collections: &Collections,
+    csv_format: &CsvFormat,
+) -> Result<()> {
     if collections.comments.is_empty() {
         return Ok(());
     }
+    let _span = info_span!(
+        "write_file",
+        file = "comments.txt and comment_links.txt",
+        objects = collections.comments.len()
+    )
+    .entered();
     info!("Writing comments.txt and comment_links.txt");
 
     let comments_path = path.join("comments.txt");
     let comment_links_path = path.join("comment_links.txt");
 
-    let mut c_wtr = csv::Writer::from_path(&comments_path)
+    let mut c_wtr = csv_format
+        .writer_from_path(&comments_path)
         .with_context(|| format!("Error reading {:?}", comments_path))?;
-    let mut cl_wtr = csv::Writer::from_path(&comment_links_path)
+    let mut cl_wtr = csv_format
+        .writer_from_path(&comment_links_path)
         .with_context(|| format!("Error reading {:?}", comment_links_path))?;
-    for c in collections.comments.values() {
+    for c in sorted_by_id(&collections.comments) {
         c_wtr
             .serialize(c)
             .with_context(|| format!("Error reading {:?}", comments_path))?;
@@ -682,6 +742,11 @@ pub fn write_comments(path: &path::Path, collections: &Collections) -> Result<()
         &collections.stop_points,
         &comment_links_path,
     )?;
+    write_comment_links_from_collection_with_id(
+        &mut cl_wtr,
+        &collections.stop_locations,
+        &comment_links_path,
+    )?;
     write_comment_links_from_collection_with_id(
         &mut cl_wtr,
         &collections.lines,
@@ -718,7 +783,7 @@ pub fn write_comments(path: &path::Path, collections: &Collections) -> Result<()
 }
 
 fn write_codes_from_collection_with_id<W, T>(
-    wtr: &mut csv::Writer<W>,
+    wtr: &mut CsvWriter<W>,
     collections: &CollectionWithId<T>,
     path: &path::Path,
 ) -> Result<()>
@@ -726,7 +791,7 @@ where
     T: Id<T> + Codes + GetObjectType,
     W: ::std::io::Write,
 {
-    for obj in collections.values() {
+    for obj in sorted_by_id(collections) {
         for c in obj.codes() {
             wtr.serialize(Code {
                 object_id: obj.id().to_string(),
@@ -741,7 +806,11 @@ where
     Ok(())
 }
 
-pub fn write_codes(path: &path::Path, collections: &Collections) -> Result<()> {
+pub fn write_codes(
+    path: &path::Path,
+    collections: &Collections,
+    csv_format: &CsvFormat,
+) -> Result<()> {
     fn collection_has_no_codes<T: Codes>(collection: &CollectionWithId<T>) -> bool {
         collection.values().all(|c| c.codes().is_empty())
     }
@@ -756,12 +825,14 @@ pub fn write_codes(path: &path::Path, collections: &Collections) -> Result<()> {
         return Ok(());
     }
 
+    let _span = info_span!("write_file", file = "object_codes.txt").entered();
     info!("Writing object_codes.txt");
 
     let path = path.join("object_codes.txt");
 
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let mut wtr = csv_format
+        .writer_from_path(&path)
+        .with_context(|| format!("Error reading {:?}", path))?;
     write_codes_from_collection_with_id(&mut wtr, &collections.stop_areas, &path)?;
     write_codes_from_collection_with_id(&mut wtr, &collections.stop_points, &path)?;
     write_codes_from_collection_with_id(&mut wtr, &collections.networks, &path)?;
@@ -777,7 +848,7 @@ pub fn write_codes(path: &path::Path, collections: &Collections) -> Result<()> {
 }
 
 fn write_object_properties_from_collection_with_id<W, T>(
-    wtr: &mut csv::Writer<W>,
+    wtr: &mut CsvWriter<W>,
     collection: &CollectionWithId<T>,
     path: &path::Path,
 ) -> Result<()>
@@ -785,7 +856,7 @@ where
     T: Id<T> + Properties + GetObjectType,
     W: ::std::io::Write,
 {
-    for obj in collection.values() {
+    for obj in sorted_by_id(collection) {
         for c in obj.properties() {
             wtr.serialize(ObjectProperty {
                 object_id: obj.id().to_string(),
@@ -800,7 +871,11 @@ where
     Ok(())
 }
 
-pub fn write_object_properties(path: &path::Path, collections: &Collections) -> Result<()> {
+pub fn write_object_properties(
+    path: &path::Path,
+    collections: &Collections,
+    csv_format: &CsvFormat,
+) -> Result<()> {
     fn collection_has_no_object_properties<T: Properties>(
         collection: &CollectionWithId<T>,
     ) -> bool {
@@ -815,12 +890,14 @@ pub fn write_object_properties(path: &path::Path, collections: &Collections) ->
         return Ok(());
     }
 
+    let _span = info_span!("write_file", file = "object_properties.txt").entered();
     info!("Writing object_properties.txt");
 
     let path = path.join("object_properties.txt");
 
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let mut wtr = csv_format
+        .writer_from_path(&path)
+        .with_context(|| format!("Error reading {:?}", path))?;
     write_object_properties_from_collection_with_id(&mut wtr, &collections.stop_areas, &path)?;
     write_object_properties_from_collection_with_id(&mut wtr, &collections.stop_points, &path)?;
     write_object_properties_from_collection_with_id(&mut wtr, &collections.lines, &path)?;
@@ -836,3 +913,69 @@ pub fn write_object_properties(path: &path::Path, collections: &Collections) ->
 
     Ok(())
 }
+
+pub fn write_pois(
+    path: &path::Path,
+    collections: &Collections,
+    csv_format: &CsvFormat,
+) -> Result<()> {
+    write_collection_with_id(path, "poi_type.txt", &collections.poi_types, csv_format)?;
+    if collections.pois.is_empty() {
+        return Ok(());
+    }
+    let _span = info_span!(
+        "write_file",
+        file = "poi.txt and poi_properties.txt",
+        objects = collections.pois.len()
+    )
+    .entered();
+    info!("Writing poi.txt and poi_properties.txt");
+    let poi_path = path.join("poi.txt");
+    let mut poi_wtr = csv_format
+        .writer_from_path(&poi_path)
+        .with_context(|| format!("Error reading {:?}", poi_path))?;
+    for poi in sorted_by_id(&collections.pois) {
+        poi_wtr
+            .serialize(Poi {
+                id: poi.id.clone(),
+                name: poi.name.clone(),
+                lat: poi.coord.lat,
+                lon: poi.coord.lon,
+                poi_type_id: poi.poi_type_id.clone(),
+                weight: poi.weight,
+                visible: poi.visible,
+            })
+            .with_context(|| format!("Error reading {:?}", poi_path))?;
+    }
+    poi_wtr
+        .flush()
+        .with_context(|| format!("Error reading {:?}", poi_path))?;
+
+    if collections
+        .pois
+        .values()
+        .all(|poi| poi.properties.is_empty())
+    {
+        return Ok(());
+    }
+    let properties_path = path.join("poi_properties.txt");
+    let mut properties_wtr = csv_format
+        .writer_from_path(&properties_path)
+        .with_context(|| format!("Error reading {:?}", properties_path))?;
+    for poi in sorted_by_id(&collections.pois) {
+        for (key, value) in &poi.properties {
+            properties_wtr
+                .serialize(PoiProperty {
+                    poi_id: poi.id.clone(),
+                    key: key.clone(),
+                    value: value.clone(),
+                })
+                .with_context(|| format!("Error reading {:?}", properties_path))?;
+        }
+    }
+    properties_wtr
+        .flush()
+        .with_context(|| format!("Error reading {:?}", properties_path))?;
+
+    Ok(())
+}
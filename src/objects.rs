@@ -17,15 +17,16 @@
 #![allow(missing_docs)]
 
 use crate::{serde_utils::*, AddPrefix, PrefixConfiguration};
-use chrono::NaiveDate;
+use chrono::{NaiveDate, Weekday};
 use chrono_tz::Tz;
 use derivative::Derivative;
-use geo::{Geometry as GeoGeometry, Point as GeoPoint};
+use geo::{Geometry as GeoGeometry, Point as GeoPoint, Rect};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::BTreeSet;
 use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
 use std::ops::{Add, Div, Rem, Sub};
 use std::str::FromStr;
 use thiserror::Error;
@@ -100,9 +101,55 @@ macro_rules! properties_map {
             map
     }};
 }
+#[derive(Debug, Error)]
+pub enum PropertyError {
+    #[error("invalid value '{value}' for the object property '{name}': {reason}")]
+    InvalidValue {
+        name: String,
+        value: String,
+        reason: String,
+    },
+}
+
 pub trait Properties {
     fn properties(&self) -> &PropertiesMap;
     fn properties_mut(&mut self) -> &mut PropertiesMap;
+
+    /// Raw value of the object property `name`, if any.
+    fn property(&self, name: &str) -> Option<&str> {
+        self.properties().get(name).map(String::as_str)
+    }
+
+    /// Value of the object property `name` parsed as a `T`, `Ok(None)` if the
+    /// object has no such property.
+    fn typed_property<T>(&self, name: &str) -> Result<Option<T>, PropertyError>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.property(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e: T::Err| PropertyError::InvalidValue {
+                        name: name.to_string(),
+                        value: value.to_string(),
+                        reason: e.to_string(),
+                    })
+            })
+            .transpose()
+    }
+
+    /// Set the object property `name`, returning its previous value.
+    fn set_property<V: ToString>(&mut self, name: &str, value: V) -> Option<String> {
+        self.properties_mut()
+            .insert(name.to_string(), value.to_string())
+    }
+
+    /// Remove the object property `name`, returning its value.
+    fn remove_property(&mut self, name: &str) -> Option<String> {
+        self.properties_mut().remove(name)
+    }
 }
 macro_rules! impl_properties {
     ($ty:ty) => {
@@ -147,7 +194,7 @@ macro_rules! impl_comment_links {
     };
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Contributor {
     #[serde(rename = "contributor_id")]
     pub id: String,
@@ -179,7 +226,7 @@ impl Default for Contributor {
 impl_with_id!(Contributor);
 impl_id!(Contributor);
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub enum DatasetType {
     #[serde(rename = "0")]
     Theorical,
@@ -210,7 +257,7 @@ impl Default for ValidityPeriod {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Dataset {
     #[serde(rename = "dataset_id")]
     pub id: String,
@@ -292,7 +339,7 @@ impl WithId for Dataset {
     }
 }
 
-#[derive(Derivative, Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Derivative, Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[derivative(Default)]
 pub struct CommercialMode {
     #[derivative(Default(value = "\"default_commercial_mode\".into()"))]
@@ -1121,7 +1168,7 @@ impl GetObjectType for StopArea {
         ObjectType::StopArea
     }
 }
-#[derive(Derivative, Debug, Eq, PartialEq, Clone)]
+#[derive(Derivative, Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 #[derivative(Default)]
 pub enum StopType {
     #[derivative(Default)]
@@ -1215,6 +1262,14 @@ pub struct StopLocation {
 impl_id!(StopLocation);
 impl_comment_links!(StopLocation);
 
+// The stop locations are written in `stops.txt` like the stop points, so they
+// are referenced as such in the NTFS (e.g. in `comment_links.txt`)
+impl GetObjectType for StopLocation {
+    fn get_object_type() -> ObjectType {
+        ObjectType::StopPoint
+    }
+}
+
 impl AddPrefix for StopLocation {
     fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
         self.id = prefix_conf.referential_prefix(self.id.as_str());
@@ -1337,6 +1392,21 @@ impl Calendar {
     pub fn overlaps(&self, other: &Self) -> bool {
         !self.dates.is_disjoint(&other.dates)
     }
+
+    /// Adds the `dates` to the calendar
+    pub fn union(&mut self, dates: &BTreeSet<Date>) {
+        self.dates.extend(dates);
+    }
+
+    /// Keeps only the dates of the calendar which are also in `dates`
+    pub fn intersection(&mut self, dates: &BTreeSet<Date>) {
+        self.dates.retain(|date| dates.contains(date));
+    }
+
+    /// Removes the `dates` from the calendar
+    pub fn subtraction(&mut self, dates: &BTreeSet<Date>) {
+        self.dates.retain(|date| !dates.contains(date));
+    }
 }
 
 impl AddPrefix for Calendar {
@@ -1354,7 +1424,7 @@ impl WithId for Calendar {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Company {
     #[serde(rename = "company_id")]
     pub id: String,
@@ -1401,7 +1471,7 @@ impl GetObjectType for Company {
 
 impl_with_id!(Company);
 
-#[derive(Derivative)]
+#[derive(Clone, Derivative)]
 #[derivative(Default(bound = ""))]
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "snake_case")]
@@ -1411,7 +1481,7 @@ pub enum CommentType {
     OnDemandTransport,
 }
 
-#[derive(Default, Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Default, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Comment {
     #[serde(rename = "comment_id")]
     pub id: String,
@@ -1496,7 +1566,87 @@ impl Equipment {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Derivative)]
+/// How a transfer is made, written in the `transfer_mode` extension column
+/// of the `transfers.txt` of a NTFS. A transfer without mode is walked.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferMode {
+    Walk,
+    Bike,
+    ParkAndRide,
+}
+
+/// Days of the week, written as 7 digits `0` or `1` from Monday to Sunday
+/// (e.g. `1111100` for the working days)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DaysOfWeek(u8);
+
+impl DaysOfWeek {
+    /// The days `weekdays`
+    pub fn new(weekdays: &[Weekday]) -> Self {
+        DaysOfWeek(weekdays.iter().fold(0, |days, weekday| {
+            days | 1 << weekday.num_days_from_monday()
+        }))
+    }
+
+    /// `true` if `weekday` is one of the days
+    pub fn contains(self, weekday: Weekday) -> bool {
+        self.0 & 1 << weekday.num_days_from_monday() != 0
+    }
+}
+
+impl std::fmt::Display for DaysOfWeek {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for day in 0..7 {
+            f.write_str(if self.0 & 1 << day != 0 { "1" } else { "0" })?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Days of the week should be 7 digits 0 or 1 from Monday to Sunday")]
+pub struct DaysOfWeekError;
+
+impl FromStr for DaysOfWeek {
+    type Err = DaysOfWeekError;
+
+    fn from_str(days: &str) -> Result<Self, Self::Err> {
+        if days.len() != 7 {
+            return Err(DaysOfWeekError);
+        }
+        days.bytes()
+            .enumerate()
+            .try_fold(DaysOfWeek(0), |days, (day, digit)| match digit {
+                b'0' => Ok(days),
+                b'1' => Ok(DaysOfWeek(days.0 | 1 << day)),
+                _ => Err(DaysOfWeekError),
+            })
+    }
+}
+
+impl ::serde::Serialize for DaysOfWeek {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> ::serde::Deserialize<'de> for DaysOfWeek {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        let days = String::deserialize(deserializer)?;
+        DaysOfWeek::from_str(&days).map_err(Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Derivative)]
 #[derivative(PartialEq)]
 pub struct Transfer {
     pub from_stop_id: String,
@@ -1509,6 +1659,38 @@ pub struct Transfer {
     pub real_min_transfer_time: Option<u32>,
     #[derivative(PartialEq = "ignore")]
     pub equipment_id: Option<String>,
+    #[serde(default, deserialize_with = "de_with_invalid_option")]
+    #[derivative(PartialEq = "ignore")]
+    pub transfer_mode: Option<TransferMode>,
+    /// Time of the day from which the transfer is possible, in the
+    /// `transfer_start_time` extension column
+    #[serde(default, rename = "transfer_start_time")]
+    pub start_time: Option<Time>,
+    /// Time of the day until which the transfer is possible (excluded), in
+    /// the `transfer_end_time` extension column
+    #[serde(default, rename = "transfer_end_time")]
+    pub end_time: Option<Time>,
+    /// Days of the week on which the transfer is possible, in the
+    /// `transfer_days` extension column
+    #[serde(default, rename = "transfer_days")]
+    pub days: Option<DaysOfWeek>,
+}
+
+impl Transfer {
+    /// `true` if the transfer is only possible on some days or at some times
+    /// of the day (e.g. a connection only staffed during the day)
+    pub fn is_time_dependent(&self) -> bool {
+        self.start_time.is_some() || self.end_time.is_some() || self.days.is_some()
+    }
+
+    /// `true` if the transfer is possible on `weekday` at `time`
+    pub fn is_valid_at(&self, weekday: Weekday, time: Time) -> bool {
+        self.days.map_or(true, |days| days.contains(weekday))
+            && self
+                .start_time
+                .map_or(true, |start_time| start_time <= time)
+            && self.end_time.map_or(true, |end_time| time < end_time)
+    }
 }
 
 impl AddPrefix for Transfer {
@@ -1522,14 +1704,55 @@ impl AddPrefix for Transfer {
     }
 }
 
+// The transfers between the same stops are the same transfer, unless they are
+// possible at different times
 impl Hash for Transfer {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        (&self.from_stop_id, &self.to_stop_id).hash(state);
+        (
+            &self.from_stop_id,
+            &self.to_stop_id,
+            self.start_time,
+            self.end_time,
+            self.days,
+        )
+            .hash(state);
     }
 }
 
 impl Eq for Transfer {}
 
+/// A guaranteed connection between two trips: the trip `to_vehicle_journey_id`
+/// waits for the passengers of `from_vehicle_journey_id`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Interchange {
+    #[serde(rename = "from_trip_id")]
+    pub from_vehicle_journey_id: String,
+    #[serde(rename = "from_stop_id")]
+    pub from_stop_point_id: String,
+    #[serde(rename = "to_trip_id")]
+    pub to_vehicle_journey_id: String,
+    #[serde(rename = "to_stop_id")]
+    pub to_stop_point_id: String,
+    pub min_transfer_time: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "de_from_u8",
+        serialize_with = "ser_from_bool"
+    )]
+    pub guaranteed: bool,
+}
+
+impl AddPrefix for Interchange {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.from_vehicle_journey_id =
+            prefix_conf.schedule_prefix(self.from_vehicle_journey_id.as_str());
+        self.from_stop_point_id = prefix_conf.referential_prefix(self.from_stop_point_id.as_str());
+        self.to_vehicle_journey_id =
+            prefix_conf.schedule_prefix(self.to_vehicle_journey_id.as_str());
+        self.to_stop_point_id = prefix_conf.referential_prefix(self.to_stop_point_id.as_str());
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Derivative, Eq, PartialEq, Clone)]
 #[derivative(Default)]
 pub enum TransportType {
@@ -1585,7 +1808,7 @@ impl TripProperty {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Geometry {
     #[serde(rename = "geometry_id")]
     pub id: String,
@@ -1694,7 +1917,7 @@ impl AddPrefix for FareV1 {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Ticket {
     #[serde(rename = "ticket_id")]
     pub id: String,
@@ -1746,7 +1969,7 @@ impl AddPrefix for TicketPrice {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct TicketUse {
     #[serde(rename = "ticket_use_id")]
     pub id: String,
@@ -1811,7 +2034,7 @@ impl AddPrefix for TicketUseRestriction {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GridCalendar {
     #[serde(rename = "grid_calendar_id")]
     pub id: String,
@@ -1909,7 +2132,7 @@ pub struct Address {
 
 impl_id!(Address);
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct AdministrativeRegion {
     #[serde(rename = "admin_id")]
     pub id: String,
@@ -1937,6 +2160,47 @@ impl AddPrefix for Address {
     }
 }
 
+/// A type of point of interest (e.g. bike-sharing station, car park)
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoiType {
+    #[serde(rename = "poi_type_id")]
+    pub id: String,
+    #[serde(rename = "poi_type_name")]
+    pub name: String,
+}
+
+impl_id!(PoiType);
+
+impl AddPrefix for PoiType {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.id = prefix_conf.referential_prefix(self.id.as_str());
+    }
+}
+
+/// A point of interest attached to the referential
+#[derive(Serialize, Deserialize, Debug, Clone, Derivative, PartialEq)]
+#[derivative(Default)]
+pub struct Poi {
+    pub id: String,
+    pub name: String,
+    pub coord: Coord,
+    pub poi_type_id: String,
+    pub weight: u32,
+    #[derivative(Default(value = "true"))]
+    pub visible: bool,
+    pub properties: PropertiesMap,
+}
+
+impl_id!(Poi);
+impl_id!(Poi, PoiType, poi_type_id);
+
+impl AddPrefix for Poi {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.id = prefix_conf.referential_prefix(self.id.as_str());
+        self.poi_type_id = prefix_conf.referential_prefix(self.poi_type_id.as_str());
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum OccupancyStatus {
@@ -2016,12 +2280,284 @@ impl AddPrefix for Occupancy {
     }
 }
 
+/// Expected occupancy of a vehicle journey at one of its stops
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct StopTimeOccupancy {
+    #[serde(rename = "trip_id")]
+    pub vehicle_journey_id: String,
+    pub stop_sequence: u32,
+    #[serde(rename = "occupancy_status")]
+    pub occupancy: OccupancyStatus,
+}
+
+impl AddPrefix for StopTimeOccupancy {
+    fn prefix(&mut self, prefix_conf: &PrefixConfiguration) {
+        self.vehicle_journey_id = prefix_conf.schedule_prefix(self.vehicle_journey_id.as_str());
+    }
+}
+
+/// Key/value information about a feed (the `feed_infos.txt` file of a NTFS),
+/// with getters and setters for the well-known keys; the other keys are
+/// accessible as in a map.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct FeedInfos(std::collections::BTreeMap<String, String>);
+
+impl FeedInfos {
+    /// Key of the version of the NTFS specification
+    pub const NTFS_VERSION: &'static str = "ntfs_version";
+    /// Key of the date of creation of the feed (`YYYYMMDD`)
+    pub const CREATION_DATE: &'static str = "feed_creation_date";
+    /// Key of the time of creation of the feed (`HH:MM:SS`)
+    pub const CREATION_TIME: &'static str = "feed_creation_time";
+    /// Key of the date and time of creation of the feed (RFC 3339)
+    pub const CREATION_DATETIME: &'static str = "feed_creation_datetime";
+    /// Key of the first day of service of the feed (`YYYYMMDD`)
+    pub const START_DATE: &'static str = "feed_start_date";
+    /// Key of the last day of service of the feed (`YYYYMMDD`)
+    pub const END_DATE: &'static str = "feed_end_date";
+    /// Key of the name of the publisher of the feed
+    pub const PUBLISHER_NAME: &'static str = "feed_publisher_name";
+    /// Key of the url of the publisher of the feed
+    pub const PUBLISHER_URL: &'static str = "feed_publisher_url";
+    /// Key of the license of the feed
+    pub const LICENSE: &'static str = "feed_license";
+    /// Key of the url of the license of the feed
+    pub const LICENSE_URL: &'static str = "feed_license_url";
+    /// Key of the geographic bounding box of the stops of the feed
+    /// (`min_lon,min_lat,max_lon,max_lat`)
+    pub const BOUNDING_BOX: &'static str = "feed_bounding_box";
+    /// Key of the convex hull of the stops of the feed (WKT polygon)
+    pub const CONVEX_HULL: &'static str = "feed_convex_hull";
+
+    fn date(&self, key: &str) -> Option<Date> {
+        self.0
+            .get(key)
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+    }
+
+    /// Version of the NTFS specification
+    pub fn ntfs_version(&self) -> Option<&str> {
+        self.0.get(Self::NTFS_VERSION).map(String::as_str)
+    }
+
+    /// Date of creation of the feed
+    pub fn creation_date(&self) -> Option<Date> {
+        self.date(Self::CREATION_DATE)
+    }
+
+    /// Date and time of creation of the feed
+    pub fn creation_datetime(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        self.0
+            .get(Self::CREATION_DATETIME)
+            .and_then(|datetime| chrono::DateTime::parse_from_rfc3339(datetime).ok())
+    }
+
+    /// First day of service of the feed
+    pub fn start_date(&self) -> Option<Date> {
+        self.date(Self::START_DATE)
+    }
+
+    /// Last day of service of the feed
+    pub fn end_date(&self) -> Option<Date> {
+        self.date(Self::END_DATE)
+    }
+
+    /// Name of the publisher of the feed
+    pub fn publisher_name(&self) -> Option<&str> {
+        self.0.get(Self::PUBLISHER_NAME).map(String::as_str)
+    }
+
+    /// Url of the publisher of the feed
+    pub fn publisher_url(&self) -> Option<&str> {
+        self.0.get(Self::PUBLISHER_URL).map(String::as_str)
+    }
+
+    /// License of the feed
+    pub fn license(&self) -> Option<&str> {
+        self.0.get(Self::LICENSE).map(String::as_str)
+    }
+
+    /// Url of the license of the feed
+    pub fn license_url(&self) -> Option<&str> {
+        self.0.get(Self::LICENSE_URL).map(String::as_str)
+    }
+
+    /// Geographic bounding box of the stops of the feed
+    pub fn bounding_box(&self) -> Option<Rect<f64>> {
+        let bounds: Vec<f64> = self
+            .0
+            .get(Self::BOUNDING_BOX)?
+            .split(',')
+            .map(|bound| bound.trim().parse())
+            .collect::<std::result::Result<_, _>>()
+            .ok()?;
+        match bounds[..] {
+            [min_lon, min_lat, max_lon, max_lat] => {
+                Some(Rect::new((min_lon, min_lat), (max_lon, max_lat)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Convex hull of the stops of the feed
+    pub fn convex_hull(&self) -> Option<GeoGeometry<f64>> {
+        use std::convert::TryInto;
+        let wkt = wkt::Wkt::<f64>::from_str(self.0.get(Self::CONVEX_HULL)?).ok()?;
+        wkt.try_into().ok()
+    }
+
+    /// Set the version of the NTFS specification
+    pub fn set_ntfs_version(&mut self, ntfs_version: &str) {
+        self.0
+            .insert(Self::NTFS_VERSION.to_string(), ntfs_version.to_string());
+    }
+
+    /// Set the date, the time and the date and time of creation of the feed
+    pub fn set_creation_datetime(&mut self, datetime: chrono::DateTime<chrono::FixedOffset>) {
+        self.0.insert(
+            Self::CREATION_DATE.to_string(),
+            datetime.format("%Y%m%d").to_string(),
+        );
+        self.0.insert(
+            Self::CREATION_TIME.to_string(),
+            datetime.format("%T").to_string(),
+        );
+        self.0
+            .insert(Self::CREATION_DATETIME.to_string(), datetime.to_rfc3339());
+    }
+
+    /// Set the first and the last days of service of the feed
+    pub fn set_validity_period(&mut self, start_date: Date, end_date: Date) {
+        self.0.insert(
+            Self::START_DATE.to_string(),
+            start_date.format("%Y%m%d").to_string(),
+        );
+        self.0.insert(
+            Self::END_DATE.to_string(),
+            end_date.format("%Y%m%d").to_string(),
+        );
+    }
+
+    /// Set the name of the publisher of the feed
+    pub fn set_publisher_name(&mut self, publisher_name: &str) {
+        self.0
+            .insert(Self::PUBLISHER_NAME.to_string(), publisher_name.to_string());
+    }
+
+    /// Set the url of the publisher of the feed
+    pub fn set_publisher_url(&mut self, publisher_url: &str) {
+        self.0
+            .insert(Self::PUBLISHER_URL.to_string(), publisher_url.to_string());
+    }
+
+    /// Set the license of the feed
+    pub fn set_license(&mut self, license: &str) {
+        self.0
+            .insert(Self::LICENSE.to_string(), license.to_string());
+    }
+
+    /// Set the url of the license of the feed
+    pub fn set_license_url(&mut self, license_url: &str) {
+        self.0
+            .insert(Self::LICENSE_URL.to_string(), license_url.to_string());
+    }
+
+    /// Set the geographic bounding box of the stops of the feed
+    pub fn set_bounding_box(&mut self, bounding_box: &Rect<f64>) {
+        let (min, max) = (bounding_box.min(), bounding_box.max());
+        self.0.insert(
+            Self::BOUNDING_BOX.to_string(),
+            format!("{},{},{},{}", min.x, min.y, max.x, max.y),
+        );
+    }
+
+    /// Set the convex hull of the stops of the feed
+    pub fn set_convex_hull(&mut self, convex_hull: &GeoGeometry<f64>) {
+        use wkt::ToWkt;
+        self.0.insert(
+            Self::CONVEX_HULL.to_string(),
+            convex_hull.to_wkt().item.to_string(),
+        );
+    }
+}
+
+impl std::ops::Deref for FeedInfos {
+    type Target = std::collections::BTreeMap<String, String>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for FeedInfos {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<std::collections::BTreeMap<String, String>> for FeedInfos {
+    fn from(feed_infos: std::collections::BTreeMap<String, String>) -> Self {
+        FeedInfos(feed_infos)
+    }
+}
+
+impl PartialEq<std::collections::BTreeMap<String, String>> for FeedInfos {
+    fn eq(&self, other: &std::collections::BTreeMap<String, String>) -> bool {
+        &self.0 == other
+    }
+}
+
+impl FromIterator<(String, String)> for FeedInfos {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        FeedInfos(iter.into_iter().collect())
+    }
+}
+
+impl Extend<(String, String)> for FeedInfos {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl IntoIterator for FeedInfos {
+    type Item = (String, String);
+    type IntoIter = std::collections::btree_map::IntoIter<String, String>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use approx::assert_relative_eq;
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn typed_object_properties() {
+        let mut line = Line::default();
+        assert_eq!(None, line.set_property("capacity", 120));
+        line.set_property("night_line", true);
+        line.set_property("operator_ref", "OP-1");
+
+        assert_eq!(Some("120"), line.property("capacity"));
+        assert_eq!(Some(120u32), line.typed_property("capacity").unwrap());
+        assert_eq!(Some(true), line.typed_property("night_line").unwrap());
+        assert_eq!(None, line.typed_property::<u32>("unknown").unwrap());
+        let error = line.typed_property::<u32>("operator_ref").unwrap_err();
+        assert_eq!(
+            "invalid value 'OP-1' for the object property 'operator_ref': \
+             invalid digit found in string",
+            error.to_string()
+        );
+
+        assert_eq!(
+            Some("OP-1".to_string()),
+            line.remove_property("operator_ref")
+        );
+        assert_eq!(None, line.property("operator_ref"));
+    }
+
     #[test]
     fn rgb_serialization() {
         let white = Rgb {
@@ -2128,6 +2664,39 @@ mod tests {
 
     const EPSILON: f64 = 0.001;
 
+    #[test]
+    fn days_of_week_serialization() {
+        let days: DaysOfWeek = "1111100".parse().unwrap();
+        assert!(days.contains(Weekday::Fri));
+        assert!(!days.contains(Weekday::Sat));
+        assert_eq!("1111100", days.to_string());
+        assert_eq!(
+            DaysOfWeek::new(&[Weekday::Sat, Weekday::Sun]),
+            "0000011".parse().unwrap()
+        );
+        assert!("111110".parse::<DaysOfWeek>().is_err());
+        assert!("1111102".parse::<DaysOfWeek>().is_err());
+    }
+
+    #[test]
+    fn transfer_validity() {
+        let transfer = Transfer {
+            from_stop_id: "sp1".to_string(),
+            to_stop_id: "sp2".to_string(),
+            min_transfer_time: None,
+            real_min_transfer_time: None,
+            equipment_id: None,
+            transfer_mode: None,
+            start_time: Some(Time::new(7, 0, 0)),
+            end_time: Some(Time::new(21, 0, 0)),
+            days: Some("1111100".parse().unwrap()),
+        };
+        assert!(transfer.is_time_dependent());
+        assert!(transfer.is_valid_at(Weekday::Mon, Time::new(7, 0, 0)));
+        assert!(!transfer.is_valid_at(Weekday::Mon, Time::new(21, 0, 0)));
+        assert!(!transfer.is_valid_at(Weekday::Sun, Time::new(12, 0, 0)));
+    }
+
     #[test]
     fn orthodromic_distance() {
         assert_relative_eq!(COORD1.distance_to(&COORD1), 0.0);
@@ -2150,6 +2719,46 @@ mod tests {
         );
     }
 
+    mod calendar_operations {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn dates(dates: &[&str]) -> BTreeSet<Date> {
+            dates.iter().map(|date| date.parse().unwrap()).collect()
+        }
+
+        fn calendar() -> Calendar {
+            Calendar {
+                id: "service".to_string(),
+                dates: dates(&["2020-01-01", "2020-01-02", "2020-01-03"]),
+            }
+        }
+
+        #[test]
+        fn union() {
+            let mut calendar = calendar();
+            calendar.union(&dates(&["2020-01-03", "2020-01-04"]));
+            assert_eq!(
+                dates(&["2020-01-01", "2020-01-02", "2020-01-03", "2020-01-04"]),
+                calendar.dates
+            );
+        }
+
+        #[test]
+        fn intersection() {
+            let mut calendar = calendar();
+            calendar.intersection(&dates(&["2020-01-03", "2020-01-04"]));
+            assert_eq!(dates(&["2020-01-03"]), calendar.dates);
+        }
+
+        #[test]
+        fn subtraction() {
+            let mut calendar = calendar();
+            calendar.subtraction(&dates(&["2020-01-02", "2020-01-04"]));
+            assert_eq!(dates(&["2020-01-01", "2020-01-03"]), calendar.dates);
+        }
+    }
+
     mod sort_and_check_stop_times {
         use super::*;
 
@@ -16,7 +16,8 @@
 use crate::{file_handler::FileHandler, Result};
 use anyhow::{anyhow, bail, Context};
 use skip_error::SkipError;
-use tracing::info;
+use std::{io::Read, path::PathBuf};
+use tracing::{field, info, info_span};
 use typed_index_collection::{CollectionWithId, Id};
 
 /// Read a vector of objects from a zip in a file_handler
@@ -42,15 +43,19 @@ where
             bail!("file {:?} not found", path)
         }
         (Some(reader), _) => {
+            let span = info_span!("read_file", file = %basename, objects = field::Empty);
+            let _enter = span.enter();
             info!("Reading {}", basename);
             let mut rdr = csv::ReaderBuilder::new()
                 .flexible(true)
                 .trim(csv::Trim::All)
                 .from_reader(reader);
-            Ok(rdr
+            let objects: Vec<O> = rdr
                 .deserialize()
                 .collect::<Result<_, _>>()
-                .with_context(|| format!("Error reading {:?}", path))?)
+                .with_context(|| format!("Error reading {:?}", path))?;
+            span.record("objects", objects.len());
+            Ok(objects)
         }
     }
 }
@@ -78,20 +83,53 @@ where
             bail!("file {:?} not found", path)
         }
         (Some(reader), _) => {
+            let span = info_span!("read_file", file = %basename, objects = field::Empty);
+            let _enter = span.enter();
             info!("Reading {}", basename);
             let mut rdr = csv::ReaderBuilder::new()
                 .flexible(true)
                 .trim(csv::Trim::All)
                 .from_reader(reader);
-            let objects = rdr
+            let objects: Vec<O> = rdr
                 .deserialize()
                 .map(|object| object.with_context(|| format!("Error reading {:?}", path)))
                 .skip_error_and_warn()
                 .collect();
+            span.record("objects", objects.len());
             Ok(objects)
         }
     }
 }
+
+/// Iterate lazily over the objects of the CSV `reader` of the file `path`,
+/// without loading the whole file in memory
+pub fn iter_csv_objects<R, O>(reader: R, path: PathBuf) -> impl Iterator<Item = Result<O>>
+where
+    R: Read,
+    O: serde::de::DeserializeOwned,
+{
+    csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+        .into_deserialize()
+        .map(move |object| object.with_context(|| format!("Error reading {:?}", path)))
+}
+
+/// Iterate lazily over the objects of a required file in a file_handler
+pub fn iter_objects<H, O>(
+    file_handler: H,
+    file_name: &str,
+) -> Result<impl Iterator<Item = Result<O>>>
+where
+    H: FileHandler,
+    O: serde::de::DeserializeOwned,
+{
+    let (reader, path) = file_handler.get_file(file_name)?;
+    info!("Reading {}", file_name);
+    Ok(iter_csv_objects(reader, path))
+}
+
 /// Read a CollectionId from a zip in a file_handler
 pub fn read_collection<H, O>(file_handler: &mut H, file_name: &str) -> Result<CollectionWithId<O>>
 where
@@ -0,0 +1,1472 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! A full conversion (reading, transformations and writing) described by a
+//! single JSON file, to make the complex conversions reproducible.
+//!
+//! ```json
+//! {
+//!     "input": { "format": "gtfs", "path": "gtfs.zip", "prefix": "ABC" },
+//!     "transformations": [
+//!         { "type": "name_normalization", "rules": "names.json" },
+//!         { "type": "generate_transfers" }
+//!     ],
+//!     "output": { "format": "ntfs", "path": "ntfs" }
+//! }
+//! ```
+//!
+//! The transformations are applied in their order in the file, unless they
+//! have a `priority` (`0` by default): they are then applied by increasing
+//! priority, e.g. to run the rules of a shared file after the renames of each
+//! configuration.
+//!
+//! With `check_idempotency`, each transformation is applied a second time on
+//! a copy of its result, and the conversion fails if this changes the data,
+//! e.g. to check that a pipeline can safely be run again on its own output.
+
+use crate::{
+    calendar_check::{self, PlausibleYears},
+    codes::CodeSystem,
+    company_rules, configuration, coord_check, disruptions, entrance_pathways, gtfs,
+    line_aggregation, line_branding, mode_rules,
+    model::{Collections, Model},
+    name_normalization, ntfs,
+    objects::{Date, TransferMode},
+    renumbering,
+    report::{Report, ReportEntry, Severity},
+    serde_utils::de_from_date_string,
+    snapshot, stop_area_merge,
+    stop_relocation::{self, StopRelocationStrategy},
+    street_network::StreetNetwork,
+    transfers::{self, generates_transfers, generates_transfers_with_street_network},
+    vehicle_journey_check::{self, ShortVehicleJourneyStrategy},
+    AddPrefix, PrefixConfiguration, Result,
+};
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    path::{Path, PathBuf},
+};
+use tracing::{field, info, info_span, warn};
+
+// Maximum number of identifiers of objects logged for an issue of the report
+const MAX_LOGGED_SAMPLES: usize = 5;
+
+/// Format of a dataset
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// [GTFS](https://gtfs.org/reference/static)
+    Gtfs,
+    /// [NTFS](https://github.com/hove-io/ntfs-specification/blob/master/ntfs_fr.md)
+    Ntfs,
+}
+
+/// The dataset read by the pipeline
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Input {
+    /// Format of the dataset
+    pub format: Format,
+    /// Directory or ZIP archive of the dataset
+    pub path: PathBuf,
+    /// JSON file of the contributor, the dataset and the feed infos of a GTFS
+    /// (see `configuration::read_config`)
+    #[serde(default)]
+    pub config: Option<PathBuf>,
+    /// Prefix added to all the identifiers
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+/// The dataset written by the pipeline
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    /// Format of the dataset
+    pub format: Format,
+    /// Directory, or ZIP archive if the path ends with `.zip`, of the dataset
+    pub path: PathBuf,
+    /// Date and time of the conversion (RFC 3339), the current one if empty
+    #[serde(default)]
+    pub current_datetime: Option<String>,
+    /// Write the extended route types of a GTFS
+    #[serde(default)]
+    pub extend_route_type: bool,
+}
+
+fn default_max_distance() -> f64 {
+    crate::TRANSFER_MAX_DISTANCE.parse().unwrap()
+}
+
+fn default_walking_speed() -> f64 {
+    crate::TRANSFER_WALKING_SPEED.parse().unwrap()
+}
+
+fn default_waiting_time() -> u32 {
+    crate::TRANSFER_WAITING_TIME.parse().unwrap()
+}
+
+fn default_merge_code_systems() -> Vec<String> {
+    vec![
+        CodeSystem::Uic.name().to_string(),
+        CodeSystem::Ifopt.name().to_string(),
+    ]
+}
+
+/// A transformation of the dataset, the `type` of the JSON object being the
+/// name of the variant in snake case
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Transformation {
+    /// Restrict the validity period (dates written `YYYYMMDD`)
+    RestrictValidityPeriod {
+        /// First day of the period
+        #[serde(deserialize_with = "de_from_date_string")]
+        start_date: Date,
+        /// Last day of the period
+        #[serde(deserialize_with = "de_from_date_string")]
+        end_date: Date,
+    },
+    /// Normalize the names following the rules of a JSON file (see
+    /// `name_normalization::read_rules`)
+    NameNormalization {
+        /// JSON file of the rules
+        rules: PathBuf,
+    },
+    /// Fill the branding of the lines from a CSV file (see
+    /// `line_branding::read_brandings`)
+    LineBranding {
+        /// CSV file of the brandings
+        brandings: PathBuf,
+    },
+    /// Aggregate lines following the rules of a JSON file (see
+    /// `line_aggregation::read_rules`)
+    LineAggregation {
+        /// JSON file of the rules
+        rules: PathBuf,
+    },
+    /// Update and merge the companies following the rules of a JSON file
+    /// (see `company_rules::read_rules`)
+    CompanyRules {
+        /// JSON file of the rules
+        rules: PathBuf,
+    },
+    /// Rename and merge the physical and commercial modes following the
+    /// rules of a JSON file (see `mode_rules::read_rules`)
+    ModeRules {
+        /// JSON file of the rules
+        rules: PathBuf,
+    },
+    /// Generate the pathways of the entrances without any pathway
+    EntrancePathways {
+        /// Walking speed in m/s
+        #[serde(default = "default_walking_speed")]
+        walking_speed: f64,
+    },
+    /// Generate the transfers between the close stop points
+    GenerateTransfers {
+        /// Maximum distance in meters of a transfer
+        #[serde(default = "default_max_distance")]
+        max_distance: f64,
+        /// Walking speed in m/s
+        #[serde(default = "default_walking_speed")]
+        walking_speed: f64,
+        /// Waiting time at stop in seconds
+        #[serde(default = "default_waiting_time")]
+        waiting_time: u32,
+        /// OSM XML file of the streets along which the walking distances are
+        /// computed, instead of as the crow flies
+        #[serde(default)]
+        street_network: Option<PathBuf>,
+    },
+    /// Merge the stop areas sharing a code, generating the transfers between
+    /// their stop points (see `stop_area_merge::merge_stop_areas_by_code`)
+    MergeStopAreasByCode {
+        /// Code systems identifying a station (`UIC` and `IFOPT` by default)
+        #[serde(default = "default_merge_code_systems")]
+        systems: Vec<String>,
+        /// Walking speed in m/s
+        #[serde(default = "default_walking_speed")]
+        walking_speed: f64,
+        /// Waiting time at stop in seconds
+        #[serde(default = "default_waiting_time")]
+        waiting_time: u32,
+    },
+    /// Keep only the transfers made with one of the modes, a transfer without
+    /// mode being walked (see `transfers::retain_transfer_modes`)
+    RetainTransferModes {
+        /// Modes of the transfers kept
+        modes: Vec<TransferMode>,
+    },
+    /// Trim the service dates to the validity period of the datasets and to
+    /// the plausible years (see `calendar_check::trim_calendar_dates`)
+    TrimCalendarDates {
+        /// Plausible years of the service dates
+        #[serde(default)]
+        plausible_years: PlausibleYears,
+    },
+    /// Repair the wrong coordinates of the stops (see
+    /// `coord_check::repair_coords`)
+    RepairCoords,
+    /// Report, and remove by default, the vehicle journeys with less than 2
+    /// stop times (see `vehicle_journey_check::check_short_vehicle_journeys`)
+    CheckShortVehicleJourneys {
+        /// What to do with the vehicle journeys
+        #[serde(default)]
+        strategy: ShortVehicleJourneyStrategy,
+    },
+    /// Move the stop times to temporary stop points during the periods of a
+    /// CSV file of relocations (see `stop_relocation::relocate_stop_points`)
+    StopRelocations {
+        /// CSV file of the relocations
+        relocations: PathBuf,
+        /// How the relocations are applied on the vehicle journeys
+        #[serde(default)]
+        strategy: StopRelocationStrategy,
+    },
+    /// Close lines and stops during the periods of a CSV file of planned
+    /// disruptions (see `disruptions::apply_disruptions`)
+    PlannedDisruptions {
+        /// CSV file of the disruptions
+        disruptions: PathBuf,
+    },
+    /// Replace the identifiers of the lines, the routes and the vehicle
+    /// journeys by short numeric identifiers (see `renumbering::renumber_ids`)
+    RenumberIds,
+    /// Assign a timezone to the stops and the networks without one, from the
+    /// coordinates of the stops (see `timezones::assign_timezones`)
+    #[cfg(feature = "timezone_lookup")]
+    AssignTimezones,
+    /// Remove the unused objects
+    Sanitize,
+}
+
+// A transformation with the priority of its application
+#[derive(Deserialize)]
+struct PrioritizedTransformation {
+    #[serde(default)]
+    priority: i32,
+    #[serde(flatten)]
+    transformation: Transformation,
+}
+
+// The transformations sorted by increasing priority, the transformations with
+// the same priority keeping their order
+fn de_prioritized_transformations<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<Transformation>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut transformations = Vec::<PrioritizedTransformation>::deserialize(deserializer)?;
+    transformations.sort_by_key(|transformation| transformation.priority);
+    Ok(transformations
+        .into_iter()
+        .map(|prioritized| prioritized.transformation)
+        .collect())
+}
+
+/// A full conversion: the `transformations` are applied in order on the
+/// `input` dataset, then the result is written into the `output` dataset.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    /// The dataset read
+    pub input: Input,
+    /// The transformations applied in order, sorted by their `priority` in
+    /// the JSON file
+    #[serde(default, deserialize_with = "de_prioritized_transformations")]
+    pub transformations: Vec<Transformation>,
+    /// The dataset written
+    pub output: Output,
+    /// Test mode checking that each transformation is idempotent, the
+    /// conversion failing otherwise (see `Pipeline::run_checking_idempotency`)
+    #[serde(default)]
+    pub check_idempotency: bool,
+}
+
+impl PipelineConfig {
+    /// Check the consistency of the configuration and the existence of the
+    /// files it references
+    pub fn validate(&self) -> Result<()> {
+        if !self.input.path.exists() {
+            bail!("input {:?} doesn't exist", self.input.path);
+        }
+        if self.input.config.is_some() && self.input.format != Format::Gtfs {
+            bail!("'config' of the input is only supported for a GTFS");
+        }
+        if let Some(config) = &self.input.config {
+            check_file(config)?;
+        }
+        if let Some(current_datetime) = &self.output.current_datetime {
+            DateTime::parse_from_rfc3339(current_datetime)
+                .with_context(|| format!("invalid current_datetime {:?}", current_datetime))?;
+        }
+        if self.output.extend_route_type && self.output.format != Format::Gtfs {
+            bail!("'extend_route_type' of the output is only supported for a GTFS");
+        }
+        for transformation in &self.transformations {
+            match transformation {
+                Transformation::RestrictValidityPeriod {
+                    start_date,
+                    end_date,
+                } if start_date > end_date => {
+                    bail!(
+                        "the start date {} of the validity period is after its end date {}",
+                        start_date,
+                        end_date
+                    );
+                }
+                Transformation::NameNormalization { rules }
+                | Transformation::LineAggregation { rules }
+                | Transformation::CompanyRules { rules }
+                | Transformation::ModeRules { rules } => check_file(rules)?,
+                Transformation::LineBranding { brandings } => check_file(brandings)?,
+                Transformation::StopRelocations { relocations, .. } => check_file(relocations)?,
+                Transformation::PlannedDisruptions { disruptions } => check_file(disruptions)?,
+                Transformation::MergeStopAreasByCode { systems, .. } => {
+                    code_systems(systems)?;
+                }
+                Transformation::GenerateTransfers {
+                    street_network: Some(street_network),
+                    ..
+                } => check_file(street_network)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn code_systems(names: &[String]) -> Result<Vec<CodeSystem>> {
+    names
+        .iter()
+        .map(|name| {
+            CodeSystem::from_name(name).ok_or_else(|| anyhow!("unknown code system {:?}", name))
+        })
+        .collect()
+}
+
+fn check_file(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        bail!("file {:?} doesn't exist", path);
+    }
+    Ok(())
+}
+
+/// Read and validate the `PipelineConfig` of the JSON file `path`
+pub fn read_pipeline_config<P: AsRef<Path>>(path: P) -> Result<PipelineConfig> {
+    let path = path.as_ref();
+    info!("Reading pipeline configuration from {:?}", path);
+    let file = File::open(path).with_context(|| format!("Error reading {:?}", path))?;
+    let config: PipelineConfig =
+        serde_json::from_reader(file).with_context(|| format!("Error parsing {:?}", path))?;
+    config
+        .validate()
+        .with_context(|| format!("Invalid pipeline configuration {:?}", path))?;
+    Ok(config)
+}
+
+fn read_input(input: Input) -> Result<Collections> {
+    let mut prefix_conf = PrefixConfiguration::default();
+    if let Some(prefix) = &input.prefix {
+        prefix_conf.set_data_prefix(prefix);
+    }
+    match input.format {
+        Format::Gtfs => {
+            let (contributor, dataset, feed_infos) = configuration::read_config(input.config)?;
+            let configuration = gtfs::Configuration {
+                contributor,
+                dataset,
+                feed_infos,
+                prefix_conf: Some(prefix_conf),
+                ..Default::default()
+            };
+            gtfs::Reader::new(configuration).parse_collections(input.path)
+        }
+        Format::Ntfs => {
+            let mut collections = ntfs::read_collections(input.path)?;
+            if input.prefix.is_some() {
+                collections.prefix(&prefix_conf);
+            }
+            Ok(collections)
+        }
+    }
+}
+
+/// Category of the entries of the report of a `Pipeline`: the name of the
+/// transformer followed by the category of the entry in the report of the
+/// transformer (e.g. `name_normalization:stop_point_renamed`)
+pub type PipelineReportCategory = String;
+
+/// A step of a `Pipeline` transforming the collections in place.
+///
+/// Downstream crates implement this trait to inject their own steps in the
+/// standard pipeline (see `run_pipeline_with`).
+pub trait ModelTransformer {
+    /// Name of the transformer in the logs and the report
+    fn name(&self) -> &str;
+
+    /// Transform `collections`, adding the decisions taken and the issues
+    /// found to `report`
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()>;
+}
+
+/// Move the entries of the `report` of the transformer `name` at the end of
+/// the report of a pipeline
+pub fn append_report<R: Serialize>(
+    name: &str,
+    report: Report<R>,
+    pipeline_report: &mut Report<PipelineReportCategory>,
+) {
+    let category = |category: &R| match serde_json::to_value(category) {
+        Ok(serde_json::Value::String(category)) => format!("{}:{}", name, category),
+        _ => name.to_string(),
+    };
+    for severity in [Severity::Error, Severity::Warning, Severity::Info] {
+        for entry in report.entries(severity) {
+            pipeline_report.add_entry(
+                severity,
+                ReportEntry {
+                    category: category(&entry.category),
+                    message: entry.message.clone(),
+                    object_id: entry.object_id.clone(),
+                },
+            );
+        }
+    }
+}
+
+/// Restrict the validity period of the collections
+#[derive(Debug, Clone)]
+pub struct RestrictValidityPeriod {
+    /// First day of the period
+    pub start_date: Date,
+    /// Last day of the period
+    pub end_date: Date,
+}
+
+impl ModelTransformer for RestrictValidityPeriod {
+    fn name(&self) -> &str {
+        "restrict_validity_period"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        _: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        collections.restrict_period(self.start_date, self.end_date)
+    }
+}
+
+/// Normalize the names of the stops and the lines
+#[derive(Debug, Clone)]
+pub struct NameNormalization(pub name_normalization::NormalizationRules);
+
+impl ModelTransformer for NameNormalization {
+    fn name(&self) -> &str {
+        "name_normalization"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let normalization_report = name_normalization::normalize_names(collections, &self.0);
+        append_report(self.name(), normalization_report, report);
+        Ok(())
+    }
+}
+
+/// Fill the branding of the lines
+#[derive(Debug, Clone)]
+pub struct LineBranding(pub Vec<line_branding::LineBranding>);
+
+impl ModelTransformer for LineBranding {
+    fn name(&self) -> &str {
+        "line_branding"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let branding_report = line_branding::apply_brandings(collections, &self.0);
+        append_report(self.name(), branding_report, report);
+        Ok(())
+    }
+}
+
+/// Aggregate lines
+#[derive(Debug, Clone)]
+pub struct LineAggregation(pub Vec<line_aggregation::LineAggregationRule>);
+
+impl ModelTransformer for LineAggregation {
+    fn name(&self) -> &str {
+        "line_aggregation"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let aggregation_report = line_aggregation::aggregate_lines(collections, &self.0);
+        append_report(self.name(), aggregation_report, report);
+        Ok(())
+    }
+}
+
+/// Update and merge the companies
+#[derive(Debug, Clone)]
+pub struct CompanyRules(pub Vec<company_rules::CompanyRule>);
+
+impl ModelTransformer for CompanyRules {
+    fn name(&self) -> &str {
+        "company_rules"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let company_report = company_rules::apply_company_rules(collections, &self.0);
+        append_report(self.name(), company_report, report);
+        Ok(())
+    }
+}
+
+/// Rename and merge the physical and commercial modes
+#[derive(Debug, Clone)]
+pub struct ModeRules(pub Vec<mode_rules::ModeRule>);
+
+impl ModelTransformer for ModeRules {
+    fn name(&self) -> &str {
+        "mode_rules"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let mode_report = mode_rules::apply_mode_rules(collections, &self.0);
+        append_report(self.name(), mode_report, report);
+        Ok(())
+    }
+}
+
+/// Generate the pathways of the entrances without any pathway
+#[derive(Debug, Clone)]
+pub struct EntrancePathways {
+    /// Walking speed in m/s
+    pub walking_speed: f64,
+}
+
+impl ModelTransformer for EntrancePathways {
+    fn name(&self) -> &str {
+        "entrance_pathways"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        _: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        entrance_pathways::generate_entrance_pathways(collections, self.walking_speed);
+        Ok(())
+    }
+}
+
+/// Generate the transfers between the close stop points
+#[derive(Debug, Clone)]
+pub struct GenerateTransfers {
+    /// Maximum distance in meters of a transfer
+    pub max_distance: f64,
+    /// Walking speed in m/s
+    pub walking_speed: f64,
+    /// Waiting time at stop in seconds
+    pub waiting_time: u32,
+    /// Streets along which the walking distances are computed, instead of as
+    /// the crow flies
+    pub street_network: Option<StreetNetwork>,
+}
+
+impl ModelTransformer for GenerateTransfers {
+    fn name(&self) -> &str {
+        "generate_transfers"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        _: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let model = Model::new(std::mem::take(collections))?;
+        let model = match &self.street_network {
+            Some(street_network) => generates_transfers_with_street_network(
+                model,
+                street_network,
+                self.max_distance,
+                self.walking_speed,
+                self.waiting_time,
+                None,
+            )?,
+            None => generates_transfers(
+                model,
+                self.max_distance,
+                self.walking_speed,
+                self.waiting_time,
+                None,
+            )?,
+        };
+        *collections = model.into_collections();
+        Ok(())
+    }
+}
+
+/// Keep only the transfers made with one of the modes
+#[derive(Debug, Clone)]
+pub struct RetainTransferModes(pub Vec<TransferMode>);
+
+impl ModelTransformer for RetainTransferModes {
+    fn name(&self) -> &str {
+        "retain_transfer_modes"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        _: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        transfers::retain_transfer_modes(collections, &self.0);
+        Ok(())
+    }
+}
+
+/// Remove the unused objects of the collections
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sanitize;
+
+impl ModelTransformer for Sanitize {
+    fn name(&self) -> &str {
+        "sanitize"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        _: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        collections.sanitize()
+    }
+}
+
+/// Trim the service dates to the validity period of the datasets
+#[derive(Debug, Clone)]
+pub struct TrimCalendarDates(pub PlausibleYears);
+
+impl ModelTransformer for TrimCalendarDates {
+    fn name(&self) -> &str {
+        "trim_calendar_dates"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let calendar_report = calendar_check::trim_calendar_dates(collections, &self.0);
+        append_report(self.name(), calendar_report, report);
+        Ok(())
+    }
+}
+
+/// Repair the wrong coordinates of the stops
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RepairCoords;
+
+impl ModelTransformer for RepairCoords {
+    fn name(&self) -> &str {
+        "repair_coords"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let coord_report = coord_check::repair_coords(collections);
+        append_report(self.name(), coord_report, report);
+        Ok(())
+    }
+}
+
+/// Report, and remove depending on the strategy, the vehicle journeys with
+/// less than 2 stop times
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckShortVehicleJourneys(pub ShortVehicleJourneyStrategy);
+
+impl ModelTransformer for CheckShortVehicleJourneys {
+    fn name(&self) -> &str {
+        "check_short_vehicle_journeys"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let vehicle_journey_report =
+            vehicle_journey_check::check_short_vehicle_journeys(collections, self.0);
+        append_report(self.name(), vehicle_journey_report, report);
+        Ok(())
+    }
+}
+
+/// Move the stop times to temporary stop points
+#[derive(Debug, Clone)]
+pub struct StopRelocations {
+    /// The relocations applied
+    pub relocations: Vec<stop_relocation::StopRelocation>,
+    /// How the relocations are applied on the vehicle journeys
+    pub strategy: StopRelocationStrategy,
+}
+
+impl ModelTransformer for StopRelocations {
+    fn name(&self) -> &str {
+        "stop_relocations"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let relocation_report =
+            stop_relocation::relocate_stop_points(collections, &self.relocations, self.strategy);
+        append_report(self.name(), relocation_report, report);
+        Ok(())
+    }
+}
+
+/// Merge the stop areas sharing a code
+#[derive(Debug, Clone)]
+pub struct MergeStopAreasByCode {
+    /// Code systems identifying a station
+    pub systems: Vec<CodeSystem>,
+    /// Walking speed in m/s of the transfers generated
+    pub walking_speed: f64,
+    /// Waiting time at stop in seconds of the transfers generated
+    pub waiting_time: u32,
+}
+
+impl ModelTransformer for MergeStopAreasByCode {
+    fn name(&self) -> &str {
+        "merge_stop_areas_by_code"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let merge_report = stop_area_merge::merge_stop_areas_by_code(
+            collections,
+            &self.systems,
+            self.walking_speed,
+            self.waiting_time,
+        );
+        append_report(self.name(), merge_report, report);
+        Ok(())
+    }
+}
+
+/// Close lines and stops during planned disruptions
+#[derive(Debug, Clone)]
+pub struct PlannedDisruptions(pub Vec<disruptions::PlannedDisruption>);
+
+impl ModelTransformer for PlannedDisruptions {
+    fn name(&self) -> &str {
+        "planned_disruptions"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let disruption_report = disruptions::apply_disruptions(collections, &self.0);
+        append_report(self.name(), disruption_report, report);
+        Ok(())
+    }
+}
+
+/// Replace the identifiers of the lines, the routes and the vehicle journeys
+/// by short numeric identifiers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenumberIds;
+
+impl ModelTransformer for RenumberIds {
+    fn name(&self) -> &str {
+        "renumber_ids"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        _: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        renumbering::renumber_ids(collections)
+    }
+}
+
+/// Assign a timezone to the stops and the networks without one
+#[cfg(feature = "timezone_lookup")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AssignTimezones;
+
+#[cfg(feature = "timezone_lookup")]
+impl ModelTransformer for AssignTimezones {
+    fn name(&self) -> &str {
+        "assign_timezones"
+    }
+
+    fn transform(
+        &self,
+        collections: &mut Collections,
+        report: &mut Report<PipelineReportCategory>,
+    ) -> Result<()> {
+        let timezone_report = crate::timezones::assign_timezones(collections);
+        append_report(self.name(), timezone_report, report);
+        Ok(())
+    }
+}
+
+impl Transformation {
+    /// The transformer of the transformation, reading the files it references
+    pub fn into_transformer(self) -> Result<Box<dyn ModelTransformer>> {
+        let transformer: Box<dyn ModelTransformer> = match self {
+            Transformation::RestrictValidityPeriod {
+                start_date,
+                end_date,
+            } => Box::new(RestrictValidityPeriod {
+                start_date,
+                end_date,
+            }),
+            Transformation::NameNormalization { rules } => {
+                Box::new(NameNormalization(name_normalization::read_rules(rules)?))
+            }
+            Transformation::LineBranding { brandings } => {
+                Box::new(LineBranding(line_branding::read_brandings(brandings)?))
+            }
+            Transformation::LineAggregation { rules } => {
+                Box::new(LineAggregation(line_aggregation::read_rules(rules)?))
+            }
+            Transformation::CompanyRules { rules } => {
+                Box::new(CompanyRules(company_rules::read_rules(rules)?))
+            }
+            Transformation::ModeRules { rules } => {
+                Box::new(ModeRules(mode_rules::read_rules(rules)?))
+            }
+            Transformation::EntrancePathways { walking_speed } => {
+                Box::new(EntrancePathways { walking_speed })
+            }
+            Transformation::GenerateTransfers {
+                max_distance,
+                walking_speed,
+                waiting_time,
+                street_network,
+            } => Box::new(GenerateTransfers {
+                max_distance,
+                walking_speed,
+                waiting_time,
+                street_network: street_network
+                    .map(StreetNetwork::from_osm_xml)
+                    .transpose()?,
+            }),
+            Transformation::MergeStopAreasByCode {
+                systems,
+                walking_speed,
+                waiting_time,
+            } => Box::new(MergeStopAreasByCode {
+                systems: code_systems(&systems)?,
+                walking_speed,
+                waiting_time,
+            }),
+            Transformation::RetainTransferModes { modes } => Box::new(RetainTransferModes(modes)),
+            Transformation::TrimCalendarDates { plausible_years } => {
+                Box::new(TrimCalendarDates(plausible_years))
+            }
+            Transformation::RepairCoords => Box::new(RepairCoords),
+            Transformation::CheckShortVehicleJourneys { strategy } => {
+                Box::new(CheckShortVehicleJourneys(strategy))
+            }
+            Transformation::StopRelocations {
+                relocations,
+                strategy,
+            } => Box::new(StopRelocations {
+                relocations: stop_relocation::read_relocations(relocations)?,
+                strategy,
+            }),
+            Transformation::PlannedDisruptions { disruptions } => Box::new(PlannedDisruptions(
+                disruptions::read_disruptions(disruptions)?,
+            )),
+            Transformation::RenumberIds => Box::new(RenumberIds),
+            #[cfg(feature = "timezone_lookup")]
+            Transformation::AssignTimezones => Box::new(AssignTimezones),
+            Transformation::Sanitize => Box::new(Sanitize),
+        };
+        Ok(transformer)
+    }
+}
+
+/// An ordered list of `ModelTransformer`
+#[derive(Default)]
+pub struct Pipeline {
+    transformers: Vec<Box<dyn ModelTransformer>>,
+}
+
+impl Pipeline {
+    /// The pipeline of the `transformations`
+    pub fn from_transformations(transformations: Vec<Transformation>) -> Result<Self> {
+        let transformers = transformations
+            .into_iter()
+            .map(Transformation::into_transformer)
+            .collect::<Result<_>>()?;
+        Ok(Pipeline { transformers })
+    }
+
+    /// Add `transformer` at the end of the pipeline
+    pub fn push<T: ModelTransformer + 'static>(&mut self, transformer: T) {
+        self.transformers.push(Box::new(transformer));
+    }
+
+    /// Insert `transformer` at `position` in the pipeline
+    ///
+    /// Panics if `position > len`.
+    pub fn insert<T: ModelTransformer + 'static>(&mut self, position: usize, transformer: T) {
+        self.transformers.insert(position, Box::new(transformer));
+    }
+
+    /// Number of transformers of the pipeline
+    pub fn len(&self) -> usize {
+        self.transformers.len()
+    }
+
+    /// `true` if the pipeline has no transformer
+    pub fn is_empty(&self) -> bool {
+        self.transformers.is_empty()
+    }
+
+    /// Apply the transformers in order on `collections`, stopping at the
+    /// first failing one
+    pub fn run(&self, collections: &mut Collections) -> Result<Report<PipelineReportCategory>> {
+        self.run_with(collections, false)
+    }
+
+    /// Apply the transformers in order on `collections` like `run`, each of
+    /// them being applied a second time on a copy of its result: a
+    /// transformer changing the collections again (e.g. a rule replacing
+    /// any value by a concatenation) is not idempotent, and is reported as an
+    /// error in the category `<name>:not_idempotent` with the parts of the
+    /// collections changed. The reports of the second applications are
+    /// discarded.
+    pub fn run_checking_idempotency(
+        &self,
+        collections: &mut Collections,
+    ) -> Result<Report<PipelineReportCategory>> {
+        self.run_with(collections, true)
+    }
+
+    fn run_with(
+        &self,
+        collections: &mut Collections,
+        check_idempotency: bool,
+    ) -> Result<Report<PipelineReportCategory>> {
+        let mut report = Report::default();
+        for transformer in &self.transformers {
+            let span = info_span!(
+                "transformation",
+                name = transformer.name(),
+                entries = field::Empty
+            );
+            let _enter = span.enter();
+            info!("Applying transformation {}", transformer.name());
+            let entries = report.errors().len() + report.warnings().len();
+            transformer
+                .transform(collections, &mut report)
+                .with_context(|| format!("Error applying transformation {}", transformer.name()))?;
+            let entries = report.errors().len() + report.warnings().len() - entries;
+            span.record("entries", entries);
+            info!("{}: {} entries reported", transformer.name(), entries);
+            if check_idempotency {
+                check_idempotency_of(transformer.as_ref(), collections, &mut report)?;
+            }
+        }
+        Ok(report)
+    }
+}
+
+// Apply `transformer` a second time on a copy of `collections`, reporting an
+// error if it changes them
+fn check_idempotency_of(
+    transformer: &dyn ModelTransformer,
+    collections: &Collections,
+    report: &mut Report<PipelineReportCategory>,
+) -> Result<()> {
+    let mut second_collections = collections.clone();
+    transformer
+        .transform(&mut second_collections, &mut Report::default())
+        .with_context(|| {
+            format!(
+                "Error applying transformation {} a second time",
+                transformer.name()
+            )
+        })?;
+    let changed_parts = changed_parts(
+        &snapshot::to_json_value(collections)?,
+        &snapshot::to_json_value(&second_collections)?,
+    );
+    if !changed_parts.is_empty() {
+        report.add_error(
+            format!(
+                "transformation {} is not idempotent, applying it a second time changes {}",
+                transformer.name(),
+                changed_parts.join(", ")
+            ),
+            format!("{}:not_idempotent", transformer.name()),
+        );
+    }
+    Ok(())
+}
+
+// Names of the collections (and of the fields stored aside) which differ
+// between two snapshots of the collections
+fn changed_parts(before: &serde_json::Value, after: &serde_json::Value) -> Vec<String> {
+    let mut parts = BTreeSet::new();
+    for section in ["collections", "skipped_fields"] {
+        let (before, after) = (&before[section], &after[section]);
+        match (before.as_object(), after.as_object()) {
+            (Some(before), Some(after)) => {
+                parts.extend(
+                    before
+                        .keys()
+                        .chain(after.keys())
+                        .filter(|key| before.get(*key) != after.get(*key))
+                        .cloned(),
+                );
+            }
+            _ if before != after => {
+                parts.insert(section.to_string());
+            }
+            _ => {}
+        }
+    }
+    parts.into_iter().collect()
+}
+
+fn write_output(model: Model, output: Output) -> Result<()> {
+    let is_zip = output.path.extension().map_or(false, |ext| ext == "zip");
+    match output.format {
+        Format::Ntfs => {
+            let current_datetime = match &output.current_datetime {
+                Some(current_datetime) => DateTime::parse_from_rfc3339(current_datetime)?,
+                None => chrono::Utc::now().with_timezone(&FixedOffset::east_opt(0).unwrap()),
+            };
+            if is_zip {
+                ntfs::write_to_zip(&model, output.path, current_datetime)
+            } else {
+                ntfs::write(&model, output.path, current_datetime)
+            }
+        }
+        Format::Gtfs => {
+            let config = gtfs::WriteConfiguration {
+                extend_route_type: output.extend_route_type,
+                ..Default::default()
+            };
+            if is_zip {
+                gtfs::write_to_zip_with_configuration(model, output.path, config)
+            } else {
+                gtfs::write_with_configuration(model, output.path, config)
+            }
+        }
+    }
+}
+
+/// Run the conversion described by `config`
+pub fn run_pipeline(config: PipelineConfig) -> Result<()> {
+    run_pipeline_with(config, |_| {})
+}
+
+/// Run the conversion described by `config`, the pipeline of its
+/// transformations being first modified by `customize` (e.g. to inject
+/// custom `ModelTransformer`)
+pub fn run_pipeline_with<F>(config: PipelineConfig, customize: F) -> Result<()>
+where
+    F: FnOnce(&mut Pipeline),
+{
+    config.validate()?;
+    let mut pipeline = Pipeline::from_transformations(config.transformations)?;
+    customize(&mut pipeline);
+    let mut collections = read_input(config.input)?;
+    let report = if config.check_idempotency {
+        pipeline.run_checking_idempotency(&mut collections)?
+    } else {
+        pipeline.run(&mut collections)?
+    };
+    info!(
+        "{} error(s) and {} warning(s) reported by the transformations",
+        report.count(Severity::Error),
+        report.count(Severity::Warning)
+    );
+    for entry in report.aggregate(MAX_LOGGED_SAMPLES) {
+        match entry.severity {
+            Severity::Error | Severity::Warning => warn!("{}: {}", entry.category, entry),
+            Severity::Info => info!("{}: {}", entry.category, entry),
+        }
+    }
+    if config.check_idempotency {
+        let not_idempotent: Vec<&str> = report
+            .errors()
+            .iter()
+            .filter_map(|entry| entry.category.strip_suffix(":not_idempotent"))
+            .collect();
+        if !not_idempotent.is_empty() {
+            bail!(
+                "transformation(s) not idempotent: {}",
+                not_idempotent.join(", ")
+            );
+        }
+    }
+    let model = Model::new(collections)?;
+    write_output(model, config.output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::one_vehicle_journey;
+    use pretty_assertions::assert_eq;
+
+    struct RemoveStop(&'static str);
+
+    impl ModelTransformer for RemoveStop {
+        fn name(&self) -> &str {
+            "remove_stop"
+        }
+
+        fn transform(
+            &self,
+            collections: &mut Collections,
+            report: &mut Report<PipelineReportCategory>,
+        ) -> Result<()> {
+            collections
+                .stop_points
+                .retain(|stop_point| stop_point.id != self.0);
+            report.add_warning(format!("stop point {} removed", self.0), self.name().into());
+            Ok(())
+        }
+    }
+
+    struct AppendToStopNames(&'static str);
+
+    impl ModelTransformer for AppendToStopNames {
+        fn name(&self) -> &str {
+            "append_to_stop_names"
+        }
+
+        fn transform(
+            &self,
+            collections: &mut Collections,
+            _report: &mut Report<PipelineReportCategory>,
+        ) -> Result<()> {
+            let stop_point_idxs: Vec<_> =
+                collections.stop_points.iter().map(|(idx, _)| idx).collect();
+            for idx in stop_point_idxs {
+                collections.stop_points.index_mut(idx).name.push_str(self.0);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parse_config() {
+        let config: PipelineConfig = serde_json::from_str(
+            r#"{
+                "input": { "format": "gtfs", "path": "tests/fixtures/gtfs" },
+                "transformations": [
+                    {
+                        "type": "restrict_validity_period",
+                        "start_date": "20180101",
+                        "end_date": "20180201"
+                    },
+                    { "type": "generate_transfers", "max_distance": 100 },
+                    { "type": "trim_calendar_dates", "plausible_years": { "last": 2030 } },
+                    { "type": "repair_coords" },
+                    { "type": "check_short_vehicle_journeys", "strategy": "keep" },
+                    { "type": "retain_transfer_modes", "modes": ["walk", "bike"] }
+                ],
+                "output": { "format": "ntfs", "path": "ntfs.zip" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(Format::Gtfs, config.input.format);
+        assert_eq!(
+            Transformation::GenerateTransfers {
+                max_distance: 100.0,
+                walking_speed: 0.785,
+                waiting_time: 60,
+                street_network: None,
+            },
+            config.transformations[1]
+        );
+        assert_eq!(
+            Transformation::TrimCalendarDates {
+                plausible_years: PlausibleYears {
+                    first: 2000,
+                    last: 2030
+                }
+            },
+            config.transformations[2]
+        );
+        assert_eq!(Transformation::RepairCoords, config.transformations[3]);
+        assert_eq!(
+            Transformation::CheckShortVehicleJourneys {
+                strategy: ShortVehicleJourneyStrategy::Keep
+            },
+            config.transformations[4]
+        );
+        assert_eq!(
+            Transformation::RetainTransferModes {
+                modes: vec![TransferMode::Walk, TransferMode::Bike]
+            },
+            config.transformations[5]
+        );
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn parse_stop_relocations() {
+        let transformation = serde_json::from_str::<Transformation>(
+            r#"{
+                "type": "stop_relocations",
+                "relocations": "relocations.csv",
+                "strategy": { "at_date": "20200110" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Transformation::StopRelocations {
+                relocations: PathBuf::from("relocations.csv"),
+                strategy: StopRelocationStrategy::AtDate(Date::from_ymd_opt(2020, 1, 10).unwrap()),
+            },
+            transformation
+        );
+        let transformation = serde_json::from_str::<Transformation>(
+            r#"{ "type": "stop_relocations", "relocations": "relocations.csv" }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Transformation::StopRelocations {
+                relocations: PathBuf::from("relocations.csv"),
+                strategy: StopRelocationStrategy::SplitTrips,
+            },
+            transformation
+        );
+    }
+
+    #[test]
+    fn parse_merge_stop_areas_by_code() {
+        let transformation =
+            serde_json::from_str::<Transformation>(r#"{ "type": "merge_stop_areas_by_code" }"#)
+                .unwrap();
+        assert_eq!(
+            Transformation::MergeStopAreasByCode {
+                systems: vec!["UIC".to_string(), "IFOPT".to_string()],
+                walking_speed: 0.785,
+                waiting_time: 60,
+            },
+            transformation
+        );
+        assert!(code_systems(&["uic".to_string()]).is_ok());
+        assert!(code_systems(&["unknown".to_string()]).is_err());
+    }
+
+    #[test]
+    fn transformations_sorted_by_priority() {
+        let config = serde_json::from_str::<PipelineConfig>(
+            r#"{
+                "input": { "format": "ntfs", "path": "ntfs" },
+                "transformations": [
+                    { "type": "sanitize", "priority": 10 },
+                    { "type": "repair_coords" },
+                    { "type": "renumber_ids", "priority": -1 },
+                    { "type": "trim_calendar_dates", "priority": 10 },
+                    { "type": "generate_transfers" }
+                ],
+                "output": { "format": "ntfs", "path": "output" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Transformation::RenumberIds,
+                Transformation::RepairCoords,
+                Transformation::GenerateTransfers {
+                    max_distance: 300.0,
+                    walking_speed: 0.785,
+                    waiting_time: 60,
+                    street_network: None,
+                },
+                Transformation::Sanitize,
+                Transformation::TrimCalendarDates {
+                    plausible_years: PlausibleYears::default(),
+                },
+            ],
+            config.transformations
+        );
+
+        let unknown_field = serde_json::from_str::<PipelineConfig>(
+            r#"{
+                "input": { "format": "ntfs", "path": "ntfs" },
+                "transformations": [
+                    { "type": "check_short_vehicle_journeys", "prority": 1 }
+                ],
+                "output": { "format": "ntfs", "path": "output" }
+            }"#,
+        );
+        assert!(unknown_field.is_err());
+    }
+
+    #[test]
+    fn reject_invalid_config() {
+        let unknown_field = serde_json::from_str::<PipelineConfig>(
+            r#"{
+                "input": { "format": "gtfs", "path": "gtfs", "prefx": "ABC" },
+                "output": { "format": "ntfs", "path": "ntfs" }
+            }"#,
+        );
+        assert!(unknown_field.is_err());
+
+        let config: PipelineConfig = serde_json::from_str(
+            r#"{
+                "input": { "format": "ntfs", "path": "tests/fixtures/ntfs" },
+                "transformations": [{ "type": "name_normalization", "rules": "unknown.json" }],
+                "output": { "format": "gtfs", "path": "gtfs" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            "file \"unknown.json\" doesn't exist",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn run_custom_transformers() {
+        let mut collections = one_vehicle_journey().into_collections();
+        collections.stop_points.get_mut("A").unwrap().name = "  A ".to_string();
+        let rules = name_normalization::NormalizationRules::default();
+        let mut pipeline = Pipeline::default();
+        pipeline.push(NameNormalization(rules));
+        pipeline.insert(0, RemoveStop("B"));
+
+        let report = pipeline.run(&mut collections).unwrap();
+
+        assert!(collections.stop_points.get("B").is_none());
+        assert_eq!("A", collections.stop_points.get("A").unwrap().name);
+        assert_eq!(
+            vec!["remove_stop", "name_normalization:stop_point_renamed"],
+            report
+                .warnings()
+                .iter()
+                .map(|warning| warning.category.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn run_checking_idempotency() {
+        let mut collections = one_vehicle_journey().into_collections();
+        let mut pipeline = Pipeline::default();
+        pipeline.push(RemoveStop("B"));
+        pipeline.push(AppendToStopNames(" (station)"));
+
+        let report = pipeline.run_checking_idempotency(&mut collections).unwrap();
+
+        assert_eq!(
+            "A (station)",
+            collections.stop_points.get("A").unwrap().name
+        );
+        assert_eq!(
+            vec![(
+                "append_to_stop_names:not_idempotent",
+                "transformation append_to_stop_names is not idempotent, \
+                 applying it a second time changes stop_points"
+            )],
+            report
+                .errors()
+                .iter()
+                .map(|error| (error.category.as_str(), error.message.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn run_rules_selecting_objects_by_code() {
+        let mut collections = one_vehicle_journey().into_collections();
+        for line_idx in collections.lines.indexes() {
+            collections
+                .lines
+                .index_mut(line_idx)
+                .codes
+                .insert(("SIRI".to_string(), "C01742".to_string()));
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let rules = dir.path().join("lines.json");
+        std::fs::write(
+            &rules,
+            r#"[{
+                "line_object_code": {"object_system": "SIRI", "object_code": "C01742"},
+                "merged_line_ids": [],
+                "name": "Metro 1"
+            }]"#,
+        )
+        .unwrap();
+        let pipeline =
+            Pipeline::from_transformations(vec![Transformation::LineAggregation { rules }])
+                .unwrap();
+
+        let report = pipeline.run(&mut collections).unwrap();
+
+        assert!(report.errors().is_empty());
+        assert!(collections
+            .lines
+            .values()
+            .all(|line| line.name == "Metro 1"));
+    }
+}
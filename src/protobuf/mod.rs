@@ -0,0 +1,876 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Encoding of `Collections` with [Protocol Buffers](https://protobuf.dev),
+//! a compact interchange format for services written in other languages.
+//!
+//! The schema is available in `src/protobuf/transit_model.proto`. It covers
+//! the description of the networks (referential, stops, schedules, comments,
+//! transfers and geometries); the interchanges, fares, pathways, levels,
+//! grid calendars, addresses, points of interest and occupancies are not part
+//! of it.
+
+pub mod schema;
+
+use crate::{
+    model::{Collections, Model},
+    objects::*,
+    Result,
+};
+use anyhow::{anyhow, Context};
+use chrono_tz::Tz;
+use prost::Message;
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::TryInto,
+    fs,
+    path::Path,
+    str::FromStr,
+};
+use tracing::info;
+use typed_index_collection::{Collection, CollectionWithId};
+use wkt::ToWkt;
+
+fn codes_to_proto(codes: &KeysValues) -> Vec<schema::Code> {
+    codes
+        .iter()
+        .map(|(r#type, value)| schema::Code {
+            r#type: r#type.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+fn codes_from_proto(codes: Vec<schema::Code>) -> KeysValues {
+    codes
+        .into_iter()
+        .map(|code| (code.r#type, code.value))
+        .collect()
+}
+
+fn properties_to_proto(properties: &PropertiesMap) -> Vec<schema::ObjectProperty> {
+    properties
+        .iter()
+        .map(|(name, value)| schema::ObjectProperty {
+            name: name.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+fn properties_from_proto(properties: Vec<schema::ObjectProperty>) -> PropertiesMap {
+    properties
+        .into_iter()
+        .map(|property| (property.name, property.value))
+        .collect()
+}
+
+fn comment_links_to_proto(comment_links: &CommentLinksT) -> Vec<String> {
+    comment_links.iter().cloned().collect()
+}
+
+fn comment_links_from_proto(comment_links: Vec<String>) -> CommentLinksT {
+    comment_links.into_iter().collect()
+}
+
+fn timezone_from_proto(timezone: Option<String>) -> Result<Option<Tz>> {
+    timezone
+        .map(|timezone| {
+            timezone
+                .parse::<Tz>()
+                .map_err(|e| anyhow!("invalid timezone '{}': {}", timezone, e))
+        })
+        .transpose()
+}
+
+fn date_from_proto(date: &str) -> Result<Date> {
+    Date::parse_from_str(date, "%Y-%m-%d").with_context(|| format!("invalid date '{}'", date))
+}
+
+fn rgb_from_proto(color: Option<String>) -> Result<Option<Rgb>> {
+    color
+        .map(|color| Rgb::from_str(&color).with_context(|| format!("invalid color '{}'", color)))
+        .transpose()
+}
+
+fn stop_type_to_proto(stop_type: &StopType) -> schema::StopType {
+    match stop_type {
+        StopType::Point => schema::StopType::Point,
+        StopType::Zone => schema::StopType::Zone,
+        StopType::StopEntrance => schema::StopType::StopEntrance,
+        StopType::GenericNode => schema::StopType::GenericNode,
+        StopType::BoardingArea => schema::StopType::BoardingArea,
+    }
+}
+
+fn stop_type_from_proto(stop_type: i32) -> Result<StopType> {
+    match schema::StopType::from_i32(stop_type) {
+        Some(schema::StopType::Point) => Ok(StopType::Point),
+        Some(schema::StopType::Zone) => Ok(StopType::Zone),
+        Some(schema::StopType::StopEntrance) => Ok(StopType::StopEntrance),
+        Some(schema::StopType::GenericNode) => Ok(StopType::GenericNode),
+        Some(schema::StopType::BoardingArea) => Ok(StopType::BoardingArea),
+        None => Err(anyhow!("invalid stop type {}", stop_type)),
+    }
+}
+
+fn vehicle_journey_to_proto(
+    collections: &Collections,
+    vj: &VehicleJourney,
+) -> schema::VehicleJourney {
+    let stop_time_field = |map: &HashMap<(String, u32), String>, sequence: u32| {
+        map.get(&(vj.id.clone(), sequence)).cloned()
+    };
+    let stop_times = vj
+        .stop_times
+        .iter()
+        .map(|stop_time| schema::StopTime {
+            stop_point_id: collections.stop_points[stop_time.stop_point_idx].id.clone(),
+            sequence: stop_time.sequence,
+            arrival_time: stop_time.arrival_time.total_seconds(),
+            departure_time: stop_time.departure_time.total_seconds(),
+            boarding_duration: stop_time.boarding_duration.into(),
+            alighting_duration: stop_time.alighting_duration.into(),
+            pickup_type: stop_time.pickup_type.into(),
+            drop_off_type: stop_time.drop_off_type.into(),
+            local_zone_id: stop_time.local_zone_id.map(Into::into),
+            precision: stop_time.precision.as_ref().map(|precision| {
+                let precision = match precision {
+                    StopTimePrecision::Exact => schema::StopTimePrecision::Exact,
+                    StopTimePrecision::Approximate => schema::StopTimePrecision::Approximate,
+                    StopTimePrecision::Estimated => schema::StopTimePrecision::Estimated,
+                };
+                precision as i32
+            }),
+            headsign: stop_time_field(&collections.stop_time_headsigns, stop_time.sequence),
+            stop_time_id: stop_time_field(&collections.stop_time_ids, stop_time.sequence),
+            comment_id: stop_time_field(&collections.stop_time_comments, stop_time.sequence),
+        })
+        .collect();
+    schema::VehicleJourney {
+        id: vj.id.clone(),
+        codes: codes_to_proto(&vj.codes),
+        object_properties: properties_to_proto(&vj.object_properties),
+        comment_links: comment_links_to_proto(&vj.comment_links),
+        route_id: vj.route_id.clone(),
+        physical_mode_id: vj.physical_mode_id.clone(),
+        dataset_id: vj.dataset_id.clone(),
+        service_id: vj.service_id.clone(),
+        headsign: vj.headsign.clone(),
+        short_name: vj.short_name.clone(),
+        block_id: vj.block_id.clone(),
+        company_id: vj.company_id.clone(),
+        trip_property_id: vj.trip_property_id.clone(),
+        geometry_id: vj.geometry_id.clone(),
+        stop_times,
+        journey_pattern_id: vj.journey_pattern_id.clone(),
+    }
+}
+
+/// Converts the `Collections` into their protobuf message.
+pub fn to_proto(collections: &Collections) -> schema::Collections {
+    schema::Collections {
+        contributors: collections
+            .contributors
+            .values()
+            .map(|contributor| schema::Contributor {
+                id: contributor.id.clone(),
+                name: contributor.name.clone(),
+                license: contributor.license.clone(),
+                website: contributor.website.clone(),
+            })
+            .collect(),
+        datasets: collections
+            .datasets
+            .values()
+            .map(|dataset| schema::Dataset {
+                id: dataset.id.clone(),
+                contributor_id: dataset.contributor_id.clone(),
+                start_date: dataset.start_date.format("%Y-%m-%d").to_string(),
+                end_date: dataset.end_date.format("%Y-%m-%d").to_string(),
+                dataset_type: dataset.dataset_type.as_ref().map(|dataset_type| {
+                    let dataset_type = match dataset_type {
+                        DatasetType::Theorical => schema::DatasetType::Theorical,
+                        DatasetType::Revised => schema::DatasetType::Revised,
+                        DatasetType::Production => schema::DatasetType::Production,
+                    };
+                    dataset_type as i32
+                }),
+                extrapolation: dataset.extrapolation,
+                desc: dataset.desc.clone(),
+                system: dataset.system.clone(),
+            })
+            .collect(),
+        networks: collections
+            .networks
+            .values()
+            .map(|network| schema::Network {
+                id: network.id.clone(),
+                name: network.name.clone(),
+                url: network.url.clone(),
+                codes: codes_to_proto(&network.codes),
+                timezone: network.timezone.map(|tz| tz.name().to_string()),
+                lang: network.lang.clone(),
+                phone: network.phone.clone(),
+                address: network.address.clone(),
+                sort_order: network.sort_order,
+            })
+            .collect(),
+        commercial_modes: collections
+            .commercial_modes
+            .values()
+            .map(|commercial_mode| schema::CommercialMode {
+                id: commercial_mode.id.clone(),
+                name: commercial_mode.name.clone(),
+            })
+            .collect(),
+        physical_modes: collections
+            .physical_modes
+            .values()
+            .map(|physical_mode| schema::PhysicalMode {
+                id: physical_mode.id.clone(),
+                name: physical_mode.name.clone(),
+                co2_emission: physical_mode.co2_emission,
+            })
+            .collect(),
+        companies: collections
+            .companies
+            .values()
+            .map(|company| schema::Company {
+                id: company.id.clone(),
+                name: company.name.clone(),
+                address: company.address.clone(),
+                url: company.url.clone(),
+                mail: company.mail.clone(),
+                phone: company.phone.clone(),
+                codes: codes_to_proto(&company.codes),
+            })
+            .collect(),
+        lines: collections
+            .lines
+            .values()
+            .map(|line| schema::Line {
+                id: line.id.clone(),
+                code: line.code.clone(),
+                codes: codes_to_proto(&line.codes),
+                object_properties: properties_to_proto(&line.object_properties),
+                comment_links: comment_links_to_proto(&line.comment_links),
+                name: line.name.clone(),
+                forward_name: line.forward_name.clone(),
+                backward_name: line.backward_name.clone(),
+                color: line.color.as_ref().map(ToString::to_string),
+                text_color: line.text_color.as_ref().map(ToString::to_string),
+                sort_order: line.sort_order,
+                network_id: line.network_id.clone(),
+                commercial_mode_id: line.commercial_mode_id.clone(),
+                geometry_id: line.geometry_id.clone(),
+                opening_time: line.opening_time.map(Time::total_seconds),
+                closing_time: line.closing_time.map(Time::total_seconds),
+            })
+            .collect(),
+        routes: collections
+            .routes
+            .values()
+            .map(|route| schema::Route {
+                id: route.id.clone(),
+                name: route.name.clone(),
+                direction_type: route.direction_type.clone(),
+                codes: codes_to_proto(&route.codes),
+                object_properties: properties_to_proto(&route.object_properties),
+                comment_links: comment_links_to_proto(&route.comment_links),
+                line_id: route.line_id.clone(),
+                geometry_id: route.geometry_id.clone(),
+                destination_id: route.destination_id.clone(),
+            })
+            .collect(),
+        vehicle_journeys: collections
+            .vehicle_journeys
+            .values()
+            .map(|vj| vehicle_journey_to_proto(collections, vj))
+            .collect(),
+        frequencies: collections
+            .frequencies
+            .values()
+            .map(|frequency| schema::Frequency {
+                vehicle_journey_id: frequency.vehicle_journey_id.clone(),
+                start_time: frequency.start_time.total_seconds(),
+                end_time: frequency.end_time.total_seconds(),
+                headway_secs: frequency.headway_secs,
+            })
+            .collect(),
+        stop_areas: collections
+            .stop_areas
+            .values()
+            .map(|stop_area| schema::StopArea {
+                id: stop_area.id.clone(),
+                name: stop_area.name.clone(),
+                codes: codes_to_proto(&stop_area.codes),
+                object_properties: properties_to_proto(&stop_area.object_properties),
+                comment_links: comment_links_to_proto(&stop_area.comment_links),
+                visible: stop_area.visible,
+                lon: stop_area.coord.lon,
+                lat: stop_area.coord.lat,
+                timezone: stop_area.timezone.map(|tz| tz.name().to_string()),
+                geometry_id: stop_area.geometry_id.clone(),
+                equipment_id: stop_area.equipment_id.clone(),
+                level_id: stop_area.level_id.clone(),
+                address_id: stop_area.address_id.clone(),
+            })
+            .collect(),
+        stop_points: collections
+            .stop_points
+            .values()
+            .map(|stop_point| schema::StopPoint {
+                id: stop_point.id.clone(),
+                name: stop_point.name.clone(),
+                code: stop_point.code.clone(),
+                codes: codes_to_proto(&stop_point.codes),
+                object_properties: properties_to_proto(&stop_point.object_properties),
+                comment_links: comment_links_to_proto(&stop_point.comment_links),
+                visible: stop_point.visible,
+                lon: stop_point.coord.lon,
+                lat: stop_point.coord.lat,
+                stop_area_id: stop_point.stop_area_id.clone(),
+                timezone: stop_point.timezone.map(|tz| tz.name().to_string()),
+                geometry_id: stop_point.geometry_id.clone(),
+                equipment_id: stop_point.equipment_id.clone(),
+                fare_zone_id: stop_point.fare_zone_id.clone(),
+                level_id: stop_point.level_id.clone(),
+                platform_code: stop_point.platform_code.clone(),
+                stop_type: stop_type_to_proto(&stop_point.stop_type) as i32,
+                address_id: stop_point.address_id.clone(),
+            })
+            .collect(),
+        calendars: collections
+            .calendars
+            .values()
+            .map(|calendar| schema::Calendar {
+                id: calendar.id.clone(),
+                dates: calendar
+                    .dates
+                    .iter()
+                    .map(|date| date.format("%Y-%m-%d").to_string())
+                    .collect(),
+            })
+            .collect(),
+        comments: collections
+            .comments
+            .values()
+            .map(|comment| schema::Comment {
+                id: comment.id.clone(),
+                comment_type: match comment.comment_type {
+                    CommentType::Information => schema::CommentType::Information,
+                    CommentType::OnDemandTransport => schema::CommentType::OnDemandTransport,
+                } as i32,
+                label: comment.label.clone(),
+                name: comment.name.clone(),
+                url: comment.url.clone(),
+            })
+            .collect(),
+        transfers: collections
+            .transfers
+            .values()
+            .map(|transfer| schema::Transfer {
+                from_stop_id: transfer.from_stop_id.clone(),
+                to_stop_id: transfer.to_stop_id.clone(),
+                min_transfer_time: transfer.min_transfer_time,
+                real_min_transfer_time: transfer.real_min_transfer_time,
+                equipment_id: transfer.equipment_id.clone(),
+                transfer_mode: transfer.transfer_mode.map(|transfer_mode| {
+                    let transfer_mode = match transfer_mode {
+                        TransferMode::Walk => schema::TransferMode::Walk,
+                        TransferMode::Bike => schema::TransferMode::Bike,
+                        TransferMode::ParkAndRide => schema::TransferMode::ParkAndRide,
+                    };
+                    transfer_mode as i32
+                }),
+                start_time: transfer.start_time.map(Time::total_seconds),
+                end_time: transfer.end_time.map(Time::total_seconds),
+                days: transfer.days.map(|days| days.to_string()),
+            })
+            .collect(),
+        geometries: collections
+            .geometries
+            .values()
+            .map(|geometry| schema::Geometry {
+                id: geometry.id.clone(),
+                wkt: geometry.geometry.to_wkt().item.to_string(),
+            })
+            .collect(),
+        feed_infos: collections
+            .feed_infos
+            .iter()
+            .map(|(key, value)| schema::FeedInfo {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect(),
+    }
+}
+
+fn stop_points_from_proto(
+    stop_points: Vec<schema::StopPoint>,
+) -> Result<CollectionWithId<StopPoint>> {
+    let stop_points = stop_points
+        .into_iter()
+        .map(|stop_point| {
+            Ok(StopPoint {
+                timezone: timezone_from_proto(stop_point.timezone)?,
+                stop_type: stop_type_from_proto(stop_point.stop_type)?,
+                id: stop_point.id,
+                name: stop_point.name,
+                code: stop_point.code,
+                codes: codes_from_proto(stop_point.codes),
+                object_properties: properties_from_proto(stop_point.object_properties),
+                comment_links: comment_links_from_proto(stop_point.comment_links),
+                visible: stop_point.visible,
+                coord: Coord {
+                    lon: stop_point.lon,
+                    lat: stop_point.lat,
+                },
+                stop_area_id: stop_point.stop_area_id,
+                geometry_id: stop_point.geometry_id,
+                equipment_id: stop_point.equipment_id,
+                fare_zone_id: stop_point.fare_zone_id,
+                level_id: stop_point.level_id,
+                platform_code: stop_point.platform_code,
+                address_id: stop_point.address_id,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(CollectionWithId::new(stop_points)?)
+}
+
+fn vehicle_journeys_from_proto(
+    collections: &mut Collections,
+    vehicle_journeys: Vec<schema::VehicleJourney>,
+) -> Result<()> {
+    let mut vjs = Vec::with_capacity(vehicle_journeys.len());
+    for vj in vehicle_journeys {
+        let mut stop_times = Vec::with_capacity(vj.stop_times.len());
+        for stop_time in vj.stop_times {
+            let stop_point_idx = collections
+                .stop_points
+                .get_idx(&stop_time.stop_point_id)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "stop point {} of vehicle journey {} not found",
+                        stop_time.stop_point_id,
+                        vj.id
+                    )
+                })?;
+            let key = (vj.id.clone(), stop_time.sequence);
+            if let Some(headsign) = stop_time.headsign {
+                collections
+                    .stop_time_headsigns
+                    .insert(key.clone(), headsign);
+            }
+            if let Some(stop_time_id) = stop_time.stop_time_id {
+                collections.stop_time_ids.insert(key.clone(), stop_time_id);
+            }
+            if let Some(comment_id) = stop_time.comment_id {
+                collections.stop_time_comments.insert(key, comment_id);
+            }
+            let precision = stop_time
+                .precision
+                .map(
+                    |precision| match schema::StopTimePrecision::from_i32(precision) {
+                        Some(schema::StopTimePrecision::Exact) => Ok(StopTimePrecision::Exact),
+                        Some(schema::StopTimePrecision::Approximate) => {
+                            Ok(StopTimePrecision::Approximate)
+                        }
+                        Some(schema::StopTimePrecision::Estimated) => {
+                            Ok(StopTimePrecision::Estimated)
+                        }
+                        None => Err(anyhow!("invalid stop time precision {}", precision)),
+                    },
+                )
+                .transpose()?;
+            stop_times.push(StopTime {
+                stop_point_idx,
+                sequence: stop_time.sequence,
+                arrival_time: Time::new(0, 0, stop_time.arrival_time),
+                departure_time: Time::new(0, 0, stop_time.departure_time),
+                boarding_duration: stop_time.boarding_duration.try_into()?,
+                alighting_duration: stop_time.alighting_duration.try_into()?,
+                pickup_type: stop_time.pickup_type.try_into()?,
+                drop_off_type: stop_time.drop_off_type.try_into()?,
+                local_zone_id: stop_time.local_zone_id.map(TryInto::try_into).transpose()?,
+                precision,
+            });
+        }
+        vjs.push(VehicleJourney {
+            id: vj.id,
+            codes: codes_from_proto(vj.codes),
+            object_properties: properties_from_proto(vj.object_properties),
+            comment_links: comment_links_from_proto(vj.comment_links),
+            route_id: vj.route_id,
+            physical_mode_id: vj.physical_mode_id,
+            dataset_id: vj.dataset_id,
+            service_id: vj.service_id,
+            headsign: vj.headsign,
+            short_name: vj.short_name,
+            block_id: vj.block_id,
+            company_id: vj.company_id,
+            trip_property_id: vj.trip_property_id,
+            geometry_id: vj.geometry_id,
+            stop_times,
+            journey_pattern_id: vj.journey_pattern_id,
+        });
+    }
+    collections.vehicle_journeys = CollectionWithId::new(vjs)?;
+    Ok(())
+}
+
+/// Converts a protobuf message into `Collections`.
+pub fn from_proto(message: schema::Collections) -> Result<Collections> {
+    let mut collections = Collections {
+        contributors: CollectionWithId::new(
+            message
+                .contributors
+                .into_iter()
+                .map(|contributor| Contributor {
+                    id: contributor.id,
+                    name: contributor.name,
+                    license: contributor.license,
+                    website: contributor.website,
+                })
+                .collect(),
+        )?,
+        commercial_modes: CollectionWithId::new(
+            message
+                .commercial_modes
+                .into_iter()
+                .map(|commercial_mode| CommercialMode {
+                    id: commercial_mode.id,
+                    name: commercial_mode.name,
+                })
+                .collect(),
+        )?,
+        physical_modes: CollectionWithId::new(
+            message
+                .physical_modes
+                .into_iter()
+                .map(|physical_mode| PhysicalMode {
+                    id: physical_mode.id,
+                    name: physical_mode.name,
+                    co2_emission: physical_mode.co2_emission,
+                })
+                .collect(),
+        )?,
+        companies: CollectionWithId::new(
+            message
+                .companies
+                .into_iter()
+                .map(|company| Company {
+                    id: company.id,
+                    name: company.name,
+                    address: company.address,
+                    url: company.url,
+                    mail: company.mail,
+                    phone: company.phone,
+                    codes: codes_from_proto(company.codes),
+                })
+                .collect(),
+        )?,
+        routes: CollectionWithId::new(
+            message
+                .routes
+                .into_iter()
+                .map(|route| Route {
+                    id: route.id,
+                    name: route.name,
+                    direction_type: route.direction_type,
+                    codes: codes_from_proto(route.codes),
+                    object_properties: properties_from_proto(route.object_properties),
+                    comment_links: comment_links_from_proto(route.comment_links),
+                    line_id: route.line_id,
+                    geometry_id: route.geometry_id,
+                    destination_id: route.destination_id,
+                })
+                .collect(),
+        )?,
+        frequencies: Collection::new(
+            message
+                .frequencies
+                .into_iter()
+                .map(|frequency| Frequency {
+                    vehicle_journey_id: frequency.vehicle_journey_id,
+                    start_time: Time::new(0, 0, frequency.start_time),
+                    end_time: Time::new(0, 0, frequency.end_time),
+                    headway_secs: frequency.headway_secs,
+                })
+                .collect(),
+        ),
+        stop_points: stop_points_from_proto(message.stop_points)?,
+        comments: CollectionWithId::new(
+            message
+                .comments
+                .into_iter()
+                .map(|comment| Comment {
+                    comment_type: match schema::CommentType::from_i32(comment.comment_type) {
+                        Some(schema::CommentType::OnDemandTransport) => {
+                            CommentType::OnDemandTransport
+                        }
+                        _ => CommentType::Information,
+                    },
+                    id: comment.id,
+                    label: comment.label,
+                    name: comment.name,
+                    url: comment.url,
+                })
+                .collect(),
+        )?,
+        transfers: Collection::new(
+            message
+                .transfers
+                .into_iter()
+                .map(|transfer| {
+                    let transfer_mode = transfer
+                        .transfer_mode
+                        .map(
+                            |transfer_mode| match schema::TransferMode::from_i32(transfer_mode) {
+                                Some(schema::TransferMode::Walk) => Ok(TransferMode::Walk),
+                                Some(schema::TransferMode::Bike) => Ok(TransferMode::Bike),
+                                Some(schema::TransferMode::ParkAndRide) => {
+                                    Ok(TransferMode::ParkAndRide)
+                                }
+                                None => Err(anyhow!("invalid transfer mode {}", transfer_mode)),
+                            },
+                        )
+                        .transpose()?;
+                    Ok(Transfer {
+                        from_stop_id: transfer.from_stop_id,
+                        to_stop_id: transfer.to_stop_id,
+                        min_transfer_time: transfer.min_transfer_time,
+                        real_min_transfer_time: transfer.real_min_transfer_time,
+                        equipment_id: transfer.equipment_id,
+                        transfer_mode,
+                        start_time: transfer.start_time.map(|time| Time::new(0, 0, time)),
+                        end_time: transfer.end_time.map(|time| Time::new(0, 0, time)),
+                        days: transfer.days.as_deref().map(str::parse).transpose()?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        ),
+        feed_infos: message
+            .feed_infos
+            .into_iter()
+            .map(|feed_info| (feed_info.key, feed_info.value))
+            .collect(),
+        ..Default::default()
+    };
+
+    let mut datasets = Vec::with_capacity(message.datasets.len());
+    for dataset in message.datasets {
+        let dataset_type = dataset
+            .dataset_type
+            .map(
+                |dataset_type| match schema::DatasetType::from_i32(dataset_type) {
+                    Some(schema::DatasetType::Theorical) => Ok(DatasetType::Theorical),
+                    Some(schema::DatasetType::Revised) => Ok(DatasetType::Revised),
+                    Some(schema::DatasetType::Production) => Ok(DatasetType::Production),
+                    None => Err(anyhow!("invalid dataset type {}", dataset_type)),
+                },
+            )
+            .transpose()?;
+        datasets.push(Dataset {
+            start_date: date_from_proto(&dataset.start_date)?,
+            end_date: date_from_proto(&dataset.end_date)?,
+            id: dataset.id,
+            contributor_id: dataset.contributor_id,
+            dataset_type,
+            extrapolation: dataset.extrapolation,
+            desc: dataset.desc,
+            system: dataset.system,
+        });
+    }
+    collections.datasets = CollectionWithId::new(datasets)?;
+
+    let mut networks = Vec::with_capacity(message.networks.len());
+    for network in message.networks {
+        networks.push(Network {
+            timezone: timezone_from_proto(network.timezone)?,
+            id: network.id,
+            name: network.name,
+            url: network.url,
+            codes: codes_from_proto(network.codes),
+            lang: network.lang,
+            phone: network.phone,
+            address: network.address,
+            sort_order: network.sort_order,
+        });
+    }
+    collections.networks = CollectionWithId::new(networks)?;
+
+    let mut lines = Vec::with_capacity(message.lines.len());
+    for line in message.lines {
+        lines.push(Line {
+            color: rgb_from_proto(line.color)?,
+            text_color: rgb_from_proto(line.text_color)?,
+            id: line.id,
+            code: line.code,
+            codes: codes_from_proto(line.codes),
+            object_properties: properties_from_proto(line.object_properties),
+            comment_links: comment_links_from_proto(line.comment_links),
+            name: line.name,
+            forward_name: line.forward_name,
+            backward_name: line.backward_name,
+            sort_order: line.sort_order,
+            network_id: line.network_id,
+            commercial_mode_id: line.commercial_mode_id,
+            geometry_id: line.geometry_id,
+            opening_time: line.opening_time.map(|seconds| Time::new(0, 0, seconds)),
+            closing_time: line.closing_time.map(|seconds| Time::new(0, 0, seconds)),
+        });
+    }
+    collections.lines = CollectionWithId::new(lines)?;
+
+    let mut stop_areas = Vec::with_capacity(message.stop_areas.len());
+    for stop_area in message.stop_areas {
+        stop_areas.push(StopArea {
+            timezone: timezone_from_proto(stop_area.timezone)?,
+            id: stop_area.id,
+            name: stop_area.name,
+            codes: codes_from_proto(stop_area.codes),
+            object_properties: properties_from_proto(stop_area.object_properties),
+            comment_links: comment_links_from_proto(stop_area.comment_links),
+            visible: stop_area.visible,
+            coord: Coord {
+                lon: stop_area.lon,
+                lat: stop_area.lat,
+            },
+            geometry_id: stop_area.geometry_id,
+            equipment_id: stop_area.equipment_id,
+            level_id: stop_area.level_id,
+            address_id: stop_area.address_id,
+        });
+    }
+    collections.stop_areas = CollectionWithId::new(stop_areas)?;
+
+    let mut calendars = Vec::with_capacity(message.calendars.len());
+    for calendar in message.calendars {
+        let dates = calendar
+            .dates
+            .iter()
+            .map(|date| date_from_proto(date))
+            .collect::<Result<BTreeSet<Date>>>()?;
+        calendars.push(Calendar {
+            id: calendar.id,
+            dates,
+        });
+    }
+    collections.calendars = CollectionWithId::new(calendars)?;
+
+    let mut geometries = Vec::with_capacity(message.geometries.len());
+    for geometry in message.geometries {
+        let wkt = wkt::Wkt::<f64>::from_str(&geometry.wkt)
+            .map_err(|e| anyhow!("invalid geometry {}: {}", geometry.id, e))?;
+        geometries.push(Geometry {
+            geometry: wkt
+                .try_into()
+                .map_err(|e| anyhow!("invalid geometry {}: {}", geometry.id, e))?,
+            id: geometry.id,
+        });
+    }
+    collections.geometries = CollectionWithId::new(geometries)?;
+
+    vehicle_journeys_from_proto(&mut collections, message.vehicle_journeys)?;
+    Ok(collections)
+}
+
+/// Encodes the `Collections` into protobuf bytes.
+pub fn encode(collections: &Collections) -> Vec<u8> {
+    to_proto(collections).encode_to_vec()
+}
+
+/// Decodes `Collections` from protobuf bytes.
+pub fn decode(bytes: &[u8]) -> Result<Collections> {
+    let message = schema::Collections::decode(bytes).context("invalid protobuf message")?;
+    from_proto(message)
+}
+
+/// Writes the `Model` into the protobuf file `path`.
+pub fn write<P: AsRef<Path>>(model: &Model, path: P) -> Result<()> {
+    let path = path.as_ref();
+    info!("Writing protobuf file {:?}", path);
+    fs::write(path, encode(model)).with_context(|| format!("Error writing {:?}", path))
+}
+
+/// Reads a `Model` from the protobuf file `path`.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Model> {
+    let path = path.as_ref();
+    info!("Reading protobuf file {:?}", path);
+    let bytes = fs::read(path).with_context(|| format!("Error reading {:?}", path))?;
+    let collections = decode(&bytes).with_context(|| format!("Error decoding {:?}", path))?;
+    Model::new(collections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{one_vehicle_journey, BuildModel};
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    #[test]
+    fn encode_decode() {
+        let model = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("B", "10:00:00", "10:01:00")
+                    .st("C", "25:00:00", "25:01:00");
+            })
+            .build_model();
+        let mut collections = model.into_collections();
+        collections
+            .stop_time_headsigns
+            .insert(("vj2".to_string(), 1), "Somewhere".to_string());
+        collections.lines.get_mut("default_line").unwrap().color = Some(Rgb {
+            red: 255,
+            green: 0,
+            blue: 128,
+        });
+
+        let decoded = decode(&encode(&collections)).unwrap();
+        assert_eq!(to_proto(&collections), to_proto(&decoded));
+        let vj = decoded.vehicle_journeys.get("vj2").unwrap();
+        assert_eq!("C", decoded.stop_points[vj.stop_times[1].stop_point_idx].id);
+        assert_eq!(Time::new(25, 1, 0), vj.stop_times[1].departure_time);
+        assert_eq!(
+            Some(&"Somewhere".to_string()),
+            decoded.stop_time_headsigns.get(&("vj2".to_string(), 1))
+        );
+    }
+
+    #[test]
+    fn write_read_file() {
+        let model = one_vehicle_journey();
+        test_in_tmp_dir(|path| {
+            let file = path.join("model.pb");
+            write(&model, &file).unwrap();
+            let read_model = read(&file).unwrap();
+            assert_eq!(to_proto(&model), to_proto(&read_model));
+        });
+    }
+
+    #[test]
+    fn invalid_message() {
+        assert!(decode(&[0xff, 0xff, 0xff]).is_err());
+    }
+}
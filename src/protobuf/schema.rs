@@ -0,0 +1,472 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Messages of the protobuf schema `transit_model.proto`.
+//!
+//! The messages are declared by hand (and not generated at build time) to
+//! avoid depending on `protoc`; they must be kept in sync with the `.proto`
+//! file.
+
+#![allow(missing_docs)]
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Code {
+    #[prost(string, tag = "1")]
+    pub r#type: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ObjectProperty {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Contributor {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, optional, tag = "3")]
+    pub license: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub website: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum DatasetType {
+    Theorical = 0,
+    Revised = 1,
+    Production = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Dataset {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub contributor_id: String,
+    #[prost(string, tag = "3")]
+    pub start_date: String,
+    #[prost(string, tag = "4")]
+    pub end_date: String,
+    #[prost(enumeration = "DatasetType", optional, tag = "5")]
+    pub dataset_type: Option<i32>,
+    #[prost(bool, tag = "6")]
+    pub extrapolation: bool,
+    #[prost(string, optional, tag = "7")]
+    pub desc: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    pub system: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Network {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, optional, tag = "3")]
+    pub url: Option<String>,
+    #[prost(message, repeated, tag = "4")]
+    pub codes: Vec<Code>,
+    #[prost(string, optional, tag = "5")]
+    pub timezone: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub lang: Option<String>,
+    #[prost(string, optional, tag = "7")]
+    pub phone: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    pub address: Option<String>,
+    #[prost(uint32, optional, tag = "9")]
+    pub sort_order: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CommercialMode {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PhysicalMode {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(float, optional, tag = "3")]
+    pub co2_emission: Option<f32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Company {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, optional, tag = "3")]
+    pub address: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub url: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub mail: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub phone: Option<String>,
+    #[prost(message, repeated, tag = "7")]
+    pub codes: Vec<Code>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Line {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, optional, tag = "2")]
+    pub code: Option<String>,
+    #[prost(message, repeated, tag = "3")]
+    pub codes: Vec<Code>,
+    #[prost(message, repeated, tag = "4")]
+    pub object_properties: Vec<ObjectProperty>,
+    #[prost(string, repeated, tag = "5")]
+    pub comment_links: Vec<String>,
+    #[prost(string, tag = "6")]
+    pub name: String,
+    #[prost(string, optional, tag = "7")]
+    pub forward_name: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    pub backward_name: Option<String>,
+    #[prost(string, optional, tag = "9")]
+    pub color: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub text_color: Option<String>,
+    #[prost(uint32, optional, tag = "11")]
+    pub sort_order: Option<u32>,
+    #[prost(string, tag = "12")]
+    pub network_id: String,
+    #[prost(string, tag = "13")]
+    pub commercial_mode_id: String,
+    #[prost(string, optional, tag = "14")]
+    pub geometry_id: Option<String>,
+    #[prost(uint32, optional, tag = "15")]
+    pub opening_time: Option<u32>,
+    #[prost(uint32, optional, tag = "16")]
+    pub closing_time: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Route {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, optional, tag = "3")]
+    pub direction_type: Option<String>,
+    #[prost(message, repeated, tag = "4")]
+    pub codes: Vec<Code>,
+    #[prost(message, repeated, tag = "5")]
+    pub object_properties: Vec<ObjectProperty>,
+    #[prost(string, repeated, tag = "6")]
+    pub comment_links: Vec<String>,
+    #[prost(string, tag = "7")]
+    pub line_id: String,
+    #[prost(string, optional, tag = "8")]
+    pub geometry_id: Option<String>,
+    #[prost(string, optional, tag = "9")]
+    pub destination_id: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum StopTimePrecision {
+    Exact = 0,
+    Approximate = 1,
+    Estimated = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StopTime {
+    #[prost(string, tag = "1")]
+    pub stop_point_id: String,
+    #[prost(uint32, tag = "2")]
+    pub sequence: u32,
+    #[prost(uint32, tag = "3")]
+    pub arrival_time: u32,
+    #[prost(uint32, tag = "4")]
+    pub departure_time: u32,
+    #[prost(uint32, tag = "5")]
+    pub boarding_duration: u32,
+    #[prost(uint32, tag = "6")]
+    pub alighting_duration: u32,
+    #[prost(uint32, tag = "7")]
+    pub pickup_type: u32,
+    #[prost(uint32, tag = "8")]
+    pub drop_off_type: u32,
+    #[prost(uint32, optional, tag = "9")]
+    pub local_zone_id: Option<u32>,
+    #[prost(enumeration = "StopTimePrecision", optional, tag = "10")]
+    pub precision: Option<i32>,
+    #[prost(string, optional, tag = "11")]
+    pub headsign: Option<String>,
+    #[prost(string, optional, tag = "12")]
+    pub stop_time_id: Option<String>,
+    #[prost(string, optional, tag = "13")]
+    pub comment_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VehicleJourney {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(message, repeated, tag = "2")]
+    pub codes: Vec<Code>,
+    #[prost(message, repeated, tag = "3")]
+    pub object_properties: Vec<ObjectProperty>,
+    #[prost(string, repeated, tag = "4")]
+    pub comment_links: Vec<String>,
+    #[prost(string, tag = "5")]
+    pub route_id: String,
+    #[prost(string, tag = "6")]
+    pub physical_mode_id: String,
+    #[prost(string, tag = "7")]
+    pub dataset_id: String,
+    #[prost(string, tag = "8")]
+    pub service_id: String,
+    #[prost(string, optional, tag = "9")]
+    pub headsign: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub short_name: Option<String>,
+    #[prost(string, optional, tag = "11")]
+    pub block_id: Option<String>,
+    #[prost(string, tag = "12")]
+    pub company_id: String,
+    #[prost(string, optional, tag = "13")]
+    pub trip_property_id: Option<String>,
+    #[prost(string, optional, tag = "14")]
+    pub geometry_id: Option<String>,
+    #[prost(message, repeated, tag = "15")]
+    pub stop_times: Vec<StopTime>,
+    #[prost(string, optional, tag = "16")]
+    pub journey_pattern_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Frequency {
+    #[prost(string, tag = "1")]
+    pub vehicle_journey_id: String,
+    #[prost(uint32, tag = "2")]
+    pub start_time: u32,
+    #[prost(uint32, tag = "3")]
+    pub end_time: u32,
+    #[prost(uint32, tag = "4")]
+    pub headway_secs: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum StopType {
+    Point = 0,
+    Zone = 1,
+    StopEntrance = 2,
+    GenericNode = 3,
+    BoardingArea = 4,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StopArea {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(message, repeated, tag = "3")]
+    pub codes: Vec<Code>,
+    #[prost(message, repeated, tag = "4")]
+    pub object_properties: Vec<ObjectProperty>,
+    #[prost(string, repeated, tag = "5")]
+    pub comment_links: Vec<String>,
+    #[prost(bool, tag = "6")]
+    pub visible: bool,
+    #[prost(double, tag = "7")]
+    pub lon: f64,
+    #[prost(double, tag = "8")]
+    pub lat: f64,
+    #[prost(string, optional, tag = "9")]
+    pub timezone: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub geometry_id: Option<String>,
+    #[prost(string, optional, tag = "11")]
+    pub equipment_id: Option<String>,
+    #[prost(string, optional, tag = "12")]
+    pub level_id: Option<String>,
+    #[prost(string, optional, tag = "13")]
+    pub address_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StopPoint {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, optional, tag = "3")]
+    pub code: Option<String>,
+    #[prost(message, repeated, tag = "4")]
+    pub codes: Vec<Code>,
+    #[prost(message, repeated, tag = "5")]
+    pub object_properties: Vec<ObjectProperty>,
+    #[prost(string, repeated, tag = "6")]
+    pub comment_links: Vec<String>,
+    #[prost(bool, tag = "7")]
+    pub visible: bool,
+    #[prost(double, tag = "8")]
+    pub lon: f64,
+    #[prost(double, tag = "9")]
+    pub lat: f64,
+    #[prost(string, tag = "10")]
+    pub stop_area_id: String,
+    #[prost(string, optional, tag = "11")]
+    pub timezone: Option<String>,
+    #[prost(string, optional, tag = "12")]
+    pub geometry_id: Option<String>,
+    #[prost(string, optional, tag = "13")]
+    pub equipment_id: Option<String>,
+    #[prost(string, optional, tag = "14")]
+    pub fare_zone_id: Option<String>,
+    #[prost(string, optional, tag = "15")]
+    pub level_id: Option<String>,
+    #[prost(string, optional, tag = "16")]
+    pub platform_code: Option<String>,
+    #[prost(enumeration = "StopType", tag = "17")]
+    pub stop_type: i32,
+    #[prost(string, optional, tag = "18")]
+    pub address_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Calendar {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, repeated, tag = "2")]
+    pub dates: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum CommentType {
+    Information = 0,
+    OnDemandTransport = 1,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Comment {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(enumeration = "CommentType", tag = "2")]
+    pub comment_type: i32,
+    #[prost(string, optional, tag = "3")]
+    pub label: Option<String>,
+    #[prost(string, tag = "4")]
+    pub name: String,
+    #[prost(string, optional, tag = "5")]
+    pub url: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum TransferMode {
+    Walk = 0,
+    Bike = 1,
+    ParkAndRide = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Transfer {
+    #[prost(string, tag = "1")]
+    pub from_stop_id: String,
+    #[prost(string, tag = "2")]
+    pub to_stop_id: String,
+    #[prost(uint32, optional, tag = "3")]
+    pub min_transfer_time: Option<u32>,
+    #[prost(uint32, optional, tag = "4")]
+    pub real_min_transfer_time: Option<u32>,
+    #[prost(string, optional, tag = "5")]
+    pub equipment_id: Option<String>,
+    #[prost(enumeration = "TransferMode", optional, tag = "6")]
+    pub transfer_mode: Option<i32>,
+    #[prost(uint32, optional, tag = "7")]
+    pub start_time: Option<u32>,
+    #[prost(uint32, optional, tag = "8")]
+    pub end_time: Option<u32>,
+    #[prost(string, optional, tag = "9")]
+    pub days: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Geometry {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub wkt: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FeedInfo {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Collections {
+    #[prost(message, repeated, tag = "1")]
+    pub contributors: Vec<Contributor>,
+    #[prost(message, repeated, tag = "2")]
+    pub datasets: Vec<Dataset>,
+    #[prost(message, repeated, tag = "3")]
+    pub networks: Vec<Network>,
+    #[prost(message, repeated, tag = "4")]
+    pub commercial_modes: Vec<CommercialMode>,
+    #[prost(message, repeated, tag = "5")]
+    pub physical_modes: Vec<PhysicalMode>,
+    #[prost(message, repeated, tag = "6")]
+    pub companies: Vec<Company>,
+    #[prost(message, repeated, tag = "7")]
+    pub lines: Vec<Line>,
+    #[prost(message, repeated, tag = "8")]
+    pub routes: Vec<Route>,
+    #[prost(message, repeated, tag = "9")]
+    pub vehicle_journeys: Vec<VehicleJourney>,
+    #[prost(message, repeated, tag = "10")]
+    pub frequencies: Vec<Frequency>,
+    #[prost(message, repeated, tag = "11")]
+    pub stop_areas: Vec<StopArea>,
+    #[prost(message, repeated, tag = "12")]
+    pub stop_points: Vec<StopPoint>,
+    #[prost(message, repeated, tag = "13")]
+    pub calendars: Vec<Calendar>,
+    #[prost(message, repeated, tag = "14")]
+    pub comments: Vec<Comment>,
+    #[prost(message, repeated, tag = "15")]
+    pub transfers: Vec<Transfer>,
+    #[prost(message, repeated, tag = "16")]
+    pub geometries: Vec<Geometry>,
+    #[prost(message, repeated, tag = "17")]
+    pub feed_infos: Vec<FeedInfo>,
+}
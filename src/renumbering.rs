@@ -0,0 +1,163 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Replacement of the identifiers of the lines, the routes and the vehicle
+//! journeys by short numeric identifiers (`1`, `2`, ...), for the legacy
+//! systems limiting the length of the identifiers. The original identifiers
+//! are kept as `source` codes of the objects.
+
+use crate::{
+    codes::CodeSystem,
+    model::Collections,
+    objects::{Codes, ObjectType},
+    Result,
+};
+use std::collections::HashMap;
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Id};
+
+// Renumber the objects of `collection` in their order, returning the new
+// identifiers by original identifier
+fn renumber_collection<T: Id<T> + Codes>(
+    collection: &mut CollectionWithId<T>,
+    object_type: &str,
+) -> Result<HashMap<String, String>> {
+    let mut objects = collection.take();
+    let mut new_ids = HashMap::with_capacity(objects.len());
+    for (number, object) in (1..).zip(objects.iter_mut()) {
+        let original_id = object.id().to_string();
+        let new_id = number.to_string();
+        object
+            .codes_mut()
+            .insert((CodeSystem::Source.name().to_string(), original_id.clone()));
+        object.set_id(new_id.clone());
+        new_ids.insert(original_id, new_id);
+    }
+    *collection = CollectionWithId::new(objects)?;
+    info!("{} {}s renumbered", new_ids.len(), object_type);
+    Ok(new_ids)
+}
+
+fn update_id(id: &mut String, new_ids: &HashMap<String, String>) {
+    if let Some(new_id) = new_ids.get(id.as_str()) {
+        *id = new_id.clone();
+    }
+}
+
+fn update_stop_time_keys(
+    values: &mut HashMap<(String, u32), String>,
+    new_ids: &HashMap<String, String>,
+) {
+    *values = values
+        .drain()
+        .map(|((mut vj_id, sequence), value)| {
+            update_id(&mut vj_id, new_ids);
+            ((vj_id, sequence), value)
+        })
+        .collect();
+}
+
+/// Replace the identifiers of the lines, the routes and the vehicle journeys
+/// of `collections` by numbers, starting from 1 for each type of object in
+/// the order of the collections, and update the references to them.
+///
+/// The original identifier of each object is added to its codes, with the
+/// `source` system (see [CodeSystem::Source]).
+pub fn renumber_ids(collections: &mut Collections) -> Result<()> {
+    let line_ids = renumber_collection(&mut collections.lines, "line")?;
+    let route_ids = renumber_collection(&mut collections.routes, "route")?;
+    let vj_ids = renumber_collection(&mut collections.vehicle_journeys, "vehicle journey")?;
+
+    for idx in collections.routes.indexes() {
+        update_id(&mut collections.routes.index_mut(idx).line_id, &line_ids);
+    }
+    for idx in collections.vehicle_journeys.indexes() {
+        update_id(
+            &mut collections.vehicle_journeys.index_mut(idx).route_id,
+            &route_ids,
+        );
+    }
+    for frequency in collections.frequencies.values_mut() {
+        update_id(&mut frequency.vehicle_journey_id, &vj_ids);
+    }
+    for interchange in collections.interchanges.values_mut() {
+        update_id(&mut interchange.from_vehicle_journey_id, &vj_ids);
+        update_id(&mut interchange.to_vehicle_journey_id, &vj_ids);
+    }
+    for perimeter in collections.ticket_use_perimeters.values_mut() {
+        if perimeter.object_type == ObjectType::Line {
+            update_id(&mut perimeter.object_id, &line_ids);
+        }
+    }
+    for grid_rel_calendar_line in collections.grid_rel_calendar_line.values_mut() {
+        update_id(&mut grid_rel_calendar_line.line_id, &line_ids);
+    }
+    for occupancy in collections.occupancies.values_mut() {
+        update_id(&mut occupancy.line_id, &line_ids);
+    }
+    for stop_time_occupancy in collections.stop_time_occupancies.values_mut() {
+        update_id(&mut stop_time_occupancy.vehicle_journey_id, &vj_ids);
+    }
+    update_stop_time_keys(&mut collections.stop_time_headsigns, &vj_ids);
+    update_stop_time_keys(&mut collections.stop_time_ids, &vj_ids);
+    update_stop_time_keys(&mut collections.stop_time_comments, &vj_ids);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    #[test]
+    fn renumber_lines_routes_and_vehicle_journeys() {
+        let mut collections = ModelBuilder::default()
+            .route("route_with_a_very_long_identifier", |route| {
+                route.line_id = "line_with_a_very_long_identifier".to_string();
+            })
+            .vj("vj_with_a_very_long_identifier", |vj| {
+                vj.route("route_with_a_very_long_identifier")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections();
+        collections.stop_time_headsigns.insert(
+            ("vj_with_a_very_long_identifier".to_string(), 0),
+            "B".to_string(),
+        );
+        renumber_ids(&mut collections).unwrap();
+
+        let line = collections.lines.get("1").unwrap();
+        assert!(line.codes.contains(&(
+            "source".to_string(),
+            "line_with_a_very_long_identifier".to_string()
+        )));
+        assert_eq!("1", collections.routes.get("1").unwrap().line_id);
+        let vj = collections.vehicle_journeys.get("1").unwrap();
+        assert_eq!("1", vj.route_id);
+        assert!(vj.codes.contains(&(
+            "source".to_string(),
+            "vj_with_a_very_long_identifier".to_string()
+        )));
+        assert_eq!(
+            Some(&"B".to_string()),
+            collections.stop_time_headsigns.get(&("1".to_string(), 0))
+        );
+        Model::new(collections).unwrap();
+    }
+}
@@ -0,0 +1,468 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Report of the decisions taken and the issues found while processing a
+//! dataset, each entry being tagged by a category specific to the processing.
+//!
+//! The entries of a systemic issue, reported once per object, can be
+//! aggregated with `Report::aggregate` into one entry counting the objects.
+
+use crate::Result;
+use anyhow::{anyhow, Context};
+use derivative::Derivative;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+// Maximum number of identifiers of objects listed for an issue in the text
+// format
+const TEXT_MAX_SAMPLES: usize = 10;
+
+/// Format of a `Report` written by `Report::write`
+#[derive(Derivative, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum ReportFormat {
+    /// Pretty JSON object with the list of the errors, warnings and infos
+    #[derivative(Default)]
+    Json,
+    /// One JSON object per line and per entry, with its severity, for the
+    /// ingestion by a log aggregation platform
+    JsonLines,
+    /// Human-readable summary, the entries with the same message being
+    /// aggregated
+    Text,
+}
+
+impl FromStr for ReportFormat {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(ReportFormat::Json),
+            "jsonl" => Ok(ReportFormat::JsonLines),
+            "text" => Ok(ReportFormat::Text),
+            _ => Err(anyhow!(
+                "unknown report format {:?} (expected 'json', 'jsonl' or 'text')",
+                s
+            )),
+        }
+    }
+}
+
+/// Severity of an entry of a `Report`
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// A decision taken while processing the dataset
+    Info,
+    /// An issue of the dataset worked around while processing it
+    Warning,
+    /// An issue of the dataset which could not be worked around
+    Error,
+}
+
+/// An entry of a `Report`
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ReportEntry<R> {
+    /// Category of the entry
+    pub category: R,
+    /// Human-readable description of the entry
+    pub message: String,
+    /// Identifier of the object concerned by the entry, the `message` being
+    /// then the same for all the objects having the same issue
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_id: Option<String>,
+}
+
+impl<R> ReportEntry<R> {
+    /// Create an entry not attached to an object
+    pub fn new(category: R, message: String) -> Self {
+        ReportEntry {
+            category,
+            message,
+            object_id: None,
+        }
+    }
+
+    /// Attach the entry to the object `object_id`
+    pub fn with_object_id<S: Into<String>>(mut self, object_id: S) -> Self {
+        self.object_id = Some(object_id.into());
+        self
+    }
+}
+
+/// Entries of a `Report` having the same severity, category and message,
+/// aggregated by `Report::aggregate`
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct AggregatedEntry<R> {
+    /// Severity of the entries
+    pub severity: Severity,
+    /// Category of the entries
+    pub category: R,
+    /// Message of the entries
+    pub message: String,
+    /// Number of entries
+    pub count: usize,
+    /// Identifiers of the first objects concerned by the entries
+    pub sample_ids: Vec<String>,
+    /// Number of objects concerned by the entries not in `sample_ids`
+    pub omitted: usize,
+}
+
+impl<R> fmt::Display for AggregatedEntry<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if self.count > 1 {
+            write!(f, " ({} times)", self.count)?;
+        }
+        if !self.sample_ids.is_empty() {
+            write!(f, ": {}", self.sample_ids.join(", "))?;
+        }
+        if self.omitted > 0 {
+            write!(f, " and {} more", self.omitted)?;
+        }
+        Ok(())
+    }
+}
+
+/// A list of errors, warnings and infos, each of them having a category `R`
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Report<R> {
+    errors: Vec<ReportEntry<R>>,
+    warnings: Vec<ReportEntry<R>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    infos: Vec<ReportEntry<R>>,
+}
+
+impl<R> Default for Report<R> {
+    fn default() -> Self {
+        Report {
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            infos: Vec::new(),
+        }
+    }
+}
+
+impl<R> Report<R> {
+    /// Add a warning to the report
+    pub fn add_warning(&mut self, message: String, category: R) {
+        self.warnings.push(ReportEntry::new(category, message));
+    }
+
+    /// Add an error to the report
+    pub fn add_error(&mut self, message: String, category: R) {
+        self.errors.push(ReportEntry::new(category, message));
+    }
+
+    /// Add an info to the report
+    pub fn add_info(&mut self, message: String, category: R) {
+        self.infos.push(ReportEntry::new(category, message));
+    }
+
+    /// Add an `entry` of the given `severity` to the report
+    pub fn add_entry(&mut self, severity: Severity, entry: ReportEntry<R>) {
+        self.entries_mut(severity).push(entry);
+    }
+
+    fn entries_mut(&mut self, severity: Severity) -> &mut Vec<ReportEntry<R>> {
+        match severity {
+            Severity::Info => &mut self.infos,
+            Severity::Warning => &mut self.warnings,
+            Severity::Error => &mut self.errors,
+        }
+    }
+
+    /// Warnings of the report, in insertion order
+    pub fn warnings(&self) -> &[ReportEntry<R>] {
+        &self.warnings
+    }
+
+    /// Errors of the report, in insertion order
+    pub fn errors(&self) -> &[ReportEntry<R>] {
+        &self.errors
+    }
+
+    /// Infos of the report, in insertion order
+    pub fn infos(&self) -> &[ReportEntry<R>] {
+        &self.infos
+    }
+
+    /// Entries of the report of the given `severity`, in insertion order
+    pub fn entries(&self, severity: Severity) -> &[ReportEntry<R>] {
+        match severity {
+            Severity::Info => &self.infos,
+            Severity::Warning => &self.warnings,
+            Severity::Error => &self.errors,
+        }
+    }
+
+    /// Number of entries of the given `severity`
+    pub fn count(&self, severity: Severity) -> usize {
+        self.entries(severity).len()
+    }
+
+    /// `true` if the report has neither warnings nor errors (the infos
+    /// are not taken into account)
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty() && self.warnings.is_empty()
+    }
+
+    /// Move all the entries of `other` at the end of this report
+    pub fn append(&mut self, other: &mut Report<R>) {
+        self.errors.append(&mut other.errors);
+        self.warnings.append(&mut other.warnings);
+        self.infos.append(&mut other.infos);
+    }
+}
+
+impl<R: PartialEq> Report<R> {
+    /// Number of entries of the given `severity` and `category`
+    pub fn count_category(&self, severity: Severity, category: &R) -> usize {
+        self.entries(severity)
+            .iter()
+            .filter(|entry| &entry.category == category)
+            .count()
+    }
+}
+
+impl<R: Clone + PartialEq> Report<R> {
+    /// Aggregate the entries having the same severity, category and message,
+    /// from the errors to the infos and in insertion order otherwise.
+    ///
+    /// At most `max_samples` identifiers of the objects concerned are kept
+    /// for each aggregated entry, the other ones being only counted.
+    pub fn aggregate(&self, max_samples: usize) -> Vec<AggregatedEntry<R>> {
+        let mut aggregated: Vec<AggregatedEntry<R>> = Vec::new();
+        for severity in [Severity::Error, Severity::Warning, Severity::Info] {
+            let mut groups: HashMap<&str, Vec<usize>> = HashMap::new();
+            for entry in self.entries(severity) {
+                let indexes = groups.entry(entry.message.as_str()).or_default();
+                let index = match indexes
+                    .iter()
+                    .find(|&&index| aggregated[index].category == entry.category)
+                {
+                    Some(&index) => index,
+                    None => {
+                        indexes.push(aggregated.len());
+                        aggregated.push(AggregatedEntry {
+                            severity,
+                            category: entry.category.clone(),
+                            message: entry.message.clone(),
+                            count: 0,
+                            sample_ids: Vec::new(),
+                            omitted: 0,
+                        });
+                        aggregated.len() - 1
+                    }
+                };
+                let aggregated_entry = &mut aggregated[index];
+                aggregated_entry.count += 1;
+                if let Some(object_id) = &entry.object_id {
+                    if aggregated_entry.sample_ids.len() < max_samples {
+                        aggregated_entry.sample_ids.push(object_id.clone());
+                    } else {
+                        aggregated_entry.omitted += 1;
+                    }
+                }
+            }
+        }
+        aggregated
+    }
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a, R> {
+    severity: Severity,
+    #[serde(flatten)]
+    entry: &'a ReportEntry<R>,
+}
+
+impl<R: Serialize> Report<R> {
+    /// Write the report as pretty JSON into the file `path`
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Error creating {:?}", path))?;
+        serde_json::to_writer_pretty(file, self)
+            .with_context(|| format!("Error writing {:?}", path))?;
+        Ok(())
+    }
+
+    /// Write the report as JSON lines into `writer`, one line per entry from
+    /// the errors to the infos
+    pub fn write_json_lines<W: Write>(&self, mut writer: W) -> Result<()> {
+        for severity in [Severity::Error, Severity::Warning, Severity::Info] {
+            for entry in self.entries(severity) {
+                serde_json::to_writer(&mut writer, &JsonLine { severity, entry })?;
+                writeln!(writer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Serialize + Clone + PartialEq> Report<R> {
+    /// Write a human-readable summary of the report into `writer`, the
+    /// entries with the same severity, category and message being aggregated
+    pub fn write_text<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(
+            writer,
+            "{} error(s), {} warning(s), {} info(s)",
+            self.count(Severity::Error),
+            self.count(Severity::Warning),
+            self.count(Severity::Info)
+        )?;
+        for entry in self.aggregate(TEXT_MAX_SAMPLES) {
+            let severity = serde_json::to_value(entry.severity)?;
+            let category = serde_json::to_value(&entry.category)?;
+            writeln!(
+                writer,
+                "[{}] {}: {}",
+                severity.as_str().unwrap_or_default(),
+                category.as_str().unwrap_or_default(),
+                entry
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the report in the `format` into `writer`
+    pub fn write_to<W: Write>(&self, mut writer: W, format: ReportFormat) -> Result<()> {
+        match format {
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, self)?;
+                writeln!(writer)?;
+            }
+            ReportFormat::JsonLines => self.write_json_lines(&mut writer)?,
+            ReportFormat::Text => self.write_text(&mut writer)?,
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the report in the `format` into the file `path`
+    pub fn write<P: AsRef<Path>>(&self, path: P, format: ReportFormat) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Error creating {:?}", path))?;
+        self.write_to(BufWriter::new(file), format)
+            .with_context(|| format!("Error writing {:?}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    enum Category {
+        Something,
+        Other,
+    }
+
+    #[test]
+    fn serialize_report() {
+        let mut report = Report::default();
+        assert!(report.is_empty());
+        report.add_warning("a warning".to_string(), Category::Something);
+        report.add_error("an error".to_string(), Category::Something);
+        assert_eq!(1, report.warnings().len());
+        assert_eq!(1, report.errors().len());
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "errors": [{ "category": "something", "message": "an error" }],
+                "warnings": [{ "category": "something", "message": "a warning" }],
+            }),
+            json
+        );
+    }
+
+    #[test]
+    fn aggregate_report() {
+        let mut report = Report::default();
+        for object_id in &["A", "B", "C", "D"] {
+            report.add_entry(
+                Severity::Warning,
+                ReportEntry::new(Category::Something, "missing name".to_string())
+                    .with_object_id(*object_id),
+            );
+        }
+        report.add_error("an error".to_string(), Category::Something);
+        report.add_info("an info".to_string(), Category::Other);
+        report.add_info("an info".to_string(), Category::Other);
+
+        assert_eq!(4, report.count(Severity::Warning));
+        assert_eq!(2, report.count_category(Severity::Info, &Category::Other));
+        assert_eq!(
+            0,
+            report.count_category(Severity::Info, &Category::Something)
+        );
+        let aggregated = report.aggregate(2);
+        assert_eq!(3, aggregated.len());
+        assert_eq!("an error", aggregated[0].to_string());
+        assert_eq!(Severity::Warning, aggregated[1].severity);
+        assert_eq!(4, aggregated[1].count);
+        assert_eq!(
+            "missing name (4 times): A, B and 2 more",
+            aggregated[1].to_string()
+        );
+        assert_eq!("an info (2 times)", aggregated[2].to_string());
+    }
+
+    fn report() -> Report<Category> {
+        let mut report = Report::default();
+        report.add_warning("a warning".to_string(), Category::Something);
+        for object_id in &["A", "B"] {
+            report.add_entry(
+                Severity::Error,
+                ReportEntry::new(Category::Other, "an error".to_string())
+                    .with_object_id(*object_id),
+            );
+        }
+        report
+    }
+
+    #[test]
+    fn write_json_lines() {
+        let mut output = Vec::new();
+        report()
+            .write_to(&mut output, ReportFormat::JsonLines)
+            .unwrap();
+        assert_eq!(
+            "{\"severity\":\"error\",\"category\":\"other\",\"message\":\"an error\",\"object_id\":\"A\"}\n\
+             {\"severity\":\"error\",\"category\":\"other\",\"message\":\"an error\",\"object_id\":\"B\"}\n\
+             {\"severity\":\"warning\",\"category\":\"something\",\"message\":\"a warning\"}\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn write_text() {
+        let mut output = Vec::new();
+        report().write_to(&mut output, ReportFormat::Text).unwrap();
+        assert_eq!(
+            "2 error(s), 1 warning(s), 0 info(s)\n\
+             [error] other: an error (2 times): A, B\n\
+             [warning] something: a warning\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+}
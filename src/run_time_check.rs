@@ -0,0 +1,305 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Detection, and optional fix, of the negative or zero dwell and run times of
+//! the trips.
+//!
+//! `Model::new` removes the trips whose stop times are not in chronological
+//! order: the fix has to be applied on the `Collections` before building the
+//! `Model` to keep them.
+
+use crate::{
+    model::Collections,
+    objects::{StopTime, Time, VehicleJourney},
+    report::{Report, ReportEntry, Severity},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::info;
+
+/// Categories of the `Report` produced by the run times checks
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunTimeReportCategory {
+    /// The departure from a stop is before the arrival at this stop
+    DepartureBeforeArrival,
+    /// The arrival at a stop is before the departure from the previous stop
+    NegativeRunTime,
+    /// The arrival at a stop is at the departure from the previous and
+    /// distinct stop
+    ZeroRunTime,
+    /// The stop times of the trip have been fixed
+    RunTimeFixed,
+}
+
+/// Minimum run times between two distinct stops, in seconds, injected by
+/// `fix_run_times`
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MinimumRunTimes {
+    /// Minimum run time of the physical modes absent from
+    /// `by_physical_mode`, the trips of these modes are not fixed if empty
+    pub default: Option<u32>,
+    /// Minimum run time by physical mode identifier
+    pub by_physical_mode: HashMap<String, u32>,
+}
+
+impl MinimumRunTimes {
+    /// Minimum run time of the physical mode `physical_mode_id`, if any
+    pub fn minimum_run_time(&self, physical_mode_id: &str) -> Option<u32> {
+        self.by_physical_mode
+            .get(physical_mode_id)
+            .copied()
+            .or(self.default)
+    }
+}
+
+fn check_stop_times(
+    vehicle_journey: &VehicleJourney,
+    report: &mut Report<RunTimeReportCategory>,
+) -> bool {
+    let mut is_valid = true;
+    for stop_time in &vehicle_journey.stop_times {
+        if stop_time.departure_time < stop_time.arrival_time {
+            is_valid = false;
+            report.add_error(
+                format!(
+                    "vehicle journey {} departs at {} before arriving at {} at sequence {}",
+                    vehicle_journey.id,
+                    stop_time.departure_time,
+                    stop_time.arrival_time,
+                    stop_time.sequence
+                ),
+                RunTimeReportCategory::DepartureBeforeArrival,
+            );
+        }
+    }
+    for (from, to) in vehicle_journey
+        .stop_times
+        .iter()
+        .zip(vehicle_journey.stop_times.iter().skip(1))
+    {
+        if to.arrival_time < from.departure_time {
+            is_valid = false;
+            report.add_error(
+                format!(
+                    "vehicle journey {} arrives at {} at sequence {} before departing at {} from sequence {}",
+                    vehicle_journey.id,
+                    to.arrival_time,
+                    to.sequence,
+                    from.departure_time,
+                    from.sequence
+                ),
+                RunTimeReportCategory::NegativeRunTime,
+            );
+        } else if to.arrival_time == from.departure_time && to.stop_point_idx != from.stop_point_idx
+        {
+            is_valid = false;
+            report.add_warning(
+                format!(
+                    "vehicle journey {} runs in 0 second from sequence {} to sequence {}",
+                    vehicle_journey.id, from.sequence, to.sequence
+                ),
+                RunTimeReportCategory::ZeroRunTime,
+            );
+        }
+    }
+    is_valid
+}
+
+/// Check the dwell times and the run times of the trips of `collections`.
+///
+/// The departures before the arrivals and the negative run times are errors
+/// of the returned `Report`, the zero run times between distinct stop points
+/// are warnings.
+pub fn check_run_times(collections: &Collections) -> Report<RunTimeReportCategory> {
+    let mut report = Report::default();
+    for vehicle_journey in collections.vehicle_journeys.values() {
+        check_stop_times(vehicle_journey, &mut report);
+    }
+    report
+}
+
+// Shift the stop times so that each departure is at or after its arrival,
+// and each arrival at least `minimum_run_time` after the previous departure
+// from a distinct stop point
+fn fix_stop_times(stop_times: &mut [StopTime], minimum_run_time: u32) {
+    let minimum_run_time = Time::new(0, 0, minimum_run_time);
+    for position in 0..stop_times.len() {
+        if position > 0 {
+            let previous = &stop_times[position - 1];
+            let minimum_arrival_time =
+                if previous.stop_point_idx == stop_times[position].stop_point_idx {
+                    previous.departure_time
+                } else {
+                    previous.departure_time + minimum_run_time
+                };
+            let stop_time = &mut stop_times[position];
+            if stop_time.arrival_time < minimum_arrival_time {
+                let dwell_time = if stop_time.departure_time > stop_time.arrival_time {
+                    stop_time.departure_time - stop_time.arrival_time
+                } else {
+                    Time::new(0, 0, 0)
+                };
+                stop_time.arrival_time = minimum_arrival_time;
+                stop_time.departure_time = minimum_arrival_time + dwell_time;
+            }
+        }
+        let stop_time = &mut stop_times[position];
+        if stop_time.departure_time < stop_time.arrival_time {
+            stop_time.departure_time = stop_time.arrival_time;
+        }
+    }
+}
+
+/// Fix the dwell times and the run times of the trips of `collections` whose
+/// physical mode has a minimum run time in `minimum_run_times`.
+///
+/// A departure before the arrival at a stop is moved to the arrival; an
+/// arrival less than the minimum run time after the departure from the
+/// previous stop is delayed, along with the departure from this stop.
+/// The following stop times are delayed only if needed.
+///
+/// Every fixed trip is a warning of the returned `Report`, every trip still
+/// needing a fix is listed as in `check_run_times`.
+pub fn fix_run_times(
+    collections: &mut Collections,
+    minimum_run_times: &MinimumRunTimes,
+) -> Report<RunTimeReportCategory> {
+    let mut report = Report::default();
+    let mut fixed_count = 0;
+    for vj_idx in collections.vehicle_journeys.indexes() {
+        let vehicle_journey = &collections.vehicle_journeys[vj_idx];
+        let mut vj_report = Report::default();
+        if check_stop_times(vehicle_journey, &mut vj_report) {
+            continue;
+        }
+        match minimum_run_times.minimum_run_time(&vehicle_journey.physical_mode_id) {
+            Some(minimum_run_time) => {
+                let mut vehicle_journey = collections.vehicle_journeys.index_mut(vj_idx);
+                fix_stop_times(&mut vehicle_journey.stop_times, minimum_run_time);
+                report.add_entry(
+                    Severity::Warning,
+                    ReportEntry::new(
+                        RunTimeReportCategory::RunTimeFixed,
+                        format!(
+                            "stop times of vehicle journey fixed with a minimum run time of {} second(s)",
+                            minimum_run_time
+                        ),
+                    )
+                    .with_object_id(vehicle_journey.id.clone()),
+                );
+                fixed_count += 1;
+            }
+            None => report.append(&mut vj_report),
+        }
+    }
+    info!("{} vehicle journey(s) fixed", fixed_count);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn times(collections: &Collections) -> Vec<(String, String)> {
+        collections
+            .vehicle_journeys
+            .get("vj1")
+            .unwrap()
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                (
+                    stop_time.arrival_time.to_string(),
+                    stop_time.departure_time.to_string(),
+                )
+            })
+            .collect()
+    }
+
+    // A vehicle journey with a run time of 0 second from 'A' to 'B' and a
+    // departure before the arrival at 'C'
+    fn zero_and_negative_run_times() -> Collections {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "10:01:00", "10:01:00")
+                    .st("C", "10:01:30", "10:02:00");
+            })
+            .build_model()
+            .into_collections();
+        // stop times in chronological order are needed to build the model
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .stop_times[2]
+            .departure_time = Time::new(10, 1, 0);
+        collections
+    }
+
+    #[test]
+    fn detect_run_times() {
+        let collections = zero_and_negative_run_times();
+        let report = check_run_times(&collections);
+
+        assert_eq!(1, report.errors().len());
+        assert_eq!(
+            RunTimeReportCategory::DepartureBeforeArrival,
+            report.errors()[0].category
+        );
+        assert_eq!(1, report.warnings().len());
+        assert_eq!(
+            "vehicle journey vj1 runs in 0 second from sequence 0 to sequence 1",
+            report.warnings()[0].message
+        );
+    }
+
+    #[test]
+    fn fix_with_minimum_run_time() {
+        let mut collections = zero_and_negative_run_times();
+        let minimum_run_times = MinimumRunTimes {
+            default: None,
+            by_physical_mode: vec![("default_physical_mode".to_string(), 60)]
+                .into_iter()
+                .collect(),
+        };
+        let report = fix_run_times(&mut collections, &minimum_run_times);
+
+        assert!(report.errors().is_empty());
+        assert_eq!(1, report.warnings().len());
+        assert_eq!(
+            vec![
+                ("10:00:00".to_string(), "10:01:00".to_string()),
+                ("10:02:00".to_string(), "10:02:00".to_string()),
+                ("10:03:00".to_string(), "10:03:00".to_string()),
+            ],
+            times(&collections)
+        );
+        assert!(check_run_times(&collections).is_empty());
+    }
+
+    #[test]
+    fn no_fix_without_minimum_run_time() {
+        let mut collections = zero_and_negative_run_times();
+        let report = fix_run_times(&mut collections, &MinimumRunTimes::default());
+
+        assert_eq!(1, report.errors().len());
+        assert_eq!(1, report.warnings().len());
+        assert_eq!("10:01:00", times(&collections)[2].1);
+    }
+}
@@ -0,0 +1,446 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Snapshot of a whole `Model` in a single file, used as a cache between the
+//! steps of a processing pipeline to avoid parsing the NTFS or GTFS files
+//! again.
+//!
+//! The objects are serialized with their usual serde implementation, the
+//! fields which are not part of it (codes, object properties, comment links,
+//! stop times, ...) being stored aside. The serde implementations of the
+//! objects are designed for CSV files and are not symmetric for
+//! non-self-describing formats, so the snapshot is always a JSON document,
+//! optionally compressed in a zip archive.
+
+use crate::{
+    model::{Collections, Model},
+    objects::{
+        CommentLinksT, Date, KeysValues, PropertiesMap, StopTime, StopTimePrecision, StopType, Time,
+    },
+    Result,
+};
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Id};
+
+const SNAPSHOT_FILE_NAME: &str = "snapshot.json";
+const SNAPSHOT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Format of the snapshot file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// A JSON document
+    #[default]
+    Json,
+    /// A JSON document compressed in a zip archive
+    ZippedJson,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotStopTime {
+    stop_point_id: String,
+    sequence: u32,
+    arrival_time: Time,
+    departure_time: Time,
+    boarding_duration: u16,
+    alighting_duration: u16,
+    pickup_type: u8,
+    drop_off_type: u8,
+    local_zone_id: Option<u16>,
+    precision: Option<StopTimePrecision>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ObjectFields {
+    #[serde(default)]
+    codes: BTreeMap<String, KeysValues>,
+    #[serde(default)]
+    object_properties: BTreeMap<String, PropertiesMap>,
+    #[serde(default)]
+    comment_links: BTreeMap<String, CommentLinksT>,
+}
+
+// Fields of the objects which are not serialized with the objects themselves
+#[derive(Default, Serialize, Deserialize)]
+struct SkippedFields {
+    networks: ObjectFields,
+    lines: ObjectFields,
+    routes: ObjectFields,
+    vehicle_journeys: ObjectFields,
+    stop_areas: ObjectFields,
+    stop_points: ObjectFields,
+    stop_locations: ObjectFields,
+    companies: ObjectFields,
+    stop_times: BTreeMap<String, Vec<SnapshotStopTime>>,
+    calendar_dates: BTreeMap<String, Vec<String>>,
+    stop_point_types: BTreeMap<String, StopType>,
+    stop_location_types: BTreeMap<String, StopType>,
+    pathway_from_stop_types: BTreeMap<String, StopType>,
+    pathway_to_stop_types: BTreeMap<String, StopType>,
+    stop_time_headsigns: Vec<(String, u32, String)>,
+    stop_time_ids: Vec<(String, u32, String)>,
+    stop_time_comments: Vec<(String, u32, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<C> {
+    version: String,
+    collections: C,
+    skipped_fields: SkippedFields,
+}
+
+// Values of the field of each object, when different from the default value
+fn extract<T: Id<T>, V: Clone + Default + PartialEq>(
+    collection: &CollectionWithId<T>,
+    field: impl Fn(&T) -> &V,
+) -> BTreeMap<String, V> {
+    let default = V::default();
+    collection
+        .values()
+        .filter(|object| field(object) != &default)
+        .map(|object| (object.id().to_string(), field(object).clone()))
+        .collect()
+}
+
+fn restore<T: Id<T>, V>(
+    collection: &mut CollectionWithId<T>,
+    values: BTreeMap<String, V>,
+    field: impl Fn(&mut T) -> &mut V,
+) -> Result<()> {
+    for (id, value) in values {
+        let mut object = collection
+            .get_mut(&id)
+            .ok_or_else(|| anyhow!("object {} of the snapshot not found", id))?;
+        *field(&mut *object) = value;
+    }
+    Ok(())
+}
+
+fn sorted_stop_time_values(values: &HashMap<(String, u32), String>) -> Vec<(String, u32, String)> {
+    let mut values: Vec<_> = values
+        .iter()
+        .map(|((vj_id, sequence), value)| (vj_id.clone(), *sequence, value.clone()))
+        .collect();
+    values.sort();
+    values
+}
+
+impl SkippedFields {
+    fn from_collections(c: &Collections) -> Self {
+        macro_rules! object_fields {
+            ($collection:expr) => {
+                ObjectFields {
+                    codes: extract(&$collection, |o| &o.codes),
+                    object_properties: extract(&$collection, |o| &o.object_properties),
+                    comment_links: extract(&$collection, |o| &o.comment_links),
+                }
+            };
+        }
+        let stop_times = c
+            .vehicle_journeys
+            .values()
+            .map(|vj| {
+                let stop_times = vj
+                    .stop_times
+                    .iter()
+                    .map(|st| SnapshotStopTime {
+                        stop_point_id: c.stop_points[st.stop_point_idx].id.clone(),
+                        sequence: st.sequence,
+                        arrival_time: st.arrival_time,
+                        departure_time: st.departure_time,
+                        boarding_duration: st.boarding_duration,
+                        alighting_duration: st.alighting_duration,
+                        pickup_type: st.pickup_type,
+                        drop_off_type: st.drop_off_type,
+                        local_zone_id: st.local_zone_id,
+                        precision: st.precision.clone(),
+                    })
+                    .collect();
+                (vj.id.clone(), stop_times)
+            })
+            .collect();
+        let calendar_dates = c
+            .calendars
+            .values()
+            .map(|calendar| {
+                let dates = calendar
+                    .dates
+                    .iter()
+                    .map(|date| date.format("%Y%m%d").to_string())
+                    .collect();
+                (calendar.id.clone(), dates)
+            })
+            .collect();
+        SkippedFields {
+            networks: ObjectFields {
+                codes: extract(&c.networks, |o| &o.codes),
+                ..Default::default()
+            },
+            lines: object_fields!(c.lines),
+            routes: object_fields!(c.routes),
+            vehicle_journeys: object_fields!(c.vehicle_journeys),
+            stop_areas: object_fields!(c.stop_areas),
+            stop_points: object_fields!(c.stop_points),
+            stop_locations: ObjectFields {
+                comment_links: extract(&c.stop_locations, |o| &o.comment_links),
+                ..Default::default()
+            },
+            companies: ObjectFields {
+                codes: extract(&c.companies, |o| &o.codes),
+                ..Default::default()
+            },
+            stop_times,
+            calendar_dates,
+            stop_point_types: extract(&c.stop_points, |o| &o.stop_type),
+            stop_location_types: extract(&c.stop_locations, |o| &o.stop_type),
+            pathway_from_stop_types: extract(&c.pathways, |o| &o.from_stop_type),
+            pathway_to_stop_types: extract(&c.pathways, |o| &o.to_stop_type),
+            stop_time_headsigns: sorted_stop_time_values(&c.stop_time_headsigns),
+            stop_time_ids: sorted_stop_time_values(&c.stop_time_ids),
+            stop_time_comments: sorted_stop_time_values(&c.stop_time_comments),
+        }
+    }
+
+    fn restore(self, c: &mut Collections) -> Result<()> {
+        macro_rules! restore_object_fields {
+            ($collection:expr, $fields:expr) => {
+                restore(&mut $collection, $fields.codes, |o| &mut o.codes)?;
+                restore(&mut $collection, $fields.object_properties, |o| {
+                    &mut o.object_properties
+                })?;
+                restore(&mut $collection, $fields.comment_links, |o| {
+                    &mut o.comment_links
+                })?;
+            };
+        }
+        restore(&mut c.networks, self.networks.codes, |o| &mut o.codes)?;
+        restore_object_fields!(c.lines, self.lines);
+        restore_object_fields!(c.routes, self.routes);
+        restore_object_fields!(c.vehicle_journeys, self.vehicle_journeys);
+        restore_object_fields!(c.stop_areas, self.stop_areas);
+        restore_object_fields!(c.stop_points, self.stop_points);
+        restore(
+            &mut c.stop_locations,
+            self.stop_locations.comment_links,
+            |o| &mut o.comment_links,
+        )?;
+        restore(&mut c.companies, self.companies.codes, |o| &mut o.codes)?;
+        restore(&mut c.stop_points, self.stop_point_types, |o| {
+            &mut o.stop_type
+        })?;
+        restore(&mut c.stop_locations, self.stop_location_types, |o| {
+            &mut o.stop_type
+        })?;
+        restore(&mut c.pathways, self.pathway_from_stop_types, |o| {
+            &mut o.from_stop_type
+        })?;
+        restore(&mut c.pathways, self.pathway_to_stop_types, |o| {
+            &mut o.to_stop_type
+        })?;
+
+        let mut calendar_dates = BTreeMap::new();
+        for (calendar_id, dates) in self.calendar_dates {
+            let dates = dates
+                .iter()
+                .map(|date| Date::parse_from_str(date, "%Y%m%d"))
+                .collect::<Result<_, _>>()
+                .with_context(|| format!("invalid date in calendar {}", calendar_id))?;
+            calendar_dates.insert(calendar_id, dates);
+        }
+        restore(&mut c.calendars, calendar_dates, |o| &mut o.dates)?;
+
+        let mut stop_times = BTreeMap::new();
+        for (vj_id, snapshot_stop_times) in self.stop_times {
+            let mut vj_stop_times = Vec::with_capacity(snapshot_stop_times.len());
+            for st in snapshot_stop_times {
+                let stop_point_idx = c.stop_points.get_idx(&st.stop_point_id).ok_or_else(|| {
+                    anyhow!(
+                        "stop point {} of vehicle journey {} not found",
+                        st.stop_point_id,
+                        vj_id
+                    )
+                })?;
+                vj_stop_times.push(StopTime {
+                    stop_point_idx,
+                    sequence: st.sequence,
+                    arrival_time: st.arrival_time,
+                    departure_time: st.departure_time,
+                    boarding_duration: st.boarding_duration,
+                    alighting_duration: st.alighting_duration,
+                    pickup_type: st.pickup_type,
+                    drop_off_type: st.drop_off_type,
+                    local_zone_id: st.local_zone_id,
+                    precision: st.precision,
+                });
+            }
+            stop_times.insert(vj_id, vj_stop_times);
+        }
+        restore(&mut c.vehicle_journeys, stop_times, |o| &mut o.stop_times)?;
+
+        let into_map = |values: Vec<(String, u32, String)>| {
+            values
+                .into_iter()
+                .map(|(vj_id, sequence, value)| ((vj_id, sequence), value))
+                .collect()
+        };
+        c.stop_time_headsigns = into_map(self.stop_time_headsigns);
+        c.stop_time_ids = into_map(self.stop_time_ids);
+        c.stop_time_comments = into_map(self.stop_time_comments);
+        Ok(())
+    }
+}
+
+/// The whole content of `collections` as a JSON value, including the fields
+/// which are not serialized with the objects, e.g. to compare two states of
+/// the collections
+pub fn to_json_value(collections: &Collections) -> Result<serde_json::Value> {
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION.to_string(),
+        collections,
+        skipped_fields: SkippedFields::from_collections(collections),
+    };
+    Ok(serde_json::to_value(&snapshot)?)
+}
+
+impl Model {
+    /// Write the whole model into the single file `path`, to be read back
+    /// with [`Model::from_snapshot`] by the same version of the crate.
+    pub fn to_snapshot<P: AsRef<Path>>(&self, path: P, format: SnapshotFormat) -> Result<()> {
+        let path = path.as_ref();
+        info!("Writing snapshot {:?}", path);
+        let collections: &Collections = self;
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION.to_string(),
+            collections,
+            skipped_fields: SkippedFields::from_collections(collections),
+        };
+        let file = File::create(path).with_context(|| format!("Error creating {:?}", path))?;
+        match format {
+            SnapshotFormat::Json => {
+                let mut writer = BufWriter::new(file);
+                serde_json::to_writer(&mut writer, &snapshot)?;
+                writer.flush()?;
+            }
+            SnapshotFormat::ZippedJson => {
+                let mut zip = zip::ZipWriter::new(file);
+                let options = zip::write::FileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated);
+                zip.start_file(SNAPSHOT_FILE_NAME, options)?;
+                serde_json::to_writer(&mut zip, &snapshot)?;
+                zip.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a model from a file written by [`Model::to_snapshot`].
+    pub fn from_snapshot<P: AsRef<Path>>(path: P, format: SnapshotFormat) -> Result<Model> {
+        let path = path.as_ref();
+        info!("Reading snapshot {:?}", path);
+        let file = File::open(path).with_context(|| format!("Error reading {:?}", path))?;
+        let snapshot: Snapshot<Collections> = match format {
+            SnapshotFormat::Json => serde_json::from_reader(BufReader::new(file))?,
+            SnapshotFormat::ZippedJson => {
+                let mut archive = zip::ZipArchive::new(file)?;
+                let reader = BufReader::new(archive.by_name(SNAPSHOT_FILE_NAME)?);
+                serde_json::from_reader(reader)?
+            }
+        };
+        if snapshot.version != SNAPSHOT_VERSION {
+            bail!(
+                "snapshot {:?} has been written by version {} (expected version {})",
+                path,
+                snapshot.version,
+                SNAPSHOT_VERSION
+            );
+        }
+        let mut collections = snapshot.collections;
+        snapshot.skipped_fields.restore(&mut collections)?;
+        Model::new(collections)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn snapshot_round_trip(format: SnapshotFormat) {
+        let model = ModelBuilder::default()
+            .calendar("service1", &["2020-01-01", "2020-01-02"])
+            .vj("vj1", |vj| {
+                vj.calendar("service1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .build_model();
+        let mut collections = model.into_collections();
+        collections
+            .stop_points
+            .get_mut("A")
+            .unwrap()
+            .codes
+            .insert(("source".to_string(), "stop_a".to_string()));
+        collections
+            .stop_time_headsigns
+            .insert(("vj1".to_string(), 1), "somewhere".to_string());
+        let model = Model::new(collections).unwrap();
+
+        test_in_tmp_dir(|path| {
+            let snapshot_path = path.join("snapshot");
+            model.to_snapshot(&snapshot_path, format).unwrap();
+            let read_model = Model::from_snapshot(&snapshot_path, format).unwrap();
+            let read_vj = read_model.vehicle_journeys.get("vj1").unwrap();
+            let stop_point_ids: Vec<&str> = read_vj
+                .stop_times
+                .iter()
+                .map(|st| read_model.stop_points[st.stop_point_idx].id.as_str())
+                .collect();
+            assert_eq!(vec!["A", "B"], stop_point_ids);
+            assert_eq!(
+                model.vehicle_journeys.get("vj1").unwrap().stop_times,
+                read_vj.stop_times
+            );
+            assert_eq!(
+                model.calendars.get("service1").unwrap().dates,
+                read_model.calendars.get("service1").unwrap().dates
+            );
+            assert_eq!(
+                model.stop_points.get("A").unwrap().codes,
+                read_model.stop_points.get("A").unwrap().codes
+            );
+            assert_eq!(model.stop_time_headsigns, read_model.stop_time_headsigns);
+        });
+    }
+
+    #[test]
+    fn json_snapshot() {
+        snapshot_round_trip(SnapshotFormat::Json);
+    }
+
+    #[test]
+    fn zipped_json_snapshot() {
+        snapshot_round_trip(SnapshotFormat::ZippedJson);
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Plausibility of the speeds implied by the stop times of the trips, catching
+//! wrong coordinates of stops or wrong schedules.
+
+use crate::{model::*, report::Report};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Categories of the `Report` produced by the speed checks
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedReportCategory {
+    /// The speed between two consecutive stops exceeds the threshold of the
+    /// physical mode of the trip
+    ExcessiveSpeed,
+}
+
+/// Maximum plausible speeds, in km/h
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SpeedThresholds {
+    /// Threshold of the physical modes absent from `by_physical_mode`
+    pub default: f64,
+    /// Threshold by physical mode identifier
+    pub by_physical_mode: HashMap<String, f64>,
+}
+
+impl Default for SpeedThresholds {
+    fn default() -> Self {
+        let by_physical_mode = vec![
+            (AIR_PHYSICAL_MODE, 1000.),
+            (BIKE_PHYSICAL_MODE, 40.),
+            (BUS_PHYSICAL_MODE, 110.),
+            (BUS_RAPID_TRANSIT_PHYSICAL_MODE, 110.),
+            (CAR_PHYSICAL_MODE, 150.),
+            (COACH_PHYSICAL_MODE, 130.),
+            (FERRY_PHYSICAL_MODE, 80.),
+            (FUNICULAR_PHYSICAL_MODE, 50.),
+            (LOCAL_TRAIN_PHYSICAL_MODE, 200.),
+            (LONG_DISTANCE_TRAIN_PHYSICAL_MODE, 360.),
+            (METRO_PHYSICAL_MODE, 110.),
+            (RAPID_TRANSIT_PHYSICAL_MODE, 200.),
+            (TAXI_PHYSICAL_MODE, 150.),
+            (TRAIN_PHYSICAL_MODE, 360.),
+            (TRAMWAY_PHYSICAL_MODE, 90.),
+        ]
+        .into_iter()
+        .map(|(physical_mode_id, threshold)| (physical_mode_id.to_string(), threshold))
+        .collect();
+        SpeedThresholds {
+            default: 360.,
+            by_physical_mode,
+        }
+    }
+}
+
+impl SpeedThresholds {
+    /// Threshold of the physical mode `physical_mode_id`, in km/h
+    pub fn threshold(&self, physical_mode_id: &str) -> f64 {
+        self.by_physical_mode
+            .get(physical_mode_id)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Check the speed of every trip of `collections` between each pair of
+/// consecutive stops.
+///
+/// The speed is the distance as the crow flies between the coordinates of the
+/// stop points divided by the time between the departure from the first one
+/// and the arrival at the second one. The distances along the shapes are not
+/// kept in the model, so the computed speed is a lower bound of the actual
+/// one. The segments without running time are ignored.
+///
+/// Every segment faster than the threshold of the physical mode of its trip
+/// is a warning of the returned `Report`.
+pub fn check_speeds(
+    collections: &Collections,
+    thresholds: &SpeedThresholds,
+) -> Report<SpeedReportCategory> {
+    let mut report = Report::default();
+    for vehicle_journey in collections.vehicle_journeys.values() {
+        let threshold = thresholds.threshold(&vehicle_journey.physical_mode_id);
+        for (from, to) in vehicle_journey
+            .stop_times
+            .iter()
+            .zip(vehicle_journey.stop_times.iter().skip(1))
+        {
+            if to.arrival_time <= from.departure_time {
+                continue;
+            }
+            let duration = (to.arrival_time - from.departure_time).total_seconds();
+            let from_stop_point = &collections.stop_points[from.stop_point_idx];
+            let to_stop_point = &collections.stop_points[to.stop_point_idx];
+            let distance = from_stop_point.coord.distance_to(&to_stop_point.coord);
+            // m/s to km/h
+            let speed = distance / f64::from(duration) * 3.6;
+            if speed > threshold {
+                report.add_warning(
+                    format!(
+                        "vehicle journey {} runs at {:.0} km/h from stop point {} (sequence {}) to stop point {} (sequence {}), above the {} km/h of physical mode {}",
+                        vehicle_journey.id,
+                        speed,
+                        from_stop_point.id,
+                        from.sequence,
+                        to_stop_point.id,
+                        to.sequence,
+                        threshold,
+                        vehicle_journey.physical_mode_id
+                    ),
+                    SpeedReportCategory::ExcessiveSpeed,
+                );
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Coord;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    // A vehicle journey running 1.1 km, then 4.4 km, in 1 minute each
+    fn one_minute_runs(physical_mode_id: &str) -> Collections {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:00:00")
+                    .st("B", "10:01:00", "10:01:00")
+                    .st("C", "10:02:00", "10:02:00");
+            })
+            .build_model()
+            .into_collections();
+        for (stop_point_id, lon) in vec![("A", 0.), ("B", 0.01), ("C", 0.05)] {
+            let mut stop_point = collections.stop_points.get_mut(stop_point_id).unwrap();
+            stop_point.coord = Coord { lon, lat: 0. };
+        }
+        collections
+            .vehicle_journeys
+            .get_mut("vj1")
+            .unwrap()
+            .physical_mode_id = physical_mode_id.to_string();
+        collections
+    }
+
+    #[test]
+    fn excessive_bus_speed() {
+        let collections = one_minute_runs(BUS_PHYSICAL_MODE);
+        let report = check_speeds(&collections, &SpeedThresholds::default());
+
+        assert_eq!(1, report.warnings().len());
+        assert_eq!(
+            "vehicle journey vj1 runs at 267 km/h from stop point B (sequence 1) to stop point C (sequence 2), above the 110 km/h of physical mode Bus",
+            report.warnings()[0].message
+        );
+    }
+
+    #[test]
+    fn plausible_train_speed() {
+        let collections = one_minute_runs(TRAIN_PHYSICAL_MODE);
+        let report = check_speeds(&collections, &SpeedThresholds::default());
+
+        assert!(report.is_empty());
+    }
+}
@@ -0,0 +1,700 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Experimental reading of the airline schedules in the
+//! [IATA SSIM](https://www.iata.org/en/publications/manuals/standard-schedules-information/)
+//! format (chapter 7, fixed-width records of 200 characters).
+//!
+//! Each flight (airline designator, flight number and itinerary variation)
+//! of a passenger service becomes a vehicle journey of the `Air` physical
+//! mode, its legs (type 3 records) being chained at the airports. The lines
+//! are the flight numbers and the networks and the companies the airlines.
+//!
+//! The SSIM times are local to each airport: they are converted into the
+//! timezone of the networks with the UTC variation of the records and the
+//! offset of the networks' timezone at the beginning of the period of
+//! operation. The SSIM files don't describe the airports, their names,
+//! coordinates and timezones can be given in the `Configuration`.
+
+use crate::{
+    model::{Collections, Model},
+    objects::{
+        Calendar, CommercialMode, Company, Contributor, Coord, Dataset, Date, Line, Network,
+        PhysicalMode, Route, StopArea, StopPoint, StopTime, Time, VehicleJourney,
+    },
+    validity_period, AddPrefix, PrefixConfiguration, Result,
+};
+use anyhow::{anyhow, bail, Context};
+use chrono::{Datelike, Duration, Offset, TimeZone};
+use chrono_tz::Tz;
+use derivative::Derivative;
+use skip_error::skip_error_and_warn;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+use tracing::{info, warn};
+use typed_index_collection::CollectionWithId;
+
+const AIR_MODE: &str = "Air";
+/// Service types (SSIM appendix C) of the flights carrying passengers
+const PASSENGER_SERVICE_TYPES: &[char] = &['J', 'S', 'U', 'Q', 'G', 'B', 'C', 'O'];
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Description of an airport, missing from the SSIM files
+#[derive(Debug, Clone, Default)]
+pub struct Airport {
+    /// Name of the airport
+    pub name: String,
+    /// Coordinates of the airport
+    pub coord: Coord,
+    /// Timezone of the airport
+    pub timezone: Option<Tz>,
+}
+
+/// Parameters of the SSIM reading
+#[derive(Derivative)]
+#[derivative(Default)]
+pub struct Configuration {
+    /// The Contributor providing the Dataset
+    pub contributor: Contributor,
+    /// Describe the Dataset being parsed
+    pub dataset: Dataset,
+    /// Additional key-values for the 'feed_infos.txt'
+    pub feed_infos: BTreeMap<String, String>,
+    /// used to prefix objects
+    pub prefix_conf: Option<PrefixConfiguration>,
+    /// Timezone of the networks, in which the times of the flights are
+    /// expressed
+    #[derivative(Default(value = "chrono_tz::UTC"))]
+    pub timezone: Tz,
+    /// Airports by IATA code. The airports not found are created with their
+    /// code as name and without coordinates.
+    pub airports: BTreeMap<String, Airport>,
+}
+
+/// A flight leg (type 3 record)
+#[derive(Debug)]
+struct Leg {
+    airline: String,
+    flight_number: u32,
+    operational_suffix: String,
+    itinerary_variation: String,
+    leg_sequence: u32,
+    service_type: char,
+    period_from: Date,
+    period_to: Date,
+    days_of_operation: BTreeSet<u32>,
+    departure_station: String,
+    departure_time: i64,
+    departure_utc_variation: i64,
+    departure_date_variation: i64,
+    arrival_station: String,
+    arrival_time: i64,
+    arrival_utc_variation: i64,
+    arrival_date_variation: i64,
+}
+
+/// Returns the field at the 1-based positions `start` to `end` (included),
+/// trimmed
+fn field(record: &str, start: usize, end: usize) -> &str {
+    let end = end.min(record.len());
+    record.get(start - 1..end).unwrap_or("").trim()
+}
+
+/// Parses a time written as `HHMM` into seconds
+fn parse_time(value: &str) -> Result<i64> {
+    if value.len() != 4 || !value.bytes().all(|b| b.is_ascii_digit()) {
+        bail!("invalid time {:?}", value);
+    }
+    let hours: i64 = value[..2].parse()?;
+    let minutes: i64 = value[2..].parse()?;
+    Ok(hours * 3600 + minutes * 60)
+}
+
+/// Parses a UTC/local time variation written as `±HHMM` into seconds
+fn parse_utc_variation(value: &str) -> Result<i64> {
+    let (sign, time) = match value.split_at(value.len().min(1)) {
+        ("+", time) => (1, time),
+        ("-", time) => (-1, time),
+        _ => bail!("invalid UTC variation {:?}", value),
+    };
+    Ok(sign * parse_time(time).with_context(|| format!("invalid UTC variation {:?}", value))?)
+}
+
+/// Parses a date variation, in days: a digit or `A` for the day before
+fn parse_date_variation(value: &str) -> Result<i64> {
+    match value {
+        "" => Ok(0),
+        "A" => Ok(-1),
+        _ => value
+            .parse()
+            .map_err(|_| anyhow!("invalid date variation {:?}", value)),
+    }
+}
+
+/// Parses a date written as `DDMMMYY` (e.g. `01JAN24`)
+fn parse_date(value: &str) -> Result<Date> {
+    Date::parse_from_str(value, "%d%b%y").with_context(|| format!("invalid date {:?}", value))
+}
+
+fn parse_leg(record: &str) -> Result<Leg> {
+    let days_of_operation = field(record, 29, 35)
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .filter(|day| (1..=7).contains(day))
+        .collect();
+    Ok(Leg {
+        airline: field(record, 3, 5).to_string(),
+        flight_number: field(record, 6, 9)
+            .parse()
+            .with_context(|| format!("invalid flight number {:?}", field(record, 6, 9)))?,
+        operational_suffix: field(record, 2, 2).to_string(),
+        itinerary_variation: field(record, 10, 11).to_string(),
+        leg_sequence: field(record, 12, 13)
+            .parse()
+            .with_context(|| format!("invalid leg sequence {:?}", field(record, 12, 13)))?,
+        service_type: field(record, 14, 14).chars().next().unwrap_or(' '),
+        period_from: parse_date(field(record, 15, 21))?,
+        period_to: parse_date(field(record, 22, 28))?,
+        days_of_operation,
+        departure_station: field(record, 37, 39).to_string(),
+        departure_time: parse_time(field(record, 40, 43))?,
+        departure_utc_variation: parse_utc_variation(field(record, 48, 52))?,
+        departure_date_variation: parse_date_variation(field(record, 193, 193))?,
+        arrival_station: field(record, 55, 57).to_string(),
+        arrival_time: parse_time(field(record, 62, 65))?,
+        arrival_utc_variation: parse_utc_variation(field(record, 66, 70))?,
+        arrival_date_variation: parse_date_variation(field(record, 194, 194))?,
+    })
+}
+
+/// Reads the passenger flight legs of the SSIM `content`, grouped by flight
+/// and sorted by leg sequence
+fn read_legs(content: &str) -> Result<BTreeMap<(String, u32, String, String), Vec<Leg>>> {
+    let mut flights: BTreeMap<_, Vec<Leg>> = BTreeMap::new();
+    for (line_idx, record) in content.lines().enumerate() {
+        if !record.starts_with('3') {
+            continue;
+        }
+        let leg = parse_leg(record).with_context(|| format!("at line {}", line_idx + 1))?;
+        if !PASSENGER_SERVICE_TYPES.contains(&leg.service_type) {
+            continue;
+        }
+        let key = (
+            leg.airline.clone(),
+            leg.flight_number,
+            leg.operational_suffix.clone(),
+            leg.itinerary_variation.clone(),
+        );
+        flights.entry(key).or_default().push(leg);
+    }
+    for legs in flights.values_mut() {
+        legs.sort_by_key(|leg| leg.leg_sequence);
+    }
+    Ok(flights)
+}
+
+/// Computes the calls of a flight (station, arrival and departure in
+/// seconds) in the timezone of the networks, and the shift in days to apply
+/// to its days of operation for its first departure to be on the first day
+fn compute_calls(legs: &[Leg], timezone: &Tz) -> Result<(Vec<(&str, i64, i64)>, i64)> {
+    let first_leg = &legs[0];
+    let offset = i64::from(
+        timezone
+            .offset_from_utc_date(&first_leg.period_from)
+            .fix()
+            .local_minus_utc(),
+    );
+    let to_network_time = |time: i64, utc_variation: i64, date_variation: i64| {
+        date_variation * SECONDS_PER_DAY + time - utc_variation + offset
+    };
+    let mut calls = Vec::new();
+    let mut arrival = None;
+    for (leg_idx, leg) in legs.iter().enumerate() {
+        if leg_idx > 0 && legs[leg_idx - 1].arrival_station != leg.departure_station {
+            bail!(
+                "leg {} departs from {} instead of {}",
+                leg.leg_sequence,
+                leg.departure_station,
+                legs[leg_idx - 1].arrival_station
+            );
+        }
+        let departure = to_network_time(
+            leg.departure_time,
+            leg.departure_utc_variation,
+            leg.departure_date_variation,
+        );
+        calls.push((
+            leg.departure_station.as_str(),
+            arrival.unwrap_or(departure),
+            departure,
+        ));
+        arrival = Some(to_network_time(
+            leg.arrival_time,
+            leg.arrival_utc_variation,
+            leg.arrival_date_variation,
+        ));
+    }
+    let last_arrival = arrival.ok_or_else(|| anyhow!("no leg"))?;
+    let last_station = legs[legs.len() - 1].arrival_station.as_str();
+    calls.push((last_station, last_arrival, last_arrival));
+    if calls.windows(2).any(|w| w[0].1 > w[0].2 || w[0].2 > w[1].1) {
+        bail!("the times of the legs are not increasing");
+    }
+
+    let day_shift = calls[0].2.div_euclid(SECONDS_PER_DAY);
+    for (_, arrival, departure) in calls.iter_mut() {
+        *arrival -= day_shift * SECONDS_PER_DAY;
+        *departure -= day_shift * SECONDS_PER_DAY;
+    }
+    Ok((calls, day_shift))
+}
+
+/// Returns the days of operation of a flight, shifted by `day_shift` days
+fn operating_dates(leg: &Leg, day_shift: i64) -> BTreeSet<Date> {
+    leg.period_from
+        .iter_days()
+        .take_while(|date| *date <= leg.period_to)
+        .filter(|date| {
+            leg.days_of_operation
+                .contains(&date.weekday().number_from_monday())
+        })
+        .map(|date| date + Duration::days(day_shift))
+        .collect()
+}
+
+fn get_stop_point(code: &str, airports: &BTreeMap<String, Airport>) -> StopPoint {
+    let airport = airports.get(code);
+    StopPoint {
+        id: code.to_string(),
+        name: airport
+            .map(|airport| airport.name.clone())
+            .unwrap_or_else(|| code.to_string()),
+        code: Some(code.to_string()),
+        visible: true,
+        coord: airport.map(|airport| airport.coord).unwrap_or_default(),
+        stop_area_id: StopArea::from(StopPoint {
+            id: code.to_string(),
+            ..Default::default()
+        })
+        .id,
+        timezone: airport.and_then(|airport| airport.timezone),
+        ..Default::default()
+    }
+}
+
+fn read_content(content: &str, configuration: Configuration) -> Result<Collections> {
+    let Configuration {
+        contributor,
+        mut dataset,
+        feed_infos,
+        prefix_conf,
+        timezone,
+        airports,
+    } = configuration;
+
+    let mut collections = Collections::default();
+    let mut lines = BTreeMap::new();
+    let mut routes = BTreeMap::new();
+    let mut airlines = BTreeSet::new();
+    let mut calendars = Vec::new();
+    let mut vehicle_journeys = Vec::new();
+    for ((airline, flight_number, suffix, itinerary_variation), legs) in read_legs(content)? {
+        let line_id = format!("{}{}{}", airline, flight_number, suffix);
+        let vj_id = format!("{}:{}", line_id, itinerary_variation);
+        let (calls, day_shift) =
+            skip_error_and_warn!(compute_calls(&legs, &timezone)
+                .with_context(|| format!("flight {} ignored", vj_id)));
+        let dates = operating_dates(&legs[0], day_shift);
+        if dates.is_empty() {
+            warn!("flight {} ignored: no day of operation", vj_id);
+            continue;
+        }
+
+        let mut stop_times = Vec::new();
+        for (sequence, (station, arrival, departure)) in calls.iter().enumerate() {
+            let stop_point_idx = match collections.stop_points.get_idx(station) {
+                Some(idx) => idx,
+                None => collections
+                    .stop_points
+                    .push(get_stop_point(station, &airports))?,
+            };
+            stop_times.push(StopTime {
+                stop_point_idx,
+                sequence: sequence as u32,
+                arrival_time: Time::new(0, 0, *arrival as u32),
+                departure_time: Time::new(0, 0, *departure as u32),
+                boarding_duration: 0,
+                alighting_duration: 0,
+                pickup_type: 0,
+                drop_off_type: 0,
+                local_zone_id: None,
+                precision: None,
+            });
+        }
+        let origin = &collections.stop_points[stop_times[0].stop_point_idx];
+        let destination = &collections.stop_points[stop_times[stop_times.len() - 1].stop_point_idx];
+        let route_id = format!("{}:{}:{}", line_id, origin.id, destination.id);
+        routes.entry(route_id.clone()).or_insert_with(|| Route {
+            id: route_id.clone(),
+            name: format!("{} - {}", origin.name, destination.name),
+            direction_type: Some("forward".to_string()),
+            line_id: line_id.clone(),
+            destination_id: Some(destination.stop_area_id.clone()),
+            ..Default::default()
+        });
+        lines.entry(line_id.clone()).or_insert_with(|| Line {
+            id: line_id.clone(),
+            code: Some(line_id.clone()),
+            name: format!("{} {}{}", airline, flight_number, suffix),
+            network_id: airline.clone(),
+            commercial_mode_id: AIR_MODE.to_string(),
+            ..Default::default()
+        });
+        airlines.insert(airline.clone());
+        calendars.push(Calendar {
+            id: vj_id.clone(),
+            dates,
+        });
+        vehicle_journeys.push(VehicleJourney {
+            id: vj_id.clone(),
+            route_id,
+            physical_mode_id: AIR_MODE.to_string(),
+            dataset_id: dataset.id.clone(),
+            service_id: vj_id,
+            headsign: Some(line_id),
+            company_id: airline,
+            stop_times,
+            ..Default::default()
+        });
+    }
+
+    collections.calendars = CollectionWithId::new(calendars)?;
+    validity_period::compute_dataset_validity_period(&mut dataset, &collections.calendars)?;
+    collections.contributors = CollectionWithId::from(contributor);
+    collections.datasets = CollectionWithId::from(dataset);
+    collections.feed_infos = feed_infos.into();
+    collections.networks = CollectionWithId::new(
+        airlines
+            .iter()
+            .map(|airline| Network {
+                id: airline.clone(),
+                name: airline.clone(),
+                timezone: Some(timezone),
+                ..Default::default()
+            })
+            .collect(),
+    )?;
+    collections.companies = CollectionWithId::new(
+        airlines
+            .iter()
+            .map(|airline| Company {
+                id: airline.clone(),
+                name: airline.clone(),
+                ..Default::default()
+            })
+            .collect(),
+    )?;
+    collections.commercial_modes = CollectionWithId::from(CommercialMode {
+        id: AIR_MODE.to_string(),
+        name: "Airplane".to_string(),
+    });
+    collections.physical_modes = CollectionWithId::from(PhysicalMode {
+        id: AIR_MODE.to_string(),
+        name: AIR_MODE.to_string(),
+        co2_emission: None,
+    });
+    collections.stop_areas = CollectionWithId::new(
+        collections
+            .stop_points
+            .values()
+            .map(|stop_point| StopArea::from(stop_point.clone()))
+            .collect(),
+    )?;
+    collections.lines = CollectionWithId::new(lines.into_values().collect())?;
+    collections.routes = CollectionWithId::new(routes.into_values().collect())?;
+    collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
+
+    if let Some(prefix_conf) = prefix_conf {
+        collections.prefix(&prefix_conf);
+    }
+    collections.calendar_deduplication();
+    Ok(collections)
+}
+
+/// Imports `Collections` from the SSIM file `path`.
+pub fn read_collections<P: AsRef<Path>>(
+    path: P,
+    configuration: Configuration,
+) -> Result<Collections> {
+    let path = path.as_ref();
+    info!("Reading SSIM from {:?}", path);
+    let content = fs::read_to_string(path).with_context(|| format!("Error reading {:?}", path))?;
+    read_content(&content, configuration).with_context(|| format!("Error reading {:?}", path))
+}
+
+/// Imports a `Model` from the SSIM file `path`.
+///
+/// The `Configuration` gives the contributor and the dataset of the data, the
+/// timezone of the networks and the description of the airports.
+pub fn read<P: AsRef<Path>>(path: P, configuration: Configuration) -> Result<Model> {
+    Model::new(read_collections(path, configuration)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{create_file_with_content, test_in_tmp_dir};
+    use pretty_assertions::assert_eq;
+
+    /// Builds a record of 200 characters with the `fields` at their 1-based
+    /// positions
+    fn record(fields: &[(usize, &str)]) -> String {
+        let mut record = vec![b' '; 200];
+        for (position, value) in fields {
+            record[position - 1..position - 1 + value.len()].copy_from_slice(value.as_bytes());
+        }
+        String::from_utf8(record).unwrap()
+    }
+
+    fn leg(
+        flight: &str,
+        leg_sequence: &str,
+        service_type: &str,
+        days: &str,
+        departure: (&str, &str, &str),
+        arrival: (&str, &str, &str),
+        date_variations: &str,
+    ) -> String {
+        record(&[
+            (1, "3"),
+            (3, "AF"),
+            (6, flight),
+            (10, "01"),
+            (12, leg_sequence),
+            (14, service_type),
+            (15, "01JAN24"),
+            (22, "14JAN24"),
+            (29, days),
+            (37, departure.0),
+            (40, departure.1),
+            (44, departure.1),
+            (48, departure.2),
+            (55, arrival.0),
+            (58, arrival.1),
+            (62, arrival.1),
+            (66, arrival.2),
+            (193, date_variations),
+        ])
+    }
+
+    #[test]
+    fn parse_fields() {
+        assert_eq!(8 * 3600 + 35 * 60, parse_time("0835").unwrap());
+        assert!(parse_time("8:35").is_err());
+        assert_eq!(-5 * 3600, parse_utc_variation("-0500").unwrap());
+        assert_eq!(3600 + 1800, parse_utc_variation("+0130").unwrap());
+        assert!(parse_utc_variation("0100").is_err());
+        assert_eq!(-1, parse_date_variation("A").unwrap());
+        assert_eq!(1, parse_date_variation("1").unwrap());
+        assert_eq!(0, parse_date_variation("").unwrap());
+        assert_eq!(
+            Date::from_ymd_opt(2024, 2, 29).unwrap(),
+            parse_date("29FEB24").unwrap()
+        );
+        assert_eq!("AF", field("3 AF 0010", 3, 5));
+        assert_eq!("", field("3 AF", 37, 39));
+    }
+
+    #[test]
+    fn read_ssim() {
+        let content = [
+            record(&[(1, "1AIRLINE STANDARD SCHEDULE DATA SET")]),
+            record(&[(1, "2UAF")]),
+            // Paris - Nice - Ajaccio, every day except on Sunday
+            leg(
+                "0010",
+                "01",
+                "J",
+                "123456 ",
+                ("ORY", "0700", "+0100"),
+                ("NCE", "0825", "+0100"),
+                "00",
+            ),
+            leg(
+                "0010",
+                "02",
+                "J",
+                "123456 ",
+                ("NCE", "0900", "+0100"),
+                ("AJA", "0950", "+0100"),
+                "00",
+            ),
+            // New York - Paris overnight, on Monday
+            leg(
+                "0023",
+                "01",
+                "J",
+                "1      ",
+                ("JFK", "2200", "-0500"),
+                ("CDG", "1130", "+0100"),
+                "01",
+            ),
+            // cargo flight, ignored
+            leg(
+                "6000",
+                "01",
+                "F",
+                "1234567",
+                ("CDG", "0100", "+0100"),
+                ("LYS", "0200", "+0100"),
+                "00",
+            ),
+            record(&[(1, "5 AF")]),
+        ]
+        .join("\n");
+        test_in_tmp_dir(|path| {
+            create_file_with_content(path, "schedule.ssim", &content);
+            let mut configuration = Configuration {
+                timezone: chrono_tz::Europe::Paris,
+                ..Default::default()
+            };
+            configuration.airports.insert(
+                "ORY".to_string(),
+                Airport {
+                    name: "Paris Orly".to_string(),
+                    coord: Coord {
+                        lon: 2.3652,
+                        lat: 48.7262,
+                    },
+                    timezone: Some(chrono_tz::Europe::Paris),
+                },
+            );
+            let model = read(path.join("schedule.ssim"), configuration).unwrap();
+
+            assert_eq!(
+                vec!["AF10:01", "AF23:01"],
+                model
+                    .vehicle_journeys
+                    .values()
+                    .map(|vj| vj.id.as_str())
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                vec!["AF"],
+                model
+                    .networks
+                    .values()
+                    .map(|n| n.id.as_str())
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                vec!["Air"],
+                model
+                    .physical_modes
+                    .values()
+                    .map(|m| m.id.as_str())
+                    .collect::<Vec<_>>()
+            );
+            let orly = model.stop_points.get("ORY").unwrap();
+            assert_eq!("Paris Orly", orly.name);
+            assert_eq!("Navitia:ORY", orly.stop_area_id);
+            assert_eq!("NCE", model.stop_points.get("NCE").unwrap().name);
+
+            let times = |vj_id: &str| {
+                model
+                    .vehicle_journeys
+                    .get(vj_id)
+                    .unwrap()
+                    .stop_times
+                    .iter()
+                    .map(|st| {
+                        (
+                            model.stop_points[st.stop_point_idx].id.as_str(),
+                            st.arrival_time,
+                            st.departure_time,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                vec![
+                    ("ORY", Time::new(7, 0, 0), Time::new(7, 0, 0)),
+                    ("NCE", Time::new(8, 25, 0), Time::new(9, 0, 0)),
+                    ("AJA", Time::new(9, 50, 0), Time::new(9, 50, 0)),
+                ],
+                times("AF10:01")
+            );
+            // 22:00 in New York is 04:00 in Paris, the day after
+            assert_eq!(
+                vec![
+                    ("JFK", Time::new(4, 0, 0), Time::new(4, 0, 0)),
+                    ("CDG", Time::new(11, 30, 0), Time::new(11, 30, 0)),
+                ],
+                times("AF23:01")
+            );
+
+            let route = model.routes.get("AF10:ORY:AJA").unwrap();
+            assert_eq!("Paris Orly - AJA", route.name);
+            assert_eq!(Some("Navitia:AJA".to_string()), route.destination_id);
+            let calendar = model
+                .calendars
+                .get(&model.vehicle_journeys.get("AF10:01").unwrap().service_id)
+                .unwrap();
+            assert_eq!(12, calendar.dates.len());
+            let calendar = model
+                .calendars
+                .get(&model.vehicle_journeys.get("AF23:01").unwrap().service_id)
+                .unwrap();
+            assert_eq!(
+                vec![
+                    Date::from_ymd_opt(2024, 1, 2).unwrap(),
+                    Date::from_ymd_opt(2024, 1, 9).unwrap()
+                ],
+                calendar.dates.iter().copied().collect::<Vec<_>>()
+            );
+        });
+    }
+
+    #[test]
+    fn flight_with_disconnected_legs() {
+        let legs = [
+            leg(
+                "0010",
+                "01",
+                "J",
+                "1234567",
+                ("ORY", "0700", "+0100"),
+                ("NCE", "0825", "+0100"),
+                "00",
+            ),
+            leg(
+                "0010",
+                "02",
+                "J",
+                "1234567",
+                ("MRS", "0900", "+0100"),
+                ("AJA", "0950", "+0100"),
+                "00",
+            ),
+        ]
+        .iter()
+        .map(|record| parse_leg(record).unwrap())
+        .collect::<Vec<_>>();
+        let error = compute_calls(&legs, &chrono_tz::UTC).unwrap_err();
+        assert_eq!("leg 2 departs from MRS instead of NCE", error.to_string());
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Production metadata stamped on the written datasets (the run of the
+//! pipeline, the versions of the source feeds and the date of the
+//! conversion), in the `feed_infos.txt` of a NTFS and in a JSON sidecar file.
+
+use crate::{objects::FeedInfos, Result};
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::{collections::BTreeMap, fs::File, path::Path};
+use tracing::info;
+
+/// Name of the JSON sidecar file in the directory of the dataset
+pub const STAMP_FILE: &str = "stamp.json";
+/// Key of the identifier of the run of the pipeline in the feed infos
+pub const RUN_ID_KEY: &str = "production_run_id";
+/// Key of the date and time of the conversion (RFC 3339) in the feed infos
+pub const CONVERSION_DATETIME_KEY: &str = "production_datetime";
+/// Prefix of the keys of the versions of the source feeds in the feed infos
+pub const SOURCE_VERSION_KEY_PREFIX: &str = "source_version:";
+
+/// Production metadata of a written dataset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamp {
+    run_id: Option<String>,
+    source_versions: BTreeMap<String, String>,
+    conversion_datetime: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+struct StampFile<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<&'a str>,
+    source_versions: &'a BTreeMap<String, String>,
+    conversion_datetime: String,
+}
+
+impl Stamp {
+    /// Create a new `Stamp` of a conversion done at `conversion_datetime`.
+    pub fn new(conversion_datetime: DateTime<FixedOffset>) -> Self {
+        Stamp {
+            run_id: None,
+            source_versions: BTreeMap::new(),
+            conversion_datetime,
+        }
+    }
+    /// Setup the identifier of the run of the pipeline.
+    pub fn run_id<S: Into<String>>(self, run_id: S) -> Self {
+        Stamp {
+            run_id: Some(run_id.into()),
+            ..self
+        }
+    }
+    /// Add the version of the source feed `feed`.
+    pub fn source_version<S: Into<String>, V: Into<String>>(mut self, feed: S, version: V) -> Self {
+        self.source_versions.insert(feed.into(), version.into());
+        self
+    }
+
+    /// Add the metadata to `feed_infos`
+    pub fn stamp_feed_infos(&self, feed_infos: &mut FeedInfos) {
+        if let Some(run_id) = &self.run_id {
+            feed_infos.insert(RUN_ID_KEY.to_string(), run_id.clone());
+        }
+        feed_infos.insert(
+            CONVERSION_DATETIME_KEY.to_string(),
+            self.conversion_datetime.to_rfc3339(),
+        );
+        for (feed, version) in &self.source_versions {
+            feed_infos.insert(
+                format!("{}{}", SOURCE_VERSION_KEY_PREFIX, feed),
+                version.clone(),
+            );
+        }
+    }
+
+    /// Write the metadata into the `stamp.json` file of the directory `path`
+    pub fn write_sidecar<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref().join(STAMP_FILE);
+        info!("Writing {}", STAMP_FILE);
+        let stamp_file = StampFile {
+            run_id: self.run_id.as_deref(),
+            source_versions: &self.source_versions,
+            conversion_datetime: self.conversion_datetime.to_rfc3339(),
+        };
+        let file = File::create(&path).with_context(|| format!("Error creating {:?}", path))?;
+        serde_json::to_writer_pretty(file, &stamp_file)
+            .with_context(|| format!("Error writing {:?}", path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn stamp_feed_infos_and_sidecar() {
+        let stamp = Stamp::new(get_test_datetime())
+            .run_id("run-42")
+            .source_version("sncf", "2019-04-01");
+        let mut feed_infos = FeedInfos::default();
+        stamp.stamp_feed_infos(&mut feed_infos);
+        assert_eq!(
+            vec![
+                ("production_datetime", "2019-04-03T17:19:00+00:00"),
+                ("production_run_id", "run-42"),
+                ("source_version:sncf", "2019-04-01"),
+            ],
+            feed_infos
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect::<Vec<_>>()
+        );
+
+        test_in_tmp_dir(|path| {
+            stamp.write_sidecar(path).unwrap();
+            let sidecar: serde_json::Value =
+                serde_json::from_reader(File::open(path.join(STAMP_FILE)).unwrap()).unwrap();
+            assert_eq!(
+                serde_json::json!({
+                    "run_id": "run-42",
+                    "source_versions": { "sncf": "2019-04-01" },
+                    "conversion_datetime": "2019-04-03T17:19:00+00:00",
+                }),
+                sidecar
+            );
+        });
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Merge of the stop areas of the same station, identified by a shared code
+//! (e.g. the UIC code of a station served by the rail feeds of several
+//! contributors).
+
+use crate::{
+    codes::CodeSystem,
+    model::Collections,
+    objects::{Coord, RestrictionType, StopArea, Transfer, TransferMode},
+    report::{Report, ReportEntry, Severity},
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::info;
+use typed_index_collection::Idx;
+
+/// Categories of the `Report` produced by the merge of the stop areas
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopAreaMergeReportCategory {
+    /// A stop area has been merged into another one having the same code
+    StopAreaMerged,
+    /// Transfers have been generated between the stop points of merged stop
+    /// areas
+    TransfersGenerated,
+}
+
+// Representative of the stop area `idx`, the first stop area of its group
+fn find(parents: &mut HashMap<Idx<StopArea>, Idx<StopArea>>, idx: Idx<StopArea>) -> Idx<StopArea> {
+    let mut root = idx;
+    while let Some(&parent) = parents.get(&root) {
+        if parent == root {
+            break;
+        }
+        root = parent;
+    }
+    parents.insert(idx, root);
+    root
+}
+
+/// Merge the stop areas of `collections` sharing a code of one of the
+/// `systems`, into the first of them.
+///
+/// The stop points, the entrances and the other references to a merged stop
+/// area are moved to the stop area kept, which also receives its codes,
+/// object properties and comments. Walking transfers, as the crow flies at
+/// `walking_speed` (in m/s) plus `waiting_time` (in seconds), are then
+/// generated between the stop points coming from different stop areas, when
+/// not already defined.
+///
+/// Every merge is listed in the returned `Report`.
+pub fn merge_stop_areas_by_code(
+    collections: &mut Collections,
+    systems: &[CodeSystem],
+    walking_speed: f64,
+    waiting_time: u32,
+) -> Report<StopAreaMergeReportCategory> {
+    let mut report = Report::default();
+    let mut parents: HashMap<Idx<StopArea>, Idx<StopArea>> = HashMap::new();
+    let mut first_by_code: HashMap<(&str, &str), Idx<StopArea>> = HashMap::new();
+    for (stop_area_idx, stop_area) in collections.stop_areas.iter() {
+        for (system, code) in &stop_area.codes {
+            let known = CodeSystem::from_name(system).map_or(false, |s| systems.contains(&s));
+            if !known || code.is_empty() {
+                continue;
+            }
+            match first_by_code.get(&(system.as_str(), code.as_str())) {
+                Some(&first_idx) => {
+                    let (root, other_root) = (
+                        find(&mut parents, first_idx),
+                        find(&mut parents, stop_area_idx),
+                    );
+                    // the first stop area of the collection is kept
+                    let (kept, merged) = if root < other_root {
+                        (root, other_root)
+                    } else {
+                        (other_root, root)
+                    };
+                    parents.insert(merged, kept);
+                }
+                None => {
+                    first_by_code.insert((system.as_str(), code.as_str()), stop_area_idx);
+                }
+            }
+        }
+    }
+    // merged stop area id => id of the stop area kept
+    let stop_area_idxs: Vec<_> = parents.keys().copied().collect();
+    let replacements: BTreeMap<String, String> = stop_area_idxs
+        .into_iter()
+        .filter_map(|stop_area_idx| {
+            let kept_idx = find(&mut parents, stop_area_idx);
+            (kept_idx != stop_area_idx).then(|| {
+                (
+                    collections.stop_areas[stop_area_idx].id.clone(),
+                    collections.stop_areas[kept_idx].id.clone(),
+                )
+            })
+        })
+        .collect();
+    if replacements.is_empty() {
+        return report;
+    }
+
+    for (merged_id, kept_id) in &replacements {
+        let merged_stop_area = collections.stop_areas.get(merged_id).unwrap().clone();
+        let mut stop_area = collections.stop_areas.get_mut(kept_id).unwrap();
+        stop_area.codes.extend(merged_stop_area.codes);
+        for (key, value) in merged_stop_area.object_properties {
+            stop_area.object_properties.entry(key).or_insert(value);
+        }
+        stop_area
+            .comment_links
+            .extend(merged_stop_area.comment_links);
+        drop(stop_area);
+        report.add_entry(
+            Severity::Info,
+            ReportEntry::new(
+                StopAreaMergeReportCategory::StopAreaMerged,
+                format!("stop area {} is merged into {}", merged_id, kept_id),
+            )
+            .with_object_id(merged_id.clone()),
+        );
+    }
+
+    // stop points of the merged stop areas, with the id of their original
+    // stop area, by id of the stop area kept
+    let kept_ids: HashSet<String> = replacements.values().cloned().collect();
+    let mut stop_points_by_kept_id: BTreeMap<String, Vec<_>> = BTreeMap::new();
+    for stop_point_idx in collections.stop_points.indexes() {
+        let stop_area_id = &collections.stop_points[stop_point_idx].stop_area_id;
+        let kept_id = replacements.get(stop_area_id).unwrap_or(stop_area_id);
+        if kept_ids.contains(kept_id) {
+            stop_points_by_kept_id
+                .entry(kept_id.clone())
+                .or_default()
+                .push((stop_point_idx, stop_area_id.clone()));
+        }
+        if let Some(kept_id) = replacements.get(stop_area_id).cloned() {
+            collections
+                .stop_points
+                .index_mut(stop_point_idx)
+                .stop_area_id = kept_id;
+        }
+    }
+    let replace = |id: &mut String| {
+        if let Some(kept_id) = replacements.get(id) {
+            *id = kept_id.clone();
+        }
+    };
+    for stop_location_idx in collections.stop_locations.indexes() {
+        let mut stop_location = collections.stop_locations.index_mut(stop_location_idx);
+        if let Some(parent_id) = &mut stop_location.parent_id {
+            replace(parent_id);
+        }
+    }
+    for admin_station in collections.admin_stations.values_mut() {
+        replace(&mut admin_station.stop_id);
+    }
+    for occupancy in collections.occupancies.values_mut() {
+        replace(&mut occupancy.from_stop_area);
+        replace(&mut occupancy.to_stop_area);
+    }
+    for restriction in collections.ticket_use_restrictions.values_mut() {
+        if restriction.restriction_type == RestrictionType::OriginDestination {
+            replace(&mut restriction.use_origin);
+            replace(&mut restriction.use_destination);
+        }
+    }
+    collections
+        .stop_areas
+        .retain(|stop_area| !replacements.contains_key(&stop_area.id));
+
+    let existing_transfers: HashSet<(String, String)> = collections
+        .transfers
+        .values()
+        .map(|transfer| (transfer.from_stop_id.clone(), transfer.to_stop_id.clone()))
+        .collect();
+    for (kept_id, stop_points) in stop_points_by_kept_id {
+        let mut transfers = Vec::new();
+        for (from_idx, from_stop_area_id) in &stop_points {
+            for (to_idx, to_stop_area_id) in &stop_points {
+                if from_stop_area_id == to_stop_area_id {
+                    continue;
+                }
+                let from = &collections.stop_points[*from_idx];
+                let to = &collections.stop_points[*to_idx];
+                if from.coord == Coord::default()
+                    || to.coord == Coord::default()
+                    || existing_transfers.contains(&(from.id.clone(), to.id.clone()))
+                {
+                    continue;
+                }
+                let transfer_time = (from.coord.distance_to(&to.coord) / walking_speed) as u32;
+                transfers.push(Transfer {
+                    from_stop_id: from.id.clone(),
+                    to_stop_id: to.id.clone(),
+                    min_transfer_time: Some(transfer_time),
+                    real_min_transfer_time: Some(transfer_time + waiting_time),
+                    equipment_id: None,
+                    transfer_mode: Some(TransferMode::Walk),
+                    ..Default::default()
+                });
+            }
+        }
+        if transfers.is_empty() {
+            continue;
+        }
+        report.add_entry(
+            Severity::Info,
+            ReportEntry::new(
+                StopAreaMergeReportCategory::TransfersGenerated,
+                format!(
+                    "{} transfers generated in the merged stop area {}",
+                    transfers.len(),
+                    kept_id
+                ),
+            )
+            .with_object_id(kept_id),
+        );
+        for transfer in transfers {
+            collections.transfers.push(transfer);
+        }
+    }
+    info!("{} stop area(s) merged", replacements.len());
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::StopPoint;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn two_vehicle_journeys() -> Collections {
+        ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("C", "10:00:00", "10:01:00")
+                    .st("D", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections()
+    }
+
+    fn stop_area_id<'a>(collections: &'a Collections, stop_point_id: &str) -> &'a str {
+        let stop_point: &StopPoint = collections.stop_points.get(stop_point_id).unwrap();
+        stop_point.stop_area_id.as_str()
+    }
+
+    #[test]
+    fn merge_stop_areas_sharing_a_code() {
+        let mut collections = two_vehicle_journeys();
+        let coords = [
+            ("A", 2.3735, 48.8443),
+            ("B", 2.3580, 48.8763),
+            ("C", 2.3740, 48.8445),
+            ("D", 2.3555, 48.8809),
+        ];
+        for (stop_point_id, lon, lat) in coords {
+            collections
+                .stop_points
+                .get_mut(stop_point_id)
+                .unwrap()
+                .coord = Coord { lon, lat };
+        }
+        let mut add_code = |stop_point_id: &str, system: &str, code: &str| {
+            let stop_area_id = collections
+                .stop_points
+                .get(stop_point_id)
+                .unwrap()
+                .stop_area_id
+                .clone();
+            collections
+                .stop_areas
+                .get_mut(&stop_area_id)
+                .unwrap()
+                .codes
+                .insert((system.to_string(), code.to_string()));
+        };
+        add_code("A", "UIC", "87686006");
+        add_code("C", "UIC", "87686006");
+        add_code("B", "UIC", "87271007");
+        add_code("D", "IFOPT", "FR:75056:1234");
+        let stop_areas_count = collections.stop_areas.len();
+        let report = merge_stop_areas_by_code(
+            &mut collections,
+            &[CodeSystem::Uic, CodeSystem::Ifopt],
+            0.785,
+            60,
+        );
+
+        assert_eq!(stop_areas_count - 1, collections.stop_areas.len());
+        assert_eq!(
+            stop_area_id(&collections, "A"),
+            stop_area_id(&collections, "C")
+        );
+        assert_ne!(
+            stop_area_id(&collections, "B"),
+            stop_area_id(&collections, "D")
+        );
+        let transfers: Vec<_> = collections
+            .transfers
+            .values()
+            .map(|transfer| (transfer.from_stop_id.as_str(), transfer.to_stop_id.as_str()))
+            .collect();
+        assert_eq!(vec![("A", "C"), ("C", "A")], transfers);
+        assert_eq!(
+            1,
+            report.count_category(Severity::Info, &StopAreaMergeReportCategory::StopAreaMerged)
+        );
+    }
+
+    #[test]
+    fn ignore_other_systems() {
+        let mut collections = two_vehicle_journeys();
+        for stop_area_id in ["sa:A", "sa:C"] {
+            collections
+                .stop_areas
+                .get_mut(stop_area_id)
+                .unwrap()
+                .codes
+                .insert(("UIC".to_string(), "87686006".to_string()));
+        }
+        let stop_areas_count = collections.stop_areas.len();
+        let report = merge_stop_areas_by_code(&mut collections, &[CodeSystem::Ifopt], 0.785, 60);
+
+        assert_eq!(stop_areas_count, collections.stop_areas.len());
+        assert!(collections.transfers.is_empty());
+        assert!(report.is_empty());
+    }
+}
@@ -0,0 +1,472 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Temporary relocation of stop points (e.g. during works): during a period,
+//! the vehicle journeys stop at a temporary stop point instead of their usual
+//! one, following a CSV file of relocations.
+
+use crate::{
+    model::Collections,
+    objects::{Calendar, Date, StopPoint, VehicleJourney},
+    report::{Report, ReportEntry, Severity},
+    serde_utils::de_from_date_string,
+    Result,
+};
+use anyhow::Context;
+use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+use tracing::info;
+use typed_index_collection::Idx;
+
+/// A stop point replaced by a temporary stop point from `start_date` to
+/// `end_date`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StopRelocation {
+    /// Identifier of the stop point relocated
+    pub stop_point_id: String,
+    /// Identifier of the stop point replacing it during the period
+    pub temporary_stop_point_id: String,
+    /// First day of the relocation
+    #[serde(deserialize_with = "de_from_date_string")]
+    pub start_date: Date,
+    /// Last day of the relocation
+    #[serde(deserialize_with = "de_from_date_string")]
+    pub end_date: Date,
+}
+
+/// How the relocations are applied on the vehicle journeys
+#[derive(Derivative, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StopRelocationStrategy {
+    /// The vehicle journeys running both during and outside a relocation are
+    /// split in two, one for each period
+    #[derivative(Default)]
+    SplitTrips,
+    /// Only the relocations in progress at the first day of the datasets are
+    /// applied, on all the days of the vehicle journeys
+    AtDatasetDate,
+    /// Only the relocations in progress at the date (written `YYYYMMDD`) are
+    /// applied, on all the days of the vehicle journeys
+    AtDate(#[serde(deserialize_with = "de_from_date_string")] Date),
+}
+
+/// Categories of the `Report` produced by the relocation of stop points
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopRelocationReportCategory {
+    /// A stop point of a relocation doesn't exist, the relocation is ignored
+    UnknownStopPoint,
+    /// The start date of a relocation is after its end date, the relocation
+    /// is ignored
+    InvalidPeriod,
+    /// The stop times of a vehicle journey have been moved to a temporary
+    /// stop point
+    VehicleJourneyRelocated,
+    /// A vehicle journey has been split in two, one running during a
+    /// relocation and the other one outside
+    VehicleJourneySplit,
+}
+
+/// Read the relocations from the CSV file `path`, with the columns
+/// `stop_point_id`, `temporary_stop_point_id`, `start_date` and `end_date`
+/// (dates written `YYYYMMDD`)
+pub fn read_relocations<P: AsRef<Path>>(path: P) -> Result<Vec<StopRelocation>> {
+    let path = path.as_ref();
+    info!("Reading stop relocations from {:?}", path);
+    let mut rdr =
+        csv::Reader::from_path(path).with_context(|| format!("Error reading {:?}", path))?;
+    let relocations = rdr
+        .deserialize()
+        .collect::<std::result::Result<_, _>>()
+        .with_context(|| format!("Error parsing {:?}", path))?;
+    Ok(relocations)
+}
+
+fn relocate(
+    vehicle_journey: &mut VehicleJourney,
+    stop_point_idx: Idx<StopPoint>,
+    temporary_stop_point_idx: Idx<StopPoint>,
+) {
+    for stop_time in &mut vehicle_journey.stop_times {
+        if stop_time.stop_point_idx == stop_point_idx {
+            stop_time.stop_point_idx = temporary_stop_point_idx;
+        }
+    }
+}
+
+pub(crate) fn add_calendar(
+    collections: &mut Collections,
+    id: String,
+    dates: BTreeSet<Date>,
+) -> String {
+    if !collections.calendars.contains_id(&id) {
+        // the identifier is checked just above
+        let _ = collections.calendars.push(Calendar {
+            id: id.clone(),
+            dates,
+        });
+    }
+    id
+}
+
+// Copy the properties of the stop times of the vehicle journeys split, by
+// identifier of the original vehicle journey
+pub(crate) fn copy_stop_time_properties(
+    properties: &mut HashMap<(String, u32), String>,
+    split_vehicle_journeys: &HashMap<String, String>,
+    new_value: impl Fn(&str) -> String,
+) {
+    let copies: Vec<_> = properties
+        .iter()
+        .filter_map(|((vj_id, sequence), value)| {
+            split_vehicle_journeys
+                .get(vj_id)
+                .map(|new_vj_id| ((new_vj_id.clone(), *sequence), new_value(value)))
+        })
+        .collect();
+    properties.extend(copies);
+}
+
+// Copy the frequencies of the vehicle journeys split, by identifier of the
+// original vehicle journey
+pub(crate) fn copy_frequencies(
+    collections: &mut Collections,
+    split_vehicle_journeys: &HashMap<String, String>,
+) {
+    let frequencies: Vec<_> = collections
+        .frequencies
+        .values()
+        .filter_map(|frequency| {
+            split_vehicle_journeys
+                .get(&frequency.vehicle_journey_id)
+                .map(|new_vj_id| {
+                    let mut frequency = frequency.clone();
+                    frequency.vehicle_journey_id = new_vj_id.clone();
+                    frequency
+                })
+        })
+        .collect();
+    for frequency in frequencies {
+        collections.frequencies.push(frequency);
+    }
+}
+
+/// Move the stop times of the vehicle journeys of `collections` to the
+/// temporary stop points of the `relocations`, following the `strategy`.
+///
+/// With `StopRelocationStrategy::SplitTrips`, a vehicle journey running both
+/// during and outside a relocation is split: the copy runs the days of the
+/// relocation at the temporary stop point, with its own calendar, its
+/// frequencies and the headsigns, identifiers and comments of its stop
+/// times. The interchanges and the occupancies are not copied.
+///
+/// Every relocated or split vehicle journey is listed in the returned
+/// `Report`. The stop points and calendars no longer used are left to
+/// `Collections::sanitize`.
+pub fn relocate_stop_points(
+    collections: &mut Collections,
+    relocations: &[StopRelocation],
+    strategy: StopRelocationStrategy,
+) -> Report<StopRelocationReportCategory> {
+    let mut report = Report::default();
+    let at_date = match strategy {
+        StopRelocationStrategy::SplitTrips => None,
+        StopRelocationStrategy::AtDatasetDate => collections
+            .datasets
+            .values()
+            .map(|dataset| dataset.start_date)
+            .min(),
+        StopRelocationStrategy::AtDate(date) => Some(date),
+    };
+    for (position, relocation) in relocations.iter().enumerate() {
+        let (stop_point_idx, temporary_stop_point_idx) = match (
+            collections.stop_points.get_idx(&relocation.stop_point_id),
+            collections
+                .stop_points
+                .get_idx(&relocation.temporary_stop_point_id),
+        ) {
+            (Some(stop_point_idx), Some(temporary_stop_point_idx)) => {
+                (stop_point_idx, temporary_stop_point_idx)
+            }
+            _ => {
+                report.add_error(
+                    format!(
+                        "stop point {} or {} doesn't exist, the relocation is ignored",
+                        relocation.stop_point_id, relocation.temporary_stop_point_id
+                    ),
+                    StopRelocationReportCategory::UnknownStopPoint,
+                );
+                continue;
+            }
+        };
+        if relocation.start_date > relocation.end_date {
+            report.add_error(
+                format!(
+                    "the relocation of stop point {} starts after its end, it is ignored",
+                    relocation.stop_point_id
+                ),
+                StopRelocationReportCategory::InvalidPeriod,
+            );
+            continue;
+        }
+        let during = |date: &Date| relocation.start_date <= *date && *date <= relocation.end_date;
+        if strategy != StopRelocationStrategy::SplitTrips && !at_date.iter().any(during) {
+            continue;
+        }
+
+        // original vehicle journey id => id of its copy during the relocation
+        let mut split_vehicle_journeys = HashMap::new();
+        let vj_idxs: Vec<_> = collections
+            .vehicle_journeys
+            .iter()
+            .filter(|(_, vj)| {
+                vj.stop_times
+                    .iter()
+                    .any(|stop_time| stop_time.stop_point_idx == stop_point_idx)
+            })
+            .map(|(vj_idx, _)| vj_idx)
+            .collect();
+        for vj_idx in vj_idxs {
+            let vj = &collections.vehicle_journeys[vj_idx];
+            let (dates_during, dates_outside): (BTreeSet<Date>, BTreeSet<Date>) =
+                match (strategy, collections.calendars.get(&vj.service_id)) {
+                    (StopRelocationStrategy::SplitTrips, Some(calendar)) => calendar
+                        .dates
+                        .iter()
+                        .copied()
+                        .partition(|date| during(date)),
+                    (StopRelocationStrategy::SplitTrips, None) => continue,
+                    // the relocation is in progress at the date of the strategy
+                    _ => (BTreeSet::new(), BTreeSet::new()),
+                };
+            if strategy == StopRelocationStrategy::SplitTrips && dates_during.is_empty() {
+                continue;
+            }
+            if dates_outside.is_empty() {
+                let vj_id = vj.id.clone();
+                relocate(
+                    &mut collections.vehicle_journeys.index_mut(vj_idx),
+                    stop_point_idx,
+                    temporary_stop_point_idx,
+                );
+                report.add_entry(
+                    Severity::Info,
+                    ReportEntry::new(
+                        StopRelocationReportCategory::VehicleJourneyRelocated,
+                        format!(
+                            "vehicle journey {} stops at {} instead of {}",
+                            vj_id, relocation.temporary_stop_point_id, relocation.stop_point_id
+                        ),
+                    )
+                    .with_object_id(vj_id),
+                );
+                continue;
+            }
+
+            let mut relocated_vj = vj.clone();
+            relocated_vj.id = format!("{}:relocated:{}", vj.id, position);
+            if collections.vehicle_journeys.contains_id(&relocated_vj.id) {
+                continue;
+            }
+            let service_id = vj.service_id.clone();
+            relocated_vj.service_id = add_calendar(
+                collections,
+                format!("{}:relocated:{}", service_id, position),
+                dates_during,
+            );
+            relocate(&mut relocated_vj, stop_point_idx, temporary_stop_point_idx);
+            let outside_service_id = add_calendar(
+                collections,
+                format!("{}:not_relocated:{}", service_id, position),
+                dates_outside,
+            );
+            let mut vj = collections.vehicle_journeys.index_mut(vj_idx);
+            vj.service_id = outside_service_id;
+            let vj_id = vj.id.clone();
+            drop(vj);
+            report.add_entry(
+                Severity::Info,
+                ReportEntry::new(
+                    StopRelocationReportCategory::VehicleJourneySplit,
+                    format!(
+                        "vehicle journey {} split, {} stops at {} instead of {}",
+                        vj_id,
+                        relocated_vj.id,
+                        relocation.temporary_stop_point_id,
+                        relocation.stop_point_id
+                    ),
+                )
+                .with_object_id(vj_id.clone()),
+            );
+            split_vehicle_journeys.insert(vj_id, relocated_vj.id.clone());
+            // the identifier is checked above
+            let _ = collections.vehicle_journeys.push(relocated_vj);
+        }
+        if split_vehicle_journeys.is_empty() {
+            continue;
+        }
+
+        let suffix = format!(":relocated:{}", position);
+        copy_stop_time_properties(
+            &mut collections.stop_time_headsigns,
+            &split_vehicle_journeys,
+            str::to_string,
+        );
+        copy_stop_time_properties(
+            &mut collections.stop_time_ids,
+            &split_vehicle_journeys,
+            |id| format!("{}{}", id, suffix),
+        );
+        copy_stop_time_properties(
+            &mut collections.stop_time_comments,
+            &split_vehicle_journeys,
+            str::to_string,
+        );
+        copy_frequencies(collections, &split_vehicle_journeys);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{one_vehicle_journey, BuildModel};
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    fn relocation() -> StopRelocation {
+        StopRelocation {
+            stop_point_id: "B".to_string(),
+            temporary_stop_point_id: "C".to_string(),
+            start_date: Date::from_ymd_opt(2020, 1, 2).unwrap(),
+            end_date: Date::from_ymd_opt(2020, 1, 31).unwrap(),
+        }
+    }
+
+    fn stop_point_ids<'a>(collections: &'a Collections, vj_id: &str) -> Vec<&'a str> {
+        collections
+            .vehicle_journeys
+            .get(vj_id)
+            .unwrap()
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                collections.stop_points[stop_time.stop_point_idx]
+                    .id
+                    .as_str()
+            })
+            .collect()
+    }
+
+    fn two_vehicle_journeys() -> Collections {
+        ModelBuilder::default()
+            .calendar("c1", &["2020-01-01", "2020-01-02", "2020-01-03"])
+            .vj("vj1", |vj| {
+                vj.calendar("c1")
+                    .st("A", "10:00:00", "10:01:00")
+                    .st("B", "11:00:00", "11:01:00");
+            })
+            .vj("vj2", |vj| {
+                vj.calendar("c1")
+                    .st("A", "12:00:00", "12:01:00")
+                    .st("C", "13:00:00", "13:01:00");
+            })
+            .build_model()
+            .into_collections()
+    }
+
+    #[test]
+    fn split_trips() {
+        let mut collections = two_vehicle_journeys();
+        let report = relocate_stop_points(
+            &mut collections,
+            &[relocation()],
+            StopRelocationStrategy::SplitTrips,
+        );
+
+        assert!(report.errors().is_empty());
+        assert_eq!(3, collections.vehicle_journeys.len());
+        assert_eq!(vec!["A", "B"], stop_point_ids(&collections, "vj1"));
+        assert_eq!(
+            vec!["A", "C"],
+            stop_point_ids(&collections, "vj1:relocated:0")
+        );
+        let dates = |vj_id: &str| -> Vec<String> {
+            let service_id = &collections.vehicle_journeys.get(vj_id).unwrap().service_id;
+            collections
+                .calendars
+                .get(service_id)
+                .unwrap()
+                .dates
+                .iter()
+                .map(Date::to_string)
+                .collect()
+        };
+        assert_eq!(vec!["2020-01-01"], dates("vj1"));
+        assert_eq!(vec!["2020-01-02", "2020-01-03"], dates("vj1:relocated:0"));
+        // vj2 doesn't stop at B
+        assert_eq!(
+            "c1",
+            collections.vehicle_journeys.get("vj2").unwrap().service_id
+        );
+    }
+
+    #[test]
+    fn relocate_at_date() {
+        let mut collections = two_vehicle_journeys();
+        let date = Date::from_ymd_opt(2020, 1, 10).unwrap();
+        let report = relocate_stop_points(
+            &mut collections,
+            &[relocation()],
+            StopRelocationStrategy::AtDate(date),
+        );
+
+        assert_eq!(1, report.infos().len());
+        assert_eq!(2, collections.vehicle_journeys.len());
+        assert_eq!(vec!["A", "C"], stop_point_ids(&collections, "vj1"));
+
+        let mut collections = two_vehicle_journeys();
+        let date = Date::from_ymd_opt(2020, 1, 1).unwrap();
+        relocate_stop_points(
+            &mut collections,
+            &[relocation()],
+            StopRelocationStrategy::AtDate(date),
+        );
+        assert_eq!(vec!["A", "B"], stop_point_ids(&collections, "vj1"));
+    }
+
+    #[test]
+    fn unknown_stop_point() {
+        let mut collections = one_vehicle_journey().into_collections();
+        let relocation = StopRelocation {
+            temporary_stop_point_id: "unknown".to_string(),
+            ..relocation()
+        };
+        let report = relocate_stop_points(
+            &mut collections,
+            &[relocation],
+            StopRelocationStrategy::SplitTrips,
+        );
+        assert_eq!(
+            StopRelocationReportCategory::UnknownStopPoint,
+            report.errors()[0].category
+        );
+        assert_eq!(vec!["A", "B"], stop_point_ids(&collections, "vj1"));
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Expected occupancy of the trips at each of their stops (crowding), read
+//! from the `stop_time_occupancies.txt` extension file of a NTFS or a GTFS,
+//! or from a separate CSV file with the same columns:
+//! `trip_id,stop_sequence,occupancy_status` where `occupancy_status` is one
+//! of the values of `OccupancyStatus` (e.g. `MANY_SEATS_AVAILABLE`).
+
+use crate::{
+    file_handler::{FileHandler, PathFileHandler},
+    model::Collections,
+    objects::StopTimeOccupancy,
+    parser::{read_objects, read_objects_loose},
+    Result,
+};
+use anyhow::anyhow;
+use std::path::Path;
+use tracing::warn;
+
+/// Name of the extension file of the occupancies in a NTFS or a GTFS
+pub const STOP_TIME_OCCUPANCIES_FILE: &str = "stop_time_occupancies.txt";
+
+// Add the occupancies referencing an existing stop time
+fn add_occupancies(collections: &mut Collections, occupancies: Vec<StopTimeOccupancy>) {
+    for occupancy in occupancies {
+        let stop_time_exists = collections
+            .vehicle_journeys
+            .get(&occupancy.vehicle_journey_id)
+            .map(|vehicle_journey| {
+                vehicle_journey
+                    .stop_times
+                    .iter()
+                    .any(|stop_time| stop_time.sequence == occupancy.stop_sequence)
+            })
+            .unwrap_or(false);
+        if !stop_time_exists {
+            warn!(
+                "Occupancy of trip_id={} at stop_sequence={} ignored: stop time not found",
+                occupancy.vehicle_journey_id, occupancy.stop_sequence
+            );
+            continue;
+        }
+        collections.stop_time_occupancies.push(occupancy);
+    }
+}
+
+pub(crate) fn manage_stop_time_occupancies<H>(
+    collections: &mut Collections,
+    file_handler: &mut H,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let occupancies = read_objects_loose(file_handler, STOP_TIME_OCCUPANCIES_FILE, false)?;
+    add_occupancies(collections, occupancies);
+    Ok(())
+}
+
+/// Read the occupancies of the CSV file `path` and add them to the
+/// `collections`, the vehicle journeys and their stop times being already
+/// loaded. The occupancies of an unknown stop time are ignored.
+pub fn read<P: AsRef<Path>>(collections: &mut Collections, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .ok_or_else(|| anyhow!("{:?} is not a file", path))?;
+    let mut file_handler = PathFileHandler::new(path.parent().unwrap_or_else(|| Path::new("")));
+    let occupancies = read_objects(&mut file_handler, file_name, true)?;
+    add_occupancies(collections, occupancies);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::one_vehicle_journey;
+    use crate::{objects::OccupancyStatus, test_utils::*};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn read_occupancies_from_csv() {
+        let mut collections = one_vehicle_journey().into_collections();
+        test_in_tmp_dir(|path| {
+            let file_path = path.join("crowding.csv");
+            std::fs::write(
+                &file_path,
+                "trip_id,stop_sequence,occupancy_status\n\
+                 vj1,0,FEW_SEATS_AVAILABLE\n\
+                 vj1,5,FULL\n\
+                 unknown,0,FULL\n",
+            )
+            .unwrap();
+            read(&mut collections, &file_path).unwrap();
+        });
+
+        assert_eq!(
+            vec![StopTimeOccupancy {
+                vehicle_journey_id: "vj1".to_string(),
+                stop_sequence: 0,
+                occupancy: OccupancyStatus::FewSeatsAvailable,
+            }],
+            collections.stop_time_occupancies.take()
+        );
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! A walkable street network, read from an
+//! [OpenStreetMap](https://wiki.openstreetmap.org/wiki/OSM_XML) extract, to
+//! compute the walking distances between the stops along the streets instead
+//! of as the crow flies (see `transfers::generates_transfers_with_street_network`).
+
+use crate::{objects::Coord, Result};
+use anyhow::{anyhow, Context};
+use minidom::Element;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    fs,
+    path::Path,
+};
+use tracing::info;
+
+/// Maximum distance in meters between a stop and the node of the network it
+/// is attached to. A stop farther from the network is not reachable.
+pub const MAX_SNAP_DISTANCE: f64 = 100.0;
+
+// Size in degrees of the cells of the index of the nodes, larger than
+// `MAX_SNAP_DISTANCE` below the polar circles
+const CELL_SIZE: f64 = 0.002;
+
+// Highways not open to the pedestrians
+const NOT_WALKABLE_HIGHWAYS: &[&str] = &[
+    "motorway",
+    "motorway_link",
+    "trunk",
+    "trunk_link",
+    "construction",
+    "proposed",
+    "raceway",
+];
+
+type Cell = (i64, i64);
+
+fn cell(coord: &Coord) -> Cell {
+    (
+        (coord.lon / CELL_SIZE).floor() as i64,
+        (coord.lat / CELL_SIZE).floor() as i64,
+    )
+}
+
+// Distances are compared in millimeters in the queue of Dijkstra
+fn millimeters(distance: f64) -> u64 {
+    (distance * 1000.0).round() as u64
+}
+
+/// A graph of walkable streets, the edges being weighted by their length in
+/// meters
+#[derive(Debug, Clone, Default)]
+pub struct StreetNetwork {
+    coords: Vec<Coord>,
+    edges: Vec<Vec<(usize, f64)>>,
+    cells: HashMap<Cell, Vec<usize>>,
+}
+
+impl StreetNetwork {
+    /// Add a node at `coord`, returning its index
+    pub fn add_node(&mut self, coord: Coord) -> usize {
+        let node = self.coords.len();
+        self.coords.push(coord);
+        self.edges.push(Vec::new());
+        self.cells.entry(cell(&coord)).or_default().push(node);
+        node
+    }
+
+    /// Add a street between the nodes `from` and `to`, walkable in both
+    /// directions
+    pub fn add_street(&mut self, from: usize, to: usize) {
+        let length = self.coords[from].distance_to(&self.coords[to]);
+        self.edges[from].push((to, length));
+        self.edges[to].push((from, length));
+    }
+
+    /// Number of nodes
+    pub fn len(&self) -> usize {
+        self.coords.len()
+    }
+
+    /// No node
+    pub fn is_empty(&self) -> bool {
+        self.coords.is_empty()
+    }
+
+    /// Read the walkable streets (the ways with a `highway` tag, except the
+    /// motorways and the trunks) of the OSM XML file at `path`
+    pub fn from_osm_xml<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        info!("Reading street network from {:?}", path);
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Error reading {:?}", path))?;
+        let root: Element = content
+            .parse()
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("Error reading {:?}", path))?;
+
+        let mut osm_coords: HashMap<&str, Coord> = HashMap::new();
+        for node in root.children().filter(|child| child.name() == "node") {
+            let (id, lon, lat) = match (node.attr("id"), node.attr("lon"), node.attr("lat")) {
+                (Some(id), Some(lon), Some(lat)) => (id, lon, lat),
+                _ => continue,
+            };
+            let coord = Coord {
+                lon: lon.parse()?,
+                lat: lat.parse()?,
+            };
+            osm_coords.insert(id, coord);
+        }
+
+        let mut network = StreetNetwork::default();
+        let mut nodes: HashMap<&str, usize> = HashMap::new();
+        for way in root.children().filter(|child| child.name() == "way") {
+            let highway = way
+                .children()
+                .filter(|child| child.name() == "tag" && child.attr("k") == Some("highway"))
+                .find_map(|tag| tag.attr("v"));
+            match highway {
+                Some(highway) if !NOT_WALKABLE_HIGHWAYS.contains(&highway) => {}
+                _ => continue,
+            }
+            let mut previous = None;
+            for node_ref in way
+                .children()
+                .filter(|child| child.name() == "nd")
+                .filter_map(|nd| nd.attr("ref"))
+            {
+                let coord = match osm_coords.get(node_ref) {
+                    Some(coord) => *coord,
+                    // the node is outside the extract
+                    None => {
+                        previous = None;
+                        continue;
+                    }
+                };
+                let node = *nodes
+                    .entry(node_ref)
+                    .or_insert_with(|| network.add_node(coord));
+                if let Some(previous) = previous {
+                    network.add_street(previous, node);
+                }
+                previous = Some(node);
+            }
+        }
+        info!("{} nodes in the street network", network.len());
+        Ok(network)
+    }
+
+    // The closest node to `coord`, with its distance, within
+    // `MAX_SNAP_DISTANCE`
+    fn nearest_node(&self, coord: &Coord) -> Option<(usize, f64)> {
+        let (lon_cell, lat_cell) = cell(coord);
+        let approx = coord.approx();
+        (lon_cell - 1..=lon_cell + 1)
+            .flat_map(|lon| (lat_cell - 1..=lat_cell + 1).map(move |lat| (lon, lat)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .map(|&node| (node, approx.sq_distance_to(&self.coords[node]).sqrt()))
+            .filter(|&(_, distance)| distance <= MAX_SNAP_DISTANCE)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+    }
+
+    /// The walking distances along the streets from `from`, up to
+    /// `max_distance` meters
+    pub fn distances_from(&self, from: &Coord, max_distance: f64) -> NetworkDistances<'_> {
+        let mut distances = HashMap::new();
+        if let Some((source, snap_distance)) = self.nearest_node(from) {
+            let mut queue = BinaryHeap::new();
+            queue.push(Reverse((millimeters(snap_distance), source)));
+            distances.insert(source, snap_distance);
+            while let Some(Reverse((_, node))) = queue.pop() {
+                let distance = distances[&node];
+                for &(next, length) in &self.edges[node] {
+                    let next_distance = distance + length;
+                    if next_distance > max_distance
+                        || distances.get(&next).map_or(false, |&d| d <= next_distance)
+                    {
+                        continue;
+                    }
+                    distances.insert(next, next_distance);
+                    queue.push(Reverse((millimeters(next_distance), next)));
+                }
+            }
+        }
+        NetworkDistances {
+            network: self,
+            distances,
+        }
+    }
+}
+
+/// The walking distances along the streets from a coordinate (see
+/// `StreetNetwork::distances_from`)
+#[derive(Debug)]
+pub struct NetworkDistances<'a> {
+    network: &'a StreetNetwork,
+    distances: HashMap<usize, f64>,
+}
+
+impl NetworkDistances<'_> {
+    /// The walking distance in meters to `coord`, if it is reachable
+    pub fn distance_to(&self, coord: &Coord) -> Option<f64> {
+        let (node, snap_distance) = self.network.nearest_node(coord)?;
+        self.distances
+            .get(&node)
+            .map(|distance| distance + snap_distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn walk_along_the_streets() {
+        test_in_tmp_dir(|path| {
+            // an L-shaped street from A to C through B, and a motorway
+            // from A to C
+            create_file_with_content(
+                path,
+                "streets.osm",
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+                <osm version="0.6">
+                    <node id="1" lat="48.8500" lon="2.3500"/>
+                    <node id="2" lat="48.8500" lon="2.3530"/>
+                    <node id="3" lat="48.8520" lon="2.3530"/>
+                    <way id="10">
+                        <nd ref="1"/><nd ref="2"/><nd ref="3"/>
+                        <tag k="highway" v="residential"/>
+                    </way>
+                    <way id="11">
+                        <nd ref="1"/><nd ref="3"/>
+                        <tag k="highway" v="motorway"/>
+                    </way>
+                </osm>"#,
+            );
+            let network = StreetNetwork::from_osm_xml(path.join("streets.osm")).unwrap();
+            assert_eq!(3, network.len());
+
+            let a = Coord {
+                lon: 2.35,
+                lat: 48.85,
+            };
+            let b = Coord {
+                lon: 2.353,
+                lat: 48.85,
+            };
+            let c = Coord {
+                lon: 2.353,
+                lat: 48.852,
+            };
+            let distances = network.distances_from(&a, 1000.0);
+            let expected = a.distance_to(&b) + b.distance_to(&c);
+            assert_relative_eq!(expected, distances.distance_to(&c).unwrap(), epsilon = 1.0);
+            assert!(distances.distance_to(&c).unwrap() > a.distance_to(&c) + 50.0);
+
+            let distances = network.distances_from(&a, 100.0);
+            assert_eq!(None, distances.distance_to(&c));
+        });
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Fixtures of the unit tests.
+//!
+//! `transit_model_builder` depends on `transit_model`, so the `Model` built
+//! by its `ModelBuilder` is a `Model` of another instance of the crate, which
+//! the functions of the crate don't accept. [`BuildModel::build_model`] builds
+//! it and reads it back as a `Model` of this crate through a snapshot.
+//!
+//! The fixtures used by the tests of several modules are shared here.
+
+use crate::{model::Model, snapshot::SnapshotFormat};
+use transit_model_builder::ModelBuilder;
+
+pub(crate) trait BuildModel {
+    /// Build a `Model` of this crate
+    fn build_model(self) -> Model;
+}
+
+impl BuildModel for ModelBuilder {
+    fn build_model(self) -> Model {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.json");
+        // the snapshot format of the other instance of the crate can't be
+        // named here, its default being a JSON document
+        self.build().to_snapshot(&path, Default::default()).unwrap();
+        Model::from_snapshot(&path, SnapshotFormat::Json).unwrap()
+    }
+}
+
+/// A single vehicle journey `vj1` from the stop point `A` to the stop point `B`
+pub(crate) fn one_vehicle_journey() -> Model {
+    ModelBuilder::default()
+        .vj("vj1", |vj| {
+            vj.st("A", "10:00:00", "10:01:00")
+                .st("B", "11:00:00", "11:01:00");
+        })
+        .build_model()
+}
@@ -0,0 +1,200 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Golden-file testing of the conversions: the files produced in a directory
+//! are compared with the files of an expected directory, independently of the
+//! order of the rows and, for the CSV files (`.txt` and `.csv`), of the order
+//! of the columns.
+//!
+//! ```no_run
+//! # fn main() -> transit_model::Result<()> {
+//! let model = transit_model::gtfs::read("path/to/gtfs")?;
+//! let output_dir = tempfile::tempdir()?;
+//! transit_model::ntfs::write(&model, output_dir.path(), chrono::Utc::now().into())?;
+//! transit_model::testing::assert_same_dir(
+//!     output_dir.path(),
+//!     "path/to/expected_ntfs",
+//!     Some(&["stops.txt", "trips.txt"]),
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Result;
+use anyhow::Context;
+use pretty_assertions::assert_eq;
+use std::{collections::BTreeSet, fs, path::Path};
+
+fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext == "txt" || ext == "csv")
+}
+
+fn to_csv_line<'a, I: IntoIterator<Item = &'a str>>(fields: I) -> Result<String> {
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    wtr.write_record(fields)?;
+    let line = String::from_utf8(wtr.into_inner()?)?;
+    Ok(line.trim_end().to_string())
+}
+
+/// Content of the file at `path` which does not depend on the order of the
+/// rows, nor on the order of the columns for a CSV file (the header, with the
+/// sorted column names, being the first line).
+pub fn normalized_content<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    if !is_csv(path) {
+        let mut lines: Vec<String> = fs::read_to_string(path)
+            .with_context(|| format!("Error reading {:?}", path))?
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.sort();
+        return Ok(lines);
+    }
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .with_context(|| format!("Error reading {:?}", path))?;
+    let headers = rdr
+        .headers()
+        .with_context(|| format!("Error reading {:?}", path))?
+        .clone();
+    let mut column_order: Vec<usize> = (0..headers.len()).collect();
+    column_order.sort_by_key(|&column| &headers[column]);
+
+    let mut rows = Vec::new();
+    for record in rdr.records() {
+        let record = record.with_context(|| format!("Error reading {:?}", path))?;
+        rows.push(to_csv_line(
+            column_order
+                .iter()
+                .map(|&column| record.get(column).unwrap_or_default()),
+        )?);
+    }
+    rows.sort();
+    let mut content = vec![to_csv_line(
+        column_order.iter().map(|&column| &headers[column]),
+    )?];
+    content.extend(rows);
+    Ok(content)
+}
+
+fn file_names(dir: &Path) -> Result<BTreeSet<String>> {
+    let mut file_names = BTreeSet::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Error reading {:?}", dir))? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        // hidden files are not compared
+        if entry.path().is_file() && !file_name.starts_with('.') {
+            file_names.insert(file_name);
+        }
+    }
+    Ok(file_names)
+}
+
+/// Panics if the content of the file `output_file` is not the one of the
+/// file `expected_file` (see [normalized_content]), showing the difference.
+pub fn assert_same_file<P: AsRef<Path>, Q: AsRef<Path>>(output_file: P, expected_file: Q) {
+    let (output_file, expected_file) = (output_file.as_ref(), expected_file.as_ref());
+    let output_content = normalized_content(output_file)
+        .unwrap_or_else(|e| panic!("cannot read produced file {:?}: {:?}", output_file, e));
+    let expected_content = normalized_content(expected_file)
+        .unwrap_or_else(|e| panic!("cannot read expected file {:?}: {:?}", expected_file, e));
+    assert_eq!(
+        expected_content, output_content,
+        "{:?} differs from {:?}",
+        output_file, expected_file
+    );
+}
+
+/// Panics if the files of the directory `output_dir` are not the ones of the
+/// directory `expected_dir`, or if their content differ (see
+/// [normalized_content]). Only the `files` are compared if given.
+pub fn assert_same_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+    output_dir: P,
+    expected_dir: Q,
+    files: Option<&[&str]>,
+) {
+    let (output_dir, expected_dir) = (output_dir.as_ref(), expected_dir.as_ref());
+    let file_names = match files {
+        Some(files) => files.iter().map(|file| file.to_string()).collect(),
+        None => {
+            let output_files = file_names(output_dir)
+                .unwrap_or_else(|e| panic!("cannot list {:?}: {:?}", output_dir, e));
+            let expected_files = file_names(expected_dir)
+                .unwrap_or_else(|e| panic!("cannot list {:?}: {:?}", expected_dir, e));
+            assert_eq!(
+                expected_files, output_files,
+                "the produced files differ from the expected ones"
+            );
+            output_files
+        }
+    };
+    for file_name in file_names {
+        assert_same_file(output_dir.join(&file_name), expected_dir.join(&file_name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn columns_and_rows_order_is_ignored() {
+        test_in_tmp_dir(|path| {
+            create_file_with_content(
+                path,
+                "stops.txt",
+                "stop_id,stop_name\nsp2,B\nsp1,\"A, B\"\n",
+            );
+            create_file_with_content(
+                path,
+                "expected.txt",
+                "stop_name,stop_id\n\"A, B\",sp1\nB,sp2\n",
+            );
+            assert_eq!(
+                vec!["stop_id,stop_name", "sp1,\"A, B\"", "sp2,B"],
+                normalized_content(path.join("stops.txt")).unwrap()
+            );
+            assert_same_file(path.join("stops.txt"), path.join("expected.txt"));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "differs from")]
+    fn different_values() {
+        test_in_tmp_dir(|path| {
+            create_file_with_content(path, "stops.txt", "stop_id,stop_name\nsp1,A\n");
+            create_file_with_content(path, "expected.txt", "stop_name,stop_id\nB,sp1\n");
+            assert_same_file(path.join("stops.txt"), path.join("expected.txt"));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "the produced files differ from the expected ones")]
+    fn missing_file() {
+        test_in_tmp_dir(|path| {
+            let (output_dir, expected_dir) = (path.join("output"), path.join("expected"));
+            fs::create_dir(&output_dir).unwrap();
+            fs::create_dir(&expected_dir).unwrap();
+            create_file_with_content(&output_dir, "stops.txt", "stop_id\nsp1\n");
+            create_file_with_content(&expected_dir, "stops.txt", "stop_id\nsp1\n");
+            create_file_with_content(&expected_dir, "trips.txt", "trip_id\nt1\n");
+            assert_same_dir(&output_dir, &expected_dir, Some(&["stops.txt"]));
+            assert_same_dir(&output_dir, &expected_dir, None);
+        });
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Assignment of a timezone to the stops and the networks without one, by
+//! looking up the coordinates of the stops in the timezone boundaries
+//! embedded in the crate (`timezone_lookup` feature). Worldwide feeds then
+//! don't fall back on the default timezone (Europe/Paris) of the writers.
+
+use crate::{
+    model::Collections,
+    objects::{Coord, StopPoint},
+    report::{Report, ReportEntry, Severity},
+};
+use chrono_tz::Tz;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+use typed_index_collection::{CollectionWithId, Id, Idx};
+use tzf_rs::DefaultFinder;
+
+static FINDER: Lazy<DefaultFinder> = Lazy::new(DefaultFinder::new);
+
+/// Categories of the `Report` produced by the timezone assignment
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimezoneReportCategory {
+    /// A timezone has been assigned to a stop
+    StopTimezoneAssigned,
+    /// A timezone has been assigned to a network
+    NetworkTimezoneAssigned,
+    /// No timezone has been found at the coordinates of a stop
+    TimezoneNotFound,
+}
+
+/// Timezone at `coord`, `None` for the missing coordinates (`0, 0`) or outside
+/// the timezone boundaries
+pub fn lookup_timezone(coord: &Coord) -> Option<Tz> {
+    if coord.lon == 0.0 && coord.lat == 0.0 {
+        return None;
+    }
+    FINDER.get_tz_name(coord.lon, coord.lat).parse().ok()
+}
+
+fn assign_stop_timezones<T: Id<T>>(
+    stops: &mut CollectionWithId<T>,
+    object_type: &str,
+    coord_and_timezone: fn(&mut T) -> (&Coord, &mut Option<Tz>),
+    report: &mut Report<TimezoneReportCategory>,
+) {
+    for idx in stops.indexes() {
+        let mut stop = stops.index_mut(idx);
+        let id = stop.id().to_string();
+        let (coord, timezone) = coord_and_timezone(&mut stop);
+        if timezone.is_some() {
+            continue;
+        }
+        match lookup_timezone(coord) {
+            Some(found) => {
+                *timezone = Some(found);
+                report.add_entry(
+                    Severity::Info,
+                    ReportEntry::new(
+                        TimezoneReportCategory::StopTimezoneAssigned,
+                        format!("{} timezone assigned from its coordinates", object_type),
+                    )
+                    .with_object_id(id),
+                );
+            }
+            None => report.add_entry(
+                Severity::Warning,
+                ReportEntry::new(
+                    TimezoneReportCategory::TimezoneNotFound,
+                    format!(
+                        "no timezone found at the coordinates of the {}",
+                        object_type
+                    ),
+                )
+                .with_object_id(id),
+            ),
+        }
+    }
+}
+
+// Most frequent timezone of the stop points, the first one by name in case of
+// a tie
+fn main_timezone(stop_points: &HashSet<Idx<StopPoint>>, collections: &Collections) -> Option<Tz> {
+    let mut counts: HashMap<Tz, usize> = HashMap::new();
+    for &stop_point_idx in stop_points {
+        let stop_point = &collections.stop_points[stop_point_idx];
+        let timezone = stop_point.timezone.or_else(|| {
+            collections
+                .stop_areas
+                .get(&stop_point.stop_area_id)
+                .and_then(|stop_area| stop_area.timezone)
+        });
+        if let Some(timezone) = timezone {
+            *counts.entry(timezone).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|(left_tz, left_count), (right_tz, right_count)| {
+            left_count
+                .cmp(right_count)
+                .then_with(|| right_tz.name().cmp(left_tz.name()))
+        })
+        .map(|(timezone, _)| timezone)
+}
+
+/// Assigns a timezone to the stop areas, the stop points and the other stops
+/// without one, from their coordinates. The networks without timezone are
+/// then given the most frequent timezone of the stop points served by their
+/// trips.
+///
+/// The objects already having a timezone are left unchanged.
+pub fn assign_timezones(collections: &mut Collections) -> Report<TimezoneReportCategory> {
+    info!("Assigning the missing timezones");
+    let mut report = Report::default();
+    assign_stop_timezones(
+        &mut collections.stop_areas,
+        "stop area",
+        |stop_area| (&stop_area.coord, &mut stop_area.timezone),
+        &mut report,
+    );
+    assign_stop_timezones(
+        &mut collections.stop_points,
+        "stop point",
+        |stop_point| (&stop_point.coord, &mut stop_point.timezone),
+        &mut report,
+    );
+    assign_stop_timezones(
+        &mut collections.stop_locations,
+        "stop location",
+        |stop_location| (&stop_location.coord, &mut stop_location.timezone),
+        &mut report,
+    );
+
+    let mut stop_points_by_network: HashMap<&str, HashSet<Idx<StopPoint>>> = HashMap::new();
+    for vehicle_journey in collections.vehicle_journeys.values() {
+        let network_id = collections
+            .routes
+            .get(&vehicle_journey.route_id)
+            .and_then(|route| collections.lines.get(&route.line_id))
+            .map(|line| line.network_id.as_str());
+        if let Some(network_id) = network_id {
+            stop_points_by_network
+                .entry(network_id)
+                .or_default()
+                .extend(
+                    vehicle_journey
+                        .stop_times
+                        .iter()
+                        .map(|stop_time| stop_time.stop_point_idx),
+                );
+        }
+    }
+    let network_timezones: Vec<(String, Tz)> = collections
+        .networks
+        .values()
+        .filter(|network| network.timezone.is_none())
+        .filter_map(|network| {
+            let stop_points = stop_points_by_network.get(network.id.as_str())?;
+            let timezone = main_timezone(stop_points, collections)?;
+            Some((network.id.clone(), timezone))
+        })
+        .collect();
+    for (network_id, timezone) in network_timezones {
+        if let Some(mut network) = collections.networks.get_mut(&network_id) {
+            network.timezone = Some(timezone);
+        }
+        report.add_entry(
+            Severity::Info,
+            ReportEntry::new(
+                TimezoneReportCategory::NetworkTimezoneAssigned,
+                format!(
+                    "network timezone assigned from its stops ({})",
+                    timezone.name()
+                ),
+            )
+            .with_object_id(network_id),
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::one_vehicle_journey;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn lookup_timezones() {
+        let paris = Coord {
+            lon: 2.3522,
+            lat: 48.8566,
+        };
+        let new_york = Coord {
+            lon: -74.0060,
+            lat: 40.7128,
+        };
+        assert_eq!(Some(chrono_tz::Europe::Paris), lookup_timezone(&paris));
+        assert_eq!(
+            Some(chrono_tz::America::New_York),
+            lookup_timezone(&new_york)
+        );
+        assert_eq!(None, lookup_timezone(&Coord::default()));
+    }
+
+    #[test]
+    fn assign_missing_timezones() {
+        let mut collections = one_vehicle_journey().into_collections();
+        collections
+            .networks
+            .get_mut("default_network")
+            .unwrap()
+            .timezone = None;
+        let mut stop_point = collections.stop_points.get_mut("A").unwrap();
+        stop_point.coord = Coord {
+            lon: -43.1729,
+            lat: -22.9068,
+        };
+        drop(stop_point);
+        let mut stop_point = collections.stop_points.get_mut("B").unwrap();
+        stop_point.coord = Coord {
+            lon: -43.1822,
+            lat: -22.9110,
+        };
+        stop_point.timezone = Some(chrono_tz::America::Bahia);
+        drop(stop_point);
+
+        let report = assign_timezones(&mut collections);
+
+        assert_eq!(
+            Some(chrono_tz::America::Sao_Paulo),
+            collections.stop_points.get("A").unwrap().timezone
+        );
+        // timezones already set are kept
+        assert_eq!(
+            Some(chrono_tz::America::Bahia),
+            collections.stop_points.get("B").unwrap().timezone
+        );
+        // the tie is broken by the name of the timezones
+        assert_eq!(
+            Some(chrono_tz::America::Bahia),
+            collections
+                .networks
+                .get("default_network")
+                .unwrap()
+                .timezone
+        );
+        assert_eq!(
+            1,
+            report.count_category(
+                Severity::Info,
+                &TimezoneReportCategory::NetworkTimezoneAssigned
+            )
+        );
+    }
+}
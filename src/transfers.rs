@@ -15,15 +15,18 @@
 //! See function generates_transfers
 
 use crate::{
-    model::Model,
-    objects::{Coord, StopPoint, Transfer},
+    model::{Collections, Model},
+    objects::{Coord, StopPoint, Transfer, TransferMode},
+    street_network::StreetNetwork,
     Result,
 };
 use std::collections::HashMap;
-use tracing::info;
+use tracing::{field, info, info_span};
 use typed_index_collection::{Collection, CollectionWithId, Idx};
 
-type TransferMap = HashMap<(Idx<StopPoint>, Idx<StopPoint>), Transfer>;
+// the transfers between two stop points, several when they are possible at
+// different times
+type TransferMap = HashMap<(Idx<StopPoint>, Idx<StopPoint>), Vec<Transfer>>;
 
 /// The closure that will determine whether a connection should be created between 2 stops.
 /// See [generates_transfers](./fn.generates_transfers.html).
@@ -33,18 +36,17 @@ fn make_transfers_map(
     transfers: Collection<Transfer>,
     sp: &CollectionWithId<StopPoint>,
 ) -> TransferMap {
-    transfers
-        .into_iter()
-        .map(|t| {
-            (
-                (
-                    sp.get_idx(&t.from_stop_id).unwrap(),
-                    sp.get_idx(&t.to_stop_id).unwrap(),
-                ),
-                t,
-            )
-        })
-        .collect()
+    let mut transfers_map = TransferMap::new();
+    for t in transfers {
+        transfers_map
+            .entry((
+                sp.get_idx(&t.from_stop_id).unwrap(),
+                sp.get_idx(&t.to_stop_id).unwrap(),
+            ))
+            .or_default()
+            .push(t);
+    }
+    transfers_map
 }
 
 fn generate_transfers_from_sp(
@@ -54,6 +56,7 @@ fn generate_transfers_from_sp(
     walking_speed: f64,
     waiting_time: u32,
     need_transfer: Option<NeedTransfer>,
+    street_network: Option<&StreetNetwork>,
 ) {
     info!("Adding missing transfers from stop points.");
     let sq_max_distance = max_distance * max_distance;
@@ -62,6 +65,8 @@ fn generate_transfers_from_sp(
             continue;
         }
         let approx = sp1.coord.approx();
+        let network_distances =
+            street_network.map(|network| network.distances_from(&sp1.coord, max_distance));
         for (idx2, sp2) in model.stop_points.iter() {
             if sp2.coord == Coord::default() {
                 continue;
@@ -78,16 +83,28 @@ fn generate_transfers_from_sp(
             if sq_distance > sq_max_distance {
                 continue;
             }
-            let transfer_time = (sq_distance.sqrt() / walking_speed) as u32;
+            let distance = match &network_distances {
+                // the transfers within a stop point are walked as the crow flies
+                Some(_) if idx1 == idx2 => sq_distance.sqrt(),
+                Some(network_distances) => match network_distances.distance_to(&sp2.coord) {
+                    Some(distance) if distance <= max_distance => distance,
+                    _ => continue,
+                },
+                None => sq_distance.sqrt(),
+            };
+            let transfer_time = (distance / walking_speed) as u32;
             transfers_map.insert(
                 (idx1, idx2),
-                Transfer {
+                vec![Transfer {
                     from_stop_id: sp1.id.clone(),
                     to_stop_id: sp2.id.clone(),
                     min_transfer_time: Some(transfer_time),
                     real_min_transfer_time: Some(transfer_time + waiting_time),
                     equipment_id: None,
-                },
+                    // a transfer without mode is walked
+                    transfer_mode: None,
+                    ..Default::default()
+                }],
             );
         }
     }
@@ -126,6 +143,51 @@ pub fn generates_transfers(
     waiting_time: u32,
     need_transfer: Option<NeedTransfer>,
 ) -> Result<Model> {
+    generate_transfers(
+        model,
+        max_distance,
+        walking_speed,
+        waiting_time,
+        need_transfer,
+        None,
+    )
+}
+
+/// Generates missing transfers like [generates_transfers], the walking
+/// distances being computed along the streets of the `street_network`
+/// instead of as the crow flies.
+///
+/// The stop points farther than `street_network::MAX_SNAP_DISTANCE` from the
+/// streets, or not connected by the streets within `max_distance`, don't get
+/// a generated transfer between them.
+pub fn generates_transfers_with_street_network(
+    model: Model,
+    street_network: &StreetNetwork,
+    max_distance: f64,
+    walking_speed: f64,
+    waiting_time: u32,
+    need_transfer: Option<NeedTransfer>,
+) -> Result<Model> {
+    generate_transfers(
+        model,
+        max_distance,
+        walking_speed,
+        waiting_time,
+        need_transfer,
+        Some(street_network),
+    )
+}
+
+fn generate_transfers(
+    model: Model,
+    max_distance: f64,
+    walking_speed: f64,
+    waiting_time: u32,
+    need_transfer: Option<NeedTransfer>,
+    street_network: Option<&StreetNetwork>,
+) -> Result<Model> {
+    let span = info_span!("generate_transfers", transfers = field::Empty);
+    let _enter = span.enter();
     info!("Generating transfers...");
     let mut transfers_map = make_transfers_map(model.transfers.clone(), &model.stop_points);
     generate_transfers_from_sp(
@@ -135,14 +197,32 @@ pub fn generates_transfers(
         walking_speed,
         waiting_time,
         need_transfer,
+        street_network,
     );
 
-    let mut new_transfers: Vec<_> = transfers_map.into_values().collect();
-    new_transfers.sort_unstable_by(|t1, t2| {
+    let mut new_transfers: Vec<_> = transfers_map.into_values().flatten().collect();
+    new_transfers.sort_by(|t1, t2| {
         (&t1.from_stop_id, &t1.to_stop_id).cmp(&(&t2.from_stop_id, &t2.to_stop_id))
     });
+    span.record("transfers", new_transfers.len());
 
     let mut collections = model.into_collections();
     collections.transfers = Collection::new(new_transfers);
     Model::new(collections)
 }
+
+/// Keep only the transfers made with one of the `modes`, a transfer without
+/// mode being walked.
+///
+/// For instance, the park-and-ride transfers can be removed from a dataset
+/// written for a pedestrian journey planner.
+pub fn retain_transfer_modes(collections: &mut Collections, modes: &[TransferMode]) {
+    let transfers_count = collections.transfers.len();
+    collections
+        .transfers
+        .retain(|transfer| modes.contains(&transfer.transfer_mode.unwrap_or(TransferMode::Walk)));
+    info!(
+        "{} transfers removed by mode",
+        transfers_count - collections.transfers.len()
+    );
+}
@@ -12,26 +12,53 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>
 
-use crate::{file_handler::FileHandler, parser::read_objects};
+use crate::{
+    csv_format::CsvFormat,
+    file_handler::{FileHandler, FileWriter, PathFileWriter},
+    parser::read_objects,
+};
 use anyhow::Context;
 use skip_error::skip_error_and_warn;
 use std::{
+    cmp::Ordering,
     fs,
-    io::{Read, Write},
+    io::{Read, Seek, Write},
     path,
 };
-use tracing::{debug, info};
+use tracing::{debug, info, info_span};
 use typed_index_collection::{Collection, CollectionWithId, Id};
 use walkdir::WalkDir;
 
+/// Record the number of stop points, vehicle journeys and stop times of
+/// `collections` into the fields of the same name of `span`
+pub(crate) fn record_dataset_size(span: &tracing::Span, collections: &crate::model::Collections) {
+    let stop_times: usize = collections
+        .vehicle_journeys
+        .values()
+        .map(|vehicle_journey| vehicle_journey.stop_times.len())
+        .sum();
+    span.record("stop_points", collections.stop_points.len());
+    span.record("vehicle_journeys", collections.vehicle_journeys.len());
+    span.record("stop_times", stop_times);
+}
+
 pub fn zip_to<P, R>(source_path: P, zip_file: R) -> crate::Result<()>
 where
     P: AsRef<path::Path>,
     R: AsRef<path::Path>,
 {
-    let source_path = source_path.as_ref();
     let file = fs::File::create(zip_file.as_ref())?;
-    let mut zip = zip::ZipWriter::new(file);
+    zip_to_writer(source_path, file)?;
+    Ok(())
+}
+
+pub fn zip_to_writer<P, W>(source_path: P, writer: W) -> crate::Result<W>
+where
+    P: AsRef<path::Path>,
+    W: Write + Seek,
+{
+    let source_path = source_path.as_ref();
+    let mut zip = zip::ZipWriter::new(writer);
     let options =
         zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
     let mut buffer = Vec::new();
@@ -50,8 +77,7 @@ where
             }
         }
     }
-    zip.finish()?;
-    Ok(())
+    Ok(zip.finish()?)
 }
 
 pub(crate) fn make_collection_with_id<T, H>(
@@ -96,22 +122,69 @@ where
     Ok(collection)
 }
 
+/// Objects of `collection` sorted by identifier, for the writers to produce
+/// the same output whatever the order of the collection
+pub(crate) fn sorted_by_id<T: Id<T>>(collection: &CollectionWithId<T>) -> Vec<&T> {
+    let mut objects: Vec<&T> = collection.values().collect();
+    objects.sort_unstable_by(|left, right| left.id().cmp(right.id()));
+    objects
+}
+
+// Compare two fields of CSV records, the integers (e.g. the sequences) being
+// compared as numbers and sorted before the other fields, compared as strings.
+// This is a total order, so that the sorted records do not depend on their
+// initial order (`2` < `10` < `1a` < `b`).
+fn cmp_fields(left: &str, right: &str) -> Ordering {
+    match (left.parse::<u64>(), right.parse::<u64>()) {
+        // `1` and `01` are ordered by their text
+        (Ok(left_number), Ok(right_number)) => {
+            left_number.cmp(&right_number).then_with(|| left.cmp(right))
+        }
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => left.cmp(right),
+    }
+}
+
+// Compare two CSV records field by field
+fn cmp_records(left: &[String], right: &[String]) -> Ordering {
+    left.iter()
+        .zip(right.iter())
+        .map(|(left, right)| cmp_fields(left, right))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or_else(|| left.len().cmp(&right.len()))
+}
+
 pub fn write_collection_with_id<T>(
     path: &path::Path,
     file: &str,
     collection: &CollectionWithId<T>,
+    csv_format: &CsvFormat,
+) -> crate::Result<()>
+where
+    T: Id<T> + serde::Serialize,
+{
+    write_collection_with_id_to(&mut PathFileWriter::new(path), file, collection, csv_format)
+}
+
+pub fn write_collection_with_id_to<H, T>(
+    file_writer: &mut H,
+    file: &str,
+    collection: &CollectionWithId<T>,
+    csv_format: &CsvFormat,
 ) -> crate::Result<()>
 where
+    for<'a> &'a mut H: FileWriter,
     T: Id<T> + serde::Serialize,
 {
     if collection.is_empty() {
         return Ok(());
     }
+    let _span = info_span!("write_file", file, objects = collection.len()).entered();
     info!("Writing {}", file);
-    let path = path.join(file);
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
-    for obj in collection.values() {
+    let (writer, path) = file_writer.create_file(file)?;
+    let mut wtr = csv_format.writer(writer);
+    for obj in sorted_by_id(collection) {
         wtr.serialize(obj)
             .with_context(|| format!("Error reading {:?}", path))?;
     }
@@ -121,23 +194,106 @@ where
     Ok(())
 }
 
+/// Write the objects of `collection` (which have no identifier) sorted by
+/// their fields in the order of the columns, so that the output does not
+/// depend on the order of the collection.
 pub fn write_collection<T>(
     path: &path::Path,
     file: &str,
     collection: &Collection<T>,
+    csv_format: &CsvFormat,
+) -> crate::Result<()>
+where
+    T: serde::Serialize,
+{
+    write_collection_to(&mut PathFileWriter::new(path), file, collection, csv_format)
+}
+
+pub fn write_collection_to<H, T>(
+    file_writer: &mut H,
+    file: &str,
+    collection: &Collection<T>,
+    csv_format: &CsvFormat,
+) -> crate::Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+    T: serde::Serialize,
+{
+    write_collection_with_optional_columns_to(file_writer, file, collection, &[], csv_format)
+}
+
+/// Write the objects of `collection` like `write_collection`, the
+/// `optional_columns` being written only when at least one object has a value
+/// (e.g. the extension columns of a file, to keep the files of the producers
+/// not using them unchanged).
+pub fn write_collection_with_optional_columns<T>(
+    path: &path::Path,
+    file: &str,
+    collection: &Collection<T>,
+    optional_columns: &[&str],
+    csv_format: &CsvFormat,
 ) -> crate::Result<()>
 where
     T: serde::Serialize,
+{
+    write_collection_with_optional_columns_to(
+        &mut PathFileWriter::new(path),
+        file,
+        collection,
+        optional_columns,
+        csv_format,
+    )
+}
+
+fn write_collection_with_optional_columns_to<H, T>(
+    file_writer: &mut H,
+    file: &str,
+    collection: &Collection<T>,
+    optional_columns: &[&str],
+    csv_format: &CsvFormat,
+) -> crate::Result<()>
+where
+    for<'a> &'a mut H: FileWriter,
+    T: serde::Serialize,
 {
     if collection.is_empty() {
         return Ok(());
     }
+    let _span = info_span!("write_file", file, objects = collection.len()).entered();
     info!("Writing {}", file);
-    let path = path.join(file);
-    let mut wtr =
-        csv::Writer::from_path(&path).with_context(|| format!("Error reading {:?}", path))?;
+    let path = path::PathBuf::from(file);
+    let mut header = None;
+    let mut records = Vec::with_capacity(collection.len());
     for obj in collection.values() {
-        wtr.serialize(obj)
+        let (obj_header, fields) = csv_format
+            .serialize_record(obj)
+            .with_context(|| format!("Error reading {:?}", path))?;
+        header = header.or(obj_header);
+        records.push(fields);
+    }
+    let header = header.with_context(|| format!("Error reading {:?}: no header", path))?;
+    records.sort_by(|left, right| cmp_records(left, right));
+    let written_columns: Vec<usize> = header
+        .iter()
+        .enumerate()
+        .filter(|(position, column)| {
+            !optional_columns.contains(*column)
+                || records.iter().any(|record| {
+                    record
+                        .get(*position)
+                        .map_or(false, |field| !field.is_empty())
+                })
+        })
+        .map(|(position, _)| position)
+        .collect();
+
+    let (writer, path) = file_writer.create_file(file)?;
+    // the fields are already formatted
+    let mut wtr = csv_format.writer_builder().from_writer(writer);
+    wtr.write_record(written_columns.iter().map(|&position| header[position]))
+        .with_context(|| format!("Error reading {:?}", path))?;
+    for record in records {
+        wtr.write_record(written_columns.iter().map(|&position| &record[position]))
             .with_context(|| format!("Error reading {:?}", path))?;
     }
     wtr.flush()
@@ -145,3 +301,61 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[derive(serde::Serialize)]
+    struct Code {
+        object_id: &'static str,
+        object_code: &'static str,
+    }
+
+    #[test]
+    fn write_collection_in_the_same_order_whatever_the_input_order() {
+        let codes = [
+            ("b", "1"),
+            ("10", "x"),
+            ("1a", "1"),
+            ("2", "x"),
+            ("01", "x"),
+            ("1", "x"),
+            ("2", "10"),
+            ("2", "9"),
+            ("", "x"),
+        ];
+        let write = |codes: &[(&'static str, &'static str)]| {
+            let dir = tempfile::tempdir().unwrap();
+            let collection = Collection::new(
+                codes
+                    .iter()
+                    .map(|&(object_id, object_code)| Code {
+                        object_id,
+                        object_code,
+                    })
+                    .collect(),
+            );
+            write_collection(dir.path(), "codes.txt", &collection, &CsvFormat::default()).unwrap();
+            fs::read_to_string(dir.path().join("codes.txt")).unwrap()
+        };
+        let expected = "object_id,object_code\n\
+                        01,x\n\
+                        1,x\n\
+                        2,9\n\
+                        2,10\n\
+                        2,x\n\
+                        10,x\n\
+                        ,x\n\
+                        1a,1\n\
+                        b,1\n";
+        let mut shuffled = codes.to_vec();
+        for _ in 0..codes.len() {
+            assert_eq!(expected, write(&shuffled));
+            shuffled.reverse();
+            assert_eq!(expected, write(&shuffled));
+            shuffled.rotate_left(1);
+        }
+    }
+}
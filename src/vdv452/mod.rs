@@ -0,0 +1,335 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! [VDV 452](https://www.vdv.de/i-d-s-downloads.aspx) format management.
+//!
+//! The VDV 452 tables are read from the `.x10` files (VDV 451 format) of a
+//! directory or of a zip archive, each file being named after its table in
+//! lowercase (e.g. `rec_ort.x10`). The following tables are used:
+//! - `REC_ORT`: the stop points (`ONR_TYP_NR` 1), grouped in stop areas by
+//!   `ORT_REF_ORT`
+//! - `FIRMENKALENDER`: the calendars, one for each day type (`TAGESART_NR`)
+//! - `REC_LID` and `LID_VERLAUF`: the lines, one route for each direction,
+//!   and the points of their variants
+//! - `REC_FRT`: the trips
+//! - `SEL_FZT_FELD`, `ORT_HZTF` (optional) and `REC_FRT_HZT` (optional): the
+//!   run times and dwell times from which the stop times are computed
+
+mod read;
+mod x10;
+
+use crate::{
+    file_handler::{FileHandler, PathFileHandler, ZipHandler},
+    model::{Collections, Model},
+    objects::{self, Contributor, Dataset, Network},
+    validity_period, AddPrefix, PrefixConfiguration, Result,
+};
+use anyhow::{anyhow, Context};
+use chrono_tz::Tz;
+use derivative::Derivative;
+use std::{collections::BTreeMap, path::Path};
+use tracing::info;
+use typed_index_collection::CollectionWithId;
+
+/// Parameters of the VDV 452 reading
+#[derive(Derivative)]
+#[derivative(Default)]
+pub struct Configuration {
+    /// The Contributor providing the Dataset
+    pub contributor: Contributor,
+    /// Describe the Dataset being parsed
+    pub dataset: Dataset,
+    /// Additional key-values for the 'feed_infos.txt'
+    pub feed_infos: BTreeMap<String, String>,
+    /// used to prefix objects
+    pub prefix_conf: Option<PrefixConfiguration>,
+    /// Timezone of the network, the VDV 452 tables don't provide it
+    #[derivative(Default(value = "chrono_tz::Europe::Berlin"))]
+    pub timezone: Tz,
+}
+
+fn read_file_handler<H>(file_handler: &mut H, configuration: Configuration) -> Result<Collections>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    info!("Reading VDV 452 from {}", file_handler.source_name());
+    let Configuration {
+        contributor,
+        mut dataset,
+        feed_infos,
+        prefix_conf,
+        timezone,
+    } = configuration;
+
+    let mut collections = Collections::default();
+    read::read_calendars(file_handler, &mut collections)?;
+    validity_period::compute_dataset_validity_period(&mut dataset, &collections.calendars)?;
+    let dataset_id = dataset.id.clone();
+
+    collections.contributors = CollectionWithId::from(contributor);
+    collections.datasets = CollectionWithId::from(dataset);
+    collections.feed_infos = feed_infos.into();
+    collections.networks = CollectionWithId::from(Network {
+        timezone: Some(timezone),
+        ..Default::default()
+    });
+    collections.companies = CollectionWithId::from(objects::Company::default());
+
+    read::read_stops(file_handler, &mut collections)?;
+    let variants = read::read_lines(file_handler, &mut collections)?;
+    read::read_trips(file_handler, &mut collections, &variants, &dataset_id)?;
+
+    if let Some(prefix_conf) = prefix_conf {
+        collections.prefix(&prefix_conf);
+    }
+    collections.calendar_deduplication();
+    Ok(collections)
+}
+
+/// Imports `Collections` from the VDV 452 tables in the `path` directory or
+/// zip archive.
+pub fn read_collections<P: AsRef<Path>>(
+    path: P,
+    configuration: Configuration,
+) -> Result<Collections> {
+    let p = path.as_ref();
+    if p.is_file() {
+        let reader = std::fs::File::open(p).with_context(|| format!("Error reading {:?}", p))?;
+        let mut file_handler = ZipHandler::new(reader, p)?;
+        read_file_handler(&mut file_handler, configuration)
+            .with_context(|| format!("impossible to read zipped VDV 452 {:?}", p))
+    } else if p.is_dir() {
+        let mut file_handler = PathFileHandler::new(p.to_path_buf());
+        read_file_handler(&mut file_handler, configuration)
+            .with_context(|| format!("impossible to read VDV 452 directory from {:?}", p))
+    } else {
+        Err(anyhow!(
+            "file {:?} is neither a file nor a directory, cannot read a VDV 452 from it",
+            p
+        ))
+    }
+}
+
+/// Imports a `Model` from the VDV 452 tables in the `path` directory or zip
+/// archive.
+///
+/// The `Configuration` gives the contributor and the dataset of the data, and
+/// the timezone of the network.
+pub fn read<P: AsRef<Path>>(path: P, configuration: Configuration) -> Result<Model> {
+    Model::new(read_collections(path, configuration)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::Time,
+        test_utils::{create_file_with_content, test_in_tmp_dir},
+    };
+    use pretty_assertions::assert_eq;
+
+    fn create_tables(path: &Path) {
+        create_file_with_content(
+            path,
+            "rec_ort.x10",
+            r#"tbl; REC_ORT
+atr; BASIS_VERSION; ONR_TYP_NR; ORT_NR; ORT_NAME; ORT_REF_ORT; ORT_REF_ORT_NAME; ORT_POS_LAENGE; ORT_POS_BREITE; HST_NR_NATIONAL
+rec; 1; 1; 101; "Hauptbahnhof Steig A"; 10; "Hauptbahnhof"; 0132200000; 0523000000; "de:11000:10:1"
+rec; 1; 1; 102; "Hauptbahnhof Steig B"; 10; "Hauptbahnhof"; 0132400000; 0523000000; ""
+rec; 1; 1; 201; "Rathaus"; 20; "Rathaus"; 0132500000; 0523100000; ""
+rec; 1; 2; 901; "Betriebshof"; 90; "Betriebshof"; ; ; ""
+rec; 1; 1; 301; "Marktplatz"; 30; ""; 0133000000; 0523200000; ""
+end; 5
+"#,
+        );
+        create_file_with_content(
+            path,
+            "firmenkalender.x10",
+            r#"tbl; FIRMENKALENDER
+atr; BASIS_VERSION; BETRIEBSTAG; BETRIEBSTAG_TEXT; TAGESART_NR
+rec; 1; 20240101; "Montag"; 1
+rec; 1; 20240102; "Dienstag"; 1
+rec; 1; 20240106; "Samstag"; 2
+end; 3
+"#,
+        );
+        create_file_with_content(
+            path,
+            "rec_lid.x10",
+            r#"tbl; REC_LID
+atr; BASIS_VERSION; LI_NR; STR_LI_VAR; ROUTEN_NR; LI_RI_NR; LI_KUERZEL; LIDNAME
+rec; 1; 42; "1"; 1; 1; "42"; "Bus 42"
+rec; 1; 42; "2"; 2; 2; "42"; "Bus 42"
+end; 2
+"#,
+        );
+        create_file_with_content(
+            path,
+            "lid_verlauf.x10",
+            r#"tbl; LID_VERLAUF
+atr; BASIS_VERSION; LI_LFD_NR; LI_NR; STR_LI_VAR; ONR_TYP_NR; ORT_NR
+rec; 1; 1; 42; "1"; 1; 101
+rec; 1; 2; 42; "1"; 2; 901
+rec; 1; 3; 42; "1"; 1; 201
+rec; 1; 4; 42; "1"; 1; 301
+rec; 1; 1; 42; "2"; 1; 301
+rec; 1; 2; 42; "2"; 1; 102
+end; 6
+"#,
+        );
+        create_file_with_content(
+            path,
+            "sel_fzt_feld.x10",
+            r#"tbl; SEL_FZT_FELD
+atr; BASIS_VERSION; BEREICH_NR; FGR_NR; ONR_TYP_NR; ORT_NR; SEL_ZIEL_TYP; SEL_ZIEL; SEL_FZT
+rec; 1; 0; 1; 1; 101; 2; 901; 60
+rec; 1; 0; 1; 2; 901; 1; 201; 120
+rec; 1; 0; 1; 1; 201; 1; 301; 180
+rec; 1; 0; 1; 1; 301; 1; 102; 300
+end; 4
+"#,
+        );
+        create_file_with_content(
+            path,
+            "ort_hztf.x10",
+            r#"tbl; ORT_HZTF
+atr; BASIS_VERSION; FGR_NR; ONR_TYP_NR; ORT_NR; HP_HZT
+rec; 1; 1; 1; 201; 30
+end; 1
+"#,
+        );
+        create_file_with_content(
+            path,
+            "rec_frt_hzt.x10",
+            r#"tbl; REC_FRT_HZT
+atr; BASIS_VERSION; FRT_FID; ONR_TYP_NR; ORT_NR; FRT_HZT_ZEIT
+rec; 1; 1002; 1; 201; 90
+end; 1
+"#,
+        );
+        create_file_with_content(
+            path,
+            "rec_frt.x10",
+            r#"tbl; REC_FRT
+atr; BASIS_VERSION; FRT_FID; FRT_START; LI_NR; TAGESART_NR; FAHRTART_NR; FGR_NR; STR_LI_VAR
+rec; 1; 1001; 28800; 42; 1; 1; 1; "1"
+rec; 1; 1002; 32400; 42; 2; 1; 1; "1"
+rec; 1; 1003; 36000; 42; 1; 1; 1; "2"
+rec; 1; 1004; 36000; 42; 3; 1; 1; "2"
+rec; 1; 1005; 36000; 42; 1; 1; 2; "2"
+end; 5
+"#,
+        );
+    }
+
+    #[test]
+    fn read_vdv452() {
+        test_in_tmp_dir(|path| {
+            create_tables(path);
+            let model = read(path, Configuration::default()).unwrap();
+
+            assert_eq!(
+                vec!["101", "102", "201", "301"],
+                model
+                    .stop_points
+                    .values()
+                    .map(|sp| &sp.id)
+                    .collect::<Vec<_>>()
+            );
+            let stop_point = model.stop_points.get("101").unwrap();
+            assert_eq!("sa:10", stop_point.stop_area_id);
+            assert!((stop_point.coord.lon - 13.366667).abs() < 1e-6);
+            assert!((stop_point.coord.lat - 52.5).abs() < 1e-6);
+            assert!(stop_point
+                .codes
+                .contains(&("DHID".to_string(), "de:11000:10:1".to_string())));
+            let stop_area = model.stop_areas.get("sa:10").unwrap();
+            assert_eq!("Hauptbahnhof", stop_area.name);
+            assert!((stop_area.coord.lon - 13.383333).abs() < 1e-6);
+            assert_eq!("Marktplatz", model.stop_areas.get("sa:30").unwrap().name);
+
+            let line = model.lines.get("42").unwrap();
+            assert_eq!("Bus 42", line.name);
+            assert_eq!(Some("42".to_string()), line.code);
+            let route = model.routes.get("42:1").unwrap();
+            assert_eq!(Some("forward".to_string()), route.direction_type);
+            assert_eq!(Some("sa:30".to_string()), route.destination_id);
+            assert_eq!(
+                Some("backward".to_string()),
+                model.routes.get("42:2").unwrap().direction_type
+            );
+            assert_eq!(
+                Some(chrono_tz::Europe::Berlin),
+                model.networks.values().next().unwrap().timezone
+            );
+
+            // 1004 has an unknown day type, 1005 an unknown travel time group
+            let mut vj_ids: Vec<_> = model.vehicle_journeys.values().map(|vj| &vj.id).collect();
+            vj_ids.sort();
+            assert_eq!(vec!["1001", "1002", "1003"], vj_ids);
+            let times = |vj_id: &str| {
+                model
+                    .vehicle_journeys
+                    .get(vj_id)
+                    .unwrap()
+                    .stop_times
+                    .iter()
+                    .map(|st| {
+                        (
+                            model.stop_points[st.stop_point_idx].id.as_str(),
+                            st.arrival_time,
+                            st.departure_time,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                vec![
+                    ("101", Time::new(8, 0, 0), Time::new(8, 0, 0)),
+                    ("201", Time::new(8, 3, 0), Time::new(8, 3, 30)),
+                    ("301", Time::new(8, 6, 30), Time::new(8, 6, 30)),
+                ],
+                times("1001")
+            );
+            assert_eq!(
+                vec![
+                    ("101", Time::new(9, 0, 0), Time::new(9, 0, 0)),
+                    ("201", Time::new(9, 3, 0), Time::new(9, 4, 30)),
+                    ("301", Time::new(9, 7, 30), Time::new(9, 7, 30)),
+                ],
+                times("1002")
+            );
+            assert_eq!(
+                vec![
+                    ("301", Time::new(10, 0, 0), Time::new(10, 0, 0)),
+                    ("102", Time::new(10, 5, 0), Time::new(10, 5, 0)),
+                ],
+                times("1003")
+            );
+
+            let calendar = model.calendars.get("1").unwrap();
+            assert_eq!(2, calendar.dates.len());
+        });
+    }
+
+    #[test]
+    fn missing_table() {
+        test_in_tmp_dir(|path| {
+            create_tables(path);
+            std::fs::remove_file(path.join("rec_frt.x10")).unwrap();
+            let error = read(path, Configuration::default()).unwrap_err();
+            assert!(format!("{:?}", error).contains("file rec_frt.x10 not found"));
+        });
+    }
+}
@@ -0,0 +1,434 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+use super::x10::{read_opt_table, read_table};
+use crate::{
+    file_handler::FileHandler,
+    model::Collections,
+    objects::{
+        Calendar, CommercialMode, Coord, Date, KeysValues, Line, PhysicalMode, Route, StopArea,
+        StopPoint, StopTime, Time, VehicleJourney,
+    },
+    Result,
+};
+use anyhow::{anyhow, bail, Context};
+use skip_error::skip_error_and_warn;
+use std::collections::{BTreeMap, HashMap};
+use tracing::warn;
+use typed_index_collection::CollectionWithId;
+
+/// `ONR_TYP_NR` of the points where the passengers can board (Haltepunkt)
+const STOP_POINT_TYPE: u32 = 1;
+const PHYSICAL_MODE: &str = "Bus";
+
+/// A point of the network, identified by its type and its number
+type PointKey = (u32, u64);
+
+/// A variant of a line (`STR_LI_VAR`), with the points it serves in order
+#[derive(Debug)]
+pub(super) struct LineVariant {
+    route_id: String,
+    points: Vec<PointKey>,
+}
+
+pub(super) type LineVariants = HashMap<(u32, String), LineVariant>;
+
+/// Converts a coordinate written as `±DDDMMSSsss` (degrees, minutes, seconds
+/// and thousandths of seconds) into decimal degrees
+fn parse_vdv_coord(value: i64) -> f64 {
+    let abs = value.abs();
+    let degrees = (abs / 10_000_000) as f64;
+    let minutes = (abs / 100_000 % 100) as f64;
+    let seconds = (abs % 100_000) as f64 / 1000.0;
+    value.signum() as f64 * (degrees + minutes / 60.0 + seconds / 3600.0)
+}
+
+fn stop_point_id(ort_nr: u64) -> String {
+    ort_nr.to_string()
+}
+
+fn stop_area_id(ort_ref_ort: u64) -> String {
+    format!("sa:{}", ort_ref_ort)
+}
+
+/// Reads the stop points and stop areas from the table `REC_ORT`. The stop
+/// areas (Haltestelle) are located at the centroid of their stop points.
+pub(super) fn read_stops<H>(file_handler: &mut H, collections: &mut Collections) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let table = read_table(file_handler, "REC_ORT")?;
+    let mut stop_points = Vec::new();
+    let mut stop_areas: BTreeMap<String, (StopArea, Vec<Coord>)> = BTreeMap::new();
+    for record in table.records() {
+        if record.parse::<u32>("ONR_TYP_NR")? != STOP_POINT_TYPE {
+            continue;
+        }
+        let ort_nr = record.parse::<u64>("ORT_NR")?;
+        let name = record.get("ORT_NAME")?.to_string();
+        let coord = match (
+            record.get_opt("ORT_POS_LAENGE"),
+            record.get_opt("ORT_POS_BREITE"),
+        ) {
+            (Some(_), Some(_)) => Coord {
+                lon: parse_vdv_coord(record.parse("ORT_POS_LAENGE")?),
+                lat: parse_vdv_coord(record.parse("ORT_POS_BREITE")?),
+            },
+            _ => Coord::default(),
+        };
+        let stop_area_id = stop_area_id(record.parse("ORT_REF_ORT")?);
+        let mut codes = KeysValues::default();
+        if let Some(dhid) = record.get_opt("HST_NR_NATIONAL") {
+            codes.insert(("DHID".to_string(), dhid.to_string()));
+        }
+        let (_, coords) = stop_areas.entry(stop_area_id.clone()).or_insert_with(|| {
+            let stop_area = StopArea {
+                id: stop_area_id.clone(),
+                name: record
+                    .get_opt("ORT_REF_ORT_NAME")
+                    .unwrap_or(&name)
+                    .to_string(),
+                visible: true,
+                ..Default::default()
+            };
+            (stop_area, Vec::new())
+        });
+        coords.push(coord);
+        stop_points.push(StopPoint {
+            id: stop_point_id(ort_nr),
+            name,
+            codes,
+            visible: true,
+            coord,
+            stop_area_id,
+            ..Default::default()
+        });
+    }
+    collections.stop_points = CollectionWithId::new(stop_points)?;
+    collections.stop_areas = CollectionWithId::new(
+        stop_areas
+            .into_values()
+            .map(|(mut stop_area, coords)| {
+                let count = coords.len() as f64;
+                stop_area.coord = Coord {
+                    lon: coords.iter().map(|c| c.lon).sum::<f64>() / count,
+                    lat: coords.iter().map(|c| c.lat).sum::<f64>() / count,
+                };
+                stop_area
+            })
+            .collect(),
+    )?;
+    Ok(())
+}
+
+/// Reads the calendars from the table `FIRMENKALENDER`, a calendar being
+/// created for each day type (`TAGESART_NR`) with its operating days
+pub(super) fn read_calendars<H>(file_handler: &mut H, collections: &mut Collections) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let table = read_table(file_handler, "FIRMENKALENDER")?;
+    let mut calendars: BTreeMap<String, Calendar> = BTreeMap::new();
+    for record in table.records() {
+        let day_type = record.parse::<u32>("TAGESART_NR")?.to_string();
+        let date = Date::parse_from_str(record.get("BETRIEBSTAG")?, "%Y%m%d")
+            .with_context(|| format!("invalid date for the day type {}", day_type))?;
+        calendars
+            .entry(day_type.clone())
+            .or_insert_with(|| Calendar::new(day_type))
+            .dates
+            .insert(date);
+    }
+    collections.calendars = CollectionWithId::new(calendars.into_values().collect())?;
+    Ok(())
+}
+
+/// Reads the lines from the table `REC_LID` and the points of their variants
+/// from the table `LID_VERLAUF`. A route is created for each direction
+/// (`LI_RI_NR`) of a line.
+pub(super) fn read_lines<H>(
+    file_handler: &mut H,
+    collections: &mut Collections,
+) -> Result<LineVariants>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let mut points: HashMap<(u32, String), BTreeMap<u32, PointKey>> = HashMap::new();
+    for record in read_table(file_handler, "LID_VERLAUF")?.records() {
+        let variant = (
+            record.parse("LI_NR")?,
+            record.get("STR_LI_VAR")?.to_string(),
+        );
+        points.entry(variant).or_default().insert(
+            record.parse("LI_LFD_NR")?,
+            (record.parse("ONR_TYP_NR")?, record.parse("ORT_NR")?),
+        );
+    }
+
+    let mut lines = BTreeMap::new();
+    let mut routes = BTreeMap::new();
+    let mut variants = LineVariants::new();
+    for record in read_table(file_handler, "REC_LID")?.records() {
+        let li_nr: u32 = record.parse("LI_NR")?;
+        let str_li_var = record.get("STR_LI_VAR")?.to_string();
+        let direction: u32 = record.parse("LI_RI_NR")?;
+        let code = record.get_opt("LI_KUERZEL").map(str::to_string);
+        let name = record
+            .get_opt("LIDNAME")
+            .map(str::to_string)
+            .or_else(|| code.clone())
+            .unwrap_or_else(|| li_nr.to_string());
+        let line_id = li_nr.to_string();
+        let route_id = format!("{}:{}", li_nr, direction);
+        let variant_points: Vec<PointKey> = points
+            .remove(&(li_nr, str_li_var.clone()))
+            .unwrap_or_default()
+            .into_values()
+            .collect();
+        routes.entry(route_id.clone()).or_insert_with(|| Route {
+            id: route_id.clone(),
+            name: name.clone(),
+            direction_type: match direction {
+                1 => Some("forward".to_string()),
+                2 => Some("backward".to_string()),
+                _ => None,
+            },
+            line_id: line_id.clone(),
+            destination_id: variant_points
+                .iter()
+                .rev()
+                .filter(|(point_type, _)| *point_type == STOP_POINT_TYPE)
+                .find_map(|(_, ort_nr)| collections.stop_points.get(&stop_point_id(*ort_nr)))
+                .map(|stop_point| stop_point.stop_area_id.clone()),
+            ..Default::default()
+        });
+        lines.entry(line_id.clone()).or_insert_with(|| Line {
+            id: line_id,
+            code,
+            name,
+            commercial_mode_id: PHYSICAL_MODE.to_string(),
+            ..Default::default()
+        });
+        variants.insert(
+            (li_nr, str_li_var),
+            LineVariant {
+                route_id,
+                points: variant_points,
+            },
+        );
+    }
+    collections.lines = CollectionWithId::new(lines.into_values().collect())?;
+    collections.routes = CollectionWithId::new(routes.into_values().collect())?;
+    collections.commercial_modes = CollectionWithId::from(CommercialMode {
+        id: PHYSICAL_MODE.to_string(),
+        name: PHYSICAL_MODE.to_string(),
+    });
+    collections.physical_modes = CollectionWithId::from(PhysicalMode {
+        id: PHYSICAL_MODE.to_string(),
+        name: PHYSICAL_MODE.to_string(),
+        co2_emission: None,
+    });
+    Ok(variants)
+}
+
+/// Run times and dwell times of the trips, by travel time group (`FGR_NR`)
+#[derive(Default)]
+struct RunTimes {
+    /// Run time between 2 points, from `SEL_FZT_FELD`
+    travel_times: HashMap<(u32, PointKey, PointKey), u32>,
+    /// Dwell time at a point, from `ORT_HZTF`
+    dwell_times: HashMap<(u32, PointKey), u32>,
+    /// Dwell time of a trip at a point, from `REC_FRT_HZT`
+    trip_dwell_times: HashMap<(u64, PointKey), u32>,
+}
+
+impl RunTimes {
+    fn read<H>(file_handler: &mut H) -> Result<Self>
+    where
+        for<'a> &'a mut H: FileHandler,
+    {
+        let mut run_times = RunTimes::default();
+        for record in read_table(file_handler, "SEL_FZT_FELD")?.records() {
+            let from = (record.parse("ONR_TYP_NR")?, record.parse("ORT_NR")?);
+            let to = (record.parse("SEL_ZIEL_TYP")?, record.parse("SEL_ZIEL")?);
+            run_times.travel_times.insert(
+                (record.parse("FGR_NR")?, from, to),
+                record.parse("SEL_FZT")?,
+            );
+        }
+        if let Some(table) = read_opt_table(file_handler, "ORT_HZTF")? {
+            for record in table.records() {
+                let point = (record.parse("ONR_TYP_NR")?, record.parse("ORT_NR")?);
+                run_times
+                    .dwell_times
+                    .insert((record.parse("FGR_NR")?, point), record.parse("HP_HZT")?);
+            }
+        }
+        if let Some(table) = read_opt_table(file_handler, "REC_FRT_HZT")? {
+            for record in table.records() {
+                let point = (record.parse("ONR_TYP_NR")?, record.parse("ORT_NR")?);
+                run_times.trip_dwell_times.insert(
+                    (record.parse("FRT_FID")?, point),
+                    record.parse("FRT_HZT_ZEIT")?,
+                );
+            }
+        }
+        Ok(run_times)
+    }
+
+    fn dwell_time(&self, trip_id: u64, travel_time_group: u32, point: PointKey) -> u32 {
+        self.trip_dwell_times
+            .get(&(trip_id, point))
+            .or_else(|| self.dwell_times.get(&(travel_time_group, point)))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Computes the stop times of a trip starting at `start_time` (in seconds
+/// after midnight) on the points of the `variant`. The dwell times are
+/// applied at the intermediate points only.
+fn compute_stop_times(
+    trip_id: u64,
+    travel_time_group: u32,
+    start_time: u32,
+    variant: &LineVariant,
+    run_times: &RunTimes,
+    stop_points: &CollectionWithId<StopPoint>,
+) -> Result<Vec<StopTime>> {
+    let mut time = start_time;
+    let mut stop_times = Vec::new();
+    let last_point_idx = variant.points.len().saturating_sub(1);
+    for (point_idx, point) in variant.points.iter().enumerate() {
+        if point_idx > 0 {
+            let previous = variant.points[point_idx - 1];
+            time += run_times
+                .travel_times
+                .get(&(travel_time_group, previous, *point))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "trip {} ignored: no run time from {:?} to {:?} for the travel time group {}",
+                        trip_id,
+                        previous,
+                        point,
+                        travel_time_group
+                    )
+                })?;
+        }
+        let arrival_time = time;
+        if point_idx > 0 && point_idx < last_point_idx {
+            time += run_times.dwell_time(trip_id, travel_time_group, *point);
+        }
+        if point.0 != STOP_POINT_TYPE {
+            continue;
+        }
+        let stop_point_idx = stop_points
+            .get_idx(&stop_point_id(point.1))
+            .ok_or_else(|| anyhow!("trip {} ignored: stop point {} not found", trip_id, point.1))?;
+        stop_times.push(StopTime {
+            stop_point_idx,
+            sequence: stop_times.len() as u32,
+            arrival_time: Time::new(0, 0, arrival_time),
+            departure_time: Time::new(0, 0, time),
+            boarding_duration: 0,
+            alighting_duration: 0,
+            pickup_type: 0,
+            drop_off_type: 0,
+            local_zone_id: None,
+            precision: None,
+        });
+    }
+    if stop_times.len() < 2 {
+        bail!("trip {} ignored: less than 2 stop points", trip_id);
+    }
+    Ok(stop_times)
+}
+
+/// Reads the trips from the table `REC_FRT`. Their stop times are computed
+/// from their start time, the points of their line variant and the run times
+/// of their travel time group.
+pub(super) fn read_trips<H>(
+    file_handler: &mut H,
+    collections: &mut Collections,
+    variants: &LineVariants,
+    dataset_id: &str,
+) -> Result<()>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let run_times = RunTimes::read(file_handler)?;
+    let company_id = collections
+        .companies
+        .values()
+        .next()
+        .map(|company| company.id.clone())
+        .unwrap_or_default();
+    let mut vehicle_journeys = Vec::new();
+    for record in read_table(file_handler, "REC_FRT")?.records() {
+        let trip_id: u64 = record.parse("FRT_FID")?;
+        let variant_key = (
+            record.parse("LI_NR")?,
+            record.get("STR_LI_VAR")?.to_string(),
+        );
+        let variant = skip_error_and_warn!(variants.get(&variant_key).ok_or_else(|| {
+            anyhow!(
+                "trip {} ignored: variant {} of the line {} not found",
+                trip_id,
+                variant_key.1,
+                variant_key.0
+            )
+        }));
+        let service_id = record.parse::<u32>("TAGESART_NR")?.to_string();
+        if !collections.calendars.contains_id(&service_id) {
+            warn!(
+                "trip {} ignored: day type {} not found in the calendar",
+                trip_id, service_id
+            );
+            continue;
+        }
+        let stop_times = skip_error_and_warn!(compute_stop_times(
+            trip_id,
+            record.parse("FGR_NR")?,
+            record.parse("FRT_START")?,
+            variant,
+            &run_times,
+            &collections.stop_points,
+        ));
+        vehicle_journeys.push(VehicleJourney {
+            id: trip_id.to_string(),
+            route_id: variant.route_id.clone(),
+            physical_mode_id: PHYSICAL_MODE.to_string(),
+            dataset_id: dataset_id.to_string(),
+            service_id,
+            company_id: company_id.clone(),
+            stop_times,
+            ..Default::default()
+        });
+    }
+    collections.vehicle_journeys = CollectionWithId::new(vehicle_journeys)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn vdv_coordinates() {
+        assert_relative_eq!(13.753429, parse_vdv_coord(134512345), epsilon = 1e-6);
+        assert_relative_eq!(-3.5, parse_vdv_coord(-33000000), epsilon = 1e-6);
+        assert_relative_eq!(0.0, parse_vdv_coord(0));
+    }
+}
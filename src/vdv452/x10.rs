@@ -0,0 +1,278 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Parsing of the VDV 451 files (`.x10`) in which the VDV 452 tables are
+//! exchanged.
+//!
+//! Each line of a file starts with its kind (`tbl`, `atr`, `rec`...)
+//! followed by semicolon separated values, the strings being double quoted.
+
+use crate::{file_handler::FileHandler, Result};
+use anyhow::{anyhow, bail, Context};
+use std::{collections::HashMap, fmt::Display, io::Read, path::PathBuf, str::FromStr};
+
+/// A table of a VDV 452 export
+#[derive(Debug)]
+pub(super) struct Table {
+    pub name: String,
+    path: PathBuf,
+    columns: HashMap<String, usize>,
+    records: Vec<(usize, Vec<String>)>,
+}
+
+/// A record of a `Table`, giving access to its values by column name
+pub(super) struct Record<'a> {
+    table: &'a Table,
+    line: usize,
+    values: &'a [String],
+}
+
+impl Table {
+    /// Parses the content of a `.x10` file
+    pub fn parse(content: &str, path: PathBuf) -> Result<Table> {
+        let mut name = None;
+        let mut columns = HashMap::new();
+        let mut records = Vec::new();
+        for (line_idx, line) in content.lines().enumerate() {
+            let line_number = line_idx + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = split_fields(line)
+                .with_context(|| format!("Error reading {:?} at line {}", path, line_number))?;
+            let kind = fields.remove(0).to_lowercase();
+            match kind.as_str() {
+                "tbl" => {
+                    name = fields.into_iter().next().map(|n| n.to_uppercase());
+                }
+                "atr" => {
+                    columns = fields
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, column)| (column.to_uppercase(), idx))
+                        .collect();
+                }
+                "rec" => {
+                    if fields.len() != columns.len() {
+                        bail!(
+                            "Error reading {:?} at line {}: {} values found for {} columns",
+                            path,
+                            line_number,
+                            fields.len(),
+                            columns.len()
+                        );
+                    }
+                    records.push((line_number, fields));
+                }
+                _ => {}
+            }
+        }
+        let name = name.ok_or_else(|| anyhow!("Error reading {:?}: no table found", path))?;
+        Ok(Table {
+            name,
+            path,
+            columns,
+            records,
+        })
+    }
+
+    /// Iterates over the records of the table
+    pub fn records(&self) -> impl Iterator<Item = Record<'_>> {
+        self.records.iter().map(move |(line, values)| Record {
+            table: self,
+            line: *line,
+            values,
+        })
+    }
+}
+
+impl<'a> Record<'a> {
+    /// Returns the value of the `column`, `None` if the column doesn't exist
+    /// in the table or if the value is empty
+    pub fn get_opt(&self, column: &str) -> Option<&'a str> {
+        self.table
+            .columns
+            .get(column)
+            .map(|idx| self.values[*idx].as_str())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Returns the value of the mandatory `column`
+    pub fn get(&self, column: &str) -> Result<&'a str> {
+        self.get_opt(column).ok_or_else(|| {
+            anyhow!(
+                "Error reading {:?} at line {}: no value for the column {} of the table {}",
+                self.table.path,
+                self.line,
+                column,
+                self.table.name
+            )
+        })
+    }
+
+    /// Parses the value of the mandatory `column`
+    pub fn parse<T>(&self, column: &str) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.get(column)?;
+        value.parse().map_err(|e| {
+            anyhow!(
+                "Error reading {:?} at line {}: invalid value {:?} for the column {}: {}",
+                self.table.path,
+                self.line,
+                value,
+                column,
+                e
+            )
+        })
+    }
+}
+
+/// Splits a line on the semicolons which are not in a quoted string.
+/// The quotes are removed and the values are trimmed.
+fn split_fields(line: &str) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => bail!("unterminated string in {:?}", line),
+                }
+            }
+            while chars.peek().map_or(false, |c| *c != ';') {
+                chars.next();
+            }
+        } else {
+            while let Some(c) = chars.peek().filter(|c| **c != ';') {
+                field.push(*c);
+                chars.next();
+            }
+        }
+        fields.push(field.trim().to_string());
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+/// Decodes the content of a file, the VDV exports being encoded either in
+/// UTF-8 or in ISO 8859-1
+fn decode(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes)
+        .unwrap_or_else(|e| e.into_bytes().into_iter().map(char::from).collect())
+}
+
+/// Reads the table `name` from the file `<name>.x10` (in lowercase), `None`
+/// if the file doesn't exist
+pub(super) fn read_opt_table<H>(file_handler: &mut H, name: &str) -> Result<Option<Table>>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    let file_name = format!("{}.x10", name.to_lowercase());
+    let (reader, path) = file_handler.get_file_if_exists(&file_name)?;
+    let mut reader = match reader {
+        Some(reader) => reader,
+        None => return Ok(None),
+    };
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Error reading {:?}", path))?;
+    let table = Table::parse(&decode(bytes), path)?;
+    if table.name != name {
+        bail!(
+            "Error reading {:?}: table {} found instead of {}",
+            table.path,
+            table.name,
+            name
+        );
+    }
+    Ok(Some(table))
+}
+
+/// Reads the mandatory table `name` from the file `<name>.x10`
+pub(super) fn read_table<H>(file_handler: &mut H, name: &str) -> Result<Table>
+where
+    for<'a> &'a mut H: FileHandler,
+{
+    read_opt_table(file_handler, name)?
+        .ok_or_else(|| anyhow!("file {}.x10 not found", name.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn split_quoted_fields() {
+        let fields = split_fields(r#"rec; 1; "Haupt;bahnhof" ; "Der ""Platz"""; ;  42"#).unwrap();
+        assert_eq!(
+            vec!["rec", "1", "Haupt;bahnhof", "Der \"Platz\"", "", "42"],
+            fields
+        );
+    }
+
+    #[test]
+    fn parse_table() {
+        let content = r#"mod; DD.MM.YYYY; HH:MM:SS; free
+src; "Operator"; "01.01.2024"; "00:00:00"
+chs; "ISO8859-1"
+tbl; rec_ort
+atr; BASIS_VERSION; ONR_TYP_NR; ORT_NR; ORT_NAME
+frm; num[9.0]; num[2.0]; num[6.0]; char[40]
+rec; 1; 1; 1001; "Hauptbahnhof"
+rec; 1; 1; 1002; ""
+end; 2
+eof; 1
+"#;
+        let table = Table::parse(content, PathBuf::from("rec_ort.x10")).unwrap();
+        assert_eq!("REC_ORT", table.name);
+        let records: Vec<_> = table.records().collect();
+        assert_eq!(2, records.len());
+        assert_eq!(1001, records[0].parse::<u32>("ORT_NR").unwrap());
+        assert_eq!(Some("Hauptbahnhof"), records[0].get_opt("ORT_NAME"));
+        assert_eq!(None, records[1].get_opt("ORT_NAME"));
+        assert_eq!(None, records[1].get_opt("ORT_POS_LAENGE"));
+        assert!(records[1].get("ORT_NAME").is_err());
+    }
+
+    #[test]
+    fn record_with_missing_values() {
+        let content = "tbl; REC_ORT\natr; ORT_NR; ORT_NAME\nrec; 1001\n";
+        let error = Table::parse(content, PathBuf::from("rec_ort.x10")).unwrap_err();
+        assert!(error.to_string().contains("1 values found for 2 columns"));
+    }
+
+    #[test]
+    fn decode_latin1() {
+        assert_eq!("Straße", decode(vec![b'S', b't', b'r', b'a', 0xDF, b'e']));
+        assert_eq!("Straße", decode("Straße".as_bytes().to_vec()));
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (C) 2017 Hove and/or its affiliates.
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as published by the
+// Free Software Foundation, version 3.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more
+// details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>
+
+//! Detection, and optional removal, of the vehicle journeys with less than 2
+//! stop times. They appear in broken feeds or after a filtering of the stops,
+//! and cannot be exported as valid trips.
+
+use crate::{
+    model::Collections,
+    report::{Report, ReportEntry, Severity},
+};
+use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Categories of the `Report` produced by the vehicle journey checks
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VehicleJourneyReportCategory {
+    /// A vehicle journey has no stop time
+    NoStopTime,
+    /// A vehicle journey has a single stop time
+    SingleStopTime,
+    /// A vehicle journey with less than 2 stop times has been removed
+    VehicleJourneyRemoved,
+}
+
+/// What to do with the vehicle journeys with less than 2 stop times
+#[derive(Derivative, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortVehicleJourneyStrategy {
+    /// Remove the vehicle journeys
+    #[derivative(Default)]
+    Remove,
+    /// Keep the vehicle journeys, only reporting them
+    Keep,
+}
+
+/// Check the vehicle journeys of `collections` with less than 2 stop times,
+/// removing them with the `ShortVehicleJourneyStrategy::Remove` strategy.
+///
+/// Every vehicle journey found is a warning of the returned `Report`, attached
+/// to the vehicle journey. The objects only used by the removed vehicle
+/// journeys are left to `Collections::sanitize`.
+pub fn check_short_vehicle_journeys(
+    collections: &mut Collections,
+    strategy: ShortVehicleJourneyStrategy,
+) -> Report<VehicleJourneyReportCategory> {
+    let mut report = Report::default();
+    for vj in collections.vehicle_journeys.values() {
+        let (category, description) = match vj.stop_times.len() {
+            0 => (VehicleJourneyReportCategory::NoStopTime, "no stop time"),
+            1 => (
+                VehicleJourneyReportCategory::SingleStopTime,
+                "a single stop time",
+            ),
+            _ => continue,
+        };
+        let (category, message) = match strategy {
+            ShortVehicleJourneyStrategy::Remove => (
+                VehicleJourneyReportCategory::VehicleJourneyRemoved,
+                format!("vehicle journey with {} removed", description),
+            ),
+            ShortVehicleJourneyStrategy::Keep => {
+                (category, format!("vehicle journey with {}", description))
+            }
+        };
+        report.add_entry(
+            Severity::Warning,
+            ReportEntry::new(category, message).with_object_id(&vj.id),
+        );
+    }
+    if strategy == ShortVehicleJourneyStrategy::Remove {
+        let count = collections.vehicle_journeys.len();
+        collections
+            .vehicle_journeys
+            .retain(|vj| vj.stop_times.len() >= 2);
+        info!(
+            "{} vehicle journeys with less than 2 stop times removed",
+            count - collections.vehicle_journeys.len()
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::BuildModel;
+    use pretty_assertions::assert_eq;
+    use transit_model_builder::ModelBuilder;
+
+    /// A vehicle journey `vj1` of two stop times, `vj2` of one and `vj3` of none
+    fn short_vehicle_journeys() -> Collections {
+        let mut collections = ModelBuilder::default()
+            .vj("vj1", |vj| {
+                vj.st("A", "10:00:00", "10:01:00")
+                    .st("B", "10:10:00", "10:11:00");
+            })
+            .vj("vj2", |vj| {
+                vj.st("A", "11:00:00", "11:01:00");
+            })
+            .build_model()
+            .into_collections();
+        // an empty vehicle journey is removed by the sanitization of the model
+        let mut vj3 = collections.vehicle_journeys.get("vj2").unwrap().clone();
+        vj3.id = "vj3".to_string();
+        vj3.stop_times.clear();
+        collections.vehicle_journeys.push(vj3).unwrap();
+        collections
+    }
+
+    #[test]
+    fn remove_short_vehicle_journeys() {
+        let mut collections = short_vehicle_journeys();
+        let report =
+            check_short_vehicle_journeys(&mut collections, ShortVehicleJourneyStrategy::Remove);
+
+        assert_eq!(
+            vec!["vj1"],
+            collections
+                .vehicle_journeys
+                .values()
+                .map(|vj| vj.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            2,
+            report.count_category(
+                Severity::Warning,
+                &VehicleJourneyReportCategory::VehicleJourneyRemoved
+            )
+        );
+    }
+
+    #[test]
+    fn keep_short_vehicle_journeys() {
+        let mut collections = short_vehicle_journeys();
+        let report =
+            check_short_vehicle_journeys(&mut collections, ShortVehicleJourneyStrategy::Keep);
+
+        assert_eq!(3, collections.vehicle_journeys.len());
+        assert_eq!(
+            1,
+            report.count_category(Severity::Warning, &VehicleJourneyReportCategory::NoStopTime)
+        );
+        assert_eq!(
+            1,
+            report.count_category(
+                Severity::Warning,
+                &VehicleJourneyReportCategory::SingleStopTime
+            )
+        );
+    }
+}
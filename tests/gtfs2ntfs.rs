@@ -38,6 +38,11 @@ fn test_gtfs() {
             on_demand_transport: false,
             on_demand_transport_comment: None,
             read_as_line: false,
+            duplicate_id_strategy: gtfs::DuplicateIdStrategy::Fail,
+            lenient: false,
+            route_name_strategy: gtfs::RouteNameStrategy::CodeAndName,
+            merge_directions: false,
+            network_rules: vec![],
         };
         let model = transit_model::gtfs::Reader::new(configuration)
             .parse(input_dir)
@@ -143,6 +148,11 @@ fn test_minimal_gtfs_with_odt_comment() {
                 "Service à réservation {agency_name} {agency_phone}".to_string(),
             ),
             read_as_line: false,
+            duplicate_id_strategy: gtfs::DuplicateIdStrategy::Fail,
+            lenient: false,
+            route_name_strategy: gtfs::RouteNameStrategy::CodeAndName,
+            merge_directions: false,
+            network_rules: vec![],
         };
         let model = transit_model::gtfs::Reader::new(configuration)
             .parse(input_dir)
@@ -172,6 +182,11 @@ fn test_minimal_gtfs_frequencies_with_odt_comment() {
                 "Service à réservation {agency_name} {agency_phone}".to_string(),
             ),
             read_as_line: false,
+            duplicate_id_strategy: gtfs::DuplicateIdStrategy::Fail,
+            lenient: false,
+            route_name_strategy: gtfs::RouteNameStrategy::CodeAndName,
+            merge_directions: false,
+            network_rules: vec![],
         };
 
         let model = transit_model::gtfs::Reader::new(configuration)
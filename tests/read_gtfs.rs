@@ -69,3 +69,243 @@ fn directory_not_a_gtfs() {
     // reading a directory that does not contain the gtfs files will lead to an error
     let _ = transit_model::gtfs::read("tests/fixtures/netex_france").unwrap();
 }
+
+#[test]
+fn read_many_gtfs() {
+    let paths = [
+        "tests/fixtures/gtfs2ntfs/minimal/input",
+        "tests/fixtures/gtfs2ntfs/minimal/input",
+    ];
+    let configuration = transit_model::gtfs::MultiFeedConfiguration {
+        prefixes: vec!["first".to_string(), "second".to_string()],
+        ..Default::default()
+    };
+    let model = transit_model::gtfs::read_many(&paths, configuration).unwrap();
+    let single = transit_model::gtfs::read(paths[0]).unwrap();
+    assert_eq!(2 * single.stop_points.len(), model.stop_points.len());
+    assert_eq!(2, model.networks.len());
+    assert!(model.stop_points.get("first:stop:11").is_some());
+    assert!(model.stop_points.get("second:stop:11").is_some());
+    // all the stops are at the same place, only the transfers between feeds
+    // are generated
+    assert_eq!(
+        2 * single.stop_points.len() * single.stop_points.len(),
+        model.transfers.len()
+    );
+    assert!(model
+        .transfers
+        .values()
+        .all(|transfer| transfer.from_stop_id[..5] != transfer.to_stop_id[..5]));
+}
+
+#[test]
+fn read_many_gtfs_default_prefixes() {
+    let paths = ["tests/fixtures/gtfs", "tests/fixtures/zipped_gtfs/gtfs.zip"];
+    let model = transit_model::gtfs::read_many(
+        &paths,
+        transit_model::gtfs::MultiFeedConfiguration::default(),
+    )
+    .unwrap();
+    assert!(model
+        .networks
+        .values()
+        .any(|network| network.id.starts_with("gtfs:")));
+    assert!(model
+        .networks
+        .values()
+        .any(|network| network.id.starts_with("gtfs2:")));
+}
+
+#[test]
+fn stream_stop_times() {
+    let stop_times = transit_model::gtfs::iter_stop_times("tests/fixtures/gtfs")
+        .unwrap()
+        .collect::<transit_model::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(13, stop_times.len());
+    assert_eq!("trip:3", stop_times[0].trip_id);
+    assert_eq!("stop:31", stop_times[0].stop_id);
+
+    transit_model::test_utils::test_in_tmp_dir(|path| {
+        transit_model::gtfs::write_stop_times(path, stop_times.clone(), &Default::default())
+            .unwrap();
+        let written = transit_model::gtfs::iter_stop_times(path)
+            .unwrap()
+            .map(|stop_time| stop_time.unwrap().stop_sequence)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stop_times
+                .iter()
+                .map(|stop_time| stop_time.stop_sequence)
+                .collect::<Vec<_>>(),
+            written
+        );
+    });
+}
+
+#[test]
+fn gtfs_in_memory() {
+    let archive = std::fs::read("tests/fixtures/zipped_gtfs/gtfs.zip").unwrap();
+    let model =
+        transit_model::gtfs::from_zip_reader(std::io::Cursor::new(archive), "gtfs.zip").unwrap();
+    let stop_points = model.stop_points.len();
+    let vehicle_journeys = model.vehicle_journeys.len();
+
+    let archive = transit_model::gtfs::write_to_zip_writer(
+        model,
+        std::io::Cursor::new(Vec::new()),
+        "gtfs.zip",
+        transit_model::gtfs::WriteConfiguration::default(),
+    )
+    .unwrap();
+    let model = transit_model::gtfs::from_zip_reader(archive, "gtfs.zip").unwrap();
+
+    assert_eq!(stop_points, model.stop_points.len());
+    assert_eq!(vehicle_journeys, model.vehicle_journeys.len());
+}
+
+#[test]
+fn gtfs_from_memory() {
+    let files = std::fs::read_dir("tests/fixtures/gtfs")
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            (name, std::fs::read(&path).unwrap())
+        })
+        .collect();
+    let model = transit_model::gtfs::from_memory(files, "gtfs").unwrap();
+    let expected = transit_model::gtfs::from_dir("tests/fixtures/gtfs").unwrap();
+
+    assert_eq!(expected.stop_points.len(), model.stop_points.len());
+    assert_eq!(
+        expected.vehicle_journeys.len(),
+        model.vehicle_journeys.len()
+    );
+}
+
+#[test]
+fn lenient_gtfs_reading() {
+    assert!(transit_model::gtfs::read("tests/fixtures/gtfs_lenient").is_err());
+
+    let configuration = transit_model::gtfs::Configuration {
+        lenient: true,
+        ..Default::default()
+    };
+    let lenient_model = transit_model::gtfs::Reader::new(configuration)
+        .parse("tests/fixtures/gtfs_lenient")
+        .unwrap();
+    let model = transit_model::gtfs::read("tests/fixtures/gtfs").unwrap();
+    assert_eq!(
+        model.calendars.values().collect::<Vec<_>>(),
+        lenient_model.calendars.values().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        model.vehicle_journeys.values().collect::<Vec<_>>(),
+        lenient_model.vehicle_journeys.values().collect::<Vec<_>>()
+    );
+}
+
+fn route_names_round_trip(
+    route_name_strategy: transit_model::gtfs::RouteNameStrategy,
+) -> (Vec<(Option<String>, String)>, Vec<(Option<String>, String)>) {
+    let line_names = |model: &transit_model::Model| {
+        let mut names: Vec<_> = model
+            .lines
+            .values()
+            .map(|line| (line.code.clone(), line.name.clone()))
+            .collect();
+        names.sort();
+        names
+    };
+    let mut collections = transit_model::ntfs::read("tests/fixtures/ntfs")
+        .unwrap()
+        .into_collections();
+    let m1_idx = collections.lines.get_idx("M1").unwrap();
+    collections.lines.index_mut(m1_idx).code = Some("1".to_string());
+    let model = transit_model::Model::new(collections).unwrap();
+    let names = line_names(&model);
+
+    let mut read_names = Vec::new();
+    transit_model::test_utils::test_in_tmp_dir(|path| {
+        let config = transit_model::gtfs::WriteConfiguration {
+            route_name_strategy,
+            ..Default::default()
+        };
+        transit_model::gtfs::write_with_configuration(model, path, config).unwrap();
+        let configuration = transit_model::gtfs::Configuration {
+            route_name_strategy,
+            ..Default::default()
+        };
+        let model = transit_model::gtfs::Reader::new(configuration)
+            .parse(path)
+            .unwrap();
+        read_names = line_names(&model);
+    });
+    (names, read_names)
+}
+
+#[test]
+fn route_names_with_code_and_name() {
+    let (names, read_names) =
+        route_names_round_trip(transit_model::gtfs::RouteNameStrategy::CodeAndName);
+    assert!(names.contains(&(Some("1".to_string()), "Metro 1".to_string())));
+    assert_eq!(names, read_names);
+}
+
+#[test]
+fn route_names_with_duplicated_name() {
+    let (names, read_names) =
+        route_names_round_trip(transit_model::gtfs::RouteNameStrategy::DuplicatedName);
+    let names: Vec<_> = names.into_iter().map(|(_, name)| (None, name)).collect();
+    assert_eq!(names, read_names);
+}
+
+#[derive(Debug)]
+struct LineCountWriter;
+
+impl transit_model::gtfs::GtfsFileWriter for LineCountWriter {
+    fn file_name(&self) -> &str {
+        "line_count.txt"
+    }
+
+    fn write(
+        &self,
+        model: &transit_model::Model,
+        writer: &mut dyn std::io::Write,
+    ) -> transit_model::Result<()> {
+        writeln!(writer, "line_count")?;
+        writeln!(writer, "{}", model.lines.len())?;
+        Ok(())
+    }
+}
+
+#[test]
+fn gtfs_selective_export() {
+    let model = transit_model::ntfs::read("tests/fixtures/ntfs").unwrap();
+    let line_count = model.lines.len();
+    transit_model::test_utils::test_in_tmp_dir(|path| {
+        let config = transit_model::gtfs::WriteConfiguration {
+            files: Some(
+                vec![transit_model::gtfs::GtfsFile::Stops]
+                    .into_iter()
+                    .collect(),
+            ),
+            extension_writers: vec![std::sync::Arc::new(LineCountWriter)],
+            empty_file_strategy: transit_model::gtfs::EmptyFileStrategy::HeaderOnly,
+            ..Default::default()
+        };
+        transit_model::gtfs::write_with_configuration(model, path, config).unwrap();
+
+        let mut written: Vec<_> = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        written.sort();
+        assert_eq!(vec!["line_count.txt", "stops.txt"], written);
+        assert_eq!(
+            format!("line_count\n{}\n", line_count),
+            std::fs::read_to_string(path.join("line_count.txt")).unwrap()
+        );
+    });
+}
@@ -91,6 +91,39 @@ fn minimal() {
     test_minimal_ntfs(&ntm);
 }
 
+#[test]
+fn minimal_with_semicolons_and_decimal_commas() {
+    test_in_tmp_dir(|path| {
+        for entry in std::fs::read_dir("tests/fixtures/minimal_ntfs/").unwrap() {
+            let file_path = entry.unwrap().path();
+            let mut rdr = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_path(&file_path)
+                .unwrap();
+            let mut wtr = csv::WriterBuilder::new()
+                .delimiter(b';')
+                .flexible(true)
+                .from_path(path.join(file_path.file_name().unwrap()))
+                .unwrap();
+            for record in rdr.records() {
+                let record = record.unwrap();
+                let fields = record.iter().map(|field| match field.parse::<f64>() {
+                    Ok(_) => field.replace('.', ","),
+                    Err(_) => field.to_string(),
+                });
+                wtr.write_record(fields).unwrap();
+            }
+        }
+        let dialect = transit_model::csv_format::CsvDialect {
+            delimiter: b';',
+            decimal_separator: b',',
+        };
+        let ntm = transit_model::ntfs::read_with_dialect(path, dialect).unwrap();
+        test_minimal_ntfs(&ntm);
+    });
+}
+
 #[test]
 fn zipped_minimal() {
     let ntm = transit_model::ntfs::read("tests/fixtures/zipped_ntfs/minimal_ntfs.zip").unwrap();
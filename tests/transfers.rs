@@ -90,3 +90,25 @@ fn test_generates_transfers_with_closure_inter_contributors() {
         );
     });
 }
+
+#[test]
+fn test_retain_transfer_modes() {
+    use transit_model::objects::TransferMode;
+    let input_dir = "tests/fixtures/transfers/mono_contributor/input";
+    let model = transit_model::ntfs::read(input_dir).unwrap();
+    let model = transfers::generates_transfers(model, 100.0, 0.785, 120, None).unwrap();
+    let mut collections = model.into_collections();
+    assert_eq!(6, collections.transfers.len());
+    for transfer in collections.transfers.values_mut() {
+        if transfer.from_stop_id == "sp_1" && transfer.to_stop_id == "sp_3" {
+            transfer.transfer_mode = Some(TransferMode::ParkAndRide);
+        }
+    }
+
+    transfers::retain_transfer_modes(&mut collections, &[TransferMode::Walk]);
+    assert_eq!(5, collections.transfers.len());
+    assert!(collections
+        .transfers
+        .values()
+        .all(|transfer| transfer.to_stop_id != "sp_3" || transfer.from_stop_id == "sp_3"));
+}